@@ -0,0 +1,739 @@
+use crate::backup::{BackupFile, BackupOutcome};
+use crate::cleanup::CleanReport;
+use crate::config::Schedule;
+use crate::history::RunStats;
+use crate::launchctl::{
+    Conflict, DeleteReport, EffectiveCommand, FileEntry, FileWritten, GitCreated, GitRefresh,
+    NewLabel, PlistDiff, ProgramCheck, RebuildReport, ResourceUsage, RestartReport, TaskInfo,
+    TaskLintReport, TrashEntry, YamlPreview, YamlVersion,
+};
+use crate::maintenance::{MaintenanceState, MaintenanceUpdate};
+use crate::manual_run::RunStatus;
+use crate::notify::NotifyResult;
+use crate::provenance::Provenance;
+use crate::server::{
+    AllZipExport, ArtifactList, ArtifactPath, ConfigName, ConfirmationRequired, DeleteTask,
+    DiskUsageParams, FilePath, GitSource, Health, Label, LabelFilter, LabelStatus, LintFilter,
+    ListFilter, OutputLimited, PlistSource, ProgramParam, RestartSignal, RunId, RunOnceParams,
+    RunOverrides, ScheduleUpdate, SecretKey, StopSignal, TaskNotFound, TemplateParams,
+    TextListFilter, TlsReload, TrashParams, YamlUpdate, YamlView, ZipExport,
+};
+use crate::stats::DiskUsage;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+static OPENAPI_VERSION: &str = "3.0.3";
+static TEXT: &str = "text/plain";
+static HTML: &str = "text/html";
+static ZIP: &str = "application/zip";
+static BINARY: &str = "application/octet-stream";
+
+///
+/// an OpenAPI document built route by route. Schemas of query parameters,
+/// request bodies and responses are generated from the structs used by the handlers.
+///
+struct ApiDoc {
+    gen: SchemaGenerator,
+    paths: Map<String, Value>,
+    current: (String, String),
+}
+
+impl ApiDoc {
+    fn new() -> ApiDoc {
+        ApiDoc {
+            gen: SchemaSettings::openapi3().into_generator(),
+            paths: Map::new(),
+            current: (String::new(), String::new()),
+        }
+    }
+
+    ///
+    /// start describing `method` of `path`. Every route requires authentication.
+    ///
+    fn route(&mut self, method: &str, path: &str, summary: &str) -> &mut ApiDoc {
+        let operation = json!({
+            "summary": summary,
+            "responses": {
+                "401": {"description": "missing or wrong credentials"},
+                "429": {"description": "too many requests"}
+            }
+        });
+        let path_item = self
+            .paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        path_item[method] = operation;
+        self.current = (path.to_string(), method.to_string());
+        self
+    }
+
+    fn operation(&mut self) -> &mut Value {
+        let (path, method) = &self.current;
+        &mut self.paths[path][method]
+    }
+
+    fn schema<T: ?Sized + JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.gen.subschema_for::<T>()).unwrap_or_default()
+    }
+
+    ///
+    /// a query parameter for each field of `T`, required unless it is an `Option`
+    ///
+    fn query<T: JsonSchema>(&mut self) -> &mut ApiDoc {
+        let root = serde_json::to_value(self.gen.root_schema_for::<T>().schema).unwrap_or_default();
+        let required = root["required"].as_array().cloned().unwrap_or_default();
+        let mut parameters = Vec::new();
+        if let Some(properties) = root["properties"].as_object() {
+            for (name, schema) in properties {
+                parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&Value::from(name.as_str())),
+                    "schema": schema,
+                }));
+            }
+        }
+        self.operation()["parameters"] = Value::Array(parameters);
+        self
+    }
+
+    fn body(&mut self, content_type: &str, schema: Value) -> &mut ApiDoc {
+        self.operation()["requestBody"] = json!({
+            "required": true,
+            "content": {content_type: {"schema": schema}}
+        });
+        self
+    }
+
+    fn json_body<T: JsonSchema>(&mut self) -> &mut ApiDoc {
+        let schema = self.schema::<T>();
+        self.body("application/json", schema)
+    }
+
+    fn text_body(&mut self, content_type: &str) -> &mut ApiDoc {
+        self.body(content_type, json!({"type": "string"}))
+    }
+
+    ///
+    /// a multipart form with a zip or tar.gz file in field `file`
+    ///
+    fn zip_upload(&mut self) -> &mut ApiDoc {
+        let schema = json!({
+            "type": "object",
+            "properties": {"file": {"type": "string", "format": "binary"}}
+        });
+        self.body("multipart/form-data", schema)
+    }
+
+    ///
+    /// a response with `content` by media type. Responses with the same `status`
+    /// but different media types are merged.
+    ///
+    fn response(&mut self, status: u16, description: &str, content: Value) -> &mut ApiDoc {
+        let response = &mut self.operation()["responses"][status.to_string()];
+        match (response["description"].as_str(), content.as_object()) {
+            (Some(previous), Some(content)) => {
+                response["description"] = Value::from(format!("{}, or {}", previous, description));
+                for (media_type, schema) in content {
+                    response["content"][media_type] = schema.clone();
+                }
+            }
+            _ => {
+                *response = json!({ "description": description });
+                if !content.is_null() {
+                    response["content"] = content;
+                }
+            }
+        }
+        self
+    }
+
+    fn json<T: ?Sized + JsonSchema>(&mut self, status: u16, description: &str) -> &mut ApiDoc {
+        let schema = self.schema::<T>();
+        self.response(
+            status,
+            description,
+            json!({"application/json": {"schema": schema}}),
+        )
+    }
+
+    fn text(&mut self, status: u16, content_type: &str, description: &str) -> &mut ApiDoc {
+        let schema = if content_type == ZIP || content_type == BINARY {
+            json!({"type": "string", "format": "binary"})
+        } else {
+            json!({"type": "string"})
+        };
+        self.response(
+            status,
+            description,
+            json!({ content_type: {"schema": schema} }),
+        )
+    }
+
+    ///
+    /// `400` with the error as text
+    ///
+    fn bad_request(&mut self) -> &mut ApiDoc {
+        self.text(400, TEXT, "the error")
+    }
+
+    ///
+    /// `404` if the task does not exist
+    ///
+    fn not_found(&mut self) -> &mut ApiDoc {
+        self.json::<TaskNotFound>(404, "the task does not exist")
+    }
+
+    fn build(mut self) -> Value {
+        let schemas: Map<String, Value> = self
+            .gen
+            .take_definitions()
+            .into_iter()
+            .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+            .collect();
+        json!({
+            "openapi": OPENAPI_VERSION,
+            "info": {
+                "title": "tasker",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "security": [{"basic": []}, {"bearer": []}],
+            "paths": self.paths,
+            "components": {
+                "schemas": schemas,
+                "securitySchemes": {
+                    "basic": {"type": "http", "scheme": "basic"},
+                    "bearer": {"type": "http", "scheme": "bearer"},
+                },
+            },
+        })
+    }
+}
+
+///
+/// the OpenAPI 3 description of every route of `build_app`
+///
+pub fn openapi() -> Value {
+    let mut doc = ApiDoc::new();
+
+    // pages
+    doc.route("get", "/", "index page")
+        .text(200, HTML, "the page");
+    doc.route(
+        "post",
+        "/",
+        "create tasks from uploaded zip or tar.gz files",
+    )
+    .zip_upload()
+    .text(200, HTML, "the tasks are created")
+    .text(
+        429,
+        TEXT,
+        "`MAX_CONCURRENT_UPLOADS` uploads are in progress, see `Retry-After`",
+    )
+    .bad_request();
+    for page in &[
+        "/list_all",
+        "/list_part.html",
+        "/edit_yaml.html",
+        "/stderr.html",
+        "/stdout.html",
+    ] {
+        doc.route("get", page, "web page")
+            .text(200, HTML, "the page");
+    }
+    doc.route("get", "/openapi.json", "this document").response(
+        200,
+        "OpenAPI 3 document",
+        json!({"application/json": {}}),
+    );
+    doc.route("get", "/yaml_schema", "JSON Schema of a task yaml")
+        .response(
+            200,
+            "JSON Schema (2020-12) document",
+            json!({"application/json": {}}),
+        );
+    doc.route(
+        "get",
+        "/ws",
+        "task list on connect, then every change of it",
+    )
+    .response(101, "switching to WebSocket", Value::Null);
+
+    // tasks
+    let tasks = doc.schema::<Vec<TaskInfo>>();
+    let counts = doc.schema::<BTreeMap<String, usize>>();
+    doc.route("get", "/list_raw_json", "list tasks")
+        .query::<ListFilter>()
+        .response(
+            200,
+            "the tasks, or the count of each status with `count_only=true`",
+            json!({"application/json": {"schema": {"oneOf": [tasks, counts]}}}),
+        )
+        .bad_request()
+        .text(500, TEXT, "the error");
+    doc.route("get", "/list_text", "list tasks as a plain text table")
+        .query::<TextListFilter>()
+        .text(
+            200,
+            "text/plain; charset=utf-8",
+            "the table, and a line counting the tasks of each status",
+        )
+        .bad_request()
+        .text(500, TEXT, "the error");
+    let labels = doc.schema::<Vec<String>>();
+    let labels_with_status = doc.schema::<Vec<LabelStatus>>();
+    doc.route("get", "/labels", "labels of all tasks, for autocompletion")
+        .query::<LabelFilter>()
+        .response(
+            200,
+            "the labels, or labels with status with `with_status=true`",
+            json!({"application/json": {"schema": {"oneOf": [labels, labels_with_status]}}}),
+        )
+        .text(500, TEXT, "the error");
+    doc.route("get", "/delete", "delete a task")
+        .query::<DeleteTask>()
+        .json::<DeleteReport>(200, "the task is deleted")
+        .json::<DeleteReport>(400, "some steps failed")
+        .not_found()
+        .json::<ConfirmationRequired>(409, "the task is loaded, confirm with the token");
+    for (path, summary) in &[("/load", "load a task"), ("/unload", "unload a task")] {
+        doc.route("get", path, summary)
+            .query::<Label>()
+            .text(200, TEXT, "success")
+            .bad_request()
+            .not_found();
+    }
+    doc.route("get", "/stop", "send a signal to the running task")
+        .query::<StopSignal>()
+        .text(200, TEXT, "the signal is sent")
+        .bad_request()
+        .not_found();
+    doc.route(
+        "get",
+        "/restart",
+        "stop the task with a signal, kill it after the grace period, and start it",
+    )
+    .query::<RestartSignal>()
+    .json::<RestartReport>(200, "the task is started again")
+    .bad_request()
+    .not_found();
+    doc.route("get", "/rotate_logs", "rotate stdout and stderr")
+        .query::<Label>()
+        .text(200, TEXT, "success")
+        .bad_request();
+    doc.route(
+        "post",
+        "/clear_output",
+        "move the output folder to trash and start over",
+    )
+    .query::<Label>()
+    .text(200, TEXT, "success")
+    .bad_request()
+    .not_found();
+    for (path, summary) in &[
+        ("/stdout_raw", "tail of stdout"),
+        ("/stderr_raw", "tail of stderr"),
+    ] {
+        doc.route("get", path, summary)
+            .query::<OutputLimited>()
+            .text(
+                200,
+                TEXT,
+                "the last `limit` lines, separated by `<br>`, between `since` and `until` if given",
+            )
+            .bad_request()
+            .not_found();
+    }
+    doc.route("get", "/usage", "cpu and memory usage of a running task")
+        .query::<Label>()
+        .json::<Option<ResourceUsage>>(200, "the usage, `null` if not running")
+        .bad_request();
+    doc.route("get", "/command", "the effective command of a task")
+        .query::<YamlView>()
+        .json::<EffectiveCommand>(200, "the command")
+        .bad_request()
+        .not_found();
+    doc.route("get", "/stats", "statistics of the run history of a task")
+        .query::<Label>()
+        .json::<RunStats>(200, "the statistics")
+        .not_found();
+    doc.route("post", "/adopt", "manage an existing daemon as a task")
+        .query::<Label>()
+        .text(200, TEXT, "success")
+        .bad_request();
+    doc.route("post", "/update_task", "replace the files of a task")
+        .query::<Label>()
+        .zip_upload()
+        .text(200, TEXT, "success")
+        .text(
+            429,
+            TEXT,
+            "`MAX_CONCURRENT_UPLOADS` uploads are in progress, see `Retry-After`",
+        )
+        .bad_request();
+    doc.route("get", "/get_task_zip", "export a task as zip")
+        .query::<ZipExport>()
+        .text(200, ZIP, "the zip")
+        .text(
+            429,
+            TEXT,
+            "`MAX_CONCURRENT_EXPORTS` other zips are being built, see `Retry-After`",
+        )
+        .bad_request()
+        .not_found();
+    doc.route("get", "/export_all", "export all tasks as one zip")
+        .query::<AllZipExport>()
+        .text(200, ZIP, "the zip, with a folder for each task")
+        .text(
+            429,
+            TEXT,
+            "`MAX_CONCURRENT_EXPORTS` other zips are being built, see `Retry-After`",
+        )
+        .text(500, TEXT, "the error")
+        .bad_request();
+
+    // configuration
+    doc.route("get", "/get_yaml", "the yaml of a task")
+        .query::<YamlView>()
+        .text(200, TEXT, "the yaml, with its version in the `ETag` header")
+        .bad_request()
+        .not_found();
+    doc.route("post", "/post_yaml", "update the yaml of a task")
+        .query::<YamlUpdate>()
+        .text_body("text/plain")
+        .text(200, TEXT, "success")
+        .json::<YamlPreview>(200, "the changes with `dry_run=true`")
+        .bad_request()
+        .not_found()
+        .text(409, TEXT, "the plist was modified outside of tasker")
+        .json::<YamlVersion>(412, "the yaml changed since the `If-Match` version")
+        .text(413, TEXT, "the yaml is too large");
+    doc.route("get", "/get_plist", "the plist of a task")
+        .query::<PlistSource>()
+        .text(200, "application/xml", "the plist")
+        .bad_request();
+    doc.route(
+        "get",
+        "/diff",
+        "difference between the yaml and the plist on disk",
+    )
+    .query::<YamlView>()
+    .json::<PlistDiff>(200, "the difference")
+    .bad_request();
+    doc.route(
+        "get",
+        "/provenance",
+        "who created a task, how, and its edits",
+    )
+    .query::<Label>()
+    .json::<Provenance>(200, "the provenance")
+    .not_found();
+    doc.route("post", "/config/set", "set a configuration of a task")
+        .query::<Label>()
+        .text_body("text/plain")
+        .text(200, TEXT, "success")
+        .bad_request();
+    doc.route("get", "/validate/label", "check the label of a new task")
+        .query::<Label>()
+        .json::<NewLabel>(200, "the full and the short label")
+        .bad_request()
+        .text(409, TEXT, "a task has the label");
+    doc.route(
+        "get",
+        "/validate/program",
+        "check the program of a new task and find its owner",
+    )
+    .query::<ProgramParam>()
+    .json::<ProgramCheck>(200, "its mode and owner")
+    .bad_request();
+    doc.route(
+        "post",
+        "/validate/config_entry",
+        "check a single configuration without changing any task",
+    )
+    .text_body("text/plain")
+    .text(200, TEXT, "the configuration as normalized yaml")
+    .bad_request();
+    doc.route("post", "/config/remove", "remove a configuration of a task")
+        .query::<ConfigName>()
+        .text(200, TEXT, "success")
+        .bad_request();
+    doc.route("get", "/schedule", "the schedule of a task")
+        .query::<Label>()
+        .json::<Schedule>(200, "the schedule")
+        .bad_request()
+        .not_found();
+    doc.route("post", "/schedule", "replace the schedule of a task")
+        .query::<ScheduleUpdate>()
+        .json_body::<Schedule>()
+        .text(200, TEXT, "success")
+        .bad_request()
+        .not_found();
+    doc.route(
+        "post",
+        "/run_once",
+        "run a task once now outside of launchd",
+    )
+    .query::<RunOnceParams>()
+    .json_body::<RunOverrides>()
+    .json::<RunStatus>(200, "the started run")
+    .text(409, TEXT, "the task is running under launchd")
+    .bad_request()
+    .not_found();
+    doc.route(
+        "get",
+        "/run_once_status",
+        "the status of a run of /run_once",
+    )
+    .query::<RunId>()
+    .json::<RunStatus>(200, "the run")
+    .text(404, TEXT, "unknown run id");
+    doc.route(
+        "post",
+        "/create_from_template",
+        "create a task from a template",
+    )
+    .query::<TemplateParams>()
+    .json_body::<BTreeMap<String, String>>()
+    .text(200, TEXT, "success")
+    .bad_request()
+    .not_found();
+    doc.route(
+        "post",
+        "/create_from_git",
+        "create a task from a shallow checkout of a git repository",
+    )
+    .json_body::<GitSource>()
+    .json::<GitCreated>(200, "the new task and the commit checked out")
+    .bad_request()
+    .text(404, TEXT, "no such branch, tag or commit")
+    .text(409, TEXT, "a task already has the label")
+    .text(502, TEXT, "the repository cannot be reached");
+    doc.route(
+        "post",
+        "/refresh_from_git",
+        "replace the files of a task created from git with a new checkout",
+    )
+    .query::<Label>()
+    .json::<GitRefresh>(200, "the old and new commits")
+    .bad_request()
+    .not_found()
+    .text(404, TEXT, "no such branch, tag or commit")
+    .text(502, TEXT, "the repository cannot be reached");
+    for (path, summary) in &[
+        ("/mark_secret", "mark an environment variable as secret"),
+        ("/unmark_secret", "unmark a secret environment variable"),
+    ] {
+        doc.route("post", path, summary)
+            .query::<SecretKey>()
+            .json::<Vec<String>>(200, "the secret environment variables")
+            .bad_request()
+            .not_found();
+    }
+
+    // files
+    doc.route("get", "/files", "files in the task folder")
+        .query::<Label>()
+        .json::<Vec<FileEntry>>(200, "the files")
+        .bad_request()
+        .not_found();
+    doc.route("get", "/file", "download a file in the task folder")
+        .query::<FilePath>()
+        .text(200, BINARY, "the file")
+        .bad_request()
+        .not_found();
+    doc.route(
+        "post",
+        "/file",
+        "create or replace a file in the task folder",
+    )
+    .query::<FilePath>()
+    .text_body(BINARY)
+    .json::<FileWritten>(200, "the file is written")
+    .bad_request()
+    .not_found()
+    .text(413, TEXT, "the file is too large")
+    .text(
+        429,
+        TEXT,
+        "`MAX_CONCURRENT_UPLOADS` uploads are in progress, see `Retry-After`",
+    );
+    doc.route("get", "/artifacts", "files produced by a task")
+        .query::<ArtifactList>()
+        .json::<Vec<FileEntry>>(200, "the artifacts")
+        .text(200, ZIP, "a zip of them with `zip=true`")
+        .bad_request()
+        .not_found();
+    doc.route("get", "/artifact", "download an artifact")
+        .query::<ArtifactPath>()
+        .text(200, BINARY, "the artifact")
+        .bad_request()
+        .not_found();
+    doc.route("delete", "/artifact", "delete an artifact")
+        .query::<ArtifactPath>()
+        .text(200, TEXT, "success")
+        .bad_request()
+        .not_found();
+
+    // server
+    doc.route(
+        "post",
+        "/rebuild",
+        "rebuild output folders and plists from meta and tasks",
+    )
+    .json::<RebuildReport>(200, "what is done to each task")
+    .bad_request();
+    doc.route(
+        "get",
+        "/lint_report",
+        "check every stored yaml as an edit or a load would",
+    )
+    .query::<LintFilter>()
+    .json::<Vec<TaskLintReport>>(200, "errors and warnings of each task")
+    .bad_request();
+    doc.route(
+        "get",
+        "/conflicts",
+        "pairs of tasks likely to get in each other's way",
+    )
+    .json::<Vec<Conflict>>(200, "the conflicts, by kind and labels")
+    .bad_request();
+    doc.route("post", "/cleanup_temp", "remove stale temp files")
+        .json::<CleanReport>(200, "what is removed");
+    doc.route("get", "/disk_usage", "bytes used by the tasker folders")
+        .query::<DiskUsageParams>()
+        .json::<DiskUsage>(200, "the disk usage");
+    doc.route("get", "/trash", "the entries of trash")
+        .query::<TrashParams>()
+        .json::<Vec<TrashEntry>>(200, "the entries, oldest or largest first");
+    doc.route("post", "/backup_now", "back up all tasks into `BACKUP_DIR`")
+        .json::<BackupOutcome>(200, "the backup")
+        .text(409, TEXT, "another backup is in progress")
+        .text(500, TEXT, "the error, also recorded as the last backup");
+    doc.route("get", "/backups", "the backups in `BACKUP_DIR`")
+        .json::<Vec<BackupFile>>(200, "the backups, newest first");
+    doc.route(
+        "post",
+        "/test_notification",
+        "send a synthetic task failure through all notifiers",
+    )
+    .json::<Vec<NotifyResult>>(200, "whether each notifier sent it");
+    doc.route("post", "/shutdown", "stop the server")
+        .text(202, TEXT, "shutting down")
+        .text(503, TEXT, "the server is not running");
+    doc.route(
+        "post",
+        "/reload_tls",
+        "check the SSL key and certificate, and restart gracefully to serve them",
+    )
+    .json::<TlsReload>(202, "the certificate served after the restart")
+    .text(
+        400,
+        TEXT,
+        "SSL is not enabled, or the key or certificate is invalid",
+    )
+    .text(503, TEXT, "the server is not running");
+    doc.route("get", "/health", "whether the server is up")
+        .json::<Health>(
+            200,
+            "the health, also reporting maintenance mode, certificate expiry and backups",
+        );
+    doc.route("get", "/metrics", "request durations and launchctl queue")
+        .text(200, TEXT, "histograms in the Prometheus text format");
+    doc.route("get", "/maintenance", "the maintenance mode")
+        .json::<MaintenanceState>(200, "the maintenance mode");
+    doc.route(
+        "post",
+        "/maintenance",
+        "turn maintenance mode on or off, refusing mutations with 503 while on",
+    )
+    .json_body::<MaintenanceUpdate>()
+    .json::<MaintenanceState>(200, "the new maintenance mode")
+    .bad_request();
+
+    doc.build()
+}
+
+#[cfg(test)]
+mod test_api_doc_mod {
+    use super::*;
+    use regex::Regex;
+
+    ///
+    /// (method, path) of every route registered in `build_app`
+    ///
+    fn registered_routes() -> Vec<(String, String)> {
+        let source = include_str!("server.rs");
+        let build_app = &source[source.find("pub fn build_app(").unwrap()..];
+        let handler =
+            Regex::new(r#"#\[(get|post|delete)\("([^"]+)"\)\]\s*pub async fn (\w+)"#).unwrap();
+        let handlers: BTreeMap<&str, (String, String)> = handler
+            .captures_iter(source)
+            .map(|c| {
+                let route = (c[1].to_string(), c[2].to_string());
+                (c.get(3).unwrap().as_str(), route)
+            })
+            .collect();
+        let mut routes = Vec::new();
+        for service in Regex::new(r"\.service\((\w+)\)")
+            .unwrap()
+            .captures_iter(build_app)
+        {
+            routes.push(handlers[&service[1]].clone());
+        }
+        let resource =
+            Regex::new(r#"web::resource\("([^"]+)"\)((?:\s*\.route\(web::\w+\(\)\.to\(\w+\)\))+)"#)
+                .unwrap();
+        let method = Regex::new(r"web::(\w+)\(\)").unwrap();
+        for c in resource.captures_iter(build_app) {
+            for m in method.captures_iter(&c[2]) {
+                routes.push((m[1].to_string(), c[1].to_string()));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn every_route_documented() {
+        let doc: Value = serde_json::from_str(&openapi().to_string()).unwrap();
+        assert_eq!(doc["openapi"], OPENAPI_VERSION);
+        let routes = registered_routes();
+        assert!(routes.len() > 30);
+        for (method, path) in routes {
+            assert!(
+                doc["paths"][&path][&method].is_object(),
+                "{} {} is not documented",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn schemas_from_structs() {
+        let doc = openapi();
+        let task_info = &doc["components"]["schemas"]["TaskInfo"];
+        for field in &[
+            "label",
+            "status",
+            "scope",
+            "last_run_duration_secs",
+            "created_by",
+            "usage",
+        ] {
+            assert!(task_info["properties"][field].is_object(), "{}", field);
+        }
+        let parameters = doc["paths"]["/get_task_zip"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        let label = parameters.iter().find(|p| p["name"] == "label").unwrap();
+        assert_eq!(label["in"], "query");
+        assert_eq!(label["required"], true);
+        let level = parameters.iter().find(|p| p["name"] == "level").unwrap();
+        assert_eq!(level["required"], false);
+        assert!(doc["components"]["schemas"]["TaskNotFound"].is_object());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::initialize::Env;
+use crate::utils::constant_time_eq;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, HeaderValue};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use actix_web_httpauth::headers::authorization::{Basic, Bearer, Scheme};
+use futures::future::{err, ok, Either, Ready};
+use std::task::{Context, Poll};
+
+static BASIC_CHALLENGE: &str = "Basic realm=\"tasker\"";
+static BEARER_CHALLENGE: &str = "Bearer realm=\"tasker\", error=\"invalid_token\"";
+static UNKNOWN_USER: &str = "unknown";
+
+///
+/// the reason a request is rejected, which decides the `WWW-Authenticate` challenge
+///
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    Basic,
+    Bearer,
+}
+
+///
+/// the client of a request, inserted into the request extensions by `Auth`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser(pub String);
+
+///
+/// who sent `req`: the basic auth user name, or `token #<n>` for the n-th of `API_TOKENS`
+///
+pub fn authenticated_user(req: &HttpRequest) -> String {
+    match req.extensions().get::<AuthenticatedUser>() {
+        Some(AuthenticatedUser(user)) => user.clone(),
+        None => UNKNOWN_USER.to_string(),
+    }
+}
+
+///
+/// accept `Authorization: Bearer <token>` matching one of `tokens`,
+/// or `Authorization: Basic ...` matching `user_name` and `password`,
+/// and return who the client is. All comparisons are constant-time.
+///
+pub fn authorize(
+    authorization: Option<&HeaderValue>,
+    user_name: &str,
+    password: &str,
+    tokens: &[String],
+) -> Result<AuthenticatedUser, Rejection> {
+    let authorization = match authorization {
+        Some(a) => a,
+        None => return Err(Rejection::Basic),
+    };
+    if let Ok(bearer) = Bearer::parse(authorization) {
+        let token = bearer.token().as_bytes();
+        // check all tokens to avoid leaking which one matches
+        let matched = tokens.iter().enumerate().fold(None, |matched, (i, t)| {
+            if constant_time_eq(t.as_bytes(), token) {
+                Some(i)
+            } else {
+                matched
+            }
+        });
+        return match matched {
+            Some(i) => Ok(AuthenticatedUser(format!("token #{}", i + 1))),
+            None => Err(Rejection::Bearer),
+        };
+    }
+    if let Ok(basic) = Basic::parse(authorization) {
+        let user_matched = constant_time_eq(basic.user_id().as_bytes(), user_name.as_bytes());
+        let password_matched = match basic.password() {
+            Some(p) => constant_time_eq(p.as_bytes(), password.as_bytes()),
+            None => false,
+        };
+        if user_matched & password_matched {
+            return Ok(AuthenticatedUser(user_name.to_string()));
+        }
+    }
+    Err(Rejection::Basic)
+}
+
+fn reject(rejection: Rejection) -> actix_web::Error {
+    let challenge = match rejection {
+        Rejection::Basic => BASIC_CHALLENGE,
+        Rejection::Bearer => BEARER_CHALLENGE,
+    };
+    actix_web::Error::from(
+        HttpResponse::Unauthorized()
+            .header(header::WWW_AUTHENTICATE, challenge)
+            .finish(),
+    )
+}
+
+///
+/// authentication middleware supporting both basic auth (for browsers)
+/// and bearer tokens in `API_TOKENS` (for scripts)
+///
+pub struct Auth;
+
+impl<S, B> Transform<S> for Auth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = AuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthMiddleware { service })
+    }
+}
+
+pub struct AuthMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for AuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let env = Env::get();
+        match authorize(
+            req.headers().get(header::AUTHORIZATION),
+            &env.user_name,
+            &env.password,
+            &env.api_tokens,
+        ) {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+                Either::Left(self.service.call(req))
+            }
+            Err(rejection) => Either::Right(err(reject(rejection))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_auth_mod {
+    use super::*;
+
+    static USER_NAME: &str = "tasker_user";
+    static PASSWORD: &str = "tasker_password";
+
+    fn tokens() -> Vec<String> {
+        vec![
+            "first_token_0123456789".to_string(),
+            "second_token_0123456789".to_string(),
+        ]
+    }
+
+    fn check(authorization: Option<&'static str>) -> Result<AuthenticatedUser, Rejection> {
+        let header = authorization.map(HeaderValue::from_static);
+        authorize(header.as_ref(), USER_NAME, PASSWORD, &tokens())
+    }
+
+    #[test]
+    fn bearer_valid() {
+        assert_eq!(
+            check(Some("Bearer second_token_0123456789")),
+            Ok(AuthenticatedUser("token #2".to_string()))
+        );
+    }
+
+    #[test]
+    fn bearer_invalid() {
+        assert_eq!(
+            check(Some("Bearer second_token_012345678")),
+            Err(Rejection::Bearer)
+        );
+    }
+
+    #[test]
+    fn basic_valid() {
+        // base64 of `tasker_user:tasker_password`
+        assert_eq!(
+            check(Some("Basic dGFza2VyX3VzZXI6dGFza2VyX3Bhc3N3b3Jk")),
+            Ok(AuthenticatedUser(USER_NAME.to_string()))
+        );
+    }
+
+    #[test]
+    fn basic_invalid() {
+        // base64 of `tasker_user:wrong_password`
+        assert_eq!(
+            check(Some("Basic dGFza2VyX3VzZXI6d3JvbmdfcGFzc3dvcmQ=")),
+            Err(Rejection::Basic)
+        );
+    }
+
+    #[test]
+    fn no_header() {
+        assert_eq!(check(None), Err(Rejection::Basic));
+    }
+}
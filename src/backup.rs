@@ -0,0 +1,394 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::launchctl::export_all;
+use crate::utils::{create_dir_check, format_rfc3339, read_utf8_file, write_atomic};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, TryLockError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static BACKUP_STATE_FILE: &str = "backup.json";
+static BACKUP_PREFIX: &str = "tasker-backup-";
+static BACKUP_EXTENSION: &str = ".zip";
+/// the last successful backup is stale once this many intervals passed without another
+static STALE_INTERVALS: u32 = 2;
+
+lazy_static! {
+    /// one backup at a time, scheduled or by `/backup_now`
+    static ref BACKUP_LOCK: Mutex<()> = Mutex::new(());
+}
+
+///
+/// the result of a backup, kept in `meta/backup.json`
+///
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct BackupOutcome {
+    /// RFC3339 in UTC
+    pub started_at: String,
+    pub started_at_secs: u64,
+    /// the file name in `BACKUP_DIR`, `None` if the backup failed
+    pub file: Option<String>,
+    pub bytes: Option<u64>,
+    pub tasks: usize,
+    /// the error code and message, like the body of an error response
+    pub error: Option<String>,
+}
+
+///
+/// the last backup and the last successful backup, persisted in `meta/backup.json`
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct BackupState {
+    pub last: Option<BackupOutcome>,
+    pub last_success: Option<BackupOutcome>,
+}
+
+///
+/// a backup in `BACKUP_DIR`
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct BackupFile {
+    pub name: String,
+    pub bytes: u64,
+}
+
+///
+/// whether the last backup succeeded, and is recent if backups are scheduled, for `/health`
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct BackupCheck {
+    pub passed: bool,
+    /// whether `BACKUP_INTERVAL_HOURS` is set
+    scheduled: bool,
+    warning: Option<String>,
+    last: Option<BackupOutcome>,
+    last_success: Option<BackupOutcome>,
+}
+
+pub fn backup_state_file() -> PathBuf {
+    Env::get().meta_dir.join(BACKUP_STATE_FILE)
+}
+
+impl BackupState {
+    ///
+    /// no backup is known if the state file is missing or corrupted
+    ///
+    pub fn load(file: &Path) -> BackupState {
+        match read_utf8_file(file) {
+            Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
+            Err(_) => BackupState::default(),
+        }
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), Error> {
+        match serde_json::to_string_pretty(self) {
+            Ok(s) => write_atomic(file, s.as_bytes()),
+            Err(e) => Err(Error::CopyError(format!("{}", e))),
+        }
+    }
+
+    pub fn record(&mut self, outcome: BackupOutcome) {
+        if outcome.error.is_none() {
+            self.last_success = Some(outcome.clone());
+        }
+        self.last = Some(outcome);
+    }
+
+    ///
+    /// `interval` is `BACKUP_INTERVAL_HOURS`, without which a missing or old backup is fine
+    ///
+    pub fn check(self, interval: Option<Duration>, now: SystemTime) -> BackupCheck {
+        let now_secs = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let failed = self.last.as_ref().and_then(|last| last.error.as_ref());
+        let warning = match (failed, interval, &self.last_success) {
+            (Some(error), _, _) => Some(format!("the last backup failed: {}", error)),
+            (None, Some(_), None) => Some("no backup has succeeded yet".to_string()),
+            (None, Some(interval), Some(success))
+                if now_secs.saturating_sub(success.started_at_secs)
+                    > (interval * STALE_INTERVALS).as_secs() =>
+            {
+                Some(format!(
+                    "the last successful backup at {} is older than {} intervals",
+                    success.started_at, STALE_INTERVALS
+                ))
+            }
+            _ => None,
+        };
+        BackupCheck {
+            passed: warning.is_none(),
+            scheduled: interval.is_some(),
+            warning,
+            last: self.last,
+            last_success: self.last_success,
+        }
+    }
+
+    ///
+    /// how long until the next scheduled backup, so that restarts do not postpone backups
+    ///
+    pub fn next_backup_in(&self, interval: Duration, now: SystemTime) -> Duration {
+        let last = match &self.last_success {
+            Some(success) => UNIX_EPOCH + Duration::from_secs(success.started_at_secs),
+            None => return Duration::from_secs(0),
+        };
+        match now.duration_since(last) {
+            Ok(elapsed) => interval.checked_sub(elapsed).unwrap_or_default(),
+            Err(_) => interval,
+        }
+    }
+}
+
+///
+/// `tasker-backup-<timestamp>.zip`, in the order of `now` when sorted by name
+///
+pub fn backup_file_name(now: SystemTime) -> String {
+    let millis = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_millis())
+        .unwrap_or(0);
+    let timestamp = format_rfc3339(now)
+        .unwrap_or_default()
+        .replace(&['-', ':'][..], "")
+        .replace('Z', &format!(".{:03}Z", millis));
+    format!("{}{}{}", BACKUP_PREFIX, timestamp, BACKUP_EXTENSION)
+}
+
+fn is_backup_file(name: &str) -> bool {
+    name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_EXTENSION)
+}
+
+///
+/// the backups in `dir`, newest first. Other files are ignored.
+///
+pub fn list_backups(dir: &Path) -> Vec<BackupFile> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut backups: Vec<BackupFile> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let meta = entry.metadata().ok()?;
+            if !is_backup_file(&name) || !meta.is_file() {
+                return None;
+            }
+            Some(BackupFile {
+                name,
+                bytes: meta.len(),
+            })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    backups
+}
+
+///
+/// delete the backups of `dir` beyond the `keep` newest, returning the deleted names.
+/// The newest backup is always kept, even with `keep` 0.
+/// Failed backups leave no file, so every backup listed is a successful one.
+///
+pub fn prune_backups(dir: &Path, keep: usize) -> Vec<String> {
+    let mut deleted = Vec::new();
+    for backup in list_backups(dir).into_iter().skip(keep.max(1)) {
+        match std::fs::remove_file(dir.join(&backup.name)) {
+            Ok(_) => deleted.push(backup.name),
+            Err(e) => eprintln!("failed to delete old backup `{}`: {}", backup.name, e),
+        }
+    }
+    deleted
+}
+
+///
+/// export all tasks into `dir` like `/export_all` without redacting secrets,
+/// then prune the backups beyond `keep`. The outcome is recorded in `state_file`,
+/// whether the backup succeeded or not.
+///
+pub fn run_backup(
+    dir: &Path,
+    keep: usize,
+    state_file: &Path,
+    now: SystemTime,
+) -> Result<BackupOutcome, Error> {
+    let _running = match BACKUP_LOCK.try_lock() {
+        Ok(guard) => guard,
+        // a panic during a previous backup, which left no file
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            return Err(Error::BackupInProgress(
+                "another backup is in progress".to_string(),
+            ))
+        }
+    };
+    let name = backup_file_name(now);
+    let written = write_backup(dir, &name);
+    let mut outcome = BackupOutcome {
+        started_at: format_rfc3339(now).unwrap_or_default(),
+        started_at_secs: now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        file: None,
+        bytes: None,
+        tasks: 0,
+        error: None,
+    };
+    match &written {
+        Ok((tasks, bytes)) => {
+            outcome.file = Some(name);
+            outcome.bytes = Some(*bytes);
+            outcome.tasks = *tasks;
+        }
+        Err(e) => {
+            eprintln!("backup failed: {}", e.detail());
+            outcome.error = Some(e.response_body(false));
+        }
+    }
+    let mut state = BackupState::load(state_file);
+    state.record(outcome.clone());
+    if let Err(e) = state.save(state_file) {
+        eprintln!("failed to save backup state: {:?}", e);
+    }
+    written?;
+    prune_backups(dir, keep);
+    Ok(outcome)
+}
+
+///
+/// write the backup to a hidden temp file first, so that a failed backup leaves no file
+/// that could be mistaken for a successful one
+///
+fn write_backup(dir: &Path, name: &str) -> Result<(usize, u64), Error> {
+    create_dir_check(dir)?;
+    let temp = dir.join(format!(".{}.tmp", name));
+    let exported = export_all(&temp, zip::CompressionMethod::Deflated, false).and_then(|labels| {
+        let bytes = match std::fs::metadata(&temp) {
+            Ok(meta) => meta.len(),
+            Err(e) => return Err(Error::ZipFailure(format!("{}", e))),
+        };
+        match std::fs::rename(&temp, dir.join(name)) {
+            Ok(_) => Ok((labels.len(), bytes)),
+            Err(e) => Err(Error::RenameError(format!(
+                "cannot rename backup `{}`: {}",
+                temp.to_string_lossy(),
+                e
+            ))),
+        }
+    });
+    if exported.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    exported
+}
+
+#[cfg(test)]
+mod test_backup_mod {
+    use super::*;
+
+    fn outcome(started_at_secs: u64, error: Option<&str>) -> BackupOutcome {
+        BackupOutcome {
+            started_at: format_rfc3339(UNIX_EPOCH + Duration::from_secs(started_at_secs)).unwrap(),
+            started_at_secs,
+            file: None,
+            bytes: None,
+            tasks: 1,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn backup_names_sort_by_time() {
+        let at = |millis: u64| backup_file_name(UNIX_EPOCH + Duration::from_millis(millis));
+        assert_eq!(
+            at(1_792_206_000_042),
+            "tasker-backup-20261017T030000.042Z.zip"
+        );
+        assert!(at(1_792_206_000_042) < at(1_792_206_000_100));
+        assert!(at(1_792_206_000_100) < at(1_792_292_400_000));
+        assert!(is_backup_file(&at(0)));
+        assert!(!is_backup_file(&format!(".{}.tmp", at(0))));
+    }
+
+    #[test]
+    fn prune_keeps_newest() {
+        let dir = Path::new("test_backup_prune");
+        let _ = std::fs::remove_dir_all(dir);
+        create_dir_check(dir).unwrap();
+        let names: Vec<String> = (0..5)
+            .map(|day| backup_file_name(UNIX_EPOCH + Duration::from_secs(day * 86400)))
+            .collect();
+        for name in &names {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not a backup").unwrap();
+
+        assert_eq!(
+            prune_backups(dir, 3),
+            vec![names[1].clone(), names[0].clone()]
+        );
+        let kept: Vec<String> = list_backups(dir).into_iter().map(|b| b.name).collect();
+        assert_eq!(
+            kept,
+            vec![names[4].clone(), names[3].clone(), names[2].clone()]
+        );
+        assert_eq!(list_backups(dir)[0].bytes, names[4].len() as u64);
+
+        // the only remaining backup is never deleted
+        prune_backups(dir, 0);
+        assert_eq!(list_backups(dir).len(), 1);
+        assert_eq!(prune_backups(dir, 0), Vec::<String>::new());
+        assert!(dir.join(&names[4]).exists());
+        assert!(dir.join("notes.txt").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_or_failed_backup_warns() {
+        let day = Some(Duration::from_secs(86400));
+        let now = UNIX_EPOCH + Duration::from_secs(10 * 86400);
+        let mut state = BackupState::default();
+        assert!(state.clone().check(None, now).passed);
+        assert_eq!(
+            state.clone().check(day, now).warning.unwrap(),
+            "no backup has succeeded yet"
+        );
+
+        state.record(outcome(9 * 86400, None));
+        assert!(state.clone().check(day, now).passed);
+        // two days without a backup
+        let later = now + Duration::from_secs(2 * 86400);
+        assert!(!state.clone().check(day, later).passed);
+        assert!(state.clone().check(None, later).passed);
+
+        state.record(outcome(
+            10 * 86400,
+            Some("ZipFailure(\"the zip cannot be written\")"),
+        ));
+        let check = state.clone().check(day, now);
+        assert!(!check.passed);
+        assert!(check
+            .warning
+            .unwrap()
+            .starts_with("the last backup failed: ZipFailure"));
+        assert_eq!(check.last_success.unwrap().started_at_secs, 9 * 86400);
+    }
+
+    #[test]
+    fn next_backup_after_restart() {
+        let day = Duration::from_secs(86400);
+        let now = UNIX_EPOCH + Duration::from_secs(10 * 86400);
+        let mut state = BackupState::default();
+        assert_eq!(state.next_backup_in(day, now), Duration::from_secs(0));
+        state.record(outcome(10 * 86400 - 3600, None));
+        assert_eq!(
+            state.next_backup_in(day, now),
+            day - Duration::from_secs(3600)
+        );
+        state.record(outcome(8 * 86400, None));
+        assert_eq!(state.next_backup_in(day, now), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::{format_rfc3339, parse_rfc3339, read_utf8_file, write_atomic};
+use crate::TEMP_ZIP_PATH;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// written by `delete_task` into the trash folder of a task
+pub static DELETION_MARKER_FILE: &str = "deleted_at.json";
+
+lazy_static! {
+    static ref IN_PROGRESS: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Debug, Default, Serialize, JsonSchema, PartialEq)]
+pub struct CleanReport {
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+}
+
+///
+/// a temp path being written, which is skipped by `clean_temp`
+/// until this guard is dropped
+///
+pub struct InProgress {
+    path: PathBuf,
+}
+
+impl InProgress {
+    pub fn new(path: &Path) -> InProgress {
+        IN_PROGRESS.lock().unwrap().insert(path.to_path_buf());
+        InProgress {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        IN_PROGRESS.lock().unwrap().remove(&self.path);
+    }
+}
+
+///
+/// when and which task was moved to a trash folder, kept in `deleted_at.json`
+/// since the mtime of the folder changes with its content
+///
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeletionMarker {
+    pub label: String,
+    /// RFC3339 in UTC
+    pub deleted_at: String,
+}
+
+impl DeletionMarker {
+    pub fn new(label: &str, now: SystemTime) -> DeletionMarker {
+        DeletionMarker {
+            label: label.to_string(),
+            deleted_at: format_rfc3339(now).unwrap_or_default(),
+        }
+    }
+
+    ///
+    /// `None` if the trash entry `folder` has no marker, or it is corrupted
+    ///
+    pub fn read(folder: &Path) -> Option<DeletionMarker> {
+        let s = read_utf8_file(&folder.join(DELETION_MARKER_FILE)).ok()?;
+        serde_json::from_str(&s).ok()
+    }
+
+    pub fn write(&self, folder: &Path) -> Result<(), Error> {
+        match serde_json::to_string_pretty(self) {
+            Ok(s) => write_atomic(&folder.join(DELETION_MARKER_FILE), s.as_bytes()),
+            Err(e) => Err(Error::CopyError(format!("{}", e))),
+        }
+    }
+
+    pub fn deleted_time(&self) -> Option<SystemTime> {
+        let millis = parse_rfc3339(&self.deleted_at)?;
+        Some(UNIX_EPOCH + Duration::from_millis(millis as u64))
+    }
+}
+
+///
+/// when a trash entry was trashed: from its `DeletionMarker`, or else its own mtime,
+/// as for entries trashed before markers were written
+///
+pub fn trashed_at(entry: &Path) -> Option<SystemTime> {
+    match DeletionMarker::read(entry).and_then(|marker| marker.deleted_time()) {
+        Some(time) => Some(time),
+        None => std::fs::symlink_metadata(entry).ok()?.modified().ok(),
+    }
+}
+
+///
+/// remove entries in `TEMP_ZIP_PATH` not modified within `older_than`.
+/// The in-progress registry stays locked while cleaning,
+/// so no operation can start writing an entry being removed.
+///
+pub fn clean_temp(older_than: Duration) -> CleanReport {
+    let in_progress = IN_PROGRESS.lock().unwrap();
+    clean_folder(Path::new(TEMP_ZIP_PATH), older_than, &in_progress)
+}
+
+///
+/// remove trash entries trashed longer than `retention` ago, logging each removed entry
+///
+pub fn purge_expired_trash(retention: Duration) -> Vec<PathBuf> {
+    purge_folder(&Env::get().trash_dir, retention, SystemTime::now())
+}
+
+///
+/// remove the top-level entries of `folder` trashed longer than `retention` ago,
+/// as told by `trashed_at`
+///
+fn purge_folder(folder: &Path, retention: Duration, now: SystemTime) -> Vec<PathBuf> {
+    let mut purged = Vec::new();
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return purged,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        match trashed_at(&path).map(|time| now.duration_since(time)) {
+            Some(Ok(age)) if age > retention => {}
+            _ => continue,
+        }
+        let removed = if metadata.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(_) => {
+                println!("purged `{}` from trash", path.display());
+                purged.push(path);
+            }
+            Err(e) => eprintln!("failed to purge `{}` from trash: {}", path.display(), e),
+        }
+    }
+    purged
+}
+
+///
+/// remove the top-level entries of `folder` whose latest modification
+/// is older than `older_than`, except those overlapping `in_progress` paths
+///
+fn clean_folder(
+    folder: &Path,
+    older_than: Duration,
+    in_progress: &HashSet<PathBuf>,
+) -> CleanReport {
+    let mut report = CleanReport::default();
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return report,
+    };
+    let now = SystemTime::now();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if in_progress
+            .iter()
+            .any(|p| p.starts_with(&path) || path.starts_with(p))
+        {
+            continue;
+        }
+        let (latest, files, bytes) = match entry_stats(&path) {
+            Some(stats) => stats,
+            None => continue,
+        };
+        match now.duration_since(latest) {
+            Ok(age) if age >= older_than => {}
+            _ => continue,
+        }
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(_) => {
+                report.files_removed += files;
+                report.bytes_freed += bytes;
+            }
+            Err(e) => eprintln!("failed to remove temp `{}`: {}", path.display(), e),
+        }
+    }
+    report
+}
+
+///
+/// the latest modification time, file count and total size of an entry
+///
+pub fn entry_stats(path: &Path) -> Option<(SystemTime, usize, u64)> {
+    let mut latest = std::fs::symlink_metadata(path).ok()?.modified().ok()?;
+    let mut files = 0;
+    let mut bytes = 0;
+    for e in walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let metadata = e.metadata().ok()?;
+        latest = latest.max(metadata.modified().ok()?);
+        if !metadata.is_dir() {
+            files += 1;
+            bytes += metadata.len();
+        }
+    }
+    Some((latest, files, bytes))
+}
+
+#[cfg(test)]
+mod test_cleanup_mod {
+    use super::*;
+    use crate::utils::set_mtime;
+
+    fn write_aged(path: &Path, content: &str, age: Duration) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        set_mtime(path, SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn age_filtering() {
+        let folder = Path::new("test_cleanup");
+        let hour = Duration::from_secs(3600);
+        write_aged(&folder.join("old.zip"), "old", 3 * hour);
+        write_aged(&folder.join("new.zip"), "new", Duration::from_secs(0));
+        write_aged(&folder.join("old_dir/a.txt"), "a", 3 * hour);
+        write_aged(&folder.join("old_dir/b.txt"), "bb", 3 * hour);
+        set_mtime(&folder.join("old_dir"), SystemTime::now() - 3 * hour).unwrap();
+        // a folder with a recently modified file is kept
+        write_aged(&folder.join("mixed_dir/old.txt"), "old", 3 * hour);
+        write_aged(
+            &folder.join("mixed_dir/new.txt"),
+            "new",
+            Duration::from_secs(0),
+        );
+        // in-progress paths are never removed
+        write_aged(&folder.join("busy/c.txt"), "c", 3 * hour);
+        let mut in_progress = HashSet::new();
+        set_mtime(&folder.join("busy"), SystemTime::now() - 3 * hour).unwrap();
+        in_progress.insert(folder.join("busy"));
+
+        let report = clean_folder(folder, 2 * hour, &in_progress);
+        assert_eq!(
+            report,
+            CleanReport {
+                files_removed: 3,
+                bytes_freed: 6,
+            }
+        );
+        assert!(!folder.join("old.zip").exists());
+        assert!(!folder.join("old_dir").exists());
+        assert!(folder.join("new.zip").exists());
+        assert!(folder.join("mixed_dir/old.txt").exists());
+        assert!(folder.join("busy/c.txt").exists());
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn trash_retention_boundary() {
+        let folder = Path::new("test_trash_retention");
+        let day = Duration::from_secs(24 * 3600);
+        let hour = Duration::from_secs(3600);
+        let now = SystemTime::now();
+        write_aged(&folder.join("expired/task.yaml"), "old", 31 * day);
+        set_mtime(&folder.join("expired"), now - (30 * day + hour)).unwrap();
+        // files inside are old, but the entry itself was trashed recently
+        write_aged(&folder.join("recent/task.yaml"), "old", 31 * day);
+        set_mtime(&folder.join("recent"), now - (30 * day - hour)).unwrap();
+
+        // the marker wins over a recent mtime, e.g. from writing the marker itself
+        write_aged(&folder.join("marked/task.yaml"), "old", 31 * day);
+        DeletionMarker::new("marked", now - 31 * day)
+            .write(&folder.join("marked"))
+            .unwrap();
+        assert_eq!(
+            trashed_at(&folder.join("marked")).unwrap(),
+            UNIX_EPOCH
+                + Duration::from_secs(
+                    (now - 31 * day)
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs()
+                )
+        );
+
+        let mut purged = purge_folder(folder, 30 * day, now);
+        purged.sort();
+        assert_eq!(purged, vec![folder.join("expired"), folder.join("marked")]);
+        assert!(!folder.join("expired").exists());
+        assert!(folder.join("recent/task.yaml").exists());
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
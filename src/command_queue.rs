@@ -0,0 +1,232 @@
+use crate::error::Error;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::{Condvar, Mutex};
+
+///
+/// runs at most `concurrency` operations at a time, in the order they arrive.
+/// At most `max_pending` operations wait for their turn, and further operations
+/// are rejected with `Error::LaunchctlBusy` instead of piling up.
+///
+pub struct CommandQueue {
+    concurrency: usize,
+    max_pending: usize,
+    state: Mutex<QueueState>,
+    turn: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    in_flight: usize,
+    pending: usize,
+    /// the ticket of the next operation to wait
+    next_ticket: u64,
+    /// the ticket of the next operation to start
+    serving: u64,
+}
+
+///
+/// the depth of a `CommandQueue`, for `/health` and `/metrics`
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone)]
+pub struct QueueStats {
+    concurrency: usize,
+    max_pending: usize,
+    in_flight: usize,
+    pending: usize,
+}
+
+impl QueueStats {
+    ///
+    /// gauges in the Prometheus text format, named `<name>_in_flight` and `<name>_pending`
+    ///
+    pub fn render(&self, name: &str) -> String {
+        let mut text = String::new();
+        for (gauge, value) in &[("in_flight", self.in_flight), ("pending", self.pending)] {
+            text += &format!(
+                "# TYPE {}_{} gauge\n{}_{} {}\n",
+                name, gauge, name, gauge, value
+            );
+        }
+        text
+    }
+}
+
+///
+/// frees the slot of an operation even if it panics
+///
+struct Slot<'a>(&'a CommandQueue);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.in_flight -= 1;
+        self.0.turn.notify_all();
+    }
+}
+
+impl CommandQueue {
+    pub fn new(concurrency: usize, max_pending: usize) -> CommandQueue {
+        CommandQueue {
+            concurrency: concurrency.max(1),
+            max_pending,
+            state: Mutex::new(QueueState::default()),
+            turn: Condvar::new(),
+        }
+    }
+
+    ///
+    /// run `operation` once a slot is free, or fail right away if the queue is full
+    ///
+    pub fn run<T, F: FnOnce() -> Result<T, Error>>(&self, operation: F) -> Result<T, Error> {
+        let _slot = self.acquire()?;
+        operation()
+    }
+
+    fn acquire(&self) -> Result<Slot<'_>, Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending == 0 && state.in_flight < self.concurrency {
+            state.in_flight += 1;
+            return Ok(Slot(self));
+        }
+        if state.pending >= self.max_pending {
+            return Err(Error::LaunchctlBusy(format!(
+                "{} operations running and {} waiting, try again later",
+                state.in_flight, state.pending
+            )));
+        }
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.pending += 1;
+        while state.serving != ticket || state.in_flight >= self.concurrency {
+            state = self.turn.wait(state).unwrap();
+        }
+        state.serving += 1;
+        state.pending -= 1;
+        state.in_flight += 1;
+        // the next in line may fit into another free slot
+        self.turn.notify_all();
+        Ok(Slot(self))
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.state.lock().unwrap();
+        QueueStats {
+            concurrency: self.concurrency,
+            max_pending: self.max_pending,
+            in_flight: state.in_flight,
+            pending: state.pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_command_queue_mod {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn wait_until<F: Fn() -> bool>(condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn concurrency_never_exceeds_limit() {
+        let queue = Arc::new(CommandQueue::new(2, 100));
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let workers = (0..8)
+            .map(|i| {
+                let (queue, running, most_running) =
+                    (queue.clone(), running.clone(), most_running.clone());
+                std::thread::spawn(move || {
+                    // a slow launchctl
+                    queue.run(|| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most_running.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(i)
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, (0..8).collect::<Vec<_>>());
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+        assert_eq!(queue.stats().in_flight, 0);
+        assert_eq!(queue.stats().pending, 0);
+    }
+
+    #[test]
+    fn overflow_keeps_queued_work() {
+        let queue = Arc::new(CommandQueue::new(1, 2));
+        let (release, blocked) = channel::<()>();
+        let first = {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                queue.run(|| {
+                    blocked.recv().unwrap();
+                    Ok("first")
+                })
+            })
+        };
+        wait_until(|| queue.stats().in_flight == 1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut queued = Vec::new();
+        for name in ["second", "third"].iter() {
+            let (worker_queue, order) = (queue.clone(), order.clone());
+            queued.push(std::thread::spawn(move || {
+                worker_queue.run(|| {
+                    order.lock().unwrap().push(*name);
+                    Ok(*name)
+                })
+            }));
+            let pending = queued.len();
+            wait_until(|| queue.stats().pending == pending);
+        }
+
+        match queue.run(|| Ok("fourth")) {
+            Err(Error::LaunchctlBusy(e)) => {
+                assert_eq!(e, "1 operations running and 2 waiting, try again later")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        release.send(()).unwrap();
+        assert_eq!(first.join().unwrap().unwrap(), "first");
+        for worker in queued {
+            assert!(worker.join().unwrap().is_ok());
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["second", "third"]);
+        assert_eq!(
+            queue.stats(),
+            QueueStats {
+                concurrency: 1,
+                max_pending: 2,
+                in_flight: 0,
+                pending: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn panicking_operation_frees_its_slot() {
+        let queue = Arc::new(CommandQueue::new(1, 0));
+        let panicking = queue.clone();
+        let result =
+            std::thread::spawn(move || panicking.run::<(), _>(|| panic!("launchctl exploded")))
+                .join();
+        assert!(result.is_err());
+        assert_eq!(queue.run(|| Ok(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::initialize::Env;
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub static EXPORT_ROUTE: &str = "get_task_zip";
+pub static UPLOAD_ROUTE: &str = "create_new_tasks";
+/// `Retry-After` while a route is saturated
+static SATURATED_RETRY_AFTER_SECS: u64 = 5;
+
+///
+/// at most `limit` requests of each limited route running at once, shared by all workers.
+/// A request over the limit is refused instead of waiting,
+/// so that a client retrying in a loop cannot pile up expensive work.
+///
+pub struct ConcurrencyLimiter {
+    limits: BTreeMap<&'static str, usize>,
+    running: Arc<Mutex<HashMap<&'static str, usize>>>,
+}
+
+///
+/// the requests of a limited route, for `/health`
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct RouteConcurrency {
+    limit: usize,
+    running: usize,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limits: &[(&'static str, usize)]) -> ConcurrencyLimiter {
+        ConcurrencyLimiter {
+            limits: limits.iter().cloned().collect(),
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    ///
+    /// a limiter of `MAX_CONCURRENT_EXPORTS` and `MAX_CONCURRENT_UPLOADS`
+    ///
+    pub fn shared() -> Arc<ConcurrencyLimiter> {
+        let env = Env::get();
+        Arc::new(ConcurrencyLimiter::new(&[
+            (EXPORT_ROUTE, env.max_concurrent_exports),
+            (UPLOAD_ROUTE, env.max_concurrent_uploads),
+        ]))
+    }
+
+    ///
+    /// a slot of `route` until the permit is dropped, `None` if all slots are taken.
+    /// Routes without a limit always get a slot.
+    ///
+    pub fn try_acquire(&self, route: &'static str) -> Option<Permit> {
+        let limit = match self.limits.get(route) {
+            Some(limit) => *limit,
+            None => {
+                return Some(Permit {
+                    route,
+                    running: None,
+                })
+            }
+        };
+        let mut running = self.running.lock().unwrap();
+        let count = running.entry(route).or_insert(0);
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(Permit {
+            route,
+            running: Some(self.running.clone()),
+        })
+    }
+
+    pub fn stats(&self) -> BTreeMap<&'static str, RouteConcurrency> {
+        let running = self.running.lock().unwrap();
+        self.limits
+            .iter()
+            .map(|(route, limit)| {
+                let stats = RouteConcurrency {
+                    limit: *limit,
+                    running: running.get(route).cloned().unwrap_or(0),
+                };
+                (*route, stats)
+            })
+            .collect()
+    }
+}
+
+///
+/// frees the slot of a request even if it panics or the client goes away
+///
+pub struct Permit {
+    route: &'static str,
+    running: Option<Arc<Mutex<HashMap<&'static str, usize>>>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(running) = &self.running {
+            if let Some(count) = running.lock().unwrap().get_mut(self.route) {
+                *count -= 1;
+            }
+        }
+    }
+}
+
+///
+/// `429 Too Many Requests` with `Retry-After` for a saturated `route`
+///
+pub fn saturated(route: &str) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .header(header::RETRY_AFTER, SATURATED_RETRY_AFTER_SECS.to_string())
+        .body(format!(
+            "too many `{}` requests in progress, retry later",
+            route
+        ))
+}
+
+///
+/// a job in progress with its id, awaited by every request of its key
+///
+type Job<T> = (u64, Shared<BoxFuture<'static, T>>);
+
+///
+/// jobs in progress by key, so that requests for the same result wait for one job
+/// instead of each starting their own
+///
+pub struct InFlight<K, T> {
+    /// the id of each job tells it apart from a later job of the same key
+    jobs: Mutex<HashMap<K, Job<T>>>,
+    next_id: AtomicU64,
+}
+
+impl<K, T> Default for InFlight<K, T> {
+    fn default() -> InFlight<K, T> {
+        InFlight {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Clone> InFlight<K, T> {
+    ///
+    /// the result of the job of `key` in progress, or else of the job returned by `start`.
+    /// `start` is not called if a job is in progress, and its error is returned as it is.
+    /// The job keeps running if the requests waiting for it go away,
+    /// and the next request of `key` waits for it again.
+    ///
+    pub async fn run<F, Fut, E>(&self, key: K, start: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<Fut, E>,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let (id, job) = {
+            let mut jobs = self.jobs.lock().unwrap();
+            match jobs.get(&key) {
+                Some(job) => job.clone(),
+                None => {
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    let job = (id, start()?.boxed().shared());
+                    jobs.insert(key.clone(), job.clone());
+                    job
+                }
+            }
+        };
+        let result = job.await;
+        let mut jobs = self.jobs.lock().unwrap();
+        if matches!(jobs.get(&key), Some((current, _)) if *current == id) {
+            jobs.remove(&key);
+        }
+        Ok(result)
+    }
+
+    ///
+    /// the number of jobs in progress
+    ///
+    pub fn in_progress(&self) -> usize {
+        self.jobs.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod test_concurrency_mod {
+    use super::*;
+    use actix_web::rt::time::delay_for;
+    use futures::future::join_all;
+    use std::time::Duration;
+
+    ///
+    /// a zip taking 100ms, counting how many are started
+    ///
+    fn slow_zip(
+        label: &str,
+        zips: &Arc<AtomicU64>,
+    ) -> impl Future<Output = Result<String, String>> + Send + 'static {
+        zips.fetch_add(1, Ordering::SeqCst);
+        let zip_path = format!("/tmp/{}.zip", label);
+        async move {
+            delay_for(Duration::from_millis(100)).await;
+            Ok(zip_path)
+        }
+    }
+
+    #[test]
+    fn limit_per_route() {
+        let limiter = ConcurrencyLimiter::new(&[(EXPORT_ROUTE, 2), (UPLOAD_ROUTE, 1)]);
+        let first = limiter.try_acquire(EXPORT_ROUTE).unwrap();
+        let _second = limiter.try_acquire(EXPORT_ROUTE).unwrap();
+        assert!(limiter.try_acquire(EXPORT_ROUTE).is_none());
+        // other routes have their own slots
+        let upload = limiter.try_acquire(UPLOAD_ROUTE).unwrap();
+        assert!(limiter.try_acquire(UPLOAD_ROUTE).is_none());
+        assert!(limiter.try_acquire("list_raw_json").is_some());
+        assert_eq!(
+            limiter.stats()[EXPORT_ROUTE],
+            RouteConcurrency {
+                limit: 2,
+                running: 2
+            }
+        );
+        drop(first);
+        drop(upload);
+        assert!(limiter.try_acquire(EXPORT_ROUTE).is_some());
+        assert_eq!(limiter.stats()[EXPORT_ROUTE].running, 1);
+        assert_eq!(limiter.stats()[UPLOAD_ROUTE].running, 0);
+    }
+
+    #[test]
+    fn coalesce_exports_of_same_key() {
+        actix_web::rt::System::new("test_coalesce").block_on(async {
+            let limiter = ConcurrencyLimiter::new(&[(EXPORT_ROUTE, 1)]);
+            let exports = InFlight::<String, Result<String, String>>::default();
+            let zips = Arc::new(AtomicU64::new(0));
+            let (limiter, exports, zips) = (&limiter, &exports, &zips);
+            let export = move |label: &'static str| {
+                exports.run(label.to_string(), move || {
+                    match limiter.try_acquire(EXPORT_ROUTE) {
+                        Some(permit) => {
+                            let zip = slow_zip(label, zips);
+                            Ok(async move {
+                                let _permit = permit;
+                                zip.await
+                            })
+                        }
+                        None => Err(EXPORT_ROUTE),
+                    }
+                })
+            };
+
+            // 40 requests of the same task share a zip, and another task waits for a slot
+            let mut requests: Vec<_> = (0..40).map(|_| export("a")).collect();
+            requests.push(export("b"));
+            let results = join_all(requests).await;
+            assert_eq!(zips.load(Ordering::SeqCst), 1);
+            for result in &results[..40] {
+                assert_eq!(result, &Ok(Ok("/tmp/a.zip".to_string())));
+            }
+            assert_eq!(results[40], Err(EXPORT_ROUTE));
+            assert_eq!(exports.in_progress(), 0);
+            assert_eq!(limiter.stats()[EXPORT_ROUTE].running, 0);
+
+            // a finished export is not reused
+            assert_eq!(export("b").await, Ok(Ok("/tmp/b.zip".to_string())));
+            assert_eq!(export("a").await, Ok(Ok("/tmp/a.zip".to_string())));
+            assert_eq!(zips.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn abandoned_export_keeps_running() {
+        actix_web::rt::System::new("test_abandoned").block_on(async {
+            let exports = InFlight::<String, Result<String, String>>::default();
+            let zips = Arc::new(AtomicU64::new(0));
+            let export = || exports.run("a".to_string(), || Ok::<_, ()>(slow_zip("a", &zips)));
+
+            // the client of the first request goes away while zipping
+            let abandoned = futures::future::select(
+                Box::pin(export()),
+                Box::pin(delay_for(Duration::from_millis(20))),
+            );
+            assert!(matches!(abandoned.await, futures::future::Either::Right(_)));
+            assert_eq!(exports.in_progress(), 1);
+            assert_eq!(export().await, Ok(Ok("/tmp/a.zip".to_string())));
+            assert_eq!(zips.load(Ordering::SeqCst), 1);
+            assert_eq!(exports.in_progress(), 0);
+        });
+    }
+}
@@ -1,15 +1,61 @@
 use crate::error::Error;
-use crate::TASKER_TASK_NAME;
+use crate::{TASKER_TASK_NAME, TASK_OUT_ALIAS, TASK_ROOT_ALIAS};
 use regex::Regex;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::string::FromUtf8Error;
 use std::string::ToString;
+#[cfg(unix)]
+use std::sync::Mutex;
+use strum::VariantNames;
 
 static LABEL_REG: &str = "^[A-Za-z0-9_]+(\\.[A-Za-z0-9_]+)*$";
+/// the longest label without `com.tasker.tasks.`, so that the names of its files
+/// (e.g. `<label>.out.<time>` in trash) stay within 255 bytes
+pub static MAX_LABEL_LEN: usize = 200;
+pub static SECRET_MASK: &str = "***";
+/// an unknown user or group is suggested existing names sharing at least this long a prefix
+static MIN_SIMILAR_PREFIX: usize = 2;
+static MAX_SIMILAR_NAMES: usize = 5;
+static PLACEHOLDER_REG: &str = "\\{\\{([A-Za-z0-9_]+)\\}\\}";
+static ENVIRONMENT_NAME_REG: &str = "^[A-Za-z_][A-Za-z0-9_]*$";
+static PROGRAM_REG: &str = "^/";
+static YAML_DOCUMENT_START: &str = "---\n";
+static UTF8_BOM: char = '\u{feff}';
+static WEEKDAY_NAMES: [&str; 7] = [
+    "Sundays",
+    "Mondays",
+    "Tuesdays",
+    "Wednesdays",
+    "Thursdays",
+    "Fridays",
+    "Saturdays",
+];
+static PROCESS_TYPES: [&str; 4] = ["Background", "Standard", "Adaptive", "Interactive"];
+static MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
 lazy_static! {
     static ref LABEL_REGEX: Regex = Regex::new(LABEL_REG).unwrap();
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(PLACEHOLDER_REG).unwrap();
+    static ref ENVIRONMENT_NAME_REGEX: Regex = Regex::new(ENVIRONMENT_NAME_REG).unwrap();
+    #[cfg(unix)]
+    static ref ACCOUNT_LIST_LOCK: Mutex<()> = Mutex::new(());
 }
 
 macro_rules! check_range_return_err {
@@ -41,14 +87,106 @@ macro_rules! check_option_range_return_err {
 /// Notes:
 /// The Program key must be an absolute path.
 /// </p>
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug, PartialEq, Clone)]
 pub struct Configuration {
     #[serde(rename = "Label")]
+    #[schemars(regex = "LABEL_REG")]
     pub label: String,
+    /// an absolute path
     #[serde(rename = "Program")]
+    #[schemars(regex = "PROGRAM_REG")]
     program: String,
     #[serde(rename = "Configuration")]
     pub configuration: Vec<Config>,
+    /// a template is never loaded, only copied by `create_from_template`
+    #[serde(rename = "Template", default, skip_serializing_if = "is_false")]
+    template: bool,
+    /// whether the task runs as a system daemon or as an agent in the user's GUI session
+    #[serde(rename = "Scope", default, skip_serializing_if = "Scope::is_daemon")]
+    scope: Scope,
+    /// a run longer than this is stopped by tasker, since launchd has no such limit
+    #[serde(
+        rename = "MaxRunSeconds",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    max_run_seconds: Option<u64>,
+    /// variables copied from the environment of tasker into `EnvironmentVariables`
+    /// when the plist is written, since launchd starts tasks with a minimal environment
+    #[serde(
+        rename = "InheritEnvironment",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[schemars(inner(regex = "ENVIRONMENT_NAME_REG"))]
+    inherit_environment: Vec<String>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+///
+/// strip a UTF-8 BOM and turn CRLF into LF, as left by editors on Windows
+///
+pub fn normalize_yaml_input(yaml: &str) -> String {
+    yaml.trim_start_matches(UTF8_BOM).replace("\r\n", "\n")
+}
+
+///
+/// the kind of a plist value that would not survive a round trip through yaml,
+/// at any depth: dates become strings and data becomes lists of integers
+///
+fn yaml_incompatible(value: &plist::Value) -> Option<&'static str> {
+    match value {
+        plist::Value::Date(_) => Some("a date"),
+        plist::Value::Data(_) => Some("data"),
+        plist::Value::Uid(_) => Some("a uid"),
+        plist::Value::Array(values) => values.iter().find_map(yaml_incompatible),
+        plist::Value::Dictionary(dict) => dict.values().find_map(yaml_incompatible),
+        _ => None,
+    }
+}
+
+fn yaml_snippet<T: Serialize>(value: &T) -> String {
+    // serializing configurations does not fail
+    let yaml = serde_yaml::to_string(value).unwrap_or_default();
+    yaml.trim_start_matches("---").trim().to_string()
+}
+
+///
+/// `daemon` tasks are placed in `/Library/LaunchDaemons` and run in the system domain.
+/// `agent` tasks are placed in `/Library/LaunchAgents` and run in the GUI session of
+/// their `UserName`, so that they can use AppleScript, notifications, etc.
+///
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    #[default]
+    Daemon,
+    Agent,
+}
+
+///
+/// how a `Configuration` is checked. `Lenient` skips the checks against the local machine
+/// (existence of `Program`, paths, users and groups), for configurations of another Mac.
+///
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Validation {
+    Strict,
+    Lenient,
+}
+
+impl Validation {
+    fn checks_local_machine(self) -> bool {
+        self == Validation::Strict
+    }
+}
+
+impl Scope {
+    fn is_daemon(&self) -> bool {
+        *self == Scope::Daemon
+    }
 }
 
 impl Configuration {
@@ -57,6 +195,10 @@ impl Configuration {
             label: String::from(label),
             program: String::from(program),
             configuration: Vec::new(),
+            template: false,
+            scope: Scope::Daemon,
+            max_run_seconds: None,
+            inherit_environment: Vec::new(),
         }
     }
 
@@ -75,40 +217,218 @@ impl Configuration {
         self
     }
 
+    /// remove the configuration named `name` if it exists.
+    /// This function does not do any checking
+    pub fn remove_config(mut self, name: &str) -> Configuration {
+        self.configuration.retain(|conf| conf.to_string() != name);
+        self
+    }
+
+    ///
+    /// parse yaml as it is, without checking or appending domain to label
+    ///
+    pub fn parse_yaml(yaml: &str) -> Result<Configuration, Error> {
+        match serde_yaml::from_str::<Configuration>(&normalize_yaml_input(yaml)) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(Error::YamlError(e.to_string())),
+        }
+    }
+
     /// this function does checking, and removes duplicates to keep the last items
     pub fn from_yaml(yaml: &str) -> Result<Configuration, Error> {
-        let config = match serde_yaml::from_str::<Configuration>(yaml) {
-            Ok(config) => config,
-            Err(e) => return Err(Error::YamlError(e.to_string())),
-        }
-        .check_label()?
-        .check_program()?
-        .append_domain();
+        Configuration::from_yaml_with(yaml, Validation::Strict)
+    }
 
-        let mut new_config = Configuration::new(&config.label, &config.program);
-        for c in config.configuration {
-            new_config = new_config.add_config(c.check()?);
+    ///
+    /// `from_yaml` with `Validation::Lenient` to skip checks against the local machine
+    ///
+    pub fn from_yaml_with(yaml: &str, validation: Validation) -> Result<Configuration, Error> {
+        Configuration::parse_yaml(yaml)?.validate(validation)
+    }
+
+    ///
+    /// check a configuration as parsed by `parse_yaml`, like `from_yaml_with`
+    ///
+    pub fn validate(self, validation: Validation) -> Result<Configuration, Error> {
+        let mut builder = Configuration::builder(&self.label, &self.program)
+            .validation(validation)
+            .template(self.template)
+            .scope(self.scope)
+            .max_run_seconds(self.max_run_seconds)
+            .inherit_environment(self.inherit_environment);
+        for c in self.configuration {
+            builder = builder.config(c);
         }
-        Ok(new_config)
+        builder.build()
     }
 
+    ///
+    /// build a plist dictionary with `Label`, `Program`, and every `Config` at top level
+    ///
     pub fn to_plist(&self) -> String {
-        let raw_plist = Configuration::serde_plist(self).unwrap();
-        raw_plist
-            .lines()
-            .filter(|&line| {
-                !line.starts_with("\t\t<dict>")
-                    && !line.starts_with("\t\t</dict>")
-                    && !line.starts_with("\t<key>Configuration</key>")
-                    && !line.starts_with("\t<array>")
-                    && !line.starts_with("\t</array>")
-            })
-            .map(|line| line.replacen("\t\t", "", 1))
-            .collect::<Vec<String>>()
-            .join("\n")
-    }
-
-    pub fn get_user_name(&self) -> Option<String> {
+        let mut dict = plist::Dictionary::new();
+        dict.insert(
+            "Label".to_string(),
+            plist::Value::String(self.label.clone()),
+        );
+        dict.insert(
+            "Program".to_string(),
+            plist::Value::String(self.program.clone()),
+        );
+        for conf in &self.configuration {
+            match conf {
+                // keys not modeled by `Config` are placed at top level as they are
+                Config::Other(others) => {
+                    for (key, value) in others {
+                        dict.insert(key.clone(), value.clone());
+                    }
+                }
+                // each other `Config` serializes to a dictionary with a single key
+                _ => {
+                    if let Some(conf) = Configuration::serde_plist_value(conf).into_dictionary() {
+                        for (key, value) in conf {
+                            dict.insert(key, value);
+                        }
+                    }
+                }
+            }
+        }
+        Configuration::serde_plist(&plist::Value::Dictionary(dict)).unwrap()
+    }
+
+    ///
+    /// parse an existing launchd.plist (XML or binary). Keys modeled by `Config` are
+    /// mapped to their variants, other keys are preserved in `Config::Other`.
+    /// `Program` defaults to the first of `ProgramArguments` if missing.
+    /// Dates and data, which yaml cannot hold as such, are refused.
+    /// The result is not checked: convert it to yaml and use `from_yaml` for checking.
+    ///
+    pub fn from_plist_file(path: &Path) -> Result<Configuration, Error> {
+        let dict = match plist::Value::from_file(path) {
+            Ok(plist::Value::Dictionary(dict)) => dict,
+            _ => {
+                return Err(Error::PlistParseError(format!(
+                    "`{}` is not a plist dictionary",
+                    path.display()
+                )))
+            }
+        };
+        let label = match dict.get("Label").and_then(|label| label.as_string()) {
+            Some(label) => label.to_string(),
+            None => return Err(Error::PlistParseError("`Label` not found".to_string())),
+        };
+        let program = dict.get("Program").or_else(|| {
+            dict.get("ProgramArguments")
+                .and_then(|args| args.as_array())
+                .and_then(|args| args.first())
+        });
+        let program = match program.and_then(|program| program.as_string()) {
+            Some(program) => program.to_string(),
+            None => {
+                return Err(Error::PlistParseError(
+                    "neither `Program` nor `ProgramArguments` found".to_string(),
+                ))
+            }
+        };
+        let mut config = Configuration::new(&label, &program);
+        let mut others = BTreeMap::new();
+        for (key, value) in dict {
+            if key.eq("Label") || key.eq("Program") {
+                continue;
+            }
+            match Config::from_plist_entry(&key, &value) {
+                Some(conf) => config = config.add_config(conf),
+                None => {
+                    if let Some(kind) = yaml_incompatible(&value) {
+                        return Err(Error::PlistParseError(format!(
+                            "`{}` holds {}, which cannot be kept in yaml",
+                            key, kind
+                        )));
+                    }
+                    others.insert(key, value);
+                }
+            }
+        }
+        if !others.is_empty() {
+            config = config.add_config(Config::Other(others));
+        }
+        Ok(config)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        match serde_yaml::to_string(self) {
+            Ok(yaml) => Ok(yaml),
+            Err(e) => Err(Error::YamlError(e.to_string())),
+        }
+    }
+
+    ///
+    /// the yaml stored by tasker: a leading `---`, two-space indentation, `Label`, `Program`
+    /// and `Configuration` in this order (configurations as given), and a trailing newline.
+    /// Serializing it again gives the same bytes.
+    ///
+    pub fn to_canonical_yaml(&self) -> Result<String, Error> {
+        let yaml = self.to_yaml()?;
+        let body = yaml.trim_start_matches(YAML_DOCUMENT_START).trim_end();
+        Ok(String::from(YAML_DOCUMENT_START) + body + "\n")
+    }
+
+    ///
+    /// `yaml` in canonical form (see `to_canonical_yaml`), without checking it or appending
+    /// domain to label, so that it reads back to the same configuration
+    ///
+    pub fn canonicalize_yaml(yaml: &str) -> Result<String, Error> {
+        Configuration::parse_yaml(yaml)?.to_canonical_yaml()
+    }
+
+    pub fn builder(label: &str, program: &str) -> ConfigurationBuilder {
+        ConfigurationBuilder {
+            config: Configuration::new(label, program),
+            validation: Validation::Strict,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn is_template(&self) -> bool {
+        self.template
+    }
+
+    pub fn set_template(&mut self, template: bool) {
+        self.template = template;
+    }
+
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    pub fn max_run_seconds(&self) -> Option<u64> {
+        self.max_run_seconds
+    }
+
+    pub fn inherit_environment(&self) -> &[String] {
+        &self.inherit_environment
+    }
+
+    ///
+    /// a one-line description of when the task runs, like `every 3600s` or
+    /// `daily at 09:15, 13:00`, `None` if nothing starts it
+    ///
+    pub fn schedule_summary(&self) -> Option<String> {
+        Schedule::of(self).summary()
+    }
+
+    pub fn configs(&self) -> &[Config] {
+        &self.configuration
+    }
+
+    pub fn user_name(&self) -> Option<String> {
         for conf in &self.configuration {
             if let Config::UserName(name) = conf {
                 return Some(name.to_string());
@@ -117,7 +437,7 @@ impl Configuration {
         None
     }
 
-    pub fn get_group_name(&self) -> Option<String> {
+    pub fn group_name(&self) -> Option<String> {
         for conf in &self.configuration {
             if let Config::GroupName(name) = conf {
                 return Some(name.to_string());
@@ -126,6 +446,255 @@ impl Configuration {
         None
     }
 
+    pub fn working_directory(&self) -> Option<String> {
+        for conf in &self.configuration {
+            if let Config::WorkingDirectory(path) = conf {
+                return Some(path.to_string());
+            }
+        }
+        None
+    }
+
+    ///
+    /// the command line launchd runs: `Program` followed by `ProgramArguments`
+    /// without argv\[0\], which is `Program` after normalization
+    ///
+    pub fn effective_command(&self) -> Vec<String> {
+        let mut command = vec![self.program.clone()];
+        for conf in &self.configuration {
+            if let Config::ProgramArguments(args) = conf {
+                command.extend(args.iter().skip(1).cloned());
+            }
+        }
+        command
+    }
+
+    ///
+    /// the environment variables set by launchd in addition to its defaults
+    ///
+    pub fn effective_environment(&self) -> BTreeMap<String, String> {
+        let mut environment = BTreeMap::new();
+        for conf in &self.configuration {
+            if let Config::EnvironmentVariables(vars) = conf {
+                environment.extend(vars.clone());
+            }
+        }
+        environment
+    }
+
+    ///
+    /// copy the variables of `InheritEnvironment` found in `environment` into
+    /// `EnvironmentVariables`. Variables set in `EnvironmentVariables` are kept.
+    ///
+    pub fn inherit_environment_from(self, environment: &BTreeMap<String, String>) -> Configuration {
+        let mut variables = self.effective_environment();
+        let mut inherited = false;
+        for name in &self.inherit_environment {
+            if let Some(value) = environment.get(name) {
+                if !variables.contains_key(name) {
+                    variables.insert(name.clone(), value.clone());
+                    inherited = true;
+                }
+            }
+        }
+        if !inherited {
+            return self;
+        }
+        self.add_config(Config::EnvironmentVariables(variables))
+    }
+
+    ///
+    /// this configuration with the values of `EnvironmentVariables` in `keys` replaced by `***`
+    ///
+    pub fn redacted(&self, keys: &[String]) -> Configuration {
+        let mut config = self.clone();
+        for conf in config.configuration.iter_mut() {
+            if let Config::EnvironmentVariables(vars) = conf {
+                for (key, value) in vars.iter_mut() {
+                    if keys.contains(key) {
+                        *value = SECRET_MASK.to_string();
+                    }
+                }
+            }
+        }
+        config
+    }
+
+    ///
+    /// yaml with the values of `EnvironmentVariables` in `keys` replaced by `***`
+    ///
+    pub fn redacted_yaml(&self, keys: &[String]) -> String {
+        // serializing a `Configuration` does not fail
+        self.redacted(keys).to_canonical_yaml().unwrap_or_default()
+    }
+
+    ///
+    /// replace `***` values of `EnvironmentVariables` in `keys` with the values in `previous`,
+    /// so that editing a redacted yaml keeps the secrets
+    ///
+    pub fn restore_secrets(&mut self, previous: &Configuration, keys: &[String]) {
+        let previous = previous.effective_environment();
+        for conf in self.configuration.iter_mut() {
+            if let Config::EnvironmentVariables(vars) = conf {
+                for (key, value) in vars.iter_mut() {
+                    if value == SECRET_MASK && keys.contains(key) {
+                        if let Some(secret) = previous.get(key) {
+                            *value = secret.clone();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    ///
+    /// substitute `{{placeholder}}` in `ProgramArguments` and `EnvironmentVariables` values.
+    /// Every placeholder must have a value, and every value must be used.
+    ///
+    pub fn fill_template(
+        mut self,
+        values: &BTreeMap<String, String>,
+    ) -> Result<Configuration, Error> {
+        let mut missing = BTreeSet::new();
+        let mut used = BTreeSet::new();
+        let mut fill = |s: &str| -> String {
+            PLACEHOLDER_REGEX
+                .replace_all(s, |caps: &regex::Captures| {
+                    let name = &caps[1];
+                    match values.get(name) {
+                        Some(value) => {
+                            used.insert(name.to_string());
+                            value.clone()
+                        }
+                        None => {
+                            missing.insert(name.to_string());
+                            caps[0].to_string()
+                        }
+                    }
+                })
+                .to_string()
+        };
+        for conf in self.configuration.iter_mut() {
+            match conf {
+                Config::ProgramArguments(args) => {
+                    for arg in args.iter_mut() {
+                        *arg = fill(arg);
+                    }
+                }
+                Config::EnvironmentVariables(vars) => {
+                    for value in vars.values_mut() {
+                        *value = fill(value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::MissingTemplateValues(missing.into_iter().collect()));
+        }
+        let unused: Vec<String> = values
+            .keys()
+            .filter(|k| !used.contains(*k))
+            .cloned()
+            .collect();
+        if !unused.is_empty() {
+            return Err(Error::UnusedTemplateValues(unused));
+        }
+        Ok(self)
+    }
+
+    pub fn standard_out_path(&self) -> Option<String> {
+        for conf in &self.configuration {
+            if let Config::StandardOutPath(path) = conf {
+                return Some(path.to_string());
+            }
+        }
+        None
+    }
+
+    pub fn standard_error_path(&self) -> Option<String> {
+        for conf in &self.configuration {
+            if let Config::StandardErrorPath(path) = conf {
+                return Some(path.to_string());
+            }
+        }
+        None
+    }
+
+    ///
+    /// replace the scheduling configurations with `schedule`, keeping the others
+    ///
+    pub fn with_schedule(
+        self,
+        schedule: &Schedule,
+        allow_both: bool,
+    ) -> Result<Configuration, Error> {
+        let configs = schedule.to_configs(allow_both)?;
+        let mut config = self;
+        for name in SCHEDULE_CONFIG_NAMES.iter() {
+            config = config.remove_config(name);
+        }
+        for conf in configs {
+            config = config.add_config(conf);
+        }
+        Ok(config)
+    }
+
+    ///
+    /// the changes from `self` to `other`. Top-level keys and configurations are
+    /// compared by name, so reordering configurations is not a change.
+    ///
+    pub fn diff(&self, other: &Configuration) -> Vec<ConfigChange> {
+        let before = self.entries();
+        let after = other.entries();
+        let mut changes = Vec::new();
+        for (name, old) in &before {
+            match after.get(name) {
+                None => changes.push(ConfigChange::Removed {
+                    name: name.clone(),
+                    before: old.clone(),
+                }),
+                Some(new) if new != old => changes.push(ConfigChange::Modified {
+                    name: name.clone(),
+                    before: old.clone(),
+                    after: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for (name, new) in &after {
+            if !before.contains_key(name) {
+                changes.push(ConfigChange::Added {
+                    name: name.clone(),
+                    after: new.clone(),
+                });
+            }
+        }
+        changes
+    }
+
+    ///
+    /// yaml snippet of each top-level key and each configuration, by name
+    ///
+    fn entries(&self) -> BTreeMap<String, String> {
+        let mut entries = BTreeMap::new();
+        let mut top_level = self.clone();
+        top_level.configuration.clear();
+        if let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::to_value(&top_level) {
+            for (key, value) in mapping {
+                if let Some(name) = key.as_str().filter(|name| *name != "Configuration") {
+                    let mut entry = serde_yaml::Mapping::new();
+                    entry.insert(key.clone(), value);
+                    entries.insert(name.to_string(), yaml_snippet(&entry));
+                }
+            }
+        }
+        for conf in &self.configuration {
+            entries.insert(conf.to_string(), yaml_snippet(conf));
+        }
+        entries
+    }
+
     fn serde_plist<T>(ser: &T) -> Result<String, FromUtf8Error>
     where
         T: Serialize,
@@ -135,32 +704,74 @@ impl Configuration {
         String::from_utf8(buf)
     }
 
-    fn check_program(self) -> Result<Configuration, Error> {
-        let program = Path::new(&self.program);
-        if !program.is_absolute() {
-            return Err(Error::ConfigProgramError(format!(
-                "program path `{}` is not an absolute path",
-                &self.program
-            )));
-        }
-        if !program.is_file() {
-            return Err(Error::ConfigProgramError(format!(
-                "program `{}` is not found or not permitted to access",
-                &self.program
-            )));
+    fn serde_plist_value<T>(ser: &T) -> plist::Value
+    where
+        T: Serialize,
+    {
+        let mut buf = Vec::new();
+        plist::to_writer_xml(&mut buf, ser).expect("inner error (function: serde_plist_value)");
+        plist::Value::from_reader_xml(&buf[..]).expect("inner error (function: serde_plist_value)")
+    }
+
+    fn check_program(self, validation: Validation) -> Result<Configuration, Error> {
+        check_program_str(&self.program, validation)?;
+        Ok(self)
+    }
+
+    fn check_label(self) -> Result<Configuration, Error> {
+        check_label_str(&self.label)?;
+        Ok(self)
+    }
+
+    ///
+    /// an agent runs in the GUI session of its `UserName`, which is therefore required
+    ///
+    fn check_scope(self) -> Result<Configuration, Error> {
+        if self.scope == Scope::Agent && self.user_name().is_none() {
+            return Err(Error::ScopeError(
+                "`Scope: agent` requires `UserName` of the GUI session to run in".to_string(),
+            ));
         }
         Ok(self)
     }
 
     ///
-    /// label only allows patterns as follows `[A-Za-z]+(\\.[A-Za-z]+)*`.
+    /// with `RootDirectory` (chroot), `WorkingDirectory` is a path inside the chroot,
+    /// so it must be absolute and cannot use the `~root~/` alias.
+    /// Otherwise, `WorkingDirectory` must be an existing directory or use the alias.
     ///
-    fn check_label(self) -> Result<Configuration, Error> {
-        if !LABEL_REGEX.is_match(&self.label) {
-            return Err(Error::ConfigLabelError(format!(
-                "`{}` is not a valid label",
-                &self.label
-            )));
+    fn check_working_directory(self, validation: Validation) -> Result<Configuration, Error> {
+        let mut root_directory = None;
+        let mut working_directory = None;
+        for conf in &self.configuration {
+            match conf {
+                Config::RootDirectory(p) => root_directory = Some(p),
+                Config::WorkingDirectory(p) => working_directory = Some(p),
+                _ => {}
+            }
+        }
+        match (root_directory, working_directory) {
+            (Some(root), Some(working)) => {
+                if Config::is_aliased(working) || !Path::new(working).is_absolute() {
+                    return Err(Error::ConfigPathError(format!(
+                        "`WorkingDirectory` `{}` must be an absolute path inside `RootDirectory`",
+                        working
+                    )));
+                }
+                if validation.checks_local_machine() && !Config::is_aliased(root) {
+                    let inside = Path::new(root).join(working.trim_start_matches('/'));
+                    if !inside.is_dir() {
+                        return Err(Error::ConfigPathError(format!(
+                            "`{}` is not a directory inside `RootDirectory` `{}`",
+                            working, root
+                        )));
+                    }
+                }
+            }
+            (None, Some(working)) => {
+                Config::check_path(working.to_string(), validation)?;
+            }
+            _ => {}
         }
         Ok(self)
     }
@@ -171,31 +782,225 @@ impl Configuration {
     }
 }
 
-#[derive(Deserialize, Serialize, PartialEq, Debug, Display)]
+///
+/// build a `Configuration`, which is checked on `build()` like `Configuration::from_yaml`
+///
+pub struct ConfigurationBuilder {
+    config: Configuration,
+    validation: Validation,
+}
+
+impl ConfigurationBuilder {
+    ///
+    /// add or replace a configuration
+    ///
+    pub fn config(mut self, config: Config) -> ConfigurationBuilder {
+        self.config = self.config.add_config(config);
+        self
+    }
+
+    ///
+    /// mark the configuration as a template
+    ///
+    pub fn template(mut self, template: bool) -> ConfigurationBuilder {
+        self.config.template = template;
+        self
+    }
+
+    ///
+    /// run as a system daemon or as an agent of `UserName`
+    ///
+    pub fn scope(mut self, scope: Scope) -> ConfigurationBuilder {
+        self.config.scope = scope;
+        self
+    }
+
+    ///
+    /// stop runs longer than `max_run_seconds`
+    ///
+    pub fn max_run_seconds(mut self, max_run_seconds: Option<u64>) -> ConfigurationBuilder {
+        self.config.max_run_seconds = max_run_seconds;
+        self
+    }
+
+    ///
+    /// copy these variables from the environment of tasker when the plist is written
+    ///
+    pub fn inherit_environment(mut self, names: Vec<String>) -> ConfigurationBuilder {
+        self.config.inherit_environment = names;
+        self
+    }
+
+    ///
+    /// check against the local machine (default), or not
+    ///
+    pub fn validation(mut self, validation: Validation) -> ConfigurationBuilder {
+        self.validation = validation;
+        self
+    }
+
+    pub fn build(self) -> Result<Configuration, Error> {
+        let validation = self.validation;
+        let config = self
+            .config
+            .check_label()?
+            .check_program(validation)?
+            .append_domain();
+
+        let mut new_config = Configuration::new(&config.label, &config.program);
+        new_config.template = config.template;
+        new_config.scope = config.scope;
+        new_config.max_run_seconds = config.max_run_seconds;
+        check_option_range_return_err!(new_config, max_run_seconds, 1, u64::MAX);
+        for name in &config.inherit_environment {
+            check_environment_name("InheritEnvironment", name)?;
+        }
+        new_config.inherit_environment = config.inherit_environment;
+        for c in config.configuration {
+            new_config = new_config.add_config(c.check_with(validation)?);
+        }
+        new_config
+            .check_working_directory(validation)?
+            .check_scope()
+    }
+}
+
+///
+/// names of the `Config` variants, in the order they are declared
+///
+pub static CONFIG_NAMES: &[&str] = Config::VARIANTS;
+
+#[derive(
+    Deserialize, Serialize, JsonSchema, PartialEq, Debug, Display, EnumVariantNames, Clone,
+)]
 pub enum Config {
     ProgramArguments(Vec<String>),
     EnvironmentVariables(BTreeMap<String, String>),
-    KeepAlive(AliveCondition),
+    KeepAlive(KeepAliveValue),
     RunAtLoad(bool),
     UserName(String),
     GroupName(String),
     WorkingDirectory(String),
     RootDirectory(String),
-    ExitTimeOut(i64),
-    StartInterval(i64),
+    ExitTimeOut(#[schemars(range(min = 1))] i64),
+    StartInterval(#[schemars(range(min = 1))] i64),
     StartCalendarInterval(Vec<CalendarInterval>),
     StandardInPath(String),
     StandardOutPath(String),
     StandardErrorPath(String),
     SoftResourceLimit(ResourceLimit),
     HardResourceLimits(ResourceLimit),
+    /// keys of launchd.plist not modeled above, preserved as they are
+    #[schemars(schema_with = "other_schema")]
+    Other(BTreeMap<String, plist::Value>),
+}
+
+///
+/// `Other` takes any keys of launchd.plist, of which `ProcessType` is the most common
+///
+fn other_schema(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    let process_type = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(PROCESS_TYPES.iter().map(|t| (*t).into()).collect()),
+        ..Default::default()
+    };
+    let object = schema.object();
+    object
+        .properties
+        .insert("ProcessType".to_string(), process_type.into());
+    object.additional_properties = Some(Box::new(gen.subschema_for::<serde_json::Value>()));
+    schema.into()
+}
+
+///
+/// the JSON Schema (2020-12) of a task yaml, for completion and validation in editors.
+/// It is derived from `Configuration`, so it follows every change of `Config`.
+/// Checks against this machine, like whether `Program` exists, are not in the schema.
+///
+pub fn json_schema() -> serde_json::Value {
+    let mut settings = SchemaSettings::draft2019_09();
+    settings.definitions_path = "#/$defs/".to_string();
+    settings.meta_schema = Some("https://json-schema.org/draft/2020-12/schema".to_string());
+    let mut root = settings
+        .into_generator()
+        .into_root_schema_for::<Configuration>();
+    root.schema.metadata = Some(Box::new(Metadata {
+        title: Some("tasker task".to_string()),
+        ..Default::default()
+    }));
+    let mut schema = serde_json::to_value(root).unwrap_or_default();
+    if let Some(object) = schema.as_object_mut() {
+        if let Some(definitions) = object.remove("definitions") {
+            object.insert("$defs".to_string(), definitions);
+        }
+    }
+    schema
 }
 
 impl Config {
+    ///
+    /// parse and check a single configuration such as `StartInterval: 3600`.
+    /// `Label` and `Program` are not configurations and are rejected.
+    ///
+    pub fn from_yaml(yaml: &str) -> Result<Config, Error> {
+        let value = match serde_yaml::from_str::<serde_yaml::Value>(&normalize_yaml_input(yaml)) {
+            Ok(value) => value,
+            Err(e) => return Err(Error::YamlError(e.to_string())),
+        };
+        if let Some(mapping) = value.as_mapping() {
+            for key in ["Label", "Program"].iter() {
+                if mapping.contains_key(&serde_yaml::Value::String(key.to_string())) {
+                    return Err(Error::InvalidConfigPatch(format!(
+                        "`{}` cannot be changed as a configuration",
+                        key
+                    )));
+                }
+            }
+        }
+        match serde_yaml::from_value::<Config>(value) {
+            Ok(config) => config.check(),
+            Err(e) => Err(Error::YamlError(e.to_string())),
+        }
+    }
+
+    ///
+    /// a single configuration as yaml, like `---\nStartInterval: 3600`
+    ///
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        match serde_yaml::to_string(self) {
+            Ok(yaml) => Ok(yaml),
+            Err(e) => Err(Error::YamlError(e.to_string())),
+        }
+    }
+
+    ///
+    /// map a key of launchd.plist to `Config`,
+    /// `None` if the key is not modeled or its value does not match
+    ///
+    fn from_plist_entry(key: &str, value: &plist::Value) -> Option<Config> {
+        if key.eq("Other") {
+            return None;
+        }
+        let mut entry = serde_yaml::Mapping::new();
+        entry.insert(
+            serde_yaml::Value::String(key.to_string()),
+            serde_yaml::to_value(value).ok()?,
+        );
+        serde_yaml::from_value(serde_yaml::Value::Mapping(entry)).ok()
+    }
+
     ///
     /// each configuration must satisfy several details
     ///
     fn check(self) -> Result<Config, Error> {
+        self.check_with(Validation::Strict)
+    }
+
+    fn check_with(self, validation: Validation) -> Result<Config, Error> {
         match self {
             Config::SoftResourceLimit(limit) => match limit.check() {
                 Ok(l) => Ok(Config::SoftResourceLimit(l)),
@@ -223,52 +1028,74 @@ impl Config {
                 check_range_return_err!(StartInterval, t, 1, i64::MAX);
                 Ok(Config::StartInterval(t))
             }
-            Config::WorkingDirectory(p) => {
-                let p: String = Config::check_path(p)?;
-                Ok(Config::WorkingDirectory(p))
+            Config::KeepAlive(keep_alive) => Ok(Config::KeepAlive(keep_alive.check()?)),
+            Config::EnvironmentVariables(variables) => {
+                for (name, value) in &variables {
+                    check_environment_name("EnvironmentVariables", name)?;
+                    if value.contains('\0') {
+                        return Err(Error::IllegalEnvironmentVariable(format!(
+                            "the value of `{}` contains NUL",
+                            name
+                        )));
+                    }
+                }
+                Ok(Config::EnvironmentVariables(variables))
             }
             Config::RootDirectory(p) => {
-                let p: String = Config::check_path(p)?;
+                let p: String = Config::check_path(p, validation)?;
                 Ok(Config::RootDirectory(p))
             }
             Config::StandardInPath(p) => {
-                let p: String = Config::check_file(p)?;
+                let p: String = Config::check_file(p, validation)?;
                 Ok(Config::StandardInPath(p))
             }
             Config::StandardOutPath(p) => {
-                let p: String = Config::check_file(p)?;
+                let p: String = Config::check_parent_path(p, validation)?;
                 Ok(Config::StandardOutPath(p))
             }
             Config::StandardErrorPath(p) => {
-                let p: String = Config::check_file(p)?;
+                let p: String = Config::check_parent_path(p, validation)?;
                 Ok(Config::StandardErrorPath(p))
             }
             Config::UserName(name) => {
-                if users::get_user_by_name(&name).is_some() {
-                    Ok(Config::UserName(name))
-                } else {
-                    Err(Error::IllegalUserName(format!(
-                        "user `{}` does not exist",
-                        name
-                    )))
+                let name = name.trim().to_string();
+                if validation.checks_local_machine() && !user_exists(&name) {
+                    let similar = similar_names(&name, user_names());
+                    return Err(Error::ConfigUnknownUser(unknown_account(
+                        "user", &name, &similar,
+                    )));
                 }
+                Ok(Config::UserName(name))
             }
             Config::GroupName(name) => {
-                if users::get_group_by_name(&name).is_some() {
-                    Ok(Config::GroupName(name))
-                } else {
-                    Err(Error::IllegalGroupName(format!(
-                        "group `{}` does not exist",
-                        name
-                    )))
+                let name = name.trim().to_string();
+                if validation.checks_local_machine() && !group_exists(&name) {
+                    let similar = similar_names(&name, group_names());
+                    return Err(Error::ConfigUnknownGroup(unknown_account(
+                        "group", &name, &similar,
+                    )));
                 }
+                Ok(Config::GroupName(name))
             }
             _ => Ok(self),
         }
     }
 
-    fn check_path(path: String) -> Result<String, Error> {
-        if !Path::new(&path).is_dir() {
+    ///
+    /// whether a path starts with `~root~/` or `~out~/` alias
+    ///
+    fn is_aliased(path: &str) -> bool {
+        path.starts_with(TASK_ROOT_ALIAS) || path.starts_with(TASK_OUT_ALIAS)
+    }
+
+    ///
+    /// paths using `~root~/` or `~out~/` alias are checked after the task folder is created
+    ///
+    fn check_path(path: String, validation: Validation) -> Result<String, Error> {
+        if !validation.checks_local_machine() {
+            return Ok(path);
+        }
+        if !Config::is_aliased(&path) && !Path::new(&path).is_dir() {
             return Err(Error::ConfigPathError(format!(
                 "`{}` is not a directory",
                 path
@@ -277,52 +1104,223 @@ impl Config {
         Ok(path)
     }
 
-    fn check_file(path: String) -> Result<String, Error> {
-        if !Path::new(&path).is_file() {
+    fn check_file(path: String, validation: Validation) -> Result<String, Error> {
+        if !validation.checks_local_machine() {
+            return Ok(path);
+        }
+        if !Config::is_aliased(&path) && !Path::new(&path).is_file() {
             return Err(Error::ConfigPathError(format!("`{}` is not a file", path)));
         }
-        Ok(path)
+        Ok(path)
+    }
+
+    ///
+    /// output files are created by launchd, so only their parent directories must exist
+    ///
+    fn check_parent_path(path: String, validation: Validation) -> Result<String, Error> {
+        if !validation.checks_local_machine() || Config::is_aliased(&path) {
+            return Ok(path);
+        }
+        match Path::new(&path).parent() {
+            Some(parent) if parent.is_dir() => Ok(path),
+            Some(parent) => Err(Error::ConfigPathError(format!(
+                "`{}` is not a directory",
+                parent.display()
+            ))),
+            None => Err(Error::ConfigPathError(format!("`{}` is not a file", path))),
+        }
+    }
+}
+
+/// KeepAlive
+///
+/// Either a boolean, to keep the job alive unconditionally (`KeepAlive: true`) or not,
+/// or a dictionary of `AliveCondition`. The form given is kept in yaml and plist.
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Clone)]
+#[serde(untagged)]
+pub enum KeepAliveValue {
+    Bool(bool),
+    Conditions(AliveCondition),
+}
+
+impl KeepAliveValue {
+    pub fn check(self) -> Result<KeepAliveValue, Error> {
+        match self {
+            KeepAliveValue::Conditions(condition) => {
+                Ok(KeepAliveValue::Conditions(condition.check()?))
+            }
+            b => Ok(b),
+        }
+    }
+
+    ///
+    /// apply `f` to each path of `PathState`, if any
+    ///
+    pub fn map_path_state<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut String) -> Result<(), Error>,
+    {
+        match self {
+            KeepAliveValue::Conditions(condition) => condition.map_path_state(f),
+            KeepAliveValue::Bool(_) => Ok(()),
+        }
+    }
+}
+
+/// AliveCondition
+///
+/// <ul>
+///
+/// <li>SuccessfulExit (boolean):<br>
+/// If true, the job will be restarted as long as the program exits and with an exit status of zero. If
+/// false, the job will be restarted in the inverse condition.  This key implies that "RunAtLoad" is set to
+/// true, since the job needs to run at least once before an exit status can be determined.</li>
+///
+/// <li>OtherJobEnabled (dictionary of booleans):<br>
+/// Each key in this dictionary is the name of another job. If the value is true, then the job will be kept
+/// alive as long as one of the specified other jobs is loaded in launchd(8).</li>
+///
+/// <p>
+/// NOTE: This key only evaluates whether the job is loaded, not whether it is running. Use of this key is
+/// highly discouraged. If multiple jobs need to coordinate coordinate their lifecycles, they should
+/// establish contracts using IPC.
+/// </p>
+///
+/// <li>Crashed (boolean):<br>
+/// If true, the the job will be restarted as long as it exited due to a signal which is typically
+/// associated with a crash (SIGILL, SIGSEGV, etc.). If false, the job will be restarted in the
+/// inverse condition.</li>
+///
+/// <li>NetworkState (boolean):<br>
+/// If true, the job will be kept alive as long as the network is up, where up is defined as at least one
+/// non-loopback interface being up and having IPv4 or IPv6 addresses assigned to it. If false, the job will
+/// be kept alive in the inverse condition.</li>
+///
+/// <li>PathState (dictionary of booleans):<br>
+/// Each key in this dictionary is a file-system path. If the value of the key is true, then the job will be
+/// kept alive as long as the path exists. If false, the job will be kept alive in the inverse condition.
+/// The intent of this feature is that two or more jobs may create semaphores in the file-system namespace.
+/// The paths must be absolute, and may start with `~root~/` or `~out~/` alias.</li>
+///
+/// </ul>
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Clone, Default)]
+pub struct AliveCondition {
+    #[serde(rename = "SuccessfulExit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    successful_exit: Option<bool>,
+    #[serde(rename = "OtherJobEnabled")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    other_job_enabled: Option<BTreeMap<String, bool>>,
+    #[serde(rename = "Crashed")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crashed: Option<bool>,
+    #[serde(rename = "NetworkState")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network_state: Option<bool>,
+    #[serde(rename = "PathState")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path_state: Option<BTreeMap<String, bool>>,
+}
+
+impl AliveCondition {
+    ///
+    /// an empty condition, to be built like this, and checked by `check`:
+    ///
+    /// ```
+    /// use tasker::config::{AliveCondition, Config, KeepAliveValue};
+    ///
+    /// let condition = AliveCondition::new()
+    ///     .crashed(true)
+    ///     .successful_exit(false)
+    ///     .other_job("com.tasker.tasks.db", true);
+    /// let keep_alive = Config::KeepAlive(KeepAliveValue::Conditions(condition));
+    /// assert_eq!(
+    ///     serde_yaml::to_string(&vec![keep_alive]).unwrap(),
+    ///     "---\n\
+    ///      - KeepAlive:\n    \
+    ///          SuccessfulExit: false\n    \
+    ///          OtherJobEnabled:\n      \
+    ///            com.tasker.tasks.db: true\n    \
+    ///          Crashed: true"
+    /// );
+    /// ```
+    ///
+    pub fn new() -> AliveCondition {
+        AliveCondition::default()
+    }
+
+    pub fn successful_exit(mut self, successful_exit: bool) -> AliveCondition {
+        self.successful_exit = Some(successful_exit);
+        self
+    }
+
+    ///
+    /// keep the task alive while job `label` is loaded (`true`) or not loaded (`false`)
+    ///
+    pub fn other_job(mut self, label: &str, enabled: bool) -> AliveCondition {
+        self.other_job_enabled
+            .get_or_insert_with(BTreeMap::new)
+            .insert(label.to_string(), enabled);
+        self
+    }
+
+    pub fn crashed(mut self, crashed: bool) -> AliveCondition {
+        self.crashed = Some(crashed);
+        self
+    }
+
+    pub fn network_state(mut self, network_state: bool) -> AliveCondition {
+        self.network_state = Some(network_state);
+        self
+    }
+
+    ///
+    /// keep the task alive while `path` exists (`true`) or does not exist (`false`)
+    ///
+    pub fn path_state(mut self, path: &str, exists: bool) -> AliveCondition {
+        self.path_state
+            .get_or_insert_with(BTreeMap::new)
+            .insert(path.to_string(), exists);
+        self
+    }
+
+    ///
+    /// paths of `PathState` must be absolute, or use `~root~/` or `~out~/` alias
+    ///
+    pub fn check(self) -> Result<AliveCondition, Error> {
+        if let Some(path_state) = &self.path_state {
+            for path in path_state.keys() {
+                if !Config::is_aliased(path) && !Path::new(path).is_absolute() {
+                    return Err(Error::ConfigPathError(format!(
+                        "`PathState` path `{}` is not absolute",
+                        path
+                    )));
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    ///
+    /// apply `f` to each path of `PathState`.
+    /// The map is rebuilt since its keys cannot be changed in place.
+    ///
+    pub fn map_path_state<F>(&mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut String) -> Result<(), Error>,
+    {
+        if let Some(path_state) = self.path_state.take() {
+            let mut new_path_state = BTreeMap::new();
+            for (mut path, state) in path_state {
+                f(&mut path)?;
+                new_path_state.insert(path, state);
+            }
+            self.path_state = Some(new_path_state);
+        }
+        Ok(())
     }
 }
 
-/// AliveCondition
-///
-/// <ul>
-///
-/// <li>SuccessfulExit (boolean):<br>
-/// If true, the job will be restarted as long as the program exits and with an exit status of zero. If
-/// false, the job will be restarted in the inverse condition.  This key implies that "RunAtLoad" is set to
-/// true, since the job needs to run at least once before an exit status can be determined.</li>
-///
-/// <li>OtherJobEnabled (dictionary of booleans):<br>
-/// Each key in this dictionary is the name of another job. If the value is true, then the job will be kept
-/// alive as long as one of the specified other jobs is loaded in launchd(8).</li>
-///
-/// <p>
-/// NOTE: This key only evaluates whether the job is loaded, not whether it is running. Use of this key is
-/// highly discouraged. If multiple jobs need to coordinate coordinate their lifecycles, they should
-/// establish contracts using IPC.
-/// </p>
-///
-/// <li>Crashed (boolean):<br>
-/// If true, the the job will be restarted as long as it exited due to a signal which is typically
-/// associated with a crash (SIGILL, SIGSEGV, etc.). If false, the job will be restarted in the
-/// inverse condition.</li>
-///
-/// </ul>
-#[derive(Deserialize, Serialize, PartialEq, Debug)]
-pub struct AliveCondition {
-    #[serde(rename = "SuccessfulExit")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    successful_exit: Option<bool>,
-    #[serde(rename = "OtherJobEnabled")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    other_job_enabled: Option<BTreeMap<String, bool>>,
-    #[serde(rename = "Crashed")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    crashed: Option<bool>,
-}
-
 /// Calendar intervals
 /// <ul>
 /// <li>Minute (integer):<br>
@@ -338,26 +1336,80 @@ pub struct AliveCondition {
 /// <li>Month (integer):<br>
 /// The month (1-12) on which this job will be run.</li>
 /// </ul>
-#[derive(Deserialize, Serialize, PartialEq, Debug, Hash, Eq)]
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Hash, Eq, Clone, Default)]
 pub struct CalendarInterval {
     #[serde(rename = "Minute")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0, max = 59))]
     minute: Option<i64>,
     #[serde(rename = "Hour")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0, max = 23))]
     hour: Option<i64>,
     #[serde(rename = "Day")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 31))]
     day: Option<i64>,
     #[serde(rename = "Weekday")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0, max = 7))]
     weekday: Option<i64>,
     #[serde(rename = "Month")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 12))]
     month: Option<i64>,
 }
 
 impl CalendarInterval {
+    ///
+    /// an interval matching every minute, to be narrowed like this, and checked by `check`:
+    ///
+    /// ```
+    /// use tasker::config::{CalendarInterval, Config};
+    ///
+    /// let weekdays = (1..=5)
+    ///     .map(|weekday| CalendarInterval::new().minute(15).hour(9).weekday(weekday))
+    ///     .collect::<Vec<_>>();
+    /// let start = Config::StartCalendarInterval(weekdays);
+    /// let yaml = serde_yaml::to_string(&vec![start]).unwrap();
+    /// assert!(yaml.starts_with(
+    ///     "---\n\
+    ///      - StartCalendarInterval:\n    \
+    ///          - Minute: 15\n      \
+    ///            Hour: 9\n      \
+    ///            Weekday: 1\n"
+    /// ));
+    /// ```
+    ///
+    pub fn new() -> CalendarInterval {
+        CalendarInterval::default()
+    }
+
+    pub fn minute(mut self, minute: i64) -> CalendarInterval {
+        self.minute = Some(minute);
+        self
+    }
+
+    pub fn hour(mut self, hour: i64) -> CalendarInterval {
+        self.hour = Some(hour);
+        self
+    }
+
+    pub fn day(mut self, day: i64) -> CalendarInterval {
+        self.day = Some(day);
+        self
+    }
+
+    pub fn weekday(mut self, weekday: i64) -> CalendarInterval {
+        self.weekday = Some(weekday);
+        self
+    }
+
+    pub fn month(mut self, month: i64) -> CalendarInterval {
+        self.month = Some(month);
+        self
+    }
+
     pub fn check(self) -> Result<CalendarInterval, Error> {
         check_option_range_return_err!(self, minute, 0, 59);
         check_option_range_return_err!(self, hour, 0, 23);
@@ -368,12 +1420,289 @@ impl CalendarInterval {
     }
 }
 
+///
+/// the scheduling configurations of a task in a simple JSON shape for the schedule form:
+/// `StartInterval`, `StartCalendarInterval`, `RunAtLoad`, and `KeepAlive`
+/// (in the same shape as in yaml). Configurations not set are `null` or missing.
+///
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Clone, Default)]
+pub struct Schedule {
+    #[serde(default)]
+    pub start_interval: Option<i64>,
+    #[serde(default)]
+    pub calendar: Vec<CalendarTime>,
+    #[serde(default)]
+    pub run_at_load: Option<bool>,
+    #[serde(default)]
+    pub keep_alive: Option<KeepAliveValue>,
+}
+
+///
+/// an entry of `StartCalendarInterval` in the schedule form
+///
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Clone, Default)]
+pub struct CalendarTime {
+    #[serde(default)]
+    pub minute: Option<i64>,
+    #[serde(default)]
+    pub hour: Option<i64>,
+    #[serde(default)]
+    pub day: Option<i64>,
+    #[serde(default)]
+    pub weekday: Option<i64>,
+    #[serde(default)]
+    pub month: Option<i64>,
+}
+
+impl CalendarTime {
+    ///
+    /// whether a date matches `Month`, `Day` and `Weekday` (`0` and `7` are Sunday).
+    /// As in crontab, a date matching either `Day` or `Weekday` matches if both are set.
+    ///
+    pub fn matches_date(&self, month: i64, day: i64, weekday: i64) -> bool {
+        if matches!(self.month, Some(m) if m != month) {
+            return false;
+        }
+        let day_matches = self.day.map(|d| d == day);
+        let weekday_matches = self.weekday.map(|w| w % 7 == weekday);
+        match (day_matches, weekday_matches) {
+            (Some(d), Some(w)) => d || w,
+            (Some(d), None) => d,
+            (None, Some(w)) => w,
+            (None, None) => true,
+        }
+    }
+
+    ///
+    /// the first `(hour, minute)` of a matching date later than `after`, if given
+    ///
+    pub fn first_time_after(&self, after: Option<(i64, i64)>) -> Option<(i64, i64)> {
+        let hours = self.hour.map_or(0..=23, |h| h..=h);
+        for hour in hours {
+            let minutes = self.minute.map_or(0..=59, |m| m..=m);
+            for minute in minutes {
+                let later = match after {
+                    Some(after) => (hour, minute) > after,
+                    None => true,
+                };
+                if later {
+                    return Some((hour, minute));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl CalendarTime {
+    ///
+    /// the dates matched, like `on Mondays in March`, empty for every day
+    ///
+    fn date_summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(weekday) = self.weekday {
+            parts.push(format!("on {}", WEEKDAY_NAMES[(weekday % 7) as usize]));
+        }
+        if let Some(day) = self.day {
+            parts.push(format!("on day {}", day));
+        }
+        if let Some(month) = self.month {
+            parts.push(format!("in {}", MONTH_NAMES[(month - 1) as usize]));
+        }
+        parts.join(" ")
+    }
+
+    ///
+    /// the times matched on a matching date, like `hourly at :15`
+    ///
+    fn time_summary(&self) -> String {
+        match (self.hour, self.minute) {
+            (Some(hour), Some(minute)) => format!("at {:02}:{:02}", hour, minute),
+            (None, Some(minute)) => format!("hourly at :{:02}", minute),
+            (Some(hour), None) => format!("every minute from {:02}:00 to {:02}:59", hour, hour),
+            (None, None) => "every minute".to_string(),
+        }
+    }
+}
+
+impl From<&CalendarInterval> for CalendarTime {
+    fn from(cal: &CalendarInterval) -> CalendarTime {
+        CalendarTime {
+            minute: cal.minute,
+            hour: cal.hour,
+            day: cal.day,
+            weekday: cal.weekday,
+            month: cal.month,
+        }
+    }
+}
+
+impl From<CalendarTime> for CalendarInterval {
+    fn from(time: CalendarTime) -> CalendarInterval {
+        CalendarInterval {
+            minute: time.minute,
+            hour: time.hour,
+            day: time.day,
+            weekday: time.weekday,
+            month: time.month,
+        }
+    }
+}
+
+pub static SCHEDULE_CONFIG_NAMES: [&str; 4] = [
+    "StartInterval",
+    "StartCalendarInterval",
+    "RunAtLoad",
+    "KeepAlive",
+];
+
+impl Schedule {
+    ///
+    /// see `Configuration::schedule_summary`. Exact times on the same dates are listed
+    /// together, `RunAtLoad` is not included.
+    ///
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(interval) = self.start_interval {
+            parts.push(format!("every {}s", interval));
+        }
+        // exact times grouped by their dates, in the order the dates first appear
+        let mut exact_times: Vec<(String, Vec<(i64, i64)>)> = Vec::new();
+        let mut others = Vec::new();
+        for time in &self.calendar {
+            let date = time.date_summary();
+            if let (Some(hour), Some(minute)) = (time.hour, time.minute) {
+                match exact_times.iter_mut().find(|(d, _)| *d == date) {
+                    Some((_, times)) => times.push((hour, minute)),
+                    None => exact_times.push((date, vec![(hour, minute)])),
+                }
+            } else if date.is_empty() {
+                others.push(time.time_summary());
+            } else {
+                others.push(format!("{} {}", date, time.time_summary()));
+            }
+        }
+        for (date, mut times) in exact_times {
+            times.sort_unstable();
+            times.dedup();
+            let times = times
+                .iter()
+                .map(|(hour, minute)| format!("{:02}:{:02}", hour, minute))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let date = if date.is_empty() { "daily" } else { &date };
+            parts.push(format!("{} at {}", date, times));
+        }
+        parts.extend(others);
+        match &self.keep_alive {
+            Some(KeepAliveValue::Bool(true)) => parts.push("kept alive".to_string()),
+            Some(KeepAliveValue::Conditions(_)) => {
+                parts.push("kept alive conditionally".to_string())
+            }
+            _ => {}
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("; "))
+        }
+    }
+
+    ///
+    /// the scheduling configurations of `config`
+    ///
+    pub fn of(config: &Configuration) -> Schedule {
+        let mut schedule = Schedule::default();
+        for conf in &config.configuration {
+            match conf {
+                Config::StartInterval(t) => schedule.start_interval = Some(*t),
+                Config::StartCalendarInterval(calendar) => {
+                    schedule.calendar = calendar.iter().map(CalendarTime::from).collect()
+                }
+                Config::RunAtLoad(b) => schedule.run_at_load = Some(*b),
+                Config::KeepAlive(keep_alive) => schedule.keep_alive = Some(keep_alive.clone()),
+                _ => {}
+            }
+        }
+        schedule
+    }
+
+    ///
+    /// checked configurations of this schedule. Setting both `start_interval` and
+    /// `calendar` is rejected unless `allow_both`.
+    ///
+    pub fn to_configs(&self, allow_both: bool) -> Result<Vec<Config>, Error> {
+        if self.start_interval.is_some() && !self.calendar.is_empty() && !allow_both {
+            return Err(Error::InvalidConfigPatch(
+                "both `StartInterval` and `StartCalendarInterval` are set, \
+                 use allow_both=true to keep both"
+                    .to_string(),
+            ));
+        }
+        let mut configs = Vec::new();
+        if let Some(t) = self.start_interval {
+            configs.push(Config::StartInterval(t));
+        }
+        if !self.calendar.is_empty() {
+            configs.push(Config::StartCalendarInterval(
+                self.calendar
+                    .iter()
+                    .cloned()
+                    .map(CalendarInterval::from)
+                    .collect(),
+            ));
+        }
+        if let Some(b) = self.run_at_load {
+            configs.push(Config::RunAtLoad(b));
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            configs.push(Config::KeepAlive(keep_alive.clone()));
+        }
+        configs.into_iter().map(Config::check).collect()
+    }
+}
+
+///
+/// a difference between two configurations of a task, see `Configuration::diff`.
+/// `before` and `after` are yaml snippets like `StartInterval: 3600`.
+///
+#[derive(Serialize, JsonSchema, Debug, PartialEq, Clone)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum ConfigChange {
+    Added {
+        name: String,
+        after: String,
+    },
+    Removed {
+        name: String,
+        before: String,
+    },
+    Modified {
+        name: String,
+        before: String,
+        after: String,
+    },
+}
+
 /// Resource Limit
 /// <ul>
 ///
+/// <li>Core (integer): <br>
+/// The largest size (in bytes) core file that may be created.</li>
+///
+/// <li>CPU (integer): <br>
+/// The maximum amount of cpu time (in seconds) to be used by each process.</li>
+///
+/// <li>Data (integer): <br>
+/// The maximum size (in bytes) of the data segment for a process; this defines how far a program may
+/// extend its break with the sbrk(2) system call.</li>
+///
 /// <li>FileSize (integer): <br>
 /// The largest size (in bytes) file that may be created.</li>
 ///
+/// <li>MemoryLock (integer): <br>
+/// The maximum size (in bytes) which a process may lock into memory using the mlock(2) function.</li>
+///
 /// <li>NumberOfFiles (integer): <br>
 /// The maximum number of open files for this process.  Setting this value in a system wide daemon will set
 /// the sysctl(3) kern.maxfiles (SoftResourceLimits) or kern.maxfilesperproc (HardResourceLimits) value in
@@ -394,49 +1723,423 @@ impl CalendarInterval {
 /// segment may be extended.  Stack extension is performed automatically by the system.</li>
 ///
 /// </ul>
-#[derive(Deserialize, Serialize, PartialEq, Debug)]
+#[derive(Deserialize, Serialize, JsonSchema, PartialEq, Debug, Clone, Default)]
 pub struct ResourceLimit {
+    #[serde(rename = "Core")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
+    core: Option<i64>,
     #[serde(rename = "CPU")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
     cpu: Option<i64>,
+    #[serde(rename = "Data")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
+    data: Option<i64>,
     #[serde(rename = "FileSize")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
     file_size: Option<i64>,
+    #[serde(rename = "MemoryLock")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
+    memory_lock: Option<i64>,
     #[serde(rename = "NumberOfFiles")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
     number_of_files: Option<i64>,
     #[serde(rename = "NumberOfProcesses")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0, max = 500))]
     number_of_processes: Option<i64>,
     #[serde(rename = "ResidentSetSize")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
     resident_set_size: Option<i64>,
     #[serde(rename = "Stack")]
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0, max = 67104768))]
     stack: Option<i64>,
 }
 
-impl ResourceLimit {
-    pub fn check(self) -> Result<ResourceLimit, Error> {
-        check_option_range_return_err!(self, file_size, 0, i64::MAX);
-        check_option_range_return_err!(self, number_of_files, 0, i64::MAX);
-        check_option_range_return_err!(self, number_of_processes, 0, 500);
-        check_option_range_return_err!(self, resident_set_size, 0, i64::MAX);
-        check_option_range_return_err!(self, stack, 0, 67104768);
-        Ok(self)
-    }
+impl ResourceLimit {
+    ///
+    /// no limits, to be set like this, and checked by `check`:
+    ///
+    /// ```
+    /// use tasker::config::{Config, ResourceLimit};
+    ///
+    /// let limits = ResourceLimit::new().number_of_files(10000).number_of_processes(100);
+    /// let soft = Config::SoftResourceLimit(limits.check().unwrap());
+    /// assert_eq!(
+    ///     serde_yaml::to_string(&vec![soft]).unwrap(),
+    ///     "---\n\
+    ///      - SoftResourceLimit:\n    \
+    ///          NumberOfFiles: 10000\n    \
+    ///          NumberOfProcesses: 100"
+    /// );
+    /// ```
+    ///
+    pub fn new() -> ResourceLimit {
+        ResourceLimit::default()
+    }
+
+    pub fn core(mut self, core: i64) -> ResourceLimit {
+        self.core = Some(core);
+        self
+    }
+
+    pub fn cpu(mut self, cpu: i64) -> ResourceLimit {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    pub fn data(mut self, data: i64) -> ResourceLimit {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn file_size(mut self, file_size: i64) -> ResourceLimit {
+        self.file_size = Some(file_size);
+        self
+    }
+
+    pub fn memory_lock(mut self, memory_lock: i64) -> ResourceLimit {
+        self.memory_lock = Some(memory_lock);
+        self
+    }
+
+    pub fn number_of_files(mut self, number_of_files: i64) -> ResourceLimit {
+        self.number_of_files = Some(number_of_files);
+        self
+    }
+
+    pub fn number_of_processes(mut self, number_of_processes: i64) -> ResourceLimit {
+        self.number_of_processes = Some(number_of_processes);
+        self
+    }
+
+    pub fn resident_set_size(mut self, resident_set_size: i64) -> ResourceLimit {
+        self.resident_set_size = Some(resident_set_size);
+        self
+    }
+
+    pub fn stack(mut self, stack: i64) -> ResourceLimit {
+        self.stack = Some(stack);
+        self
+    }
+
+    pub fn check(self) -> Result<ResourceLimit, Error> {
+        check_option_range_return_err!(self, core, 0, i64::MAX);
+        check_option_range_return_err!(self, cpu, 0, i64::MAX);
+        check_option_range_return_err!(self, data, 0, i64::MAX);
+        check_option_range_return_err!(self, file_size, 0, i64::MAX);
+        check_option_range_return_err!(self, memory_lock, 0, i64::MAX);
+        check_option_range_return_err!(self, number_of_files, 0, i64::MAX);
+        check_option_range_return_err!(self, number_of_processes, 0, 500);
+        check_option_range_return_err!(self, resident_set_size, 0, i64::MAX);
+        check_option_range_return_err!(self, stack, 0, 67104768);
+        Ok(self)
+    }
+}
+
+///
+/// launchd silently drops variables whose name is not like `[A-Za-z_][A-Za-z0-9_]*`
+///
+fn check_environment_name(key: &str, name: &str) -> Result<(), Error> {
+    if ENVIRONMENT_NAME_REGEX.is_match(name) {
+        Ok(())
+    } else {
+        Err(Error::IllegalEnvironmentVariable(format!(
+            "`{}` in `{}` is not a valid variable name",
+            name, key
+        )))
+    }
+}
+
+///
+/// reject a `label` or `pattern` query parameter that is unsafe as a path component:
+/// empty, containing `/`, `..`, NUL or whitespace
+///
+fn check_path_component(kind: &str, value: &str) -> Result<(), Error> {
+    let unsafe_char = value
+        .chars()
+        .find(|c| *c == '/' || c.is_whitespace() || c.is_control());
+    if value.is_empty() || value.contains("..") || unsafe_char.is_some() {
+        return Err(Error::IllegalLabel(format!(
+            "`{}` is not a valid {}",
+            value.escape_default(),
+            kind
+        )));
+    }
+    Ok(())
+}
+
+///
+/// label (without `com.tasker.tasks.`) only allows patterns as follows
+/// `[A-Za-z0-9_]+(\\.[A-Za-z0-9_]+)*`, and at most `MAX_LABEL_LEN` bytes
+///
+pub fn check_label_str(label: &str) -> Result<(), Error> {
+    if !LABEL_REGEX.is_match(label) {
+        return Err(Error::ConfigLabelError(format!(
+            "`{}` is not a valid label",
+            label
+        )));
+    }
+    if label.len() > MAX_LABEL_LEN {
+        return Err(Error::ConfigLabelError(format!(
+            "label is longer than {} bytes",
+            MAX_LABEL_LEN
+        )));
+    }
+    Ok(())
+}
+
+///
+/// program must be an absolute path, and an accessible file unless `Validation::Lenient`
+///
+pub fn check_program_str(program: &str, validation: Validation) -> Result<(), Error> {
+    let path = Path::new(program);
+    if !path.is_absolute() {
+        return Err(Error::ConfigProgramError(format!(
+            "program path `{}` is not an absolute path",
+            program
+        )));
+    }
+    if validation.checks_local_machine() && !path.is_file() {
+        return Err(Error::ConfigProgramError(format!(
+            "program `{}` is not found or not permitted to access",
+            program
+        )));
+    }
+    Ok(())
+}
+
+///
+/// a `label` query parameter naming a tasker task, like `com.tasker.tasks.backup`.
+/// It is joined into paths, so only labels that tasker could have created pass.
+///
+pub fn validate_label_param(label: &str) -> Result<(), Error> {
+    check_path_component("label", label)?;
+    let short_label = label
+        .strip_prefix(TASKER_TASK_NAME)
+        .and_then(|rest| rest.strip_prefix('.'));
+    match short_label {
+        Some(short_label) if LABEL_REGEX.is_match(short_label) => Ok(()),
+        _ => Err(Error::IllegalLabel(format!(
+            "`{}` is not a valid label, expected `{}.<name>` with `<name>` matching `{}`",
+            label.escape_default(),
+            TASKER_TASK_NAME,
+            LABEL_REG
+        ))),
+    }
+}
+
+///
+/// a parameter matched as a substring of labels (e.g. `label` of `/list_raw_json`), or a
+/// label not created by tasker (the daemon to adopt). Anything path-safe passes, and an
+/// empty pattern matches every task.
+///
+pub fn validate_pattern_param(pattern: &str) -> Result<(), Error> {
+    if pattern.is_empty() {
+        return Ok(());
+    }
+    check_path_component("pattern", pattern)
+}
+
+///
+/// the message of an unknown user or group, with similar existing names as suggestions
+///
+fn unknown_account(kind: &str, name: &str, similar: &[String]) -> String {
+    if similar.is_empty() {
+        return format!("{} `{}` does not exist", kind, name);
+    }
+    let similar: Vec<String> = similar.iter().map(|n| format!("`{}`", n)).collect();
+    format!(
+        "{} `{}` does not exist, did you mean {}?",
+        kind,
+        name,
+        similar.join(", ")
+    )
+}
+
+///
+/// the names among `candidates` sharing the longest prefix with `name` (ignoring case),
+/// if at least `MIN_SIMILAR_PREFIX` characters long. At most `MAX_SIMILAR_NAMES` are returned.
+///
+fn similar_names(name: &str, candidates: BTreeSet<String>) -> Vec<String> {
+    let name = name.to_lowercase();
+    let common_prefix = |candidate: &str| {
+        candidate
+            .to_lowercase()
+            .chars()
+            .zip(name.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    let longest = match candidates.iter().map(|c| common_prefix(c)).max() {
+        Some(longest) if longest >= MIN_SIMILAR_PREFIX => longest,
+        _ => return Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|c| common_prefix(c) == longest)
+        .take(MAX_SIMILAR_NAMES)
+        .collect()
+}
+
+#[cfg(unix)]
+fn user_exists(name: &str) -> bool {
+    users::get_user_by_name(name).is_some()
+}
+
+#[cfg(unix)]
+fn group_exists(name: &str) -> bool {
+    users::get_group_by_name(name).is_some()
+}
+
+///
+/// the names of all users, only listed to suggest a name for an unknown user
+///
+#[cfg(unix)]
+fn user_names() -> BTreeSet<String> {
+    all_users()
+        .iter()
+        .map(|user| user.name().to_string_lossy().to_string())
+        .collect()
+}
+
+///
+/// the names of the groups of all users. There is no listing of groups,
+/// and a group without members is not worth suggesting anyway.
+///
+#[cfg(unix)]
+fn group_names() -> BTreeSet<String> {
+    all_users()
+        .iter()
+        .filter_map(|user| users::get_user_groups(user.name(), user.primary_group_id()))
+        .flatten()
+        .map(|group| group.name().to_string_lossy().to_string())
+        .collect()
+}
+
+#[cfg(unix)]
+fn all_users() -> Vec<users::User> {
+    // `getpwent` iterates with a process-wide cursor
+    let _locked = ACCOUNT_LIST_LOCK.lock().unwrap();
+    unsafe { users::all_users() }.collect()
+}
+
+///
+/// whether `user` is a member of `group`, as its primary group or a listed member.
+/// `None` if either does not exist.
+///
+#[cfg(unix)]
+pub fn user_in_group(user: &str, group: &str) -> Option<bool> {
+    use users::os::unix::GroupExt;
+    let user = users::get_user_by_name(user)?;
+    let group = users::get_group_by_name(group)?;
+    // not `users::get_user_groups`, whose list wrongly includes gid 0
+    Some(
+        user.primary_group_id() == group.gid()
+            || group.members().iter().any(|member| member == user.name()),
+    )
+}
+
+/// there is no user database to check against off unix
+#[cfg(not(unix))]
+fn user_exists(_name: &str) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn group_exists(_name: &str) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn user_names() -> BTreeSet<String> {
+    BTreeSet::new()
+}
+
+#[cfg(not(unix))]
+fn group_names() -> BTreeSet<String> {
+    BTreeSet::new()
+}
+
+#[cfg(not(unix))]
+pub fn user_in_group(_user: &str, _group: &str) -> Option<bool> {
+    None
 }
 
 #[cfg(test)]
 mod test_config_mod {
     use super::*;
 
+    #[test]
+    fn unknown_accounts() {
+        let yaml = |user: &str, group: &str| {
+            String::new()
+                + "---\n"
+                + "Label: test_accounts\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + &format!("  - UserName: \"{}\"\n", user)
+                + &format!("  - GroupName: \"{}\"", group)
+        };
+        let root_group = users::get_group_by_gid(0).unwrap();
+        let root_group = root_group.name().to_string_lossy().to_string();
+        let config = Configuration::from_yaml(&yaml(" root ", &root_group)).unwrap();
+        assert_eq!(config.user_name(), Some("root".to_string()));
+        assert_eq!(config.group_name(), Some(root_group.clone()));
+
+        match Configuration::from_yaml(&yaml("rootx", &root_group)) {
+            Err(Error::ConfigUnknownUser(message)) => {
+                assert_eq!(message, "user `rootx` does not exist, did you mean `root`?")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match Configuration::from_yaml(&yaml("root", "tasker_no_such_group")) {
+            Err(Error::ConfigUnknownGroup(message)) => {
+                assert!(message.starts_with("group `tasker_no_such_group` does not exist"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // accounts of another machine
+        let yaml = yaml("tasker_no_such_user", "tasker_no_such_group");
+        assert!(Configuration::from_yaml_with(&yaml, Validation::Lenient).is_ok());
+
+        assert_eq!(user_in_group("root", &root_group), Some(true));
+        assert_eq!(user_in_group("daemon", &root_group), Some(false));
+        assert_eq!(user_in_group("tasker_no_such_user", &root_group), None);
+    }
+
+    #[test]
+    fn similar_account_names() {
+        let candidates: BTreeSet<String> = ["_www", "_windowserver", "_webauthserver", "staff"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(similar_names("_www2", candidates.clone()), vec!["_www"]);
+        assert_eq!(
+            similar_names("_w", candidates.clone()),
+            vec!["_webauthserver", "_windowserver", "_www"]
+        );
+        assert_eq!(similar_names("Staf", candidates.clone()), vec!["staff"]);
+        assert!(similar_names("sys", candidates).is_empty());
+        assert_eq!(unknown_account("user", "x", &[]), "user `x` does not exist");
+    }
+
     #[test]
     fn mock_config_yaml() {
         let test_config = Configuration::new("com.tasker.tasks.test_task", "/usr/bin/python")
             .add_config(Config::HardResourceLimits(ResourceLimit {
+                core: Some(0),
                 cpu: None,
+                data: None,
                 file_size: None,
+                memory_lock: Some(65536),
                 number_of_files: Some(10000),
                 number_of_processes: Some(8),
                 resident_set_size: None,
@@ -444,16 +2147,20 @@ mod test_config_mod {
             }))
             .add_config(Config::UserName("Congyu WANG".to_string()))
             .add_config(Config::GroupName("staff".to_string()))
-            .add_config(Config::KeepAlive(AliveCondition {
-                crashed: Some(true),
-                other_job_enabled: Some({
-                    let mut other_jobs = BTreeMap::new();
-                    other_jobs.insert(String::from("com.tasker.conflict"), false);
-                    other_jobs.insert(String::from("com.tasker.depended"), true);
-                    other_jobs
-                }),
-                successful_exit: Some(false),
-            }))
+            .add_config(Config::KeepAlive(KeepAliveValue::Conditions(
+                AliveCondition {
+                    crashed: Some(true),
+                    other_job_enabled: Some({
+                        let mut other_jobs = BTreeMap::new();
+                        other_jobs.insert(String::from("com.tasker.conflict"), false);
+                        other_jobs.insert(String::from("com.tasker.depended"), true);
+                        other_jobs
+                    }),
+                    successful_exit: Some(false),
+                    network_state: None,
+                    path_state: None,
+                },
+            )))
             .add_config(Config::StartCalendarInterval(vec![
                 CalendarInterval {
                     minute: Some(15),
@@ -487,6 +2194,8 @@ mod test_config_mod {
             + "Program: /usr/bin/python\n"
             + "Configuration:\n"
             + "  - HardResourceLimits:\n"
+            + "      Core: 0\n"
+            + "      MemoryLock: 65536\n"
             + "      NumberOfFiles: 10000\n"
             + "      NumberOfProcesses: 8\n"
             + "  - UserName: Congyu WANG\n"
@@ -515,6 +2224,41 @@ mod test_config_mod {
         );
     }
 
+    #[test]
+    fn resource_limits_plist() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: limited_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - HardResourceLimits:\n"
+            + "      Core: 0\n"
+            + "      MemoryLock: 65536\n"
+            + "      Data: 1048576\n";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        let expected_limits = String::new()
+            + "\t<key>HardResourceLimits</key>\n"
+            + "\t<dict>\n"
+            + "\t\t<key>Core</key>\n"
+            + "\t\t<integer>0</integer>\n"
+            + "\t\t<key>Data</key>\n"
+            + "\t\t<integer>1048576</integer>\n"
+            + "\t\t<key>MemoryLock</key>\n"
+            + "\t\t<integer>65536</integer>\n"
+            + "\t</dict>\n";
+        assert!(config.to_plist().contains(&expected_limits));
+
+        // yaml round trip
+        let round_trip = Configuration::from_yaml(&config.to_yaml().unwrap()).unwrap();
+        assert_eq!(round_trip.configuration, config.configuration);
+
+        let negative = yaml.replace("Core: 0", "Core: -1");
+        match Configuration::from_yaml(&negative) {
+            Err(Error::ConfigRangeError(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
     #[test]
     fn test_get_plist() {
         let yaml_config = String::new()
@@ -606,6 +2350,152 @@ mod test_config_mod {
         assert_eq!(plist, expected_plist);
     }
 
+    #[test]
+    fn test_get_plist_nested_multiline() {
+        let script = "line_1\n\t\t<dict>\n\t</array>\n\t\tline_4".to_string();
+        let mut env = BTreeMap::new();
+        env.insert("SCRIPT".to_string(), script.clone());
+        let config = Configuration::new("test_task", "/bin/sh")
+            .add_config(Config::EnvironmentVariables(env))
+            .add_config(Config::ProgramArguments(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                script.clone(),
+            ]));
+
+        let plist = plist::Value::from_reader_xml(config.to_plist().as_bytes()).unwrap();
+        let plist = plist.as_dictionary().unwrap();
+        assert_eq!(
+            plist.keys().collect::<Vec<&String>>(),
+            vec![
+                "Label",
+                "Program",
+                "EnvironmentVariables",
+                "ProgramArguments"
+            ]
+        );
+        let env = plist
+            .get("EnvironmentVariables")
+            .and_then(|env| env.as_dictionary())
+            .unwrap();
+        assert_eq!(
+            env.get("SCRIPT").and_then(|s| s.as_string()),
+            Some(&script[..])
+        );
+        let args = plist
+            .get("ProgramArguments")
+            .and_then(|args| args.as_array())
+            .unwrap();
+        assert_eq!(args[2].as_string(), Some(&script[..]));
+    }
+
+    #[test]
+    fn test_from_plist_file_refuses_dates_and_data() {
+        let plist_file = Path::new("/tmp/tasker_test_adopt_date.plist");
+        let plist = |value: &str| {
+            String::new()
+                + "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"
+                + "<plist version=\"1.0\">\n"
+                + "<dict>\n"
+                + "\t<key>Label</key>\n"
+                + "\t<string>com.example.daemon</string>\n"
+                + "\t<key>Program</key>\n"
+                + "\t<string>/bin/echo</string>\n"
+                + "\t<key>Extra</key>\n"
+                + value
+                + "\n</dict>\n"
+                + "</plist>"
+        };
+        let nested_date = "<dict><key>At</key><date>2020-01-01T00:00:00Z</date></dict>";
+        for (value, kind) in &[
+            (nested_date, "a date"),
+            ("<array><data>AAEC</data></array>", "data"),
+        ] {
+            std::fs::write(plist_file, plist(value)).unwrap();
+            match Configuration::from_plist_file(plist_file) {
+                Err(Error::PlistParseError(e)) => {
+                    assert!(e.contains("`Extra`"), "{}", e);
+                    assert!(e.contains(kind), "{}", e);
+                }
+                other => panic!("expected PlistParseError, got {:?}", other),
+            }
+        }
+        // other unknown values are kept
+        std::fs::write(plist_file, plist("<string>kept</string>")).unwrap();
+        let adopted = Configuration::from_plist_file(plist_file).unwrap();
+        std::fs::remove_file(plist_file).unwrap();
+        let mut others = BTreeMap::new();
+        others.insert(
+            "Extra".to_string(),
+            plist::Value::String("kept".to_string()),
+        );
+        assert!(adopted.configuration.contains(&Config::Other(others)));
+    }
+
+    #[test]
+    fn test_from_plist_file_round_trip() {
+        let plist_file = Path::new("/tmp/tasker_test_adopt.plist");
+        let plist = String::new()
+            + "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"
+            + "<plist version=\"1.0\">\n"
+            + "<dict>\n"
+            + "\t<key>Label</key>\n"
+            + "\t<string>com.example.daemon</string>\n"
+            + "\t<key>ProgramArguments</key>\n"
+            + "\t<array>\n"
+            + "\t\t<string>/bin/sh</string>\n"
+            + "\t\t<string>-c</string>\n"
+            + "\t\t<string>echo hello</string>\n"
+            + "\t</array>\n"
+            + "\t<key>RunAtLoad</key>\n"
+            + "\t<true/>\n"
+            + "\t<key>KeepAlive</key>\n"
+            + "\t<true/>\n"
+            + "\t<key>LimitLoadToSessionType</key>\n"
+            + "\t<string>Aqua</string>\n"
+            + "\t<key>ThrottleInterval</key>\n"
+            + "\t<integer>30</integer>\n"
+            + "</dict>\n"
+            + "</plist>";
+        std::fs::write(plist_file, plist).unwrap();
+
+        let adopted = Configuration::from_plist_file(plist_file).unwrap();
+        std::fs::remove_file(plist_file).unwrap();
+        assert_eq!(adopted.program(), "/bin/sh");
+        assert!(adopted.configuration.contains(&Config::RunAtLoad(true)));
+
+        let config = Configuration::from_yaml(&adopted.to_yaml().unwrap()).unwrap();
+        assert_eq!(
+            config.label,
+            String::from(TASKER_TASK_NAME) + ".com.example.daemon"
+        );
+        let plist = plist::Value::from_reader_xml(config.to_plist().as_bytes()).unwrap();
+        let plist = plist.as_dictionary().unwrap();
+        assert_eq!(
+            plist
+                .get("LimitLoadToSessionType")
+                .and_then(|v| v.as_string()),
+            Some("Aqua")
+        );
+        assert_eq!(
+            plist.get("KeepAlive").and_then(|v| v.as_boolean()),
+            Some(true)
+        );
+        assert_eq!(
+            plist
+                .get("ThrottleInterval")
+                .and_then(|v| v.as_signed_integer()),
+            Some(30)
+        );
+        assert_eq!(
+            plist
+                .get("ProgramArguments")
+                .and_then(|v| v.as_array())
+                .map(|args| args.len()),
+            Some(3)
+        );
+    }
+
     #[test]
     #[should_panic]
     fn no_such_attribute() {
@@ -684,18 +2574,703 @@ mod test_config_mod {
     }
 
     #[test]
-    #[should_panic(expected = "`/tmp/no such path` is not a file")]
+    #[should_panic(expected = "`/tmp/no such path` is not a directory")]
     fn config_panic_standard_out_path() {
         let yaml = String::new()
             + "---\n"
             + "Label: test_task\n"
             + "Program: /usr/bin/python\n"
             + "Configuration:\n"
-            + "  - StandardOutPath: \"/tmp/no such path\"\n"
+            + "  - StandardOutPath: \"/tmp/no such path/stdout.log\"\n"
             + "  - StartCalendarInterval:\n"
             + "      - Minute: 15\n"
             + "        Hour: 20";
 
         let _config = Configuration::from_yaml(&yaml).unwrap();
     }
+
+    #[test]
+    #[should_panic(expected = "must be an absolute path inside `RootDirectory`")]
+    fn config_panic_working_directory_alias_in_chroot() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /usr/bin/python\n"
+            + "Configuration:\n"
+            + "  - RootDirectory: /tmp\n"
+            + "  - WorkingDirectory: ~root~/scripts";
+
+        let _config = Configuration::from_yaml(&yaml).unwrap();
+    }
+
+    #[test]
+    fn patch_single_config() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /usr/bin/python\n"
+            + "Configuration:\n"
+            + "  - StartInterval: 60\n"
+            + "  - RunAtLoad: true";
+        let config = Configuration::parse_yaml(&yaml)
+            .unwrap()
+            .add_config(Config::from_yaml("StartInterval: 3600").unwrap())
+            .remove_config("RunAtLoad")
+            .remove_config("KeepAlive");
+        assert_eq!(config.label, "test_task");
+        assert_eq!(config.configuration, vec![Config::StartInterval(3600)]);
+        assert!(matches!(
+            Config::from_yaml("Program: /bin/ls"),
+            Err(Error::InvalidConfigPatch(_))
+        ));
+        assert!(matches!(
+            Config::from_yaml("StartInterval: 0"),
+            Err(Error::ConfigRangeError(_))
+        ));
+    }
+
+    #[test]
+    fn builder_matches_from_yaml() {
+        let built = Configuration::builder("test_task", "/bin/sh")
+            .config(Config::ProgramArguments(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                "echo hello".to_string(),
+            ]))
+            .config(Config::StartInterval(60))
+            .config(Config::RunAtLoad(true))
+            .config(Config::StartInterval(3600))
+            .build()
+            .unwrap();
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - /bin/sh\n"
+            + "      - \"-c\"\n"
+            + "      - echo hello\n"
+            + "  - StartInterval: 3600\n"
+            + "  - RunAtLoad: true";
+        let parsed = Configuration::from_yaml(&yaml).unwrap();
+        assert_eq!(built.to_yaml().unwrap(), parsed.to_yaml().unwrap());
+        assert_eq!(built.label(), "com.tasker.tasks.test_task");
+        assert_eq!(built.program(), "/bin/sh");
+        assert_eq!(built.configs().len(), 3);
+        assert_eq!(built.user_name(), None);
+        assert_eq!(built.clone(), parsed);
+        assert!(Configuration::builder("not a label", "/bin/sh")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn schedule_summaries() {
+        let summary = |configs: &str| {
+            let yaml = String::new()
+                + "---\n"
+                + "Label: summary_task\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + configs;
+            Configuration::from_yaml(&yaml).unwrap().schedule_summary()
+        };
+        let calendar = String::new()
+            + "  - StartCalendarInterval:\n"
+            + "      - Hour: 13\n"
+            + "        Minute: 0\n"
+            + "      - Hour: 9\n"
+            + "        Minute: 15\n"
+            + "      - Weekday: 1\n"
+            + "        Hour: 8\n"
+            + "        Minute: 0\n"
+            + "      - Minute: 30\n"
+            + "      - Day: 1\n"
+            + "        Month: 3\n"
+            + "        Hour: 0\n"
+            + "        Minute: 0\n"
+            + "      - Weekday: 7\n"
+            + "        Hour: 6\n";
+        assert_eq!(
+            summary(&calendar).unwrap(),
+            "daily at 09:15, 13:00; on Mondays at 08:00; on day 1 in March at 00:00; \
+             hourly at :30; on Sundays every minute from 06:00 to 06:59"
+        );
+        assert_eq!(summary("  - StartInterval: 3600\n").unwrap(), "every 3600s");
+        assert_eq!(
+            summary(
+                "  - StartInterval: 60\n  - StartCalendarInterval:\n      - Hour: 9\n        Minute: 5\n"
+            )
+            .unwrap(),
+            "every 60s; daily at 09:05"
+        );
+        assert_eq!(summary("  - KeepAlive: true\n").unwrap(), "kept alive");
+        assert_eq!(
+            summary("  - KeepAlive:\n      SuccessfulExit: false\n").unwrap(),
+            "kept alive conditionally"
+        );
+        assert_eq!(summary("  - KeepAlive: false\n"), None);
+        assert_eq!(summary("  - RunAtLoad: true\n"), None);
+    }
+
+    #[test]
+    fn schedule_round_trip() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: schedule_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - /bin/sh\n"
+            + "  - StartCalendarInterval:\n"
+            + "      - Hour: 8\n"
+            + "        Minute: 30\n"
+            + "  - RunAtLoad: true\n"
+            + "  - ExitTimeOut: 30";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        let schedule = Schedule::of(&config);
+        assert_eq!(schedule.start_interval, None);
+        assert_eq!(schedule.run_at_load, Some(true));
+        assert_eq!(
+            schedule.calendar,
+            vec![CalendarTime {
+                hour: Some(8),
+                minute: Some(30),
+                ..CalendarTime::default()
+            }]
+        );
+        let json = serde_json::to_value(&schedule).unwrap();
+        assert_eq!(json["calendar"][0]["hour"], 8);
+        let parsed: Schedule = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, schedule);
+
+        // replace the schedule, keeping other configurations
+        let new_schedule: Schedule = serde_json::from_str(r#"{"start_interval": 600}"#).unwrap();
+        let updated = config.clone().with_schedule(&new_schedule, false).unwrap();
+        assert_eq!(Schedule::of(&updated), new_schedule);
+        assert!(updated.configuration.contains(&Config::ExitTimeOut(30)));
+        assert!(updated
+            .configuration
+            .contains(&Config::ProgramArguments(vec!["/bin/sh".to_string()])));
+
+        // calendar ranges are checked
+        let bad: Schedule = serde_json::from_str(r#"{"calendar": [{"hour": 24}]}"#).unwrap();
+        match config.clone().with_schedule(&bad, false) {
+            Err(Error::ConfigRangeError(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // both intervals need `allow_both`
+        let both: Schedule =
+            serde_json::from_str(r#"{"start_interval": 600, "calendar": [{"minute": 0}]}"#)
+                .unwrap();
+        match config.clone().with_schedule(&both, false) {
+            Err(Error::InvalidConfigPatch(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        let updated = config.with_schedule(&both, true).unwrap();
+        assert_eq!(Schedule::of(&updated), both);
+    }
+
+    #[test]
+    fn agent_scope() {
+        let yaml = |scope: &str, user: &str| {
+            String::new()
+                + "---\n"
+                + "Label: notify\n"
+                + "Program: /usr/bin/osascript\n"
+                + scope
+                + "Configuration:\n"
+                + "  - ProgramArguments:\n"
+                + "      - /usr/bin/osascript\n"
+                + user
+        };
+        let daemon = Configuration::from_yaml(&yaml("", "")).unwrap();
+        assert_eq!(daemon.scope(), Scope::Daemon);
+        assert!(!daemon.to_yaml().unwrap().contains("Scope"));
+
+        let agent =
+            Configuration::from_yaml(&yaml("Scope: agent\n", "  - UserName: alice\n")).unwrap();
+        assert_eq!(agent.scope(), Scope::Agent);
+        assert!(agent.to_yaml().unwrap().contains("Scope: agent"));
+        assert!(!agent.to_plist().contains("Scope"));
+
+        match Configuration::from_yaml(&yaml("Scope: agent\n", "")) {
+            Err(Error::ScopeError(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Configuration::from_yaml(&yaml("Scope: user\n", "")).is_err());
+    }
+
+    #[test]
+    fn environment_variables_checked() {
+        let yaml = |variables: &str, inherit: &str| {
+            String::new()
+                + "---\n"
+                + "Label: env_task\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + "  - EnvironmentVariables:\n"
+                + variables
+                + inherit
+        };
+        let config = Configuration::from_yaml(&yaml("      _DATA_DIR2: /tmp\n", "")).unwrap();
+        assert_eq!(config.effective_environment()["_DATA_DIR2"], "/tmp");
+
+        for (variables, name) in [
+            ("      DATA DIR: /tmp\n", "DATA DIR"),
+            ("      2DATA: /tmp\n", "2DATA"),
+            ("      DATA-DIR: /tmp\n", "DATA-DIR"),
+        ]
+        .iter()
+        {
+            match Configuration::from_yaml(&yaml(variables, "")) {
+                Err(Error::IllegalEnvironmentVariable(e)) => assert_eq!(
+                    e,
+                    format!(
+                        "`{}` in `EnvironmentVariables` is not a valid variable name",
+                        name
+                    )
+                ),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        match Configuration::from_yaml(&yaml("      DATA: \"a\\0b\"\n", "")) {
+            Err(Error::IllegalEnvironmentVariable(e)) => {
+                assert_eq!(e, "the value of `DATA` contains NUL")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match Configuration::from_yaml(&yaml(
+            "      DATA: /tmp\n",
+            "InheritEnvironment:\n  - PATH\n  - $PATH\n",
+        )) {
+            Err(Error::IllegalEnvironmentVariable(e)) => {
+                assert_eq!(
+                    e,
+                    "`$PATH` in `InheritEnvironment` is not a valid variable name"
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn inherit_environment() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: env_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      LANG: C\n"
+            + "InheritEnvironment:\n"
+            + "  - PATH\n"
+            + "  - LANG\n"
+            + "  - NOT_SET\n";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        assert_eq!(config.inherit_environment(), ["PATH", "LANG", "NOT_SET"]);
+        assert!(config.to_yaml().unwrap().contains("InheritEnvironment"));
+        assert!(!config.to_plist().contains("InheritEnvironment"));
+
+        let mut server = BTreeMap::new();
+        server.insert(
+            "PATH".to_string(),
+            "/usr/local/bin:/usr/bin:/bin".to_string(),
+        );
+        server.insert("LANG".to_string(), "en_US.UTF-8".to_string());
+        server.insert("SECRET".to_string(), "not inherited".to_string());
+        let config = config.inherit_environment_from(&server);
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            "PATH".to_string(),
+            "/usr/local/bin:/usr/bin:/bin".to_string(),
+        );
+        expected.insert("LANG".to_string(), "C".to_string());
+        assert_eq!(config.effective_environment(), expected);
+        assert!(config
+            .to_plist()
+            .contains("<string>/usr/local/bin:/usr/bin:/bin</string>"));
+
+        // nothing to inherit leaves the configuration as it is
+        let bare = Configuration::from_yaml(
+            "---\nLabel: env_task\nProgram: /bin/sh\nConfiguration:\n  - RunAtLoad: true\n",
+        )
+        .unwrap();
+        assert_eq!(bare.clone().inherit_environment_from(&server), bare);
+    }
+
+    #[test]
+    fn keep_alive_path_and_network_state() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: sync_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - KeepAlive:\n"
+            + "      NetworkState: true\n"
+            + "      PathState:\n"
+            + "        /Volumes/Backup: true\n"
+            + "        ~root~/pause: false\n";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        let mut path_state = BTreeMap::new();
+        path_state.insert("/Volumes/Backup".to_string(), true);
+        path_state.insert("~root~/pause".to_string(), false);
+        let keep_alive = Config::KeepAlive(KeepAliveValue::Conditions(AliveCondition {
+            successful_exit: None,
+            other_job_enabled: None,
+            crashed: None,
+            network_state: Some(true),
+            path_state: Some(path_state),
+        }));
+        assert!(config.configuration.contains(&keep_alive));
+
+        // yaml round trip
+        let round_trip = Configuration::from_yaml(&config.to_yaml().unwrap()).unwrap();
+        assert!(round_trip.configuration.contains(&keep_alive));
+        assert!(!config.to_yaml().unwrap().contains("Crashed"));
+
+        // plist nested dictionaries
+        let plist = plist::Value::from_reader_xml(config.to_plist().as_bytes()).unwrap();
+        let keep_alive = plist
+            .as_dictionary()
+            .and_then(|plist| plist.get("KeepAlive"))
+            .and_then(|v| v.as_dictionary())
+            .unwrap();
+        assert_eq!(
+            keep_alive.get("NetworkState").and_then(|v| v.as_boolean()),
+            Some(true)
+        );
+        let path_state = keep_alive
+            .get("PathState")
+            .and_then(|v| v.as_dictionary())
+            .unwrap();
+        assert_eq!(
+            path_state
+                .get("/Volumes/Backup")
+                .and_then(|v| v.as_boolean()),
+            Some(true)
+        );
+        assert_eq!(
+            path_state.get("~root~/pause").and_then(|v| v.as_boolean()),
+            Some(false)
+        );
+        assert!(keep_alive.get("Crashed").is_none());
+
+        // plist round trip
+        let plist_file = Path::new("/tmp/tasker_test_keep_alive.plist");
+        std::fs::write(plist_file, config.to_plist()).unwrap();
+        let parsed = Configuration::from_plist_file(plist_file).unwrap();
+        std::fs::remove_file(plist_file).unwrap();
+        assert_eq!(parsed.configuration, config.configuration);
+
+        let relative = yaml.replace("/Volumes/Backup", "Volumes/Backup");
+        match Configuration::from_yaml(&relative) {
+            Err(Error::ConfigPathError(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keep_alive_boolean() {
+        let yaml = |keep_alive: &str| {
+            String::new()
+                + "---\n"
+                + "Label: server_task\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + "  - KeepAlive:"
+                + keep_alive
+        };
+        let keep_alive_of = |config: &Configuration| {
+            let plist = plist::Value::from_reader_xml(config.to_plist().as_bytes()).unwrap();
+            plist.as_dictionary().unwrap().get("KeepAlive").cloned()
+        };
+        for b in &[true, false] {
+            let config =
+                Configuration::from_yaml_with(&yaml(&format!(" {}\n", b)), Validation::Lenient)
+                    .unwrap();
+            let keep_alive = Config::KeepAlive(KeepAliveValue::Bool(*b));
+            assert!(config.configuration.contains(&keep_alive));
+            assert_eq!(keep_alive_of(&config), Some(plist::Value::Boolean(*b)));
+            // the boolean form is kept in yaml and when adopting the plist
+            let yaml = config.to_yaml().unwrap();
+            assert!(yaml.contains(&format!("- KeepAlive: {}", b)));
+            let round_trip = Configuration::from_yaml_with(&yaml, Validation::Lenient).unwrap();
+            assert!(round_trip.configuration.contains(&keep_alive));
+            let plist_file = Path::new("/tmp/tasker_test_keep_alive_bool.plist");
+            std::fs::write(plist_file, config.to_plist()).unwrap();
+            let parsed = Configuration::from_plist_file(plist_file).unwrap();
+            std::fs::remove_file(plist_file).unwrap();
+            assert_eq!(parsed.configuration, config.configuration);
+        }
+
+        // edited from the boolean into the dictionary form
+        let config = Configuration::from_yaml_with(&yaml(" true\n"), Validation::Lenient).unwrap();
+        let edited = config
+            .to_yaml()
+            .unwrap()
+            .replace("KeepAlive: true", "KeepAlive:\n      Crashed: true");
+        let edited = Configuration::from_yaml_with(&edited, Validation::Lenient).unwrap();
+        let keep_alive = keep_alive_of(&edited).unwrap();
+        let keep_alive = keep_alive.as_dictionary().unwrap();
+        assert_eq!(
+            keep_alive.get("Crashed").and_then(|v| v.as_boolean()),
+            Some(true)
+        );
+        assert_eq!(keep_alive.len(), 1);
+        assert!(edited.to_yaml().unwrap().contains("Crashed: true"));
+
+        assert!(Configuration::from_yaml_with(&yaml(" sometimes\n"), Validation::Lenient).is_err());
+    }
+
+    #[test]
+    fn fill_template_placeholders() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: backup_template\n"
+            + "Program: /bin/sh\n"
+            + "Template: true\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - /bin/sh\n"
+            + "      - backup.sh\n"
+            + "      - \"{{source}}/{{name}}\"\n"
+            + "  - EnvironmentVariables:\n"
+            + "      TARGET: \"{{target}}\"\n"
+            + "  - StandardOutPath: \"/tmp/{{name}}.log\"";
+        let template = Configuration::from_yaml(&yaml).unwrap();
+        assert!(template.is_template());
+        let values = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let filled = template
+            .clone()
+            .fill_template(&values(&[
+                ("source", "/data"),
+                ("name", "photos"),
+                ("target", "/backup"),
+            ]))
+            .unwrap();
+        assert_eq!(
+            filled.effective_command(),
+            vec!["/bin/sh", "backup.sh", "/data/photos"]
+        );
+        assert_eq!(
+            filled.effective_environment().get("TARGET").unwrap(),
+            "/backup"
+        );
+        // only arguments and environment are substituted
+        assert_eq!(filled.standard_out_path().unwrap(), "/tmp/{{name}}.log");
+
+        match template
+            .clone()
+            .fill_template(&values(&[("name", "photos")]))
+        {
+            Err(Error::MissingTemplateValues(names)) => assert_eq!(names, vec!["source", "target"]),
+            other => panic!("unexpected {:?}", other),
+        }
+        match template.fill_template(&values(&[
+            ("source", "/data"),
+            ("name", "photos"),
+            ("target", "/backup"),
+            ("extra", "1"),
+        ])) {
+            Err(Error::UnusedTemplateValues(names)) => assert_eq!(names, vec!["extra"]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn diff_yaml(configs: &str) -> Configuration {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: diff_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + configs;
+        Configuration::from_yaml(&yaml).unwrap()
+    }
+
+    #[test]
+    fn diff_configurations() {
+        let stored = diff_yaml(
+            &(String::new()
+                + "  - StartInterval: 3600\n"
+                + "  - RunAtLoad: true\n"
+                + "  - UserName: root"),
+        );
+        let posted = diff_yaml(
+            &(String::new()
+                + "  - StartInterval: 600\n"
+                + "  - UserName: root\n"
+                + "  - ExitTimeOut: 30"),
+        );
+        assert_eq!(
+            stored.diff(&posted),
+            vec![
+                ConfigChange::Removed {
+                    name: "RunAtLoad".to_string(),
+                    before: "RunAtLoad: true".to_string(),
+                },
+                ConfigChange::Modified {
+                    name: "StartInterval".to_string(),
+                    before: "StartInterval: 3600".to_string(),
+                    after: "StartInterval: 600".to_string(),
+                },
+                ConfigChange::Added {
+                    name: "ExitTimeOut".to_string(),
+                    after: "ExitTimeOut: 30".to_string(),
+                },
+            ]
+        );
+        let json = serde_json::to_value(stored.diff(&posted)).unwrap();
+        assert_eq!(json[0]["change"], "removed");
+        assert_eq!(json[1]["after"], "StartInterval: 600");
+
+        // top-level keys are compared as well
+        let mut agent = diff_yaml("  - UserName: root");
+        agent.scope = Scope::Agent;
+        assert_eq!(
+            diff_yaml("  - UserName: root").diff(&agent),
+            vec![ConfigChange::Added {
+                name: "Scope".to_string(),
+                after: "Scope: agent".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_ignores_order() {
+        let stored = diff_yaml(
+            &(String::new()
+                + "  - StartInterval: 3600\n"
+                + "  - EnvironmentVariables:\n"
+                + "      A: \"1\"\n"
+                + "      B: \"2\"\n"
+                + "  - RunAtLoad: true"),
+        );
+        let reordered = diff_yaml(
+            &(String::new()
+                + "  - RunAtLoad: true\n"
+                + "  - EnvironmentVariables:\n"
+                + "      B: \"2\"\n"
+                + "      A: \"1\"\n"
+                + "  - StartInterval: 3600"),
+        );
+        assert_ne!(stored, reordered);
+        assert!(stored.diff(&reordered).is_empty());
+        assert!(stored.diff(&stored).is_empty());
+    }
+
+    #[test]
+    fn canonical_yaml_round_trip() {
+        let uploaded = String::new()
+            + "\u{feff}Label: test_task\r\n"
+            + "Configuration:\r\n"
+            + "- StartInterval: 60\r\n"
+            + "- EnvironmentVariables:\r\n"
+            + "    A: \"1\"\r\n"
+            + "- RunAtLoad: true\r\n"
+            + "Program: /bin/echo\r\n";
+        let parsed = Configuration::parse_yaml(&uploaded).unwrap();
+        let canonical = parsed.to_canonical_yaml().unwrap();
+        assert_eq!(
+            canonical,
+            String::new()
+                + "---\n"
+                + "Label: test_task\n"
+                + "Program: /bin/echo\n"
+                + "Configuration:\n"
+                + "  - StartInterval: 60\n"
+                + "  - EnvironmentVariables:\n"
+                + "      A: \"1\"\n"
+                + "  - RunAtLoad: true\n"
+        );
+        let reparsed = Configuration::parse_yaml(&canonical).unwrap();
+        assert_eq!(reparsed, parsed);
+        assert_eq!(reparsed.to_canonical_yaml().unwrap(), canonical);
+        assert_eq!(
+            Configuration::canonicalize_yaml(&canonical).unwrap(),
+            canonical
+        );
+        assert_eq!(
+            Configuration::canonicalize_yaml(&uploaded).unwrap(),
+            canonical
+        );
+        // the domain is appended on reading, not stored
+        assert_eq!(
+            Configuration::from_yaml_with(&canonical, Validation::Lenient)
+                .unwrap()
+                .label,
+            "com.tasker.tasks.test_task"
+        );
+        assert_eq!(
+            Config::from_yaml("\u{feff}StartInterval: 60\r\n").unwrap(),
+            Config::StartInterval(60)
+        );
+    }
+
+    #[test]
+    fn label_and_pattern_params() {
+        for label in &["com.tasker.tasks.backup", "com.tasker.tasks.db.nightly_2"] {
+            assert!(validate_label_param(label).is_ok(), "{}", label);
+        }
+        for label in &[
+            "../../../etc/passwd\0",
+            "com.tasker.tasks.a/../../etc/passwd",
+            "com.tasker.tasks..",
+            "com.tasker.tasks.a\0",
+            "com.tasker.tasks.a b",
+            "com.tasker.tasks.a\n",
+            "com.tasker.tasks.",
+            "com.tasker.tasks",
+            "com.tasker.tasksx.a",
+            "com.example.daemon",
+            "backup",
+            "",
+        ] {
+            match validate_label_param(label) {
+                Err(Error::IllegalLabel(_)) => {}
+                other => panic!("{:?} accepted: {:?}", label, other),
+            }
+        }
+        for pattern in &["", "backup", "com.tasker", "com.example.daemon", "a-b"] {
+            assert!(validate_pattern_param(pattern).is_ok(), "{}", pattern);
+        }
+        for pattern in &["../etc", "a/b", "a\0", " ", "a\tb", ".."] {
+            assert!(validate_pattern_param(pattern).is_err(), "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn json_schema_has_every_config() {
+        let schema = json_schema();
+        assert_eq!(
+            schema["$schema"],
+            "https://json-schema.org/draft/2020-12/schema"
+        );
+        assert_eq!(schema["properties"]["Label"]["pattern"], LABEL_REG);
+        let variants = schema["$defs"]["Config"]["oneOf"].as_array().unwrap();
+        let names = variants
+            .iter()
+            .flat_map(|v| v["required"].as_array().unwrap().iter())
+            .map(|name| name.as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, CONFIG_NAMES.to_vec());
+        let minute = &schema["$defs"]["CalendarInterval"]["properties"]["Minute"];
+        assert_eq!(
+            (&minute["minimum"], &minute["maximum"]),
+            (&0.0.into(), &59.0.into())
+        );
+        let other = variants.last().unwrap();
+        assert_eq!(
+            other["properties"]["Other"]["properties"]["ProcessType"]["enum"][0],
+            "Background"
+        );
+    }
 }
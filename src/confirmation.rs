@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub static CONFIRMATION_TTL: Duration = Duration::from_secs(60);
+static TOKEN_BYTES: usize = 16;
+
+///
+/// short-lived tokens confirming a dangerous operation on a task, one per label.
+/// The first request is answered with a token, and the operation proceeds
+/// only when the request is repeated with that token before it expires.
+///
+pub struct Confirmations {
+    ttl: Duration,
+    tokens: HashMap<String, (String, Instant)>,
+}
+
+impl Confirmations {
+    pub fn new(ttl: Duration) -> Confirmations {
+        Confirmations {
+            ttl,
+            tokens: HashMap::new(),
+        }
+    }
+
+    ///
+    /// confirmations expiring after `CONFIRMATION_TTL`, shared by all workers
+    ///
+    pub fn shared() -> Arc<Mutex<Confirmations>> {
+        Arc::new(Mutex::new(Confirmations::new(CONFIRMATION_TTL)))
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    ///
+    /// a new random token for `label`, replacing any previous one
+    ///
+    pub fn issue(&mut self, label: &str, now: Instant) -> String {
+        self.remove_expired(now);
+        let mut bytes = [0u8; TOKEN_BYTES];
+        openssl::rand::rand_bytes(&mut bytes).expect("cannot generate random token");
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        self.tokens
+            .insert(label.to_string(), (token.clone(), now + self.ttl));
+        token
+    }
+
+    ///
+    /// whether `token` is the unexpired token of `label`.
+    /// A matching token is used up.
+    ///
+    pub fn confirm(&mut self, label: &str, token: &str, now: Instant) -> bool {
+        self.remove_expired(now);
+        match self.tokens.get(label) {
+            Some((expected, _)) if expected == token => {
+                self.tokens.remove(label);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        self.tokens.retain(|_, (_, expires_at)| now < *expires_at);
+    }
+}
+
+#[cfg(test)]
+mod test_confirmation_mod {
+    use super::*;
+
+    #[test]
+    fn two_step_confirmation() {
+        let mut confirmations = Confirmations::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let token = confirmations.issue("backup", now);
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+
+        // wrong token, or token of another label
+        assert!(!confirmations.confirm("backup", "0123", now));
+        assert!(!confirmations.confirm("scraper", &token, now));
+
+        assert!(confirmations.confirm("backup", &token, now + Duration::from_secs(30)));
+        // a token is used only once
+        assert!(!confirmations.confirm("backup", &token, now + Duration::from_secs(31)));
+
+        // a new token replaces the previous one
+        let first = confirmations.issue("backup", now);
+        let second = confirmations.issue("backup", now);
+        assert_ne!(first, second);
+        assert!(!confirmations.confirm("backup", &first, now));
+        assert!(confirmations.confirm("backup", &second, now));
+    }
+
+    #[test]
+    fn token_expires() {
+        let mut confirmations = Confirmations::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let token = confirmations.issue("backup", now);
+        confirmations.issue("scraper", now + Duration::from_secs(30));
+        assert!(!confirmations.confirm("backup", &token, now + Duration::from_secs(60)));
+        // expired tokens are removed
+        assert_eq!(confirmations.tokens.len(), 1);
+        assert!(confirmations.tokens.contains_key("scraper"));
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "server")]
+use crate::launchctl::DeleteReport;
+
 #[derive(Debug)]
 pub enum Error {
     YamlError(String),
@@ -11,6 +14,12 @@ pub enum Error {
     RenameError(String),
     CopyError(String),
     IllegalDomainName(String),
+    IllegalBindAddress(String),
+    IllegalCidr(String),
+    IllegalTimestamp(String),
+    IllegalLabel(String),
+    InvalidEnvironment(String),
+    SslError(String),
     NonUtfError(String),
     ErrorCreatingFolder(String),
     ErrorCreatingPlist(String),
@@ -31,4 +40,148 @@ pub enum Error {
     PathDoesNotExist(String),
     ZipFailure(String),
     FailedToFindYamlInMeta(String),
+    FailedToRotateLog(String),
+    FailedToKickstartTask(String),
+    #[cfg(feature = "server")]
+    FailedToDeleteTask(DeleteReport),
+    PlistNotFound(String),
+    UnknownStatus(String),
+    FailedToUpdateDesiredState(String),
+    CommandTimeout(String),
+    MultipleYamlFound(Vec<String>),
+    ZipTooLarge(String),
+    PlistParseError(String),
+    FailedToAdoptTask(String),
+    UnsupportedCompression(String),
+    InvalidConfigPatch(String),
+    CannotLoadTemplate(String),
+    NotATemplate(String),
+    FailedToCreateFromTemplate(String),
+    MissingTemplateValues(Vec<String>),
+    UnusedTemplateValues(Vec<String>),
+    FailedToUpdateSecrets(String),
+    UnixSocketError(String),
+    PathEscapesTask(String),
+    PlistModifiedExternally(String),
+    ScopeError(String),
+    FailedToUpdateProvenance(String),
+    FailedToRunOnce(String),
+    TaskIsRunning(String),
+    FailedToNotify(String),
+    IllegalEnvironmentVariable(String),
+    LaunchctlBusy(String),
+    NotManagedByTasker(String),
+    YamlVersionMismatch(String),
+    InvalidLabelPattern(String),
+    TaskAlreadyExists(String),
+    GitRemoteUnreachable(String),
+    GitRefNotFound(String),
+    GitError(String),
+    ConfigUnknownUser(String),
+    ConfigUnknownGroup(String),
+    FailedToUpdateMetaIndex(String),
+    InvalidSignal(String),
+    TaskNotRunning(String),
+    FailedToSignalTask(String),
+    BackupInProgress(String),
+}
+
+impl Error {
+    ///
+    /// the name of the variant, e.g. `FailedToChown`, which clients may match on
+    ///
+    pub fn code(&self) -> String {
+        let detail = self.detail();
+        match detail.find('(') {
+            Some(end) => detail[..end].to_string(),
+            None => detail,
+        }
+    }
+
+    ///
+    /// the full internals of the error, for the server log
+    ///
+    pub fn detail(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    ///
+    /// a generic message replacing the message of errors that may reveal the internals
+    /// of the server, like the stderr of `launchctl`, user names or absolute paths.
+    /// `None` for errors that only describe the request, like validation errors,
+    /// whose message is safe to return.
+    ///
+    fn generic_message(&self) -> Option<&'static str> {
+        let message = match self {
+            Error::LaunchctlListError(_) => "`launchctl list` failed",
+            Error::RenameError(_) => "a file of the task cannot be renamed",
+            Error::CopyError(_) => "the files of the task cannot be copied",
+            Error::SslError(_) => "the SSL key or certificate cannot be used",
+            Error::NonUtfError(_) => "a file of the task is not valid UTF-8",
+            Error::ErrorCreatingFolder(_) => "a folder of the task cannot be created",
+            Error::ErrorCreatingPlist(_) => "the plist of the task cannot be written",
+            Error::ErrorMoveYamlToMeta(_) => "the yaml of the task cannot be stored",
+            Error::NoFileToDelete(_) => "a file of the task cannot be deleted",
+            Error::FailedToLoadTask(_) => {
+                "launchctl failed to load the task, e.g. it is already loaded"
+            }
+            Error::FailedToUnloadTask(_) => {
+                "launchctl failed to unload the task, e.g. it is not loaded"
+            }
+            Error::FailedToReplaceRootAlias(_) => "`~root~/` cannot be expanded",
+            Error::CommandExecutionError(_) => "a system command failed",
+            Error::FailedToReadMetaFolder(_) => "the stored yaml files cannot be listed",
+            Error::FailedToRemoveFolder(_) => "a folder of the task cannot be removed",
+            Error::FailedToUpdateMetaYaml(_) => "the yaml of the task cannot be updated",
+            Error::FailedToChown(_) => {
+                "the owner of the task files cannot be changed, check `UserName` and `GroupName`"
+            }
+            Error::ZipFailure(_) => "the zip cannot be written",
+            Error::FailedToFindYamlInMeta(_) => "the stored yaml of the task is missing",
+            Error::FailedToRotateLog(_) => "the logs of the task cannot be rotated",
+            Error::FailedToKickstartTask(_) => {
+                "launchctl failed to start the task, which must be loaded"
+            }
+            #[cfg(feature = "server")]
+            Error::FailedToDeleteTask(_) => "some steps of deleting the task failed",
+            Error::PlistNotFound(_) => "the plist of the task is missing",
+            Error::FailedToUpdateDesiredState(_) => {
+                "whether the task should be loaded cannot be stored"
+            }
+            Error::CommandTimeout(_) => "a system command timed out",
+            Error::PlistParseError(_) => "the plist cannot be read",
+            Error::FailedToUpdateSecrets(_) => "the secret keys of the task cannot be stored",
+            Error::UnixSocketError(_) => "the unix socket cannot be set up",
+            Error::FailedToUpdateProvenance(_) => "the provenance of the task cannot be stored",
+            Error::FailedToRunOnce(_) => "the task cannot be started",
+            Error::FailedToNotify(_) => "the notification cannot be sent",
+            Error::GitRemoteUnreachable(_) => {
+                "the repository cannot be reached, check the url and that it is public"
+            }
+            Error::GitRefNotFound(_) => "the branch, tag or commit is not in the repository",
+            Error::GitError(_) => "git failed, check the url, ref and subdir",
+            Error::FailedToUpdateMetaIndex(_) => "the index of stored yaml files cannot be written",
+            Error::FailedToSignalTask(_) => "the process of the task cannot be signaled",
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    ///
+    /// whether the message is replaced by a generic one in responses
+    ///
+    pub fn is_hidden(&self) -> bool {
+        self.generic_message().is_some()
+    }
+
+    ///
+    /// the body of an error response: `detail` if the error reveals nothing of the server
+    /// or `verbose`, else `code` with the generic message, like `CommandTimeout("...")`
+    ///
+    pub fn response_body(&self, verbose: bool) -> String {
+        match self.generic_message() {
+            Some(message) if !verbose => format!("{}({:?})", self.code(), message),
+            _ => self.detail(),
+        }
+    }
 }
@@ -0,0 +1,151 @@
+use crate::launchctl::{diff_snapshots, snapshot, Status, TaskInfo};
+use crate::notify::notify_errors;
+use crate::supervisor::supervise;
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::web;
+use actix_web_actors::ws;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+static POLL_INTERVAL_SECS: u64 = 2;
+static EVENT_BUFFER: usize = 256;
+static HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+static CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<Arc<String>> = broadcast::channel(EVENT_BUFFER).0;
+    static ref SNAPSHOT: Mutex<BTreeSet<TaskInfo>> = Mutex::new(BTreeSet::new());
+}
+
+///
+/// the latest snapshot as JSON, and a receiver of every change after it
+///
+fn snapshot_and_receiver() -> (String, broadcast::Receiver<Arc<String>>) {
+    // subscribe under the lock so that no change is missed or sent twice
+    let snapshot = SNAPSHOT.lock().unwrap();
+    let tasks = serde_json::to_string(&*snapshot).unwrap_or_else(|_| "[]".to_string());
+    (tasks, EVENTS.subscribe())
+}
+
+///
+/// the status of each task in the latest snapshot, at most `POLL_INTERVAL_SECS` old
+///
+pub fn latest_statuses() -> BTreeMap<String, Status> {
+    SNAPSHOT
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|task| (task.label().to_string(), task.status()))
+        .collect()
+}
+
+///
+/// broadcast the changes from the previous snapshot to `new`
+///
+fn publish(new: BTreeSet<TaskInfo>) {
+    let mut snapshot = SNAPSHOT.lock().unwrap();
+    for event in diff_snapshots(&snapshot, &new) {
+        if let Ok(message) = serde_json::to_string(&event) {
+            // sending fails only if nobody is listening
+            let _ = EVENTS.send(Arc::new(message));
+        }
+    }
+    *snapshot = new;
+}
+
+///
+/// list all tasks every `POLL_INTERVAL_SECS`, stop runs exceeding `MaxRunSeconds`,
+/// alert notifiers of failed tasks, and broadcast the changes to `/ws` clients
+///
+pub async fn poll_changes_periodically() {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(POLL_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        // the run history and run state are written in the same blocking call
+        let snapshot_and_supervise = || snapshot().inspect(supervise);
+        match web::block(snapshot_and_supervise).await {
+            Ok(new) => {
+                notify_errors(&new);
+                publish(new)
+            }
+            Err(e) => eprintln!("task poll failure: {:?}", e),
+        }
+    }
+}
+
+///
+/// a WebSocket session, which sends the task list on connect and then every change.
+/// A client that falls `EVENT_BUFFER` events behind is disconnected.
+///
+pub struct TaskEvents {
+    snapshot: String,
+    events: Option<broadcast::Receiver<Arc<String>>>,
+    heartbeat: Instant,
+}
+
+impl TaskEvents {
+    pub fn subscribe() -> TaskEvents {
+        let (snapshot, events) = snapshot_and_receiver();
+        TaskEvents {
+            snapshot,
+            events: Some(events),
+            heartbeat: Instant::now(),
+        }
+    }
+}
+
+impl Actor for TaskEvents {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.text(std::mem::take(&mut self.snapshot));
+        if let Some(events) = self.events.take() {
+            ctx.add_stream(events.into_stream());
+        }
+        ctx.run_interval(HEARTBEAT_INTERVAL, |session, ctx| {
+            if Instant::now().duration_since(session.heartbeat) > CLIENT_TIMEOUT {
+                ctx.stop();
+            } else {
+                ctx.ping(b"");
+            }
+        });
+    }
+}
+
+impl StreamHandler<Result<Arc<String>, broadcast::RecvError>> for TaskEvents {
+    fn handle(
+        &mut self,
+        event: Result<Arc<String>, broadcast::RecvError>,
+        ctx: &mut Self::Context,
+    ) {
+        match event {
+            Ok(message) => ctx.text(message.as_str()),
+            Err(_) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Policy,
+                    description: Some("client too slow".to_string()),
+                }));
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for TaskEvents {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => self.heartbeat = Instant::now(),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(_) => ctx.stop(),
+        }
+    }
+}
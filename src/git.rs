@@ -0,0 +1,264 @@
+use crate::cleanup::InProgress;
+use crate::error::Error;
+use crate::utils::{execute_command_timeout, resolve_inside, try_to_remove_folder};
+use crate::TEMP_ZIP_PATH;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static GIT: &str = "git";
+static GIT_DIR: &str = ".git";
+/// a clone or fetch taking longer than this is killed
+static GIT_TIMEOUT: Duration = Duration::from_secs(300);
+static CHECKOUT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// a shallow checkout of a git repository in a unique temp folder, without `.git`.
+/// The folder is removed when this is dropped, whether the task was created or not.
+///
+pub struct GitCheckout {
+    dir: PathBuf,
+    /// the commit checked out
+    pub commit: String,
+    _in_progress: InProgress,
+}
+
+impl GitCheckout {
+    ///
+    /// check out `git_ref` (a branch, a tag or a full commit hash) of the repository at `url`:
+    /// - `GitRemoteUnreachable` if the repository cannot be listed
+    /// - `GitRefNotFound` if it has no such branch, tag or commit
+    ///
+    pub fn checkout(url: &str, git_ref: &str) -> Result<GitCheckout, Error> {
+        check_argument("url", url)?;
+        check_argument("ref", git_ref)?;
+        let refs = match git(None, &["ls-remote", "--", url]) {
+            Ok(refs) => refs,
+            Err(e) => {
+                return Err(Error::GitRemoteUnreachable(format!(
+                    "cannot list `{}`: {}",
+                    url,
+                    git_message(e)
+                )))
+            }
+        };
+        let dir = unique_checkout_dir();
+        let mut checkout = GitCheckout {
+            _in_progress: InProgress::new(&dir),
+            dir,
+            commit: String::new(),
+        };
+        try_to_remove_folder(&checkout.dir)?;
+        let dir = checkout.dir.to_string_lossy().to_string();
+        let not_found = |reason: String| {
+            Error::GitRefNotFound(format!(
+                "ref `{}` not found in `{}`{}",
+                git_ref, url, reason
+            ))
+        };
+        if has_ref(&refs, git_ref) {
+            git(
+                None,
+                &[
+                    "clone", "--quiet", "--depth", "1", "--branch", git_ref, "--", url, &dir,
+                ],
+            )?;
+        } else if is_commit_hash(git_ref) {
+            git(None, &["init", "--quiet", "--", &dir])?;
+            let dir = Some(checkout.dir.as_path());
+            if let Err(e) = git(
+                dir,
+                &["fetch", "--quiet", "--depth", "1", "--", url, git_ref],
+            ) {
+                return Err(not_found(format!(": {}", git_message(e))));
+            }
+            git(dir, &["checkout", "--quiet", "--detach", "FETCH_HEAD"])?;
+        } else {
+            return Err(not_found(String::new()));
+        }
+        checkout.commit = git(Some(&checkout.dir), &["rev-parse", "HEAD"])?
+            .trim()
+            .to_string();
+        try_to_remove_folder(&checkout.dir.join(GIT_DIR))?;
+        Ok(checkout)
+    }
+
+    ///
+    /// the folder of the task: `subdir` of the checkout, or else the whole checkout
+    ///
+    pub fn task_folder(&self, subdir: &Option<String>) -> Result<PathBuf, Error> {
+        let folder = match subdir.as_deref().map(|s| s.trim_matches('/')) {
+            None | Some("") => return Ok(self.dir.clone()),
+            Some(subdir) => resolve_inside(&self.dir, subdir)?,
+        };
+        if !folder.is_dir() {
+            return Err(Error::GitError(format!(
+                "`{}` is not a folder of the repository",
+                subdir.as_deref().unwrap_or_default()
+            )));
+        }
+        Ok(folder)
+    }
+}
+
+impl Drop for GitCheckout {
+    fn drop(&mut self) {
+        if let Err(e) = try_to_remove_folder(&self.dir) {
+            eprintln!("failed to remove git checkout: {:?}", e);
+        }
+    }
+}
+
+fn unique_checkout_dir() -> PathBuf {
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos(),
+        Err(_) => 0,
+    };
+    Path::new(TEMP_ZIP_PATH).join(format!(
+        "git_checkout.{}.{}.{}",
+        std::process::id(),
+        timestamp,
+        CHECKOUT_COUNT.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+///
+/// run git without asking for credentials, in `dir` if given
+///
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, Error> {
+    let mut command = Command::new(GIT);
+    command.env("GIT_TERMINAL_PROMPT", "0");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    match execute_command_timeout(command.args(args), GIT_TIMEOUT) {
+        Ok(output) => Ok(output),
+        Err(e) => Err(Error::GitError(git_message(e))),
+    }
+}
+
+fn git_message(e: Error) -> String {
+    match e {
+        Error::CommandExecutionError(message) => message.trim().to_string(),
+        Error::GitError(message) => message,
+        e => format!("{:?}", e),
+    }
+}
+
+///
+/// urls and refs are passed after `--` where possible, and must not look like options
+///
+fn check_argument(name: &str, value: &str) -> Result<(), Error> {
+    if value.is_empty() || value.starts_with('-') || value.chars().any(char::is_control) {
+        return Err(Error::GitError(format!("invalid {} `{}`", name, value)));
+    }
+    Ok(())
+}
+
+///
+/// whether `git_ref` is a branch or a tag in the output of `git ls-remote`
+///
+fn has_ref(refs: &str, git_ref: &str) -> bool {
+    let branch = format!("refs/heads/{}", git_ref);
+    let tag = format!("refs/tags/{}", git_ref);
+    refs.lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .any(|name| name == branch || name == tag)
+}
+
+fn is_commit_hash(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test_git_mod {
+    use super::*;
+
+    #[test]
+    fn ref_kinds() {
+        let refs = "1111111111111111111111111111111111111111\tHEAD\n\
+                    1111111111111111111111111111111111111111\trefs/heads/main\n\
+                    2222222222222222222222222222222222222222\trefs/tags/v1.0\n\
+                    3333333333333333333333333333333333333333\trefs/tags/v1.0^{}\n";
+        assert!(has_ref(refs, "main"));
+        assert!(has_ref(refs, "v1.0"));
+        assert!(!has_ref(refs, "HEAD"));
+        assert!(!has_ref(refs, "mai"));
+        assert!(is_commit_hash("3333333333333333333333333333333333333333"));
+        assert!(!is_commit_hash("3333333"));
+        assert!(check_argument("url", "--upload-pack=touch /tmp/x").is_err());
+        assert!(check_argument("ref", "").is_err());
+        assert!(check_argument("ref", "feature/x").is_ok());
+    }
+
+    ///
+    /// a bare repository at `root/repo.git` with a task in `tasks/hello`,
+    /// branch `main` and tag `v1`. Returns the url and the commit.
+    ///
+    fn bare_repo_fixture(root: &Path) -> (String, String) {
+        let work = root.join("work");
+        let task = work.join("tasks/hello");
+        std::fs::create_dir_all(&task).unwrap();
+        std::fs::write(task.join("hello.yaml"), "Label: hello\nProgram: /bin/ls\n").unwrap();
+        std::fs::write(task.join("run.sh"), "echo hello\n").unwrap();
+        let commit = |args: &[&str]| {
+            let mut all = vec![
+                "-c",
+                "user.name=tasker",
+                "-c",
+                "user.email=tasker@localhost",
+            ];
+            all.extend_from_slice(args);
+            git(Some(&work), &all).unwrap()
+        };
+        commit(&["init", "--quiet", "--initial-branch", "main"]);
+        commit(&["add", "."]);
+        commit(&["commit", "--quiet", "-m", "hello"]);
+        commit(&["tag", "v1"]);
+        let bare = root.join("repo.git");
+        let bare = bare.to_string_lossy();
+        commit(&["clone", "--quiet", "--bare", ".", &bare]);
+        let head = commit(&["rev-parse", "HEAD"]).trim().to_string();
+        (format!("file://{}", bare), head)
+    }
+
+    #[test]
+    fn checkout_bare_repo() {
+        let root = std::env::current_dir().unwrap().join("test_git_checkout");
+        let _ = std::fs::remove_dir_all(&root);
+        let (url, head) = bare_repo_fixture(&root);
+
+        for git_ref in &["main", "v1", head.as_str()] {
+            let checkout = GitCheckout::checkout(&url, git_ref).unwrap();
+            assert_eq!(checkout.commit, head);
+            assert!(!checkout.dir.join(GIT_DIR).exists());
+            let folder = checkout
+                .task_folder(&Some("tasks/hello/".to_string()))
+                .unwrap();
+            assert!(folder.join("hello.yaml").is_file());
+            assert!(checkout.task_folder(&Some("../..".to_string())).is_err());
+            assert!(checkout
+                .task_folder(&Some("tasks/none".to_string()))
+                .is_err());
+            let dir = checkout.dir.clone();
+            drop(checkout);
+            assert!(!dir.exists());
+        }
+
+        match GitCheckout::checkout(&url, "no-such-branch") {
+            Err(Error::GitRefNotFound(_)) => {}
+            _ => panic!("a missing branch must be GitRefNotFound"),
+        }
+        match GitCheckout::checkout(&url, &"0".repeat(40)) {
+            Err(Error::GitRefNotFound(_)) => {}
+            _ => panic!("a missing commit must be GitRefNotFound"),
+        }
+        let missing = format!("file://{}", root.join("missing.git").to_string_lossy());
+        match GitCheckout::checkout(&missing, "main") {
+            Err(Error::GitRemoteUnreachable(_)) => {}
+            _ => panic!("a missing repository must be GitRemoteUnreachable"),
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
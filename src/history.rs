@@ -0,0 +1,225 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::{format_rfc3339, read_utf8_file, write_atomic, MtimeCache};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+pub static HISTORY_EXTENSION: &str = ".history.jsonl";
+static HISTORY_KEEP_COUNT: usize = 100;
+lazy_static! {
+    static ref HISTORY_LOCK: Mutex<()> = Mutex::new(());
+    static ref LAST_RUN_CACHE: Mutex<MtimeCache<Option<RunRecord>>> = Mutex::new(MtimeCache::new());
+}
+
+///
+/// the run history of a task in meta folder, one JSON record per line
+///
+pub fn history_file(label: &str) -> PathBuf {
+    Env::get()
+        .meta_dir
+        .join(String::from(label) + HISTORY_EXTENSION)
+}
+
+///
+/// a completed run of a task. `start` is unknown if the run started while
+/// tasker was not running, and `end` if it ended while tasker was not running,
+/// in which case `duration_secs` is unknown as well.
+///
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct RunRecord {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub exit_status: Option<i32>,
+    /// why tasker stopped the run, e.g. `exceeded MaxRunSeconds of 60s`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+impl RunRecord {
+    ///
+    /// a record of a run between `start` and `end` in seconds since epoch
+    ///
+    pub fn new(start: Option<u64>, end: Option<u64>, exit_status: Option<i32>) -> RunRecord {
+        let format = |secs: u64| format_rfc3339(UNIX_EPOCH + Duration::from_secs(secs));
+        RunRecord {
+            start: start.and_then(format),
+            end: end.and_then(format),
+            duration_secs: match (start, end) {
+                (Some(start), Some(end)) => Some(end.saturating_sub(start)),
+                _ => None,
+            },
+            exit_status,
+            stop_reason: None,
+        }
+    }
+}
+
+///
+/// records in a history file, skipping corrupted lines
+///
+fn read_history(file: &Path) -> Vec<RunRecord> {
+    match read_utf8_file(file) {
+        Ok(s) => s
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+///
+/// append `record` to a history file, keeping the last `keep` records
+///
+fn append_history(file: &Path, record: &RunRecord, keep: usize) -> Result<(), Error> {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    let mut records = read_history(file);
+    records.push(record.clone());
+    let skip = records.len().saturating_sub(keep);
+    let mut content = String::new();
+    for record in &records[skip..] {
+        // serializing a `RunRecord` does not fail
+        content.push_str(&serde_json::to_string(record).unwrap_or_default());
+        content.push('\n');
+    }
+    write_atomic(file, content.as_bytes())
+}
+
+///
+/// append a completed run to the history of task `label`
+///
+pub fn record_run(label: &str, record: &RunRecord) -> Result<(), Error> {
+    append_history(&history_file(label), record, HISTORY_KEEP_COUNT)
+}
+
+///
+/// the most recent completed run of task `label`, cached until its history changes
+///
+pub fn last_run(label: &str) -> Option<RunRecord> {
+    let file = history_file(label);
+    let mtime = match std::fs::metadata(&file).and_then(|meta| meta.modified()) {
+        Ok(mtime) => mtime,
+        Err(_) => return None,
+    };
+    let mut cache = LAST_RUN_CACHE.lock().unwrap();
+    cache.get_or_update(label, mtime, || read_history(&file).pop())
+}
+
+///
+/// statistics of the runs in the retained history of a task
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct RunStats {
+    runs: usize,
+    /// runs with a known duration, over which mean and max are computed
+    timed_runs: usize,
+    mean_duration_secs: Option<f64>,
+    max_duration_secs: Option<u64>,
+    /// runs exiting with a non-zero status
+    failures: usize,
+    /// failures among the runs with a known exit status
+    failure_rate: Option<f64>,
+}
+
+impl RunStats {
+    pub fn of(records: &[RunRecord]) -> RunStats {
+        let durations: Vec<u64> = records.iter().filter_map(|r| r.duration_secs).collect();
+        let exits: Vec<i32> = records.iter().filter_map(|r| r.exit_status).collect();
+        let failures = exits.iter().filter(|status| **status != 0).count();
+        RunStats {
+            runs: records.len(),
+            timed_runs: durations.len(),
+            mean_duration_secs: if durations.is_empty() {
+                None
+            } else {
+                Some(durations.iter().sum::<u64>() as f64 / durations.len() as f64)
+            },
+            max_duration_secs: durations.iter().max().cloned(),
+            failures,
+            failure_rate: if exits.is_empty() {
+                None
+            } else {
+                Some(failures as f64 / exits.len() as f64)
+            },
+        }
+    }
+}
+
+///
+/// statistics of the retained run history of task `label`
+///
+pub fn run_stats(label: &str) -> RunStats {
+    RunStats::of(&read_history(&history_file(label)))
+}
+
+#[cfg(test)]
+mod test_history_mod {
+    use super::*;
+
+    #[test]
+    fn run_record_durations() {
+        let record = RunRecord::new(Some(1_600_000_000), Some(1_600_000_240), Some(0));
+        assert_eq!(record.start.as_deref(), Some("2020-09-13T12:26:40Z"));
+        assert_eq!(record.end.as_deref(), Some("2020-09-13T12:30:40Z"));
+        assert_eq!(record.duration_secs, Some(240));
+
+        // tasker restarted during the run
+        assert_eq!(
+            RunRecord::new(None, Some(1_600_000_240), Some(0)).duration_secs,
+            None
+        );
+        assert_eq!(
+            RunRecord::new(Some(1_600_000_000), None, None).duration_secs,
+            None
+        );
+    }
+
+    #[test]
+    fn stats_of_history_file() {
+        let file = Path::new("test_history.jsonl");
+        let lines = [
+            r#"{"start":"2020-09-13T12:00:00Z","end":"2020-09-13T12:04:00Z","duration_secs":240,"exit_status":0}"#,
+            r#"{"start":null,"end":"2020-09-14T12:01:00Z","duration_secs":null,"exit_status":0}"#,
+            "corrupted line",
+            r#"{"start":"2020-09-15T12:00:00Z","end":"2020-09-15T14:00:00Z","duration_secs":7200,"exit_status":1}"#,
+            r#"{"start":"2020-09-16T12:00:00Z","end":"2020-09-16T12:01:00Z","duration_secs":60,"exit_status":null}"#,
+        ];
+        std::fs::write(file, lines.join("\n")).unwrap();
+        let records = read_history(file);
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            RunStats::of(&records),
+            RunStats {
+                runs: 4,
+                timed_runs: 3,
+                mean_duration_secs: Some(2500.0),
+                max_duration_secs: Some(7200),
+                failures: 1,
+                failure_rate: Some(1.0 / 3.0),
+            }
+        );
+
+        // only the last records are kept
+        append_history(file, &RunRecord::new(Some(0), Some(120), Some(0)), 2).unwrap();
+        let records = read_history(file);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].duration_secs, Some(60));
+        assert_eq!(records[1].duration_secs, Some(120));
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(
+            RunStats::of(&[]),
+            RunStats {
+                runs: 0,
+                timed_runs: 0,
+                mean_duration_secs: None,
+                max_duration_secs: None,
+                failures: 0,
+                failure_rate: None,
+            }
+        );
+    }
+}
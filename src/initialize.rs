@@ -1,10 +1,21 @@
 use crate::error::Error;
+use crate::launchctl_backend;
+use crate::net_util::Cidr;
 use crate::utils;
+use crate::{AGENT_PLIST_FOLDER, PLIST_FOLDER};
+use lettre::message::Mailbox;
+use openssl::asn1::Asn1Time;
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use openssl::x509::X509;
 use regex::Regex;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub struct Env {
     domain: String,
+    bind_address: IpAddr,
     port: u16,
     pub tasker_root: PathBuf,
     pub meta_dir: PathBuf,
@@ -13,14 +24,66 @@ pub struct Env {
     pub out_dir: PathBuf,
     pub pk_dir: Option<PathBuf>,
     pub crt_dir: Option<PathBuf>,
+    /// the subject and expiry of `SSL_CERTIFICATE`, read at startup
+    pub certificate: Option<CertificateInfo>,
     pub user_name: String,
     pub password: String,
+    pub max_log_bytes: u64,
+    pub autoload_on_start: bool,
+    pub api_tokens: Vec<String>,
+    pub rate_limit_per_min: u32,
+    pub command_timeout: Duration,
+    pub max_uncompressed_bytes: u64,
+    pub temp_cleanup_interval: Duration,
+    pub temp_max_age: Duration,
+    pub base_path: String,
+    pub trash_retention: Option<Duration>,
+    pub shutdown_timeout: Duration,
+    pub unix_socket: Option<PathBuf>,
+    pub unix_socket_mode: u32,
+    pub unix_socket_owner: Option<String>,
+    pub unix_socket_group: Option<String>,
+    pub trusted_proxies: Vec<Cidr>,
+    pub disk_usage_cache: Duration,
+    pub slow_request: Duration,
+    pub launchctl_concurrency: usize,
+    pub launchctl_max_pending: usize,
+    pub launchctl_list_cache: Duration,
+    pub max_concurrent_exports: usize,
+    pub max_concurrent_uploads: usize,
+    pub verbose_errors: bool,
+    pub backup_dir: PathBuf,
+    pub backup_interval: Option<Duration>,
+    pub backup_keep: usize,
+    /// where the plists of daemons are placed, `/Library/LaunchDaemons/` unless `PLIST_DIR`
+    pub plist_dir: PathBuf,
+    /// where the plists of agents are placed, `/Library/LaunchAgents/` unless `PLIST_DIR`
+    pub agent_plist_dir: PathBuf,
+    pub smtp: Option<SmtpSettings>,
 }
 
-static META_FOLDER: &str = "meta";
-static TASK_FOLDER: &str = "tasks";
-static TRASH_FOLDER: &str = "trash";
-static OUT_FOLDER: &str = "out";
+///
+/// the SMTP server and the addresses of email alerts, set if `SMTP_HOST` is
+///
+#[derive(Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub credentials: Option<(String, String)>,
+    pub from: Mailbox,
+    pub to: Vec<Mailbox>,
+}
+
+pub(crate) static META_FOLDER: &str = "meta";
+pub(crate) static TASK_FOLDER: &str = "tasks";
+pub(crate) static TRASH_FOLDER: &str = "trash";
+pub(crate) static OUT_FOLDER: &str = "out";
+static BACKUP_FOLDER: &str = "backups";
+static WRITE_CHECK_FILE: &str = ".tasker_write_check";
+static MASKED: &str = "********";
+static SECS_PER_DAY: i64 = 24 * 60 * 60;
+/// warn at startup if the certificate expires within this many days
+pub static CERTIFICATE_WARNING_DAYS: i64 = 30;
 static DOMAIN_RE: &str = "^[A-Za-z0-9]{1,63}(\\.[A-Za-z0-9]{1,63})*$";
 lazy_static! {
     static ref ENVIRONMENT: Env = Env::init();
@@ -29,9 +92,17 @@ lazy_static! {
 
 impl Env {
     fn init() -> Env {
+        Env::try_init().unwrap_or_else(|e| panic!("{:?}", e))
+    }
+
+    ///
+    /// read and check the environment variables, and create missing tasker folders
+    ///
+    pub fn try_init() -> Result<Env, Error> {
         std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
         // check or create folders
-        let tasker_root = std::env::var("TASKER_ROOT").expect("TASKER_ROOT not found in Env");
+        let tasker_root =
+            std::env::var("TASKER_ROOT").map_err(|_| invalid("TASKER_ROOT not found in Env"))?;
         let pk_dir = match std::env::var("SSL_PRIVATE_KEY") {
             Ok(d) => Some(Path::new(&d).to_owned()),
             Err(_) => None,
@@ -40,47 +111,308 @@ impl Env {
             Ok(d) => Some(Path::new(&d).to_owned()),
             Err(_) => None,
         };
+        let certificate = match &crt_dir {
+            Some(crt) => Some(read_certificate(crt)?),
+            None => None,
+        };
         let user_name = match std::env::var("USERNAME") {
             Ok(d) => {
                 if d.len() < 5 {
-                    panic!("USERNAME must be at least 5 characters")
+                    return Err(invalid("USERNAME must be at least 5 characters"));
                 } else {
                     d
                 }
             }
-            Err(_) => panic!("USERNAME missing in env"),
+            Err(_) => return Err(invalid("USERNAME missing in env")),
         };
         let password = match std::env::var("PASSWORD") {
             Ok(d) => {
                 if d.len() < 12 {
-                    panic!("PASSWORD must be at least 12 characters")
+                    return Err(invalid("PASSWORD must be at least 12 characters"));
                 } else {
                     d
                 }
             }
-            Err(_) => panic!("PASSWORD missing in env"),
+            Err(_) => return Err(invalid("PASSWORD missing in env")),
         };
+        let api_tokens = Env::read_api_tokens()?;
         let tasker_root = std::path::Path::new(&tasker_root).to_owned();
         let meta_dir = tasker_root.join(META_FOLDER);
         let trash_dir = tasker_root.join(TRASH_FOLDER);
         let task_dir = tasker_root.join(TASK_FOLDER);
         let out_dir = tasker_root.join(OUT_FOLDER);
-        utils::create_dir_check(&tasker_root).expect("failed to create tasker_root");
-        utils::create_dir_check(&meta_dir).expect("failed to create meta_dir");
-        utils::create_dir_check(&trash_dir).expect("failed to create trash_dir");
-        utils::create_dir_check(&task_dir).expect("failed to create task_dir");
-        utils::create_dir_check(&out_dir).expect("failed to create out_dir");
+        utils::create_dir_check(&tasker_root)
+            .map_err(|e| invalid(&format!("failed to create tasker_root: {:?}", e)))?;
+        utils::create_dir_check(&meta_dir)
+            .map_err(|e| invalid(&format!("failed to create meta_dir: {:?}", e)))?;
+        utils::create_dir_check(&trash_dir)
+            .map_err(|e| invalid(&format!("failed to create trash_dir: {:?}", e)))?;
+        utils::create_dir_check(&task_dir)
+            .map_err(|e| invalid(&format!("failed to create task_dir: {:?}", e)))?;
+        utils::create_dir_check(&out_dir)
+            .map_err(|e| invalid(&format!("failed to create out_dir: {:?}", e)))?;
 
-        // check domain and port number
+        // check domain, bind address and port number
         let domain: String = std::env::var("DOMAIN").unwrap_or_else(|_| "localhost".to_string());
-        Env::check_domain_name(&domain).unwrap();
+        Env::check_domain_name(&domain)?;
+        let bind_address: String =
+            std::env::var("BIND_ADDRESS").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let bind_address = Env::check_bind_address(&bind_address)?;
         let port: String = std::env::var("PORT").unwrap_or_else(|_| "54321".to_string());
-        let port: u16 = port.parse().expect("mis-specified port number");
-        if port > 65353 {
-            panic!("port number out of range")
+        let port: u16 = port
+            .parse()
+            .map_err(|_| invalid("mis-specified port number"))?;
+        if port < 1024 && users::get_current_uid() != 0 {
+            eprintln!("warning: port {} is reserved and requires root", port);
         }
-        Env {
+
+        // log rotation threshold
+        let max_log_mb: String = std::env::var("MAX_LOG_MB").unwrap_or_else(|_| "100".to_string());
+        let max_log_mb: u64 = max_log_mb
+            .parse()
+            .map_err(|_| invalid("mis-specified MAX_LOG_MB"))?;
+        if max_log_mb == 0 {
+            return Err(invalid("MAX_LOG_MB must be positive"));
+        }
+
+        // requests allowed per client per minute
+        let rate_limit_per_min: String =
+            std::env::var("RATE_LIMIT_PER_MIN").unwrap_or_else(|_| "600".to_string());
+        let rate_limit_per_min: u32 = rate_limit_per_min
+            .parse()
+            .map_err(|_| invalid("mis-specified RATE_LIMIT_PER_MIN"))?;
+        if rate_limit_per_min == 0 {
+            return Err(invalid("RATE_LIMIT_PER_MIN must be positive"));
+        }
+
+        // timeout of launchctl and other commands
+        let command_timeout_secs: String =
+            std::env::var("COMMAND_TIMEOUT_SECS").unwrap_or_else(|_| "10".to_string());
+        let command_timeout_secs: u64 = command_timeout_secs
+            .parse()
+            .map_err(|_| invalid("mis-specified COMMAND_TIMEOUT_SECS"))?;
+        if command_timeout_secs == 0 {
+            return Err(invalid("COMMAND_TIMEOUT_SECS must be positive"));
+        }
+
+        // maximum uncompressed size of uploaded zip
+        let max_uncompressed_mb: String =
+            std::env::var("MAX_UNCOMPRESSED_MB").unwrap_or_else(|_| "1024".to_string());
+        let max_uncompressed_mb: u64 = max_uncompressed_mb
+            .parse()
+            .map_err(|_| invalid("mis-specified MAX_UNCOMPRESSED_MB"))?;
+        if max_uncompressed_mb == 0 {
+            return Err(invalid("MAX_UNCOMPRESSED_MB must be positive"));
+        }
+
+        // interval of temp folder cleanup
+        let temp_cleanup_interval_secs: String =
+            std::env::var("TEMP_CLEANUP_INTERVAL_SECS").unwrap_or_else(|_| "3600".to_string());
+        let temp_cleanup_interval_secs: u64 = temp_cleanup_interval_secs
+            .parse()
+            .map_err(|_| invalid("mis-specified TEMP_CLEANUP_INTERVAL_SECS"))?;
+        if temp_cleanup_interval_secs == 0 {
+            return Err(invalid("TEMP_CLEANUP_INTERVAL_SECS must be positive"));
+        }
+
+        // temp files older than this are removed by cleanup
+        let temp_max_age_secs: String =
+            std::env::var("TEMP_MAX_AGE_SECS").unwrap_or_else(|_| "86400".to_string());
+        let temp_max_age_secs: u64 = temp_max_age_secs
+            .parse()
+            .map_err(|_| invalid("mis-specified TEMP_MAX_AGE_SECS"))?;
+        if temp_max_age_secs == 0 {
+            return Err(invalid("TEMP_MAX_AGE_SECS must be positive"));
+        }
+
+        // trash older than this is purged, 0 to keep forever
+        let trash_retention_days: String =
+            std::env::var("TRASH_RETENTION_DAYS").unwrap_or_else(|_| "30".to_string());
+        let trash_retention_days: u64 = trash_retention_days
+            .parse()
+            .map_err(|_| invalid("mis-specified TRASH_RETENTION_DAYS"))?;
+        let trash_retention = match trash_retention_days {
+            0 => None,
+            days => Some(Duration::from_secs(days * 24 * 60 * 60)),
+        };
+
+        // path prefix of the web pages behind a reverse proxy
+        let base_path = std::env::var("BASE_PATH").unwrap_or_default();
+        let base_path = base_path.trim_end_matches('/').to_string();
+        if !base_path.is_empty() && !base_path.starts_with('/') {
+            return Err(invalid("BASE_PATH must start with `/`"));
+        }
+
+        // time for requests and task operations in progress to finish on shutdown
+        let shutdown_timeout_secs: String =
+            std::env::var("SHUTDOWN_TIMEOUT_SECS").unwrap_or_else(|_| "30".to_string());
+        let shutdown_timeout_secs: u64 = shutdown_timeout_secs
+            .parse()
+            .map_err(|_| invalid("mis-specified SHUTDOWN_TIMEOUT_SECS"))?;
+        if shutdown_timeout_secs == 0 {
+            return Err(invalid("SHUTDOWN_TIMEOUT_SECS must be positive"));
+        }
+
+        // listen on a unix domain socket instead of tcp
+        let unix_socket = match std::env::var("UNIX_SOCKET") {
+            Ok(d) => {
+                let path = PathBuf::from(d);
+                if !path.is_absolute() {
+                    return Err(invalid("UNIX_SOCKET must be an absolute path"));
+                }
+                Some(path)
+            }
+            Err(_) => None,
+        };
+        let unix_socket_mode: String =
+            std::env::var("UNIX_SOCKET_MODE").unwrap_or_else(|_| "660".to_string());
+        let unix_socket_mode = u32::from_str_radix(&unix_socket_mode, 8)
+            .map_err(|_| invalid("mis-specified UNIX_SOCKET_MODE, must be octal like 660"))?;
+        if unix_socket_mode > 0o777 {
+            return Err(invalid("UNIX_SOCKET_MODE out of range"));
+        }
+        let unix_socket_owner = std::env::var("UNIX_SOCKET_OWNER").ok();
+        if let Some(owner) = &unix_socket_owner {
+            if users::get_user_by_name(owner).is_none() {
+                return Err(invalid(&format!(
+                    "UNIX_SOCKET_OWNER `{}` does not exist",
+                    owner
+                )));
+            }
+        }
+        let unix_socket_group = std::env::var("UNIX_SOCKET_GROUP").ok();
+        if let Some(group) = &unix_socket_group {
+            if users::get_group_by_name(group).is_none() {
+                return Err(invalid(&format!(
+                    "UNIX_SOCKET_GROUP `{}` does not exist",
+                    group
+                )));
+            }
+        }
+
+        // honor X-Forwarded-For and X-Forwarded-Proto from these reverse proxies
+        let trusted_proxies = match std::env::var("TRUSTED_PROXIES") {
+            Ok(d) => d
+                .split(',')
+                .filter(|cidr| !cidr.trim().is_empty())
+                .map(|cidr| cidr.parse())
+                .collect::<Result<Vec<Cidr>, Error>>()
+                .map_err(|e| invalid(&format!("mis-specified TRUSTED_PROXIES: {:?}", e)))?,
+            Err(_) => Vec::new(),
+        };
+
+        // `/disk_usage` walks the tasker folders at most once in this time, 0 to always walk
+        let disk_usage_cache_secs: String =
+            std::env::var("DISK_USAGE_CACHE_SECS").unwrap_or_else(|_| "60".to_string());
+        let disk_usage_cache_secs: u64 = disk_usage_cache_secs
+            .parse()
+            .map_err(|_| invalid("mis-specified DISK_USAGE_CACHE_SECS"))?;
+
+        // requests taking at least this long are logged with their request id
+        let slow_request_ms: String =
+            std::env::var("SLOW_REQUEST_MS").unwrap_or_else(|_| "1000".to_string());
+        let slow_request_ms: u64 = slow_request_ms
+            .parse()
+            .map_err(|_| invalid("mis-specified SLOW_REQUEST_MS"))?;
+
+        // launchctl invocations running at once, and waiting before new ones are refused
+        let launchctl_concurrency: String =
+            std::env::var("LAUNCHCTL_CONCURRENCY").unwrap_or_else(|_| "2".to_string());
+        let launchctl_concurrency: usize = launchctl_concurrency
+            .parse()
+            .map_err(|_| invalid("mis-specified LAUNCHCTL_CONCURRENCY"))?;
+        if launchctl_concurrency == 0 {
+            return Err(invalid("LAUNCHCTL_CONCURRENCY must be positive"));
+        }
+        let launchctl_max_pending: String =
+            std::env::var("LAUNCHCTL_MAX_PENDING").unwrap_or_else(|_| "32".to_string());
+        let launchctl_max_pending: usize = launchctl_max_pending
+            .parse()
+            .map_err(|_| invalid("mis-specified LAUNCHCTL_MAX_PENDING"))?;
+
+        // `launchctl list` is reused for this long unless a task is loaded or unloaded
+        let launchctl_list_cache_ms: String =
+            std::env::var("LAUNCHCTL_LIST_CACHE_MS").unwrap_or_else(|_| "2000".to_string());
+        let launchctl_list_cache_ms: u64 = launchctl_list_cache_ms
+            .parse()
+            .map_err(|_| invalid("mis-specified LAUNCHCTL_LIST_CACHE_MS"))?;
+
+        // task zips built at once, and uploads unpacked at once, see `ConcurrencyLimiter`
+        let max_concurrent_exports: String =
+            std::env::var("MAX_CONCURRENT_EXPORTS").unwrap_or_else(|_| "2".to_string());
+        let max_concurrent_exports: usize = max_concurrent_exports
+            .parse()
+            .map_err(|_| invalid("mis-specified MAX_CONCURRENT_EXPORTS"))?;
+        if max_concurrent_exports == 0 {
+            return Err(invalid("MAX_CONCURRENT_EXPORTS must be positive"));
+        }
+        let max_concurrent_uploads: String =
+            std::env::var("MAX_CONCURRENT_UPLOADS").unwrap_or_else(|_| "1".to_string());
+        let max_concurrent_uploads: usize = max_concurrent_uploads
+            .parse()
+            .map_err(|_| invalid("mis-specified MAX_CONCURRENT_UPLOADS"))?;
+        if max_concurrent_uploads == 0 {
+            return Err(invalid("MAX_CONCURRENT_UPLOADS must be positive"));
+        }
+
+        // return the internals of errors to clients, like absolute paths and command output
+        let verbose_errors = match std::env::var("VERBOSE_ERRORS") {
+            Ok(d) => d.eq("1"),
+            Err(_) => false,
+        };
+
+        // back up all tasks into `BACKUP_DIR` every `BACKUP_INTERVAL_HOURS`
+        let backup_dir = match std::env::var("BACKUP_DIR") {
+            Ok(d) => PathBuf::from(d),
+            Err(_) => tasker_root.join(BACKUP_FOLDER),
+        };
+        let backup_interval_hours: String =
+            std::env::var("BACKUP_INTERVAL_HOURS").unwrap_or_else(|_| "0".to_string());
+        let backup_interval_hours: u64 = backup_interval_hours
+            .parse()
+            .map_err(|_| invalid("mis-specified BACKUP_INTERVAL_HOURS"))?;
+        let backup_interval = match backup_interval_hours {
+            0 => None,
+            hours => Some(Duration::from_secs(hours * 60 * 60)),
+        };
+        let backup_keep: String = std::env::var("BACKUP_KEEP").unwrap_or_else(|_| "7".to_string());
+        let backup_keep: usize = backup_keep
+            .parse()
+            .map_err(|_| invalid("mis-specified BACKUP_KEEP"))?;
+        if backup_keep == 0 {
+            return Err(invalid("BACKUP_KEEP must be positive"));
+        }
+
+        // place plists in `PLIST_DIR/LaunchDaemons` and `PLIST_DIR/LaunchAgents` instead,
+        // e.g. to test tasker without root together with `launchctl_backend::MockLaunchctl`
+        let (plist_dir, agent_plist_dir) = match std::env::var("PLIST_DIR") {
+            Ok(d) => {
+                let plist_dir = Path::new(&d).join("LaunchDaemons");
+                let agent_plist_dir = Path::new(&d).join("LaunchAgents");
+                for dir in &[&plist_dir, &agent_plist_dir] {
+                    utils::create_dir_check(dir).map_err(|e| {
+                        invalid(&format!("failed to create {}: {:?}", dir.display(), e))
+                    })?;
+                }
+                (plist_dir, agent_plist_dir)
+            }
+            Err(_) => (
+                PathBuf::from(PLIST_FOLDER),
+                PathBuf::from(AGENT_PLIST_FOLDER),
+            ),
+        };
+
+        // send email alerts about failing tasks
+        let smtp = Env::read_smtp_settings()?;
+
+        // reload tasks that should be loaded on start
+        let autoload_on_start = match std::env::var("AUTOLOAD_ON_START") {
+            Ok(d) => d.eq("1"),
+            Err(_) => false,
+        };
+        Ok(Env {
             domain,
+            bind_address,
             port,
             tasker_root,
             meta_dir,
@@ -89,9 +421,103 @@ impl Env {
             out_dir,
             pk_dir,
             crt_dir,
+            certificate,
             user_name,
             password,
+            max_log_bytes: max_log_mb * 1024 * 1024,
+            autoload_on_start,
+            api_tokens,
+            rate_limit_per_min,
+            command_timeout: Duration::from_secs(command_timeout_secs),
+            max_uncompressed_bytes: max_uncompressed_mb * 1024 * 1024,
+            temp_cleanup_interval: Duration::from_secs(temp_cleanup_interval_secs),
+            temp_max_age: Duration::from_secs(temp_max_age_secs),
+            base_path,
+            trash_retention,
+            shutdown_timeout: Duration::from_secs(shutdown_timeout_secs),
+            unix_socket,
+            unix_socket_mode,
+            unix_socket_owner,
+            unix_socket_group,
+            trusted_proxies,
+            disk_usage_cache: Duration::from_secs(disk_usage_cache_secs),
+            slow_request: Duration::from_millis(slow_request_ms),
+            launchctl_concurrency,
+            launchctl_max_pending,
+            launchctl_list_cache: Duration::from_millis(launchctl_list_cache_ms),
+            max_concurrent_exports,
+            max_concurrent_uploads,
+            verbose_errors,
+            backup_dir,
+            backup_interval,
+            backup_keep,
+            plist_dir,
+            agent_plist_dir,
+            smtp,
+        })
+    }
+
+    /// `SMTP_HOST`, `SMTP_PORT` (587 by default), `SMTP_USER` and `SMTP_PASSWORD`,
+    /// `ALERT_EMAIL_FROM` and the comma-separated `ALERT_EMAIL_TO`, `None` without `SMTP_HOST`
+    fn read_smtp_settings() -> Result<Option<SmtpSettings>, Error> {
+        let host = match std::env::var("SMTP_HOST") {
+            Ok(d) if !d.trim().is_empty() => d.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let port: String = std::env::var("SMTP_PORT").unwrap_or_else(|_| "587".to_string());
+        let port: u16 = port
+            .parse()
+            .map_err(|_| invalid("mis-specified SMTP_PORT"))?;
+        let credentials = match (std::env::var("SMTP_USER"), std::env::var("SMTP_PASSWORD")) {
+            (Ok(user), Ok(password)) => Some((user, password)),
+            (Err(_), Err(_)) => None,
+            _ => return Err(invalid("SMTP_USER and SMTP_PASSWORD must be set together")),
+        };
+        let mailbox = |address: &str| {
+            address
+                .trim()
+                .parse::<Mailbox>()
+                .map_err(|_| invalid(&format!("`{}` is not a valid email address", address)))
+        };
+        let from = match std::env::var("ALERT_EMAIL_FROM") {
+            Ok(d) => mailbox(&d)?,
+            Err(_) => return Err(invalid("ALERT_EMAIL_FROM is required with SMTP_HOST")),
+        };
+        let to = std::env::var("ALERT_EMAIL_TO")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|address| !address.trim().is_empty())
+            .map(mailbox)
+            .collect::<Result<Vec<Mailbox>, Error>>()?;
+        if to.is_empty() {
+            return Err(invalid("ALERT_EMAIL_TO is required with SMTP_HOST"));
         }
+        Ok(Some(SmtpSettings {
+            host,
+            port,
+            credentials,
+            from,
+            to,
+        }))
+    }
+
+    /// Bearer tokens from `API_TOKENS` (comma-separated) and
+    /// `API_TOKENS_FILE` (one token per line), each at least 16 characters
+    fn read_api_tokens() -> Result<Vec<String>, Error> {
+        let mut tokens: Vec<String> = Vec::new();
+        if let Ok(d) = std::env::var("API_TOKENS") {
+            tokens.extend(d.split(',').map(|t| t.trim().to_string()));
+        }
+        if let Ok(d) = std::env::var("API_TOKENS_FILE") {
+            let content = std::fs::read_to_string(&d)
+                .map_err(|_| invalid("failed to read API_TOKENS_FILE"))?;
+            tokens.extend(content.lines().map(|t| t.trim().to_string()));
+        }
+        tokens.retain(|t| !t.is_empty());
+        if tokens.iter().any(|t| t.len() < 16) {
+            return Err(invalid("API token must be at least 16 characters"));
+        }
+        Ok(tokens)
     }
 
     /// Characters should only be a-z | A-Z | 0-9 and period(.) and dash(-)
@@ -107,11 +533,536 @@ impl Env {
         }
     }
 
+    ///
+    /// `BIND_ADDRESS` must be an IPv4 or IPv6 address, not a host name
+    ///
+    fn check_bind_address(address: &str) -> Result<IpAddr, Error> {
+        address.parse().map_err(|_| {
+            Error::IllegalBindAddress(
+                String::from("'") + address + "' is not an IPv4 or IPv6 address.",
+            )
+        })
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    ///
+    /// `unix:<path>` if `UNIX_SOCKET` is set, otherwise `<bind address>:<port>`
+    ///
     pub fn address(&self) -> String {
-        format!("{}:{}", &self.domain, &self.port)
+        match &self.unix_socket {
+            Some(path) => format!("unix:{}", path.display()),
+            None => self.bind_address(),
+        }
+    }
+
+    ///
+    /// the socket address to listen on, like `0.0.0.0:54321` or `[::1]:54321`
+    ///
+    pub fn bind_address(&self) -> String {
+        SocketAddr::new(self.bind_address, self.port).to_string()
+    }
+
+    ///
+    /// the url of the web pages at `DOMAIN`, `https` if SSL is configured
+    ///
+    pub fn public_url(&self) -> String {
+        self.public_url_for(None)
+    }
+
+    ///
+    /// the url of the web pages at `DOMAIN` with the scheme forwarded by a trusted proxy,
+    /// which differs from ours when the proxy terminates TLS
+    ///
+    pub fn public_url_for(&self, forwarded_proto: Option<&str>) -> String {
+        let ssl = match forwarded_proto {
+            Some(proto) => proto == "https",
+            None => self.pk_dir.is_some() && self.crt_dir.is_some(),
+        };
+        public_url(&self.domain, self.port, ssl, &self.base_path)
+    }
+
+    ///
+    /// `true` if the server listens with SSL, which is not used with `UNIX_SOCKET`
+    ///
+    pub fn ssl_enabled(&self) -> bool {
+        self.unix_socket.is_none() && self.pk_dir.is_some() && self.crt_dir.is_some()
+    }
+
+    ///
+    /// create the parent folder of `UNIX_SOCKET`, and remove the socket left by a previous crash
+    ///
+    pub fn prepare_unix_socket(&self) -> Result<(), Error> {
+        if let Some(path) = &self.unix_socket {
+            if let Some(parent) = path.parent() {
+                utils::create_dir_check(parent)?;
+            }
+            utils::remove_stale_socket(path)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// set `UNIX_SOCKET_MODE` and owner of the bound socket
+    ///
+    pub fn secure_unix_socket(&self) -> Result<(), Error> {
+        if let Some(path) = &self.unix_socket {
+            utils::set_mode(path, self.unix_socket_mode)?;
+            if self.unix_socket_owner.is_some() || self.unix_socket_group.is_some() {
+                utils::chown_by_name(path, &self.unix_socket_owner, &self.unix_socket_group)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// the resolved value of each environment variable, with password and tokens masked
+    ///
+    pub fn effective_config(&self) -> Vec<(&'static str, String)> {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "(not set)".to_string());
+        let path =
+            |path: &Option<PathBuf>| optional(path.as_ref().map(|p| p.display().to_string()));
+        let smtp = self.smtp.as_ref();
+        vec![
+            ("TASKER_ROOT", self.tasker_root.display().to_string()),
+            ("USERNAME", self.user_name.clone()),
+            ("PASSWORD", MASKED.to_string()),
+            (
+                "API_TOKENS",
+                format!("{} ({} tokens)", MASKED, self.api_tokens.len()),
+            ),
+            ("DOMAIN", self.domain.clone()),
+            ("BIND_ADDRESS", self.bind_address.to_string()),
+            ("PORT", self.port.to_string()),
+            ("SSL_PRIVATE_KEY", path(&self.pk_dir)),
+            ("SSL_CERTIFICATE", path(&self.crt_dir)),
+            ("BASE_PATH", self.base_path.clone()),
+            ("MAX_LOG_MB", (self.max_log_bytes / 1024 / 1024).to_string()),
+            ("AUTOLOAD_ON_START", self.autoload_on_start.to_string()),
+            ("RATE_LIMIT_PER_MIN", self.rate_limit_per_min.to_string()),
+            (
+                "COMMAND_TIMEOUT_SECS",
+                self.command_timeout.as_secs().to_string(),
+            ),
+            (
+                "MAX_UNCOMPRESSED_MB",
+                (self.max_uncompressed_bytes / 1024 / 1024).to_string(),
+            ),
+            (
+                "TEMP_CLEANUP_INTERVAL_SECS",
+                self.temp_cleanup_interval.as_secs().to_string(),
+            ),
+            ("TEMP_MAX_AGE_SECS", self.temp_max_age.as_secs().to_string()),
+            (
+                "TRASH_RETENTION_DAYS",
+                self.trash_retention
+                    .map(|d| d.as_secs() / 24 / 60 / 60)
+                    .unwrap_or(0)
+                    .to_string(),
+            ),
+            (
+                "SHUTDOWN_TIMEOUT_SECS",
+                self.shutdown_timeout.as_secs().to_string(),
+            ),
+            ("UNIX_SOCKET", path(&self.unix_socket)),
+            ("UNIX_SOCKET_MODE", format!("{:o}", self.unix_socket_mode)),
+            (
+                "UNIX_SOCKET_OWNER",
+                optional(self.unix_socket_owner.clone()),
+            ),
+            (
+                "UNIX_SOCKET_GROUP",
+                optional(self.unix_socket_group.clone()),
+            ),
+            (
+                "TRUSTED_PROXIES",
+                self.trusted_proxies
+                    .iter()
+                    .map(|cidr| cidr.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            ("SLOW_REQUEST_MS", self.slow_request.as_millis().to_string()),
+            (
+                "LAUNCHCTL_CONCURRENCY",
+                self.launchctl_concurrency.to_string(),
+            ),
+            (
+                "LAUNCHCTL_MAX_PENDING",
+                self.launchctl_max_pending.to_string(),
+            ),
+            (
+                "LAUNCHCTL_LIST_CACHE_MS",
+                self.launchctl_list_cache.as_millis().to_string(),
+            ),
+            (
+                "MAX_CONCURRENT_EXPORTS",
+                self.max_concurrent_exports.to_string(),
+            ),
+            (
+                "MAX_CONCURRENT_UPLOADS",
+                self.max_concurrent_uploads.to_string(),
+            ),
+            ("VERBOSE_ERRORS", self.verbose_errors.to_string()),
+            ("BACKUP_DIR", self.backup_dir.display().to_string()),
+            (
+                "BACKUP_INTERVAL_HOURS",
+                self.backup_interval
+                    .map(|d| d.as_secs() / 60 / 60)
+                    .unwrap_or(0)
+                    .to_string(),
+            ),
+            ("BACKUP_KEEP", self.backup_keep.to_string()),
+            (
+                "PLIST_DIR",
+                path(&self.plist_dir.parent().map(Path::to_path_buf)),
+            ),
+            ("SMTP_HOST", optional(smtp.map(|s| s.host.clone()))),
+            ("SMTP_PORT", optional(smtp.map(|s| s.port.to_string()))),
+            (
+                "SMTP_USER",
+                optional(smtp.and_then(|s| s.credentials.as_ref().map(|(user, _)| user.clone()))),
+            ),
+            (
+                "SMTP_PASSWORD",
+                optional(
+                    smtp.and_then(|s| s.credentials.as_ref())
+                        .map(|_| MASKED.to_string()),
+                ),
+            ),
+            (
+                "ALERT_EMAIL_FROM",
+                optional(smtp.map(|s| s.from.to_string())),
+            ),
+            (
+                "ALERT_EMAIL_TO",
+                optional(smtp.map(|s| {
+                    s.to.iter()
+                        .map(|to| to.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                })),
+            ),
+        ]
+    }
+
+    ///
+    /// check write access to tasker folders and `plist_folders`
+    ///
+    fn check_folders(&self, plist_folders: &[&Path]) -> Vec<CheckResult> {
+        let tasker_folders = [
+            &self.tasker_root,
+            &self.meta_dir,
+            &self.task_dir,
+            &self.trash_dir,
+            &self.out_dir,
+        ];
+        tasker_folders
+            .iter()
+            .map(|folder| folder.as_path())
+            .chain(plist_folders.iter().cloned())
+            .map(|folder| {
+                CheckResult::new(
+                    &format!("write access to {}", folder.display()),
+                    check_writable(folder),
+                )
+            })
+            .collect()
     }
 
     pub fn get() -> &'static Env {
         return &ENVIRONMENT;
     }
 }
+
+///
+/// load the SSL private key and certificate chain for the server
+///
+pub fn ssl_acceptor(pk: &Path, crt: &Path) -> Result<SslAcceptorBuilder, Error> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+        .map_err(|e| Error::SslError(format!("{}", e)))?;
+    builder
+        .set_private_key_file(pk, SslFiletype::PEM)
+        .map_err(|e| Error::SslError(format!("private ssl key error: {}", e)))?;
+    builder
+        .set_certificate_chain_file(crt)
+        .map_err(|e| Error::SslError(format!("ssl crt file error: {}", e)))?;
+    builder
+        .check_private_key()
+        .map_err(|e| Error::SslError(format!("ssl key does not match crt: {}", e)))?;
+    Ok(builder)
+}
+
+///
+/// the subject and expiry of an SSL certificate
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateInfo {
+    /// like `CN=tasker.example.com, O=Example`
+    pub subject: String,
+    /// `notAfter` in seconds since epoch
+    pub not_after: i64,
+}
+
+impl CertificateInfo {
+    ///
+    /// `notAfter` as RFC3339 in UTC
+    ///
+    pub fn expires_at(&self) -> Option<String> {
+        if self.not_after < 0 {
+            return None;
+        }
+        utils::format_rfc3339(UNIX_EPOCH + Duration::from_secs(self.not_after as u64))
+    }
+
+    ///
+    /// whole days until expiry at `now` (seconds since epoch), negative once expired
+    ///
+    pub fn days_remaining(&self, now: i64) -> i64 {
+        (self.not_after - now).div_euclid(SECS_PER_DAY)
+    }
+
+    ///
+    /// `days_remaining` at the current time
+    ///
+    pub fn days_remaining_now(&self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.days_remaining(now)
+    }
+}
+
+///
+/// read the subject and expiry of the first (leaf) certificate in the PEM file `crt`
+///
+pub fn read_certificate(crt: &Path) -> Result<CertificateInfo, Error> {
+    let pem =
+        std::fs::read(crt).map_err(|e| Error::SslError(format!("ssl crt file error: {}", e)))?;
+    let certificate =
+        X509::from_pem(&pem).map_err(|e| Error::SslError(format!("ssl crt file error: {}", e)))?;
+    let subject = certificate
+        .subject_name()
+        .entries()
+        .map(|entry| {
+            let name = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{}={}", name, value)
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    let epoch = Asn1Time::from_unix(0).map_err(|e| Error::SslError(format!("{}", e)))?;
+    let diff = epoch
+        .diff(certificate.not_after())
+        .map_err(|e| Error::SslError(format!("ssl crt expiry error: {}", e)))?;
+    Ok(CertificateInfo {
+        subject,
+        not_after: diff.days as i64 * SECS_PER_DAY + diff.secs as i64,
+    })
+}
+
+///
+/// create and remove a file in `folder`
+///
+fn check_writable(folder: &Path) -> Result<String, Error> {
+    let file = folder.join(WRITE_CHECK_FILE);
+    match std::fs::write(&file, b"") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&file);
+            Ok("writable".to_string())
+        }
+        Err(e) => Err(invalid(&format!(
+            "`{}` is not writable: {}",
+            folder.display(),
+            e
+        ))),
+    }
+}
+
+///
+/// the outcome of a check of `self_check`
+///
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &str, result: Result<String, Error>) -> CheckResult {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => (false, format!("{:?}", e)),
+        };
+        CheckResult {
+            name: name.to_string(),
+            passed,
+            detail,
+        }
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed { " OK " } else { "FAIL" };
+        write!(f, "[{}] {}: {}", status, self.name, self.detail)
+    }
+}
+
+///
+/// check the environment variables, write access to tasker and plist folders,
+/// the SSL key and certificate, and `launchctl list`, without starting the server.
+/// The other checks are skipped if the environment is invalid.
+///
+pub fn self_check() -> Vec<CheckResult> {
+    let env = match Env::try_init() {
+        Ok(env) => env,
+        Err(e) => return vec![CheckResult::new("environment", Err(e))],
+    };
+    let mut results = vec![CheckResult::new(
+        "environment",
+        Ok(format!("listening on {}", env.address())),
+    )];
+    results.extend(env.check_folders(&[env.plist_dir.as_path(), env.agent_plist_dir.as_path()]));
+    match (&env.pk_dir, &env.crt_dir) {
+        (Some(pk), Some(crt)) => results.push(CheckResult::new(
+            "ssl",
+            ssl_acceptor(pk, crt)
+                .and_then(|_| read_certificate(crt))
+                .map(|certificate| {
+                    format!(
+                        "key and certificate loaded, {} days until expiry",
+                        certificate.days_remaining_now()
+                    )
+                }),
+        )),
+        (None, None) => {}
+        _ => results.push(CheckResult::new(
+            "ssl",
+            Err(Error::SslError(
+                "SSL_PRIVATE_KEY and SSL_CERTIFICATE must be set together".to_string(),
+            )),
+        )),
+    }
+    let list = launchctl_backend::backend().run(&["list".to_string()]);
+    results.push(CheckResult::new(
+        "launchctl list",
+        list.map(|output| format!("{} services", output.lines().count().saturating_sub(1))),
+    ));
+    results
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidEnvironment(message.to_string())
+}
+
+fn public_url(domain: &str, port: u16, ssl: bool, base_path: &str) -> String {
+    let scheme = if ssl { "https" } else { "http" };
+    format!("{}://{}:{}{}", scheme, domain, port, base_path)
+}
+
+#[cfg(test)]
+mod test_initialize_mod {
+    use super::*;
+
+    #[test]
+    fn bind_address() {
+        assert_eq!(
+            Env::check_bind_address("0.0.0.0").unwrap(),
+            IpAddr::from([0, 0, 0, 0])
+        );
+        let ipv6 = Env::check_bind_address("::1").unwrap();
+        assert_eq!(SocketAddr::new(ipv6, 54321).to_string(), "[::1]:54321");
+        assert!(Env::check_bind_address("tasker.example.com").is_err());
+        assert!(Env::check_bind_address("localhost").is_err());
+        assert!(Env::check_bind_address("127.0.0.1:54321").is_err());
+    }
+
+    #[test]
+    fn writable_and_ssl_checks() {
+        let folder = Path::new("test_writable_check");
+        utils::create_dir_check(folder).unwrap();
+        assert!(check_writable(folder).is_ok());
+        assert!(!folder.join(WRITE_CHECK_FILE).exists());
+        std::fs::remove_dir(folder).unwrap();
+        assert!(check_writable(folder).is_err());
+
+        let not_pem = Path::new("test_not_pem.txt");
+        std::fs::write(not_pem, "not a pem file").unwrap();
+        assert!(ssl_acceptor(not_pem, not_pem).is_err());
+        std::fs::remove_file(not_pem).unwrap();
+
+        let failed = CheckResult::new("ssl", Err(Error::SslError("bad key".to_string())));
+        assert_eq!(failed.to_string(), "[FAIL] ssl: SslError(\"bad key\")");
+    }
+
+    /// a self-signed certificate for `subject` valid from `not_before` to `not_after`
+    fn self_signed_pem(subject: &str, not_before: i64, not_after: i64) -> Vec<u8> {
+        use openssl::pkey::PKey;
+        use openssl::rsa::Rsa;
+        use openssl::x509::X509NameBuilder;
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", subject).unwrap();
+        name.append_entry_by_text("O", "tasker").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::from_unix(not_before).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::from_unix(not_after).unwrap())
+            .unwrap();
+        builder
+            .sign(&key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+        builder.build().to_pem().unwrap()
+    }
+
+    #[test]
+    fn certificate_expiry() {
+        // 2020-09-13T12:26:40Z
+        let now = 1_600_000_000;
+        let crt = Path::new("test_certificate_expiry.crt");
+        std::fs::write(
+            crt,
+            self_signed_pem("tasker.example.com", now, now + 45 * SECS_PER_DAY),
+        )
+        .unwrap();
+        let certificate = read_certificate(crt).unwrap();
+        std::fs::remove_file(crt).unwrap();
+        assert_eq!(certificate.subject, "CN=tasker.example.com, O=tasker");
+        assert_eq!(certificate.not_after, now + 45 * SECS_PER_DAY);
+        assert_eq!(certificate.expires_at().unwrap(), "2020-10-28T12:26:40Z");
+        assert_eq!(certificate.days_remaining(now), 45);
+        assert_eq!(certificate.days_remaining(now + 1), 44);
+        assert_eq!(certificate.days_remaining(now + 46 * SECS_PER_DAY), -1);
+        assert!(certificate.days_remaining_now() < 0);
+
+        let not_pem = Path::new("test_certificate_not_pem.crt");
+        std::fs::write(not_pem, "not a pem file").unwrap();
+        assert!(read_certificate(not_pem).is_err());
+        std::fs::remove_file(not_pem).unwrap();
+        assert!(read_certificate(not_pem).is_err());
+    }
+
+    #[test]
+    fn public_url_scheme() {
+        assert_eq!(
+            public_url("tasker.example.com", 54321, false, ""),
+            "http://tasker.example.com:54321"
+        );
+        assert_eq!(
+            public_url("tasker.example.com", 443, true, "/tasker"),
+            "https://tasker.example.com:443/tasker"
+        );
+    }
+}
@@ -1,29 +1,159 @@
-use crate::config::Config::{ProgramArguments, RootDirectory, WorkingDirectory};
-use crate::config::{Config, Configuration};
+use crate::cleanup::{entry_stats, trashed_at, DeletionMarker, InProgress};
+use crate::command_queue::{CommandQueue, QueueStats};
+use crate::config::Config::{
+    EnvironmentVariables, KeepAlive, ProgramArguments, RootDirectory, RunAtLoad, StandardErrorPath,
+    StandardInPath, StandardOutPath, WorkingDirectory,
+};
+use crate::config::{
+    check_label_str, check_program_str, normalize_yaml_input, user_in_group,
+    validate_pattern_param, CalendarInterval, CalendarTime, Config, ConfigChange, Configuration,
+    Schedule, Scope, Validation, CONFIG_NAMES, SECRET_MASK,
+};
 use crate::error::Error;
+use crate::git::GitCheckout;
+use crate::history::{history_file, last_run, HISTORY_EXTENSION};
 use crate::initialize::Env;
+use crate::launchctl_backend;
+use crate::manual_run::ManualRun;
+use crate::meta_index::MetaIndex;
+use crate::provenance::{
+    provenance_file, read_provenance, record_edit, record_git_refresh, write_provenance, GitOrigin,
+    Provenance, PROVENANCE_EXTENSION,
+};
+use crate::settings_snapshot::{settings_changes, settings_migrated};
+use crate::shutdown::Operation;
+use crate::stats::task_size;
+use crate::supervisor::run_elapsed_seconds;
 use crate::utils::{
-    chown_by_name_recursive, copy_folder, create_dir_check, decompress, delete_file_check,
-    execute_command, move_by_rename, read_last_n_lines, read_utf8_file, try_to_remove_folder,
-    zip_dir,
+    canonicalize_lossy, chown_by_id, chown_by_name, chown_by_name_recursive,
+    chown_by_name_recursive_lossy, civil_from_days, copy_folder, create_dir_check,
+    delete_file_check, execute_command_timeout, extract_archive, file_sha256, format_rfc3339,
+    is_junk_path, is_log_file, local_day_and_minute, local_secs, move_by_rename, parse_rfc3339,
+    read_last_n_lines_multi, read_lines_in_time_range_multi, read_utf8_file, render,
+    resolve_inside, rotate_file, rotated_file_name, run_as_ids, set_mtime, sha256_hex, shell_quote,
+    try_to_remove_folder, unified_diff, write_atomic, zip_dir, zip_dir_filtered, ChownWarning,
 };
 use crate::{
-    PLIST_FOLDER, STD_ERR_FILE, STD_OUT_FILE, TASKER_TASK_NAME, TASK_ROOT_ALIAS, TEMP_UNZIP_FOLDER,
-    TEMP_ZIP_FOLDER, TEMP_ZIP_PATH,
+    DESIRED_STATE_FILE, LOG_KEEP_COUNT, PLIST_CHECKSUM_FILE, STD_ERR_FILE, STD_OUT_FILE,
+    TASKER_TASK_NAME, TASK_OUT_ALIAS, TASK_ROOT_ALIAS, TEMP_UNZIP_FOLDER, TEMP_ZIP_FOLDER,
+    TEMP_ZIP_PATH,
 };
-use regex::Regex;
-use serde::Serialize;
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
 use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static MAX_TASK_SIZE_ENTRIES: usize = 10000;
+static MAX_USAGE_PROCESSES: usize = 100;
+static MAX_YAML_DEPTH: usize = 3;
+static SECRETS_EXTENSION: &str = ".secrets";
+/// attempts of `patch_config` when the yaml keeps changing between reading and writing it
+static PATCH_ATTEMPTS: usize = 3;
+/// numbers the folders of zips extracted by `update_task_files`, which may run at once
+static UPDATE_UNZIP_COUNT: AtomicUsize = AtomicUsize::new(0);
+static LAUNCHCTL_RETRY_ATTEMPTS: usize = 4;
+static LAUNCHCTL_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// launchctl errors of a job that launchd has not finished tearing down
+static TRANSIENT_LAUNCHCTL_ERRORS: [&str; 3] = [
+    "Operation already in progress",
+    "Input/output error",
+    "Resource temporarily unavailable",
+];
+/// output of `run_once` in the output folder of a task
+static MANUAL_RUN_FOLDER: &str = "manual";
+/// launchd runs jobs with this `PATH` unless `EnvironmentVariables` sets it
+static LAUNCHD_DEFAULT_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+/// launchd refuses plists that are group or world writable
+static PLIST_MODE: u32 = 0o644;
+/// days searched for the next calendar run, enough for `Month: 2` and `Day: 29`
+static NEXT_RUN_SEARCH_DAYS: i64 = 8 * 366;
+/// the longest glob or regex label pattern
+static MAX_LABEL_PATTERN_LEN: usize = 256;
+/// the compiled size limit of a regex label pattern, in bytes
+static LABEL_PATTERN_SIZE_LIMIT: usize = 64 * 1024;
+static LABEL_PATTERN_NEST_LIMIT: u32 = 16;
+/// the label column of `/list_text` is not shortened below this, however narrow the width
+static MIN_TEXT_LABEL_WIDTH: usize = 12;
+static TEXT_COLUMN_GAP: &str = "  ";
+/// the signals `/stop` and `/restart` may send, by name without `SIG`
+static STOP_SIGNALS: [(&str, libc::c_int); 6] = [
+    ("TERM", libc::SIGTERM),
+    ("INT", libc::SIGINT),
+    ("HUP", libc::SIGHUP),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+];
+static DEFAULT_STOP_SIGNAL: &str = "TERM";
+/// how often `restart_task` checks whether the stopped process is gone
+static RESTART_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// explanations of launchctl errors by a pattern of its stderr, the first match is used
+static LAUNCHCTL_ERROR_HINTS: [(&str, &str); 7] = [
+    (
+        "bad ownership/permissions",
+        "the plist of `{{label}}` must be owned by root:wheel with mode 644; \
+         tasker sets them whenever it places the plist, so load the task again",
+    ),
+    (
+        "Invalid property list",
+        "launchd cannot parse the plist of `{{label}}`; check the yaml for a configuration \
+         of the wrong type (e.g. text where a number is expected) with /get_plist",
+    ),
+    (
+        "already loaded",
+        "`{{label}}` is already loaded in launchd; unload it before loading it again",
+    ),
+    (
+        "Service is disabled",
+        "`{{label}}` is disabled in launchd; run `launchctl enable system/{{label}}` \
+         (or `gui/<uid>/{{label}}` for agents) and load the task again",
+    ),
+    (
+        "Operation not permitted",
+        "launchd did not permit loading `{{label}}`; tasker must run as root",
+    ),
+    (
+        "No such file or directory",
+        "the plist of `{{label}}` is missing; load the task again to place it",
+    ),
+    (
+        ": 5: ",
+        "launchd refused `{{label}}`; usually its `UserName` or `GroupName` does not exist, \
+         its `Program` is not executable, or it is disabled. Check /command and the log of \
+         launchd (`log show --last 5m --predicate 'subsystem == \"com.apple.xpc.launchd\"'`)",
+    ),
+];
 
 lazy_static! {
     static ref LABEL_REGEX: Regex = Regex::new("^(.+)\\.yaml$").unwrap();
+    static ref META_INDEX: Mutex<MetaIndex<YamlMeta>> =
+        Mutex::new(MetaIndex::load(&Env::get().meta_dir));
+    static ref LABEL_CACHE: Mutex<LabelCache> = Mutex::new(LabelCache::default());
+    static ref LIST_CACHE: Mutex<ListCache> = Mutex::new(ListCache::default());
+    static ref DESIRED_STATE_LOCK: Mutex<()> = Mutex::new(());
+    static ref PLIST_CHECKSUM_LOCK: Mutex<()> = Mutex::new(());
+    static ref YAML_LOCKS: Mutex<BTreeMap<String, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+    static ref LAUNCHCTL_QUEUE: CommandQueue = CommandQueue::new(
+        Env::get().launchctl_concurrency,
+        Env::get().launchctl_max_pending
+    );
+    static ref SECRET_KEY_REGEX: Regex = Regex::new("^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    static ref SHELL_VARIABLE_REGEX: Regex =
+        Regex::new("\\$(\\{[A-Za-z_][A-Za-z0-9_]*\\}|[A-Za-z_][A-Za-z0-9_]*)").unwrap();
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Display, PartialEq, Clone, Copy)]
 pub enum Status {
     RUNNING,
     LOADED,
@@ -32,668 +162,5047 @@ pub enum Status {
     ERROR,
 }
 
-#[derive(Debug, Serialize)]
-pub struct TaskInfo {
-    pid: Option<i32>,
-    last_exit_status: Option<i32>,
-    label: String,
-    status: Status,
-}
-
-fn get_plist_path(label_name: &str) -> PathBuf {
-    Path::new(PLIST_FOLDER).join(String::from(label_name) + ".plist")
-}
+static ALL_STATUS: [Status; 5] = [
+    Status::RUNNING,
+    Status::LOADED,
+    Status::UNLOADED,
+    Status::NORMAL,
+    Status::ERROR,
+];
 
-fn get_task_folder_name(label_name: &str) -> PathBuf {
-    Env::get().task_dir.join(label_name)
-}
+impl FromStr for Status {
+    type Err = Error;
 
-fn get_output_folder_name(label_name: &str) -> PathBuf {
-    Env::get().out_dir.join(label_name)
+    ///
+    /// parse status case-insensitively
+    ///
+    fn from_str(s: &str) -> Result<Status, Error> {
+        for status in &ALL_STATUS {
+            if status.to_string().eq_ignore_ascii_case(s.trim()) {
+                return Ok(*status);
+            }
+        }
+        Err(Error::UnknownStatus(format!(
+            "unknown status `{}`, valid values are: {}",
+            s,
+            ALL_STATUS
+                .iter()
+                .map(|status| status.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )))
+    }
 }
 
-fn get_trash_folder_name(label_name: &str) -> PathBuf {
-    Env::get().trash_dir.join(label_name)
+///
+/// parse comma-separated statuses, e.g. `ERROR,UNLOADED`
+///
+pub fn parse_statuses(statuses: &str) -> Result<Vec<Status>, Error> {
+    statuses
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(Status::from_str)
+        .collect()
 }
 
 ///
-/// `load_task` takes the following steps:
-/// - read yaml from meta folder
-/// - process yaml
-/// - clear, create, and chown output folder
-/// - place plist in LaunchDaemons folder and load task
+/// how a label pattern (e.g. `label` of `/list_raw_json`) matches task labels
 ///
-pub fn load_task(task_label: &str) -> Result<(), Error> {
-    let yaml = view_yaml(task_label)?;
-    let config = process_config(Configuration::from_yaml(&yaml)?)?;
-    place_plist_and_load(&config)
+#[derive(Debug, Default, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// labels containing the pattern
+    #[default]
+    Substring,
+    /// the whole label, where `*` is any characters and `?` is one character
+    Glob,
+    /// labels with a match of the regex, which may be anchored by `^` and `$`
+    Regex,
 }
 
 ///
-/// execute launchctl load command, return error if already loaded
+/// a label pattern compiled once per request, used by every source of the task list.
+/// It matches the label without the `com.tasker.tasks.` prefix.
+/// A substring or glob pattern may still start with the prefix: it is then removed,
+/// and a substring pattern only matches at the start of the label, as it would match
+/// the full label.
 ///
-fn load_inner(task_label: &str) -> Result<(), Error> {
-    if is_loaded(task_label)? {
-        return Err(Error::FailedToLoadTask(
-            "task is already loaded".to_string(),
-        ));
-    }
-    if !exist(task_label)? {
-        return Err(Error::TaskDoesNotExist("no such task to load".to_string()));
+#[derive(Debug, Clone)]
+pub enum LabelMatcher {
+    Substring { pattern: String, anchored: bool },
+    Glob(Regex),
+    Regex(Regex),
+}
+
+impl LabelMatcher {
+    ///
+    /// compile `pattern`, `InvalidLabelPattern` if it is too long or not a valid regex.
+    /// Substring and glob patterns must be path-safe.
+    ///
+    pub fn new(pattern: &str, mode: MatchMode) -> Result<LabelMatcher, Error> {
+        if mode == MatchMode::Substring {
+            validate_pattern_param(pattern)?;
+            return Ok(LabelMatcher::substring(pattern));
+        }
+        if pattern.len() > MAX_LABEL_PATTERN_LEN {
+            return Err(Error::InvalidLabelPattern(format!(
+                "label pattern is longer than {} bytes",
+                MAX_LABEL_PATTERN_LEN
+            )));
+        }
+        if mode == MatchMode::Glob {
+            validate_pattern_param(pattern)?;
+            let glob = strip_task_domain(pattern).unwrap_or(pattern);
+            return Ok(LabelMatcher::Glob(compile_label_regex(&glob_to_regex(
+                glob,
+            ))?));
+        }
+        Ok(LabelMatcher::Regex(compile_label_regex(pattern)?))
     }
-    match execute_command(Command::new("launchctl").args(&[
-        "load",
-        get_plist_path(task_label).to_str().unwrap_or_default(),
-    ])) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
+
+    ///
+    /// labels containing `pattern`, which never fails
+    ///
+    pub fn substring(pattern: &str) -> LabelMatcher {
+        match strip_task_domain(pattern) {
+            Some(rest) => LabelMatcher::Substring {
+                pattern: rest.to_string(),
+                anchored: true,
+            },
+            None => LabelMatcher::Substring {
+                pattern: pattern.to_string(),
+                anchored: false,
+            },
+        }
     }
-}
 
-///
-/// always try to delete plist
-///
-pub fn unload_task(task_label: &str) -> Result<(), Error> {
-    let is_loaded = is_loaded(task_label)?;
-    if is_loaded {
-        unload_inner(task_label)?;
+    ///
+    /// every task
+    ///
+    pub fn all() -> LabelMatcher {
+        LabelMatcher::substring("")
     }
-    try_remove_plist(task_label);
-    if !is_loaded {
-        return Err(Error::FailedToUnloadTask(
-            "task is already unloaded or does not exist".to_string(),
-        ));
+
+    pub fn matches(&self, label: &str) -> bool {
+        let short_label = strip_task_domain(label).unwrap_or(label);
+        match self {
+            LabelMatcher::Substring {
+                pattern,
+                anchored: true,
+            } => short_label.starts_with(pattern.as_str()),
+            LabelMatcher::Substring { pattern, .. } => short_label.contains(pattern.as_str()),
+            LabelMatcher::Glob(regex) | LabelMatcher::Regex(regex) => regex.is_match(short_label),
+        }
     }
-    Ok(())
 }
 
 ///
-/// execute launchctl unload command, return error if already unloaded
+/// `label` without the leading `com.tasker.tasks.`, `None` if it has no such prefix
 ///
-fn unload_inner(task_label: &str) -> Result<(), Error> {
-    match execute_command(Command::new("launchctl").args(&[
-        "unload",
-        get_plist_path(task_label).to_str().unwrap_or_default(),
-    ])) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
+fn strip_task_domain(label: &str) -> Option<&str> {
+    let rest = label.strip_prefix(TASKER_TASK_NAME)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('.')
     }
 }
 
 ///
-/// ignore most failure in this function so as not to be interrupted
-/// during deletion.
+/// an anchored regex of a glob, where `*` is any characters and `?` is one character
 ///
-pub fn delete_task(task_label: &str) -> Result<(), Error> {
-    match unload_task(task_label) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
-
-    // move 'task' folder to trash
-    match move_by_rename(
-        get_task_folder_name(task_label).as_path(),
-        get_trash_folder_name(task_label).as_path(),
-    ) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
-
-    // move yaml to trash
-    let yaml_in_meta = Env::get().meta_dir.join(String::from(task_label) + ".yaml");
-    if let Some(file_name) = yaml_in_meta.file_name() {
-        match std::fs::copy(
-            &yaml_in_meta,
-            get_trash_folder_name(task_label).join(file_name),
-        ) {
-            Ok(_) => {}
-            Err(_) => {}
+fn glob_to_regex(glob: &str) -> String {
+    let mut expression = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => expression.push_str(".*"),
+            '?' => expression.push('.'),
+            c => expression.push_str(&regex::escape(&c.to_string())),
         }
     }
-    match std::fs::remove_file(&yaml_in_meta) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
+    expression.push('$');
+    expression
+}
 
-    // move 'out' folder to trash
-    try_clear_output(task_label);
-    Ok(())
+fn compile_label_regex(pattern: &str) -> Result<Regex, Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(LABEL_PATTERN_SIZE_LIMIT)
+        .dfa_size_limit(LABEL_PATTERN_SIZE_LIMIT)
+        .nest_limit(LABEL_PATTERN_NEST_LIMIT)
+        .build()
+        .map_err(|e| Error::InvalidLabelPattern(format!("invalid label pattern: {}", e)))
 }
 
-fn try_remove_plist(task_label: &str) {
-    match delete_file_check(get_plist_path(task_label)) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
+#[derive(Debug, Serialize, JsonSchema, Clone)]
+pub struct TaskInfo {
+    pid: Option<i32>,
+    last_exit_status: Option<i32>,
+    label: String,
+    status: Status,
+    stdout_bytes: Option<u64>,
+    stderr_bytes: Option<u64>,
+    created_at: Option<String>,
+    /// who created the task, if its provenance was recorded
+    created_by: Option<String>,
+    yaml_modified_at: Option<String>,
+    task_size_bytes: Option<u64>,
+    should_be_loaded: Option<bool>,
+    is_template: bool,
+    scope: Scope,
+    plist_tampered: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_run_seconds: Option<u64>,
+    /// how long the current run has lasted, for tasks with `max_run_seconds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_elapsed_seconds: Option<u64>,
+    /// the most recent completed run in the run history
+    last_run_duration_secs: Option<u64>,
+    last_run_started_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr_tail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<ResourceUsage>,
+    /// the program from the yaml of the task
+    program: Option<String>,
+    /// when the task runs by its yaml, like `daily at 09:15, 13:00`
+    schedule_summary: Option<String>,
+    run_at_load: Option<bool>,
+    /// why the yaml of the task cannot be read, while the rest of the list is fine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// the next scheduled run, with `include_next_run`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_run_at: Option<String>,
 }
 
-fn try_clear_output(task_label: &str) {
-    match move_by_rename(
-        get_output_folder_name(task_label).as_path(),
-        get_trash_folder_name(task_label).join("out").as_path(),
-    ) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
+///
+/// resource usage of a running task, including its child processes
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone)]
+pub struct ResourceUsage {
+    rss_kb: u64,
+    cpu_percent: f64,
+    elapsed: String,
+    process_count: usize,
 }
 
 ///
-/// create a new task based on a zip package
+/// execute a command with `COMMAND_TIMEOUT_SECS` timeout
 ///
-pub fn create_task(task_zip: &Path) -> Result<(), Error> {
-    let unzip_folder = Path::new(TEMP_UNZIP_FOLDER);
-    try_to_remove_folder(unzip_folder)?;
-    decompress(&task_zip, Path::new(TEMP_UNZIP_FOLDER))?;
-    let yaml = find_yaml_file(&unzip_folder)?;
+fn run_command(command: &mut Command) -> Result<String, Error> {
+    execute_command_timeout(command, Env::get().command_timeout)
+}
 
-    return if let Ok(yaml_content) = read_utf8_file(&yaml) {
-        let mut config = Configuration::from_yaml(&yaml_content)?;
-        let label = &config.label.clone();
+///
+/// execute launchctl through `LAUNCHCTL_QUEUE`, so that bulk operations do not
+/// overwhelm launchd. Fails with `Error::LaunchctlBusy` if too many are waiting.
+///
+fn launchctl<S: AsRef<OsStr>>(args: &[S]) -> Result<String, Error> {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| arg.as_ref().to_string_lossy().to_string())
+        .collect();
+    LAUNCHCTL_QUEUE.run(|| launchctl_backend::backend().run(&args))
+}
 
-        // process configuration: view `process_config` documentation for detail
-        config = process_config(config)?;
+///
+/// the launchctl invocations running and waiting, for `/health` and `/metrics`
+///
+pub fn launchctl_queue_stats() -> QueueStats {
+    LAUNCHCTL_QUEUE.stats()
+}
 
-        // move yaml to meta folder
-        move_yaml_to_meta(&yaml, label)?;
+///
+/// run a launchctl command, retrying with exponential backoff
+/// (`LAUNCHCTL_RETRY_BASE_DELAY`, then doubled) while it fails transiently
+///
+fn run_launchctl(args: &[String]) -> Result<String, Error> {
+    retry_transient(
+        LAUNCHCTL_RETRY_ATTEMPTS,
+        LAUNCHCTL_RETRY_BASE_DELAY,
+        |attempt| {
+            if attempt > 1 {
+                eprintln!(
+                    "warning: retrying `launchctl {}` (attempt {}/{})",
+                    args.join(" "),
+                    attempt,
+                    LAUNCHCTL_RETRY_ATTEMPTS
+                );
+            }
+            launchctl(args)
+        },
+    )
+}
 
-        // move the files to task folder
-        let task_folder_name = get_task_folder_name(label);
-        create_dir_check(&task_folder_name)?;
-        move_by_rename(&unzip_folder, task_folder_name.as_path())?;
-        chown_by_name_recursive(
-            task_folder_name.as_path(),
-            &config.get_user_name(),
-            &config.get_group_name(),
-        )?;
+///
+/// whether launchctl failed only because launchd is still busy with the job
+///
+fn is_transient_failure(e: &Error) -> bool {
+    match e {
+        Error::CommandExecutionError(message) => TRANSIENT_LAUNCHCTL_ERRORS
+            .iter()
+            .any(|pattern| message.contains(pattern)),
+        _ => false,
+    }
+}
 
-        // place plist and load task
-        place_plist_and_load(&config)
-    } else {
-        Err(Error::YamlError(
-            "error reading yaml as utf8 text".to_string(),
-        ))
-    };
+///
+/// an actionable explanation of launchctl's `stderr` when loading task `label`,
+/// followed by the original message. Unknown errors are returned as they are.
+///
+pub fn interpret_launchctl_error(stderr: &str, label: &str) -> String {
+    let stderr = stderr.trim();
+    match LAUNCHCTL_ERROR_HINTS
+        .iter()
+        .find(|(pattern, _)| stderr.contains(pattern))
+    {
+        Some((_, hint)) => format!("{} ({})", render(hint, &[("label", label)]), stderr),
+        None => stderr.to_string(),
+    }
 }
 
 ///
-/// find the position of yaml in zip package
+/// call `f` with the attempt number (starting from 1) at most `attempts` times,
+/// sleeping `base_delay`, `2 * base_delay`, ... between transient failures.
+/// Other errors are returned immediately.
 ///
-fn find_yaml_file(unzipped_folder: &Path) -> Result<PathBuf, Error> {
-    return if let Ok(path) = unzipped_folder.read_dir() {
-        // loop over entries
-        for entry in path {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if let Some(ext) = path.extension() {
-                    if ext.eq("yaml") {
-                        return Ok(path);
-                    }
-                }
+fn retry_transient<T, F: FnMut(usize) -> Result<T, Error>>(
+    attempts: usize,
+    base_delay: Duration,
+    mut f: F,
+) -> Result<T, Error> {
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    loop {
+        match f(attempt) {
+            Err(e) if attempt < attempts && is_transient_failure(&e) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
             }
+            result => return result,
         }
-        Err(Error::YamlNotFound("yaml not found".to_owned()))
-    } else {
-        Err(Error::YamlNotFound(
-            "cannot read unzipped folder".to_owned(),
-        ))
-    };
+    }
 }
 
 ///
-/// update yaml after editing yaml
+/// the launchd domain that a task is loaded into
 ///
-pub fn update_yaml(yaml_content: &str, this_label: &str) -> Result<(), Error> {
-    let mut config = Configuration::from_yaml(&yaml_content)?;
-    let label = &config.label.clone();
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Domain {
+    /// daemons in `Env::plist_dir`, loaded into the system domain
+    System,
+    /// agents in `Env::agent_plist_dir`, loaded into the GUI session of a user
+    Gui(u32),
+}
 
-    if !label.eq(this_label) {
-        return Err(Error::WrongLabelInYaml(format!(
-            "label `{}` must be `{}`",
-            label, this_label
-        )));
+impl Domain {
+    fn of(scope: Scope, user_name: Option<&str>) -> Result<Domain, Error> {
+        match (scope, user_name) {
+            (Scope::Daemon, _) => Ok(Domain::System),
+            (Scope::Agent, Some(user_name)) => match users::get_user_by_name(user_name) {
+                Some(user) => Ok(Domain::Gui(user.uid())),
+                None => Err(Error::IllegalUserName(format!(
+                    "user `{}` of agent does not exist",
+                    user_name
+                ))),
+            },
+            (Scope::Agent, None) => Err(Error::ScopeError(
+                "`Scope: agent` requires `UserName`".to_string(),
+            )),
+        }
     }
 
-    if !exist(label)? {
-        return Err(Error::TaskDoesNotExist(format!(
-            "task with label `{}` does not exist",
-            label
-        )));
+    fn plist_folder(self) -> &'static Path {
+        match self {
+            Domain::System => &Env::get().plist_dir,
+            Domain::Gui(_) => &Env::get().agent_plist_dir,
+        }
     }
 
-    let is_loaded = is_loaded(label)?;
+    ///
+    /// the launchctl service target, e.g. `system/<label>` or `gui/501/<label>`
+    ///
+    fn service_target(self, label: &str) -> String {
+        match self {
+            Domain::System => format!("system/{}", label),
+            Domain::Gui(uid) => format!("gui/{}/{}", uid, label),
+        }
+    }
 
-    if is_loaded {
-        unload_inner(label)?;
+    ///
+    /// `launchctl load <plist>`, or `launchctl bootstrap gui/<uid> <plist>` for agents
+    ///
+    fn load_args(self, plist: &Path) -> Vec<String> {
+        let plist = plist.to_string_lossy().to_string();
+        match self {
+            Domain::System => vec!["load".to_string(), plist],
+            Domain::Gui(uid) => vec!["bootstrap".to_string(), format!("gui/{}", uid), plist],
+        }
     }
 
-    // process configuration: view `process_config` documentation for detail
-    config = process_config(config)?;
+    ///
+    /// `launchctl unload <plist>`, or `launchctl bootout gui/<uid> <plist>` for agents
+    ///
+    fn unload_args(self, plist: &Path) -> Vec<String> {
+        let plist = plist.to_string_lossy().to_string();
+        match self {
+            Domain::System => vec!["unload".to_string(), plist],
+            Domain::Gui(uid) => vec!["bootout".to_string(), format!("gui/{}", uid), plist],
+        }
+    }
+}
 
-    // move yaml in meta folder
-    update_yaml_in_meta(yaml_content, label)?;
+///
+/// settings of a task read from its yaml, which decide how tasker handles the task
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct YamlMeta {
+    is_template: bool,
+    scope: Scope,
+    user_name: Option<String>,
+    max_run_seconds: Option<u64>,
+    schedule: Schedule,
+    program: Option<String>,
+    schedule_summary: Option<String>,
+    /// the yaml exists but cannot be read or parsed
+    error: Option<String>,
+    /// the yaml as parsed, without checks, for `lint_all`.
+    /// It is not kept in the index, so it is only set right after parsing.
+    #[serde(skip)]
+    config: Option<Arc<Configuration>>,
+}
 
-    // place plist and load task
-    if is_loaded {
-        place_plist_and_load(&config)?
-    }
+///
+/// the `YamlMeta` of a task from its yaml in meta folder, kept in the meta index until
+/// the yaml changes. Tasks without yaml are daemons without limits,
+/// and so are tasks with a corrupt yaml, which carry the `error`.
+///
+fn yaml_meta(label: &str) -> YamlMeta {
+    META_INDEX
+        .lock()
+        .unwrap()
+        .get(label, parse_yaml_meta)
+        .unwrap_or_default()
+}
 
-    Ok(())
+fn parse_yaml_meta(yaml: Result<&str, Error>) -> YamlMeta {
+    let config = match yaml {
+        Ok(yaml) => Configuration::parse_yaml(yaml).map_err(|e| (e, yaml_is_template(yaml))),
+        Err(e) => Err((e, false)),
+    };
+    match config {
+        Ok(config) => YamlMeta {
+            is_template: config.is_template(),
+            scope: config.scope(),
+            user_name: config.user_name(),
+            max_run_seconds: config.max_run_seconds(),
+            schedule: Schedule::of(&config),
+            program: Some(config.program().to_string()),
+            schedule_summary: config.schedule_summary(),
+            error: None,
+            config: Some(Arc::new(config)),
+        },
+        // a template with placeholders is still listed as a template
+        Err((e, is_template)) => YamlMeta {
+            is_template,
+            error: Some(format!("{:?}", e)),
+            ..YamlMeta::default()
+        },
+    }
 }
 
-fn replace_root_alias(path: &mut String, task_folder: &PathBuf) -> Result<(), Error> {
-    if path.starts_with(TASK_ROOT_ALIAS) {
-        let alias_removed = path.replacen(TASK_ROOT_ALIAS, "", 1);
-        let alias_replaced = task_folder.join(alias_removed);
-        if let Some(new_path) = alias_replaced.to_str() {
-            *path = new_path.to_string();
-        } else {
-            return Err(Error::FailedToReplaceRootAlias(
-                "failed to replace root folder, do not use non-utf-8 character in path".to_string(),
-            ));
-        }
+///
+/// the `YamlMeta` of a task parsed from its yaml, bypassing the meta index
+///
+fn read_yaml_meta(label: &str) -> YamlMeta {
+    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    match read_utf8_file(&yaml_file) {
+        Ok(yaml) => parse_yaml_meta(Ok(&yaml)),
+        Err(e) => parse_yaml_meta(Err(Error::YamlError(format!("cannot read yaml: {}", e)))),
     }
-    Ok(())
 }
 
 ///
-/// this function replaces ROOT_ALIAS to root folder for each task
+/// write the meta index if it changed. The index only saves parsing, so a failure
+/// is logged and the index is written again with the next change.
 ///
-fn replace_task_root_alias(config: &mut Configuration, task_label: &str) -> Result<(), Error> {
-    let configuration = &mut config.configuration;
-    let task_folder = get_task_folder_name(task_label);
-    for conf in configuration {
-        if let ProgramArguments(arguments) = conf {
-            for arg in arguments {
-                replace_root_alias(arg, &task_folder)?;
-            }
-        } else if let WorkingDirectory(working_directory) = conf {
-            replace_root_alias(working_directory, &task_folder)?;
-        } else if let RootDirectory(working_directory) = conf {
-            replace_root_alias(working_directory, &task_folder)?;
-        }
+fn save_meta_index(index: &mut MetaIndex<YamlMeta>) {
+    if let Err(e) = index.save() {
+        eprintln!("failed to save meta index: {:?}", e);
     }
-    Ok(())
 }
 
-fn set_working_directory_as_root_alias(config: Configuration) -> Configuration {
-    for c in &config.configuration {
-        match c {
-            WorkingDirectory(_) => {
-                return config;
-            }
-            __ => {}
+///
+/// the first time after `now` (seconds since epoch) matching any entry of `calendar`,
+/// in the local time zone like launchd
+///
+fn next_calendar_run(calendar: &[CalendarTime], now: i64) -> Option<i64> {
+    if calendar.is_empty() {
+        return None;
+    }
+    let (today, hour, minute) = local_day_and_minute(now);
+    for days in today..today + NEXT_RUN_SEARCH_DAYS {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 is a Thursday
+        let weekday = (days + 4).rem_euclid(7);
+        let after = if days == today {
+            Some((hour, minute))
+        } else {
+            None
+        };
+        let first = calendar
+            .iter()
+            .filter(|cal| cal.matches_date(month, day, weekday))
+            .filter_map(|cal| cal.first_time_after(after))
+            .min();
+        if let Some((hour, minute)) = first {
+            return local_secs(days, hour, minute);
         }
     }
-    config.add_config(WorkingDirectory(TASK_ROOT_ALIAS.to_owned()))
+    None
 }
 
 ///
-/// configuration is processed here:
-/// - replace root alias
-/// - clear output folder
-/// - add or override stdout stderr path
+/// the next run after `now` by `StartCalendarInterval`, or by `StartInterval` counted from
+/// the last start (unknown if the task never ran), whichever comes first
 ///
-fn process_config(mut config: Configuration) -> Result<Configuration, Error> {
-    let label = &config.label.clone();
-
-    config = set_working_directory_as_root_alias(config);
+fn next_run(schedule: &Schedule, last_start: Option<i64>, now: i64) -> Option<i64> {
+    let by_calendar = next_calendar_run(&schedule.calendar, now);
+    let by_interval = match (schedule.start_interval, last_start) {
+        (Some(interval), Some(start)) if interval > 0 => {
+            let elapsed = (now - start).max(0);
+            Some(start + (elapsed / interval + 1) * interval)
+        }
+        _ => None,
+    };
+    by_calendar.into_iter().chain(by_interval).min()
+}
 
-    // replace root alias
-    replace_task_root_alias(&mut config, label)?;
+fn task_domain(label: &str) -> Result<Domain, Error> {
+    let meta = yaml_meta(label);
+    Domain::of(meta.scope, meta.user_name.as_deref())
+}
 
-    // attempt to create task and output folder
-    let task_output_name = get_output_folder_name(label);
-    try_clear_output(&label[..]);
-    create_dir_check(&task_output_name)?;
+fn get_plist_path(label_name: &str, domain: Domain) -> PathBuf {
+    domain
+        .plist_folder()
+        .join(String::from(label_name) + ".plist")
+}
 
-    // chown for out directory
-    chown_by_name_recursive(
-        task_output_name.as_path(),
-        &config.get_user_name(),
-        &config.get_group_name(),
-    )?;
+///
+/// every place the plist of a task might be, in case its scope changed
+///
+fn all_plist_paths(label_name: &str) -> [PathBuf; 2] {
+    [
+        get_plist_path(label_name, Domain::System),
+        get_plist_path(label_name, Domain::Gui(0)),
+    ]
+}
 
-    let mut temp;
+///
+/// the plist of a task placed on disk, either as a daemon or as an agent
+///
+fn plist_on_disk(label_name: &str) -> Option<PathBuf> {
+    all_plist_paths(label_name)
+        .iter()
+        .find(|plist| plist.exists())
+        .cloned()
+}
 
-    // add or override stdout stderr path
-    if let Some(std_out_file) = task_output_name.join(STD_OUT_FILE).to_str() {
-        temp = config.add_config(Config::StandardOutPath(std_out_file.to_string()));
-    } else {
-        return Err(Error::NonUtfError(
-            "non-utf8 character not supported in stdout/stderr path".to_string(),
-        ));
-    }
-    if let Some(std_err_file) = task_output_name.join(STD_ERR_FILE).to_str() {
-        temp = temp.add_config(Config::StandardErrorPath(std_err_file.to_string()));
+///
+/// fail with `Error::NotManagedByTasker` unless tasker owns `task_label`: its yaml is in
+/// meta folder, or its plist on disk is labeled `task_label` under `com.tasker.tasks.`.
+/// Every function changing a task calls this first, since tasker runs as root and must
+/// never unload or delete a system daemon by accident. `adopt_task` is the only way to
+/// take over a daemon tasker did not create.
+///
+fn check_managed(task_label: &str) -> Result<(), Error> {
+    let yaml_file = Env::get().meta_dir.join(String::from(task_label) + ".yaml");
+    if managed_by_tasker(task_label, &yaml_file, &all_plist_paths(task_label)) {
+        Ok(())
     } else {
-        return Err(Error::NonUtfError(
-            "non-utf8 character not supported in stdout/stderr path".to_string(),
-        ));
+        Err(Error::NotManagedByTasker(task_label.to_string()))
     }
-
-    Ok(temp)
 }
 
 ///
-/// move yaml file to meta folder
+/// plists are only read if there is no yaml, and their file names are not trusted:
+/// the `Label` inside must be `task_label`
 ///
-fn move_yaml_to_meta(yaml: &PathBuf, label: &String) -> Result<(), Error> {
-    match std::fs::copy(
-        &yaml,
-        Env::get()
-            .meta_dir
-            .join(String::from(label) + ".yaml")
-            .as_path(),
-    ) {
-        Ok(_) => {}
-        Err(_) => {
-            return Err(Error::ErrorMoveYamlToMeta(
-                "cannot copy yaml to meta folder".to_string(),
-            ))
-        }
+fn managed_by_tasker(task_label: &str, yaml_file: &Path, plists: &[PathBuf]) -> bool {
+    if yaml_file.exists() {
+        return true;
     }
+    task_label.starts_with(&format!("{}.", TASKER_TASK_NAME))
+        && plists
+            .iter()
+            .filter(|plist| plist.exists())
+            .any(|plist| plist_label(plist).as_deref() == Some(task_label))
+}
 
-    match std::fs::remove_file(&yaml) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Error::ErrorMoveYamlToMeta(
-            "cannot delete old yaml".to_string(),
-        )),
+fn plist_label(plist: &Path) -> Option<String> {
+    match plist::Value::from_file(plist) {
+        Ok(plist::Value::Dictionary(dict)) => dict
+            .get("Label")
+            .and_then(|label| label.as_string())
+            .map(|label| label.to_string()),
+        _ => None,
     }
 }
 
-fn update_yaml_in_meta(yaml_content: &str, label: &String) -> Result<(), Error> {
-    match std::fs::write(
-        Env::get()
-            .meta_dir
-            .join(String::from(label) + ".yaml")
-            .as_path(),
-        yaml_content,
-    ) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Error::FailedToUpdateMetaYaml(
-            "cannot write yaml".to_string(),
-        )),
-    }
+fn get_task_folder_name(label_name: &str) -> PathBuf {
+    Env::get().task_dir.join(label_name)
+}
+
+fn get_output_folder_name(label_name: &str) -> PathBuf {
+    Env::get().out_dir.join(label_name)
+}
+
+fn get_trash_folder_name(label_name: &str) -> PathBuf {
+    Env::get().trash_dir.join(label_name)
 }
 
 ///
-/// put plist into `/Library/LaunchDaemon` and load task
+/// a trash folder for `label_name` that does not exist yet:
+/// `<label>`, or `<label>.<timestamp>` if the label was trashed before
 ///
-fn place_plist_and_load(config: &Configuration) -> Result<(), Error> {
-    let label = &config.label[..];
-    let plist = config.to_plist();
-    try_remove_plist(label);
-    if let Ok(mut plist_file) = std::fs::File::create(get_plist_path(label)) {
-        match plist_file.write_all(plist.as_ref()) {
-            Ok(_) => {
-                if is_loaded(label)? {
-                    unload_inner(label)?;
-                }
-                load_inner(label)?;
-                Ok(())
-            }
-            Err(_) => Err(Error::ErrorCreatingPlist("error writing plist".to_string())),
-        }
-    } else {
-        Err(Error::ErrorCreatingPlist("cannot create plist".to_string()))
-    }
+fn new_trash_folder_name(label_name: &str) -> PathBuf {
+    unused_path(&get_trash_folder_name(label_name), SystemTime::now())
 }
 
-fn is_loaded(label_pattern: &str) -> Result<bool, Error> {
-    let task_list = launchctl_list(label_pattern)?;
-    for t in task_list {
-        if t.label.eq(label_pattern) {
-            return Ok(true);
-        }
+fn unused_path(path: &Path, now: SystemTime) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
     }
-    Ok(false)
+    let timestamp = match now.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let mut candidate = PathBuf::from(format!("{}.{}", path.display(), timestamp));
+    let mut count = 1;
+    while candidate.exists() {
+        candidate = PathBuf::from(format!("{}.{}.{}", path.display(), timestamp, count));
+        count += 1;
+    }
+    candidate
 }
 
-fn exist(label_pattern: &str) -> Result<bool, Error> {
-    let task_list = list_combined(label_pattern)?;
-    for t in task_list {
-        if t.label.eq(label_pattern) {
-            return Ok(true);
-        }
-    }
-    Ok(false)
+///
+/// stdout file of a task: the `StandardOutPath` in yaml if specified by user,
+/// otherwise `stdout.log` in the output folder
+///
+fn get_std_out_file(label_name: &str) -> PathBuf {
+    let [std_out, _] = std_log_files(label_name);
+    std_out
 }
 
 ///
-/// This function provides an API by returning a JSON of `TaskInfo` returned by
-/// `list_combined`
+/// stderr file of a task: the `StandardErrorPath` in yaml if specified by user,
+/// otherwise `stderr.log` in the output folder
 ///
-pub fn list(label_pattern: &str) -> Result<String, Error> {
-    let task_info = list_combined(label_pattern)?;
-    match serde_json::to_string_pretty(&task_info) {
-        Ok(s) => Ok(s),
-        Err(_) => {
-            return Err(Error::LaunchctlListError(
-                "list error: serialize error".parse().unwrap(),
-            ))
-        }
-    }
+fn get_std_err_file(label_name: &str) -> PathBuf {
+    let [_, std_err] = std_log_files(label_name);
+    std_err
 }
 
 ///
-/// This function combines the result of `launchctl_list` and `library_daemons_list`
+/// stdout and stderr files of a task, reading its yaml once
 ///
-fn list_combined(label_pattern: &str) -> Result<Vec<TaskInfo>, Error> {
-    let mut launchctl_info = launchctl_list(label_pattern)?;
-    let meta_yaml_info = meta_yaml_list(label_pattern)?;
-    for task in meta_yaml_info {
-        if !launchctl_info.contains(&task) {
-            launchctl_info.insert(task);
-        }
-    }
-    let mut task_info = Vec::new();
-    for task in launchctl_info {
-        task_info.push(task);
-    }
-    Ok(task_info)
+fn std_log_files(label_name: &str) -> [PathBuf; 2] {
+    let config = read_config_with_alias_replaced(label_name);
+    let output_folder = get_output_folder_name(label_name);
+    let configured = |path: Option<String>, default: &str| match path {
+        Some(path) => PathBuf::from(path),
+        None => output_folder.join(default),
+    };
+    [
+        configured(
+            config.as_ref().and_then(|c| c.standard_out_path()),
+            STD_OUT_FILE,
+        ),
+        configured(
+            config.as_ref().and_then(|c| c.standard_error_path()),
+            STD_ERR_FILE,
+        ),
+    ]
 }
 
 ///
-/// This function obtains a list of tasks from the launchctl command and
-/// convert it into a Set of `TaskInfo`.
+/// read the stored yaml of a task with `~root~/` alias replaced
 ///
-fn launchctl_list(label_pattern: &str) -> Result<BTreeSet<TaskInfo>, Error> {
-    match execute_command(Command::new("launchctl").arg("list")) {
-        Ok(list_output) => {
-            let task_info = TaskInfo::from_str_filter(&list_output, label_pattern);
-            Ok(task_info)
-        }
-        Err(e) => {
-            return Err(Error::LaunchctlListError(format!(
-                "failed to list file: {:?}",
-                e
-            )));
-        }
+fn read_config_with_alias_replaced(label_name: &str) -> Option<Configuration> {
+    let yaml_file = Env::get().meta_dir.join(String::from(label_name) + ".yaml");
+    let yaml = read_utf8_file(&yaml_file).ok()?;
+    let mut config = Configuration::from_yaml(&yaml).ok()?;
+    expand_task_aliases(
+        &mut config,
+        &get_task_folder_name(label_name),
+        &get_output_folder_name(label_name),
+    )
+    .ok()?;
+    Some(config)
+}
+
+///
+/// size of an output file in bytes, `None` if it has not been created
+///
+fn get_file_size(file: &Path) -> Option<u64> {
+    match std::fs::metadata(file) {
+        Ok(meta) => Some(meta.len()),
+        Err(_) => None,
     }
 }
 
 ///
-/// This function obtains a list of tasks from `/Library/LaunchDaemons` folder and
-/// convert it into a vector of `TaskInfo`
+/// `load_task` takes the following steps:
+/// - read yaml from meta folder
+/// - process yaml
+/// - clear, create, and chown output folder
+/// - place plist in LaunchDaemons folder and load task
 ///
-fn meta_yaml_list(label_pattern: &str) -> Result<Vec<TaskInfo>, Error> {
-    let meta_directory = &Env::get().meta_dir;
-    if let Ok(dir) = meta_directory.read_dir() {
-        let mut tasks: Vec<TaskInfo> = Vec::new();
-        for file in dir {
-            if let Ok(f) = file {
-                let path = f.path();
-                if let Some(file_name) = f.file_name().to_str() {
-                    if path.is_file()
-                        && path.extension().unwrap_or_default().eq("yaml")
-                        && file_name.contains(label_pattern)
-                        && file_name.contains(TASKER_TASK_NAME)
-                    {
-                        if let Some(cap) = LABEL_REGEX.captures(file_name) {
-                            if cap.len() == 2 {
-                                tasks.push(TaskInfo::from_just_label(&cap[1]));
-                            } else {
-                                return Err(Error::FailedToReadMetaFolder(String::from(
-                                    "fail to find label in yaml file name",
-                                )));
-                            }
-                        } else {
-                            return Err(Error::FailedToReadMetaFolder(String::from(
-                                "fail to capture label in yaml file name",
-                            )));
-                        }
-                    }
-                } else {
-                    return Err(Error::FailedToReadMetaFolder(String::from(
-                        "unsupported character in file name",
-                    )));
-                }
-            } else {
-                return Err(Error::FailedToReadMetaFolder(
-                    String::from("cannot get file info in: ")
-                        + meta_directory.to_str().unwrap_or("unknown path"),
-                ));
-            }
-        }
-        Ok(tasks)
-    } else {
-        Err(Error::FailedToReadMetaFolder(
-            String::from("cannot list file in: ")
-                + meta_directory.to_str().unwrap_or("unknown path"),
-        ))
+/// templates cannot be loaded.
+///
+pub fn load_task(task_label: &str) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    let yaml = view_yaml(task_label)?;
+    check_managed(task_label)?;
+    let config = Configuration::from_yaml(&yaml)?;
+    refuse_template(task_label, &config)?;
+    let config = process_config(config, ConfigIntent::Update)?;
+    // a task that failed to load is not recorded as loaded
+    place_plist_and_load(&config, LoadReason::Load)?;
+    set_desired_state(task_label, Some(true))
+}
+
+///
+/// `CannotLoadTemplate` if `config` is a template
+///
+fn refuse_template(task_label: &str, config: &Configuration) -> Result<(), Error> {
+    if config.is_template() {
+        return Err(Error::CannotLoadTemplate(format!(
+            "task `{}` is a template",
+            task_label
+        )));
     }
+    Ok(())
 }
 
-pub fn view_yaml(label: &str) -> Result<String, Error> {
-    if !exist(label)? {
-        return Err(Error::TaskDoesNotExist(
-            "attempting to view yaml of non-existent tasks".to_string(),
+///
+/// execute launchctl load command, return error if already loaded
+///
+fn load_inner(task_label: &str) -> Result<(), Error> {
+    // decide with a fresh list, as the task may have been loaded outside tasker
+    invalidate_launchctl_list();
+    if is_loaded(task_label)? {
+        return Err(Error::FailedToLoadTask(
+            "task is already loaded".to_string(),
         ));
     }
-    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
-    match read_utf8_file(yaml_file.as_path()) {
-        Ok(s) => Ok(s),
-        Err(e) => Err(Error::NonUtfError(format!(
-            "cannot find or read yaml file: {:?}",
-            e
-        ))),
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist("no such task to load".to_string()));
+    }
+    let domain = task_domain(task_label)?;
+    let loaded = run_launchctl(&domain.load_args(&get_plist_path(task_label, domain)));
+    invalidate_launchctl_list();
+    match loaded {
+        Ok(_) => Ok(()),
+        Err(Error::CommandExecutionError(stderr)) => Err(Error::CommandExecutionError(
+            interpret_launchctl_error(&stderr, task_label),
+        )),
+        Err(e) => Err(e),
     }
 }
 
-pub fn view_std_err(label: &str, limit: usize, pattern: &str) -> Result<String, Error> {
-    let std_err_file = get_output_folder_name(label).join(STD_ERR_FILE);
-    match read_last_n_lines(std_err_file.as_path(), limit, pattern) {
-        Ok(s) => Ok(s),
-        Err(e) => Err(Error::NonUtfError(format!(
-            "task `{}` has not been created or its stderr has not been created: {:?}",
-            label, e
-        ))),
+///
+/// always try to delete plist
+///
+pub fn unload_task(task_label: &str) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    check_managed(task_label)?;
+    let is_loaded = is_loaded(task_label)?;
+    if is_loaded {
+        unload_inner(task_label)?;
+    }
+    // recorded only once launchd let go of the task
+    set_desired_state(task_label, Some(false))?;
+    try_remove_plist(task_label);
+    if !is_loaded {
+        return Err(Error::FailedToUnloadTask(
+            "task is already unloaded or does not exist".to_string(),
+        ));
     }
+    Ok(())
 }
 
-pub fn view_std_out(label: &str, limit: usize, pattern: &str) -> Result<String, Error> {
-    let std_out_file = get_output_folder_name(label).join(STD_OUT_FILE);
-    match read_last_n_lines(std_out_file.as_path(), limit, pattern) {
-        Ok(s) => Ok(s),
-        Err(e) => Err(Error::NonUtfError(format!(
-            "task `{}` has not been created or its stdout has not been created: {:?}",
-            label, e
+///
+/// execute launchctl unload command, return error if already unloaded
+///
+fn unload_inner(task_label: &str) -> Result<(), Error> {
+    let domain = task_domain(task_label)?;
+    let unloaded = run_launchctl(&domain.unload_args(&get_plist_path(task_label, domain)));
+    invalidate_launchctl_list();
+    unloaded.map(|_| ())
+}
+
+///
+/// a signal of `STOP_SIGNALS`, e.g. `INT`, `SIGINT` or `sigint`, `TERM` if `None`
+///
+fn stop_signal(signal: Option<&str>) -> Result<(&'static str, libc::c_int), Error> {
+    let name = signal
+        .unwrap_or(DEFAULT_STOP_SIGNAL)
+        .trim()
+        .to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    match STOP_SIGNALS.iter().find(|(allowed, _)| *allowed == name) {
+        Some(signal) => Ok(*signal),
+        None => Err(Error::InvalidSignal(format!(
+            "signal `{}` is not one of {}",
+            signal.unwrap_or_default(),
+            STOP_SIGNALS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
         ))),
     }
 }
 
-pub fn get_zip(label: &str) -> Result<PathBuf, Error> {
-    if !exist(label)? {
-        return Err(Error::TaskDoesNotExist(
-            "attempting to view yaml of non-existent tasks".to_string(),
-        ));
+///
+/// send `signal` to `pid`, or to its process group if `group`
+///
+fn kill_pid(pid: i32, signal: libc::c_int, group: bool) -> Result<(), Error> {
+    let target = if group { -pid } else { pid };
+    if unsafe { libc::kill(target, signal) } == 0 {
+        return Ok(());
     }
-    let unzip_folder = Path::new(TEMP_ZIP_FOLDER);
-    let zip_path = Path::new(TEMP_ZIP_PATH).join(label.to_string() + ".zip");
-    try_to_remove_folder(unzip_folder)?;
-    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    let e = std::io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::ESRCH) => Err(Error::TaskNotRunning(format!(
+            "no process {}{}",
+            if group { "group " } else { "" },
+            pid
+        ))),
+        _ => Err(Error::FailedToSignalTask(format!(
+            "failed to signal {}: {}",
+            pid, e
+        ))),
+    }
+}
 
-    copy_folder(&get_task_folder_name(label), unzip_folder)?;
+///
+/// the pid of the running instance of a task, from a fresh `launchctl list`
+///
+fn running_pid(label: &str) -> Result<Option<i32>, Error> {
+    invalidate_launchctl_list();
+    let task_list = launchctl_list(&LabelMatcher::substring(label))?;
+    Ok(task_list
+        .into_iter()
+        .find(|t| t.label == label)
+        .and_then(|t| t.pid))
+}
 
-    match std::fs::copy(
-        yaml_file.as_path(),
-        unzip_folder.join(String::from(label) + ".yaml"),
-    ) {
-        Ok(_) => {}
-        Err(_) => {
-            return Err(Error::FailedToFindYamlInMeta(
-                "task yaml missing in Meta".to_string(),
-            ))
+///
+/// send `signal` (`TERM` by default) to the running instance of a task,
+/// or to its whole process group if `group`. Return the signal sent and the pid.
+///
+pub fn stop_task(
+    task_label: &str,
+    signal: Option<&str>,
+    group: bool,
+) -> Result<(&'static str, i32), Error> {
+    let _operation = Operation::begin();
+    let (name, number) = stop_signal(signal)?;
+    check_managed(task_label)?;
+    let pid = match running_pid(task_label)? {
+        Some(pid) => pid,
+        None => {
+            return Err(Error::TaskNotRunning(format!(
+                "task `{}` is not running",
+                task_label
+            )))
         }
     };
+    let signaled = kill_pid(pid, number, group);
+    invalidate_launchctl_list();
+    signaled.map(|_| (name, pid))
+}
 
-    zip_dir(unzip_folder, &zip_path, zip::CompressionMethod::Deflated)?;
+///
+/// which step of `restart_task` stopped the task
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StopStage {
+    /// the task was not running, so it was only started
+    NotRunning,
+    /// the process exited within the grace period after the chosen signal
+    Signal,
+    /// the process outlived the grace period and was sent `SIGKILL`
+    Kill,
+}
 
-    Ok(zip_path)
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct RestartReport {
+    pub label: String,
+    /// the process stopped, `null` if the task was not running
+    pub pid: Option<i32>,
+    /// the signal sent first, e.g. `SIGTERM`
+    pub signal: String,
+    pub stopped_by: StopStage,
+    /// from the first signal until the process was gone or killed
+    pub waited_ms: u64,
 }
 
-impl PartialEq for TaskInfo {
-    fn eq(&self, other: &Self) -> bool {
-        self.label.eq(&other.label)
-    }
+///
+/// the processes of a task as seen by `stop_and_kickstart`
+///
+trait TaskProcess {
+    fn signal(&mut self, pid: i32, signal: libc::c_int, group: bool) -> Result<(), Error>;
+    fn alive(&mut self, pid: i32) -> bool;
+    fn kickstart(&mut self) -> Result<(), Error>;
 }
 
-impl Eq for TaskInfo {}
+trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
 
-impl PartialOrd for TaskInfo {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.label.partial_cmp(&other.label)
-    }
+struct LaunchdTask<'a> {
+    label: &'a str,
 }
 
-impl Ord for TaskInfo {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.label.cmp(&other.label)
+impl TaskProcess for LaunchdTask<'_> {
+    fn signal(&mut self, pid: i32, signal: libc::c_int, group: bool) -> Result<(), Error> {
+        kill_pid(pid, signal, group)
+    }
+
+    fn alive(&mut self, pid: i32) -> bool {
+        unsafe { libc::kill(pid, 0) == 0 }
+    }
+
+    fn kickstart(&mut self) -> Result<(), Error> {
+        kickstart_inner(self.label)
     }
 }
 
-impl TaskInfo {
-    fn from_line(line: &str) -> TaskInfo {
-        let mut split = line.split_whitespace();
-        let pid: Option<i32> = match split.next().unwrap_or("-").parse::<i32>() {
-            Ok(i) => Some(i),
-            Err(_) => None,
-        };
-        let last_exit_status = match split.next().unwrap_or("0").parse::<i32>() {
-            Ok(d) => Some(d),
-            Err(_) => None,
-        };
-        let label = String::from(split.next().unwrap_or(""));
-        let mut status = Status::NORMAL;
-        if pid.is_some() {
-            status = Status::RUNNING
-        } else if last_exit_status.unwrap() != 0 {
-            status = Status::ERROR
-        } else if !get_output_folder_name(&label).join(STD_OUT_FILE).exists() {
-            status = Status::LOADED
-        }
-        TaskInfo {
-            pid,
-            last_exit_status,
-            label,
-            status,
-        }
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
     }
 
-    fn from_str_filter(output: &str, pattern: &str) -> BTreeSet<TaskInfo> {
-        let mut lines = output.lines();
-        let mut temp = Vec::new();
-        let mut collected = BTreeSet::new();
-        lines.next();
-        for line in lines {
-            temp.push(TaskInfo::from_line(line))
-        }
-        for task in temp {
-            if task.label.contains(pattern) && task.label.contains(TASKER_TASK_NAME) {
-                collected.insert(task);
-            }
-        }
-        collected
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+///
+/// send `signal` to `pid`, wait up to `grace` for it to exit, send `SIGKILL` if it
+/// does not, and then start the task again. A process gone before it is signaled
+/// counts as not running.
+///
+fn stop_and_kickstart<P: TaskProcess, C: Clock>(
+    process: &mut P,
+    clock: &mut C,
+    pid: Option<i32>,
+    signal: libc::c_int,
+    group: bool,
+    grace: Duration,
+) -> Result<(StopStage, Duration), Error> {
+    let stopped = match pid {
+        Some(pid) => stop_process(process, clock, pid, signal, group, grace)?,
+        None => (StopStage::NotRunning, Duration::from_secs(0)),
+    };
+    process.kickstart()?;
+    Ok(stopped)
+}
+
+///
+/// send `signal` to `pid`, wait up to `grace` for it to exit, and send `SIGKILL`
+/// if it does not. A process gone before it is signaled counts as not running.
+///
+fn stop_process<P: TaskProcess, C: Clock>(
+    process: &mut P,
+    clock: &mut C,
+    pid: i32,
+    signal: libc::c_int,
+    group: bool,
+    grace: Duration,
+) -> Result<(StopStage, Duration), Error> {
+    match process.signal(pid, signal, group) {
+        Ok(_) => {}
+        Err(Error::TaskNotRunning(_)) => {
+            return Ok((StopStage::NotRunning, Duration::from_secs(0)))
+        }
+        Err(e) => return Err(e),
+    }
+    let start = clock.now();
+    let deadline = start + grace;
+    let stage = loop {
+        if !process.alive(pid) {
+            break StopStage::Signal;
+        }
+        let now = clock.now();
+        if now >= deadline {
+            match process.signal(pid, libc::SIGKILL, group) {
+                Ok(_) | Err(Error::TaskNotRunning(_)) => break StopStage::Kill,
+                Err(e) => return Err(e),
+            }
+        }
+        clock.sleep(RESTART_POLL_INTERVAL.min(deadline - now));
+    };
+    Ok((stage, clock.now() - start))
+}
+
+///
+/// stop the run `pid` of a task without starting it again: send `SIGTERM`, and
+/// `SIGKILL` if it is still alive after `grace`. Used to stop runs exceeding `MaxRunSeconds`.
+///
+pub fn stop_run(task_label: &str, pid: i32, grace: Duration) -> Result<StopStage, Error> {
+    let (stage, _) = stop_process(
+        &mut LaunchdTask { label: task_label },
+        &mut SystemClock,
+        pid,
+        libc::SIGTERM,
+        false,
+        grace,
+    )?;
+    Ok(stage)
+}
+
+///
+/// stop a task gracefully and start it again: send `signal` (`TERM` by default),
+/// wait up to `grace` for the process to exit, kill it if it does not,
+/// and kickstart the task. The task must be loaded.
+///
+pub fn restart_task(
+    task_label: &str,
+    signal: Option<&str>,
+    group: bool,
+    grace: Duration,
+) -> Result<RestartReport, Error> {
+    let _operation = Operation::begin();
+    let (name, number) = stop_signal(signal)?;
+    check_managed(task_label)?;
+    if !is_loaded(task_label)? {
+        return Err(Error::FailedToKickstartTask(format!(
+            "task `{}` is not loaded",
+            task_label
+        )));
+    }
+    let pid = running_pid(task_label)?;
+    let (stopped_by, waited) = stop_and_kickstart(
+        &mut LaunchdTask { label: task_label },
+        &mut SystemClock,
+        pid,
+        number,
+        group,
+        grace,
+    )?;
+    Ok(RestartReport {
+        label: task_label.to_string(),
+        pid,
+        signal: format!("SIG{}", name),
+        stopped_by,
+        waited_ms: waited.as_millis() as u64,
+    })
+}
+
+///
+/// execute launchctl kickstart command, killing the running instance first
+///
+fn kickstart_inner(task_label: &str) -> Result<(), Error> {
+    let target = task_domain(task_label)?.service_target(task_label);
+    let kickstarted = launchctl(&["kickstart", "-k", &target]);
+    invalidate_launchctl_list();
+    match kickstarted {
+        Ok(_) => Ok(()),
+        Err(e @ Error::LaunchctlBusy(_)) => Err(e),
+        Err(e) => Err(Error::FailedToKickstartTask(format!(
+            "failed to kickstart task: {:?}",
+            e
+        ))),
+    }
+}
+
+///
+/// rotate stdout and stderr of a task regardless of their sizes.
+///
+/// launchd holds the file descriptors of a running task, so after the rename
+/// the task keeps writing to the rotated file until it restarts. Therefore,
+/// rotation happens first (a fresh empty log is created in place), and a running
+/// task is then kickstarted so that the new process opens the fresh log.
+///
+pub fn rotate_logs(task_label: &str) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist(
+            "no such task to rotate logs".to_string(),
+        ));
+    }
+    check_managed(task_label)?;
+    rotate_then_kickstart(
+        &mut LaunchdTask { label: task_label },
+        &get_output_folder_name(task_label),
+        &std_log_files(task_label),
+        || is_running(task_label),
+    )
+}
+
+///
+/// rotate `logs` in `output_folder`, and only then kickstart the task if `is_running`
+///
+fn rotate_then_kickstart<P, R>(
+    process: &mut P,
+    output_folder: &Path,
+    logs: &[PathBuf],
+    is_running: R,
+) -> Result<(), Error>
+where
+    P: TaskProcess,
+    R: FnOnce() -> Result<bool, Error>,
+{
+    rotate_logs_inside(output_folder, logs, true)?;
+    if is_running()? {
+        process.kickstart()?;
+    }
+    Ok(())
+}
+
+///
+/// rotate all stdout and stderr files under the `out` folder that exceed `MAX_LOG_MB`.
+/// This does not restart the tasks.
+///
+pub fn rotate_oversized_logs() -> Result<(), Error> {
+    let out_dir = &Env::get().out_dir;
+    let dir = match out_dir.read_dir() {
+        Ok(dir) => dir,
+        Err(_) => {
+            return Err(Error::FailedToRotateLog(
+                String::from("cannot list file in: ") + &out_dir.to_string_lossy(),
+            ))
+        }
+    };
+    let mut errors = Vec::new();
+    for entry in dir.flatten() {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        if let Some(label) = entry.file_name().to_str() {
+            if let Err(e) = rotate_task_output(label, false) {
+                errors.push(format!("{:?}", e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::FailedToRotateLog(errors.join("; ")))
+    }
+}
+
+///
+/// rotate stdout and stderr of a task if they exceed `MAX_LOG_MB` or if `force` is set.
+/// Logs configured outside of the output folder, e.g. `/var/log/system.log`,
+/// are not owned by the task, so they are left alone.
+///
+fn rotate_task_output(task_label: &str, force: bool) -> Result<(), Error> {
+    rotate_logs_inside(
+        &get_output_folder_name(task_label),
+        &std_log_files(task_label),
+        force,
+    )
+}
+
+fn rotate_logs_inside(output_folder: &Path, logs: &[PathBuf], force: bool) -> Result<(), Error> {
+    for file in logs {
+        let file = match log_inside(output_folder, file) {
+            Some(file) => file,
+            None => continue,
+        };
+        if let Ok(meta) = std::fs::metadata(&file) {
+            if force || meta.len() > Env::get().max_log_bytes {
+                rotate_file(&file, LOG_KEEP_COUNT)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// `log` resolved inside `output_folder`, `None` if it is elsewhere
+/// or leads outside through `..` or a symlink
+///
+fn log_inside(output_folder: &Path, log: &Path) -> Option<PathBuf> {
+    let relative = log.strip_prefix(output_folder).ok()?.to_str()?;
+    resolve_inside(output_folder, relative).ok()
+}
+
+fn get_desired_state_file() -> PathBuf {
+    Env::get().meta_dir.join(DESIRED_STATE_FILE)
+}
+
+///
+/// read the desired state (whether a task should be loaded) of each task,
+/// an empty map is returned if the state file is missing or corrupted
+///
+fn read_desired_state(state_file: &Path) -> BTreeMap<String, bool> {
+    match read_utf8_file(state_file) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+///
+/// write the desired state to a temporary file first, and then rename it
+/// to the state file, so that the state file is never half written
+///
+fn write_desired_state(state_file: &Path, state: &BTreeMap<String, bool>) -> Result<(), Error> {
+    let content = match serde_json::to_string_pretty(state) {
+        Ok(s) => s,
+        Err(e) => return Err(Error::FailedToUpdateDesiredState(format!("{}", e))),
+    };
+    let temp_file = state_file.with_extension("json.tmp");
+    match std::fs::write(&temp_file, content).and_then(|_| std::fs::rename(&temp_file, state_file))
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::FailedToUpdateDesiredState(format!("{}", e))),
+    }
+}
+
+///
+/// record whether a task should be loaded, or forget the task if `should_be_loaded` is `None`
+///
+fn set_desired_state(task_label: &str, should_be_loaded: Option<bool>) -> Result<(), Error> {
+    let _lock = DESIRED_STATE_LOCK.lock().unwrap();
+    let state_file = get_desired_state_file();
+    let mut state = read_desired_state(&state_file);
+    match should_be_loaded {
+        Some(b) => state.insert(task_label.to_string(), b),
+        None => state.remove(task_label),
+    };
+    write_desired_state(&state_file, &state)
+}
+
+fn get_plist_checksum_file() -> PathBuf {
+    Env::get().meta_dir.join(PLIST_CHECKSUM_FILE)
+}
+
+///
+/// SHA-256 of the plist tasker last wrote for each task,
+/// an empty map is returned if the checksum file is missing or corrupted
+///
+fn read_plist_checksums() -> BTreeMap<String, String> {
+    match read_utf8_file(&get_plist_checksum_file()) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+///
+/// record the checksum of the plist written for a task, or forget it if `None`
+///
+fn set_plist_checksum(task_label: &str, checksum: Option<String>) -> Result<(), Error> {
+    let _lock = PLIST_CHECKSUM_LOCK.lock().unwrap();
+    let mut checksums = read_plist_checksums();
+    match checksum {
+        Some(checksum) => checksums.insert(task_label.to_string(), checksum),
+        None => checksums.remove(task_label),
+    };
+    let content = match serde_json::to_string_pretty(&checksums) {
+        Ok(s) => s,
+        Err(e) => return Err(Error::ErrorCreatingPlist(format!("{}", e))),
+    };
+    write_atomic(&get_plist_checksum_file(), content.as_bytes())
+}
+
+///
+/// whether the plist on disk differs from the one tasker wrote,
+/// `None` if there is no plist on disk or no recorded checksum
+///
+fn plist_tampered(task_label: &str, checksums: &BTreeMap<String, String>) -> Option<bool> {
+    let recorded = checksums.get(task_label)?;
+    let on_disk = file_sha256(&plist_on_disk(task_label)?)?;
+    Some(*recorded != on_disk)
+}
+
+///
+/// refuse to overwrite a plist that was edited outside of tasker
+///
+fn check_plist_untouched(task_label: &str) -> Result<(), Error> {
+    let checksums = read_plist_checksums();
+    if let Some(true) = plist_tampered(task_label, &checksums) {
+        return Err(Error::PlistModifiedExternally(format!(
+            "plist of `{}` was modified outside of tasker (recorded sha256 {}, on disk {}), \
+             use force=true to overwrite it",
+            task_label,
+            checksums[task_label],
+            plist_on_disk(task_label)
+                .and_then(|plist| file_sha256(&plist))
+                .unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+///
+/// tasks that should be loaded according to the desired state but are not loaded
+///
+fn tasks_to_autoload(
+    desired_state: &BTreeMap<String, bool>,
+    loaded: &BTreeSet<String>,
+) -> Vec<String> {
+    desired_state
+        .iter()
+        .filter(|(label, should_be_loaded)| **should_be_loaded && !loaded.contains(*label))
+        .map(|(label, _)| label.clone())
+        .collect()
+}
+
+///
+/// the result of loading each task, along with its label
+///
+pub type AutoloadResults = Vec<(String, Result<(), Error>)>;
+
+///
+/// load every task that should be loaded but is not, e.g. after the plist is
+/// removed by hand. The result of each task is returned along with its label.
+///
+pub fn autoload_tasks() -> Result<AutoloadResults, Error> {
+    let loaded = launchctl_list(&LabelMatcher::all())?
+        .into_iter()
+        .map(|task| task.label)
+        .collect();
+    let desired_state = read_desired_state(&get_desired_state_file());
+    Ok(tasks_to_autoload(&desired_state, &loaded)
+        .into_iter()
+        .map(|label| {
+            let result = load_task(&label);
+            (label, result)
+        })
+        .collect())
+}
+
+///
+/// what `rebuild_all` did to a task
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RebuildAction {
+    /// loaded with a plist matching its yaml, or loaded though it should not be, left alone
+    Unchanged,
+    /// its plist is placed if missing or outdated, and it is (re)loaded
+    Loaded,
+    /// it should not be loaded, or the desired state is missing
+    LeftUnloaded,
+    /// templates are never loaded
+    Template,
+    /// its folder in `tasks` is missing, so it cannot run
+    TaskFolderMissing,
+    Failed,
+}
+
+///
+/// outcome of `rebuild_all` for a task
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RebuildEntry {
+    label: String,
+    action: RebuildAction,
+    /// its output folder was missing and has been created
+    out_folder_created: bool,
+    error: Option<String>,
+}
+
+///
+/// outcome of `rebuild_all` for each yaml in `meta`, by label
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RebuildReport {
+    /// without `meta/desired_state.json`, every task is left unloaded
+    desired_state_found: bool,
+    /// `TASKER_ROOT` or other paths changed since the tasks were created, so every plist
+    /// is regenerated with the new paths
+    root_moved: bool,
+    /// the new paths are recorded, as no task failed
+    settings_migrated: bool,
+    tasks: Vec<RebuildEntry>,
+}
+
+///
+/// how `rebuild_all` brings a task that is not a template into its desired state
+///
+#[derive(Debug, PartialEq)]
+enum RebuildStep {
+    Done(RebuildAction),
+    Load,
+    PlaceAndLoad,
+}
+
+///
+/// a loaded task is never unloaded, and a matching plist is never rewritten.
+/// After the root moved, a loaded task that should not be loaded still gets a plist
+/// with the new paths, and stays loaded.
+///
+fn rebuild_step(
+    should_be_loaded: bool,
+    plist_matches: bool,
+    loaded: bool,
+    root_moved: bool,
+) -> RebuildStep {
+    match (should_be_loaded, plist_matches, loaded) {
+        (false, false, true) if root_moved => RebuildStep::PlaceAndLoad,
+        (false, _, false) => RebuildStep::Done(RebuildAction::LeftUnloaded),
+        (false, _, true) | (true, true, true) => RebuildStep::Done(RebuildAction::Unchanged),
+        (true, true, false) => RebuildStep::Load,
+        (true, false, _) => RebuildStep::PlaceAndLoad,
+    }
+}
+
+///
+/// rebuild everything else from `meta` and `tasks`, e.g. after only those are restored
+/// from a backup: recreate the output folder of each task, and place the plist and load
+/// the tasks that should be loaded according to the desired state.
+/// This is idempotent, a healthy task is left untouched.
+/// It also migrates the tasks after `TASKER_ROOT` moved (see `settings_snapshot`).
+///
+pub fn rebuild_all() -> Result<RebuildReport, Error> {
+    let _operation = Operation::begin();
+    let root_moved = !settings_changes().is_empty();
+    let state_file = get_desired_state_file();
+    let desired_state_found = state_file.is_file();
+    let desired_state = read_desired_state(&state_file);
+    let mut labels = meta_labels(&LabelMatcher::all())?;
+    labels.sort();
+    let tasks = labels
+        .into_iter()
+        .map(|label| {
+            let should_be_loaded = desired_state.get(&label) == Some(&true);
+            let mut entry = RebuildEntry {
+                label,
+                action: RebuildAction::Failed,
+                out_folder_created: false,
+                error: None,
+            };
+            match rebuild_task(
+                &entry.label,
+                should_be_loaded,
+                root_moved,
+                &mut entry.out_folder_created,
+            ) {
+                Ok(action) => entry.action = action,
+                Err(e) => entry.error = Some(format!("{:?}", e)),
+            }
+            entry
+        })
+        .collect::<Vec<RebuildEntry>>();
+    let settings_migrated = root_moved
+        && tasks
+            .iter()
+            .all(|task| task.action != RebuildAction::Failed)
+        && settings_migrated().is_ok();
+    Ok(RebuildReport {
+        desired_state_found,
+        root_moved,
+        settings_migrated,
+        tasks,
+    })
+}
+
+fn rebuild_task(
+    label: &str,
+    should_be_loaded: bool,
+    root_moved: bool,
+    out_folder_created: &mut bool,
+) -> Result<RebuildAction, Error> {
+    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    let yaml = read_utf8_file(&yaml_file)
+        .map_err(|e| Error::NonUtfError(format!("cannot read yaml file: {:?}", e)))?;
+    let config = Configuration::from_yaml(&yaml)?;
+    if !get_task_folder_name(label).is_dir() {
+        return Ok(RebuildAction::TaskFolderMissing);
+    }
+    if config.is_template() {
+        return Ok(RebuildAction::Template);
+    }
+    let out_folder_existed = get_output_folder_name(label).is_dir();
+    let config = process_config(config, ConfigIntent::Update)?;
+    *out_folder_created = !out_folder_existed;
+
+    let domain = Domain::of(config.scope(), config.user_name().as_deref())?;
+    let plist_matches = file_sha256(&get_plist_path(label, domain))
+        == Some(sha256_hex(config.to_plist().as_bytes()));
+    match rebuild_step(
+        should_be_loaded,
+        plist_matches,
+        is_loaded(label)?,
+        root_moved,
+    ) {
+        RebuildStep::Done(action) => Ok(action),
+        RebuildStep::Load => load_inner(label).map(|_| RebuildAction::Loaded),
+        RebuildStep::PlaceAndLoad => {
+            check_plist_untouched(label)?;
+            place_plist_and_load(&config, LoadReason::Load).map(|_| RebuildAction::Loaded)
+        }
+    }
+}
+
+///
+/// outcome of each step of `delete_task`
+///
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct DeleteReport {
+    unloaded: bool,
+    plist_removed: bool,
+    task_folder_trashed: bool,
+    yaml_trashed: bool,
+    output_trashed: bool,
+    errors: Vec<String>,
+}
+
+///
+/// every step is attempted even if previous steps fail, and the outcomes are
+/// collected into a `DeleteReport`. Deletion is considered successful only if
+/// the plist is removed and the yaml is no longer in meta folder.
+///
+pub fn delete_task(task_label: &str) -> Result<DeleteReport, Error> {
+    let _operation = Operation::begin();
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            task_label
+        )));
+    }
+    check_managed(task_label)?;
+    let mut report = DeleteReport::default();
+
+    // unload task
+    match is_loaded(task_label) {
+        Ok(true) => match unload_inner(task_label) {
+            Ok(_) => report.unloaded = true,
+            Err(e) => report
+                .errors
+                .push(format!("failed to unload task: {:?}", e)),
+        },
+        Ok(false) => report.unloaded = true,
+        Err(e) => report
+            .errors
+            .push(format!("failed to check load status: {:?}", e)),
+    }
+
+    // remove plist, as a daemon or as an agent
+    report.plist_removed = true;
+    for plist in all_plist_paths(task_label).iter().filter(|p| p.exists()) {
+        if let Err(e) = std::fs::remove_file(plist) {
+            report.plist_removed = false;
+            report.errors.push(format!("failed to remove plist: {}", e));
+        }
+    }
+
+    // move 'task' folder to trash, without mixing with earlier deletions of the same label
+    let trash_folder = new_trash_folder_name(task_label);
+    match move_to_trash(&get_task_folder_name(task_label), &trash_folder) {
+        Ok(_) => report.task_folder_trashed = true,
+        Err(e) => report
+            .errors
+            .push(format!("failed to move task folder to trash: {:?}", e)),
+    }
+
+    // move yaml to trash
+    let yaml_in_meta = Env::get().meta_dir.join(String::from(task_label) + ".yaml");
+    if yaml_in_meta.exists() {
+        let copied = create_dir_check(&trash_folder).and_then(|_| {
+            match std::fs::copy(
+                &yaml_in_meta,
+                trash_folder.join(String::from(task_label) + ".yaml"),
+            ) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(Error::CopyError(format!("{}", e))),
+            }
+        });
+        match copied {
+            Ok(_) => {
+                report.yaml_trashed = true;
+                if let Err(e) = std::fs::remove_file(&yaml_in_meta) {
+                    report
+                        .errors
+                        .push(format!("failed to remove yaml from meta folder: {}", e));
+                }
+                invalidate_task_labels();
+                let mut index = META_INDEX.lock().unwrap();
+                index.remove(task_label);
+                save_meta_index(&mut index);
+            }
+            // the yaml is the only copy of the configuration, so it stays in meta folder
+            Err(e) => report.errors.push(format!(
+                "failed to copy yaml to trash, it is kept in meta folder: {:?}",
+                e
+            )),
+        }
+    }
+
+    // move the list of secrets, the run history and the provenance to trash
+    for (file_in_meta, extension, name) in &[
+        (get_secrets_file(task_label), SECRETS_EXTENSION, "secrets"),
+        (history_file(task_label), HISTORY_EXTENSION, "run history"),
+        (
+            provenance_file(task_label),
+            PROVENANCE_EXTENSION,
+            "provenance",
+        ),
+    ] {
+        if !file_in_meta.exists() {
+            continue;
+        }
+        let moved = create_dir_check(&trash_folder).and_then(|_| {
+            match std::fs::rename(
+                file_in_meta,
+                trash_folder.join(String::from(task_label) + extension),
+            ) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(Error::RenameError(format!("{}", e))),
+            }
+        });
+        if let Err(e) = moved {
+            report
+                .errors
+                .push(format!("failed to move {} to trash: {:?}", name, e));
+        }
+    }
+
+    // move 'out' folder to trash
+    match move_to_trash(
+        &get_output_folder_name(task_label),
+        &trash_folder.join("out"),
+    ) {
+        Ok(_) => report.output_trashed = true,
+        Err(e) => report
+            .errors
+            .push(format!("failed to move output folder to trash: {:?}", e)),
+    }
+
+    // record the time of deletion, as the mtime of the trash folder changes with its content
+    let marked = create_dir_check(&trash_folder)
+        .and_then(|_| DeletionMarker::new(task_label, SystemTime::now()).write(&trash_folder));
+    if let Err(e) = marked {
+        report
+            .errors
+            .push(format!("failed to write deletion marker: {:?}", e));
+    }
+
+    // forget desired state
+    if let Err(e) = set_desired_state(task_label, None) {
+        report
+            .errors
+            .push(format!("failed to remove desired state: {:?}", e));
+    }
+    if let Err(e) = set_plist_checksum(task_label, None) {
+        report
+            .errors
+            .push(format!("failed to remove plist checksum: {:?}", e));
+    }
+
+    if report.plist_removed && !yaml_in_meta.exists() {
+        Ok(report)
+    } else {
+        Err(Error::FailedToDeleteTask(report))
+    }
+}
+
+///
+/// move a folder to trash. If rename fails (e.g. trash is on another volume),
+/// fall back to copy and then delete. Missing folders are considered trashed.
+///
+fn move_to_trash(from: &Path, to: &Path) -> Result<(), Error> {
+    if !from.exists() {
+        return Ok(());
+    }
+    if move_by_rename(from, to).is_err() {
+        copy_folder(from, to, true)?;
+        try_to_remove_folder(from)?;
+    }
+    // trash retention counts from the time of trashing
+    let _ = set_mtime(to, SystemTime::now());
+    Ok(())
+}
+
+///
+/// an entry of the trash folder: a deleted task, or an output or task folder
+/// replaced by a new one
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct TrashEntry {
+    /// the name in the trash folder, like `com.tasker.tasks.backup.out.1600000000`
+    name: String,
+    label: String,
+    /// RFC3339, from `deleted_at.json` of the entry, or else its mtime
+    deleted_at: Option<String>,
+    /// whether `deleted_at` is the mtime of an entry without `deleted_at.json`
+    estimated: bool,
+    bytes: u64,
+    /// the yaml of the task is kept in the entry
+    has_yaml: bool,
+    /// a task with the same label exists, which is in the way of restoring this entry
+    live_task_exists: bool,
+}
+
+///
+/// the order of `trash_info`
+///
+#[derive(Debug, Default, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashSort {
+    /// oldest first
+    #[default]
+    Age,
+    /// largest first
+    Size,
+}
+
+///
+/// the entries of the trash folder in the order of `sort`
+///
+pub fn trash_info(sort: TrashSort) -> Result<Vec<TrashEntry>, Error> {
+    let live_labels: BTreeSet<String> = task_labels()?.into_iter().collect();
+    Ok(trash_entries(&Env::get().trash_dir, &live_labels, sort))
+}
+
+fn trash_entries(
+    trash_dir: &Path,
+    live_labels: &BTreeSet<String>,
+    sort: TrashSort,
+) -> Vec<TrashEntry> {
+    let mut entries: Vec<(Option<SystemTime>, TrashEntry)> = match trash_dir.read_dir() {
+        Ok(dir) => dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| trash_entry(&entry.path(), live_labels))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort_by(|(a_time, a), (b_time, b)| {
+        match sort {
+            // entries of unknown age last
+            TrashSort::Age => (a_time.is_none(), a_time).cmp(&(b_time.is_none(), b_time)),
+            TrashSort::Size => b.bytes.cmp(&a.bytes),
+        }
+        .then_with(|| a.name.cmp(&b.name))
+    });
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn trash_entry(path: &Path, live_labels: &BTreeSet<String>) -> (Option<SystemTime>, TrashEntry) {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let marker = DeletionMarker::read(path);
+    let (deleted, estimated) = match marker.as_ref().and_then(|m| m.deleted_time()) {
+        Some(time) => (Some(time), false),
+        None => (trashed_at(path), true),
+    };
+    let label = match marker {
+        Some(marker) => marker.label,
+        None => trashed_label(&name).to_string(),
+    };
+    let entry = TrashEntry {
+        deleted_at: deleted.and_then(format_rfc3339),
+        estimated,
+        bytes: entry_stats(path).map(|(_, _, bytes)| bytes).unwrap_or(0),
+        has_yaml: path.join(label.clone() + ".yaml").is_file(),
+        live_task_exists: live_labels.contains(&label),
+        name,
+        label,
+    };
+    (deleted, entry)
+}
+
+///
+/// the label of a trash entry without `deleted_at.json`, named `<label>`,
+/// `<label>.<timestamp>[.<count>]` or `<label>.out.<timestamp>[.<count>]`
+///
+fn trashed_label(name: &str) -> &str {
+    // labels may have numeric components, but not as long as a timestamp
+    let is_timestamp = |s: &str| s.len() >= 9 && s.bytes().all(|b| b.is_ascii_digit());
+    let is_count = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if let Some(i) = name.rfind(".out.") {
+        if name[i + ".out.".len()..]
+            .split('.')
+            .next()
+            .is_some_and(is_timestamp)
+        {
+            return &name[..i];
+        }
+    }
+    let mut parts = name.rsplitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(count), Some(timestamp), Some(label))
+            if is_timestamp(timestamp) && is_count(count) =>
+        {
+            label
+        }
+        (Some(timestamp), Some(_), _) if is_timestamp(timestamp) => {
+            &name[..name.len() - timestamp.len() - 1]
+        }
+        _ => name,
+    }
+}
+
+fn try_remove_plist(task_label: &str) {
+    for plist in all_plist_paths(task_label).iter() {
+        let _ = delete_file_check(plist);
+    }
+}
+
+///
+/// a trash folder for the output folder of `label_name`: `<label>.out.<timestamp>`
+///
+fn output_trash_folder_name(label_name: &str, now: SystemTime) -> PathBuf {
+    let timestamp = match now.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    unused_path(
+        &Env::get()
+            .trash_dir
+            .join(format!("{}.out.{}", label_name, timestamp)),
+        now,
+    )
+}
+
+///
+/// create the output folder of a task. For a new task, an output folder that already
+/// exists is a stale leftover, and is moved to `trash` first.
+///
+fn prepare_output_folder(output: &Path, trash: &Path, intent: ConfigIntent) -> Result<(), Error> {
+    if intent == ConfigIntent::CreateNew && output.exists() {
+        move_to_trash(output, trash)?;
+    }
+    create_dir_check(output)
+}
+
+///
+/// move the output folder of a task to trash, and start over with an empty one.
+/// A running task keeps writing to its trashed logs until it restarts.
+///
+pub fn clear_output(task_label: &str) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist(
+            "no such task to clear output".to_string(),
+        ));
+    }
+    check_managed(task_label)?;
+    let config = Configuration::from_yaml(&view_yaml(task_label)?)?;
+    let output = get_output_folder_name(task_label);
+    move_to_trash(
+        &output,
+        &output_trash_folder_name(task_label, SystemTime::now()),
+    )?;
+    create_dir_check(&output)?;
+    chown_by_name_recursive(&output, &config.user_name(), &config.group_name())
+}
+
+///
+/// create a new task based on a zip or tar.gz package
+///
+pub fn create_task(task_zip: &Path, provenance: &Provenance) -> Result<Vec<ChownWarning>, Error> {
+    let _operation = Operation::begin();
+    let unzip_folder = Path::new(TEMP_UNZIP_FOLDER);
+    let _in_progress = InProgress::new(unzip_folder);
+    try_to_remove_folder(unzip_folder)?;
+    extract_archive(
+        task_zip,
+        Path::new(TEMP_UNZIP_FOLDER),
+        Env::get().max_uncompressed_bytes,
+    )?;
+    create_task_from_folder(unzip_folder, provenance, None).map(|(_, warnings)| warnings)
+}
+
+///
+/// create a new task from the files in `folder`, e.g. an extracted zip or a git checkout.
+///
+/// The yaml is found like in a zip package, and `label` replaces the label in the yaml
+/// if given. The files of the task are moved out of `folder`.
+/// Returns the label of the new task.
+///
+pub fn create_task_from_folder(
+    folder: &Path,
+    provenance: &Provenance,
+    label: Option<&str>,
+) -> Result<(String, Vec<ChownWarning>), Error> {
+    let _operation = Operation::begin();
+    let (yaml, task_root) = find_yaml_file(folder)?;
+
+    return if let Ok(yaml_content) = read_utf8_file(&yaml) {
+        let yaml_content = match label {
+            Some(label) => relabel_yaml_file(&yaml, &yaml_content, label)?,
+            None => yaml_content,
+        };
+        let mut config = Configuration::from_yaml(&yaml_content)?;
+        let label = &config.label.clone();
+
+        // process configuration: view `process_config` documentation for detail
+        config = process_config(config, ConfigIntent::CreateNew)?;
+
+        // move yaml to meta folder
+        move_yaml_to_meta(&yaml, label)?;
+        write_provenance(label, provenance)?;
+
+        // move the files to task folder
+        let task_folder_name = get_task_folder_name(label);
+        create_dir_check(&task_folder_name)?;
+        move_by_rename(&task_root, task_folder_name.as_path())?;
+        let (_, warnings) = chown_by_name_recursive_lossy(
+            task_folder_name.as_path(),
+            &config.user_name(),
+            &config.group_name(),
+        )?;
+
+        // templates are never loaded
+        if config.is_template() {
+            set_desired_state(label, Some(false))?;
+            return Ok((label.clone(), warnings));
+        }
+
+        // place plist and load task
+        set_desired_state(label, Some(true))?;
+        place_plist_and_load(&config, LoadReason::Load)?;
+        Ok((label.clone(), warnings))
+    } else {
+        Err(Error::YamlError(
+            "error reading yaml as utf8 text".to_string(),
+        ))
+    };
+}
+
+///
+/// rewrite `yaml` with `label` (with or without `com.tasker.tasks.`) as its label
+///
+fn relabel_yaml_file(yaml: &Path, yaml_content: &str, label: &str) -> Result<String, Error> {
+    let short_label = label
+        .strip_prefix(&(String::from(TASKER_TASK_NAME) + "."))
+        .unwrap_or(label);
+    check_label_str(short_label)?;
+    let mut config = Configuration::parse_yaml(yaml_content)?;
+    config.label = short_label.to_string();
+    let yaml_content = config.to_yaml()?;
+    match std::fs::write(yaml, &yaml_content) {
+        Ok(_) => Ok(yaml_content),
+        Err(e) => Err(Error::YamlError(format!("cannot relabel yaml: {}", e))),
+    }
+}
+
+///
+/// adopt an existing daemon in `/Library/LaunchDaemons` which is not managed by tasker.
+///
+/// The plist is converted to yaml (keys not modeled are preserved), and the daemon is
+/// replaced by a tasker task with label prefixed by `com.tasker.tasks`. The original
+/// plist is moved to trash, and the task is loaded if the daemon was loaded.
+///
+pub fn adopt_task(label: &str, provenance: &Provenance) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    if label.starts_with(TASKER_TASK_NAME) {
+        return Err(Error::FailedToAdoptTask(format!(
+            "`{}` is already managed by tasker",
+            label
+        )));
+    }
+    let plist = get_plist_path(label, Domain::System);
+    let yaml = Configuration::from_plist_file(&plist)?.to_yaml()?;
+    let config = Configuration::from_yaml(&yaml)?;
+    let task_label = config.label.clone();
+    if exist(&task_label)? {
+        return Err(Error::FailedToAdoptTask(format!(
+            "task `{}` already exists",
+            task_label
+        )));
+    }
+
+    // stop the original daemon, keeping its plist in trash
+    let is_loaded = is_loaded(label)?;
+    if is_loaded {
+        unload_inner(label)?;
+    }
+    let trash_folder = get_trash_folder_name(&task_label);
+    create_dir_check(&trash_folder)?;
+    if let Err(e) = std::fs::copy(&plist, trash_folder.join(String::from(label) + ".plist")) {
+        return Err(Error::CopyError(format!("{}", e)));
+    }
+    let _ = delete_file_check(&plist);
+
+    // create an empty task folder and store yaml
+    create_dir_check(get_task_folder_name(&task_label))?;
+    update_yaml_in_meta(&yaml, &task_label)?;
+    write_provenance(&task_label, provenance)?;
+    let config = process_config(config, ConfigIntent::CreateNew)?;
+    set_desired_state(&task_label, Some(is_loaded))?;
+    if is_loaded {
+        place_plist_and_load(&config, LoadReason::Load)?;
+    }
+    Ok(())
+}
+
+///
+/// replace the files of an existing task with a new zip package.
+///
+/// The zip may contain a yaml with the same label, which replaces the stored yaml,
+/// or no yaml at all, in which case the stored yaml is kept. The old task folder
+/// is moved to trash with a timestamp suffix, and the output folder is untouched.
+/// The task is reloaded if it was loaded.
+///
+/// The zip is extracted into its own folder, so that an update never shares
+/// its files with an upload or another update.
+///
+pub fn update_task_files(task_label: &str, task_zip: &Path) -> Result<Vec<ChownWarning>, Error> {
+    let _operation = Operation::begin();
+    check_updatable(task_label)?;
+    let unzip_folder = &Path::new(TEMP_UNZIP_FOLDER).join(format!(
+        "{}.update.{}.{}",
+        task_label,
+        std::process::id(),
+        UPDATE_UNZIP_COUNT.fetch_add(1, AtomicOrdering::SeqCst)
+    ));
+    let _in_progress = InProgress::new(unzip_folder);
+    let updated = extract_archive(task_zip, unzip_folder, Env::get().max_uncompressed_bytes)
+        .and_then(|_| update_task_from_folder(task_label, unzip_folder));
+    if let Err(e) = try_to_remove_folder(unzip_folder) {
+        eprintln!("failed to remove `{}`: {:?}", unzip_folder.display(), e);
+    }
+    updated
+}
+
+fn check_updatable(task_label: &str) -> Result<(), Error> {
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            task_label
+        )));
+    }
+    check_managed(task_label)
+}
+
+///
+/// replace the files of an existing task with the files in `folder`,
+/// like `update_task_files` does with an extracted zip.
+///
+/// The new configuration is validated before the task is unloaded. The yaml is
+/// replaced only once the task folder is, and if either fails, the old folder
+/// is put back and the task is reloaded as it was.
+///
+pub fn update_task_from_folder(
+    task_label: &str,
+    folder: &Path,
+) -> Result<Vec<ChownWarning>, Error> {
+    let _operation = Operation::begin();
+    check_updatable(task_label)?;
+    let lock = yaml_lock(task_label);
+    let _locked = lock.lock().unwrap();
+
+    // check yaml in folder before touching any file of the task
+    let (new_yaml, task_root) = match find_yaml_file(folder) {
+        Ok((yaml, task_root)) => (Some(yaml), task_root),
+        Err(Error::YamlNotFound(_)) => (None, folder.to_path_buf()),
+        Err(e) => return Err(e),
+    };
+    let new_yaml_content = match &new_yaml {
+        Some(yaml) => match read_utf8_file(yaml) {
+            Ok(yaml_content) => Some(yaml_content),
+            Err(_) => {
+                return Err(Error::YamlError(
+                    "error reading yaml as utf8 text".to_string(),
+                ))
+            }
+        },
+        None => None,
+    };
+    let old_config = Configuration::from_yaml(&view_yaml(task_label)?)?;
+    let config = match &new_yaml_content {
+        Some(yaml_content) => Configuration::from_yaml(yaml_content)?,
+        None => old_config.clone(),
+    };
+    if !config.label.eq(task_label) {
+        return Err(Error::WrongLabelInYaml(format!(
+            "label `{}` must be `{}`",
+            config.label, task_label
+        )));
+    }
+    let config = process_config(config, ConfigIntent::Update)?;
+
+    // the yaml goes to meta folder, not to the task folder
+    if let Some(yaml) = &new_yaml {
+        if std::fs::remove_file(yaml).is_err() {
+            return Err(Error::ErrorMoveYamlToMeta(
+                "cannot delete old yaml".to_string(),
+            ));
+        }
+    }
+
+    let is_loaded = is_loaded(task_label)?;
+    if is_loaded {
+        unload_inner(task_label)?;
+    }
+
+    // replace task folder, keeping the old one in trash
+    let task_folder_name = get_task_folder_name(task_label);
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let trashed = Env::get()
+        .trash_dir
+        .join(format!("{}.{}", task_label, timestamp));
+    let swapped = move_to_trash(&task_folder_name, &trashed)
+        .and_then(|_| move_by_rename(&task_root, task_folder_name.as_path()))
+        .and_then(|_| match &new_yaml_content {
+            Some(yaml_content) => update_yaml_in_meta(yaml_content, &config.label),
+            None => Ok(()),
+        });
+    if let Err(e) = swapped {
+        restore_task_folder(&trashed, &task_folder_name);
+        if is_loaded {
+            let reloaded = process_config(old_config, ConfigIntent::Update)
+                .and_then(|old| place_plist_and_load(&old, LoadReason::Reload));
+            if let Err(reload_error) = reloaded {
+                eprintln!(
+                    "failed to reload `{}` after a failed update: {:?}",
+                    task_label, reload_error
+                );
+            }
+        }
+        return Err(e);
+    }
+    let (_, warnings) = chown_by_name_recursive_lossy(
+        task_folder_name.as_path(),
+        &config.user_name(),
+        &config.group_name(),
+    )?;
+
+    // reload task without clearing output
+    if is_loaded {
+        place_plist_and_load(&config, LoadReason::Reload)?;
+    }
+    Ok(warnings)
+}
+
+///
+/// put the task folder moved to `trashed` back to `task_folder`, replacing
+/// whatever a failed update left there
+///
+fn restore_task_folder(trashed: &Path, task_folder: &Path) {
+    if !trashed.exists() {
+        return;
+    }
+    let restored = try_to_remove_folder(task_folder).and_then(|_| {
+        move_by_rename(trashed, task_folder)
+            .or_else(|_| copy_folder(trashed, task_folder, true).map(|_| ()))
+    });
+    if let Err(e) = restored {
+        eprintln!(
+            "failed to restore `{}` from `{}`: {:?}",
+            task_folder.display(),
+            trashed.display(),
+            e
+        );
+    }
+}
+
+///
+/// find the position of yaml in zip package, searching at most `MAX_YAML_DEPTH` deep.
+///
+/// Returns the yaml and the task root folder. If the yaml is wrapped in single
+/// top-level folders (e.g. zip created by Finder's "Compress"), the folder containing
+/// the yaml is the task root, otherwise the unzipped folder is the task root.
+///
+fn find_yaml_file(unzipped_folder: &Path) -> Result<(PathBuf, PathBuf), Error> {
+    if unzipped_folder.read_dir().is_err() {
+        return Err(Error::YamlNotFound(
+            "cannot read unzipped folder".to_owned(),
+        ));
+    }
+    let mut yaml_files: Vec<PathBuf> = walkdir::WalkDir::new(unzipped_folder)
+        .max_depth(MAX_YAML_DEPTH)
+        .into_iter()
+        .filter_entry(|entry| !is_junk_path(entry.path()))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file() && entry.path().extension().unwrap_or_default().eq("yaml")
+        })
+        .map(|entry| entry.into_path())
+        .collect();
+    yaml_files.sort();
+    if yaml_files.len() > 1 {
+        return Err(Error::MultipleYamlFound(
+            yaml_files
+                .iter()
+                .map(|yaml| {
+                    yaml.strip_prefix(unzipped_folder)
+                        .unwrap_or(yaml)
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect(),
+        ));
+    }
+    let yaml = match yaml_files.pop() {
+        Some(yaml) => yaml,
+        None => return Err(Error::YamlNotFound("yaml not found".to_owned())),
+    };
+    let mut task_root = unzipped_folder.to_path_buf();
+    while let Some(wrapper) = single_wrapper_folder(&task_root) {
+        if !yaml.starts_with(&wrapper) {
+            break;
+        }
+        task_root = wrapper;
+    }
+    Ok((yaml, task_root))
+}
+
+///
+/// the only folder in `folder`, ignoring archive metadata
+///
+fn single_wrapper_folder(folder: &Path) -> Option<PathBuf> {
+    let mut entries = folder
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| !is_junk_path(path));
+    let only = entries.next()?;
+    if entries.next().is_none() && only.is_dir() {
+        Some(only)
+    } else {
+        None
+    }
+}
+
+///
+/// update yaml after editing yaml.
+/// Secrets still masked as `***` keep their stored values.
+/// A plist modified outside of tasker is not overwritten unless `force`.
+/// With `if_match`, the yaml is only written if the stored yaml still has this etag
+/// (see `yaml_etag`), or `*`.
+/// A loaded task is reloaded without running it because of `RunAtLoad` (see `LoadReason`).
+///
+pub fn update_yaml(
+    yaml_content: &str,
+    this_label: &str,
+    force: bool,
+    if_match: Option<&str>,
+    by: &str,
+) -> Result<Reload, Error> {
+    let _operation = Operation::begin();
+    check_managed(this_label)?;
+    let lock = yaml_lock(this_label);
+    let _locked = lock.lock().unwrap();
+    if let Some(expected) = if_match {
+        let current = etag_of(&view_yaml(this_label)?);
+        if expected != "*" && expected != current {
+            return Err(Error::YamlVersionMismatch(format!(
+                "yaml of `{}` was changed to version {}, not {}",
+                this_label, current, expected
+            )));
+        }
+    }
+    let keys = read_secrets(this_label);
+    let yaml_content = &if keys.is_empty() {
+        yaml_content.to_string()
+    } else {
+        restore_secret_yaml(yaml_content, &view_yaml(this_label)?, &keys)?
+    };
+    let mut config = Configuration::from_yaml(&yaml_content)?;
+    let label = &config.label.clone();
+
+    if !label.eq(this_label) {
+        return Err(Error::WrongLabelInYaml(format!(
+            "label `{}` must be `{}`",
+            label, this_label
+        )));
+    }
+
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+
+    if !force {
+        check_plist_untouched(label)?;
+    }
+
+    let is_loaded = is_loaded(label)?;
+
+    if is_loaded {
+        unload_inner(label)?;
+    }
+
+    // process configuration: view `process_config` documentation for detail
+    config = process_config(config, ConfigIntent::Update)?;
+
+    // move yaml in meta folder
+    update_yaml_in_meta(yaml_content, label)?;
+    record_edit(label, by)?;
+
+    // a task turned into a template stays unloaded
+    if is_loaded && config.is_template() {
+        try_remove_plist(label);
+        set_desired_state(label, Some(false))?;
+        return Ok(Reload::NotLoaded);
+    }
+
+    // place plist and load task
+    if !is_loaded {
+        return Ok(Reload::NotLoaded);
+    }
+    place_plist_and_load(&config, LoadReason::Reload)?;
+    if suppresses_run_at_load(&config, LoadReason::Reload) {
+        Ok(Reload::ReloadedWithoutRunAtLoad)
+    } else {
+        Ok(Reload::Reloaded)
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct YamlPreview {
+    yaml: String,
+    changes: Vec<ConfigChange>,
+    reload: bool,
+    warnings: Vec<String>,
+}
+
+///
+/// check a yaml like `update_yaml` and report what it would change, without
+/// writing anything or touching launchctl. `reload` tells whether the task is
+/// loaded and would be loaded again with the new yaml.
+/// Secrets are masked as `***` in the report.
+///
+pub fn preview_yaml(yaml_content: &str, this_label: &str) -> Result<YamlPreview, Error> {
+    let keys = read_secrets(this_label);
+    let stored_yaml = view_yaml(this_label)?;
+    let yaml_content = &restore_secret_yaml(yaml_content, &stored_yaml, &keys)?;
+    let config = Configuration::from_yaml(yaml_content)?;
+    let label = &config.label.clone();
+
+    if !label.eq(this_label) {
+        return Err(Error::WrongLabelInYaml(format!(
+            "label `{}` must be `{}`",
+            label, this_label
+        )));
+    }
+
+    // the part of `process_config` that does not touch the file system
+    let mut warnings = lint_config(&config);
+    transform_config(
+        config.clone(),
+        &get_task_folder_name(label),
+        &get_output_folder_name(label),
+    )?;
+    if let Err(e) = check_plist_untouched(label) {
+        warnings.push(format!("{:?}", e));
+    }
+
+    let stored = Configuration::from_yaml(&stored_yaml)?;
+    let changes = stored.redacted(&keys).diff(&config.redacted(&keys));
+    Ok(YamlPreview {
+        yaml: config.redacted(&keys).to_canonical_yaml()?,
+        changes,
+        reload: is_loaded(label)? && !config.is_template(),
+        warnings,
+    })
+}
+
+///
+/// a change of a single configuration of a task
+///
+pub enum ConfigPatch {
+    /// add or replace a configuration
+    Set(Config),
+    /// remove the configuration with this name, if it exists
+    Remove(String),
+    /// replace the scheduling configurations, see `Schedule::to_configs`
+    Schedule {
+        schedule: Schedule,
+        allow_both: bool,
+    },
+}
+
+///
+/// apply `patch` to the stored yaml of task `label`, and update the task like `update_yaml`.
+/// The yaml is only written if it has not changed since it was read,
+/// otherwise `patch` is applied again to the new yaml.
+///
+pub fn patch_config(label: &str, patch: ConfigPatch, by: &str) -> Result<Reload, Error> {
+    check_managed(label)?;
+    patch_stored_yaml(
+        &patch,
+        || view_yaml(label),
+        |yaml, etag| update_yaml(yaml, label, false, Some(etag), by),
+    )
+}
+
+///
+/// read the yaml with `read`, and `write` it patched with the etag it was read at,
+/// reading it again while `write` fails with `YamlVersionMismatch`
+///
+fn patch_stored_yaml<R, W>(patch: &ConfigPatch, read: R, write: W) -> Result<Reload, Error>
+where
+    R: Fn() -> Result<String, Error>,
+    W: Fn(&str, &str) -> Result<Reload, Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let stored = read()?;
+        let yaml = apply_patch(Configuration::parse_yaml(&stored)?, patch)?.to_yaml()?;
+        match write(&yaml, &etag_of(&stored)) {
+            Err(Error::YamlVersionMismatch(_)) if attempt < PATCH_ATTEMPTS => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+fn apply_patch(config: Configuration, patch: &ConfigPatch) -> Result<Configuration, Error> {
+    match patch {
+        ConfigPatch::Set(conf) => Ok(config.add_config(conf.clone())),
+        ConfigPatch::Remove(name) => {
+            if !CONFIG_NAMES.contains(&name.as_str()) {
+                return Err(Error::InvalidConfigPatch(format!(
+                    "unknown configuration `{}`, valid names are: {}",
+                    name,
+                    CONFIG_NAMES.join(", ")
+                )));
+            }
+            Ok(config.remove_config(name))
+        }
+        ConfigPatch::Schedule {
+            schedule,
+            allow_both,
+        } => config.with_schedule(schedule, *allow_both),
+    }
+}
+
+///
+/// the scheduling configurations of a task
+///
+pub fn view_schedule(label: &str) -> Result<Schedule, Error> {
+    Ok(Schedule::of(&Configuration::parse_yaml(&view_yaml(
+        label,
+    )?)?))
+}
+
+fn get_secrets_file(label_name: &str) -> PathBuf {
+    Env::get()
+        .meta_dir
+        .join(String::from(label_name) + SECRETS_EXTENSION)
+}
+
+///
+/// names of the sensitive `EnvironmentVariables` of a task,
+/// stored one per line in `<label>.secrets` in meta folder
+///
+pub fn read_secrets(task_label: &str) -> Vec<String> {
+    match read_utf8_file(&get_secrets_file(task_label)) {
+        Ok(s) => s
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn write_secrets(task_label: &str, keys: &BTreeSet<String>) -> Result<(), Error> {
+    let secrets_file = get_secrets_file(task_label);
+    let result = if keys.is_empty() {
+        if secrets_file.exists() {
+            std::fs::remove_file(&secrets_file).map_err(|e| e.to_string())
+        } else {
+            Ok(())
+        }
+    } else {
+        let content: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        std::fs::write(&secrets_file, content.join("\n") + "\n").map_err(|e| e.to_string())
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::FailedToUpdateSecrets(format!(
+            "cannot write {}: {}",
+            secrets_file.display(),
+            e
+        ))),
+    }
+}
+
+///
+/// mark (`secret = true`) or unmark an environment variable of a task as sensitive
+///
+pub fn mark_secret(task_label: &str, key: &str, secret: bool) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    if !exist(task_label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            task_label
+        )));
+    }
+    check_managed(task_label)?;
+    if !SECRET_KEY_REGEX.is_match(key) {
+        return Err(Error::FailedToUpdateSecrets(format!(
+            "`{}` is not a valid environment variable name",
+            key
+        )));
+    }
+    let mut keys: BTreeSet<String> = read_secrets(task_label).into_iter().collect();
+    if secret {
+        keys.insert(key.to_string());
+    } else {
+        keys.remove(key);
+    }
+    write_secrets(task_label, &keys)
+}
+
+///
+/// `yaml` of a task with the values of sensitive environment variables masked
+///
+fn redact_yaml(label: &str, yaml: String) -> Result<String, Error> {
+    let keys = read_secrets(label);
+    if keys.is_empty() {
+        return Ok(yaml);
+    }
+    Ok(Configuration::parse_yaml(&yaml)?.redacted_yaml(&keys))
+}
+
+///
+/// the yaml of a task together with the etag of the stored yaml,
+/// so that an edit can be posted back with `If-Match`
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct YamlVersion {
+    pub yaml: String,
+    pub etag: String,
+}
+
+///
+/// read the stored yaml once, and return it (redacted if `redact`) with its etag
+///
+pub fn view_yaml_version(label: &str, redact: bool) -> Result<YamlVersion, Error> {
+    let stored = view_yaml(label)?;
+    let etag = etag_of(&stored);
+    let yaml = if redact {
+        redact_yaml(label, stored)?
+    } else {
+        stored
+    };
+    Ok(YamlVersion { yaml, etag })
+}
+
+///
+/// the etag of the stored yaml of a task, a quoted SHA-256 of its content.
+/// Nothing is stored: it is hashed whenever it is read.
+///
+pub fn yaml_etag(label: &str) -> Result<String, Error> {
+    Ok(etag_of(&view_yaml(label)?))
+}
+
+fn etag_of(yaml: &str) -> String {
+    format!("\"{}\"", sha256_hex(yaml.as_bytes()))
+}
+
+///
+/// the lock held while the yaml of `label` is compared and written,
+/// so that two edits of the same version cannot both succeed
+///
+fn yaml_lock(label: &str) -> Arc<Mutex<()>> {
+    YAML_LOCKS
+        .lock()
+        .unwrap()
+        .entry(label.to_string())
+        .or_default()
+        .clone()
+}
+
+///
+/// keep the stored value of sensitive environment variables that are still masked in `yaml`,
+/// e.g. when a redacted yaml is edited and posted back
+///
+fn restore_secret_yaml(yaml: &str, previous: &str, keys: &[String]) -> Result<String, Error> {
+    if keys.is_empty() || !yaml.contains(SECRET_MASK) {
+        return Ok(yaml.to_string());
+    }
+    let mut config = Configuration::parse_yaml(yaml)?;
+    config.restore_secrets(&Configuration::parse_yaml(previous)?, keys);
+    config.to_canonical_yaml()
+}
+
+///
+/// create a new task `new_label` from the template task `template`:
+/// - `{{placeholder}}` in `ProgramArguments` and `EnvironmentVariables` are substituted
+/// - the task folder of the template is copied
+/// - the new task is not a template, and is created unloaded
+///
+pub fn create_from_template(
+    template: &str,
+    new_label: &str,
+    values: &BTreeMap<String, String>,
+    provenance: &Provenance,
+) -> Result<Vec<ChownWarning>, Error> {
+    let _operation = Operation::begin();
+    let config = Configuration::parse_yaml(&view_yaml(template)?)?;
+    if !config.is_template() {
+        return Err(Error::NotATemplate(format!(
+            "task `{}` is not a template",
+            template
+        )));
+    }
+    let short_label = new_label
+        .strip_prefix(&(String::from(TASKER_TASK_NAME) + "."))
+        .unwrap_or(new_label);
+    let mut config = config.fill_template(values)?;
+    config.label = short_label.to_string();
+    config.set_template(false);
+    let yaml = config.to_yaml()?;
+    let config = Configuration::from_yaml(&yaml)?;
+    let label = &config.label.clone();
+    if exist(label)? {
+        return Err(Error::FailedToCreateFromTemplate(format!(
+            "task `{}` already exists",
+            label
+        )));
+    }
+
+    // copy the files of the template, store yaml, and keep the task unloaded
+    let task_folder_name = get_task_folder_name(label);
+    let (user_name, group_name) = (config.user_name(), config.group_name());
+    let created = create_dir_check(&task_folder_name)
+        .and_then(|_| copy_folder(&get_task_folder_name(template), &task_folder_name, false))
+        .and_then(|_| {
+            chown_by_name_recursive_lossy(task_folder_name.as_path(), &user_name, &group_name)
+        })
+        .and_then(|(_, warnings)| {
+            update_yaml_in_meta(&yaml, label)?;
+            write_provenance(label, provenance)?;
+            process_config(config, ConfigIntent::CreateNew)?;
+            set_desired_state(label, Some(false))?;
+            Ok(warnings)
+        });
+    if created.is_err() {
+        discard_new_task(label);
+    }
+    created
+}
+
+///
+/// remove the task folder, yaml and provenance of a task whose creation failed,
+/// so that its label is free again
+///
+fn discard_new_task(label: &str) {
+    if let Err(e) = try_to_remove_folder(&get_task_folder_name(label)) {
+        eprintln!("failed to remove task folder of `{}`: {:?}", label, e);
+    }
+    let yaml_in_meta = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    if yaml_in_meta.exists() {
+        if let Err(e) = std::fs::remove_file(&yaml_in_meta) {
+            eprintln!("failed to remove yaml of `{}`: {}", label, e);
+        }
+        invalidate_task_labels();
+        let mut index = META_INDEX.lock().unwrap();
+        index.remove(label);
+        save_meta_index(&mut index);
+    }
+    let provenance = provenance_file(label);
+    if provenance.exists() {
+        if let Err(e) = std::fs::remove_file(&provenance) {
+            eprintln!("failed to remove provenance of `{}`: {}", label, e);
+        }
+    }
+}
+
+///
+/// a task created from a git repository
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct GitCreated {
+    label: String,
+    commit: String,
+    /// files whose ownership could not be changed
+    warnings: Vec<String>,
+}
+
+///
+/// create a new task from a shallow checkout of `git_ref` in the repository at `url`.
+/// The yaml is found in `subdir` if given, and `label` overrides the label in the yaml.
+/// The url, ref and commit are recorded in the provenance of the task.
+///
+pub fn create_from_git(
+    origin: GitOrigin,
+    label: Option<&str>,
+    provenance: Provenance,
+) -> Result<GitCreated, Error> {
+    let _operation = Operation::begin();
+    if let Some(label) = label {
+        check_new_label(label)?;
+    }
+    let checkout = GitCheckout::checkout(&origin.url, &origin.git_ref)?;
+    let folder = checkout.task_folder(&origin.subdir)?;
+    let commit = checkout.commit.clone();
+    let provenance = provenance.git(GitOrigin {
+        commit: commit.clone(),
+        ..origin
+    });
+    let (label, warnings) = create_task_from_folder(&folder, &provenance, label)?;
+    Ok(GitCreated {
+        label,
+        commit,
+        warnings: warnings.iter().map(|w| w.to_string()).collect(),
+    })
+}
+
+///
+/// a task refreshed from its git repository
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct GitRefresh {
+    label: String,
+    old_commit: String,
+    new_commit: String,
+    /// files whose ownership could not be changed
+    warnings: Vec<String>,
+}
+
+///
+/// check out the ref that task `label` was created from again, and replace
+/// its files like `update_task_files`. The yaml in the checkout, if any,
+/// must keep the label of the task.
+///
+pub fn refresh_from_git(label: &str, by: &str) -> Result<GitRefresh, Error> {
+    let _operation = Operation::begin();
+    check_updatable(label)?;
+    let origin = match read_provenance(label).and_then(|provenance| provenance.git) {
+        Some(origin) => origin,
+        None => {
+            return Err(Error::GitError(format!(
+                "task `{}` was not created from a git repository",
+                label
+            )))
+        }
+    };
+    let checkout = GitCheckout::checkout(&origin.url, &origin.git_ref)?;
+    let folder = checkout.task_folder(&origin.subdir)?;
+    let warnings = update_task_from_folder(label, &folder)?;
+    let old_commit = record_git_refresh(label, &checkout.commit, by)?.unwrap_or(origin.commit);
+    Ok(GitRefresh {
+        label: label.to_string(),
+        old_commit,
+        new_commit: checkout.commit.clone(),
+        warnings: warnings.iter().map(|w| w.to_string()).collect(),
+    })
+}
+
+///
+/// whether a yaml is marked `Template: true`, without checking the rest of it
+///
+fn yaml_is_template(yaml: &str) -> bool {
+    match serde_yaml::from_str::<serde_yaml::Value>(&normalize_yaml_input(yaml)) {
+        Ok(value) => value
+            .get("Template")
+            .and_then(|t| t.as_bool())
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+///
+/// expand the alias at the beginning of `path`, e.g. `~root~/run.sh` to
+/// `<task folder>/run.sh`. Aliases elsewhere in `path` are kept as they are.
+///
+fn expand_alias(path: &mut String, aliases: &[(&str, &Path)]) -> Result<(), Error> {
+    for (alias, target) in aliases {
+        if path.starts_with(alias) {
+            let alias_replaced = target.join(&path[alias.len()..]);
+            return if let Some(new_path) = alias_replaced.to_str() {
+                *path = new_path.to_string();
+                Ok(())
+            } else {
+                Err(Error::FailedToReplaceRootAlias(
+                    "failed to replace alias, do not use non-utf-8 character in path".to_string(),
+                ))
+            };
+        }
+    }
+    Ok(())
+}
+
+///
+/// this function expands `~root~/` to the task folder and `~out~/` to the output folder
+/// in every string of the configuration
+///
+fn expand_task_aliases(
+    config: &mut Configuration,
+    task_folder: &Path,
+    output_folder: &Path,
+) -> Result<(), Error> {
+    let aliases = [
+        (TASK_ROOT_ALIAS, task_folder),
+        (TASK_OUT_ALIAS, output_folder),
+    ];
+    for conf in &mut config.configuration {
+        match conf {
+            ProgramArguments(arguments) => {
+                for arg in arguments {
+                    expand_alias(arg, &aliases)?;
+                }
+            }
+            EnvironmentVariables(variables) => {
+                for value in variables.values_mut() {
+                    expand_alias(value, &aliases)?;
+                }
+            }
+            KeepAlive(keep_alive) => {
+                keep_alive.map_path_state(|path| expand_alias(path, &aliases))?;
+            }
+            WorkingDirectory(path)
+            | RootDirectory(path)
+            | StandardInPath(path)
+            | StandardOutPath(path)
+            | StandardErrorPath(path) => {
+                expand_alias(path, &aliases)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+///
+/// launchd passes `ProgramArguments` as argv, so argv[0] should be the program.
+/// Insert `Program` as the first argument if it is missing.
+///
+fn normalize_program_arguments(config: &mut Configuration) {
+    let program = config.program().to_string();
+    for conf in &mut config.configuration {
+        if let ProgramArguments(arguments) = conf {
+            if arguments.first() != Some(&program) {
+                arguments.insert(0, program.clone());
+            }
+        }
+    }
+}
+
+///
+/// likely mistakes in a configuration that are not errors:
+/// - the program seems to be duplicated at the beginning of the arguments
+/// - both `StartInterval` and `StartCalendarInterval` are set
+/// - `PATH` is replaced without `/usr/bin` and `/bin`, or `HOME` is not a directory
+/// - a variable refers to `$VAR`, which launchd does not expand
+/// - a variable of `InheritEnvironment` is set in `EnvironmentVariables`, or not set in
+///   the environment of tasker
+/// - `UserName` is not a member of `GroupName`, which launchd allows
+///
+fn lint_config(config: &Configuration) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let program = config.program();
+    for conf in config.configs() {
+        if let ProgramArguments(arguments) = conf {
+            if arguments.len() > 1 && arguments[0] == program && arguments[1] == program {
+                warnings.push(format!(
+                    "`{}` is duplicated at the beginning of ProgramArguments",
+                    program
+                ));
+            }
+        }
+    }
+    let schedule = Schedule::of(config);
+    if schedule.start_interval.is_some() && !schedule.calendar.is_empty() {
+        warnings.push("both `StartInterval` and `StartCalendarInterval` are set".to_string());
+    }
+    let environment = config.effective_environment();
+    if let Some(path) = environment.get("PATH") {
+        if !path
+            .split(':')
+            .any(|dir| dir == "/usr/bin" || dir == "/bin")
+        {
+            warnings.push(
+                "`PATH` replaces the system PATH without `/usr/bin` and `/bin`, \
+                 consider `InheritEnvironment: [PATH]`"
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(home) = environment.get("HOME") {
+        let aliased = home.starts_with(TASK_ROOT_ALIAS) || home.starts_with(TASK_OUT_ALIAS);
+        if !aliased && !Path::new(home).is_dir() {
+            warnings.push(format!("`HOME` `{}` is not a directory", home));
+        }
+    }
+    for (name, value) in &environment {
+        // only the reference is shown, the value may be a secret
+        if let Some(reference) = SHELL_VARIABLE_REGEX.find(value) {
+            warnings.push(format!(
+                "`{}` contains `{}`, which launchd does not expand",
+                name,
+                reference.as_str()
+            ));
+        }
+    }
+    for name in config.inherit_environment() {
+        if environment.contains_key(name) {
+            warnings.push(format!(
+                "`{}` is both inherited and set in EnvironmentVariables, the set value is used",
+                name
+            ));
+        } else if std::env::var(name).is_err() {
+            warnings.push(format!(
+                "`{}` of InheritEnvironment is not set in the environment of tasker",
+                name
+            ));
+        }
+    }
+    if let (Some(user), Some(group)) = (config.user_name(), config.group_name()) {
+        if user_in_group(&user, &group) == Some(false) {
+            warnings.push(format!(
+                "user `{}` is not a member of group `{}`",
+                user, group
+            ));
+        }
+    }
+    warnings
+}
+
+///
+/// what would go wrong with the stored yaml of a task on its next edit or load
+///
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct TaskLintReport {
+    label: String,
+    /// the yaml cannot be read or parsed, or is invalid anywhere
+    parse_error: Option<String>,
+    /// the yaml is refused by the checks against this machine,
+    /// e.g. the program no longer exists
+    validation_error: Option<String>,
+    warnings: Vec<String>,
+    /// the conflicts with other tasks involving this task, see `/conflicts`
+    conflicts: Vec<String>,
+}
+
+impl TaskLintReport {
+    pub fn has_errors(&self) -> bool {
+        self.parse_error.is_some() || self.validation_error.is_some()
+    }
+
+    pub fn has_problems(&self) -> bool {
+        self.has_errors() || !self.warnings.is_empty() || !self.conflicts.is_empty()
+    }
+}
+
+///
+/// check every yaml in meta folder like `Configuration::from_yaml`, and lint it,
+/// sorted by label, with its conflicts with other tasks.
+///
+pub fn lint_all() -> Result<Vec<TaskLintReport>, Error> {
+    let metas = stored_yaml_metas()?;
+    let env = Env::get();
+    let conflicts = config_conflicts(&runnable_configs(&metas), &env.task_dir, &env.out_dir);
+    Ok(metas
+        .into_iter()
+        .map(|(label, meta)| {
+            let mut report = lint_task(&label, meta);
+            report.conflicts = conflicts
+                .iter()
+                .filter(|conflict| conflict.labels.contains(&label))
+                .map(|conflict| conflict.detail.clone())
+                .collect();
+            report
+        })
+        .collect())
+}
+
+///
+/// the `YamlMeta` of every yaml in meta folder with its parsed yaml, sorted by label.
+/// Yaml just parsed for listing is not read again.
+///
+fn stored_yaml_metas() -> Result<Vec<(String, YamlMeta)>, Error> {
+    let mut labels = meta_labels(&LabelMatcher::all())?;
+    labels.sort();
+    let metas = labels
+        .into_iter()
+        .map(|label| {
+            let mut meta = yaml_meta(&label);
+            if meta.program.is_some() && meta.config.is_none() {
+                // from the index, which does not keep the parsed yaml
+                meta = read_yaml_meta(&label);
+            }
+            (label, meta)
+        })
+        .collect();
+    save_meta_index(&mut META_INDEX.lock().unwrap());
+    Ok(metas)
+}
+
+///
+/// the parsed yaml of tasks that launchd may run, i.e. not templates
+///
+fn runnable_configs(metas: &[(String, YamlMeta)]) -> Vec<(String, Configuration)> {
+    metas
+        .iter()
+        .filter(|(_, meta)| !meta.is_template)
+        .filter_map(|(label, meta)| Some((label.clone(), (**meta.config.as_ref()?).clone())))
+        .collect()
+}
+
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// both tasks run the same `Program` with the same `ProgramArguments`
+    SameCommand,
+    /// both tasks have the same entry in `StartCalendarInterval`
+    SameCalendarInterval,
+    /// the `WorkingDirectory` of the first task is in the task folder of the second
+    WorkingDirectoryInTask,
+}
+
+///
+/// two tasks likely to get in each other's way, e.g. writing the same output
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// the two tasks, sorted except for `working_directory_in_task`
+    pub labels: Vec<String>,
+    pub detail: String,
+}
+
+///
+/// conflicts between the tasks of all yaml files in meta folder, see `config_conflicts`
+///
+pub fn find_conflicts() -> Result<Vec<Conflict>, Error> {
+    let metas = stored_yaml_metas()?;
+    let env = Env::get();
+    Ok(config_conflicts(
+        &runnable_configs(&metas),
+        &env.task_dir,
+        &env.out_dir,
+    ))
+}
+
+///
+/// pairs of tasks among `configs` (sorted by label) that
+/// - run the same command after alias expansion,
+/// - share an entry of `StartCalendarInterval`, so that they start at the same time,
+/// - or work in the task folder of another task, by `WorkingDirectory` after alias
+///   expansion and symlinks resolved.
+///
+/// The task folders and output folders are in `task_dir` and `out_dir`.
+///
+fn config_conflicts(
+    configs: &[(String, Configuration)],
+    task_dir: &Path,
+    out_dir: &Path,
+) -> Vec<Conflict> {
+    let configs: Vec<(&String, Configuration)> = configs
+        .iter()
+        .filter_map(|(label, config)| {
+            let config =
+                transform_config(config.clone(), &task_dir.join(label), &out_dir.join(label));
+            Some((label, config.ok()?))
+        })
+        .collect();
+    let mut conflicts = Vec::new();
+
+    let mut by_command: BTreeMap<Vec<String>, Vec<&String>> = BTreeMap::new();
+    for (label, config) in &configs {
+        by_command
+            .entry(config.effective_command())
+            .or_default()
+            .push(*label);
+    }
+    for (command, labels) in &by_command {
+        for (i, first) in labels.iter().enumerate() {
+            for second in &labels[i + 1..] {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::SameCommand,
+                    labels: vec![first.to_string(), second.to_string()],
+                    detail: format!(
+                        "`{}` and `{}` both run `{}`",
+                        first,
+                        second,
+                        command.join(" ")
+                    ),
+                });
+            }
+        }
+    }
+
+    let mut by_interval: HashMap<&CalendarInterval, BTreeSet<&String>> = HashMap::new();
+    for (label, config) in &configs {
+        for conf in config.configs() {
+            if let Config::StartCalendarInterval(calendar) = conf {
+                for interval in calendar {
+                    by_interval.entry(interval).or_default().insert(*label);
+                }
+            }
+        }
+    }
+    let mut shared: BTreeMap<(&String, &String), Vec<String>> = BTreeMap::new();
+    for (interval, labels) in &by_interval {
+        let labels: Vec<&String> = labels.iter().copied().collect();
+        for (i, first) in labels.iter().enumerate() {
+            for second in &labels[i + 1..] {
+                shared
+                    .entry((*first, *second))
+                    .or_default()
+                    .push(serde_json::to_string(interval).unwrap_or_default());
+            }
+        }
+    }
+    for ((first, second), mut intervals) in shared {
+        intervals.sort();
+        conflicts.push(Conflict {
+            kind: ConflictKind::SameCalendarInterval,
+            labels: vec![first.to_string(), second.to_string()],
+            detail: format!(
+                "`{}` and `{}` both start at StartCalendarInterval {}",
+                first,
+                second,
+                intervals.join(", ")
+            ),
+        });
+    }
+
+    let task_folders: Vec<(&String, PathBuf)> = configs
+        .iter()
+        .map(|(label, _)| (*label, canonicalize_lossy(&task_dir.join(label))))
+        .collect();
+    for (label, config) in &configs {
+        let working_directory = match config.working_directory() {
+            Some(path) => canonicalize_lossy(Path::new(&path)),
+            None => continue,
+        };
+        for (other, folder) in &task_folders {
+            if other != label && working_directory.starts_with(folder) {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::WorkingDirectoryInTask,
+                    labels: vec![label.to_string(), other.to_string()],
+                    detail: format!(
+                        "`{}` works in `{}`, inside the task folder of `{}`",
+                        label,
+                        working_directory.display(),
+                        other
+                    ),
+                });
+            }
+        }
+    }
+    conflicts.sort_by(|a, b| (a.kind, &a.labels).cmp(&(b.kind, &b.labels)));
+    conflicts
+}
+
+fn lint_task(label: &str, meta: YamlMeta) -> TaskLintReport {
+    let mut report = TaskLintReport {
+        label: label.to_string(),
+        ..TaskLintReport::default()
+    };
+    let config = match (meta.error, meta.config) {
+        (None, Some(config)) => config,
+        (error, _) => {
+            report.parse_error = Some(error.unwrap_or_else(|| "yaml not found".to_string()));
+            return report;
+        }
+    };
+    match (*config).clone().validate(Validation::Lenient) {
+        Ok(lenient) => {
+            report.warnings = lint_config(&lenient);
+            if let Err(e) = (*config).clone().validate(Validation::Strict) {
+                report.validation_error = Some(format!("{:?}", e));
+            }
+        }
+        Err(e) => report.parse_error = Some(format!("{:?}", e)),
+    }
+    report
+}
+
+///
+/// copy the variables of `InheritEnvironment` from the environment of tasker
+///
+fn inherit_server_environment(config: Configuration) -> Configuration {
+    let environment = config
+        .inherit_environment()
+        .iter()
+        .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+        .collect::<BTreeMap<String, String>>();
+    config.inherit_environment_from(&environment)
+}
+
+///
+/// use task root as the default working directory, unless `RootDirectory` is set,
+/// in which case the working directory is relative to the chroot and left to launchd.
+///
+fn set_working_directory_as_root_alias(config: Configuration) -> Configuration {
+    for c in &config.configuration {
+        match c {
+            WorkingDirectory(_) | RootDirectory(_) => {
+                return config;
+            }
+            _ => {}
+        }
+    }
+    config.add_config(WorkingDirectory(TASK_ROOT_ALIAS.to_owned()))
+}
+
+///
+/// what a configuration is processed for
+///
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ConfigIntent {
+    /// a task that did not exist before
+    CreateNew,
+    /// an existing task, whose logs and artifacts are kept
+    Update,
+}
+
+///
+/// configuration is processed here:
+/// - refuse unknown user or group names
+/// - transform configuration (view `transform_config` documentation for detail)
+/// - trash a stale output folder of a new task
+/// - create and chown output folder
+///
+fn process_config(config: Configuration, intent: ConfigIntent) -> Result<Configuration, Error> {
+    let label = &config.label.clone();
+    // unknown accounts are refused before any file operation
+    run_as_ids(&config.user_name(), &config.group_name())?;
+    for warning in lint_config(&config) {
+        eprintln!("task `{}`: {}", label, warning);
+    }
+    let task_output_name = get_output_folder_name(label);
+    let config = transform_config(config, &get_task_folder_name(label), &task_output_name)?;
+
+    let trash = output_trash_folder_name(label, SystemTime::now());
+    prepare_output_folder(&task_output_name, &trash, intent)?;
+
+    // chown for out directory
+    chown_by_name_recursive(
+        task_output_name.as_path(),
+        &config.user_name(),
+        &config.group_name(),
+    )?;
+
+    Ok(config)
+}
+
+///
+/// transform configuration without touching the file system:
+/// - default working directory
+/// - replace root alias
+/// - insert program as the first program argument
+/// - copy `InheritEnvironment` variables from the environment of tasker
+/// - add stdout stderr path in output folder if not specified by user
+///
+fn transform_config(
+    mut config: Configuration,
+    task_folder: &Path,
+    output_folder: &Path,
+) -> Result<Configuration, Error> {
+    config = set_working_directory_as_root_alias(config);
+
+    // expand `~root~/` and `~out~/` aliases
+    expand_task_aliases(&mut config, task_folder, output_folder)?;
+
+    // argv[0] must be the program (after alias expansion)
+    normalize_program_arguments(&mut config);
+
+    // inherited values are taken as they are, without alias expansion
+    config = inherit_server_environment(config);
+
+    // add stdout stderr path unless specified by user
+    if config.standard_out_path().is_none() {
+        if let Some(std_out_file) = output_folder.join(STD_OUT_FILE).to_str() {
+            config = config.add_config(Config::StandardOutPath(std_out_file.to_string()));
+        } else {
+            return Err(Error::NonUtfError(
+                "non-utf8 character not supported in stdout/stderr path".to_string(),
+            ));
+        }
+    }
+    if config.standard_error_path().is_none() {
+        if let Some(std_err_file) = output_folder.join(STD_ERR_FILE).to_str() {
+            config = config.add_config(Config::StandardErrorPath(std_err_file.to_string()));
+        } else {
+            return Err(Error::NonUtfError(
+                "non-utf8 character not supported in stdout/stderr path".to_string(),
+            ));
+        }
+    }
+
+    Ok(config)
+}
+
+///
+/// move yaml file to meta folder
+///
+fn move_yaml_to_meta(yaml: &PathBuf, label: &String) -> Result<(), Error> {
+    match read_utf8_file(yaml) {
+        Ok(yaml_content) => update_yaml_in_meta(&yaml_content, label)?,
+        Err(_) => {
+            return Err(Error::ErrorMoveYamlToMeta(
+                "cannot copy yaml to meta folder".to_string(),
+            ))
+        }
+    }
+
+    match std::fs::remove_file(&yaml) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::ErrorMoveYamlToMeta(
+            "cannot delete old yaml".to_string(),
+        )),
+    }
+}
+
+///
+/// store yaml in meta folder in canonical form, so that the stored file only changes with
+/// its content
+///
+fn update_yaml_in_meta(yaml_content: &str, label: &String) -> Result<(), Error> {
+    let yaml_content = Configuration::canonicalize_yaml(yaml_content)?;
+    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    let created = !yaml_file.exists();
+    match std::fs::write(yaml_file.as_path(), &yaml_content) {
+        Ok(_) => {
+            if created {
+                invalidate_task_labels();
+            }
+            let mut index = META_INDEX.lock().unwrap();
+            index.insert(label, &yaml_content, parse_yaml_meta(Ok(&yaml_content)));
+            save_meta_index(&mut index);
+            Ok(())
+        }
+        Err(_) => Err(Error::FailedToUpdateMetaYaml(
+            "cannot write yaml".to_string(),
+        )),
+    }
+}
+
+///
+/// why a task is loaded by `place_plist_and_load`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoadReason {
+    /// an explicit load, a new task, or a rebuild, which runs a task with `RunAtLoad`
+    Load,
+    /// a reload to apply an edit, which must not run a task with `RunAtLoad`
+    Reload,
+}
+
+///
+/// how an edit of a task was applied
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reload {
+    /// the task is not loaded, and the edit applies to its next load
+    NotLoaded,
+    Reloaded,
+    /// reloaded without running the task although it has `RunAtLoad: true`
+    ReloadedWithoutRunAtLoad,
+}
+
+impl Reload {
+    ///
+    /// explain what happened to the task, for the response to an edit
+    ///
+    pub fn describe(self) -> &'static str {
+        match self {
+            Reload::NotLoaded => "the task is not loaded",
+            Reload::Reloaded => "the task is reloaded",
+            Reload::ReloadedWithoutRunAtLoad => {
+                "the task is reloaded without running it, `RunAtLoad` applies from its next load"
+            }
+        }
+    }
+}
+
+fn suppresses_run_at_load(config: &Configuration, reason: LoadReason) -> bool {
+    reason == LoadReason::Reload && Schedule::of(config).run_at_load == Some(true)
+}
+
+///
+/// the plist to load, and the plist to put back once loaded if it differs.
+/// On a `LoadReason::Reload`, a task with `RunAtLoad: true` is loaded with `RunAtLoad` off,
+/// and then the plist as configured is put back without reloading, for its next load.
+///
+fn plists_to_load(config: &Configuration, reason: LoadReason) -> (String, Option<String>) {
+    let plist = config.to_plist();
+    if suppresses_run_at_load(config, reason) {
+        let suppressed = config.clone().add_config(RunAtLoad(false)).to_plist();
+        (suppressed, Some(plist))
+    } else {
+        (plist, None)
+    }
+}
+
+///
+/// put plist into `/Library/LaunchDaemons` (or `/Library/LaunchAgents` for agents) and load task
+///
+fn place_plist_and_load(config: &Configuration, reason: LoadReason) -> Result<(), Error> {
+    let label = &config.label[..];
+    let (plist, configured) = plists_to_load(config, reason);
+    let domain = Domain::of(config.scope(), config.user_name().as_deref())?;
+    let path = get_plist_path(label, domain);
+    try_remove_plist(label);
+    write_plist(&path, &plist)?;
+    let on_disk = configured.as_ref().unwrap_or(&plist);
+    set_plist_checksum(label, Some(sha256_hex(on_disk.as_bytes())))?;
+    let loaded = reload_inner(label);
+    if let Some(configured) = &configured {
+        write_plist(&path, configured)?;
+    }
+    loaded
+}
+
+fn reload_inner(label: &str) -> Result<(), Error> {
+    if is_loaded(label)? {
+        unload_inner(label)?;
+    }
+    load_inner(label)
+}
+
+///
+/// write a plist with mode 644 regardless of the umask, owned by root:wheel if tasker runs as root
+///
+fn write_plist(path: &Path, plist: &str) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut plist_file = std::fs::File::create(path)
+        .map_err(|e| Error::ErrorCreatingPlist(format!("cannot create plist: {}", e)))?;
+    plist_file
+        .write_all(plist.as_bytes())
+        .map_err(|e| Error::ErrorCreatingPlist(format!("error writing plist: {}", e)))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(PLIST_MODE))
+        .map_err(|e| Error::ErrorCreatingPlist(format!("cannot set mode of plist: {}", e)))?;
+    if users::get_current_uid() == 0 {
+        // root:wheel
+        chown_by_id(path, 0, 0)?;
+    }
+    Ok(())
+}
+
+pub fn is_loaded(label: &str) -> Result<bool, Error> {
+    let task_list = launchctl_list(&LabelMatcher::substring(label))?;
+    for t in task_list {
+        if t.label.eq(label) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn is_running(label: &str) -> Result<bool, Error> {
+    let task_list = launchctl_list(&LabelMatcher::substring(label))?;
+    for t in task_list {
+        if t.label.eq(label) {
+            return Ok(t.pid.is_some());
+        }
+    }
+    Ok(false)
+}
+
+pub fn exist(label: &str) -> Result<bool, Error> {
+    let task_list = list_combined(&LabelMatcher::substring(label))?;
+    for t in task_list {
+        if t.label.eq(label) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+///
+/// This function provides an API by returning a JSON of `TaskInfo` returned by
+/// `list_combined` for tasks matching `matcher`, keeping only tasks in `statuses`
+/// (all tasks if empty).
+/// The last `stderr_tail` lines of stderr are included for tasks in `ERROR` status,
+/// and resource usage is included for running tasks if `include_usage`.
+///
+pub fn list(
+    matcher: &LabelMatcher,
+    statuses: &[Status],
+    stderr_tail: Option<usize>,
+    include_usage: bool,
+    include_next_run: bool,
+) -> Result<String, Error> {
+    let mut task_info = filter_by_status(list_combined(matcher)?, statuses);
+    if let Some(n) = stderr_tail {
+        for task in task_info.iter_mut() {
+            task.fill_stderr_tail(n);
+        }
+    }
+    if include_usage {
+        for task in task_info.iter_mut() {
+            if let Some(pid) = task.pid {
+                task.usage = process_usage(pid);
+            }
+        }
+    }
+    if include_next_run {
+        fill_next_runs(&mut task_info);
+    }
+    match serde_json::to_string_pretty(&task_info) {
+        Ok(s) => Ok(s),
+        Err(_) => {
+            return Err(Error::LaunchctlListError(
+                "list error: serialize error".parse().unwrap(),
+            ))
+        }
+    }
+}
+
+fn fill_next_runs(task_info: &mut [TaskInfo]) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    for task in task_info.iter_mut() {
+        let schedule = yaml_meta(&task.label).schedule;
+        task.fill_next_run(&schedule, now);
+    }
+}
+
+///
+/// the tasks of `list` (with their next run) as a plain text table `width` characters wide,
+/// see `render_task_table`
+///
+pub fn list_text(
+    matcher: &LabelMatcher,
+    statuses: &[Status],
+    width: usize,
+) -> Result<String, Error> {
+    let mut task_info = filter_by_status(list_combined(matcher)?, statuses);
+    fill_next_runs(&mut task_info);
+    Ok(render_task_table(&task_info, width))
+}
+
+///
+/// errors first, then running and loaded tasks, and unloaded tasks last
+///
+fn status_severity(status: Status) -> usize {
+    match status {
+        Status::ERROR => 0,
+        Status::RUNNING => 1,
+        Status::LOADED => 2,
+        Status::NORMAL => 3,
+        Status::UNLOADED => 4,
+    }
+}
+
+///
+/// shorten `text` to `max` characters by replacing its middle with `…`,
+/// so that both its beginning and its end stay visible
+///
+fn ellipsize_middle(text: &str, max: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let tail = (max - 1) / 2;
+    let head = max - 1 - tail;
+    let mut shortened: String = chars[..head].iter().collect();
+    shortened.push('…');
+    shortened.extend(&chars[chars.len() - tail..]);
+    shortened
+}
+
+///
+/// render tasks as a table of aligned columns (LABEL, STATUS, PID, EXIT, NEXT RUN), one task
+/// per line sorted by `status_severity` and then label, followed by a line counting the tasks
+/// of each status, e.g. `34 tasks: 20 running, 10 loaded, 3 unloaded, 1 error`.
+///
+/// Labels are shown without `com.tasker.tasks.`, and are shortened in the middle so that lines
+/// fit in `width` characters, down to `MIN_TEXT_LABEL_WIDTH`.
+///
+pub fn render_task_table(tasks: &[TaskInfo], width: usize) -> String {
+    let prefix = String::from(TASKER_TASK_NAME) + ".";
+    let mut sorted: Vec<&TaskInfo> = tasks.iter().collect();
+    sorted.sort_by(|a, b| {
+        status_severity(a.status)
+            .cmp(&status_severity(b.status))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    let optional = |value: Option<i32>| value.map_or("-".to_string(), |v| v.to_string());
+    let rows: Vec<[String; 5]> = sorted
+        .iter()
+        .map(|task| {
+            [
+                task.label
+                    .strip_prefix(&prefix)
+                    .unwrap_or(&task.label)
+                    .to_string(),
+                task.status.to_string(),
+                optional(task.pid),
+                optional(task.last_exit_status),
+                task.next_run_at.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    let header = [
+        "LABEL".to_string(),
+        "STATUS".to_string(),
+        "PID".to_string(),
+        "EXIT".to_string(),
+        "NEXT RUN".to_string(),
+    ];
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let others: usize = widths[1..].iter().sum::<usize>() + TEXT_COLUMN_GAP.len() * 4;
+    widths[0] = widths[0].min(width.saturating_sub(others).max(MIN_TEXT_LABEL_WIDTH));
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let label = ellipsize_middle(&row[0], widths[0]);
+        let line = [
+            format!("{:<w$}", label, w = widths[0]),
+            format!("{:<w$}", row[1], w = widths[1]),
+            format!("{:>w$}", row[2], w = widths[2]),
+            format!("{:>w$}", row[3], w = widths[3]),
+            row[4].clone(),
+        ]
+        .join(TEXT_COLUMN_GAP);
+        table += line.trim_end();
+        table.push('\n');
+    }
+    let counts: Vec<String> = ALL_STATUS
+        .iter()
+        .map(|status| {
+            let count = tasks.iter().filter(|task| task.status == *status).count();
+            (count, status.to_string().to_lowercase())
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, status)| format!("{} {}", count, status))
+        .collect();
+    let total = match tasks.len() {
+        1 => "1 task".to_string(),
+        n => format!("{} tasks", n),
+    };
+    if counts.is_empty() {
+        table += &total;
+    } else {
+        table += &format!("{}: {}", total, counts.join(", "));
+    }
+    table.push('\n');
+    table
+}
+
+///
+/// This function returns a JSON of task count of each status of tasks matching `matcher`,
+/// e.g. `{"running": 3, "error": 1, ...}`
+///
+pub fn list_count(matcher: &LabelMatcher, statuses: &[Status]) -> Result<String, Error> {
+    let task_info = filter_by_status(list_combined(matcher)?, statuses);
+    match serde_json::to_string_pretty(&count_by_status(&task_info)) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(Error::LaunchctlListError(
+            "list error: serialize error".to_string(),
+        )),
+    }
+}
+
+fn filter_by_status(task_info: Vec<TaskInfo>, statuses: &[Status]) -> Vec<TaskInfo> {
+    if statuses.is_empty() {
+        return task_info;
+    }
+    task_info
+        .into_iter()
+        .filter(|task| statuses.contains(&task.status))
+        .collect()
+}
+
+fn count_by_status(task_info: &[TaskInfo]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for status in &ALL_STATUS {
+        counts.insert(status.to_string().to_lowercase(), 0);
+    }
+    for task in task_info {
+        if let Some(count) = counts.get_mut(&task.status.to_string().to_lowercase()) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+///
+/// This function combines the result of `launchctl_list` and `library_daemons_list`
+///
+fn list_combined(matcher: &LabelMatcher) -> Result<Vec<TaskInfo>, Error> {
+    let mut launchctl_info = launchctl_list(matcher)?;
+    let meta_yaml_info = meta_yaml_list(matcher)?;
+    for task in meta_yaml_info {
+        if !launchctl_info.contains(&task) {
+            launchctl_info.insert(task);
+        }
+    }
+    let desired_state = read_desired_state(&get_desired_state_file());
+    let checksums = read_plist_checksums();
+    let mut task_info = Vec::new();
+    for mut task in launchctl_info {
+        task.fill_folder_metadata();
+        task.should_be_loaded = desired_state.get(&task.label).copied();
+        task.plist_tampered = plist_tampered(&task.label, &checksums);
+        task_info.push(task);
+    }
+    save_meta_index(&mut META_INDEX.lock().unwrap());
+    Ok(task_info)
+}
+
+///
+/// a change of a task between two snapshots of the task list
+///
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChangeEvent {
+    /// a task is new, or any of its fields changed
+    Changed { task: Box<TaskInfo> },
+    /// a task no longer exists
+    Removed { label: String },
+}
+
+///
+/// all tasks, as a set ordered by label for `diff_snapshots`
+///
+pub fn snapshot() -> Result<BTreeSet<TaskInfo>, Error> {
+    Ok(list_combined(&LabelMatcher::all())?.into_iter().collect())
+}
+
+///
+/// the changes turning snapshot `old` into snapshot `new`
+///
+pub fn diff_snapshots(old: &BTreeSet<TaskInfo>, new: &BTreeSet<TaskInfo>) -> Vec<ChangeEvent> {
+    let mut events = Vec::new();
+    for task in new {
+        // `TaskInfo` equals by label, so compare all fields here
+        let changed = match old.get(task) {
+            Some(previous) => !previous.same_state(task),
+            None => true,
+        };
+        if changed {
+            events.push(ChangeEvent::Changed {
+                task: Box::new(task.clone()),
+            });
+        }
+    }
+    for task in old {
+        if !new.contains(task) {
+            events.push(ChangeEvent::Removed {
+                label: task.label.clone(),
+            });
+        }
+    }
+    events
+}
+
+///
+/// This function obtains a list of tasks from the launchctl command and
+/// convert it into a Set of `TaskInfo`, including loaded agents.
+/// The list of all tasks is reused for `LAUNCHCTL_LIST_CACHE_MS`, see `ListCache`.
+///
+fn launchctl_list(matcher: &LabelMatcher) -> Result<BTreeSet<TaskInfo>, Error> {
+    let tasks = LIST_CACHE.lock().unwrap().get(
+        Instant::now(),
+        Env::get().launchctl_list_cache,
+        launchctl_list_all,
+    )?;
+    Ok(tasks
+        .into_iter()
+        .filter(|task| matcher.matches(&task.label))
+        .collect())
+}
+
+fn launchctl_list_all() -> Result<BTreeSet<TaskInfo>, Error> {
+    let matcher = LabelMatcher::all();
+    match launchctl(&["list"]) {
+        Ok(list_output) => {
+            let mut task_info = TaskInfo::from_str_filter(&list_output, &matcher);
+            task_info.extend(launchctl_agent_list(&matcher)?);
+            Ok(task_info)
+        }
+        Err(e @ Error::LaunchctlBusy(_)) => Err(e),
+        Err(e) => {
+            return Err(Error::LaunchctlListError(format!(
+                "failed to list file: {:?}",
+                e
+            )));
+        }
+    }
+}
+
+///
+/// agents are not in the `launchctl list` of root, so each agent in meta folder
+/// is looked up in the GUI session of its user, and skipped if it is not loaded there
+///
+fn launchctl_agent_list(matcher: &LabelMatcher) -> Result<Vec<TaskInfo>, Error> {
+    let mut agents = Vec::new();
+    for task in meta_yaml_list(matcher)? {
+        if let Ok(domain @ Domain::Gui(_)) = task_domain(&task.label) {
+            let target = domain.service_target(&task.label);
+            if let Ok(output) = launchctl(&["print", &target]) {
+                let (pid, last_exit_status) = parse_launchctl_print(&output);
+                agents.push(TaskInfo::loaded(task.label, pid, last_exit_status));
+            }
+        }
+    }
+    Ok(agents)
+}
+
+///
+/// `pid` and last exit status of an agent from `launchctl print gui/<uid>/<label>`,
+/// where they are at the first indentation level
+///
+fn parse_launchctl_print(output: &str) -> (Option<i32>, Option<i32>) {
+    let mut pid = None;
+    let mut last_exit_status = Some(0);
+    for line in output.lines() {
+        if !line.starts_with('\t') || line.starts_with("\t\t") {
+            continue;
+        }
+        let mut split = line.trim().splitn(2, " = ");
+        match (split.next(), split.next()) {
+            (Some("pid"), Some(value)) => pid = value.trim().parse::<i32>().ok(),
+            // e.g. `0`, `78: EX_CONFIG`, or `(never exited)`
+            (Some("last exit code"), Some(value)) => {
+                let code = value.split(':').next().unwrap_or("").trim();
+                last_exit_status = Some(code.parse::<i32>().unwrap_or(0));
+            }
+            _ => {}
+        }
+    }
+    (pid, last_exit_status)
+}
+
+///
+/// This function obtains a list of tasks from `/Library/LaunchDaemons` folder and
+/// convert it into a vector of `TaskInfo`
+///
+fn meta_yaml_list(matcher: &LabelMatcher) -> Result<Vec<TaskInfo>, Error> {
+    let labels = meta_labels(&LabelMatcher::all())?;
+    // forget yaml removed behind the back of tasker
+    META_INDEX.lock().unwrap().retain(&labels);
+    Ok(labels
+        .iter()
+        .filter(|label| matcher.matches(label))
+        .map(|label| TaskInfo::from_just_label(label))
+        .collect())
+}
+
+///
+/// labels of the yaml files in meta folder matching `matcher`
+///
+fn meta_labels(matcher: &LabelMatcher) -> Result<Vec<String>, Error> {
+    yaml_labels_in(&Env::get().meta_dir, matcher)
+}
+
+///
+/// labels of the yaml files in `meta_directory` matching `matcher`.
+/// A file name that is not valid UTF-8 is skipped with a warning,
+/// as it cannot be the yaml of a task created by tasker.
+///
+fn yaml_labels_in(meta_directory: &Path, matcher: &LabelMatcher) -> Result<Vec<String>, Error> {
+    if let Ok(dir) = meta_directory.read_dir() {
+        let mut labels: Vec<String> = Vec::new();
+        for file in dir {
+            if let Ok(f) = file {
+                let path = f.path();
+                if let Some(file_name) = f.file_name().to_str() {
+                    if path.is_file()
+                        && path.extension().unwrap_or_default().eq("yaml")
+                        && file_name.contains(TASKER_TASK_NAME)
+                    {
+                        if let Some(cap) = LABEL_REGEX.captures(file_name) {
+                            if cap.len() == 2 {
+                                if matcher.matches(&cap[1]) {
+                                    labels.push(cap[1].to_string());
+                                }
+                            } else {
+                                return Err(Error::FailedToReadMetaFolder(String::from(
+                                    "fail to find label in yaml file name",
+                                )));
+                            }
+                        } else {
+                            return Err(Error::FailedToReadMetaFolder(String::from(
+                                "fail to capture label in yaml file name",
+                            )));
+                        }
+                    }
+                } else {
+                    eprintln!(
+                        "skipped file with unsupported character in name: {}",
+                        f.file_name().to_string_lossy()
+                    );
+                }
+            } else {
+                return Err(Error::FailedToReadMetaFolder(
+                    String::from("cannot get file info in: ") + &meta_directory.to_string_lossy(),
+                ));
+            }
+        }
+        Ok(labels)
+    } else {
+        Err(Error::FailedToReadMetaFolder(
+            String::from("cannot list file in: ") + &meta_directory.to_string_lossy(),
+        ))
+    }
+}
+
+///
+/// the last list of all loaded tasks, reused until it is older than the max age
+/// or invalidated.
+/// The cache stays locked while listing, so that concurrent requests share one
+/// `launchctl list`, and an invalidation after loading or unloading a task waits for
+/// a listing in progress instead of being overwritten by its stale result.
+///
+#[derive(Default)]
+struct ListCache {
+    listed: Option<(Instant, BTreeSet<TaskInfo>)>,
+}
+
+impl ListCache {
+    fn get<F>(
+        &mut self,
+        now: Instant,
+        max_age: Duration,
+        list: F,
+    ) -> Result<BTreeSet<TaskInfo>, Error>
+    where
+        F: FnOnce() -> Result<BTreeSet<TaskInfo>, Error>,
+    {
+        if let Some((at, tasks)) = &self.listed {
+            if now.duration_since(*at) < max_age {
+                return Ok(tasks.clone());
+            }
+        }
+        let tasks = list()?;
+        self.listed = Some((now, tasks.clone()));
+        Ok(tasks)
+    }
+
+    fn invalidate(&mut self) {
+        self.listed = None;
+    }
+}
+
+///
+/// must be called after a task is loaded, unloaded, signaled or restarted,
+/// so that the next `launchctl_list` sees the change
+///
+pub fn invalidate_launchctl_list() {
+    LIST_CACHE.lock().unwrap().invalidate();
+}
+
+///
+/// sorted labels, read again only after `invalidate`
+///
+#[derive(Default)]
+struct LabelCache {
+    labels: Option<Vec<String>>,
+}
+
+impl LabelCache {
+    fn get<F>(&mut self, read: F) -> Result<Vec<String>, Error>
+    where
+        F: FnOnce() -> Result<Vec<String>, Error>,
+    {
+        if let Some(labels) = &self.labels {
+            return Ok(labels.clone());
+        }
+        let mut labels = read()?;
+        labels.sort();
+        self.labels = Some(labels.clone());
+        Ok(labels)
+    }
+
+    fn invalidate(&mut self) {
+        self.labels = None;
+    }
+}
+
+///
+/// labels of all tasks managed by tasker, from the yaml files in meta folder.
+/// They are cached until a task is created or deleted, so this does not call launchctl
+/// and only lists the meta folder once per change.
+///
+pub fn task_labels() -> Result<Vec<String>, Error> {
+    LABEL_CACHE
+        .lock()
+        .unwrap()
+        .get(|| meta_labels(&LabelMatcher::all()))
+}
+
+///
+/// must be called after a yaml is added to or removed from meta folder
+///
+fn invalidate_task_labels() {
+    LABEL_CACHE.lock().unwrap().invalidate();
+}
+
+///
+/// a label that a new task can take
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct NewLabel {
+    /// like `com.tasker.tasks.backup`
+    label: String,
+    /// `Label` in the yaml, like `backup`
+    short_label: String,
+}
+
+///
+/// check the label of a new task, given with or without `com.tasker.tasks.`,
+/// `TaskAlreadyExists` if a task has it
+///
+pub fn check_new_label(label: &str) -> Result<NewLabel, Error> {
+    let prefix = String::from(TASKER_TASK_NAME) + ".";
+    let short_label = label.strip_prefix(&prefix).unwrap_or(label);
+    check_label_str(short_label)?;
+    let label = prefix + short_label;
+    if task_labels()?.contains(&label) {
+        return Err(Error::TaskAlreadyExists(format!(
+            "task `{}` already exists",
+            label
+        )));
+    }
+    Ok(NewLabel {
+        label,
+        short_label: short_label.to_string(),
+    })
+}
+
+///
+/// an executable that a new task can run
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct ProgramCheck {
+    program: String,
+    /// permission bits in octal, like `755`
+    mode: String,
+    /// the owner of the file, a suggestion for `UserName`
+    owner: Option<String>,
+    group: Option<String>,
+}
+
+///
+/// check that `program` is an absolute path of an executable file, and find its owner
+///
+pub fn check_new_program(program: &str) -> Result<ProgramCheck, Error> {
+    use std::os::unix::fs::MetadataExt;
+    check_program_str(program, Validation::Strict)?;
+    let metadata = std::fs::metadata(program).map_err(|e| {
+        Error::ConfigProgramError(format!("cannot read program `{}`: {}", program, e))
+    })?;
+    let mode = metadata.mode() & 0o7777;
+    if mode & 0o111 == 0 {
+        return Err(Error::ConfigProgramError(format!(
+            "program `{}` is not executable (mode {:o})",
+            program, mode
+        )));
+    }
+    Ok(ProgramCheck {
+        program: program.to_string(),
+        mode: format!("{:o}", mode),
+        owner: users::get_user_by_uid(metadata.uid())
+            .map(|user| user.name().to_string_lossy().to_string()),
+        group: users::get_group_by_gid(metadata.gid())
+            .map(|group| group.name().to_string_lossy().to_string()),
+    })
+}
+
+pub fn view_yaml(label: &str) -> Result<String, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(
+            "attempting to view yaml of non-existent tasks".to_string(),
+        ));
+    }
+    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+    match read_utf8_file(yaml_file.as_path()) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(Error::NonUtfError(format!(
+            "cannot find or read yaml file: {:?}",
+            e
+        ))),
+    }
+}
+
+///
+/// the plist that tasker generates from the stored yaml, without side effects,
+/// with secrets masked if `redact`
+///
+pub fn view_plist(label: &str, redact: bool) -> Result<String, Error> {
+    let config = Configuration::from_yaml(&view_yaml(label)?)?;
+    let config = transform_config(
+        config,
+        &get_task_folder_name(label),
+        &get_output_folder_name(label),
+    )?;
+    Ok(config.redacted(&secret_keys(label, redact)).to_plist())
+}
+
+///
+/// the marked secrets of `label` if `redact`, otherwise nothing to mask
+///
+fn secret_keys(label: &str, redact: bool) -> Vec<String> {
+    if redact {
+        read_secrets(label)
+    } else {
+        Vec::new()
+    }
+}
+
+///
+/// `plist` with the values of `EnvironmentVariables` in `keys` replaced by `***`
+///
+fn redact_plist(plist: &str, keys: &[String]) -> Result<String, Error> {
+    if keys.is_empty() {
+        return Ok(plist.to_string());
+    }
+    let mut value = match plist::Value::from_reader_xml(plist.as_bytes()) {
+        Ok(value) => value,
+        Err(e) => return Err(Error::PlistParseError(format!("cannot parse plist: {}", e))),
+    };
+    if let Some(vars) = value
+        .as_dictionary_mut()
+        .and_then(|dict| dict.get_mut("EnvironmentVariables"))
+        .and_then(|vars| vars.as_dictionary_mut())
+    {
+        for (key, value) in vars.iter_mut() {
+            if keys.contains(key) {
+                *value = plist::Value::String(SECRET_MASK.to_string());
+            }
+        }
+    }
+    let mut buf = Vec::new();
+    match plist::to_writer_xml(&mut buf, &value) {
+        Ok(_) => Ok(String::from_utf8_lossy(&buf).to_string()),
+        Err(e) => Err(Error::PlistParseError(format!("cannot write plist: {}", e))),
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EffectiveCommand {
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+    working_directory: Option<String>,
+    user: Option<String>,
+    group: Option<String>,
+    shell: String,
+}
+
+///
+/// what launchd executes for task `label`, after alias expansion,
+/// with a shell one-liner reproducing the run, and secrets masked if `redact`
+///
+pub fn effective_command(label: &str, redact: bool) -> Result<EffectiveCommand, Error> {
+    let config = Configuration::from_yaml(&view_yaml(label)?)?;
+    let config = transform_config(
+        config,
+        &get_task_folder_name(label),
+        &get_output_folder_name(label),
+    )?;
+    Ok(command_of(&config.redacted(&secret_keys(label, redact))))
+}
+
+fn command_of(config: &Configuration) -> EffectiveCommand {
+    let argv = config.effective_command();
+    let env = config.effective_environment();
+    let working_directory = config.working_directory();
+    let user = config.user_name();
+    let group = config.group_name();
+    let shell = shell_command(
+        &argv,
+        &env,
+        working_directory.as_deref(),
+        user.as_deref(),
+        group.as_deref(),
+    );
+    EffectiveCommand {
+        argv,
+        env,
+        working_directory,
+        user,
+        group,
+        shell,
+    }
+}
+
+///
+/// run task `label` once now outside of launchd, with `extra_args` appended to its command
+/// and `env_overrides` added to its environment. It runs as launchd would run it (user,
+/// group, working directory), but writes to timestamped files in `manual/` of the output
+/// folder. The overrides are not stored. A task running under launchd is refused unless `force`.
+///
+pub fn run_once(
+    label: &str,
+    extra_args: &[String],
+    env_overrides: &BTreeMap<String, String>,
+    force: bool,
+) -> Result<ManualRun, Error> {
+    let yaml = view_yaml(label)?;
+    check_managed(label)?;
+    let config = Configuration::from_yaml(&yaml)?;
+    if config
+        .configs()
+        .iter()
+        .any(|conf| matches!(conf, RootDirectory(_)))
+    {
+        return Err(Error::FailedToRunOnce(format!(
+            "`{}` runs in a chroot (`RootDirectory`), which only launchd can run",
+            label
+        )));
+    }
+    if !force && is_running(label)? {
+        return Err(Error::TaskIsRunning(format!(
+            "`{}` is running under launchd, use force=true to run it anyway",
+            label
+        )));
+    }
+    let output_folder = get_output_folder_name(label);
+    let config = transform_config(config, &get_task_folder_name(label), &output_folder)?;
+    let mut argv = config.effective_command();
+    argv.extend(extra_args.iter().cloned());
+    let mut env = config.effective_environment();
+    env.extend(env_overrides.clone());
+    let (uid, gid) = run_as_ids(&config.user_name(), &config.group_name())?;
+
+    let manual_folder = output_folder.join(MANUAL_RUN_FOLDER);
+    create_dir_check(&manual_folder)?;
+    let now = SystemTime::now();
+    let stem = format_rfc3339(now).unwrap_or_default().replace(':', "-");
+    let stdout = unused_path(&manual_folder.join(stem.clone() + ".stdout.log"), now);
+    let stderr = unused_path(&manual_folder.join(stem + ".stderr.log"), now);
+    let create = |path: &Path| match File::create(path) {
+        Ok(file) => Ok(file),
+        Err(e) => Err(Error::FailedToRunOnce(format!(
+            "cannot create `{}`: {}",
+            path.display(),
+            e
+        ))),
+    };
+    let mut command = Command::new(&argv[0]);
+    command
+        .args(&argv[1..])
+        .env_clear()
+        .env("PATH", LAUNCHD_DEFAULT_PATH)
+        .envs(&env)
+        .current_dir(
+            config
+                .working_directory()
+                .unwrap_or_else(|| "/".to_string()),
+        )
+        .stdin(Stdio::null())
+        .stdout(create(&stdout)?)
+        .stderr(create(&stderr)?);
+    if let Some(gid) = gid {
+        command.gid(gid);
+    }
+    if let Some(uid) = uid {
+        command.uid(uid);
+    }
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            return Err(Error::FailedToRunOnce(format!(
+                "cannot start `{}`: {}",
+                argv[0], e
+            )))
+        }
+    };
+    let relative = |path: &Path| {
+        let path = path.strip_prefix(&output_folder).unwrap_or(path);
+        path.to_string_lossy().to_string()
+    };
+    Ok(ManualRun::new(
+        label,
+        child,
+        relative(&stdout),
+        relative(&stderr),
+    ))
+}
+
+///
+/// `cd <dir> && sudo -u <user> -g <group> env <K=V>... <argv>...` with every part shell-quoted
+///
+fn shell_command(
+    argv: &[String],
+    env: &BTreeMap<String, String>,
+    working_directory: Option<&str>,
+    user: Option<&str>,
+    group: Option<&str>,
+) -> String {
+    let mut parts = Vec::new();
+    if let Some(dir) = working_directory {
+        parts.push(format!("cd {} &&", shell_quote(dir)));
+    }
+    if user.is_some() || group.is_some() {
+        parts.push("sudo".to_string());
+        if let Some(user) = user {
+            parts.push(format!("-u {}", shell_quote(user)));
+        }
+        if let Some(group) = group {
+            parts.push(format!("-g {}", shell_quote(group)));
+        }
+    }
+    if !env.is_empty() {
+        parts.push("env".to_string());
+        for (key, value) in env {
+            parts.push(shell_quote(&format!("{}={}", key, value)));
+        }
+    }
+    parts.extend(argv.iter().map(|arg| shell_quote(arg)));
+    parts.join(" ")
+}
+
+///
+/// the plist currently placed in `/Library/LaunchDaemons` or `/Library/LaunchAgents`,
+/// with secrets masked if `redact`
+///
+pub fn view_plist_on_disk(label: &str, redact: bool) -> Result<String, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(
+            "attempting to view plist of non-existent tasks".to_string(),
+        ));
+    }
+    let plist = plist_on_disk(label).unwrap_or_else(|| get_plist_path(label, Domain::System));
+    match read_utf8_file(&plist) {
+        Ok(s) => redact_plist(&s, &secret_keys(label, redact)),
+        Err(_) => Err(Error::PlistNotFound(format!(
+            "plist of task `{}` is not found on disk, the task might be unloaded",
+            label
+        ))),
+    }
+}
+
+///
+/// difference between the plist on disk and the plist generated from the stored yaml
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlistDiff {
+    loaded: bool,
+    plist_on_disk: bool,
+    in_sync: bool,
+    diff: String,
+}
+
+///
+/// compare the plist generated from the yaml in meta folder with the plist on disk.
+/// A loaded task that is not `in_sync` needs to be reloaded to apply the yaml.
+/// With `redact`, secrets are masked in `diff`, but still compared for `in_sync`.
+///
+pub fn diff_plist(label: &str, redact: bool) -> Result<PlistDiff, Error> {
+    let generated = view_plist(label, false)?;
+    let on_disk = match view_plist_on_disk(label, false) {
+        Ok(s) => Some(s),
+        Err(Error::PlistNotFound(_)) => None,
+        Err(e) => return Err(e),
+    };
+    plist_diff(
+        is_loaded(label)?,
+        on_disk.as_deref(),
+        &generated,
+        &secret_keys(label, redact),
+    )
+}
+
+fn plist_diff(
+    loaded: bool,
+    on_disk: Option<&str>,
+    generated: &str,
+    keys: &[String],
+) -> Result<PlistDiff, Error> {
+    let in_sync = match on_disk {
+        Some(plist) => unified_diff(plist, generated, "disk", "generated", 3).is_empty(),
+        None => false,
+    };
+    let on_disk_redacted = match on_disk {
+        Some(plist) => redact_plist(plist, keys)?,
+        None => String::new(),
+    };
+    let diff = unified_diff(
+        &on_disk_redacted,
+        &redact_plist(generated, keys)?,
+        "disk",
+        "generated",
+        3,
+    );
+    Ok(PlistDiff {
+        loaded,
+        plist_on_disk: on_disk.is_some(),
+        in_sync,
+        diff,
+    })
+}
+
+///
+/// resource usage of a task, `None` if the task is not running
+/// or has exited before `ps` is called
+///
+pub fn resource_usage(label: &str) -> Result<Option<ResourceUsage>, Error> {
+    let task_list = launchctl_list(&LabelMatcher::substring(label))?;
+    for t in task_list {
+        if t.label.eq(label) {
+            return Ok(t.pid.and_then(process_usage));
+        }
+    }
+    Err(Error::TaskDoesNotExist(format!(
+        "task with label `{}` is not loaded",
+        label
+    )))
+}
+
+///
+/// sum the usage of a process and its descendants (at most `MAX_USAGE_PROCESSES`),
+/// processes that have exited are skipped
+///
+fn process_usage(pid: i32) -> Option<ResourceUsage> {
+    let mut usage = ps_usage(pid)?;
+    let mut pending = child_pids(pid);
+    while let Some(child) = pending.pop() {
+        if usage.process_count >= MAX_USAGE_PROCESSES {
+            break;
+        }
+        if let Some(child_usage) = ps_usage(child) {
+            usage.rss_kb += child_usage.rss_kb;
+            usage.cpu_percent += child_usage.cpu_percent;
+            usage.process_count += 1;
+            pending.extend(child_pids(child));
+        }
+    }
+    Some(usage)
+}
+
+fn ps_usage(pid: i32) -> Option<ResourceUsage> {
+    let output =
+        run_command(Command::new("ps").args(["-o", "rss=,pcpu=,etime=", "-p", &pid.to_string()]))
+            .ok()?;
+    parse_ps_output(&output)
+}
+
+///
+/// `pgrep` exits with 1 if there is no child process
+///
+fn child_pids(pid: i32) -> Vec<i32> {
+    match run_command(Command::new("pgrep").args(["-P", &pid.to_string()])) {
+        Ok(output) => parse_pgrep_output(&output),
+        Err(_) => Vec::new(),
+    }
+}
+
+///
+/// parse output of `ps -o rss=,pcpu=,etime=` such as `  2048   1.5    01:02:03`
+///
+fn parse_ps_output(output: &str) -> Option<ResourceUsage> {
+    let mut split = output.lines().next()?.split_whitespace();
+    let rss_kb = split.next()?.parse::<u64>().ok()?;
+    // some locales use decimal comma
+    let cpu_percent = split.next()?.replace(',', ".").parse::<f64>().ok()?;
+    let elapsed = split.next()?.to_string();
+    Some(ResourceUsage {
+        rss_kb,
+        cpu_percent,
+        elapsed,
+        process_count: 1,
+    })
+}
+
+fn parse_pgrep_output(output: &str) -> Vec<i32> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().parse::<i32>().ok())
+        .collect()
+}
+
+///
+/// the last `limit` lines of stderr containing `pattern`, only lines between
+/// `since` and `until` (milliseconds since epoch) if either is given
+///
+pub fn view_std_err(
+    label: &str,
+    limit: usize,
+    pattern: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<String, Error> {
+    let std_err_file = get_std_err_file(label);
+    match read_log(&std_err_file, limit, pattern, since, until) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(Error::NonUtfError(format!(
+            "task `{}` has not been created or its stderr has not been created: {:?}",
+            label, e
+        ))),
+    }
+}
+
+///
+/// the last `limit` lines of stdout, like `view_std_err`
+///
+pub fn view_std_out(
+    label: &str,
+    limit: usize,
+    pattern: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<String, Error> {
+    let std_out_file = get_std_out_file(label);
+    match read_log(&std_out_file, limit, pattern, since, until) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(Error::NonUtfError(format!(
+            "task `{}` has not been created or its stdout has not been created: {:?}",
+            label, e
+        ))),
+    }
+}
+
+fn read_log(
+    file: &Path,
+    limit: usize,
+    pattern: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> std::io::Result<String> {
+    let files = with_rotated_files(file);
+    if since.is_none() && until.is_none() {
+        read_last_n_lines_multi(&files, limit, pattern)
+    } else {
+        read_lines_in_time_range_multi(&files, since, until, pattern, limit)
+    }
+}
+
+///
+/// the output file followed by its existing rotated files, from newest to oldest
+///
+fn with_rotated_files(file: &Path) -> Vec<PathBuf> {
+    let mut files = vec![file.to_path_buf()];
+    for index in 1..=LOG_KEEP_COUNT {
+        let rotated = rotated_file_name(file, index);
+        if !rotated.exists() {
+            break;
+        }
+        files.push(rotated);
+    }
+    files
+}
+///
+/// a file or folder in a task folder
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FileEntry {
+    path: String,
+    size_bytes: u64,
+    modified_at: Option<String>,
+    is_dir: bool,
+}
+
+///
+/// files and folders in the task folder, with paths relative to it.
+/// Symlinks are listed but not followed.
+///
+pub fn list_task_files(label: &str) -> Result<Vec<FileEntry>, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+    Ok(list_files(&get_task_folder_name(label), |_| true))
+}
+
+///
+/// entries in `folder` for which `keep` is true, with paths relative to it
+///
+fn list_files<F>(folder: &Path, keep: F) -> Vec<FileEntry>
+where
+    F: Fn(&walkdir::DirEntry) -> bool,
+{
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(folder)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .take(MAX_TASK_SIZE_ENTRIES)
+        .filter(|e| keep(e))
+    {
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        let path = match entry.path().strip_prefix(folder) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(_) => continue,
+        };
+        files.push(FileEntry {
+            path,
+            size_bytes: meta.len(),
+            modified_at: meta.modified().ok().and_then(format_rfc3339),
+            is_dir: meta.is_dir(),
+        });
+    }
+    files
+}
+
+///
+/// logs of a task in the output folder, which are not artifacts
+///
+fn log_files(label: &str) -> Vec<PathBuf> {
+    std_log_files(label).to_vec()
+}
+
+///
+/// files produced by a task in its output folder, except logs,
+/// with paths relative to the output folder
+///
+pub fn list_artifacts(label: &str) -> Result<Vec<FileEntry>, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+    let logs = log_files(label);
+    Ok(list_files(&get_output_folder_name(label), |entry| {
+        entry.file_type().is_file() && !is_log_file(entry.path(), &logs)
+    }))
+}
+
+///
+/// the artifact at relative `path` in the output folder, which must stay inside of it
+///
+pub fn artifact_file(label: &str, path: &str) -> Result<PathBuf, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+    let output_folder = get_output_folder_name(label);
+    let file = resolve_inside(&output_folder, path)?;
+    // compare the resolved path, so that `./stdout.log` or links to logs are caught
+    let is_log = match output_folder
+        .canonicalize()
+        .ok()
+        .and_then(|root| file.strip_prefix(root).ok().map(Path::to_path_buf))
+    {
+        Some(relative) => is_log_file(&output_folder.join(relative), &log_files(label)),
+        None => true,
+    };
+    if !file.is_file() || is_log {
+        return Err(Error::PathDoesNotExist(format!(
+            "`{}` is not an artifact",
+            path
+        )));
+    }
+    Ok(file)
+}
+
+///
+/// delete the artifact at relative `path` in the output folder
+///
+pub fn delete_artifact(label: &str, path: &str) -> Result<(), Error> {
+    let _operation = Operation::begin();
+    let file = artifact_file(label, path)?;
+    check_managed(label)?;
+    delete_file_check(file)
+}
+
+///
+/// zip the output folder of a task without logs
+///
+pub fn get_artifacts_zip(label: &str, method: zip::CompressionMethod) -> Result<PathBuf, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+    let zip_path = Path::new(TEMP_ZIP_PATH).join(label.to_string() + ".artifacts.zip");
+    let _zip_in_progress = InProgress::new(&zip_path);
+    let logs = log_files(label);
+    // the output folder is writable by the task, so links are not followed
+    let is_link = |path: &Path| match std::fs::symlink_metadata(path) {
+        Ok(meta) => meta.file_type().is_symlink(),
+        Err(_) => true,
+    };
+    zip_dir_filtered(&get_output_folder_name(label), &zip_path, method, |path| {
+        !is_link(path) && !is_log_file(path, &logs)
+    })?;
+    Ok(zip_path)
+}
+
+///
+/// the file at relative `path` in the task folder, which must stay inside of it
+///
+pub fn task_file(label: &str, path: &str) -> Result<PathBuf, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(format!(
+            "task with label `{}` does not exist",
+            label
+        )));
+    }
+    let file = resolve_inside(&get_task_folder_name(label), path)?;
+    if !file.is_file() {
+        return Err(Error::PathDoesNotExist(format!("`{}` is not a file", path)));
+    }
+    Ok(file)
+}
+
+///
+/// the result of writing a file in a task folder
+///
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FileWritten {
+    path: String,
+    size_bytes: usize,
+    /// the task is loaded, and may need a restart to use the new file
+    restart_may_be_needed: bool,
+}
+
+///
+/// create or overwrite the file at relative `path` in the task folder atomically,
+/// owned by the user and group of the task
+///
+pub fn write_task_file(label: &str, path: &str, content: &[u8]) -> Result<FileWritten, Error> {
+    let _operation = Operation::begin();
+    check_managed(label)?;
+    let config = Configuration::from_yaml(&view_yaml(label)?)?;
+    let task_folder = get_task_folder_name(label);
+    let file = resolve_inside(&task_folder, path)?;
+    if file.is_dir() {
+        return Err(Error::CopyError(format!("`{}` is a folder", path)));
+    }
+
+    // create missing folders, owned like the file
+    if let Some(parent) = file.parent() {
+        let mut first_missing = None;
+        let mut ancestor = parent;
+        while !ancestor.exists() {
+            first_missing = Some(ancestor.to_path_buf());
+            ancestor = match ancestor.parent() {
+                Some(p) => p,
+                None => break,
+            };
+        }
+        if let Some(first_missing) = first_missing {
+            create_dir_check(parent)?;
+            chown_by_name_recursive(&first_missing, &config.user_name(), &config.group_name())?;
+        }
+    }
+
+    write_atomic(&file, content)?;
+    chown_by_name(&file, &config.user_name(), &config.group_name())?;
+    Ok(FileWritten {
+        path: path.to_string(),
+        size_bytes: content.len(),
+        restart_may_be_needed: is_loaded(label)?,
+    })
+}
+
+///
+/// zip the folder and yaml of `label`.
+/// Exports of other tasks, or of the same task with other options, may run
+/// at the same time, so each has its own folder and zip.
+///
+pub fn get_zip(
+    label: &str,
+    method: zip::CompressionMethod,
+    redact: bool,
+) -> Result<PathBuf, Error> {
+    if !exist(label)? {
+        return Err(Error::TaskDoesNotExist(
+            "attempting to view yaml of non-existent tasks".to_string(),
+        ));
+    }
+    let export_name = format!(
+        "{}.{}{}",
+        label,
+        format!("{:?}", method).to_lowercase(),
+        if redact { "" } else { ".unredacted" }
+    );
+    let unzip_folder = &Path::new(TEMP_ZIP_FOLDER).join(&export_name);
+    let zip_path = Path::new(TEMP_ZIP_PATH).join(export_name + ".zip");
+    let _unzip_in_progress = InProgress::new(unzip_folder);
+    let _zip_in_progress = InProgress::new(&zip_path);
+    try_to_remove_folder(unzip_folder)?;
+    copy_task_for_export(label, unzip_folder, redact)?;
+    zip_dir(unzip_folder, &zip_path, method)?;
+
+    Ok(zip_path)
+}
+
+///
+/// zip the folders and yaml files of all tasks into `zip_path`, each task in a folder
+/// named after its label with the same content as the zip of `get_zip`.
+/// Used by `/export_all` and backups, returning the exported labels.
+///
+pub fn export_all(
+    zip_path: &Path,
+    method: zip::CompressionMethod,
+    redact: bool,
+) -> Result<Vec<String>, Error> {
+    let export_name = match zip_path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => {
+            return Err(Error::ZipFailure(format!(
+                "`{}` is not a file",
+                zip_path.to_string_lossy()
+            )))
+        }
+    };
+    let unzip_folder = &Path::new(TEMP_ZIP_FOLDER).join(export_name + ".all");
+    let _unzip_in_progress = InProgress::new(unzip_folder);
+    let _zip_in_progress = InProgress::new(zip_path);
+    try_to_remove_folder(unzip_folder)?;
+    create_dir_check(unzip_folder)?;
+    let labels = task_labels()?;
+    let exported = labels
+        .iter()
+        .try_for_each(|label| copy_task_for_export(label, &unzip_folder.join(label), redact))
+        .and_then(|_| zip_dir(unzip_folder, zip_path, method));
+    try_to_remove_folder(unzip_folder)?;
+    exported.map(|_| labels)
+}
+
+///
+/// copy the folder and yaml of `label` into `folder`, with secrets redacted if `redact`
+///
+fn copy_task_for_export(label: &str, folder: &Path, redact: bool) -> Result<(), Error> {
+    let yaml_file = Env::get().meta_dir.join(String::from(label) + ".yaml");
+
+    copy_folder(&get_task_folder_name(label), folder, false)?;
+
+    let yaml_in_zip = folder.join(String::from(label) + ".yaml");
+    let keys = if redact {
+        read_secrets(label)
+    } else {
+        Vec::new()
+    };
+    if keys.is_empty() {
+        match std::fs::copy(yaml_file.as_path(), &yaml_in_zip) {
+            Ok(_) => {}
+            Err(_) => {
+                return Err(Error::FailedToFindYamlInMeta(
+                    "task yaml missing in Meta".to_string(),
+                ))
+            }
+        };
+    } else {
+        let yaml = Configuration::parse_yaml(&view_yaml(label)?)?.redacted_yaml(&keys);
+        if let Err(e) = std::fs::write(&yaml_in_zip, yaml) {
+            return Err(Error::CopyError(format!("{}", e)));
+        }
+    }
+    Ok(())
+}
+
+impl PartialEq for TaskInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.label.eq(&other.label)
+    }
+}
+
+impl Eq for TaskInfo {}
+
+impl PartialOrd for TaskInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.label.partial_cmp(&other.label)
+    }
+}
+
+impl Ord for TaskInfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.label.cmp(&other.label)
+    }
+}
+
+impl TaskInfo {
+    fn from_line(line: &str) -> TaskInfo {
+        let mut split = line.split_whitespace();
+        let pid: Option<i32> = match split.next().unwrap_or("-").parse::<i32>() {
+            Ok(i) => Some(i),
+            Err(_) => None,
+        };
+        let last_exit_status = match split.next().unwrap_or("0").parse::<i32>() {
+            Ok(d) => Some(d),
+            Err(_) => None,
+        };
+        let label = String::from(split.next().unwrap_or(""));
+        TaskInfo::loaded(label, pid, last_exit_status)
+    }
+
+    fn loaded(label: String, pid: Option<i32>, last_exit_status: Option<i32>) -> TaskInfo {
+        let mut status = Status::NORMAL;
+        if pid.is_some() {
+            status = Status::RUNNING
+        } else if last_exit_status.unwrap() != 0 {
+            status = Status::ERROR
+        } else if !get_std_out_file(&label).exists() {
+            status = Status::LOADED
+        }
+        TaskInfo {
+            pid,
+            last_exit_status,
+            stdout_bytes: get_file_size(&get_std_out_file(&label)),
+            stderr_bytes: get_file_size(&get_std_err_file(&label)),
+            created_at: None,
+            created_by: None,
+            yaml_modified_at: None,
+            task_size_bytes: None,
+            should_be_loaded: None,
+            is_template: false,
+            scope: Scope::Daemon,
+            plist_tampered: None,
+            max_run_seconds: None,
+            run_elapsed_seconds: None,
+            last_run_duration_secs: None,
+            last_run_started_at: None,
+            stderr_tail: None,
+            usage: None,
+            program: None,
+            schedule_summary: None,
+            run_at_load: None,
+            error: None,
+            next_run_at: None,
+            label,
+            status,
+        }
+    }
+
+    fn from_str_filter(output: &str, matcher: &LabelMatcher) -> BTreeSet<TaskInfo> {
+        let mut lines = output.lines();
+        let mut temp = Vec::new();
+        let mut collected = BTreeSet::new();
+        lines.next();
+        for line in lines {
+            temp.push(TaskInfo::from_line(line))
+        }
+        for task in temp {
+            if matcher.matches(&task.label) && task.label.contains(TASKER_TASK_NAME) {
+                collected.insert(task);
+            }
+        }
+        collected
+    }
+
+    ///
+    /// fill creation time and size of task folder, modification time of yaml,
+    /// whether the task is a template, its scope, run time limit, program and schedule,
+    /// and its last run.
+    /// The size is the one found by the last `/disk_usage` walk, so listing never walks
+    /// task folders, and the settings from yaml are kept in the meta index until the yaml changes.
+    ///
+    fn fill_folder_metadata(&mut self) {
+        let yaml_file = Env::get()
+            .meta_dir
+            .join(String::from(&self.label) + ".yaml");
+        if let Ok(meta) = std::fs::metadata(&yaml_file) {
+            self.yaml_modified_at = meta.modified().ok().and_then(format_rfc3339);
+        }
+        self.apply_yaml_meta(yaml_meta(&self.label));
+        self.run_elapsed_seconds = self
+            .max_run_seconds
+            .and_then(|_| run_elapsed_seconds(&self.label));
+        if let Some(run) = last_run(&self.label) {
+            self.last_run_duration_secs = run.duration_secs;
+            self.last_run_started_at = run.start;
+        }
+        let task_folder = get_task_folder_name(&self.label);
+        if let Ok(meta) = std::fs::metadata(&task_folder) {
+            let created = meta.created().or_else(|_| meta.modified());
+            self.created_at = created.ok().and_then(format_rfc3339);
+            self.task_size_bytes = task_size(&self.label);
+        }
+        // the recorded creation time outlives copies of the task folder
+        if let Some(provenance) = read_provenance(&self.label) {
+            if provenance.created_at.is_some() {
+                self.created_at = provenance.created_at;
+            }
+            self.created_by = Some(provenance.created_by);
+        }
+    }
+
+    ///
+    /// the settings of the task from its yaml
+    ///
+    fn apply_yaml_meta(&mut self, yaml_meta: YamlMeta) {
+        self.is_template = yaml_meta.is_template;
+        self.scope = yaml_meta.scope;
+        self.max_run_seconds = yaml_meta.max_run_seconds;
+        self.program = yaml_meta.program;
+        self.schedule_summary = yaml_meta.schedule_summary;
+        self.run_at_load = yaml_meta.schedule.run_at_load;
+        self.error = yaml_meta.error;
+    }
+
+    ///
+    /// fill the last `n` lines of stderr if the task is in `ERROR` status,
+    /// a read failure is reported in the field instead of failing the list
+    ///
+    ///
+    /// the next run of a loaded task by `schedule`. Unloaded tasks and templates
+    /// never fire, so they have none.
+    ///
+    fn fill_next_run(&mut self, schedule: &Schedule, now: i64) {
+        if self.status == Status::UNLOADED || self.is_template {
+            self.next_run_at = None;
+            return;
+        }
+        let last_start = self
+            .last_run_started_at
+            .as_deref()
+            .and_then(parse_rfc3339)
+            .map(|millis| millis / 1000);
+        self.next_run_at = next_run(schedule, last_start, now)
+            .and_then(|secs| format_rfc3339(UNIX_EPOCH + Duration::from_secs(secs as u64)));
+    }
+
+    fn fill_stderr_tail(&mut self, n: usize) {
+        if self.status != Status::ERROR {
+            return;
+        }
+        self.stderr_tail = Some(match view_std_err(&self.label, n, "", None, None) {
+            Ok(s) => s,
+            Err(e) => format!("unavailable: {:?}", e),
+        });
+    }
+
+    ///
+    /// whether all fields are equal, unlike `==` which compares labels only.
+    /// `run_elapsed_seconds` is ignored, since it changes all the time.
+    ///
+    fn same_state(&self, other: &TaskInfo) -> bool {
+        let state = |task: &TaskInfo| {
+            let mut task = task.clone();
+            task.run_elapsed_seconds = None;
+            serde_json::to_value(task).ok()
+        };
+        state(self) == state(other)
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn last_exit_status(&self) -> Option<i32> {
+        self.last_exit_status
+    }
+
+    pub fn max_run_seconds(&self) -> Option<u64> {
+        self.max_run_seconds
     }
 
     fn from_just_label(label: &str) -> TaskInfo {
@@ -701,7 +5210,2073 @@ impl TaskInfo {
             pid: None,
             last_exit_status: None,
             label: label.to_string(),
-            status: Status::UNLOADED,
+            status: Status::UNLOADED,
+            stdout_bytes: get_file_size(&get_std_out_file(label)),
+            stderr_bytes: get_file_size(&get_std_err_file(label)),
+            created_at: None,
+            created_by: None,
+            yaml_modified_at: None,
+            task_size_bytes: None,
+            should_be_loaded: None,
+            is_template: false,
+            scope: Scope::Daemon,
+            plist_tampered: None,
+            max_run_seconds: None,
+            run_elapsed_seconds: None,
+            last_run_duration_secs: None,
+            last_run_started_at: None,
+            stderr_tail: None,
+            usage: None,
+            program: None,
+            schedule_summary: None,
+            run_at_load: None,
+            error: None,
+            next_run_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_launchctl_mod {
+    use super::*;
+
+    fn resolve_config(yaml: &str) -> String {
+        let mut config =
+            set_working_directory_as_root_alias(Configuration::from_yaml(yaml).unwrap());
+        expand_task_aliases(
+            &mut config,
+            Path::new("/tasker/tasks/label"),
+            Path::new("/tasker/out/label"),
+        )
+        .unwrap();
+        normalize_program_arguments(&mut config);
+        config.to_plist()
+    }
+
+    fn expected_plist(directories: &str) -> String {
+        String::new()
+            + "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"
+            + "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n"
+            + "<plist version=\"1.0\">\n"
+            + "<dict>\n"
+            + "\t<key>Label</key>\n"
+            + "\t<string>com.tasker.tasks.test_task</string>\n"
+            + "\t<key>Program</key>\n"
+            + "\t<string>/bin/sh</string>\n"
+            + directories
+            + "</dict>\n"
+            + "</plist>"
+    }
+
+    #[test]
+    fn launchctl_invocations_per_scope() {
+        let daemon = Path::new("/Library/LaunchDaemons/com.tasker.tasks.a.plist");
+        assert_eq!(
+            Domain::System.load_args(daemon),
+            vec!["load", "/Library/LaunchDaemons/com.tasker.tasks.a.plist"]
+        );
+        assert_eq!(
+            Domain::System.unload_args(daemon),
+            vec!["unload", "/Library/LaunchDaemons/com.tasker.tasks.a.plist"]
+        );
+        assert_eq!(
+            Domain::System.service_target("com.tasker.tasks.a"),
+            "system/com.tasker.tasks.a"
+        );
+
+        let agent = Path::new("/Library/LaunchAgents/com.tasker.tasks.a.plist");
+        assert_eq!(
+            Domain::Gui(501).load_args(agent),
+            vec![
+                "bootstrap",
+                "gui/501",
+                "/Library/LaunchAgents/com.tasker.tasks.a.plist"
+            ]
+        );
+        assert_eq!(
+            Domain::Gui(501).unload_args(agent),
+            vec![
+                "bootout",
+                "gui/501",
+                "/Library/LaunchAgents/com.tasker.tasks.a.plist"
+            ]
+        );
+        assert_eq!(
+            Domain::Gui(501).service_target("com.tasker.tasks.a"),
+            "gui/501/com.tasker.tasks.a"
+        );
+
+        assert_eq!(Domain::of(Scope::Daemon, None).unwrap(), Domain::System);
+        assert_eq!(
+            Domain::of(Scope::Agent, Some("root")).unwrap(),
+            Domain::Gui(0)
+        );
+        assert!(Domain::of(Scope::Agent, None).is_err());
+    }
+
+    #[test]
+    fn parse_agent_print() {
+        let output = String::new()
+            + "gui/501/com.tasker.tasks.notify = {\n"
+            + "\tactive count = 1\n"
+            + "\tpath = /Library/LaunchAgents/com.tasker.tasks.notify.plist\n"
+            + "\tstate = running\n"
+            + "\tpid = 4242\n"
+            + "\tlast exit code = 78: EX_CONFIG\n"
+            + "\tendpoints = {\n"
+            + "\t\tpid = 1\n"
+            + "\t}\n"
+            + "}\n";
+        assert_eq!(parse_launchctl_print(&output), (Some(4242), Some(78)));
+
+        let output = String::new()
+            + "gui/501/com.tasker.tasks.notify = {\n"
+            + "\tstate = not running\n"
+            + "\tlast exit code = (never exited)\n"
+            + "}\n";
+        assert_eq!(parse_launchctl_print(&output), (None, Some(0)));
+    }
+
+    #[test]
+    fn retry_transient_failures() {
+        let delay = Duration::from_millis(1);
+        let busy = || Error::CommandExecutionError("Operation already in progress".to_string());
+
+        let mut calls = 0;
+        let result = retry_transient(4, delay, |attempt| {
+            calls += 1;
+            assert_eq!(attempt, calls);
+            if attempt <= 2 {
+                Err(busy())
+            } else {
+                Ok(attempt)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: Result<(), Error> = retry_transient(4, delay, |_| {
+            calls += 1;
+            Err(busy())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let result: Result<(), Error> = retry_transient(4, delay, |_| {
+            calls += 1;
+            Err(Error::CommandExecutionError(
+                "No such file or directory".to_string(),
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn launchctl_error_hints() {
+        let label = "com.tasker.tasks.backup";
+        let hint = |stderr: &str| interpret_launchctl_error(stderr, label);
+        let cases = [
+            (
+                "/Library/LaunchDaemons/com.tasker.tasks.backup.plist: Path had bad ownership/permissions",
+                "must be owned by root:wheel with mode 644",
+            ),
+            (
+                "Load failed: 5: Input/output error",
+                "its `UserName` or `GroupName` does not exist",
+            ),
+            (
+                "Bootstrap failed: 5: Input/output error",
+                "its `Program` is not executable",
+            ),
+            (
+                "/Library/LaunchDaemons/com.tasker.tasks.backup.plist: Invalid property list",
+                "check the yaml for a configuration of the wrong type",
+            ),
+            (
+                "service already loaded",
+                "is already loaded in launchd",
+            ),
+            (
+                "Load failed: 119: Service is disabled",
+                "run `launchctl enable system/com.tasker.tasks.backup`",
+            ),
+            (
+                "Load failed: 1: Operation not permitted",
+                "tasker must run as root",
+            ),
+            (
+                "Bootstrap failed: 2: No such file or directory",
+                "the plist of `com.tasker.tasks.backup` is missing",
+            ),
+        ];
+        for (stderr, expected) in cases.iter() {
+            let message = hint(stderr);
+            assert!(message.contains(expected), "{}", message);
+            // the original message is kept
+            assert!(message.ends_with(&format!("({})", stderr)), "{}", message);
+        }
+        assert_eq!(
+            hint("  Load failed: 37: Unknown  \n"),
+            "Load failed: 37: Unknown"
+        );
+    }
+
+    #[test]
+    fn plist_written_with_mode_644() {
+        use std::os::unix::fs::PermissionsExt;
+        let folder = Path::new("test_plist_folder");
+        create_dir_check(folder).unwrap();
+        let plist = folder.join("com.tasker.tasks.test.plist");
+        // a stale plist writable by anyone, e.g. written under a permissive umask
+        std::fs::write(&plist, "stale").unwrap();
+        std::fs::set_permissions(&plist, std::fs::Permissions::from_mode(0o666)).unwrap();
+
+        write_plist(&plist, "<plist/>").unwrap();
+        assert_eq!(read_utf8_file(&plist).unwrap(), "<plist/>");
+        let meta = std::fs::metadata(&plist).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o644);
+        if users::get_current_uid() == 0 {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!((meta.uid(), meta.gid()), (0, 0));
+        }
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn ownership_by_yaml_or_plist_label() {
+        let folder = Path::new("test_ownership");
+        let _ = std::fs::remove_dir_all(folder);
+        create_dir_check(folder).unwrap();
+        let label = "com.tasker.tasks.owned";
+        let yaml = folder.join("com.tasker.tasks.owned.yaml");
+        let plists = [folder.join("com.tasker.tasks.owned.plist")];
+        let plist = &plists[0];
+        let plist_labeled = |label: &str| {
+            String::new()
+                + "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"
+                + "<plist version=\"1.0\">\n"
+                + "<dict>\n"
+                + &format!("\t<key>Label</key>\n\t<string>{}</string>\n", label)
+                + "</dict>\n"
+                + "</plist>"
+        };
+
+        // nothing on disk
+        assert!(!managed_by_tasker(label, &yaml, &plists));
+
+        // a plist written by tasker
+        std::fs::write(plist, plist_labeled(label)).unwrap();
+        assert!(managed_by_tasker(label, &yaml, &plists));
+
+        // the file name matches, but the plist is of another daemon
+        std::fs::write(plist, plist_labeled("com.apple.critical")).unwrap();
+        assert!(!managed_by_tasker(label, &yaml, &plists));
+        std::fs::write(plist, plist_labeled("com.tasker.tasks.other")).unwrap();
+        assert!(!managed_by_tasker(label, &yaml, &plists));
+        std::fs::write(plist, "not a plist").unwrap();
+        assert!(!managed_by_tasker(label, &yaml, &plists));
+
+        // a daemon not created by tasker, even if labeled like its plist
+        let daemon = folder.join("com.apple.critical.plist");
+        std::fs::write(&daemon, plist_labeled("com.apple.critical")).unwrap();
+        assert!(!managed_by_tasker("com.apple.critical", &yaml, &[daemon]));
+
+        // the yaml is enough, without reading plists
+        std::fs::write(&yaml, "").unwrap();
+        assert!(managed_by_tasker(label, &yaml, &plists));
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn reload_without_run_at_load() {
+        let config = |run_at_load: &str| {
+            let yaml = String::new()
+                + "Label: test_task\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + run_at_load;
+            Configuration::from_yaml(&yaml).unwrap()
+        };
+        let run_at_load = |plist: &str| {
+            if plist.contains("<key>RunAtLoad</key>\n\t<true/>") {
+                Some(true)
+            } else if plist.contains("<key>RunAtLoad</key>\n\t<false/>") {
+                Some(false)
+            } else {
+                None
+            }
+        };
+
+        // an edit loads the task with `RunAtLoad` off, then puts back the configured plist
+        let runs = config("  - RunAtLoad: true\n");
+        let (loaded, put_back) = plists_to_load(&runs, LoadReason::Reload);
+        assert_eq!(run_at_load(&loaded), Some(false));
+        assert_eq!(put_back, Some(runs.to_plist()));
+        assert_eq!(run_at_load(&runs.to_plist()), Some(true));
+
+        // an explicit load runs it as configured
+        assert_eq!(
+            plists_to_load(&runs, LoadReason::Load),
+            (runs.to_plist(), None)
+        );
+
+        // nothing to suppress
+        for yaml in &["  - RunAtLoad: false\n", "  - StartInterval: 60\n"] {
+            let config = config(yaml);
+            assert_eq!(
+                plists_to_load(&config, LoadReason::Reload),
+                (config.to_plist(), None)
+            );
+        }
+    }
+
+    #[test]
+    fn working_directory_without_root_directory() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration: []\n";
+        let directories = String::new()
+            + "\t<key>WorkingDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n";
+        assert_eq!(resolve_config(&yaml), expected_plist(&directories));
+    }
+
+    #[test]
+    fn working_directory_with_only_root_directory() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - RootDirectory: ~root~/\n";
+        let directories = String::new()
+            + "\t<key>RootDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n";
+        assert_eq!(resolve_config(&yaml), expected_plist(&directories));
+    }
+
+    #[test]
+    fn working_directory_with_root_directory() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - RootDirectory: ~root~/\n"
+            + "  - WorkingDirectory: /scripts\n";
+        let directories = String::new()
+            + "\t<key>RootDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n"
+            + "\t<key>WorkingDirectory</key>\n"
+            + "\t<string>/scripts</string>\n";
+        assert_eq!(resolve_config(&yaml), expected_plist(&directories));
+    }
+
+    #[test]
+    fn user_output_paths_with_root_alias() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - StandardOutPath: ~root~/logs/out.log\n"
+            + "  - StandardErrorPath: /tmp/err.log\n";
+        let directories = String::new()
+            + "\t<key>StandardOutPath</key>\n"
+            + "\t<string>/tasker/tasks/label/logs/out.log</string>\n"
+            + "\t<key>StandardErrorPath</key>\n"
+            + "\t<string>/tmp/err.log</string>\n"
+            + "\t<key>WorkingDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n";
+        assert_eq!(resolve_config(&yaml), expected_plist(&directories));
+    }
+
+    #[test]
+    fn lint_warnings() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: com.tasker.tasks.test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - /bin/sh\n"
+            + "      - /bin/sh\n"
+            + "      - run.sh\n"
+            + "  - StartInterval: 3600\n"
+            + "  - StartCalendarInterval:\n"
+            + "      - Hour: 8";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        assert_eq!(
+            lint_config(&config),
+            vec![
+                "`/bin/sh` is duplicated at the beginning of ProgramArguments",
+                "both `StartInterval` and `StartCalendarInterval` are set",
+            ]
+        );
+        let config = config
+            .remove_config("StartInterval")
+            .add_config(ProgramArguments(vec![
+                "/bin/sh".to_string(),
+                "run.sh".to_string(),
+            ]));
+        assert!(lint_config(&config).is_empty());
+    }
+
+    #[test]
+    fn account_lint_warnings() {
+        // `wheel` on macOS, `root` on Linux
+        let root_group = users::get_group_by_gid(0).unwrap();
+        let root_group = root_group.name().to_string_lossy();
+        let yaml = |user: &str| {
+            String::new()
+                + "---\n"
+                + "Label: com.tasker.tasks.test_task\n"
+                + "Program: /bin/sh\n"
+                + "Configuration:\n"
+                + &format!("  - UserName: {}\n", user)
+                + &format!("  - GroupName: {}", root_group)
+        };
+        let config = Configuration::from_yaml(&yaml("root")).unwrap();
+        assert!(lint_config(&config).is_empty());
+        let config = Configuration::from_yaml(&yaml("daemon")).unwrap();
+        assert_eq!(
+            lint_config(&config),
+            vec![format!(
+                "user `daemon` is not a member of group `{}`",
+                root_group
+            )]
+        );
+    }
+
+    #[test]
+    fn environment_lint_warnings() {
+        std::env::set_var("TASKER_TEST_LINT_INHERITED", "1");
+        let yaml = String::new()
+            + "---\n"
+            + "Label: com.tasker.tasks.test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      PATH: /opt/tool/bin\n"
+            + "      HOME: /no/such/home\n"
+            + "      DATA: ${HOME}/data\n"
+            + "      TASKER_TEST_LINT_INHERITED: '2'\n"
+            + "InheritEnvironment:\n"
+            + "  - TASKER_TEST_LINT_INHERITED\n"
+            + "  - TASKER_TEST_LINT_MISSING";
+        let config = Configuration::from_yaml(&yaml).unwrap();
+        assert_eq!(
+            lint_config(&config),
+            vec![
+                "`PATH` replaces the system PATH without `/usr/bin` and `/bin`, \
+                 consider `InheritEnvironment: [PATH]`",
+                "`HOME` `/no/such/home` is not a directory",
+                "`DATA` contains `${HOME}`, which launchd does not expand",
+                "`TASKER_TEST_LINT_INHERITED` is both inherited and set in EnvironmentVariables, \
+                 the set value is used",
+                "`TASKER_TEST_LINT_MISSING` of InheritEnvironment is not set in the environment \
+                 of tasker",
+            ]
+        );
+        let config = config.add_config(EnvironmentVariables({
+            let mut variables = BTreeMap::new();
+            variables.insert(
+                "PATH".to_string(),
+                "/opt/tool/bin:/usr/bin:/bin".to_string(),
+            );
+            variables.insert("HOME".to_string(), "~out~/".to_string());
+            variables
+        }));
+        assert_eq!(
+            lint_config(&config),
+            vec![
+                "`TASKER_TEST_LINT_MISSING` of InheritEnvironment is not set in the environment \
+                  of tasker"
+            ]
+        );
+    }
+
+    #[test]
+    fn transform_config_inherits_environment() {
+        std::env::set_var("TASKER_TEST_INHERITED", "/opt/inherited");
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      DATA: ~out~/data\n"
+            + "InheritEnvironment:\n"
+            + "  - TASKER_TEST_INHERITED\n"
+            + "  - TASKER_TEST_NOT_INHERITED";
+        let config = transform_config(
+            Configuration::from_yaml(&yaml).unwrap(),
+            Path::new("/tasker/tasks/label"),
+            Path::new("/tasker/out/label"),
+        )
+        .unwrap();
+        let environment = config.effective_environment();
+        assert_eq!(environment.len(), 2);
+        assert_eq!(environment["DATA"], "/tasker/out/label/data");
+        assert_eq!(environment["TASKER_TEST_INHERITED"], "/opt/inherited");
+        assert!(!config.to_plist().contains("InheritEnvironment"));
+    }
+
+    #[test]
+    fn transform_config_adds_output_paths() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - StandardErrorPath: /tmp/err.log\n";
+        let config = transform_config(
+            Configuration::from_yaml(&yaml).unwrap(),
+            Path::new("/tasker/tasks/label"),
+            Path::new("/tasker/out/label"),
+        )
+        .unwrap();
+        let directories = String::new()
+            + "\t<key>StandardErrorPath</key>\n"
+            + "\t<string>/tmp/err.log</string>\n"
+            + "\t<key>WorkingDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n"
+            + "\t<key>StandardOutPath</key>\n"
+            + "\t<string>/tasker/out/label/stdout.log</string>\n";
+        assert_eq!(config.to_plist(), expected_plist(&directories));
+    }
+
+    #[test]
+    fn environment_variables_with_aliases() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      DATA_DIR: ~root~/data\n"
+            + "      ARTIFACT_DIR: ~out~/artifacts\n"
+            + "      SEARCH_PATH: /usr/bin:~root~/bin\n"
+            + "  - StandardOutPath: ~out~/custom.log\n";
+        let mut config = Configuration::from_yaml(&yaml).unwrap();
+        expand_task_aliases(
+            &mut config,
+            Path::new("/tasker/tasks/label"),
+            Path::new("/tasker/out/label"),
+        )
+        .unwrap();
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            "DATA_DIR".to_string(),
+            "/tasker/tasks/label/data".to_string(),
+        );
+        expected.insert(
+            "ARTIFACT_DIR".to_string(),
+            "/tasker/out/label/artifacts".to_string(),
+        );
+        // only a leading alias is expanded
+        expected.insert("SEARCH_PATH".to_string(), "/usr/bin:~root~/bin".to_string());
+        assert!(config
+            .configuration
+            .contains(&EnvironmentVariables(expected)));
+        assert_eq!(
+            config.standard_out_path().unwrap(),
+            "/tasker/out/label/custom.log"
+        );
+    }
+
+    #[test]
+    fn path_state_with_aliases() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - KeepAlive:\n"
+            + "      PathState:\n"
+            + "        ~root~/run: true\n"
+            + "        ~out~/stop: false\n"
+            + "        /Volumes/Backup: true\n";
+        let mut config = Configuration::from_yaml(&yaml).unwrap();
+        expand_task_aliases(
+            &mut config,
+            Path::new("/tasker/tasks/label"),
+            Path::new("/tasker/out/label"),
+        )
+        .unwrap();
+        let plist = plist::Value::from_reader_xml(config.to_plist().as_bytes()).unwrap();
+        let path_state = plist
+            .as_dictionary()
+            .and_then(|plist| plist.get("KeepAlive"))
+            .and_then(|v| v.as_dictionary())
+            .and_then(|v| v.get("PathState"))
+            .and_then(|v| v.as_dictionary())
+            .unwrap();
+        let paths: Vec<(&str, bool)> = path_state
+            .iter()
+            .map(|(path, state)| (path.as_str(), state.as_boolean().unwrap()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/Volumes/Backup", true),
+                ("/tasker/out/label/stop", false),
+                ("/tasker/tasks/label/run", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn effective_command_line() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - \"-c\"\n"
+            + "      - echo \"$GREETING\" > ~/it's.log\n"
+            + "  - EnvironmentVariables:\n"
+            + "      GREETING: hello world\n"
+            + "  - UserName: root\n";
+        let config = transform_config(
+            Configuration::from_yaml(&yaml).unwrap(),
+            Path::new("/tasker/tasks/test task"),
+            Path::new("/tasker/out/test task"),
+        )
+        .unwrap();
+        let argv = config.effective_command();
+        assert_eq!(
+            argv,
+            vec!["/bin/sh", "-c", "echo \"$GREETING\" > ~/it's.log"]
+        );
+        let env = config.effective_environment();
+        assert_eq!(env.get("GREETING").unwrap(), "hello world");
+        let shell = shell_command(
+            &argv,
+            &env,
+            config.working_directory().as_deref(),
+            config.user_name().as_deref(),
+            config.group_name().as_deref(),
+        );
+        assert_eq!(
+            shell,
+            r#"cd '/tasker/tasks/test task/' && sudo -u root env 'GREETING=hello world' /bin/sh -c 'echo "$GREETING" > ~/it'\''s.log'"#
+        );
+    }
+
+    #[test]
+    fn secrets_masked_in_command_and_plists() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      API_TOKEN: real_token\n"
+            + "      DEBUG: \"1\"\n";
+        let keys = vec!["API_TOKEN".to_string()];
+        let config = transform_config(
+            Configuration::from_yaml(&yaml).unwrap(),
+            Path::new("/tasker/tasks/test_task"),
+            Path::new("/tasker/out/test_task"),
+        )
+        .unwrap();
+
+        // `/command`
+        let command = command_of(&config.redacted(&keys));
+        let json = serde_json::to_string(&command).unwrap();
+        assert!(!json.contains("real_token"), "{}", json);
+        assert!(command.shell.contains("API_TOKEN=***"));
+        assert_eq!(command.env.get("DEBUG").unwrap(), "1");
+        assert!(serde_json::to_string(&command_of(&config))
+            .unwrap()
+            .contains("real_token"));
+
+        // `/get_plist` from yaml and from disk
+        let plist = config.to_plist();
+        let generated = config.redacted(&keys).to_plist();
+        assert!(!generated.contains("real_token"));
+        assert_eq!(redact_plist(&plist, &keys).unwrap(), generated);
+        assert_eq!(redact_plist(&plist, &[]).unwrap(), plist);
+
+        // `/diff` masks both sides, but a changed secret is still out of sync
+        let changed = plist.replace("real_token", "old_token");
+        let diff = plist_diff(true, Some(&changed), &plist, &keys).unwrap();
+        assert!(!diff.in_sync);
+        assert!(!diff.diff.contains("real_token") && !diff.diff.contains("old_token"));
+        let debug = plist.replace("<string>1</string>", "<string>0</string>");
+        let diff = plist_diff(true, Some(&debug), &plist, &keys).unwrap();
+        assert!(!diff.in_sync);
+        assert!(diff.diff.contains("<string>0</string>"));
+        assert!(!diff.diff.contains("real_token"), "{}", diff.diff);
+        assert!(
+            plist_diff(true, Some(&plist), &plist, &keys)
+                .unwrap()
+                .in_sync
+        );
+    }
+
+    #[test]
+    fn patch_after_interleaved_yaml_edit() {
+        let yaml = |argument: &str| {
+            String::new()
+                + "---\n"
+                + "Label: test_task\n"
+                + "Program: /bin/echo\n"
+                + "Configuration:\n"
+                + "  - ProgramArguments:\n"
+                + "      - /bin/echo\n"
+                + &format!("      - {}\n", argument)
+        };
+        let stored = Mutex::new(yaml("before"));
+        let writes = std::cell::Cell::new(0);
+        let patch = ConfigPatch::Set(Config::StartInterval(60));
+        let reload = patch_stored_yaml(
+            &patch,
+            || Ok(stored.lock().unwrap().clone()),
+            |patched, etag| {
+                let mut stored = stored.lock().unwrap();
+                writes.set(writes.get() + 1);
+                // `/post_yaml` saves an edit between the first read and write of the patch
+                if writes.get() == 1 {
+                    *stored = yaml("edited");
+                }
+                if etag != etag_of(&stored) {
+                    return Err(Error::YamlVersionMismatch(etag.to_string()));
+                }
+                *stored = patched.to_string();
+                Ok(Reload::NotLoaded)
+            },
+        )
+        .unwrap();
+        assert_eq!(reload, Reload::NotLoaded);
+        assert_eq!(writes.get(), 2);
+        let patched = Configuration::parse_yaml(&stored.lock().unwrap()).unwrap();
+        assert_eq!(patched.effective_command(), vec!["/bin/echo", "edited"]);
+        assert!(patched
+            .configs()
+            .iter()
+            .any(|conf| matches!(conf, Config::StartInterval(60))));
+
+        // an edit before every write gives up after `PATCH_ATTEMPTS`
+        let writes = std::cell::Cell::new(0);
+        let result = patch_stored_yaml(
+            &patch,
+            || Ok(yaml("before")),
+            |_, etag| {
+                writes.set(writes.get() + 1);
+                Err(Error::YamlVersionMismatch(etag.to_string()))
+            },
+        );
+        assert!(matches!(result, Err(Error::YamlVersionMismatch(_))));
+        assert_eq!(writes.get(), PATCH_ATTEMPTS);
+    }
+
+    #[test]
+    fn only_logs_in_output_folder_rotated() {
+        let output_folder = Path::new("test_log_inside/out");
+        std::fs::create_dir_all(output_folder).unwrap();
+        std::fs::write(output_folder.join("stdout.log"), "log").unwrap();
+        std::os::unix::fs::symlink("/etc/hosts", output_folder.join("hosts.log")).unwrap();
+        assert!(log_inside(output_folder, &output_folder.join("stdout.log")).is_some());
+        assert!(log_inside(output_folder, &output_folder.join("logs/new.log")).is_some());
+        for outside in &[
+            Path::new("/etc/hosts").to_path_buf(),
+            output_folder.join("../stdout.log"),
+            output_folder.join("hosts.log"),
+            Path::new("test_log_inside/stdout.log").to_path_buf(),
+        ] {
+            assert_eq!(log_inside(output_folder, outside), None, "{:?}", outside);
+        }
+        std::fs::remove_dir_all("test_log_inside").unwrap();
+    }
+
+    ///
+    /// records whether the log was already rotated when kickstarted
+    ///
+    struct RotationCheck {
+        log: PathBuf,
+        rotated_at_kickstart: Vec<bool>,
+    }
+
+    impl TaskProcess for RotationCheck {
+        fn signal(&mut self, _: i32, _: libc::c_int, _: bool) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn alive(&mut self, _: i32) -> bool {
+            true
+        }
+
+        fn kickstart(&mut self) -> Result<(), Error> {
+            let rotated = std::fs::read_to_string(rotated_file_name(&self.log, 1));
+            self.rotated_at_kickstart
+                .push(rotated.ok().as_deref() == Some("old"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rotated_before_kickstart() {
+        let output_folder = Path::new("test_rotate_kickstart");
+        std::fs::create_dir_all(output_folder).unwrap();
+        let log = output_folder.join("stdout.log");
+        std::fs::write(&log, "old").unwrap();
+        let mut process = RotationCheck {
+            log: log.clone(),
+            rotated_at_kickstart: Vec::new(),
+        };
+        let logs = [log.clone()];
+        rotate_then_kickstart(&mut process, output_folder, &logs, || Ok(true)).unwrap();
+        assert_eq!(process.rotated_at_kickstart, vec![true]);
+
+        // rotated, but not kickstarted unless running
+        rotate_then_kickstart(&mut process, output_folder, &logs, || Ok(false)).unwrap();
+        assert_eq!(process.rotated_at_kickstart, vec![true]);
+        assert!(rotated_file_name(&log, 2).exists());
+        std::fs::remove_dir_all(output_folder).unwrap();
+    }
+
+    #[test]
+    fn template_refused_and_listed() {
+        let yaml =
+            "Label: a\nProgram: /bin/sh\nTemplate: true\nConfiguration:\n  - StartInterval: 60\n";
+        let template = Configuration::from_yaml(yaml).unwrap();
+        assert!(matches!(
+            refuse_template("com.tasker.tasks.a", &template),
+            Err(Error::CannotLoadTemplate(_))
+        ));
+        let task = Configuration::from_yaml(&yaml.replace("true", "false")).unwrap();
+        assert!(refuse_template("com.tasker.tasks.a", &task).is_ok());
+
+        // flagged in listing, and never scheduled
+        let mut info = TaskInfo::from_just_label("com.tasker.tasks.a");
+        info.status = Status::NORMAL;
+        info.last_run_started_at = Some("2020-09-13T12:26:40Z".to_string());
+        info.apply_yaml_meta(parse_yaml_meta(Ok(yaml)));
+        assert_eq!(serde_json::to_value(&info).unwrap()["is_template"], true);
+        info.fill_next_run(&Schedule::of(&template), 0);
+        assert_eq!(info.next_run_at, None);
+        let mut info = TaskInfo::from_just_label("com.tasker.tasks.a");
+        info.status = Status::NORMAL;
+        info.last_run_started_at = Some("2020-09-13T12:26:40Z".to_string());
+        info.apply_yaml_meta(parse_yaml_meta(Ok(&yaml.replace("true", "false"))));
+        assert_eq!(serde_json::to_value(&info).unwrap()["is_template"], false);
+        info.fill_next_run(&Schedule::of(&task), 0);
+        assert!(info.next_run_at.is_some());
+    }
+
+    #[test]
+    fn template_flag() {
+        assert!(yaml_is_template(
+            "Label: a\nProgram: /bin/sh\nTemplate: true\n"
+        ));
+        assert!(!yaml_is_template(
+            "Label: a\nProgram: /bin/sh\nTemplate: false\n"
+        ));
+        assert!(!yaml_is_template("Label: a\nProgram: /bin/sh\n"));
+        assert!(parse_yaml_meta(Ok("Label: a\nProgram: /bin/sh\nTemplate: true\n")).is_template);
+        assert!(!yaml_is_template("not: [yaml"));
+        let template = Configuration::from_yaml(
+            "Label: a\nProgram: /bin/sh\nTemplate: true\nConfiguration: []\n",
+        )
+        .unwrap();
+        assert!(!template.to_plist().contains("Template"));
+        assert!(template.to_yaml().unwrap().contains("Template: true"));
+    }
+
+    #[test]
+    fn secrets_restored_from_previous_yaml() {
+        let previous = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      API_TOKEN: real_token\n"
+            + "      PASSWORD: real_password\n"
+            + "      DEBUG: \"1\"\n";
+        let keys = vec!["API_TOKEN".to_string(), "PASSWORD".to_string()];
+        let redacted = Configuration::parse_yaml(&previous)
+            .unwrap()
+            .redacted_yaml(&keys);
+        assert!(!redacted.contains("real_token"));
+        assert!(!redacted.contains("real_password"));
+        assert!(redacted.contains("DEBUG"));
+
+        // posting the redacted yaml back keeps every secret
+        let restored = restore_secret_yaml(&redacted, &previous, &keys).unwrap();
+        let env = Configuration::parse_yaml(&restored)
+            .unwrap()
+            .effective_environment();
+        assert_eq!(env.get("API_TOKEN").unwrap(), "real_token");
+        assert_eq!(env.get("PASSWORD").unwrap(), "real_password");
+        assert_eq!(env.get("DEBUG").unwrap(), "1");
+
+        // an edited secret replaces the stored one, a masked one is kept
+        let edited = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      API_TOKEN: new_token\n"
+            + "      PASSWORD: \"***\"\n";
+        let restored = restore_secret_yaml(&edited, &previous, &keys).unwrap();
+        let env = Configuration::parse_yaml(&restored)
+            .unwrap()
+            .effective_environment();
+        assert_eq!(env.get("API_TOKEN").unwrap(), "new_token");
+        assert_eq!(env.get("PASSWORD").unwrap(), "real_password");
+
+        // `***` of an unmarked key, or of a secret without stored value, is a literal value
+        let posted = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - EnvironmentVariables:\n"
+            + "      API_TOKEN: \"***\"\n"
+            + "      DEBUG: \"***\"\n"
+            + "      NEW_SECRET: \"***\"\n";
+        let keys = vec!["API_TOKEN".to_string(), "NEW_SECRET".to_string()];
+        let restored = restore_secret_yaml(&posted, &previous, &keys).unwrap();
+        let env = Configuration::parse_yaml(&restored)
+            .unwrap()
+            .effective_environment();
+        assert_eq!(env.get("API_TOKEN").unwrap(), "real_token");
+        assert_eq!(env.get("DEBUG").unwrap(), "***");
+        assert_eq!(env.get("NEW_SECRET").unwrap(), "***");
+
+        // yaml without masked values is kept as it is, including comments
+        let plain = previous.clone() + "# comment\n";
+        assert_eq!(
+            restore_secret_yaml(&plain, &previous, &keys).unwrap(),
+            plain
+        );
+    }
+
+    #[test]
+    fn unused_trash_path() {
+        let trash = Path::new("test_unused_trash");
+        let label = trash.join("com.tasker.tasks.label");
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1600000000);
+        assert_eq!(unused_path(&label, now), label);
+        create_dir_check(&label).unwrap();
+        let timestamped = trash.join("com.tasker.tasks.label.1600000000");
+        assert_eq!(unused_path(&label, now), timestamped);
+        create_dir_check(&timestamped).unwrap();
+        assert_eq!(
+            unused_path(&label, now),
+            trash.join("com.tasker.tasks.label.1600000000.1")
+        );
+        std::fs::remove_dir_all(trash).unwrap();
+    }
+
+    fn task(label: &str, status: Status) -> TaskInfo {
+        TaskInfo {
+            pid: None,
+            last_exit_status: None,
+            label: label.to_string(),
+            status,
+            stdout_bytes: None,
+            stderr_bytes: None,
+            created_at: None,
+            created_by: None,
+            yaml_modified_at: None,
+            task_size_bytes: None,
+            should_be_loaded: None,
+            is_template: false,
+            scope: Scope::Daemon,
+            plist_tampered: None,
+            max_run_seconds: None,
+            run_elapsed_seconds: None,
+            last_run_duration_secs: None,
+            last_run_started_at: None,
+            stderr_tail: None,
+            usage: None,
+            program: None,
+            schedule_summary: None,
+            run_at_load: None,
+            error: None,
+            next_run_at: None,
+        }
+    }
+
+    fn tasks() -> Vec<TaskInfo> {
+        vec![
+            task("a", Status::RUNNING),
+            task("b", Status::ERROR),
+            task("c", Status::UNLOADED),
+            task("d", Status::ERROR),
+            task("e", Status::NORMAL),
+        ]
+    }
+
+    #[test]
+    fn snapshot_diff() {
+        let old: BTreeSet<TaskInfo> = tasks().into_iter().collect();
+        assert!(diff_snapshots(&old, &old).is_empty());
+
+        let mut new: Vec<TaskInfo> = tasks().into_iter().filter(|t| t.label != "c").collect();
+        new[0].status = Status::NORMAL;
+        new[0].pid = None;
+        new[1].stderr_bytes = Some(1024);
+        new.push(task("f", Status::LOADED));
+        let new: BTreeSet<TaskInfo> = new.into_iter().collect();
+
+        let events = diff_snapshots(&old, &new);
+        let labels: Vec<String> = events
+            .iter()
+            .map(|e| match e {
+                ChangeEvent::Changed { task } => format!("changed {}", task.label),
+                ChangeEvent::Removed { label } => format!("removed {}", label),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["changed a", "changed b", "changed f", "removed c"]
+        );
+        let json = serde_json::to_value(&events[3]).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "removed", "label": "c"}));
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["type"], "changed");
+        assert_eq!(json["task"]["status"], "NORMAL");
+    }
+
+    #[test]
+    fn task_table_columns() {
+        let mut tasks = vec![
+            task("com.tasker.tasks.backup", Status::RUNNING),
+            task("com.tasker.tasks.b", Status::ERROR),
+            task("com.tasker.tasks.report", Status::LOADED),
+            task("com.tasker.tasks.zz", Status::UNLOADED),
+        ];
+        tasks[0].pid = Some(123);
+        tasks[1].last_exit_status = Some(78);
+        tasks[2].next_run_at = Some("2026-10-17T09:15:00Z".to_string());
+        assert_eq!(
+            render_task_table(&tasks, 120),
+            String::new()
+                + "LABEL   STATUS    PID  EXIT  NEXT RUN\n"
+                + "b       ERROR       -    78  -\n"
+                + "backup  RUNNING   123     -  -\n"
+                + "report  LOADED      -     -  2026-10-17T09:15:00Z\n"
+                + "zz      UNLOADED    -     -  -\n"
+                + "4 tasks: 1 running, 1 loaded, 1 unloaded, 1 error\n"
+        );
+    }
+
+    #[test]
+    fn task_table_truncation() {
+        let tasks = vec![task(
+            "com.tasker.tasks.nightly_backup_of_database",
+            Status::RUNNING,
+        )];
+        let table = render_task_table(&tasks, 50);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[1], "nightly_ba…_database  RUNNING    -     -  -");
+        assert!(lines.iter().all(|line| line.chars().count() <= 50));
+        assert_eq!(lines[0].chars().count(), 50);
+        // not shortened below `MIN_TEXT_LABEL_WIDTH`
+        let table = render_task_table(&tasks, 10);
+        assert!(table.contains("\nnightl…abase  RUNNING"));
+        assert_eq!(ellipsize_middle("abcdef", 6), "abcdef");
+        assert_eq!(ellipsize_middle("abcdef", 3), "a…f");
+        assert_eq!(ellipsize_middle("abcdef", 4), "ab…f");
+        assert_eq!(ellipsize_middle("abcdef", 1), "…");
+        assert_eq!(ellipsize_middle("abcdef", 0), "");
+    }
+
+    #[test]
+    fn task_table_footer() {
+        assert_eq!(
+            render_task_table(&[], 120),
+            "LABEL  STATUS  PID  EXIT  NEXT RUN\n0 tasks\n"
+        );
+        let table = render_task_table(&tasks(), 120);
+        assert_eq!(
+            table.lines().last().unwrap(),
+            "5 tasks: 1 running, 1 unloaded, 1 normal, 2 error"
+        );
+        let labels: Vec<&str> = table
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        assert_eq!(labels, vec!["b", "d", "a", "e", "c", "5"]);
+        let table = render_task_table(&tasks()[..1], 120);
+        assert_eq!(table.lines().last().unwrap(), "1 task: 1 running");
+    }
+
+    #[test]
+    fn status_filter() {
+        let statuses = parse_statuses("error, Unloaded,").unwrap();
+        let labels: Vec<String> = filter_by_status(tasks(), &statuses)
+            .into_iter()
+            .map(|t| t.label)
+            .collect();
+        assert_eq!(labels, vec!["b", "c", "d"]);
+        assert_eq!(filter_by_status(tasks(), &[]).len(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown status `STOPPED`, valid values are: RUNNING, LOADED")]
+    fn status_filter_unknown() {
+        parse_statuses("ERROR,STOPPED").unwrap();
+    }
+
+    #[test]
+    fn status_count() {
+        let counts = count_by_status(&filter_by_status(
+            tasks(),
+            &[Status::ERROR, Status::RUNNING],
+        ));
+        let mut expected = BTreeMap::new();
+        expected.insert("running".to_string(), 1);
+        expected.insert("loaded".to_string(), 0);
+        expected.insert("unloaded".to_string(), 0);
+        expected.insert("normal".to_string(), 0);
+        expected.insert("error".to_string(), 2);
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn desired_state_autoload() {
+        let state_file = Path::new("/tmp/tasker_test_desired_state.json");
+        let _ = std::fs::remove_file(state_file);
+        assert!(read_desired_state(state_file).is_empty());
+
+        let mut state = BTreeMap::new();
+        state.insert("a".to_string(), true);
+        state.insert("b".to_string(), false);
+        state.insert("c".to_string(), true);
+        write_desired_state(state_file, &state).unwrap();
+        let state = read_desired_state(state_file);
+
+        // fresh start with all plists missing
+        assert_eq!(tasks_to_autoload(&state, &BTreeSet::new()), vec!["a", "c"]);
+        // `a` is still loaded
+        let loaded = vec!["a".to_string()].into_iter().collect();
+        assert_eq!(tasks_to_autoload(&state, &loaded), vec!["c"]);
+        std::fs::remove_file(state_file).unwrap();
+    }
+
+    #[test]
+    fn rebuild_steps() {
+        use RebuildAction::*;
+        // a healthy loaded task is untouched
+        assert_eq!(
+            rebuild_step(true, true, true, false),
+            RebuildStep::Done(Unchanged)
+        );
+        // after a restore, the plist is missing and the task unloaded
+        assert_eq!(
+            rebuild_step(true, false, false, false),
+            RebuildStep::PlaceAndLoad
+        );
+        assert_eq!(rebuild_step(true, true, false, false), RebuildStep::Load);
+        // an outdated plist is replaced and reloaded
+        assert_eq!(
+            rebuild_step(true, false, true, false),
+            RebuildStep::PlaceAndLoad
+        );
+        // a task that should not be loaded is never loaded nor unloaded
+        assert_eq!(
+            rebuild_step(false, false, false, false),
+            RebuildStep::Done(LeftUnloaded)
+        );
+        assert_eq!(
+            rebuild_step(false, true, true, false),
+            RebuildStep::Done(Unchanged)
+        );
+        assert_eq!(
+            rebuild_step(false, false, true, false),
+            RebuildStep::Done(Unchanged)
+        );
+        // unless its plist points at the previous root
+        assert_eq!(
+            rebuild_step(false, false, true, true),
+            RebuildStep::PlaceAndLoad
+        );
+        assert_eq!(
+            rebuild_step(false, false, false, true),
+            RebuildStep::Done(LeftUnloaded)
+        );
+        assert_eq!(
+            rebuild_step(true, true, true, true),
+            RebuildStep::Done(Unchanged)
+        );
+    }
+
+    #[test]
+    fn rebuild_rewrites_paths_for_new_root() {
+        let yaml = "---\nLabel: backup\nProgram: /bin/sh\n";
+        let plist = |root: &str| {
+            let config = transform_config(
+                Configuration::from_yaml(yaml).unwrap(),
+                &Path::new(root).join("tasks/com.tasker.tasks.backup"),
+                &Path::new(root).join("out/com.tasker.tasks.backup"),
+            )
+            .unwrap();
+            config.to_plist()
+        };
+        let (old, new) = (plist("/Volumes/old/tasker"), plist("/Volumes/big/tasker"));
+        assert_ne!(sha256_hex(old.as_bytes()), sha256_hex(new.as_bytes()));
+        assert!(!new.contains("/Volumes/old"));
+        for path in &[
+            "/Volumes/big/tasker/tasks/com.tasker.tasks.backup/</string>",
+            "/Volumes/big/tasker/out/com.tasker.tasks.backup/stdout.log</string>",
+            "/Volumes/big/tasker/out/com.tasker.tasks.backup/stderr.log</string>",
+        ] {
+            assert!(new.contains(path), "{} not in {}", path, new);
+        }
+    }
+
+    #[test]
+    fn stderr_tail_skipped_for_non_error_tasks() {
+        let mut running = task("a", Status::RUNNING);
+        running.fill_stderr_tail(10);
+        assert!(running.stderr_tail.is_none());
+        let json = serde_json::to_string(&running).unwrap();
+        assert!(!json.contains("stderr_tail"));
+    }
+
+    #[test]
+    fn ps_output() {
+        assert_eq!(
+            parse_ps_output("  2048   1.5    01:02:03\n"),
+            Some(ResourceUsage {
+                rss_kb: 2048,
+                cpu_percent: 1.5,
+                elapsed: "01:02:03".to_string(),
+                process_count: 1,
+            })
+        );
+        assert_eq!(
+            parse_ps_output("123456  0,7 2-03:04:05"),
+            Some(ResourceUsage {
+                rss_kb: 123456,
+                cpu_percent: 0.7,
+                elapsed: "2-03:04:05".to_string(),
+                process_count: 1,
+            })
+        );
+        // the process has exited
+        assert_eq!(parse_ps_output(""), None);
+    }
+
+    #[test]
+    fn pgrep_output() {
+        assert_eq!(parse_pgrep_output("101\n102\n"), vec![101, 102]);
+        assert!(parse_pgrep_output("").is_empty());
+    }
+
+    fn create_zip_tree(root: &str, files: &[&str]) -> PathBuf {
+        let root = Path::new(root);
+        let _ = std::fs::remove_dir_all(root);
+        for file in files {
+            let file = root.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, "").unwrap();
+        }
+        root.to_path_buf()
+    }
+
+    #[test]
+    fn find_yaml_finder_zip() {
+        let root = create_zip_tree(
+            "/tmp/tasker_test_yaml_finder",
+            &[
+                "my_task/task.yaml",
+                "my_task/script.py",
+                "__MACOSX/my_task/._task.yaml",
+            ],
+        );
+        let (yaml, task_root) = find_yaml_file(&root).unwrap();
+        assert_eq!(yaml, root.join("my_task/task.yaml"));
+        assert_eq!(task_root, root.join("my_task"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn find_yaml_flat_zip() {
+        let root = create_zip_tree(
+            "/tmp/tasker_test_yaml_flat",
+            &["task.yaml", "scripts/script.py"],
+        );
+        let (yaml, task_root) = find_yaml_file(&root).unwrap();
+        assert_eq!(yaml, root.join("task.yaml"));
+        assert_eq!(task_root, root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn find_yaml_multiple() {
+        let root = create_zip_tree(
+            "/tmp/tasker_test_yaml_multiple",
+            &["task.yaml", "config/other.yaml"],
+        );
+        match find_yaml_file(&root) {
+            Err(Error::MultipleYamlFound(found)) => {
+                assert_eq!(found, vec!["config/other.yaml", "task.yaml"])
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn program_arguments_plist(arguments: &[&str]) -> String {
+        let mut plist = String::from("\t<key>ProgramArguments</key>\n\t<array>\n");
+        for argument in arguments {
+            plist = plist + "\t\t<string>" + argument + "</string>\n";
+        }
+        plist
+            + "\t</array>\n"
+            + "\t<key>WorkingDirectory</key>\n"
+            + "\t<string>/tasker/tasks/label/</string>\n"
+    }
+
+    #[test]
+    fn program_arguments_without_argv0() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - ~root~/run.sh\n";
+        assert_eq!(
+            resolve_config(&yaml),
+            expected_plist(&program_arguments_plist(&[
+                "/bin/sh",
+                "/tasker/tasks/label/run.sh"
+            ]))
+        );
+    }
+
+    #[test]
+    fn program_arguments_with_argv0() {
+        let yaml = String::new()
+            + "---\n"
+            + "Label: test_task\n"
+            + "Program: /bin/sh\n"
+            + "Configuration:\n"
+            + "  - ProgramArguments:\n"
+            + "      - /bin/sh\n"
+            + "      - ~root~/run.sh\n";
+        assert_eq!(
+            resolve_config(&yaml),
+            expected_plist(&program_arguments_plist(&[
+                "/bin/sh",
+                "/tasker/tasks/label/run.sh"
+            ]))
+        );
+    }
+
+    fn calendar(calendar: Vec<CalendarTime>) -> Schedule {
+        Schedule {
+            calendar,
+            ..Schedule::default()
+        }
+    }
+
+    fn at(hour: Option<i64>, minute: Option<i64>) -> CalendarTime {
+        CalendarTime {
+            hour,
+            minute,
+            ..CalendarTime::default()
+        }
+    }
+
+    #[test]
+    fn next_run_times() {
+        // Monday 2020-09-14 08:00 in the local time zone
+        let monday = crate::utils::days_from_civil(2020, 9, 14);
+        let now = local_secs(monday, 8, 0).unwrap();
+        let after = |days: i64, hour: i64, minute: i64| local_secs(monday + days, hour, minute);
+
+        let daily = calendar(vec![at(Some(8), Some(30))]);
+        assert_eq!(next_run(&daily, None, now), after(0, 8, 30));
+        let earlier = calendar(vec![at(Some(7), Some(0))]);
+        assert_eq!(next_run(&earlier, None, now), after(1, 7, 0));
+        let hourly = calendar(vec![at(None, Some(15))]);
+        assert_eq!(next_run(&hourly, None, now), after(0, 8, 15));
+        // `0` and `7` are both Sunday
+        for sunday in &[0, 7] {
+            let weekly = calendar(vec![CalendarTime {
+                weekday: Some(*sunday),
+                ..at(Some(9), None)
+            }]);
+            assert_eq!(next_run(&weekly, None, now), after(6, 9, 0));
+        }
+        // the 1st or any Wednesday
+        let either = calendar(vec![CalendarTime {
+            day: Some(1),
+            weekday: Some(3),
+            ..at(Some(0), Some(0))
+        }]);
+        assert_eq!(next_run(&either, None, now), after(2, 0, 0));
+        let leap_day = calendar(vec![CalendarTime {
+            day: Some(29),
+            month: Some(2),
+            ..at(Some(0), Some(0))
+        }]);
+        let leap_day_2024 = crate::utils::days_from_civil(2024, 2, 29);
+        assert_eq!(
+            next_run(&leap_day, None, now),
+            local_secs(leap_day_2024, 0, 0)
+        );
+        let both = calendar(vec![at(Some(7), Some(0)), at(Some(20), Some(0))]);
+        assert_eq!(next_run(&both, None, now), after(0, 20, 0));
+
+        // `StartInterval` counts from the last start
+        let interval = Schedule {
+            start_interval: Some(60),
+            ..Schedule::default()
+        };
+        assert_eq!(next_run(&interval, None, now), None);
+        assert_eq!(next_run(&interval, Some(now - 100), now), Some(now + 20));
+        assert_eq!(next_run(&interval, Some(now - 60), now), Some(now + 60));
+        let mixed = Schedule {
+            start_interval: Some(3600),
+            ..daily
+        };
+        assert_eq!(next_run(&mixed, Some(now), now), after(0, 8, 30));
+        assert_eq!(next_run(&Schedule::default(), Some(now), now), None);
+    }
+
+    #[test]
+    fn lint_stored_yaml() {
+        let meta = Path::new("test_lint_meta");
+        let _ = std::fs::remove_dir_all(meta);
+        std::fs::create_dir_all(meta).unwrap();
+        let mut index = MetaIndex::load(meta);
+        let mut lint = |label: &str, yaml: &str| {
+            let yaml_file = meta.join(format!("{}.yaml", label));
+            std::fs::write(&yaml_file, yaml).unwrap();
+            lint_task(label, index.get(label, parse_yaml_meta).unwrap())
+        };
+
+        let good = lint(
+            "good",
+            "Label: good\nProgram: /bin/echo\nConfiguration:\n  - StartInterval: 60\n",
+        );
+        assert!(!good.has_problems());
+
+        let unparseable = lint("unparseable", "Label: [unparseable\n");
+        assert!(unparseable.has_errors());
+        assert!(unparseable.parse_error.unwrap().starts_with("YamlError("));
+        assert_eq!(unparseable.validation_error, None);
+
+        // e.g. python moved by an OS upgrade
+        let missing = lint(
+            "missing",
+            "Label: missing\nProgram: /no/such/python3\nConfiguration:\n  \
+             - ProgramArguments: [/no/such/python3, /no/such/python3]\n",
+        );
+        assert_eq!(missing.parse_error, None);
+        assert!(missing
+            .validation_error
+            .unwrap()
+            .starts_with("ConfigProgramError("));
+        assert_eq!(
+            missing.warnings,
+            vec!["`/no/such/python3` is duplicated at the beginning of ProgramArguments"]
+        );
+        std::fs::remove_dir_all(meta).unwrap();
+    }
+
+    #[test]
+    fn next_run_of_loaded_tasks_only() {
+        let meta = Path::new("test_next_run_meta");
+        let _ = std::fs::remove_dir_all(meta);
+        std::fs::create_dir_all(meta).unwrap();
+        let a = "com.tasker.tasks.a";
+        let yaml_file = meta.join(format!("{}.yaml", a));
+        let yaml = |interval: i64| {
+            String::new()
+                + "Label: a\n"
+                + "Program: /bin/echo\n"
+                + "Configuration:\n"
+                + &format!("  - StartInterval: {}\n", interval)
+        };
+        let mut index = MetaIndex::load(meta);
+        let mut read = |label: &str| index.get(label, parse_yaml_meta).unwrap_or_default();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        std::fs::write(&yaml_file, yaml(60)).unwrap();
+        set_mtime(&yaml_file, mtime).unwrap();
+        let schedule = read(a).schedule;
+        assert_eq!(schedule.start_interval, Some(60));
+
+        // the parsed yaml is reused until its mtime changes
+        std::fs::write(&yaml_file, yaml(120)).unwrap();
+        set_mtime(&yaml_file, mtime).unwrap();
+        let cached = read(a).schedule;
+        assert_eq!(cached.start_interval, Some(60));
+        set_mtime(&yaml_file, mtime + Duration::from_secs(1)).unwrap();
+        let schedule = read(a).schedule;
+        assert_eq!(schedule.start_interval, Some(120));
+        assert_eq!(read(a).schedule_summary.as_deref(), Some("every 120s"));
+        let missing = read("com.tasker.tasks.b");
+        assert_eq!(missing.schedule, Schedule::default());
+        assert_eq!(missing.error, None);
+
+        // a corrupt yaml is reported on its own row
+        let corrupt_file = meta.join("com.tasker.tasks.c.yaml");
+        std::fs::write(&corrupt_file, "Label: [c\n").unwrap();
+        let corrupt = read("com.tasker.tasks.c");
+        assert!(corrupt.error.unwrap().starts_with("YamlError("));
+        assert_eq!(corrupt.program, None);
+        assert!(!corrupt.is_template);
+        std::fs::remove_dir_all(meta).unwrap();
+
+        let now = 1_600_000_000;
+        let mut loaded = task("a", Status::LOADED);
+        loaded.last_run_started_at = Some("2020-09-13T12:25:00Z".to_string());
+        loaded.fill_next_run(&schedule, now);
+        assert_eq!(loaded.next_run_at.as_deref(), Some("2020-09-13T12:27:00Z"));
+        let mut unloaded = task("a", Status::UNLOADED);
+        unloaded.last_run_started_at = loaded.last_run_started_at.clone();
+        unloaded.fill_next_run(&schedule, now);
+        assert_eq!(unloaded.next_run_at, None);
+        let mut template = loaded.clone();
+        template.is_template = true;
+        template.fill_next_run(&schedule, now);
+        assert_eq!(template.next_run_at, None);
+        let mut never_ran = task("a", Status::LOADED);
+        never_ran.fill_next_run(&schedule, now);
+        assert_eq!(never_ran.next_run_at, None);
+    }
+
+    #[test]
+    fn output_kept_unless_created_anew() {
+        let root = Path::new("test_output_intent");
+        let _ = std::fs::remove_dir_all(root);
+        let output = root.join("out");
+        let trash = root.join("label.out.1600000000");
+        let log = b"first run\n\x1b[31mfailed\x1b[0m\n";
+        std::fs::create_dir_all(&output).unwrap();
+        std::fs::write(output.join(STD_OUT_FILE), log).unwrap();
+
+        prepare_output_folder(&output, &trash, ConfigIntent::Update).unwrap();
+        assert_eq!(std::fs::read(output.join(STD_OUT_FILE)).unwrap(), log);
+        assert!(!trash.exists());
+
+        // a leftover of a task created again under the same label
+        prepare_output_folder(&output, &trash, ConfigIntent::CreateNew).unwrap();
+        assert!(output.is_dir());
+        assert!(!output.join(STD_OUT_FILE).exists());
+        assert_eq!(std::fs::read(trash.join(STD_OUT_FILE)).unwrap(), log);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn label_cache_until_invalidated() {
+        let meta = Path::new("test_label_cache");
+        let _ = std::fs::remove_dir_all(meta);
+        std::fs::create_dir_all(meta).unwrap();
+        let reads = std::cell::Cell::new(0);
+        let read = || {
+            reads.set(reads.get() + 1);
+            let mut labels = Vec::new();
+            for entry in std::fs::read_dir(meta).unwrap() {
+                labels.push(entry.unwrap().file_name().to_string_lossy().to_string());
+            }
+            Ok(labels)
+        };
+        let mut cache = LabelCache::default();
+        std::fs::write(meta.join("com.tasker.tasks.b"), "").unwrap();
+        assert_eq!(cache.get(read).unwrap(), vec!["com.tasker.tasks.b"]);
+
+        // stale until the creating operation invalidates it
+        std::fs::write(meta.join("com.tasker.tasks.a"), "").unwrap();
+        assert_eq!(cache.get(read).unwrap(), vec!["com.tasker.tasks.b"]);
+        assert_eq!(reads.get(), 1);
+        cache.invalidate();
+        assert_eq!(
+            cache.get(read).unwrap(),
+            vec!["com.tasker.tasks.a", "com.tasker.tasks.b"]
+        );
+
+        std::fs::remove_file(meta.join("com.tasker.tasks.b")).unwrap();
+        cache.invalidate();
+        assert_eq!(cache.get(read).unwrap(), vec!["com.tasker.tasks.a"]);
+        assert_eq!(reads.get(), 3);
+        std::fs::remove_dir_all(meta).unwrap();
+    }
+
+    #[test]
+    fn list_cache_until_expired_or_invalidated() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+        use std::sync::mpsc::channel;
+
+        // a backend where `com.tasker.tasks.a` is loaded once `loaded` is set
+        let calls = Arc::new(AtomicUsize::new(0));
+        let loaded = Arc::new(AtomicBool::new(false));
+        let backend = |calls: &AtomicUsize, loaded: &AtomicBool| {
+            calls.fetch_add(1, SeqCst);
+            let mut tasks = BTreeSet::new();
+            if loaded.load(SeqCst) {
+                tasks.insert(TaskInfo::loaded(
+                    "com.tasker.tasks.a".to_string(),
+                    None,
+                    Some(0),
+                ));
+            }
+            Ok(tasks)
+        };
+        let list = || backend(&calls, &loaded);
+        let start = Instant::now();
+        let max_age = Duration::from_secs(2);
+        let cache = Arc::new(Mutex::new(ListCache::default()));
+        let get = |now| cache.lock().unwrap().get(now, max_age, list).unwrap();
+
+        assert!(get(start).is_empty());
+        assert!(get(start + Duration::from_secs(1)).is_empty());
+        assert_eq!(calls.load(SeqCst), 1);
+
+        // a load invalidates the list, so that checking it right after sees the task
+        loaded.store(true, SeqCst);
+        cache.lock().unwrap().invalidate();
+        assert_eq!(get(start + Duration::from_secs(1)).len(), 1);
+        assert_eq!(calls.load(SeqCst), 2);
+
+        // an outside change is seen once the list expires
+        loaded.store(false, SeqCst);
+        assert_eq!(get(start + Duration::from_secs(2)).len(), 1);
+        assert!(get(start + Duration::from_secs(3)).is_empty());
+        assert_eq!(calls.load(SeqCst), 3);
+
+        // a listing in progress while a task is loaded does not outlive the invalidation
+        let (started_tx, started_rx) = channel();
+        let (loaded_tx, loaded_rx) = channel::<()>();
+        cache.lock().unwrap().invalidate();
+        let listing = {
+            let (cache, calls, loaded) = (cache.clone(), calls.clone(), loaded.clone());
+            std::thread::spawn(move || {
+                cache
+                    .lock()
+                    .unwrap()
+                    .get(Instant::now(), max_age, || {
+                        let stale = backend(&calls, &loaded);
+                        started_tx.send(()).unwrap();
+                        loaded_rx.recv().unwrap();
+                        stale
+                    })
+                    .unwrap()
+            })
+        };
+        started_rx.recv().unwrap();
+        loaded.store(true, SeqCst);
+        loaded_tx.send(()).unwrap();
+        cache.lock().unwrap().invalidate();
+        assert!(listing.join().unwrap().is_empty());
+        assert_eq!(get(Instant::now()).len(), 1);
+        assert_eq!(calls.load(SeqCst), 5);
+
+        // no caching with a max age of zero
+        let mut uncached = ListCache::default();
+        for _ in 0..2 {
+            uncached.get(start, Duration::from_secs(0), list).unwrap();
+        }
+        assert_eq!(calls.load(SeqCst), 7);
+    }
+
+    #[test]
+    fn label_matcher_modes() {
+        let (substring, glob, regex) = (MatchMode::Substring, MatchMode::Glob, MatchMode::Regex);
+        let matches = |pattern: &str, mode: MatchMode, label: &str| {
+            LabelMatcher::new(pattern, mode).unwrap().matches(label)
+        };
+        for label in &["com.tasker.tasks.metadata_cleanup", "metadata_cleanup"] {
+            // substring, with and without the prefix
+            assert!(matches("data", substring, label));
+            assert!(matches("", substring, label));
+            assert!(matches("com.tasker.tasks", substring, label));
+            assert!(matches("com.tasker.tasks.meta", substring, label));
+            assert!(!matches("com.tasker.tasks.data", substring, label));
+            // glob of the whole label
+            assert!(matches("*_cleanup", glob, label));
+            assert!(matches("com.tasker.tasks.meta*", glob, label));
+            assert!(matches("metadata_clean??", glob, label));
+            assert!(!matches("data*", glob, label));
+            assert!(!matches("*_clean", glob, label));
+            // regex of the label without the prefix
+            assert!(matches("^meta.*up$", regex, label));
+            assert!(matches("data", regex, label));
+            assert!(!matches("^com\\.tasker", regex, label));
+        }
+        assert!(matches("*_prod", glob, "com.tasker.tasks.backup_prod"));
+        assert!(!matches("*_prod", glob, "com.tasker.tasks.backup_prod_old"));
+        // `.` is literal in a glob
+        assert!(!matches("a.c", glob, "abc"));
+        assert!(LabelMatcher::all().matches("com.tasker.tasks.backup"));
+
+        let nested = "(".repeat(20) + "a" + &")".repeat(20);
+        let too_long = "a".repeat(MAX_LABEL_PATTERN_LEN + 1);
+        for (pattern, mode) in &[
+            ("(unclosed", regex),
+            ("a{100000}", regex),
+            (nested.as_str(), regex),
+            (too_long.as_str(), glob),
+        ] {
+            match LabelMatcher::new(pattern, *mode) {
+                Err(Error::InvalidLabelPattern(_)) => {}
+                other => panic!("unexpected {:?} for {}", other, pattern),
+            }
+        }
+        // substring and glob patterns stay path-safe
+        assert!(LabelMatcher::new("../etc", substring).is_err());
+        assert!(LabelMatcher::new("*/etc", glob).is_err());
+        assert!(LabelMatcher::new("[^/]+", regex).is_ok());
+    }
+
+    #[test]
+    fn trash_listing() {
+        let trash = Path::new("test_trash_listing");
+        let _ = std::fs::remove_dir_all(trash);
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 3600);
+        let write = |entry: &str, file: &str, bytes: usize| {
+            std::fs::create_dir_all(trash.join(entry)).unwrap();
+            std::fs::write(trash.join(entry).join(file), vec![b'x'; bytes]).unwrap();
+        };
+        // deleted with a marker, while a new task took the label
+        write("com.tasker.tasks.a", "com.tasker.tasks.a.yaml", 10);
+        write("com.tasker.tasks.a", "run.sh", 1000);
+        DeletionMarker::new("com.tasker.tasks.a", now - 2 * day)
+            .write(&trash.join("com.tasker.tasks.a"))
+            .unwrap();
+        // deleted before markers, and the task folder replaced by `/update_task`
+        write(
+            "com.tasker.tasks.b.1600000000",
+            "com.tasker.tasks.b.yaml",
+            20,
+        );
+        write("com.tasker.tasks.b.1600000000.1", "run.sh", 300);
+        write("com.tasker.tasks.c.out.1600000000", "stdout.log", 50);
+        for (entry, age) in &[
+            ("com.tasker.tasks.a", 0),
+            ("com.tasker.tasks.b.1600000000", 5),
+            ("com.tasker.tasks.b.1600000000.1", 3),
+            ("com.tasker.tasks.c.out.1600000000", 1),
+        ] {
+            set_mtime(&trash.join(entry), now - *age * day).unwrap();
+        }
+        let live: BTreeSet<String> = vec!["com.tasker.tasks.a".to_string()].into_iter().collect();
+
+        let entries = trash_entries(trash, &live, TrashSort::Age);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "com.tasker.tasks.b.1600000000",
+                "com.tasker.tasks.b.1600000000.1",
+                "com.tasker.tasks.a",
+                "com.tasker.tasks.c.out.1600000000",
+            ]
+        );
+        assert_eq!(
+            entries[0],
+            TrashEntry {
+                name: "com.tasker.tasks.b.1600000000".to_string(),
+                label: "com.tasker.tasks.b".to_string(),
+                deleted_at: format_rfc3339(now - 5 * day),
+                estimated: true,
+                bytes: 20,
+                has_yaml: true,
+                live_task_exists: false,
+            }
+        );
+        assert_eq!(entries[1].label, "com.tasker.tasks.b");
+        assert!(!entries[1].has_yaml);
+        let marked = &entries[2];
+        assert_eq!(marked.deleted_at, format_rfc3339(now - 2 * day));
+        assert!(!marked.estimated);
+        assert!(marked.has_yaml);
+        assert!(marked.live_task_exists);
+        assert!(marked.bytes > 1010);
+        assert_eq!(entries[3].label, "com.tasker.tasks.c");
+        assert_eq!(entries[3].bytes, 50);
+
+        let entries = trash_entries(trash, &live, TrashSort::Size);
+        let sizes: Vec<u64> = entries.iter().skip(1).map(|e| e.bytes).collect();
+        assert_eq!(entries[0].name, "com.tasker.tasks.a");
+        assert_eq!(sizes, vec![300, 50, 20]);
+
+        assert_eq!(
+            trashed_label("com.tasker.tasks.out.1"),
+            "com.tasker.tasks.out.1"
+        );
+        assert_eq!(
+            trashed_label("com.tasker.tasks.d.2020"),
+            "com.tasker.tasks.d.2020"
+        );
+        std::fs::remove_dir_all(trash).unwrap();
+    }
+
+    ///
+    /// a process that exits on the signals in `exits_on`, and otherwise keeps running
+    ///
+    struct FakeProcess {
+        running: bool,
+        exits_on: Vec<libc::c_int>,
+        signals: Vec<libc::c_int>,
+        kickstarts: usize,
+    }
+
+    impl TaskProcess for FakeProcess {
+        fn signal(&mut self, _: i32, signal: libc::c_int, _: bool) -> Result<(), Error> {
+            if !self.running {
+                return Err(Error::TaskNotRunning("gone".to_string()));
+            }
+            self.signals.push(signal);
+            if self.exits_on.contains(&signal) {
+                self.running = false;
+            }
+            Ok(())
+        }
+
+        fn alive(&mut self, _: i32) -> bool {
+            self.running
+        }
+
+        fn kickstart(&mut self) -> Result<(), Error> {
+            self.kickstarts += 1;
+            Ok(())
+        }
+    }
+
+    struct FakeClock {
+        now: Instant,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.now += duration;
+        }
+    }
+
+    #[test]
+    fn restart_stages() {
+        let grace = Duration::from_secs(10);
+        let mut clock = FakeClock {
+            now: Instant::now(),
+        };
+        let process = |exits_on: &[libc::c_int]| FakeProcess {
+            running: true,
+            exits_on: exits_on.to_vec(),
+            signals: Vec::new(),
+            kickstarts: 0,
+        };
+
+        // traps SIGINT, ignores SIGTERM
+        let mut ignores_term = process(&[libc::SIGINT, libc::SIGKILL]);
+        let stopped = stop_and_kickstart(
+            &mut ignores_term,
+            &mut clock,
+            Some(42),
+            libc::SIGTERM,
+            false,
+            grace,
+        );
+        assert_eq!(stopped.unwrap(), (StopStage::Kill, grace));
+        assert_eq!(ignores_term.signals, vec![libc::SIGTERM, libc::SIGKILL]);
+        assert_eq!(ignores_term.kickstarts, 1);
+
+        let mut traps_int = process(&[libc::SIGINT, libc::SIGKILL]);
+        let stopped = stop_and_kickstart(
+            &mut traps_int,
+            &mut clock,
+            Some(42),
+            libc::SIGINT,
+            true,
+            grace,
+        );
+        assert_eq!(
+            stopped.unwrap(),
+            (StopStage::Signal, Duration::from_secs(0))
+        );
+        assert_eq!(traps_int.signals, vec![libc::SIGINT]);
+        assert_eq!(traps_int.kickstarts, 1);
+
+        // not running, or gone before the signal
+        let mut idle = process(&[]);
+        let stopped = stop_and_kickstart(&mut idle, &mut clock, None, libc::SIGTERM, false, grace);
+        assert_eq!(stopped.unwrap().0, StopStage::NotRunning);
+        idle.running = false;
+        let stopped =
+            stop_and_kickstart(&mut idle, &mut clock, Some(42), libc::SIGTERM, false, grace);
+        assert_eq!(stopped.unwrap().0, StopStage::NotRunning);
+        assert!(idle.signals.is_empty());
+        assert_eq!(idle.kickstarts, 2);
+
+        // stopping a run alone does not start the task again
+        let mut ignores_term = process(&[libc::SIGKILL]);
+        let stopped = stop_process(
+            &mut ignores_term,
+            &mut clock,
+            42,
+            libc::SIGTERM,
+            false,
+            grace,
+        );
+        assert_eq!(stopped.unwrap(), (StopStage::Kill, grace));
+        assert_eq!(ignores_term.signals, vec![libc::SIGTERM, libc::SIGKILL]);
+        assert_eq!(ignores_term.kickstarts, 0);
+    }
+
+    #[test]
+    fn stop_signal_allowlist() {
+        assert_eq!(stop_signal(None).unwrap(), ("TERM", libc::SIGTERM));
+        assert_eq!(stop_signal(Some("INT")).unwrap(), ("INT", libc::SIGINT));
+        assert_eq!(
+            stop_signal(Some("sigusr2")).unwrap(),
+            ("USR2", libc::SIGUSR2)
+        );
+        for refused in &["STOP", "SEGV", "9", "", "SIG"] {
+            match stop_signal(Some(refused)) {
+                Err(Error::InvalidSignal(_)) => {}
+                _ => panic!("`{}` must be refused", refused),
+            }
+        }
+    }
+
+    #[test]
+    fn task_conflicts() {
+        let root = Path::new("test_conflicts");
+        let _ = std::fs::remove_dir_all(root);
+        let (meta, tasks, out) = (root.join("meta"), root.join("tasks"), root.join("out"));
+        for dir in &[&meta, &tasks.join("com.tasker.tasks.a"), &out] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::os::unix::fs::symlink("tasks/com.tasker.tasks.a", root.join("link")).unwrap();
+        let link = root.join("link").canonicalize().unwrap();
+        let task_yaml = |label: &str, program: &str, configuration: &str| {
+            let yaml = format!(
+                "Label: {}\nProgram: {}\nConfiguration:\n{}",
+                label, program, configuration
+            );
+            let yaml_file = meta.join(format!("com.tasker.tasks.{}.yaml", label));
+            std::fs::write(yaml_file, yaml).unwrap();
+        };
+        let backup = "  - ProgramArguments: [/bin/sh, backup.sh]\n";
+        task_yaml(
+            "a",
+            "/bin/sh",
+            &(backup.to_string() + "  - StartCalendarInterval: [{Hour: 3, Minute: 0}]\n"),
+        );
+        // the same command at another time
+        task_yaml(
+            "b",
+            "/bin/sh",
+            &(backup.to_string() + "  - StartCalendarInterval: [{Hour: 4, Minute: 0}]\n"),
+        );
+        // the same time as `a`, in the task folder of `a` through a symlink
+        task_yaml(
+            "c",
+            "/bin/echo",
+            &format!(
+                "  - StartCalendarInterval: [{{Hour: 12}}, {{Minute: 0, Hour: 3}}]\n  \
+                 - WorkingDirectory: {}/data\n",
+                root.canonicalize().unwrap().join("link").display()
+            ),
+        );
+        // a clean pair: the same script in their own folders, at other times,
+        // and `~root~/../com.tasker.tasks.e` is the folder of `e` itself
+        task_yaml(
+            "d",
+            "/bin/sh",
+            "  - ProgramArguments: [/bin/sh, ~root~/run.sh]\n  \
+             - StartCalendarInterval: [{Hour: 3}]\n",
+        );
+        task_yaml(
+            "e",
+            "/bin/sh",
+            "  - ProgramArguments: [/bin/sh, ~root~/run.sh]\n  \
+             - StartCalendarInterval: [{Hour: 3, Minute: 30}]\n  \
+             - WorkingDirectory: ~root~/../com.tasker.tasks.e\n",
+        );
+        // templates do not run
+        task_yaml(
+            "f",
+            "/bin/echo",
+            "  - StartCalendarInterval: [{Hour: 12}]\n",
+        );
+        let template = meta.join("com.tasker.tasks.f.yaml");
+        let yaml = std::fs::read_to_string(&template).unwrap() + "Template: true\n";
+        std::fs::write(&template, yaml).unwrap();
+
+        let mut index = MetaIndex::load(&meta);
+        let metas: Vec<(String, YamlMeta)> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|label| {
+                let label = format!("com.tasker.tasks.{}", label);
+                let meta = index.get(&label, parse_yaml_meta).unwrap();
+                assert_eq!(meta.error, None);
+                (label, meta)
+            })
+            .collect();
+        let conflicts = config_conflicts(&runnable_configs(&metas), &tasks, &out);
+        let pairs: Vec<(ConflictKind, Vec<&str>)> = conflicts
+            .iter()
+            .map(|c| (c.kind, c.labels.iter().map(|l| &l[17..]).collect()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (ConflictKind::SameCommand, vec!["a", "b"]),
+                (ConflictKind::SameCalendarInterval, vec!["a", "c"]),
+                (ConflictKind::WorkingDirectoryInTask, vec!["c", "a"]),
+            ]
+        );
+        assert_eq!(
+            conflicts[0].detail,
+            "`com.tasker.tasks.a` and `com.tasker.tasks.b` both run `/bin/sh backup.sh`"
+        );
+        assert!(conflicts[1].detail.ends_with(r#"{"Minute":0,"Hour":3}"#));
+        assert!(conflicts[2]
+            .detail
+            .contains(&format!("`{}`", link.join("data").display())));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn meta_labels_skip_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let meta = Path::new("test_meta_non_utf8");
+        let _ = std::fs::remove_dir_all(meta);
+        std::fs::create_dir_all(meta).unwrap();
+        for label in &["com.tasker.tasks.a", "com.tasker.tasks.タスク"] {
+            std::fs::write(meta.join(format!("{}.yaml", label)), "Program: /bin/echo\n").unwrap();
         }
+        let invalid = OsStr::from_bytes(b"com.tasker.tasks.\xff\xfe.yaml");
+        // some file systems, such as APFS, refuse names that are not UTF-8
+        let created = std::fs::write(meta.join(invalid), "Program: /bin/echo\n").is_ok();
+        let mut labels = yaml_labels_in(meta, &LabelMatcher::all()).unwrap();
+        labels.sort();
+        assert_eq!(
+            labels,
+            vec!["com.tasker.tasks.a", "com.tasker.tasks.タスク"]
+        );
+        assert_eq!(
+            yaml_labels_in(meta, &LabelMatcher::substring("タスク")).unwrap(),
+            vec!["com.tasker.tasks.タスク"]
+        );
+        assert_eq!(
+            std::fs::read_dir(meta).unwrap().count(),
+            2 + created as usize
+        );
+        std::fs::remove_dir_all(meta).unwrap();
     }
 }
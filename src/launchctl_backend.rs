@@ -0,0 +1,227 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::execute_command_timeout;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex, RwLock};
+
+lazy_static! {
+    static ref BACKEND: RwLock<Arc<dyn LaunchctlBackend>> = RwLock::new(Arc::new(SystemLaunchctl));
+}
+
+///
+/// runs `launchctl`, so that launchd can be replaced, e.g. by `MockLaunchctl` in tests
+///
+pub trait LaunchctlBackend: Send + Sync {
+    ///
+    /// the stdout of `launchctl <args>`, or `Error::CommandExecutionError` if it fails
+    ///
+    fn run(&self, args: &[String]) -> Result<String, Error>;
+}
+
+///
+/// the `launchctl` of the system, killed after `COMMAND_TIMEOUT_SECS`
+///
+pub struct SystemLaunchctl;
+
+impl LaunchctlBackend for SystemLaunchctl {
+    fn run(&self, args: &[String]) -> Result<String, Error> {
+        execute_command_timeout(
+            Command::new("launchctl").args(args),
+            Env::get().command_timeout,
+        )
+    }
+}
+
+///
+/// run every later launchctl command with `backend`
+///
+pub fn set_backend(backend: Arc<dyn LaunchctlBackend>) {
+    *BACKEND.write().unwrap() = backend;
+}
+
+pub(crate) fn backend() -> Arc<dyn LaunchctlBackend> {
+    BACKEND.read().unwrap().clone()
+}
+
+///
+/// launchd in memory: `load` (or `bootstrap`) and `unload` (or `bootout`) a plist by
+/// its `Label`, `list` and `print` the loaded jobs, and `kill` and `kickstart` them.
+/// No process is started. Every command is recorded, see `calls`.
+///
+#[derive(Default)]
+pub struct MockLaunchctl {
+    state: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    /// the domain (`system` or `gui/<uid>`) of each loaded job
+    loaded: BTreeMap<String, String>,
+    calls: Vec<Vec<String>>,
+    /// the subcommand whose next call fails, see `fail_next`
+    fail_next: Option<String>,
+}
+
+impl MockLaunchctl {
+    ///
+    /// the arguments of every command run so far, oldest first
+    ///
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    pub fn is_loaded(&self, label: &str) -> bool {
+        self.state.lock().unwrap().loaded.contains_key(label)
+    }
+
+    ///
+    /// fail the next call of `subcommand`, e.g. `load`, as launchd refusing a job
+    ///
+    pub fn fail_next(&self, subcommand: &str) {
+        self.state.lock().unwrap().fail_next = Some(subcommand.to_string());
+    }
+}
+
+impl LaunchctlBackend for MockLaunchctl {
+    fn run(&self, args: &[String]) -> Result<String, Error> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(args.to_vec());
+        if state.fail_next.is_some() && state.fail_next.as_ref() == args.first() {
+            state.fail_next = None;
+            return Err(failure("Operation not permitted"));
+        }
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        match args.as_slice() {
+            ["load", plist] => state.load("system", plist),
+            ["bootstrap", domain, plist] => state.load(domain, plist),
+            ["unload", plist] | ["bootout", _, plist] => {
+                let label = plist_label(plist)?;
+                match state.loaded.remove(&label) {
+                    Some(_) => Ok(String::new()),
+                    None => Err(failure("Could not find specified service")),
+                }
+            }
+            ["list"] => Ok(state
+                .loaded
+                .iter()
+                .filter(|(_, domain)| *domain == "system")
+                .fold("PID\tStatus\tLabel\n".to_string(), |list, (label, _)| {
+                    list + &format!("-\t0\t{}\n", label)
+                })),
+            ["print", target] => {
+                state.find(target)?;
+                Ok(format!("{} = {{\n\tstate = not running\n}}\n", target))
+            }
+            ["kill", _, target] | ["kickstart", "-k", target] => {
+                state.find(target).map(|_| String::new())
+            }
+            _ => Err(failure("Unrecognized subcommand")),
+        }
+    }
+}
+
+impl MockState {
+    fn load(&mut self, domain: &str, plist: &str) -> Result<String, Error> {
+        let label = plist_label(plist)?;
+        if self.loaded.contains_key(&label) {
+            return Err(failure("service already loaded"));
+        }
+        self.loaded.insert(label, domain.to_string());
+        Ok(String::new())
+    }
+
+    ///
+    /// fail unless a service target, e.g. `system/<label>` or `gui/501/<label>`, is loaded
+    ///
+    fn find(&self, target: &str) -> Result<(), Error> {
+        match target.rsplitn(2, '/').collect::<Vec<&str>>().as_slice() {
+            [label, domain] if self.loaded.get(*label).map(|d| d.as_str()) == Some(*domain) => {
+                Ok(())
+            }
+            _ => Err(failure("Could not find service in domain")),
+        }
+    }
+}
+
+fn plist_label(plist: &str) -> Result<String, Error> {
+    match plist::Value::from_file(Path::new(plist)) {
+        Ok(plist::Value::Dictionary(dict)) => match dict.get("Label").and_then(|l| l.as_string()) {
+            Some(label) => Ok(label.to_string()),
+            None => Err(failure("Invalid property list")),
+        },
+        _ => Err(failure("Load failed: 5: Input/output error")),
+    }
+}
+
+fn failure(stderr: &str) -> Error {
+    Error::CommandExecutionError(stderr.to_string())
+}
+
+#[cfg(test)]
+mod test_launchctl_backend_mod {
+    use super::*;
+
+    fn args(command: &str) -> Vec<String> {
+        command.split(' ').map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn mock_launchd() {
+        let folder = Path::new("test_mock_launchctl");
+        std::fs::create_dir_all(folder).unwrap();
+        let plist = folder.join("com.tasker.tasks.mock.plist");
+        std::fs::write(
+            &plist,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <plist version=\"1.0\"><dict>\
+             <key>Label</key><string>com.tasker.tasks.mock</string>\
+             </dict></plist>\n",
+        )
+        .unwrap();
+        let plist = plist.display().to_string();
+        let launchd = MockLaunchctl::default();
+
+        assert!(launchd
+            .run(&args("kickstart -k system/com.tasker.tasks.mock"))
+            .is_err());
+        launchd.run(&args(&format!("load {}", plist))).unwrap();
+        assert!(launchd.is_loaded("com.tasker.tasks.mock"));
+        assert!(launchd.run(&args(&format!("load {}", plist))).is_err());
+        assert_eq!(
+            launchd.run(&args("list")).unwrap(),
+            "PID\tStatus\tLabel\n-\t0\tcom.tasker.tasks.mock\n"
+        );
+        launchd
+            .run(&args("kickstart -k system/com.tasker.tasks.mock"))
+            .unwrap();
+        // loaded into the system domain, not a user session
+        assert!(launchd
+            .run(&args("print gui/501/com.tasker.tasks.mock"))
+            .is_err());
+        launchd.fail_next("unload");
+        assert!(launchd.run(&args(&format!("unload {}", plist))).is_err());
+        assert!(launchd.is_loaded("com.tasker.tasks.mock"));
+        launchd.run(&args(&format!("unload {}", plist))).unwrap();
+        assert!(!launchd.is_loaded("com.tasker.tasks.mock"));
+        assert!(launchd.run(&args(&format!("unload {}", plist))).is_err());
+
+        // agents are listed by `print` only
+        launchd
+            .run(&args(&format!("bootstrap gui/501 {}", plist)))
+            .unwrap();
+        assert_eq!(launchd.run(&args("list")).unwrap(), "PID\tStatus\tLabel\n");
+        assert!(launchd
+            .run(&args("print gui/501/com.tasker.tasks.mock"))
+            .unwrap()
+            .starts_with("gui/501/com.tasker.tasks.mock = {"));
+        launchd
+            .run(&args(&format!("bootout gui/501 {}", plist)))
+            .unwrap();
+
+        assert_eq!(launchd.calls().len(), 13);
+        assert_eq!(launchd.calls()[1], args(&format!("load {}", plist)));
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
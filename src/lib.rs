@@ -3,20 +3,89 @@ extern crate lazy_static;
 #[macro_use]
 extern crate strum_macros;
 
+// everything but `config` and `error` needs the `server` feature (on by default),
+// `--no-default-features` builds the yaml <-> plist layer alone, on any platform.
 static TASKER_TASK_NAME: &str = "com.tasker.tasks";
+#[cfg(feature = "server")]
 static PLIST_FOLDER: &str = "/Library/LaunchDaemons/";
+#[cfg(feature = "server")]
+static AGENT_PLIST_FOLDER: &str = "/Library/LaunchAgents/";
+#[cfg(feature = "server")]
 static TEMP_UNZIP_FOLDER: &str = "/tmp/tasker.task.com/temp_unzip/";
+#[cfg(feature = "server")]
 static TEMP_ZIP_FOLDER: &str = "/tmp/tasker.task.com/temp_zip/";
+#[cfg(feature = "server")]
 static TEMP_ZIP_PATH: &str = "/tmp/tasker.task.com/";
 static TASK_ROOT_ALIAS: &str = "~root~/";
+static TASK_OUT_ALIAS: &str = "~out~/";
+#[cfg(feature = "server")]
 static STD_OUT_FILE: &str = "stdout.log";
+#[cfg(feature = "server")]
 static STD_ERR_FILE: &str = "stderr.log";
+#[cfg(feature = "server")]
+static LOG_KEEP_COUNT: usize = 5;
+#[cfg(feature = "server")]
+static DESIRED_STATE_FILE: &str = "desired_state.json";
+#[cfg(feature = "server")]
+static PLIST_CHECKSUM_FILE: &str = "plist_checksums.json";
+#[cfg(feature = "server")]
+static RUN_STATE_FILE: &str = "run_state.json";
 
+#[cfg(feature = "server")]
+mod api_doc;
+#[cfg(feature = "server")]
+pub mod auth;
+#[cfg(feature = "server")]
+pub mod backup;
+#[cfg(feature = "server")]
+pub mod cleanup;
+#[cfg(feature = "server")]
+mod command_queue;
+#[cfg(feature = "server")]
+pub mod concurrency;
 /// the config module provides api to convert task configuration to and from yaml and
 /// apple plist.
-mod config;
-mod error;
+pub mod config;
+#[cfg(feature = "server")]
+pub mod confirmation;
+pub mod error;
+#[cfg(feature = "server")]
+pub mod events;
+#[cfg(feature = "server")]
+mod git;
+#[cfg(feature = "server")]
+mod history;
+#[cfg(feature = "server")]
 pub mod initialize;
+#[cfg(feature = "server")]
 mod launchctl;
+#[cfg(feature = "server")]
+pub mod launchctl_backend;
+#[cfg(feature = "server")]
+pub mod maintenance;
+#[cfg(feature = "server")]
+pub mod manual_run;
+#[cfg(feature = "server")]
+mod meta_index;
+#[cfg(feature = "server")]
+pub mod net_util;
+#[cfg(feature = "server")]
+mod notify;
+#[cfg(feature = "server")]
+mod provenance;
+#[cfg(feature = "server")]
+pub mod rate_limit;
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "server")]
+pub mod settings_snapshot;
+#[cfg(feature = "server")]
+pub mod shutdown;
+#[cfg(feature = "server")]
+mod stats;
+#[cfg(feature = "server")]
+mod supervisor;
+#[cfg(feature = "server")]
+pub mod timing;
+#[cfg(feature = "server")]
 mod utils;
@@ -1,63 +1,159 @@
-use actix_web::dev::ServiceRequest;
-use actix_web::{middleware, web, App, HttpResponse, HttpServer};
-use actix_web_httpauth::extractors::basic::BasicAuth;
-use actix_web_httpauth::middleware::HttpAuthentication;
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
-use tasker::{initialize::Env, server};
-
-async fn validator(
-    req: ServiceRequest,
-    _credentials: BasicAuth,
-) -> Result<ServiceRequest, actix_web::Error> {
-    if _credentials.user_id().eq(&Env::get().user_name)
-        && _credentials.password().is_some()
-        && _credentials.password().unwrap().eq(&Env::get().password)
-    {
-        Ok(req)
+use actix_web::{middleware, HttpServer};
+use tasker::concurrency::ConcurrencyLimiter;
+use tasker::confirmation::Confirmations;
+use tasker::initialize::{self, Env};
+use tasker::maintenance::MaintenanceState;
+use tasker::manual_run::ManualRuns;
+use tasker::rate_limit::RateLimit;
+use tasker::timing::RequestMetrics;
+use tasker::{cleanup, events, net_util, server, settings_snapshot, shutdown};
+
+static USAGE: &str = "usage: tasker [--check | --print-config]
+    --check          check the environment, folders, SSL and launchctl, and exit
+    --print-config   print the effective configuration with secrets masked, and exit";
+
+///
+/// print the result of each check, 0 if all passed
+///
+fn check() -> i32 {
+    let results = initialize::self_check();
+    for result in &results {
+        println!("{}", result);
+    }
+    if results.iter().all(|result| result.passed) {
+        0
     } else {
-        Err(actix_web::Error::from(HttpResponse::Forbidden()))
+        1
     }
 }
 
+fn print_config() -> i32 {
+    match Env::try_init() {
+        Ok(env) => {
+            for (name, value) in env.effective_config() {
+                println!("{}={}", name, value);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{:?}", e);
+            1
+        }
+    }
+}
+
+///
+/// the default access log format, with the client behind a trusted proxy instead of the peer
+///
+fn access_logger() -> middleware::Logger {
+    middleware::Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+        .custom_request_replace("client_ip", |req| {
+            let trusted = &Env::get().trusted_proxies;
+            match net_util::forwarded_client_ip(req.peer_addr(), req.headers(), trusted) {
+                Some(ip) => ip.to_string(),
+                None => "-".to_string(),
+            }
+        })
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let app = HttpServer::new(|| {
-        let auth = HttpAuthentication::basic(validator);
-        App::new()
-            .wrap(middleware::Logger::default())
-            .wrap(auth)
-            .service(server::delete_param)
-            .service(server::load_param)
-            .service(server::unload_param)
-            .service(server::stderr_param)
-            .service(server::stdout_param)
-            .service(server::get_yaml)
-            .service(server::post_yaml)
-            .service(server::get_task_zip)
-            .service(
-                web::resource("/")
-                    .route(web::get().to(server::index))
-                    .route(web::post().to(server::create_new_tasks)),
-            )
-            .service(web::resource("/list_all").route(web::get().to(server::list_all)))
-            .service(web::resource("/list_part.html").route(web::get().to(server::list_part)))
-            .service(web::resource("/edit_yaml.html").route(web::get().to(server::edit_yaml)))
-            .service(web::resource("/stderr.html").route(web::get().to(server::stderr)))
-            .service(web::resource("/stdout.html").route(web::get().to(server::stdout)))
-            .service(server::list_raw_json)
-    });
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => {}
+        ["--check"] => std::process::exit(check()),
+        ["--print-config"] => std::process::exit(print_config()),
+        ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+    settings_snapshot::check_settings_on_start();
+    if Env::get().autoload_on_start {
+        server::autoload_on_start();
+    }
+    server::lint_on_start();
+    let report = cleanup::clean_temp(Env::get().temp_max_age);
+    println!(
+        "temp cleanup: {} files removed, {} bytes freed",
+        report.files_removed, report.bytes_freed
+    );
+    if let Some(retention) = Env::get().trash_retention {
+        cleanup::purge_expired_trash(retention);
+    }
+    actix_web::rt::spawn(server::rotate_logs_periodically());
+    actix_web::rt::spawn(server::purge_trash_periodically());
+    actix_web::rt::spawn(server::clean_temp_periodically());
+    actix_web::rt::spawn(server::backup_periodically());
+    actix_web::rt::spawn(events::poll_changes_periodically());
 
+    let limiter = RateLimit::shared_limiter();
+    let confirmations = Confirmations::shared();
+    let maintenance = MaintenanceState::shared();
+    let manual_runs = ManualRuns::shared();
+    let metrics = RequestMetrics::shared();
+    let concurrency = ConcurrencyLimiter::shared();
+    if maintenance.read().unwrap().enabled {
+        println!("maintenance mode is on, mutations are refused");
+    }
     let env = Env::get();
-    if let (Some(pk), Some(crt)) = (&env.pk_dir, &env.crt_dir) {
-        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-        builder
-            .set_private_key_file(pk, SslFiletype::PEM)
-            .expect("private ssl key error");
-        builder
-            .set_certificate_chain_file(crt)
-            .expect("ssl crt file error");
-        app.bind_openssl(Env::get().address(), builder)?.run().await
+    let app = HttpServer::new(move || {
+        server::build_app(
+            limiter.clone(),
+            confirmations.clone(),
+            maintenance.clone(),
+            manual_runs.clone(),
+            metrics.clone(),
+            concurrency.clone(),
+        )
+        .wrap(access_logger())
+    })
+    .shutdown_timeout(env.shutdown_timeout.as_secs());
+
+    println!("tasker listening on {}", env.address());
+    println!("tasker pages at {}", env.public_url());
+    if let Some(certificate) = &env.certificate {
+        let days_remaining = certificate.days_remaining_now();
+        if days_remaining < initialize::CERTIFICATE_WARNING_DAYS {
+            eprintln!(
+                "warning: ssl certificate `{}` expires in {} days at {}",
+                certificate.subject,
+                days_remaining,
+                certificate.expires_at().unwrap_or_default()
+            );
+        }
+    }
+    let server = if let Some(socket) = &env.unix_socket {
+        if env.pk_dir.is_some() || env.crt_dir.is_some() {
+            eprintln!("warning: SSL is not used with UNIX_SOCKET");
+        }
+        env.prepare_unix_socket().expect("unix socket error");
+        let app = app.bind_uds(socket)?;
+        env.secure_unix_socket().expect("unix socket error");
+        app.run()
+    } else if let (Some(pk), Some(crt)) = (&env.pk_dir, &env.crt_dir) {
+        let builder = initialize::ssl_acceptor(pk, crt).expect("ssl error");
+        app.bind_openssl(env.bind_address(), builder)?.run()
     } else {
-        app.bind(Env::get().address())?.run().await
+        app.bind(env.bind_address())?.run()
+    };
+
+    // stopped by SIGINT, SIGTERM, `/shutdown` or `/reload_tls`
+    shutdown::register(server.clone());
+    let result = server.await;
+    shutdown::finish(env.shutdown_timeout);
+    if result.is_ok() && shutdown::restart_requested() {
+        println!("restarting tasker");
+        return Err(shutdown::re_exec());
     }
+    result
 }
@@ -0,0 +1,272 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::{format_rfc3339, read_utf8_file, write_atomic};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::HttpResponse;
+use futures::future::{err, ok, Either, Ready};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+static MAINTENANCE_FILE: &str = "maintenance.json";
+pub static DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+/// reachable in maintenance mode, so that it can be turned off and the server restarted
+static MAINTENANCE_EXEMPT: [&str; 2] = ["/maintenance", "/shutdown"];
+/// routes changing tasks with `GET`, every other method is a mutation
+static MUTATING_GETS: [&str; 6] = [
+    "/delete",
+    "/load",
+    "/unload",
+    "/stop",
+    "/restart",
+    "/rotate_logs",
+];
+/// `POST` routes without side effects
+static READ_ONLY_POSTS: [&str; 1] = ["/validate/config_entry"];
+
+///
+/// the maintenance mode, persisted in `meta/maintenance.json` to survive restarts
+///
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    pub message: String,
+    /// when maintenance mode was last turned on
+    pub since: Option<String>,
+    /// `Retry-After` of refused requests
+    pub retry_after_secs: u64,
+}
+
+impl Default for MaintenanceState {
+    fn default() -> MaintenanceState {
+        MaintenanceState {
+            enabled: false,
+            message: String::new(),
+            since: None,
+            retry_after_secs: DEFAULT_RETRY_AFTER_SECS,
+        }
+    }
+}
+
+///
+/// the body of `POST /maintenance`
+///
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MaintenanceUpdate {
+    pub enabled: bool,
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+}
+
+fn maintenance_file() -> PathBuf {
+    Env::get().meta_dir.join(MAINTENANCE_FILE)
+}
+
+impl MaintenanceState {
+    ///
+    /// maintenance mode is off if the state file is missing or corrupted
+    ///
+    pub fn load(file: &Path) -> MaintenanceState {
+        match read_utf8_file(file) {
+            Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
+            Err(_) => MaintenanceState::default(),
+        }
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), Error> {
+        match serde_json::to_string_pretty(self) {
+            Ok(s) => write_atomic(file, s.as_bytes()),
+            Err(e) => Err(Error::CopyError(format!("{}", e))),
+        }
+    }
+
+    ///
+    /// the persisted maintenance mode, shared by all workers
+    ///
+    pub fn shared() -> Arc<RwLock<MaintenanceState>> {
+        Arc::new(RwLock::new(MaintenanceState::load(&maintenance_file())))
+    }
+
+    ///
+    /// apply `update`, keeping `since` while maintenance mode stays on
+    ///
+    pub fn update(&mut self, update: MaintenanceUpdate, now: SystemTime) {
+        if update.enabled && !self.enabled {
+            self.since = format_rfc3339(now);
+        }
+        if !update.enabled {
+            self.since = None;
+        }
+        self.enabled = update.enabled;
+        self.message = update.message.unwrap_or_default();
+        self.retry_after_secs = update.retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+    }
+}
+
+///
+/// apply `update` to the shared state and persist it
+///
+pub fn set_maintenance(
+    state: &RwLock<MaintenanceState>,
+    update: MaintenanceUpdate,
+) -> Result<MaintenanceState, Error> {
+    let mut state = state.write().unwrap();
+    state.update(update, SystemTime::now());
+    state.save(&maintenance_file())?;
+    Ok(state.clone())
+}
+
+///
+/// whether a request changes tasks, and is refused in maintenance mode
+///
+pub fn is_mutation(method: &Method, path: &str) -> bool {
+    if MAINTENANCE_EXEMPT.contains(&path) || READ_ONLY_POSTS.contains(&path) {
+        return false;
+    }
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => MUTATING_GETS.contains(&path),
+        _ => true,
+    }
+}
+
+///
+/// middleware refusing mutations with `503` while maintenance mode is on.
+/// It is wrapped inside `Auth`, so unauthenticated requests are still `401`.
+///
+pub struct MaintenanceGuard {
+    state: Arc<RwLock<MaintenanceState>>,
+}
+
+impl MaintenanceGuard {
+    pub fn new(state: Arc<RwLock<MaintenanceState>>) -> MaintenanceGuard {
+        MaintenanceGuard { state }
+    }
+}
+
+impl<S, B> Transform<S> for MaintenanceGuard
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = MaintenanceGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(MaintenanceGuardMiddleware {
+            service,
+            state: self.state.clone(),
+        })
+    }
+}
+
+pub struct MaintenanceGuardMiddleware<S> {
+    service: S,
+    state: Arc<RwLock<MaintenanceState>>,
+}
+
+impl<S, B> Service for MaintenanceGuardMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !is_mutation(req.method(), req.path()) {
+            return Either::Left(self.service.call(req));
+        }
+        let state = self.state.read().unwrap();
+        if !state.enabled {
+            drop(state);
+            return Either::Left(self.service.call(req));
+        }
+        Either::Right(err(actix_web::Error::from(
+            HttpResponse::ServiceUnavailable()
+                .header(header::RETRY_AFTER, state.retry_after_secs.to_string())
+                .json(&*state),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test_maintenance_mod {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn guarded_routes() {
+        for path in &[
+            "/delete",
+            "/load",
+            "/unload",
+            "/stop",
+            "/restart",
+            "/rotate_logs",
+        ] {
+            assert!(is_mutation(&Method::GET, path));
+        }
+        for path in &["/", "/post_yaml", "/file", "/schedule", "/cleanup_temp"] {
+            assert!(is_mutation(&Method::POST, path));
+        }
+        assert!(is_mutation(&Method::DELETE, "/artifact"));
+        for path in &["/", "/list_raw_json", "/file", "/artifact", "/health"] {
+            assert!(!is_mutation(&Method::GET, path));
+        }
+        assert!(!is_mutation(&Method::POST, "/maintenance"));
+        assert!(!is_mutation(&Method::POST, "/shutdown"));
+        assert!(!is_mutation(&Method::POST, "/validate/config_entry"));
+    }
+
+    #[test]
+    fn persisted_state() {
+        let file = Path::new("test_maintenance.json");
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut state = MaintenanceState::default();
+        let enable = |message: &str| MaintenanceUpdate {
+            enabled: true,
+            message: Some(message.to_string()),
+            retry_after_secs: None,
+        };
+        state.update(enable("upgrading until 5pm"), now);
+        // `since` is kept while maintenance mode stays on
+        state.update(enable("upgrading until 6pm"), now + Duration::from_secs(60));
+        assert_eq!(state.since.as_deref(), Some("2020-09-13T12:26:40Z"));
+        state.save(file).unwrap();
+        assert_eq!(MaintenanceState::load(file), state);
+        assert_eq!(MaintenanceState::load(file).message, "upgrading until 6pm");
+
+        std::fs::write(file, "corrupted").unwrap();
+        assert_eq!(MaintenanceState::load(file), MaintenanceState::default());
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(MaintenanceState::load(file), MaintenanceState::default());
+
+        state.update(
+            MaintenanceUpdate {
+                enabled: false,
+                message: None,
+                retry_after_secs: Some(60),
+            },
+            now,
+        );
+        assert!(!state.enabled);
+        assert_eq!(state.since, None);
+        assert_eq!(state.retry_after_secs, 60);
+    }
+}
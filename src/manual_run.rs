@@ -0,0 +1,200 @@
+use crate::utils::format_rfc3339;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+static RUN_ID_BYTES: usize = 8;
+/// finished runs kept for `/run_once_status`, the oldest are forgotten first
+static FINISHED_RUNS_KEPT: usize = 100;
+
+///
+/// a task started by `/run_once` outside of launchd
+///
+pub struct ManualRun {
+    label: String,
+    child: Child,
+    started_at: Option<String>,
+    /// paths of the output files relative to the output folder of the task
+    stdout: String,
+    stderr: String,
+    finished: Option<(Option<i32>, Option<i32>)>,
+}
+
+impl ManualRun {
+    pub fn new(label: &str, child: Child, stdout: String, stderr: String) -> ManualRun {
+        ManualRun {
+            label: label.to_string(),
+            child,
+            started_at: format_rfc3339(SystemTime::now()),
+            stdout,
+            stderr,
+            finished: None,
+        }
+    }
+
+    ///
+    /// check whether the process exited, reaping it
+    ///
+    fn poll(&mut self) {
+        if self.finished.is_some() {
+            return;
+        }
+        if let Ok(Some(status)) = self.child.try_wait() {
+            self.finished = Some((status.code(), status.signal()));
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct RunStatus {
+    run_id: String,
+    label: String,
+    pid: u32,
+    started_at: Option<String>,
+    running: bool,
+    exit_status: Option<i32>,
+    /// the signal which terminated the run
+    signal: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+///
+/// runs started by `/run_once`, by a random run id. Overrides of a run are never stored.
+///
+pub struct ManualRuns {
+    kept: usize,
+    runs: HashMap<String, (u64, ManualRun)>,
+    count: u64,
+}
+
+impl ManualRuns {
+    pub fn new(kept: usize) -> ManualRuns {
+        ManualRuns {
+            kept,
+            runs: HashMap::new(),
+            count: 0,
+        }
+    }
+
+    ///
+    /// manual runs keeping `FINISHED_RUNS_KEPT` finished runs, shared by all workers
+    ///
+    pub fn shared() -> Arc<Mutex<ManualRuns>> {
+        Arc::new(Mutex::new(ManualRuns::new(FINISHED_RUNS_KEPT)))
+    }
+
+    ///
+    /// track `run`, returning its status with a new run id
+    ///
+    pub fn insert(&mut self, run: ManualRun) -> RunStatus {
+        self.remove_finished();
+        let mut bytes = [0u8; RUN_ID_BYTES];
+        openssl::rand::rand_bytes(&mut bytes).expect("cannot generate random run id");
+        let run_id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        self.count += 1;
+        let status = RunStatus::of(&run_id, &run);
+        self.runs.insert(run_id, (self.count, run));
+        status
+    }
+
+    pub fn status(&mut self, run_id: &str) -> Option<RunStatus> {
+        let (_, run) = self.runs.get_mut(run_id)?;
+        run.poll();
+        Some(RunStatus::of(run_id, run))
+    }
+
+    ///
+    /// reap finished runs, and forget the oldest of them beyond `kept`
+    ///
+    fn remove_finished(&mut self) {
+        let mut finished = Vec::new();
+        for (run_id, (count, run)) in self.runs.iter_mut() {
+            run.poll();
+            if run.finished.is_some() {
+                finished.push((*count, run_id.clone()));
+            }
+        }
+        finished.sort();
+        let excess = finished.len().saturating_sub(self.kept);
+        for (_, run_id) in finished.into_iter().take(excess) {
+            self.runs.remove(&run_id);
+        }
+    }
+}
+
+impl RunStatus {
+    fn of(run_id: &str, run: &ManualRun) -> RunStatus {
+        let (exit_status, signal) = run.finished.unwrap_or((None, None));
+        RunStatus {
+            run_id: run_id.to_string(),
+            label: run.label.clone(),
+            pid: run.child.id(),
+            started_at: run.started_at.clone(),
+            running: run.finished.is_none(),
+            exit_status,
+            signal,
+            stdout: run.stdout.clone(),
+            stderr: run.stderr.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_manual_run_mod {
+    use super::*;
+    use std::process::Command;
+    use std::time::Duration;
+
+    fn spawn(script: &str) -> ManualRun {
+        let child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(script)
+            .spawn()
+            .unwrap();
+        ManualRun::new("manual", child, "out".to_string(), "err".to_string())
+    }
+
+    fn wait(runs: &mut ManualRuns, run_id: &str) -> RunStatus {
+        for _ in 0..500 {
+            let status = runs.status(run_id).unwrap();
+            if !status.running {
+                return status;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("run {} did not finish", run_id);
+    }
+
+    #[test]
+    fn run_status() {
+        let mut runs = ManualRuns::new(1);
+        let started = runs.insert(spawn("exit 3"));
+        assert_eq!(started.run_id.len(), RUN_ID_BYTES * 2);
+        assert_eq!(started.label, "manual");
+        let finished = wait(&mut runs, &started.run_id);
+        assert_eq!(finished.exit_status, Some(3));
+        assert_eq!(finished.signal, None);
+        assert_eq!(finished.pid, started.pid);
+
+        let killed = runs.insert(spawn("kill -9 $$"));
+        let killed = wait(&mut runs, &killed.run_id);
+        assert_eq!(killed.exit_status, None);
+        assert_eq!(killed.signal, Some(9));
+        assert_eq!(runs.status("unknown"), None);
+
+        // only the last finished run is kept, running ones are never forgotten
+        let running = runs.insert(spawn("sleep 10"));
+        assert_eq!(runs.status(&started.run_id), None);
+        assert!(runs.status(&killed.run_id).is_some());
+        runs.insert(spawn("exit 0"));
+        assert!(runs.status(&running.run_id).unwrap().running);
+        let (_, run) = runs.runs.get_mut(&running.run_id).unwrap();
+        run.child.kill().unwrap();
+        assert_eq!(wait(&mut runs, &running.run_id).signal, Some(9));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::error::Error;
+use crate::utils::{read_utf8_file, sha256_hex, write_atomic};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub static META_INDEX_FILE: &str = "index.json";
+/// an index written by another version is rebuilt, as parsing may have changed
+static META_INDEX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+///
+/// what the index knows of a yaml: its mtime and content hash when it was parsed,
+/// and the summary parsed from it
+///
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct IndexEntry<T> {
+    modified_at: SystemTime,
+    sha256: String,
+    summary: T,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexFile<T> {
+    version: String,
+    entries: BTreeMap<String, IndexEntry<T>>,
+}
+
+///
+/// summaries of the yaml files in meta folder by label, persisted in `meta/index.json`,
+/// so that listing many tasks after a restart does not parse every yaml again.
+/// An entry is parsed again when the mtime of its yaml changes and its content hash
+/// changes too, and the index is rebuilt from scratch if it is missing or corrupt.
+///
+pub struct MetaIndex<T> {
+    meta_dir: PathBuf,
+    entries: BTreeMap<String, IndexEntry<T>>,
+    /// entries changed since the index was last saved
+    changed: bool,
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> MetaIndex<T> {
+    ///
+    /// the index of `meta_dir`, empty if it is missing, corrupt, or of another version
+    ///
+    pub fn load(meta_dir: &Path) -> MetaIndex<T> {
+        let index_file = read_utf8_file(&meta_dir.join(META_INDEX_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<IndexFile<T>>(&json).ok())
+            .filter(|index| index.version == META_INDEX_VERSION);
+        let changed = index_file.is_none();
+        MetaIndex {
+            meta_dir: meta_dir.to_path_buf(),
+            entries: index_file.map(|index| index.entries).unwrap_or_default(),
+            changed,
+        }
+    }
+
+    fn yaml_file(&self, label: &str) -> PathBuf {
+        self.meta_dir.join(String::from(label) + ".yaml")
+    }
+
+    ///
+    /// the summary of the yaml of `label`, `None` if it does not exist.
+    /// The yaml is read if its mtime differs from the entry, and passed to `parse`
+    /// only if its content differs as well.
+    ///
+    pub fn get<F>(&mut self, label: &str, parse: F) -> Option<T>
+    where
+        F: FnOnce(Result<&str, Error>) -> T,
+    {
+        let yaml_file = self.yaml_file(label);
+        let mtime = match std::fs::metadata(&yaml_file).and_then(|meta| meta.modified()) {
+            Ok(mtime) => mtime,
+            Err(_) => {
+                self.remove(label);
+                return None;
+            }
+        };
+        if let Some(entry) = self.entries.get(label) {
+            if entry.modified_at == mtime {
+                return Some(entry.summary.clone());
+            }
+        }
+        let yaml = read_utf8_file(&yaml_file);
+        let sha256 = yaml.as_ref().ok().map(|yaml| sha256_hex(yaml.as_bytes()));
+        self.changed = true;
+        match (self.entries.get_mut(label), &sha256) {
+            (Some(entry), Some(sha256)) if entry.sha256 == *sha256 => {
+                entry.modified_at = mtime;
+                return Some(entry.summary.clone());
+            }
+            _ => {}
+        }
+        let summary = parse(match &yaml {
+            Ok(yaml) => Ok(yaml),
+            Err(e) => Err(Error::YamlError(format!("cannot read yaml: {}", e))),
+        });
+        match sha256 {
+            Some(sha256) => {
+                self.entries.insert(
+                    label.to_string(),
+                    IndexEntry {
+                        modified_at: mtime,
+                        sha256,
+                        summary: summary.clone(),
+                    },
+                );
+            }
+            // an unreadable yaml is read again next time
+            None => {
+                self.entries.remove(label);
+            }
+        }
+        Some(summary)
+    }
+
+    ///
+    /// record `summary` of the yaml of `label` just written with `yaml`
+    ///
+    pub fn insert(&mut self, label: &str, yaml: &str, summary: T) {
+        let yaml_file = self.yaml_file(label);
+        match std::fs::metadata(&yaml_file).and_then(|meta| meta.modified()) {
+            Ok(mtime) => {
+                self.entries.insert(
+                    label.to_string(),
+                    IndexEntry {
+                        modified_at: mtime,
+                        sha256: sha256_hex(yaml.as_bytes()),
+                        summary,
+                    },
+                );
+            }
+            Err(_) => {
+                self.entries.remove(label);
+            }
+        }
+        self.changed = true;
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        if self.entries.remove(label).is_some() {
+            self.changed = true;
+        }
+    }
+
+    ///
+    /// drop the entries of yaml files no longer in meta folder
+    ///
+    pub fn retain(&mut self, labels: &[String]) {
+        let count = self.entries.len();
+        self.entries.retain(|label, _| labels.contains(label));
+        if self.entries.len() != count {
+            self.changed = true;
+        }
+    }
+
+    ///
+    /// write the index to meta folder if it changed
+    ///
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.changed {
+            return Ok(());
+        }
+        let index_file = IndexFile {
+            version: META_INDEX_VERSION.to_string(),
+            entries: self.entries.clone(),
+        };
+        let json = match serde_json::to_string(&index_file) {
+            Ok(json) => json,
+            Err(e) => return Err(Error::FailedToUpdateMetaIndex(format!("{}", e))),
+        };
+        write_atomic(&self.meta_dir.join(META_INDEX_FILE), json.as_bytes())?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_meta_index_mod {
+    use super::*;
+    use crate::utils::set_mtime;
+    use std::cell::Cell;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parse_unchanged_yaml_once() {
+        let meta = Path::new("test_meta_index");
+        let _ = std::fs::remove_dir_all(meta);
+        std::fs::create_dir_all(meta).unwrap();
+        let yaml_file = meta.join("a.yaml");
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        std::fs::write(&yaml_file, "Program: /bin/echo\n").unwrap();
+        set_mtime(&yaml_file, mtime).unwrap();
+
+        let parses = Cell::new(0);
+        let parse = |yaml: Result<&str, Error>| {
+            parses.set(parses.get() + 1);
+            yaml.unwrap().len()
+        };
+        let mut index = MetaIndex::<usize>::load(meta);
+        for _ in 0..3 {
+            assert_eq!(index.get("a", parse), Some(19));
+        }
+        assert_eq!(parses.get(), 1);
+        assert_eq!(index.get("b", parse), None);
+        index.save().unwrap();
+
+        // a restart reads the index instead of the yaml
+        let mut index = MetaIndex::<usize>::load(meta);
+        assert_eq!(index.get("a", parse), Some(19));
+        assert_eq!(parses.get(), 1);
+
+        // a touched yaml keeps its entry, an edited one is parsed again
+        set_mtime(&yaml_file, mtime + Duration::from_secs(1)).unwrap();
+        assert_eq!(index.get("a", parse), Some(19));
+        assert_eq!(parses.get(), 1);
+        std::fs::write(&yaml_file, "Program: /bin/ls\n").unwrap();
+        set_mtime(&yaml_file, mtime + Duration::from_secs(2)).unwrap();
+        assert_eq!(index.get("a", parse), Some(17));
+        assert_eq!(parses.get(), 2);
+        index.save().unwrap();
+
+        // a corrupt index is rebuilt, and a deleted yaml leaves it
+        std::fs::write(meta.join(META_INDEX_FILE), "{\"version\":").unwrap();
+        let mut index = MetaIndex::<usize>::load(meta);
+        assert_eq!(index.get("a", parse), Some(17));
+        assert_eq!(parses.get(), 3);
+        std::fs::remove_file(&yaml_file).unwrap();
+        assert_eq!(index.get("a", parse), None);
+        index.save().unwrap();
+        let index = MetaIndex::<usize>::load(meta);
+        assert!(index.entries.is_empty());
+        assert!(!index.changed);
+        std::fs::remove_dir_all(meta).unwrap();
+    }
+}
@@ -0,0 +1,429 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::HeaderMap;
+use actix_web::{HttpMessage, HttpRequest};
+use futures::future::{ok, Ready};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+static X_FORWARDED_FOR: &str = "x-forwarded-for";
+static X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+///
+/// an IPv4 or IPv6 network like `10.0.0.0/8` or `::1/128`.
+/// An address without prefix length is a network of that address only.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Cidr, Error> {
+        let illegal = || Error::IllegalCidr(format!("`{}` is not a CIDR like 10.0.0.0/8", s));
+        let (address, prefix) = match s.trim().find('/') {
+            Some(i) => (&s.trim()[..i], Some(&s.trim()[i + 1..])),
+            None => (s.trim(), None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| illegal())?;
+        let width = bit_width(network);
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| illegal())?,
+            None => width,
+        };
+        if prefix > width {
+            return Err(illegal());
+        }
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        if ip.is_ipv4() != self.network.is_ipv4() {
+            return false;
+        }
+        let width = bit_width(ip);
+        let mask = u128::MAX
+            .checked_shl((width - self.prefix) as u32)
+            .unwrap_or(0);
+        bits(ip) & mask == bits(self.network) & mask
+    }
+}
+
+fn bit_width(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip) as u128,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+///
+/// an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) as IPv4,
+/// which is how IPv4 peers appear on a socket bound to `::`
+///
+fn canonical(ip: IpAddr) -> IpAddr {
+    if let IpAddr::V6(v6) = ip {
+        if let [0, 0, 0, 0, 0, 0xffff, high, low] = v6.segments() {
+            let [a, b] = high.to_be_bytes();
+            let [c, d] = low.to_be_bytes();
+            return IpAddr::V4(Ipv4Addr::new(a, b, c, d));
+        }
+    }
+    ip
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[Cidr]) -> bool {
+    trusted.iter().any(|cidr| cidr.contains(ip))
+}
+
+///
+/// a hop of `X-Forwarded-For`, optionally with a port like `1.2.3.4:5678` or `[::1]:5678`
+///
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim();
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(canonical)
+}
+
+///
+/// the client of a request from `peer` with header `X-Forwarded-For`.
+/// Only trusted proxies are believed: hops are walked from the nearest one,
+/// and the first hop that is not a trusted proxy is the client.
+/// A malformed hop stops the walk at the last address that could be verified.
+///
+pub fn resolve_client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[Cidr]) -> IpAddr {
+    let peer = canonical(peer);
+    let forwarded_for = match forwarded_for {
+        Some(forwarded_for) if is_trusted(peer, trusted) => forwarded_for,
+        _ => return peer,
+    };
+    let mut client = peer;
+    for hop in forwarded_for.rsplit(',') {
+        match parse_hop(hop) {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(ip, trusted) {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    client
+}
+
+///
+/// `http` or `https` from header `X-Forwarded-Proto` if `peer` is a trusted proxy
+///
+pub fn resolve_forwarded_proto(
+    peer: IpAddr,
+    forwarded_proto: Option<&str>,
+    trusted: &[Cidr],
+) -> Option<&'static str> {
+    if !is_trusted(canonical(peer), trusted) {
+        return None;
+    }
+    let proto = forwarded_proto?.split(',').next()?.trim();
+    if proto.eq_ignore_ascii_case("https") {
+        Some("https")
+    } else if proto.eq_ignore_ascii_case("http") {
+        Some("http")
+    } else {
+        None
+    }
+}
+
+///
+/// all values of header `name` joined by `,`, `None` if missing or not ascii
+///
+fn joined_header(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Option<Vec<&str>> = headers
+        .get_all(name)
+        .map(|value| value.to_str().ok())
+        .collect();
+    match values {
+        Some(values) if !values.is_empty() => Some(values.join(",")),
+        _ => None,
+    }
+}
+
+///
+/// the client address of a request, `None` if the peer is not an ip address (unix socket)
+///
+pub fn forwarded_client_ip(
+    peer: Option<SocketAddr>,
+    headers: &HeaderMap,
+    trusted: &[Cidr],
+) -> Option<IpAddr> {
+    let forwarded_for = joined_header(headers, X_FORWARDED_FOR);
+    peer.map(|peer| resolve_client_ip(peer.ip(), forwarded_for.as_deref(), trusted))
+}
+
+///
+/// the client address resolved by `ForwardedClient`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+///
+/// the scheme seen by the client, from a trusted proxy
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForwardedProto(pub &'static str);
+
+///
+/// the client address of a request: resolved by `ForwardedClient`, or the peer address
+///
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    match req.extensions().get::<ClientIp>() {
+        Some(ClientIp(ip)) => Some(*ip),
+        None => req.peer_addr().map(|addr| addr.ip()),
+    }
+}
+
+///
+/// the scheme of the request seen by the client, if forwarded by a trusted proxy
+///
+pub fn forwarded_proto(req: &HttpRequest) -> Option<&'static str> {
+    req.extensions()
+        .get::<ForwardedProto>()
+        .map(|proto| proto.0)
+}
+
+///
+/// middleware storing `ClientIp` and `ForwardedProto` in request extensions,
+/// honoring `X-Forwarded-For` and `X-Forwarded-Proto` only from `TRUSTED_PROXIES`
+///
+pub struct ForwardedClient {
+    trusted: Arc<Vec<Cidr>>,
+}
+
+impl ForwardedClient {
+    pub fn new(trusted: Vec<Cidr>) -> ForwardedClient {
+        ForwardedClient {
+            trusted: Arc::new(trusted),
+        }
+    }
+
+    pub fn from_env() -> ForwardedClient {
+        ForwardedClient::new(Env::get().trusted_proxies.clone())
+    }
+}
+
+impl<S, B> Transform<S> for ForwardedClient
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ForwardedClientMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ForwardedClientMiddleware {
+            service,
+            trusted: self.trusted.clone(),
+        })
+    }
+}
+
+pub struct ForwardedClientMiddleware<S> {
+    service: S,
+    trusted: Arc<Vec<Cidr>>,
+}
+
+impl<S, B> Service for ForwardedClientMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if let Some(peer) = req.peer_addr() {
+            let ip = forwarded_client_ip(Some(peer), req.headers(), &self.trusted);
+            let proto = joined_header(req.headers(), X_FORWARDED_PROTO);
+            let proto = resolve_forwarded_proto(peer.ip(), proto.as_deref(), &self.trusted);
+            let mut extensions = req.extensions_mut();
+            if let Some(ip) = ip {
+                extensions.insert(ClientIp(ip));
+            }
+            if let Some(proto) = proto {
+                extensions.insert(ForwardedProto(proto));
+            }
+        }
+        self.service.call(req)
+    }
+}
+
+#[cfg(test)]
+mod test_net_util_mod {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    fn cidrs(s: &[&str]) -> Vec<Cidr> {
+        s.iter().map(|c| c.parse().unwrap()).collect()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_matching() {
+        let private = cidrs(&["10.0.0.0/8", "192.168.1.0/24", "::1", "fd00::/8"]);
+        assert!(is_trusted(ip("10.1.2.3"), &private));
+        assert!(is_trusted(ip("192.168.1.200"), &private));
+        assert!(!is_trusted(ip("192.168.2.1"), &private));
+        assert!(is_trusted(ip("::1"), &private));
+        assert!(is_trusted(ip("fd12::1"), &private));
+        assert!(!is_trusted(ip("fe80::1"), &private));
+        // IPv4 peers on a socket bound to `::`
+        assert!(is_trusted(ip("::ffff:10.0.0.1"), &private));
+        assert!(is_trusted(ip("1.2.3.4"), &cidrs(&["0.0.0.0/0"])));
+        assert!(!is_trusted(ip("::1"), &cidrs(&["0.0.0.0/0"])));
+
+        assert_eq!(cidrs(&[" 127.0.0.1 "])[0].to_string(), "127.0.0.1/32");
+        for illegal in &[
+            "10.0.0.0/33",
+            "::/129",
+            "localhost",
+            "10.0.0.0/",
+            "10.0.0/8",
+        ] {
+            assert!(illegal.parse::<Cidr>().is_err(), "{}", illegal);
+        }
+    }
+
+    #[test]
+    fn client_behind_proxies() {
+        let trusted = cidrs(&["127.0.0.1", "10.0.0.0/8"]);
+        // from a trusted proxy
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some("203.0.113.7"), &trusted),
+            ip("203.0.113.7")
+        );
+        // through two trusted proxies, a spoofed hop added by the client is skipped
+        assert_eq!(
+            resolve_client_ip(
+                ip("127.0.0.1"),
+                Some("6.6.6.6, 203.0.113.7:51000, 10.0.0.2"),
+                &trusted
+            ),
+            ip("203.0.113.7")
+        );
+        // all hops are trusted
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some("10.0.0.3, 10.0.0.2"), &trusted),
+            ip("10.0.0.3")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), None, &trusted),
+            ip("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn spoofed_and_malformed_headers() {
+        let trusted = cidrs(&["127.0.0.1"]);
+        // from an untrusted peer
+        assert_eq!(
+            resolve_client_ip(ip("203.0.113.7"), Some("1.1.1.1"), &trusted),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            resolve_forwarded_proto(ip("203.0.113.7"), Some("https"), &trusted),
+            None
+        );
+        // malformed hops
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some("garbage"), &trusted),
+            ip("127.0.0.1")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some("1.1.1.1, unknown"), &trusted),
+            ip("127.0.0.1")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some(""), &trusted),
+            ip("127.0.0.1")
+        );
+        assert_eq!(
+            resolve_forwarded_proto(ip("127.0.0.1"), Some("HTTPS, http"), &trusted),
+            Some("https")
+        );
+        assert_eq!(
+            resolve_forwarded_proto(ip("127.0.0.1"), Some("gopher"), &trusted),
+            None
+        );
+    }
+
+    #[test]
+    fn client_ip_in_extensions() {
+        actix_web::rt::System::new("test_forwarded_client").block_on(async {
+            let mut app = test::init_service(
+                App::new()
+                    .wrap(ForwardedClient::new(cidrs(&["127.0.0.1"])))
+                    .route(
+                        "/",
+                        web::get().to(|req: HttpRequest| {
+                            HttpResponse::Ok().body(format!(
+                                "{} {}",
+                                client_ip(&req).unwrap(),
+                                forwarded_proto(&req).unwrap_or("-")
+                            ))
+                        }),
+                    ),
+            )
+            .await;
+            let request = |peer: &str| {
+                test::TestRequest::get()
+                    .uri("/")
+                    .peer_addr(peer.parse().unwrap())
+                    .header(X_FORWARDED_FOR, "203.0.113.7")
+                    .header(X_FORWARDED_PROTO, "https")
+                    .to_request()
+            };
+            let body = test::read_response(&mut app, request("127.0.0.1:50000")).await;
+            assert_eq!(body, "203.0.113.7 https");
+            let body = test::read_response(&mut app, request("198.51.100.1:50000")).await;
+            assert_eq!(body, "198.51.100.1 -");
+        });
+    }
+}
@@ -0,0 +1,457 @@
+use crate::error::Error;
+use crate::initialize::{Env, SmtpSettings};
+use crate::launchctl::{view_std_err, Status, TaskInfo};
+use crate::utils::format_rfc3339;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// lines of stderr included in an alert
+static STDERR_TAIL_LINES: usize = 20;
+static SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// a failing notifier is skipped this long, doubled after each further failure
+static BACKOFF_BASE: Duration = Duration::from_secs(60);
+static BACKOFF_MAX: Duration = Duration::from_secs(60 * 60);
+static TEST_LABEL: &str = "com.tasker.tasks.test_notification";
+lazy_static! {
+    static ref NOTIFIERS: Mutex<Notifiers> = Mutex::new(Notifiers::configured());
+    static ref ERRORED: Mutex<ErrorTracker> = Mutex::new(ErrorTracker::default());
+}
+
+///
+/// a task entering `ERROR` status
+///
+#[derive(Debug, Serialize, JsonSchema, Clone, PartialEq)]
+pub struct TaskEvent {
+    pub label: String,
+    pub exit_status: Option<i32>,
+    pub at: Option<String>,
+    /// the last 20 lines of stderr
+    pub stderr_tail: String,
+}
+
+impl TaskEvent {
+    fn new(label: &str, exit_status: Option<i32>) -> TaskEvent {
+        let stderr_tail = match view_std_err(label, STDERR_TAIL_LINES, "", None, None) {
+            Ok(s) => s,
+            Err(e) => format!("unavailable: {:?}", e),
+        };
+        TaskEvent {
+            label: label.to_string(),
+            exit_status,
+            at: format_rfc3339(SystemTime::now()),
+            stderr_tail,
+        }
+    }
+
+    ///
+    /// the event sent by `/test_notification`
+    ///
+    fn synthetic() -> TaskEvent {
+        TaskEvent {
+            label: TEST_LABEL.to_string(),
+            exit_status: Some(1),
+            at: format_rfc3339(SystemTime::now()),
+            stderr_tail: "a test notification sent by /test_notification\n".to_string(),
+        }
+    }
+
+    fn exit_status_text(&self) -> String {
+        match self.exit_status {
+            Some(status) => status.to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        format!(
+            "[tasker] {} failed with exit status {}",
+            self.label,
+            self.exit_status_text()
+        )
+    }
+
+    pub fn body(&self) -> String {
+        format!(
+            "task: {}\nexit status: {}\nat: {}\n\nlast {} lines of stderr:\n{}",
+            self.label,
+            self.exit_status_text(),
+            self.at.as_deref().unwrap_or("unknown"),
+            STDERR_TAIL_LINES,
+            self.stderr_tail
+        )
+    }
+}
+
+///
+/// a channel sending alerts about tasks
+///
+pub trait Notifier: Send {
+    /// shown in the results of `/test_notification`
+    fn name(&self) -> &str;
+
+    fn notify(&self, event: &TaskEvent) -> Result<(), Error>;
+}
+
+///
+/// alerts by email, sent with STARTTLS
+///
+pub struct SmtpNotifier {
+    settings: SmtpSettings,
+}
+
+impl SmtpNotifier {
+    pub fn new(settings: SmtpSettings) -> SmtpNotifier {
+        SmtpNotifier { settings }
+    }
+
+    fn message(&self, event: &TaskEvent) -> Result<Message, Error> {
+        let mut builder = Message::builder()
+            .from(self.settings.from.clone())
+            .subject(event.subject());
+        for to in &self.settings.to {
+            builder = builder.to(to.clone());
+        }
+        builder
+            .body(event.body())
+            .map_err(|e| Error::FailedToNotify(format!("cannot build email: {}", e)))
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify(&self, event: &TaskEvent) -> Result<(), Error> {
+        let message = self.message(event)?;
+        let smtp_error = |e| Error::FailedToNotify(format!("smtp error: {}", e));
+        let mut transport = SmtpTransport::starttls_relay(&self.settings.host)
+            .map_err(smtp_error)?
+            .port(self.settings.port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if let Some((user, password)) = &self.settings.credentials {
+            transport = transport.credentials(Credentials::new(user.clone(), password.clone()));
+        }
+        transport.build().send(&message).map_err(smtp_error)?;
+        Ok(())
+    }
+}
+
+///
+/// consecutive failures of a notifier, which is skipped until `retry_at`
+///
+#[derive(Debug, Default, PartialEq)]
+struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    fn ready(&self, now: Instant) -> bool {
+        match self.retry_at {
+            Some(retry_at) => now >= retry_at,
+            None => true,
+        }
+    }
+
+    fn record(&mut self, sent: bool, now: Instant) {
+        if sent {
+            *self = Backoff::default();
+            return;
+        }
+        self.failures += 1;
+        let delay = BACKOFF_BASE
+            .checked_mul(1 << (self.failures - 1).min(16))
+            .map_or(BACKOFF_MAX, |delay| delay.min(BACKOFF_MAX));
+        self.retry_at = Some(now + delay);
+    }
+}
+
+///
+/// whether a notifier sent an event
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct NotifyResult {
+    pub notifier: String,
+    pub sent: bool,
+    pub error: Option<String>,
+}
+
+///
+/// all configured notifiers, each backing off on its own after failures
+///
+pub struct Notifiers {
+    notifiers: Vec<(Box<dyn Notifier>, Backoff)>,
+}
+
+impl Notifiers {
+    pub fn new(notifiers: Vec<Box<dyn Notifier>>) -> Notifiers {
+        Notifiers {
+            notifiers: notifiers
+                .into_iter()
+                .map(|notifier| (notifier, Backoff::default()))
+                .collect(),
+        }
+    }
+
+    ///
+    /// email if `SMTP_HOST` is set
+    ///
+    fn configured() -> Notifiers {
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        if let Some(smtp) = &Env::get().smtp {
+            notifiers.push(Box::new(SmtpNotifier::new(smtp.clone())));
+        }
+        Notifiers::new(notifiers)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    ///
+    /// send `event` through every notifier, skipping those backing off unless `force`.
+    /// A failing notifier does not stop the others.
+    ///
+    fn send(&mut self, event: &TaskEvent, now: Instant, force: bool) -> Vec<NotifyResult> {
+        let mut results = Vec::new();
+        for (notifier, backoff) in self.notifiers.iter_mut() {
+            if !force && !backoff.ready(now) {
+                results.push(NotifyResult {
+                    notifier: notifier.name().to_string(),
+                    sent: false,
+                    error: Some(format!(
+                        "skipped after {} consecutive failures",
+                        backoff.failures
+                    )),
+                });
+                continue;
+            }
+            let error = notifier.notify(event).err().map(|e| format!("{:?}", e));
+            backoff.record(error.is_none(), now);
+            results.push(NotifyResult {
+                notifier: notifier.name().to_string(),
+                sent: error.is_none(),
+                error,
+            });
+        }
+        results
+    }
+}
+
+///
+/// the tasks in `ERROR` status, so that a task is notified once when it enters it
+///
+#[derive(Default)]
+struct ErrorTracker {
+    errored: BTreeSet<String>,
+    /// whether tasks have been observed since tasker started
+    observed: bool,
+}
+
+impl ErrorTracker {
+    ///
+    /// the labels in `errored` which were not in `ERROR` at the previous observation.
+    /// Tasks already in `ERROR` at the first observation failed before tasker started,
+    /// and are not returned.
+    ///
+    fn observe(&mut self, errored: BTreeSet<String>) -> Vec<String> {
+        let entered = if self.observed {
+            errored.difference(&self.errored).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        self.errored = errored;
+        self.observed = true;
+        entered
+    }
+}
+
+///
+/// alert all notifiers, in the background, of each task in a snapshot that entered `ERROR`
+///
+pub fn notify_errors(tasks: &BTreeSet<TaskInfo>) {
+    let errored = tasks
+        .iter()
+        .filter(|task| task.status() == Status::ERROR)
+        .map(|task| task.label().to_string())
+        .collect();
+    let entered = ERRORED.lock().unwrap().observe(errored);
+    if entered.is_empty() || NOTIFIERS.lock().unwrap().is_empty() {
+        return;
+    }
+    let failed: Vec<(String, Option<i32>)> = tasks
+        .iter()
+        .filter(|task| entered.iter().any(|label| label == task.label()))
+        .map(|task| (task.label().to_string(), task.last_exit_status()))
+        .collect();
+    std::thread::spawn(move || {
+        for (label, exit_status) in failed {
+            let event = TaskEvent::new(&label, exit_status);
+            let results = NOTIFIERS
+                .lock()
+                .unwrap()
+                .send(&event, Instant::now(), false);
+            for result in results {
+                if let Some(e) = result.error {
+                    eprintln!("failed to notify {} of `{}`: {}", result.notifier, label, e);
+                }
+            }
+        }
+    });
+}
+
+///
+/// send a synthetic event through all notifiers, including those backing off
+///
+pub fn test_notification() -> Vec<NotifyResult> {
+    NOTIFIERS
+        .lock()
+        .unwrap()
+        .send(&TaskEvent::synthetic(), Instant::now(), true)
+}
+
+#[cfg(test)]
+mod test_notify_mod {
+    use super::*;
+    use std::sync::Arc;
+
+    struct MockNotifier {
+        name: String,
+        fail: bool,
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Notifier for MockNotifier {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn notify(&self, event: &TaskEvent) -> Result<(), Error> {
+            self.sent.lock().unwrap().push(event.label.clone());
+            if self.fail {
+                Err(Error::FailedToNotify("unreachable".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn mock(name: &str, fail: bool) -> (Box<dyn Notifier>, Arc<Mutex<Vec<String>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let notifier = MockNotifier {
+            name: name.to_string(),
+            fail,
+            sent: sent.clone(),
+        };
+        (Box::new(notifier), sent)
+    }
+
+    fn event(label: &str) -> TaskEvent {
+        TaskEvent {
+            label: label.to_string(),
+            exit_status: Some(2),
+            at: Some("2020-09-13T12:26:40Z".to_string()),
+            stderr_tail: "line 1\nline 2\n".to_string(),
+        }
+    }
+
+    fn labels(labels: &[&str]) -> BTreeSet<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn event_message() {
+        let event = event("com.tasker.tasks.backup");
+        assert_eq!(
+            event.subject(),
+            "[tasker] com.tasker.tasks.backup failed with exit status 2"
+        );
+        assert_eq!(
+            event.body(),
+            "task: com.tasker.tasks.backup\nexit status: 2\nat: 2020-09-13T12:26:40Z\n\n\
+             last 20 lines of stderr:\nline 1\nline 2\n"
+        );
+    }
+
+    #[test]
+    fn fan_out_with_backoff() {
+        let (failing, failing_sent) = mock("webhook", true);
+        let (working, working_sent) = mock("email", false);
+        let mut notifiers = Notifiers::new(vec![failing, working]);
+        let start = Instant::now();
+
+        // a failure does not stop the other notifier
+        let results = notifiers.send(&event("a"), start, false);
+        assert_eq!(
+            results,
+            vec![
+                NotifyResult {
+                    notifier: "webhook".to_string(),
+                    sent: false,
+                    error: Some("FailedToNotify(\"unreachable\")".to_string()),
+                },
+                NotifyResult {
+                    notifier: "email".to_string(),
+                    sent: true,
+                    error: None,
+                },
+            ]
+        );
+
+        // the failing notifier is skipped for a minute, then for two
+        let results = notifiers.send(&event("b"), start + Duration::from_secs(30), false);
+        assert!(!results[0].sent);
+        assert!(results[1].sent);
+        notifiers.send(&event("c"), start + Duration::from_secs(60), false);
+        notifiers.send(&event("d"), start + Duration::from_secs(179), false);
+        notifiers.send(&event("e"), start + Duration::from_secs(180), false);
+        assert_eq!(*failing_sent.lock().unwrap(), vec!["a", "c", "e"]);
+        assert_eq!(*working_sent.lock().unwrap(), vec!["a", "b", "c", "d", "e"]);
+
+        // a forced test ignores the backoff
+        let results = notifiers.send(&event("f"), start + Duration::from_secs(181), true);
+        assert_eq!(results.len(), 2);
+        assert_eq!(failing_sent.lock().unwrap().last().unwrap(), "f");
+    }
+
+    #[test]
+    fn backoff_is_capped_and_reset() {
+        let start = Instant::now();
+        let mut backoff = Backoff::default();
+        for _ in 0..40 {
+            backoff.record(false, start);
+        }
+        assert_eq!(backoff.retry_at, Some(start + BACKOFF_MAX));
+        assert!(!backoff.ready(start));
+        backoff.record(true, start);
+        assert_eq!(backoff, Backoff::default());
+        assert!(backoff.ready(start));
+    }
+
+    #[test]
+    fn notified_once_per_error() {
+        let mut tracker = ErrorTracker::default();
+        // failed before tasker started
+        assert!(tracker.observe(labels(&["a"])).is_empty());
+        assert_eq!(tracker.observe(labels(&["a", "b"])), vec!["b"]);
+        // still failing
+        assert!(tracker.observe(labels(&["a", "b"])).is_empty());
+        // a recovers and fails again
+        assert!(tracker.observe(labels(&["b"])).is_empty());
+        assert_eq!(tracker.observe(labels(&["a", "b"])), vec!["a"]);
+
+        // only the tasks entering ERROR reach the notifiers
+        let (notifier, sent) = mock("email", false);
+        let mut notifiers = Notifiers::new(vec![notifier]);
+        for label in tracker.observe(labels(&["a", "b", "c"])) {
+            notifiers.send(&event(&label), Instant::now(), false);
+        }
+        assert_eq!(*sent.lock().unwrap(), vec!["c"]);
+    }
+}
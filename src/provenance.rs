@@ -0,0 +1,278 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::{format_rfc3339, read_utf8_file, write_atomic, MtimeCache};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+pub static PROVENANCE_EXTENSION: &str = ".provenance.json";
+lazy_static! {
+    static ref PROVENANCE_CACHE: Mutex<MtimeCache<Option<Provenance>>> =
+        Mutex::new(MtimeCache::new());
+}
+
+///
+/// the provenance of a task in meta folder, next to its yaml
+///
+pub fn provenance_file(label: &str) -> PathBuf {
+    Env::get()
+        .meta_dir
+        .join(String::from(label) + PROVENANCE_EXTENSION)
+}
+
+///
+/// how a task was created
+///
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// uploaded as a zip package
+    Zip,
+    /// created by `create_from_template`
+    Template,
+    /// an existing daemon taken over by `adopt`
+    Adopt,
+    /// checked out from a git repository by `create_from_git`
+    Git,
+}
+
+///
+/// the git repository a task was checked out from
+///
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct GitOrigin {
+    pub url: String,
+    /// the branch, tag or commit asked for
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// the folder of the task in the repository
+    pub subdir: Option<String>,
+    /// the commit checked out, updated by `refresh_from_git`
+    pub commit: String,
+}
+
+///
+/// an update of the yaml of a task
+///
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct Edit {
+    pub at: Option<String>,
+    pub by: String,
+}
+
+///
+/// where a task came from: who created it, how, and with which version of tasker
+///
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct Provenance {
+    pub created_at: Option<String>,
+    pub created_by: String,
+    /// the name of the uploaded zip
+    pub original_filename: Option<String>,
+    pub tasker_version: String,
+    pub source: Source,
+    /// the template, or the adopted daemon
+    pub parent_label: Option<String>,
+    #[serde(default)]
+    pub edits: Vec<Edit>,
+    /// the repository of a task created from git
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitOrigin>,
+}
+
+impl Provenance {
+    ///
+    /// a task created now by `created_by`
+    ///
+    pub fn new(source: Source, created_by: &str) -> Provenance {
+        Provenance {
+            created_at: format_rfc3339(SystemTime::now()),
+            created_by: created_by.to_string(),
+            original_filename: None,
+            tasker_version: env!("CARGO_PKG_VERSION").to_string(),
+            source,
+            parent_label: None,
+            edits: Vec::new(),
+            git: None,
+        }
+    }
+
+    pub fn original_filename(mut self, filename: &str) -> Provenance {
+        self.original_filename = Some(filename.to_string());
+        self
+    }
+
+    pub fn parent_label(mut self, label: &str) -> Provenance {
+        self.parent_label = Some(label.to_string());
+        self
+    }
+
+    pub fn git(mut self, origin: GitOrigin) -> Provenance {
+        self.git = Some(origin);
+        self
+    }
+}
+
+///
+/// `None` if the file is missing (tasks created before provenance was recorded) or corrupted
+///
+fn read_provenance_file(file: &Path) -> Option<Provenance> {
+    read_utf8_file(file)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+}
+
+fn write_provenance_file(file: &Path, provenance: &Provenance) -> Result<(), Error> {
+    match serde_json::to_string_pretty(provenance) {
+        Ok(s) => write_atomic(file, s.as_bytes()),
+        Err(e) => Err(Error::FailedToUpdateProvenance(format!("{}", e))),
+    }
+}
+
+///
+/// append an edit to a provenance file, which is left alone if missing
+///
+fn append_edit(file: &Path, by: &str, at: SystemTime) -> Result<(), Error> {
+    match read_provenance_file(file) {
+        Some(mut provenance) => {
+            provenance.edits.push(Edit {
+                at: format_rfc3339(at),
+                by: by.to_string(),
+            });
+            write_provenance_file(file, &provenance)
+        }
+        None => Ok(()),
+    }
+}
+
+///
+/// set the commit of a task created from git, recording the refresh as an edit.
+/// Returns the previous commit, `None` if the task was not created from git.
+///
+fn update_git_commit(
+    file: &Path,
+    commit: &str,
+    by: &str,
+    at: SystemTime,
+) -> Result<Option<String>, Error> {
+    let mut provenance = match read_provenance_file(file) {
+        Some(provenance) => provenance,
+        None => return Ok(None),
+    };
+    let previous = match &mut provenance.git {
+        Some(origin) => std::mem::replace(&mut origin.commit, commit.to_string()),
+        None => return Ok(None),
+    };
+    provenance.edits.push(Edit {
+        at: format_rfc3339(at),
+        by: by.to_string(),
+    });
+    write_provenance_file(file, &provenance)?;
+    Ok(Some(previous))
+}
+
+///
+/// the provenance of task `label`, cached until it changes
+///
+pub fn read_provenance(label: &str) -> Option<Provenance> {
+    let file = provenance_file(label);
+    let mtime = match std::fs::metadata(&file).and_then(|meta| meta.modified()) {
+        Ok(mtime) => mtime,
+        Err(_) => return None,
+    };
+    let mut cache = PROVENANCE_CACHE.lock().unwrap();
+    cache.get_or_update(label, mtime, || read_provenance_file(&file))
+}
+
+///
+/// record the provenance of the newly created task `label`
+///
+pub fn write_provenance(label: &str, provenance: &Provenance) -> Result<(), Error> {
+    write_provenance_file(&provenance_file(label), provenance)
+}
+
+///
+/// record that `by` updated the yaml of task `label`
+///
+pub fn record_edit(label: &str, by: &str) -> Result<(), Error> {
+    append_edit(&provenance_file(label), by, SystemTime::now())
+}
+
+///
+/// record that `by` refreshed task `label` to `commit`, returning the previous commit
+///
+pub fn record_git_refresh(label: &str, commit: &str, by: &str) -> Result<Option<String>, Error> {
+    update_git_commit(&provenance_file(label), commit, by, SystemTime::now())
+}
+
+#[cfg(test)]
+mod test_provenance_mod {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn provenance_with_edits() {
+        let file = Path::new("test_provenance.json");
+        let provenance = Provenance::new(Source::Template, "admin").parent_label("base");
+        write_provenance_file(file, &provenance).unwrap();
+        let json = read_utf8_file(file).unwrap();
+        assert!(json.contains("\"source\": \"template\""));
+        assert!(json.contains("\"parent_label\": \"base\""));
+
+        let at = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        append_edit(file, "token #1", at).unwrap();
+        append_edit(file, "admin", at + Duration::from_secs(60)).unwrap();
+        let edited = read_provenance_file(file).unwrap();
+        assert_eq!(edited.created_at, provenance.created_at);
+        assert_eq!(
+            edited.edits,
+            vec![
+                Edit {
+                    at: Some("2020-09-13T12:26:40Z".to_string()),
+                    by: "token #1".to_string(),
+                },
+                Edit {
+                    at: Some("2020-09-13T12:27:40Z".to_string()),
+                    by: "admin".to_string(),
+                },
+            ]
+        );
+
+        std::fs::write(file, "corrupted").unwrap();
+        assert_eq!(read_provenance_file(file), None);
+        std::fs::remove_file(file).unwrap();
+        // tasks created before provenance was recorded are not given one by an edit
+        append_edit(file, "admin", at).unwrap();
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn provenance_git_refresh() {
+        let file = Path::new("test_provenance_git.json");
+        let origin = GitOrigin {
+            url: "file:///srv/git/tasks.git".to_string(),
+            git_ref: "main".to_string(),
+            subdir: None,
+            commit: "a".repeat(40),
+        };
+        write_provenance_file(file, &Provenance::new(Source::Zip, "admin")).unwrap();
+        let at = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        // a task not created from git has no commit to refresh
+        assert_eq!(update_git_commit(file, "b", "admin", at).unwrap(), None);
+        assert!(read_provenance_file(file).unwrap().edits.is_empty());
+
+        let provenance = Provenance::new(Source::Git, "admin").git(origin.clone());
+        write_provenance_file(file, &provenance).unwrap();
+        let json = read_utf8_file(file).unwrap();
+        assert!(json.contains("\"source\": \"git\""));
+        assert!(json.contains("\"ref\": \"main\""));
+        let previous = update_git_commit(file, &"b".repeat(40), "token #1", at).unwrap();
+        assert_eq!(previous, Some("a".repeat(40)));
+        let refreshed = read_provenance_file(file).unwrap();
+        assert_eq!(refreshed.git.unwrap().commit, "b".repeat(40));
+        assert_eq!(refreshed.edits.len(), 1);
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use crate::initialize::Env;
+use crate::net_util::ClientIp;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{HttpMessage, HttpResponse};
+use futures::future::{err, ok, Either, Ready};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+static RATE_LIMIT_EXEMPT: [&str; 1] = ["/health"];
+static STALE_CLEANUP_INTERVAL_SECS: u64 = 60;
+
+///
+/// a token bucket per client ip: each client may burst `per_min` requests,
+/// and the bucket refills at `per_min` tokens per minute
+///
+pub struct RateLimiter {
+    per_min: u32,
+    buckets: HashMap<IpAddr, (f64, Instant)>,
+    last_cleanup: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_min: u32) -> RateLimiter {
+        RateLimiter {
+            per_min,
+            buckets: HashMap::new(),
+            last_cleanup: Instant::now(),
+        }
+    }
+
+    ///
+    /// take a token for `ip`, or return how long to wait before retrying
+    ///
+    pub fn check(&mut self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        self.remove_stale(now);
+        let capacity = self.per_min as f64;
+        let rate_per_sec = capacity / 60.0;
+        let (tokens, last) = self.buckets.entry(ip).or_insert((capacity, now));
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * rate_per_sec).min(capacity);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / rate_per_sec))
+        }
+    }
+
+    ///
+    /// drop buckets that have been refilled completely, since they are
+    /// equivalent to new buckets
+    ///
+    fn remove_stale(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.last_cleanup)
+            < Duration::from_secs(STALE_CLEANUP_INTERVAL_SECS)
+        {
+            return;
+        }
+        self.last_cleanup = now;
+        let full_after = Duration::from_secs(60);
+        self.buckets
+            .retain(|_, (_, last)| now.saturating_duration_since(*last) < full_after);
+    }
+}
+
+///
+/// rate limiting middleware returning `429 Too Many Requests` with `Retry-After`.
+/// The limiter is shared by all workers.
+///
+pub struct RateLimit {
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<Mutex<RateLimiter>>) -> RateLimit {
+        RateLimit { limiter }
+    }
+
+    ///
+    /// a limiter allowing `RATE_LIMIT_PER_MIN` requests per client per minute
+    ///
+    pub fn shared_limiter() -> Arc<Mutex<RateLimiter>> {
+        Arc::new(Mutex::new(RateLimiter::new(Env::get().rate_limit_per_min)))
+    }
+}
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if RATE_LIMIT_EXEMPT.contains(&req.path()) {
+            return Either::Left(self.service.call(req));
+        }
+        // the client behind a trusted proxy, resolved by `ForwardedClient`
+        let client_ip = req.extensions().get::<ClientIp>().map(|ip| ip.0);
+        let ip = match client_ip.or_else(|| req.peer_addr().map(|addr| addr.ip())) {
+            Some(ip) => ip,
+            None => return Either::Left(self.service.call(req)),
+        };
+        let checked = self.limiter.lock().unwrap().check(ip, Instant::now());
+        match checked {
+            Ok(_) => Either::Left(self.service.call(req)),
+            Err(retry_after) => Either::Right(err(actix_web::Error::from(
+                HttpResponse::TooManyRequests()
+                    .header(
+                        header::RETRY_AFTER,
+                        (retry_after.as_secs_f64().ceil() as u64).to_string(),
+                    )
+                    .finish(),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_rate_limit_mod {
+    use super::*;
+    use crate::server;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[test]
+    fn token_bucket() {
+        let mut limiter = RateLimiter::new(60);
+        let start = Instant::now();
+        for _ in 0..60 {
+            assert!(limiter.check(ip(1), start).is_ok());
+        }
+        let retry_after = limiter.check(ip(1), start).unwrap_err();
+        assert_eq!(retry_after.as_secs(), 1);
+        // other clients are not affected
+        assert!(limiter.check(ip(2), start).is_ok());
+        // one token per second
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.check(ip(1), later).is_ok());
+        assert!(limiter.check(ip(1), later).is_err());
+    }
+
+    #[test]
+    fn stale_buckets_removed() {
+        let mut limiter = RateLimiter::new(60);
+        let start = Instant::now();
+        limiter.check(ip(1), start).unwrap();
+        limiter
+            .check(ip(2), start + Duration::from_secs(30))
+            .unwrap();
+        limiter
+            .check(ip(3), start + Duration::from_secs(70))
+            .unwrap();
+        assert_eq!(limiter.buckets.len(), 2);
+        assert!(!limiter.buckets.contains_key(&ip(1)));
+    }
+
+    #[test]
+    fn too_many_requests() {
+        actix_web::rt::System::new("test_rate_limit").block_on(async {
+            let limiter = Arc::new(Mutex::new(RateLimiter::new(2)));
+            let mut app = test::init_service(
+                App::new()
+                    .wrap(RateLimit::new(limiter))
+                    .route("/list", web::get().to(HttpResponse::Ok))
+                    .route("/health", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let request = |path: &str| {
+                test::TestRequest::get()
+                    .uri(path)
+                    .peer_addr("192.168.1.1:50000".parse().unwrap())
+                    .to_request()
+            };
+            for _ in 0..2 {
+                let response = test::call_service(&mut app, request("/list")).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+            let error = app.call(request("/list")).await.err().unwrap();
+            let response = error.as_response_error().error_response();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+            let response = test::call_service(&mut app, request("/health")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn yaml_too_large() {
+        actix_web::rt::System::new("test_yaml_size").block_on(async {
+            let mut app = test::init_service(
+                App::new()
+                    .app_data(server::yaml_payload_config())
+                    .service(server::post_yaml),
+            )
+            .await;
+            let request = test::TestRequest::post()
+                .uri("/post_yaml?label=com.tasker.tasks.test")
+                .set_payload(vec![b'a'; 2 * 1024 * 1024])
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        });
+    }
+}
@@ -1,18 +1,63 @@
+use crate::api_doc::openapi;
+use crate::auth::{authenticated_user, Auth};
+use crate::backup::{backup_state_file, list_backups, run_backup, BackupCheck, BackupState};
+use crate::cleanup::{clean_temp, purge_expired_trash, InProgress};
+use crate::command_queue::QueueStats;
+use crate::concurrency::{
+    saturated, ConcurrencyLimiter, InFlight, RouteConcurrency, EXPORT_ROUTE, UPLOAD_ROUTE,
+};
+use crate::config::{json_schema, validate_label_param, validate_pattern_param, Config, Schedule};
+use crate::confirmation::Confirmations;
 use crate::error::Error;
+use crate::events::{latest_statuses, TaskEvents};
+use crate::history::run_stats;
+use crate::initialize::{read_certificate, ssl_acceptor, CertificateInfo, Env};
 use crate::launchctl::{
-    create_task, delete_task, get_zip, list, load_task, unload_task, update_yaml, view_std_err,
-    view_std_out, view_yaml,
+    adopt_task, artifact_file, autoload_tasks, check_new_label, check_new_program, clear_output,
+    create_from_git, create_from_template, create_task, delete_artifact, delete_task, diff_plist,
+    effective_command, exist, export_all, find_conflicts, get_artifacts_zip, get_zip,
+    invalidate_launchctl_list, is_loaded, launchctl_queue_stats, lint_all, list, list_artifacts,
+    list_count, list_task_files, list_text, load_task, mark_secret, parse_statuses, patch_config,
+    preview_yaml, read_secrets, rebuild_all, refresh_from_git, resource_usage, restart_task,
+    rotate_logs, rotate_oversized_logs, run_once, stop_task, task_file, task_labels, trash_info,
+    unload_task, update_task_files, update_yaml, view_plist, view_plist_on_disk, view_schedule,
+    view_std_err, view_std_out, view_yaml_version, write_task_file, yaml_etag, ConfigPatch,
+    LabelMatcher, MatchMode, Status, TrashSort,
+};
+use crate::maintenance::{set_maintenance, MaintenanceGuard, MaintenanceState, MaintenanceUpdate};
+use crate::manual_run::ManualRuns;
+use crate::net_util::{forwarded_proto, ForwardedClient};
+use crate::notify::test_notification;
+use crate::provenance::{read_provenance, GitOrigin, Provenance, Source};
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::settings_snapshot::{settings_changes, SettingChange, MIGRATION_GUIDANCE};
+use crate::shutdown::{request_restart, request_shutdown};
+use crate::stats::disk_usage;
+use crate::timing::{ErrorDetail, RequestMetrics, RequestTiming};
+use crate::utils::{
+    parse_compression_method, parse_rfc3339, render, render_ansi_html, strip_ansi_codes,
+    ArchiveFormat, ChownWarning, ACCEPTED_TIME_FORMATS, COMPRESSION_METHODS,
 };
+use crate::{TASKER_TASK_NAME, TEMP_ZIP_PATH};
 use actix_files::NamedFile;
 use actix_multipart::{Field, Multipart};
+use actix_service::ServiceFactory;
 use actix_web::body::Body;
-use actix_web::http::StatusCode;
+use actix_web::dev::{HttpResponseBuilder, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::{HeaderName, HeaderValue};
 use actix_web::web::Query;
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::{delete, get, post, web, App, HttpRequest, HttpResponse, Responder};
+use actix_web_actors::ws;
 use futures::{StreamExt, TryStreamExt};
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
 static INDEX_HTML: &'static str = include_str!("index.html");
 static LIST_ALL_HTML: &'static str = include_str!("list_all.html");
@@ -24,68 +69,281 @@ static STDERR: &'static str = include_str!("stderr.html");
 static MB_LIMIT: usize = 20;
 static MAX_OUTPUT_LIMIT: usize = 5000;
 static SIZE_LIMIT: usize = MB_LIMIT * 1024 * 1024;
-static TEMP_ZIP: &str = "/tmp/tasker.task.temp.zip";
+/// numbers the temp files of uploads in `TEMP_ZIP_PATH`, which may be saved at once
+static UPLOAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+static LOG_ROTATION_INTERVAL_SECS: u64 = 60;
+static TRASH_PURGE_INTERVAL_SECS: u64 = 24 * 60 * 60;
+/// `Retry-After` while launchctl invocations are refused
+static LAUNCHCTL_BUSY_RETRY_AFTER_SECS: u64 = 5;
+static MAX_STDERR_TAIL: usize = 50;
+static DEFAULT_TEXT_WIDTH: usize = 120;
+static DEFAULT_GRACE_SECONDS: u64 = 10;
+/// `/restart` blocks a worker thread while it waits
+static MAX_GRACE_SECONDS: u64 = 300;
+static MAX_YAML_BYTES: usize = 1024 * 1024;
+static DEFAULT_COMPRESSION: &str = "deflate";
+static COMPRESSION_HEADER: &str = "x-compression-method";
+static ARCHIVE_SIZE_HEADER: &str = "x-archive-size";
+/// the zip of `/export_all` in `TEMP_ZIP_PATH`, and its download name
+static EXPORT_ALL_NAME: &str = "tasker-export";
+lazy_static! {
+    static ref INDEX_PAGE: String = render_page(INDEX_HTML);
+    static ref LIST_ALL_PAGE: String = render_page(LIST_ALL_HTML);
+    static ref LIST_PART_PAGE: String = render_page(LIST_PART_HTML);
+    static ref CREATE_SUCCESS_PAGE: String = render_page(CREATE_SUCCESS);
+    static ref EDIT_YAML_PAGE: String = render_page(EDIT_YAML);
+    static ref STDOUT_PAGE: String = render_page(STDOUT);
+    static ref STDERR_PAGE: String = render_page(STDERR);
+    static ref OPENAPI_JSON: String = openapi().to_string();
+    static ref YAML_SCHEMA_JSON: String = json_schema().to_string();
+    /// zips being built by (label, compression, redact), shared by all workers
+    static ref ZIP_EXPORTS: InFlight<(String, String, bool), ZipExportResult> =
+        InFlight::default();
+    /// zips of all tasks being built by (compression, redact)
+    static ref ALL_EXPORTS: InFlight<(String, bool), ZipExportResult> = InFlight::default();
+}
+
+///
+/// fill in `base_path`, `version`, `server_name` and `public_url` of a page
+///
+fn render_page(template: &str) -> String {
+    let env = Env::get();
+    render_page_with(template, &env.base_path, env.domain(), &env.public_url())
+}
+
+fn render_page_with(
+    template: &str,
+    base_path: &str,
+    server_name: &str,
+    public_url: &str,
+) -> String {
+    render(
+        template,
+        &[
+            ("base_path", base_path),
+            ("version", env!("CARGO_PKG_VERSION")),
+            ("server_name", server_name),
+            ("public_url", public_url),
+        ],
+    )
+}
 
-pub fn index() -> HttpResponse {
-    HttpResponse::Ok().body(INDEX_HTML)
+///
+/// the index page, linking to `DOMAIN` with the scheme seen by the client behind a proxy
+///
+pub fn index(req: HttpRequest) -> HttpResponse {
+    match forwarded_proto(&req) {
+        None => HttpResponse::Ok().body(INDEX_PAGE.as_str()),
+        Some(proto) => {
+            let env = Env::get();
+            HttpResponse::Ok().body(render_page_with(
+                INDEX_HTML,
+                &env.base_path,
+                env.domain(),
+                &env.public_url_for(Some(proto)),
+            ))
+        }
+    }
 }
 
 pub fn list_all() -> HttpResponse {
-    HttpResponse::Ok().body(LIST_ALL_HTML)
+    HttpResponse::Ok().body(LIST_ALL_PAGE.as_str())
 }
 
 pub fn list_part() -> HttpResponse {
-    HttpResponse::Ok().body(LIST_PART_HTML)
+    HttpResponse::Ok().body(LIST_PART_PAGE.as_str())
 }
 
 pub fn create_success() -> HttpResponse {
-    HttpResponse::Ok().body(CREATE_SUCCESS)
+    HttpResponse::Ok().body(CREATE_SUCCESS_PAGE.as_str())
 }
 
 pub fn edit_yaml() -> HttpResponse {
-    HttpResponse::Ok().body(EDIT_YAML)
+    HttpResponse::Ok().body(EDIT_YAML_PAGE.as_str())
 }
 
 pub fn stderr() -> HttpResponse {
-    HttpResponse::Ok().body(STDERR)
+    HttpResponse::Ok().body(STDERR_PAGE.as_str())
 }
 
 pub fn stdout() -> HttpResponse {
-    HttpResponse::Ok().body(STDOUT)
+    HttpResponse::Ok().body(STDOUT_PAGE.as_str())
 }
 
 ///
-/// upload file with a size_limit of SIZE_LIMIT bytes for single files
+/// upload file with a size_limit of SIZE_LIMIT bytes for single files,
+/// `429` if `MAX_CONCURRENT_UPLOADS` uploads are in progress
 ///
-pub async fn create_new_tasks(mut payload: Multipart) -> Result<HttpResponse, actix_web::Error> {
+pub async fn create_new_tasks(
+    req: HttpRequest,
+    mut payload: Multipart,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let _permit = match concurrency.try_acquire(UPLOAD_ROUTE) {
+        Some(permit) => permit,
+        None => return Ok(saturated(UPLOAD_ROUTE)),
+    };
+    let user = authenticated_user(&req);
+    let mut warnings = Vec::new();
     while let Ok(Some(mut field)) = payload.try_next().await {
         let content_type = field.content_disposition().unwrap();
         let filename = content_type.get_filename().unwrap();
-        if !filename.ends_with(".zip") {
-            let response = HttpResponse::new(StatusCode::BAD_REQUEST);
-            return Ok(response.set_body(Body::from("not a zip file")));
-        }
-        let filepath = Path::new(TEMP_ZIP);
-        save_single_zip(&mut field, filename).await?;
-        match create_task(filepath) {
-            Ok(_) => {}
-            Err(e) => {
-                let response = HttpResponse::new(StatusCode::BAD_REQUEST);
-                return Ok(response.set_body(Body::from(format!("fail to create task: {:?}", e))));
-            }
+        let upload = TempUpload::new();
+        save_single_zip(&mut field, filename, &upload).await?;
+        if let Some(response) = not_an_archive(filename, &upload) {
+            return Ok(response);
+        }
+        let provenance = Provenance::new(Source::Zip, &user).original_filename(filename);
+        match create_task(&upload.path, &provenance) {
+            Ok(chown_warnings) => warnings.extend(chown_warnings),
+            Err(e) => return Ok(error_response(&mut HttpResponse::BadRequest(), &e)),
         };
     }
-    Ok(create_success())
+    if warnings.is_empty() {
+        Ok(create_success())
+    } else {
+        Ok(with_chown_warnings("Successfully created task", &warnings))
+    }
+}
+
+///
+/// `message` followed by a line for each file whose ownership could not be changed,
+/// with paths relative to the task folder unless `VERBOSE_ERRORS=1`
+///
+fn with_chown_warnings(message: &str, warnings: &[ChownWarning]) -> HttpResponse {
+    let env = Env::get();
+    let mut body = message.to_string();
+    let mut detail = Vec::new();
+    for warning in warnings {
+        if env.verbose_errors {
+            body += &format!("\nwarning: {}", warning);
+        } else {
+            body += &format!("\nwarning: {}", warning.relative_to(&env.task_dir));
+        }
+        detail.push(warning.to_string());
+    }
+    let mut response = HttpResponse::Ok().body(body);
+    if !detail.is_empty() {
+        response
+            .extensions_mut()
+            .insert(ErrorDetail(detail.join("; ")));
+    }
+    response
+}
+
+///
+/// manage an existing daemon `label` in `/Library/LaunchDaemons` as a tasker task
+///
+#[post("/adopt")]
+pub async fn adopt_param(req: HttpRequest, param: Query<Label>) -> impl Responder {
+    // the daemon to adopt is not named `com.tasker.tasks.*`
+    if let Some(response) = invalid_pattern(&param.label) {
+        return response;
+    }
+    let provenance =
+        Provenance::new(Source::Adopt, &authenticated_user(&req)).parent_label(&param.label);
+    match adopt_task(&param.label, &provenance) {
+        Ok(_) => HttpResponse::Ok().body("Successfully adopted task"),
+        Err(e) => task_error(e),
+    }
+}
+
+///
+/// replace the files of task `label` with the uploaded zip, keeping its label and output,
+/// `429` if `MAX_CONCURRENT_UPLOADS` uploads are in progress
+///
+#[post("/update_task")]
+pub async fn update_task(
+    mut payload: Multipart,
+    param: Query<Label>,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    let _permit = match concurrency.try_acquire(UPLOAD_ROUTE) {
+        Some(permit) => permit,
+        None => return Ok(saturated(UPLOAD_ROUTE)),
+    };
+    if let Ok(Some(mut field)) = payload.try_next().await {
+        let content_type = field.content_disposition().unwrap();
+        let filename = content_type.get_filename().unwrap();
+        let upload = TempUpload::new();
+        save_single_zip(&mut field, filename, &upload).await?;
+        if let Some(response) = not_an_archive(filename, &upload) {
+            return Ok(response);
+        }
+        match update_task_files(&param.label, &upload.path) {
+            Ok(warnings) => Ok(with_chown_warnings("Successfully updated task", &warnings)),
+            Err(e) => Ok(error_response(&mut HttpResponse::BadRequest(), &e)),
+        }
+    } else {
+        Ok(HttpResponse::BadRequest().body("no zip file uploaded"))
+    }
+}
+
+///
+/// an upload saved to a unique file in `TEMP_ZIP_PATH`, which is skipped by `clean_temp`
+/// and removed when this is dropped, so that concurrent uploads never share a file
+///
+struct TempUpload {
+    path: PathBuf,
+    _in_progress: InProgress,
+}
+
+impl TempUpload {
+    fn new() -> TempUpload {
+        let path = Path::new(TEMP_ZIP_PATH).join(format!(
+            "upload.{}.{}",
+            std::process::id(),
+            UPLOAD_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        TempUpload {
+            _in_progress: InProgress::new(&path),
+            path,
+        }
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("failed to remove upload `{}`: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+///
+/// `400` unless an upload is a zip or tar.gz, told by the extension of `filename`,
+/// or else by the first bytes of the `upload`
+///
+fn not_an_archive(filename: &str, upload: &TempUpload) -> Option<HttpResponse> {
+    if ArchiveFormat::from_filename(filename).is_some()
+        || ArchiveFormat::from_magic(&upload.path).is_some()
+    {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().body("not a zip or tar.gz file"))
 }
 
 ///
-/// this function saves the zip to TEMP_ZIP location
+/// this function saves the zip or tar.gz to the file of `upload`
 ///
-async fn save_single_zip(field: &mut Field, filename: &str) -> Result<(), actix_web::Error> {
+async fn save_single_zip(
+    field: &mut Field,
+    filename: &str,
+    upload: &TempUpload,
+) -> Result<(), actix_web::Error> {
+    let path = upload.path.clone();
     // File::create is blocking operation, use thread-pool
-    let mut f = web::block(|| std::fs::File::create(TEMP_ZIP))
-        .await
-        .unwrap();
+    let mut f = web::block(move || {
+        std::fs::create_dir_all(TEMP_ZIP_PATH)?;
+        std::fs::File::create(path)
+    })
+    .await
+    .unwrap();
 
     let mut size: usize = 0;
     while let Some(chunk) = field.next().await {
@@ -101,110 +359,2126 @@ async fn save_single_zip(field: &mut Field, filename: &str) -> Result<(), actix_
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct Label {
     label: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+pub struct ConfigName {
+    label: String,
+    name: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TemplateParams {
+    template: String,
+    new_label: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ZipExport {
+    label: String,
+    compression: Option<String>,
+    level: Option<u32>,
+    redact: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AllZipExport {
+    compression: Option<String>,
+    level: Option<u32>,
+    redact: Option<bool>,
+}
+
+///
+/// the zip of a `ZipExport`, shared by the requests waiting for it
+///
+type ZipExportResult = Result<PathBuf, Arc<actix_web::error::BlockingError<Error>>>;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct YamlView {
+    label: String,
+    redact: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SecretKey {
+    label: String,
+    key: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
 pub struct OutputLimited {
     label: String,
     limit: usize,
     filter: String,
+    /// only lines with a leading timestamp at or after this RFC3339 time
+    since: Option<String>,
+    /// only lines with a leading timestamp at or before this RFC3339 time
+    until: Option<String>,
+    /// remove ANSI escape sequences like colors, `true` by default
+    strip_ansi: Option<bool>,
+    /// render the 16 ANSI colors as html, escaping everything else
+    render_ansi: Option<bool>,
+}
+
+impl OutputLimited {
+    ///
+    /// `since` and `until` in milliseconds since epoch
+    ///
+    fn time_range(&self) -> Result<(Option<i64>, Option<i64>), Error> {
+        let parse = |name: &str, value: &Option<String>| match value {
+            None => Ok(None),
+            Some(value) => match parse_rfc3339(value) {
+                Some(time) => Ok(Some(time)),
+                None => Err(Error::IllegalTimestamp(format!(
+                    "{} `{}` is not a timestamp, accepted formats: {}",
+                    name, value, ACCEPTED_TIME_FORMATS
+                ))),
+            },
+        };
+        let since = parse("since", &self.since)?;
+        let until = parse("until", &self.until)?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since > until {
+                return Err(Error::IllegalTimestamp(
+                    "since is later than until".to_string(),
+                ));
+            }
+        }
+        Ok((since, until))
+    }
+
+    ///
+    /// each line of `log` with ANSI escape sequences rendered or stripped,
+    /// after the lines are filtered
+    ///
+    fn render_ansi(&self, log: &str) -> String {
+        let render: fn(&str) -> String = if self.render_ansi.unwrap_or(false) {
+            render_ansi_html
+        } else if self.strip_ansi.unwrap_or(true) {
+            strip_ansi_codes
+        } else {
+            return log.to_string();
+        };
+        log.split('\n').map(render).collect::<Vec<_>>().join("\n")
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ListFilter {
+    label: String,
+    /// how `label` matches, `substring` by default
+    #[serde(rename = "match")]
+    match_mode: Option<MatchMode>,
+    status: Option<String>,
+    count_only: Option<bool>,
+    include_stderr_tail: Option<usize>,
+    include_usage: Option<bool>,
+    include_next_run: Option<bool>,
+    /// run `launchctl list` again instead of reusing a list from `LAUNCHCTL_LIST_CACHE_MS`
+    fresh: Option<bool>,
 }
 
+///
+/// list tasks matching `label` as a substring, glob or regex by `match`, which is matched
+/// against the label without `com.tasker.tasks.`, optionally only those in comma-separated
+/// `status`, or only the count of each status if `count_only`.
+/// Errored tasks include their last `include_stderr_tail` lines of stderr (at most 50),
+/// and running tasks include their cpu and memory usage if `include_usage`.
+/// Loaded tasks include their next scheduled run if `include_next_run`.
+/// The status of tasks may be up to `LAUNCHCTL_LIST_CACHE_MS` old unless `fresh`.
+///
 #[get("/list_raw_json")]
-pub async fn list_raw_json(param: Query<Label>) -> impl Responder {
-    let list_result = list(&param.label);
+pub async fn list_raw_json(param: Query<ListFilter>) -> impl Responder {
+    let matcher = match LabelMatcher::new(&param.label, param.match_mode.unwrap_or_default()) {
+        Ok(matcher) => matcher,
+        Err(e) => return error_response(&mut HttpResponse::BadRequest(), &e),
+    };
+    let statuses = match parse_statuses(param.status.as_deref().unwrap_or("")) {
+        Ok(statuses) => statuses,
+        Err(e) => return task_error(e),
+    };
+    if param.fresh.unwrap_or(false) {
+        invalidate_launchctl_list();
+    }
+    let list_result = if param.count_only.unwrap_or(false) {
+        list_count(&matcher, &statuses)
+    } else {
+        let stderr_tail = param.include_stderr_tail.map(|n| n.min(MAX_STDERR_TAIL));
+        list(
+            &matcher,
+            &statuses,
+            stderr_tail,
+            param.include_usage.unwrap_or(false),
+            param.include_next_run.unwrap_or(false),
+        )
+    };
     match list_result {
         Ok(s) => HttpResponse::Ok().body(s),
-        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+        Err(e) => error_response(&mut HttpResponse::InternalServerError(), &e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TextListFilter {
+    /// all tasks if empty
+    #[serde(default)]
+    label: String,
+    /// how `label` matches, `substring` by default
+    #[serde(rename = "match")]
+    match_mode: Option<MatchMode>,
+    status: Option<String>,
+    /// the width of the table in characters, 120 by default
+    width: Option<usize>,
+}
+
+///
+/// the tasks of `/list_raw_json` as a plain text table for terminals, e.g. `curl`
+///
+#[get("/list_text")]
+pub async fn list_text_param(param: Query<TextListFilter>) -> impl Responder {
+    let matcher = match LabelMatcher::new(&param.label, param.match_mode.unwrap_or_default()) {
+        Ok(matcher) => matcher,
+        Err(e) => return error_response(&mut HttpResponse::BadRequest(), &e),
+    };
+    let statuses = match parse_statuses(param.status.as_deref().unwrap_or("")) {
+        Ok(statuses) => statuses,
+        Err(e) => return task_error(e),
+    };
+    let width = param.width.unwrap_or(DEFAULT_TEXT_WIDTH);
+    match list_text(&matcher, &statuses, width) {
+        Ok(table) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(table),
+        Err(e) => error_response(&mut HttpResponse::InternalServerError(), &e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct LabelFilter {
+    prefix: Option<String>,
+    strip_domain: Option<bool>,
+    with_status: Option<bool>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LabelStatus {
+    label: String,
+    /// `null` until the task is first polled
+    status: Option<Status>,
+}
+
+///
+/// labels of all tasks for autocompletion, only those starting with `prefix`,
+/// without `com.tasker.tasks.` if `strip_domain` (also for `prefix`),
+/// and with the status of the last poll if `with_status`.
+/// This reads neither launchctl nor, unless a task was created or deleted, the disk.
+///
+#[get("/labels")]
+pub async fn labels(param: Query<LabelFilter>) -> impl Responder {
+    let labels = match task_labels() {
+        Ok(labels) => labels,
+        Err(e) => return error_response(&mut HttpResponse::InternalServerError(), &e),
+    };
+    let domain = format!("{}.", TASKER_TASK_NAME);
+    let strip_domain = param.strip_domain.unwrap_or(false);
+    let prefix = param.prefix.as_deref().unwrap_or("");
+    let matching = labels.into_iter().filter_map(|label| {
+        let shown = match label.strip_prefix(&domain) {
+            Some(name) if strip_domain => name.to_string(),
+            _ => label.clone(),
+        };
+        if shown.starts_with(prefix) {
+            Some((label, shown))
+        } else {
+            None
+        }
+    });
+    if param.with_status.unwrap_or(false) {
+        let statuses = latest_statuses();
+        HttpResponse::Ok().json(
+            matching
+                .map(|(label, shown)| LabelStatus {
+                    label: shown,
+                    status: statuses.get(&label).copied(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        HttpResponse::Ok().json(matching.map(|(_, shown)| shown).collect::<Vec<_>>())
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TaskNotFound<'a> {
+    error: &'a str,
+    label: &'a str,
+}
+
+///
+/// `400` if `label` is not a label tasker could have created, before it is joined into any
+/// path, so that labels like `../../etc/passwd` never reach the file system
+///
+fn invalid_label(label: &str) -> Option<HttpResponse> {
+    validate_label_param(label)
+        .err()
+        .map(|e| error_response(&mut HttpResponse::BadRequest(), &e))
+}
+
+///
+/// `400`, `403` for a label tasker does not manage,
+/// or `503` with `Retry-After` while too many launchctl invocations are waiting
+///
+fn task_error(e: Error) -> HttpResponse {
+    task_error_of(&e)
+}
+
+///
+/// `task_error` of an error shared by the requests waiting for the same result
+///
+fn task_error_of(e: &Error) -> HttpResponse {
+    match e {
+        Error::LaunchctlBusy(_) => error_response(
+            HttpResponse::ServiceUnavailable().header(
+                header::RETRY_AFTER,
+                LAUNCHCTL_BUSY_RETRY_AFTER_SECS.to_string(),
+            ),
+            e,
+        ),
+        Error::NotManagedByTasker(_) => error_response(&mut HttpResponse::Forbidden(), e),
+        Error::YamlVersionMismatch(_) => error_response(&mut HttpResponse::Conflict(), e),
+        _ => error_response(&mut HttpResponse::BadRequest(), e),
     }
 }
 
+///
+/// the response of `builder` with the body of `e`, see `Error::response_body`.
+/// The detail of a hidden error is logged with the request id by `RequestTiming`,
+/// and returned only if `VERBOSE_ERRORS=1`.
+///
+fn error_response(builder: &mut HttpResponseBuilder, e: &Error) -> HttpResponse {
+    let mut response = builder.body(e.response_body(Env::get().verbose_errors));
+    if e.is_hidden() {
+        response.extensions_mut().insert(ErrorDetail(e.detail()));
+    }
+    response
+}
+
+///
+/// `400` if a substring pattern is not path-safe
+///
+fn invalid_pattern(pattern: &str) -> Option<HttpResponse> {
+    validate_pattern_param(pattern)
+        .err()
+        .map(|e| error_response(&mut HttpResponse::BadRequest(), &e))
+}
+
+///
+/// `400` if `label` is invalid (see `invalid_label`),
+/// or `404` naming the label if task `label` does not exist.
+/// Only for requests whose call does not report `TaskDoesNotExist` itself,
+/// which `label_error` turns into the same `404`.
+///
+async fn task_not_found(label: &str) -> Option<HttpResponse> {
+    if let Some(response) = invalid_label(label) {
+        return Some(response);
+    }
+    let task_label = label.to_string();
+    match web::block(move || exist(&task_label)).await {
+        Ok(true) => None,
+        Ok(false) => Some(not_found(label)),
+        Err(actix_web::error::BlockingError::Error(e)) => {
+            Some(error_response(&mut HttpResponse::InternalServerError(), &e))
+        }
+        Err(e) => Some(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+    }
+}
+
+fn not_found(label: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(TaskNotFound {
+        error: "task does not exist",
+        label,
+    })
+}
+
+///
+/// `task_error`, or the `404` of `task_not_found` for `TaskDoesNotExist`
+///
+fn label_error(label: &str, e: Error) -> HttpResponse {
+    label_error_of(label, &e)
+}
+
+fn label_error_of(label: &str, e: &Error) -> HttpResponse {
+    match e {
+        Error::TaskDoesNotExist(_) => not_found(label),
+        _ => task_error_of(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteTask {
+    label: String,
+    confirm: Option<String>,
+    force: Option<bool>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ConfirmationRequired<'a> {
+    error: &'a str,
+    label: &'a str,
+    confirm: String,
+    expires_in_secs: u64,
+}
+
+///
+/// `409` with a new confirmation token if task `label` is loaded,
+/// unless `token` is its unexpired confirmation token
+///
+fn confirmation_required(
+    label: &str,
+    token: Option<&str>,
+    confirmations: &Mutex<Confirmations>,
+) -> Option<HttpResponse> {
+    match is_loaded(label) {
+        Ok(false) => None,
+        Ok(true) => {
+            let mut confirmations = confirmations.lock().unwrap();
+            let now = Instant::now();
+            if let Some(token) = token {
+                if confirmations.confirm(label, token, now) {
+                    return None;
+                }
+            }
+            let error = match token {
+                Some(_) => "confirmation token is wrong or expired, retry with the new token",
+                None => "task is loaded, repeat the request with the confirmation token",
+            };
+            Some(HttpResponse::Conflict().json(ConfirmationRequired {
+                error,
+                label,
+                confirm: confirmations.issue(label, now),
+                expires_in_secs: confirmations.ttl().as_secs(),
+            }))
+        }
+        Err(e) => Some(error_response(&mut HttpResponse::InternalServerError(), &e)),
+    }
+}
+
+///
+/// delete a task. A loaded task is deleted only when the request is repeated with
+/// `confirm=<token>` from the `409` response before the token expires,
+/// or with `force=true` for automation.
+///
 #[get("/delete")]
-pub async fn delete_param(param: Query<Label>) -> impl Responder {
+pub async fn delete_param(
+    param: Query<DeleteTask>,
+    confirmations: web::Data<Mutex<Confirmations>>,
+) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    if !param.force.unwrap_or(false) {
+        let token = param.confirm.as_deref();
+        if let Some(response) = confirmation_required(&param.label, token, &confirmations) {
+            return response;
+        }
+    }
     let delete_result = delete_task(&param.label);
     match delete_result {
-        Ok(_) => HttpResponse::Ok().body("Successfully deleted task"),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(Error::FailedToDeleteTask(report)) => HttpResponse::BadRequest().json(report),
+        Err(e) => label_error(&param.label, e),
     }
 }
 
 #[get("/load")]
 pub async fn load_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
     let load_task = load_task(&param.label);
     match load_task {
         Ok(_) => HttpResponse::Ok().body("Successfully loaded task"),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+        Err(e) => label_error(&param.label, e),
     }
 }
 
 #[get("/unload")]
 pub async fn unload_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
     let unload_task = unload_task(&param.label);
     match unload_task {
         Ok(_) => HttpResponse::Ok().body("Successfully unloaded task"),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+        Err(e) => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct StopSignal {
+    label: String,
+    /// `TERM` (the default), `INT`, `HUP`, `KILL`, `USR1` or `USR2`
+    signal: Option<String>,
+    /// signal the whole process group of the task
+    #[serde(default)]
+    group: bool,
+}
+
+///
+/// send a signal to the running instance of a task
+///
+#[get("/stop")]
+pub async fn stop_param(param: Query<StopSignal>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    match stop_task(&param.label, param.signal.as_deref(), param.group) {
+        Ok((signal, pid)) => {
+            HttpResponse::Ok().body(format!("Successfully sent SIG{} to pid {}", signal, pid))
+        }
+        Err(e) => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RestartSignal {
+    label: String,
+    /// `TERM` (the default), `INT`, `HUP`, `KILL`, `USR1` or `USR2`
+    signal: Option<String>,
+    /// signal the whole process group of the task
+    #[serde(default)]
+    group: bool,
+    /// how long to wait for the task to exit before `SIGKILL`, 10 by default
+    grace_seconds: Option<u64>,
+}
+
+///
+/// stop a task with a signal, kill it if it is still running after the grace period,
+/// and start it again
+///
+#[get("/restart")]
+pub async fn restart_param(param: Query<RestartSignal>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    let grace = param.grace_seconds.unwrap_or(DEFAULT_GRACE_SECONDS);
+    if grace > MAX_GRACE_SECONDS {
+        return HttpResponse::BadRequest().body(format!(
+            "grace_seconds must be at most {}",
+            MAX_GRACE_SECONDS
+        ));
+    }
+    let param = param.into_inner();
+    let restarted = web::block(move || {
+        restart_task(
+            &param.label,
+            param.signal.as_deref(),
+            param.group,
+            Duration::from_secs(grace),
+        )
+    })
+    .await;
+    match restarted {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(actix_web::error::BlockingError::Error(e)) => task_error(e),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// rotate stdout and stderr, and restart the task if it is running
+///
+#[get("/rotate_logs")]
+pub async fn rotate_logs_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    let rotate_result = rotate_logs(&param.label);
+    match rotate_result {
+        Ok(_) => HttpResponse::Ok().body("Successfully rotated logs"),
+        Err(e) => task_error(e),
+    }
+}
+
+///
+/// move the output folder to trash, and start over with an empty one
+///
+#[post("/clear_output")]
+pub async fn clear_output_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match clear_output(&param.label) {
+        Ok(_) => HttpResponse::Ok().body("Successfully cleared output"),
+        Err(e) => label_error(&param.label, e),
     }
 }
 
-fn plain_text_response(s: Result<String, Error>) -> HttpResponse {
+fn plain_text_response(s: Result<String, Error>, param: &OutputLimited) -> HttpResponse {
     match s {
-        Ok(s) => HttpResponse::Ok().body(s.replace("\n", "<br>")),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+        Ok(s) => HttpResponse::Ok().body(param.render_ansi(&s).replace("\n", "<br>")),
+        Err(e) => task_error(e),
     }
 }
 
 #[get("/stdout_raw")]
 pub async fn stdout_param(param: Query<OutputLimited>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
     if param.limit < 1 || param.limit > MAX_OUTPUT_LIMIT {
         return HttpResponse::BadRequest().body(format!(
             "limit should be between 1 and {:}",
             MAX_OUTPUT_LIMIT
         ));
     }
-    let out = view_std_out(&param.label, param.limit, &param.filter);
-    plain_text_response(out)
+    let (since, until) = match param.time_range() {
+        Ok(range) => range,
+        Err(e) => return task_error(e),
+    };
+    let out = view_std_out(&param.label, param.limit, &param.filter, since, until);
+    plain_text_response(out, &param)
 }
 
 #[get("/stderr_raw")]
 pub async fn stderr_param(param: Query<OutputLimited>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
     if param.limit < 1 || param.limit > MAX_OUTPUT_LIMIT {
         return HttpResponse::BadRequest().body(format!(
             "limit should be between 1 and {:}",
             MAX_OUTPUT_LIMIT
         ));
     }
-    let err = view_std_err(&param.label, param.limit, &param.filter);
-    plain_text_response(err)
+    let (since, until) = match param.time_range() {
+        Ok(range) => range,
+        Err(e) => return task_error(e),
+    };
+    let err = view_std_err(&param.label, param.limit, &param.filter, since, until);
+    plain_text_response(err, &param)
+}
+
+///
+/// cpu and memory usage of a running task and its child processes,
+/// `null` if the task is not running
+///
+#[get("/usage")]
+pub async fn usage_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match resource_usage(&param.label) {
+        Ok(usage) => HttpResponse::Ok().json(usage),
+        Err(e) => task_error(e),
+    }
+}
+
+///
+/// the effective command line, environment, working directory, user and group of a task,
+/// with a shell one-liner to reproduce the run, and secrets masked unless `redact=false`
+///
+#[get("/command")]
+pub async fn command_param(param: Query<YamlView>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match effective_command(&param.label, param.redact.unwrap_or(true)) {
+        Ok(command) => HttpResponse::Ok().json(command),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+///
+/// run count, mean and max duration, and failure rate over the retained run history
+///
+#[get("/stats")]
+pub async fn stats_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    HttpResponse::Ok().json(run_stats(&param.label))
+}
+
+///
+/// the OpenAPI 3 description of the HTTP API
+///
+#[get("/openapi.json")]
+pub async fn openapi_param() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(OPENAPI_JSON.as_str())
+}
+
+///
+/// the JSON Schema of a task yaml, e.g. for `yaml.schemas` of VS Code
+///
+#[get("/yaml_schema")]
+pub async fn yaml_schema() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(YAML_SCHEMA_JSON.as_str())
+}
+
+///
+/// push the task list on connect, and then every change of it
+///
+#[get("/ws")]
+pub async fn ws_param(req: HttpRequest, stream: web::Payload) -> actix_web::Result<HttpResponse> {
+    ws::start(TaskEvents::subscribe(), &req, stream)
 }
 
+///
+/// view the yaml of a task, with secrets masked unless `redact=false`.
+/// The `ETag` header is the version of the stored yaml, for `If-Match` of `/post_yaml`.
+///
 #[get("/get_yaml")]
-pub async fn get_yaml(param: Query<Label>) -> impl Responder {
-    let yaml = view_yaml(&param.label);
-    match yaml {
-        Ok(s) => HttpResponse::Ok().body(s),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+pub async fn get_yaml(param: Query<YamlView>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match view_yaml_version(&param.label, param.redact.unwrap_or(true)) {
+        Ok(version) => HttpResponse::Ok()
+            .header(header::ETAG, version.etag)
+            .body(version.yaml),
+        Err(e) => label_error(&param.label, e),
     }
 }
 
-#[post("/post_yaml")]
-pub async fn post_yaml(body: String, param: Query<Label>) -> impl Responder {
-    let result = update_yaml(&body, &param.label);
-    match result {
-        Ok(_) => HttpResponse::Ok().body("Successfully updated yaml"),
-        Err(e) => HttpResponse::BadRequest().body(format!("{:?}", e)),
+#[derive(Deserialize, JsonSchema)]
+pub struct PlistSource {
+    label: String,
+    source: Option<String>,
+    redact: Option<bool>,
+}
+
+///
+/// view the plist generated from yaml (`source=generated`, default),
+/// or the plist on disk (`source=disk`), with secrets masked unless `redact=false`
+///
+#[get("/get_plist")]
+pub async fn get_plist(param: Query<PlistSource>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    let redact = param.redact.unwrap_or(true);
+    let plist = match param.source.as_deref() {
+        None | Some("generated") => view_plist(&param.label, redact),
+        Some("disk") => view_plist_on_disk(&param.label, redact),
+        Some(other) => {
+            return HttpResponse::BadRequest().body(format!(
+                "unknown source `{}`, should be `generated` or `disk`",
+                other
+            ))
+        }
+    };
+    match plist {
+        Ok(s) => HttpResponse::Ok().content_type("application/xml").body(s),
+        Err(e) => task_error(e),
     }
 }
 
-#[get("/get_task_zip")]
-pub async fn get_task_zip(param: Query<Label>) -> actix_web::Result<NamedFile> {
-    let result = get_zip(&param.label);
-    match result {
-        Ok(p) => Ok(NamedFile::open(p)?),
-        Err(e) => Err(actix_web::Error::from(
-            HttpResponse::BadRequest().body(format!("{:?}", e)),
-        )),
+///
+/// show pending changes between the yaml in meta folder and the plist on disk,
+/// with secrets masked unless `redact=false`
+///
+#[get("/diff")]
+pub async fn diff_param(param: Query<YamlView>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match diff_plist(&param.label, param.redact.unwrap_or(true)) {
+        Ok(diff) => HttpResponse::Ok().json(diff),
+        Err(e) => task_error(e),
+    }
+}
+
+///
+/// who created a task, how, and who edited its yaml since.
+/// `404` for tasks created before provenance was recorded.
+///
+#[get("/provenance")]
+pub async fn provenance_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    match read_provenance(&param.label) {
+        Some(provenance) => HttpResponse::Ok().json(provenance),
+        None => HttpResponse::NotFound().body("no provenance recorded for this task"),
+    }
+}
+
+///
+/// limit the size of posted yaml to `MAX_YAML_BYTES`, larger yaml is rejected with 413
+///
+pub fn yaml_payload_config() -> web::PayloadConfig {
+    web::PayloadConfig::new(MAX_YAML_BYTES)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct YamlUpdate {
+    label: String,
+    force: Option<bool>,
+    dry_run: Option<bool>,
+}
+
+///
+/// update the yaml of a task. A plist edited outside of tasker is not overwritten
+/// (`409`) unless `force=true`. With `dry_run=true`, the yaml is only checked,
+/// and a report of what would change is returned.
+///
+/// With an `If-Match` header (the `ETag` of `/get_yaml`), the yaml is only updated if
+/// nobody changed it since, otherwise `412` is returned with the current (redacted)
+/// `yaml` and its `etag` to merge with. Without `If-Match`, the last edit wins.
+/// The `ETag` of the updated yaml is returned on success.
+///
+/// A loaded task is reloaded to apply the yaml, but not run even if it has `RunAtLoad: true`,
+/// which the response tells. `RunAtLoad` then applies from the next load, e.g. after a reboot.
+///
+#[post("/post_yaml")]
+pub async fn post_yaml(req: HttpRequest, body: String, param: Query<YamlUpdate>) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    if param.dry_run.unwrap_or(false) {
+        return match preview_yaml(&body, &param.label) {
+            Ok(preview) => HttpResponse::Ok().json(preview),
+            Err(e) => task_error(e),
+        };
+    }
+    let if_match = req
+        .headers()
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok());
+    let result = update_yaml(
+        &body,
+        &param.label,
+        param.force.unwrap_or(false),
+        if_match,
+        &authenticated_user(&req),
+    );
+    match result {
+        Ok(reload) => {
+            let mut response = HttpResponse::Ok();
+            if let Ok(etag) = yaml_etag(&param.label) {
+                response.header(header::ETAG, etag);
+            }
+            response.body(format!("Successfully updated yaml, {}", reload.describe()))
+        }
+        Err(e @ Error::PlistModifiedExternally(_)) => {
+            error_response(&mut HttpResponse::Conflict(), &e)
+        }
+        Err(e @ Error::YamlVersionMismatch(_)) => match view_yaml_version(&param.label, true) {
+            Ok(current) => HttpResponse::PreconditionFailed().json(current),
+            Err(_) => task_error(e),
+        },
+        Err(e) => task_error(e),
+    }
+}
+
+#[post("/mark_secret")]
+pub async fn mark_secret_param(param: Query<SecretKey>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match mark_secret(&param.label, &param.key, true) {
+        Ok(_) => HttpResponse::Ok().json(read_secrets(&param.label)),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+#[post("/unmark_secret")]
+pub async fn unmark_secret_param(param: Query<SecretKey>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match mark_secret(&param.label, &param.key, false) {
+        Ok(_) => HttpResponse::Ok().json(read_secrets(&param.label)),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+///
+/// stop the server like SIGTERM, responding `202` before exiting
+///
+#[post("/shutdown")]
+pub async fn shutdown_param() -> impl Responder {
+    if request_shutdown() {
+        HttpResponse::Accepted().body("Shutting down")
+    } else {
+        HttpResponse::ServiceUnavailable().body("server is not running")
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Health {
+    /// `degraded` if a check failed
+    status: &'static str,
+    /// mutations are refused in maintenance mode, which does not fail the health check
+    maintenance: bool,
+    /// launchctl invocations running and waiting
+    launchctl: QueueStats,
+    /// requests of the routes limited by `ConcurrencyLimiter`
+    concurrency: BTreeMap<&'static str, RouteConcurrency>,
+    tls: TlsStatus,
+    settings: SettingsCheck,
+    /// a failed backup, or no successful backup within two `BACKUP_INTERVAL_HOURS`
+    backup: BackupCheck,
+}
+
+///
+/// whether the paths of existing tasks changed since they were created, e.g. `TASKER_ROOT`
+///
+#[derive(Serialize, JsonSchema)]
+pub struct SettingsCheck {
+    passed: bool,
+    changes: Vec<SettingChange>,
+    guidance: Option<&'static str>,
+}
+
+impl SettingsCheck {
+    fn new(changes: Vec<SettingChange>) -> SettingsCheck {
+        SettingsCheck {
+            passed: changes.is_empty(),
+            guidance: if changes.is_empty() {
+                None
+            } else {
+                Some(MIGRATION_GUIDANCE)
+            },
+            changes,
+        }
+    }
+}
+
+///
+/// whether the server listens with SSL, and the certificate it was started with
+///
+#[derive(Serialize, JsonSchema)]
+pub struct TlsStatus {
+    enabled: bool,
+    subject: Option<String>,
+    /// RFC3339 in UTC
+    expires_at: Option<String>,
+    /// negative once expired
+    days_remaining: Option<i64>,
+}
+
+impl TlsStatus {
+    fn new(enabled: bool, certificate: Option<&CertificateInfo>) -> TlsStatus {
+        TlsStatus {
+            enabled,
+            subject: certificate.map(|c| c.subject.clone()),
+            expires_at: certificate.and_then(|c| c.expires_at()),
+            days_remaining: certificate.map(|c| c.days_remaining_now()),
+        }
+    }
+}
+
+#[get("/health")]
+pub async fn health_param(
+    maintenance: web::Data<RwLock<MaintenanceState>>,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> impl Responder {
+    let env = Env::get();
+    let settings = SettingsCheck::new(settings_changes());
+    let backup =
+        BackupState::load(&backup_state_file()).check(env.backup_interval, SystemTime::now());
+    HttpResponse::Ok().json(Health {
+        status: if settings.passed && backup.passed {
+            "ok"
+        } else {
+            "degraded"
+        },
+        maintenance: maintenance.read().unwrap().enabled,
+        launchctl: launchctl_queue_stats(),
+        concurrency: concurrency.stats(),
+        tls: TlsStatus::new(env.ssl_enabled(), env.certificate.as_ref()),
+        settings,
+        backup,
+    })
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TlsReload {
+    restarting: bool,
+    /// the certificate that is served after the restart
+    tls: TlsStatus,
+    message: &'static str,
+}
+
+///
+/// check `SSL_PRIVATE_KEY` and `SSL_CERTIFICATE` again, and restart tasker gracefully
+/// to serve them, responding `202` before restarting.
+/// The acceptor of a running actix server cannot be replaced, so the process re-executes
+/// itself with the same pid and arguments, like `/shutdown` followed by a start.
+///
+#[post("/reload_tls")]
+pub async fn reload_tls_param() -> impl Responder {
+    let env = Env::get();
+    let (pk, crt) = match (&env.pk_dir, &env.crt_dir) {
+        (Some(pk), Some(crt)) if env.ssl_enabled() => (pk, crt),
+        _ => return HttpResponse::BadRequest().body("SSL is not enabled"),
+    };
+    let certificate = match ssl_acceptor(pk, crt).and_then(|_| read_certificate(crt)) {
+        Ok(certificate) => certificate,
+        Err(e) => return error_response(&mut HttpResponse::BadRequest(), &e),
+    };
+    if request_restart() {
+        HttpResponse::Accepted().json(TlsReload {
+            restarting: true,
+            tls: TlsStatus::new(true, Some(&certificate)),
+            message: "key and certificate are valid, tasker restarts gracefully to serve them",
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().body("server is not running")
+    }
+}
+
+///
+/// per-route request durations and the depth of the launchctl queue in the Prometheus
+/// text format
+///
+#[get("/metrics")]
+pub async fn metrics_param(metrics: web::Data<Mutex<RequestMetrics>>) -> impl Responder {
+    let body =
+        metrics.lock().unwrap().render() + &launchctl_queue_stats().render("tasker_launchctl");
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+///
+/// the maintenance mode, for the banner of the web pages
+///
+#[get("/maintenance")]
+pub async fn get_maintenance(maintenance: web::Data<RwLock<MaintenanceState>>) -> impl Responder {
+    let state = maintenance.read().unwrap().clone();
+    HttpResponse::Ok().json(state)
+}
+
+///
+/// turn maintenance mode on or off, refusing every mutation while on
+///
+#[post("/maintenance")]
+pub async fn post_maintenance(
+    maintenance: web::Data<RwLock<MaintenanceState>>,
+    update: web::Json<MaintenanceUpdate>,
+) -> impl Responder {
+    match set_maintenance(&maintenance, update.into_inner()) {
+        Ok(state) => HttpResponse::Ok().json(state),
+        Err(e) => task_error(e),
+    }
+}
+
+#[post("/config/set")]
+pub async fn config_set(req: HttpRequest, body: String, param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    let user = authenticated_user(&req);
+    let result = Config::from_yaml(&body)
+        .and_then(|config| patch_config(&param.label, ConfigPatch::Set(config), &user));
+    match result {
+        Ok(reload) => HttpResponse::Ok().body(format!(
+            "Successfully updated configuration, {}",
+            reload.describe()
+        )),
+        Err(e) => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ProgramParam {
+    program: String,
+}
+
+///
+/// check the label of a new task, e.g. for the first step of a creation wizard,
+/// and return its full form. `409` if a task has the label.
+///
+#[get("/validate/label")]
+pub async fn validate_label(param: Query<Label>) -> impl Responder {
+    match check_new_label(&param.label) {
+        Ok(label) => HttpResponse::Ok().json(label),
+        Err(e @ Error::TaskAlreadyExists(_)) => error_response(&mut HttpResponse::Conflict(), &e),
+        Err(e) => error_response(&mut HttpResponse::BadRequest(), &e),
+    }
+}
+
+///
+/// check that `program` is an absolute path of an executable file,
+/// and report its owner as a suggestion for `UserName`
+///
+#[get("/validate/program")]
+pub async fn validate_program(param: Query<ProgramParam>) -> impl Responder {
+    match check_new_program(&param.program) {
+        Ok(program) => HttpResponse::Ok().json(program),
+        Err(e) => error_response(&mut HttpResponse::BadRequest(), &e),
+    }
+}
+
+///
+/// check a single configuration such as `StartInterval: 3600` as `/config/set` would,
+/// and return it as normalized yaml, without changing any task
+///
+#[post("/validate/config_entry")]
+pub async fn validate_config_entry(body: String) -> impl Responder {
+    match Config::from_yaml(&body).and_then(|config| config.to_yaml()) {
+        Ok(yaml) => HttpResponse::Ok().body(yaml),
+        Err(e) => error_response(&mut HttpResponse::BadRequest(), &e),
+    }
+}
+
+#[post("/config/remove")]
+pub async fn config_remove(req: HttpRequest, param: Query<ConfigName>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    let patch = ConfigPatch::Remove(param.name.clone());
+    match patch_config(&param.label, patch, &authenticated_user(&req)) {
+        Ok(reload) => HttpResponse::Ok().body(format!(
+            "Successfully removed configuration, {}",
+            reload.describe()
+        )),
+        Err(e) => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ScheduleUpdate {
+    label: String,
+    allow_both: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RunOnceParams {
+    label: String,
+    force: Option<bool>,
+}
+
+///
+/// the optional body of `/run_once`, which is never stored
+///
+#[derive(Deserialize, JsonSchema, Default)]
+pub struct RunOverrides {
+    #[serde(default)]
+    extra_args: Vec<String>,
+    #[serde(default)]
+    env_overrides: BTreeMap<String, String>,
+}
+
+///
+/// run a task once now outside of launchd, with output in `manual/` of its output folder,
+/// and return the run id for `/run_once_status`. A task running under launchd is refused
+/// with `409` unless `force=true`.
+///
+#[post("/run_once")]
+pub async fn run_once_param(
+    param: Query<RunOnceParams>,
+    body: web::Bytes,
+    runs: web::Data<Mutex<ManualRuns>>,
+) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    let overrides = if body.is_empty() {
+        RunOverrides::default()
+    } else {
+        match serde_json::from_slice::<RunOverrides>(&body) {
+            Ok(overrides) => overrides,
+            Err(e) => return HttpResponse::BadRequest().body(format!("invalid overrides: {}", e)),
+        }
+    };
+    let run = run_once(
+        &param.label,
+        &overrides.extra_args,
+        &overrides.env_overrides,
+        param.force.unwrap_or(false),
+    );
+    match run {
+        Ok(run) => HttpResponse::Ok().json(runs.lock().unwrap().insert(run)),
+        Err(e @ Error::TaskIsRunning(_)) => error_response(&mut HttpResponse::Conflict(), &e),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RunId {
+    run_id: String,
+}
+
+///
+/// whether a run of `/run_once` is still running, and its exit status when done
+///
+#[get("/run_once_status")]
+pub async fn run_once_status_param(
+    param: Query<RunId>,
+    runs: web::Data<Mutex<ManualRuns>>,
+) -> impl Responder {
+    match runs.lock().unwrap().status(&param.run_id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().body("unknown run id"),
+    }
+}
+
+///
+/// the scheduling configurations of a task as JSON
+///
+#[get("/schedule")]
+pub async fn get_schedule(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match view_schedule(&param.label) {
+        Ok(schedule) => HttpResponse::Ok().json(schedule),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+///
+/// replace the scheduling configurations of a task, keeping all other configurations,
+/// and reload the task if it is loaded
+///
+#[post("/schedule")]
+pub async fn post_schedule(
+    req: HttpRequest,
+    schedule: web::Json<Schedule>,
+    param: Query<ScheduleUpdate>,
+) -> impl Responder {
+    if let Some(response) = task_not_found(&param.label).await {
+        return response;
+    }
+    let patch = ConfigPatch::Schedule {
+        schedule: schedule.into_inner(),
+        allow_both: param.allow_both.unwrap_or(false),
+    };
+    match patch_config(&param.label, patch, &authenticated_user(&req)) {
+        Ok(reload) => HttpResponse::Ok().body(format!(
+            "Successfully updated schedule, {}",
+            reload.describe()
+        )),
+        Err(e) => task_error(e),
+    }
+}
+
+///
+/// create task `new_label` from task `template`, with a JSON body of placeholder values
+///
+#[post("/create_from_template")]
+pub async fn create_from_template_param(
+    req: HttpRequest,
+    param: Query<TemplateParams>,
+    values: web::Json<BTreeMap<String, String>>,
+) -> impl Responder {
+    if let Some(response) = task_not_found(&param.template).await {
+        return response;
+    }
+    // `new_label` may omit `com.tasker.tasks.`, and is checked like any label on creation
+    if let Some(response) = invalid_pattern(&param.new_label) {
+        return response;
+    }
+    let provenance =
+        Provenance::new(Source::Template, &authenticated_user(&req)).parent_label(&param.template);
+    match create_from_template(&param.template, &param.new_label, &values, &provenance) {
+        Ok(warnings) => with_chown_warnings("Successfully created task from template", &warnings),
+        Err(e) => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct GitSource {
+    url: String,
+    /// a branch, a tag or a full commit hash
+    #[serde(rename = "ref")]
+    git_ref: String,
+    /// the folder of the task in the repository
+    subdir: Option<String>,
+    /// replaces the label in the yaml
+    label: Option<String>,
+}
+
+///
+/// create a task from a shallow checkout of a git repository, with a JSON body
+/// `{url, ref, subdir, label}`
+///
+#[post("/create_from_git")]
+pub async fn create_from_git_param(
+    req: HttpRequest,
+    source: web::Json<GitSource>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let source = source.into_inner();
+    // `label` may omit `com.tasker.tasks.`, and is checked like any label on creation
+    if let Some(label) = &source.label {
+        if let Some(response) = invalid_pattern(label) {
+            return Ok(response);
+        }
+    }
+    let provenance = Provenance::new(Source::Git, &authenticated_user(&req));
+    let origin = GitOrigin {
+        url: source.url,
+        git_ref: source.git_ref,
+        subdir: source.subdir,
+        commit: String::new(),
+    };
+    let label = source.label;
+    match web::block(move || create_from_git(origin, label.as_deref(), provenance)).await {
+        Ok(created) => Ok(HttpResponse::Ok().json(created)),
+        Err(actix_web::error::BlockingError::Error(e)) => Ok(git_error(e)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+    }
+}
+
+///
+/// check out the ref that task `label` was created from again, and replace its files
+///
+#[post("/refresh_from_git")]
+pub async fn refresh_from_git_param(
+    req: HttpRequest,
+    param: Query<Label>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    let label = param.label.clone();
+    let by = authenticated_user(&req);
+    match web::block(move || refresh_from_git(&label, &by)).await {
+        Ok(refreshed) => Ok(HttpResponse::Ok().json(refreshed)),
+        Err(actix_web::error::BlockingError::Error(Error::TaskDoesNotExist(_))) => {
+            Ok(not_found(&param.label))
+        }
+        Err(actix_web::error::BlockingError::Error(e)) => Ok(git_error(e)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+    }
+}
+
+///
+/// `502` if the remote cannot be reached, `404` for a missing ref,
+/// `409` if the label is taken, otherwise as `task_error`
+///
+fn git_error(e: Error) -> HttpResponse {
+    match e {
+        Error::GitRemoteUnreachable(_) => error_response(&mut HttpResponse::BadGateway(), &e),
+        Error::GitRefNotFound(_) => error_response(&mut HttpResponse::NotFound(), &e),
+        Error::TaskAlreadyExists(_) => error_response(&mut HttpResponse::Conflict(), &e),
+        _ => task_error(e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct FilePath {
+    label: String,
+    path: String,
+}
+
+///
+/// `400` for paths escaping the task folder, `404` for missing files,
+/// otherwise as `task_error`
+///
+fn file_error(e: Error) -> HttpResponse {
+    match e {
+        Error::PathDoesNotExist(_) => error_response(&mut HttpResponse::NotFound(), &e),
+        _ => task_error(e),
+    }
+}
+
+///
+/// files and folders in the task folder, with size and modification time
+///
+#[get("/files")]
+pub async fn files_param(param: Query<Label>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match list_task_files(&param.label) {
+        Ok(files) => HttpResponse::Ok().json(files),
+        Err(e) => label_error(&param.label, e),
+    }
+}
+
+///
+/// download a single file at relative `path` in the task folder
+///
+#[get("/file")]
+pub async fn file_param(
+    req: HttpRequest,
+    param: Query<FilePath>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    let file = match task_file(&param.label, &param.path) {
+        Ok(file) => file,
+        Err(Error::TaskDoesNotExist(_)) => return Ok(not_found(&param.label)),
+        Err(e) => return Ok(file_error(e)),
+    };
+    NamedFile::open(file)?.into_response(&req)
+}
+
+///
+/// create or replace a single file at relative `path` in the task folder
+/// with the request body (at most `MB_LIMIT` MB),
+/// `429` if `MAX_CONCURRENT_UPLOADS` uploads are in progress
+///
+#[post("/file")]
+pub async fn upload_file_param(
+    mut body: web::Payload,
+    param: Query<FilePath>,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(response) = task_not_found(&param.label).await {
+        return Ok(response);
+    }
+    let _permit = match concurrency.try_acquire(UPLOAD_ROUTE) {
+        Some(permit) => permit,
+        None => return Ok(saturated(UPLOAD_ROUTE)),
+    };
+    let mut content = web::BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if content.len() + chunk.len() > SIZE_LIMIT {
+            return Ok(HttpResponse::PayloadTooLarge()
+                .body(format!("file should be at most {} MB", MB_LIMIT)));
+        }
+        content.extend_from_slice(&chunk);
+    }
+    let label = param.label.clone();
+    let path = param.path.clone();
+    match web::block(move || write_task_file(&label, &path, &content)).await {
+        Ok(written) => Ok(HttpResponse::Ok().json(written)),
+        Err(actix_web::error::BlockingError::Error(e)) => Ok(file_error(e)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ArtifactList {
+    label: String,
+    zip: Option<bool>,
+    compression: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ArtifactPath {
+    label: String,
+    name: String,
+}
+
+///
+/// files produced by a task in its output folder except logs, with size and
+/// modification time, or all of them as a zip with `zip=true`
+///
+#[get("/artifacts")]
+pub async fn artifacts_param(
+    req: HttpRequest,
+    param: Query<ArtifactList>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    if !param.zip.unwrap_or(false) {
+        return Ok(match list_artifacts(&param.label) {
+            Ok(files) => HttpResponse::Ok().json(files),
+            Err(e) => label_error(&param.label, e),
+        });
+    }
+    let compression = param.compression.as_deref().unwrap_or(DEFAULT_COMPRESSION);
+    let method = match parse_compression_method(compression) {
+        Ok(method) => method,
+        Err(e) => return Ok(task_error(e)),
+    };
+    let label = param.label.clone();
+    let zip_path = match web::block(move || get_artifacts_zip(&label, method)).await {
+        Ok(zip_path) => zip_path,
+        Err(actix_web::error::BlockingError::Error(e)) => return Ok(label_error(&param.label, e)),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+    };
+    NamedFile::open(zip_path)?.into_response(&req)
+}
+
+///
+/// download a single artifact at relative `name` in the output folder
+///
+#[get("/artifact")]
+pub async fn artifact_param(
+    req: HttpRequest,
+    param: Query<ArtifactPath>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    let file = match artifact_file(&param.label, &param.name) {
+        Ok(file) => file,
+        Err(Error::TaskDoesNotExist(_)) => return Ok(not_found(&param.label)),
+        Err(e) => return Ok(file_error(e)),
+    };
+    let file_name = match file.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => param.name.clone(),
+    };
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(file_name)],
+    };
+    NamedFile::open(file)?
+        .set_content_disposition(disposition)
+        .into_response(&req)
+}
+
+///
+/// delete a single artifact at relative `name` in the output folder
+///
+#[delete("/artifact")]
+pub async fn delete_artifact_param(param: Query<ArtifactPath>) -> impl Responder {
+    if let Some(response) = invalid_label(&param.label) {
+        return response;
+    }
+    match delete_artifact(&param.label, &param.name) {
+        Ok(_) => HttpResponse::Ok().body("Successfully deleted artifact"),
+        Err(Error::TaskDoesNotExist(_)) => not_found(&param.label),
+        Err(e) => file_error(e),
+    }
+}
+
+///
+/// export a task as zip. Requests for the same zip while it is being built
+/// wait for it instead of building it again, and other requests are refused with `429`
+/// while `MAX_CONCURRENT_EXPORTS` zips are being built.
+///
+#[get("/get_task_zip")]
+pub async fn get_task_zip(
+    req: HttpRequest,
+    param: Query<ZipExport>,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> actix_web::Result<HttpResponse> {
+    let (compression, method) = match export_compression(&param.compression, &param.level) {
+        Ok(compression) => compression,
+        Err(e) => return Ok(task_error(e)),
+    };
+    if let Some(response) = invalid_label(&param.label) {
+        return Ok(response);
+    }
+    let label = param.label.clone();
+    let redact = param.redact.unwrap_or(true);
+    let key = (label.clone(), compression.clone(), redact);
+    let exported = ZIP_EXPORTS.run(key, || match concurrency.try_acquire(EXPORT_ROUTE) {
+        Some(permit) => Ok(async move {
+            let _permit = permit;
+            web::block(move || get_zip(&label, method, redact))
+                .await
+                .map_err(Arc::new)
+        }),
+        None => Err(saturated(EXPORT_ROUTE)),
+    });
+    let zip_path = match exported.await {
+        Ok(Ok(zip_path)) => zip_path,
+        Ok(Err(e)) => match &*e {
+            actix_web::error::BlockingError::Error(e) => {
+                return Ok(label_error_of(&param.label, e))
+            }
+            e => return Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+        },
+        Err(response) => return Ok(response),
+    };
+    // the zip is named after its options, and downloaded as `<label>.zip`
+    zip_download(
+        &req,
+        zip_path,
+        &(param.label.clone() + ".zip"),
+        &compression,
+    )
+}
+
+///
+/// the compression name and method of an export, `deflate` by default
+///
+fn export_compression(
+    compression: &Option<String>,
+    level: &Option<u32>,
+) -> Result<(String, zip::CompressionMethod), Error> {
+    let compression = compression
+        .as_deref()
+        .unwrap_or(DEFAULT_COMPRESSION)
+        .trim()
+        .to_lowercase();
+    let method = parse_compression_method(&compression)?;
+    // zip 0.5 does not expose compression levels for any method
+    if level.is_some() {
+        return Err(Error::UnsupportedCompression(format!(
+            "compression level is not supported, supported methods are: {}",
+            COMPRESSION_METHODS.join(", ")
+        )));
+    }
+    Ok((compression, method))
+}
+
+///
+/// `zip_path` downloaded as `filename`, with its compression and size in headers
+///
+fn zip_download(
+    req: &HttpRequest,
+    zip_path: PathBuf,
+    filename: &str,
+    compression: &str,
+) -> actix_web::Result<HttpResponse> {
+    let archive_size = std::fs::metadata(&zip_path)?.len();
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(filename.to_string())],
+    };
+    let mut response = NamedFile::open(zip_path)?
+        .set_content_disposition(disposition)
+        .into_response(req)?;
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static(COMPRESSION_HEADER),
+        HeaderValue::from_str(compression)?,
+    );
+    headers.insert(
+        HeaderName::from_static(ARCHIVE_SIZE_HEADER),
+        HeaderValue::from(archive_size),
+    );
+    Ok(response)
+}
+
+///
+/// export all tasks as one zip, with a folder for each task like the zip of `/get_task_zip`.
+/// Shares `MAX_CONCURRENT_EXPORTS` with `/get_task_zip`, and is what backups contain.
+///
+#[get("/export_all")]
+pub async fn export_all_param(
+    req: HttpRequest,
+    param: Query<AllZipExport>,
+    concurrency: web::Data<ConcurrencyLimiter>,
+) -> actix_web::Result<HttpResponse> {
+    let (compression, method) = match export_compression(&param.compression, &param.level) {
+        Ok(compression) => compression,
+        Err(e) => return Ok(task_error(e)),
+    };
+    let redact = param.redact.unwrap_or(true);
+    let zip_path = Path::new(TEMP_ZIP_PATH).join(format!(
+        "{}.{}{}.zip",
+        EXPORT_ALL_NAME,
+        compression,
+        if redact { "" } else { ".unredacted" }
+    ));
+    let key = (compression.clone(), redact);
+    let exported = ALL_EXPORTS.run(key, || match concurrency.try_acquire(EXPORT_ROUTE) {
+        Some(permit) => Ok(async move {
+            let _permit = permit;
+            web::block(move || export_all(&zip_path, method, redact).map(|_| zip_path))
+                .await
+                .map_err(Arc::new)
+        }),
+        None => Err(saturated(EXPORT_ROUTE)),
+    });
+    let zip_path = match exported.await {
+        Ok(Ok(zip_path)) => zip_path,
+        Ok(Err(e)) => match &*e {
+            actix_web::error::BlockingError::Error(e) => {
+                return Ok(error_response(&mut HttpResponse::InternalServerError(), e))
+            }
+            e => return Ok(HttpResponse::InternalServerError().body(format!("{:?}", e))),
+        },
+        Err(response) => return Ok(response),
+    };
+    zip_download(
+        &req,
+        zip_path,
+        &format!("{}.zip", EXPORT_ALL_NAME),
+        &compression,
+    )
+}
+
+///
+/// load all tasks that should be loaded but are not, logging the result of each task
+///
+pub fn autoload_on_start() {
+    match autoload_tasks() {
+        Ok(results) => {
+            for (label, result) in results {
+                match result {
+                    Ok(_) => println!("autoload `{}`: loaded", label),
+                    Err(e) => eprintln!("autoload `{}`: failed: {:?}", label, e),
+                }
+            }
+        }
+        Err(e) => eprintln!("autoload failure: {:?}", e),
+    }
+}
+
+///
+/// lint every stored yaml, logging the tasks that would fail on their next edit or load
+///
+pub fn lint_on_start() {
+    match lint_all() {
+        Ok(reports) => {
+            let failing: Vec<_> = reports.iter().filter(|r| r.has_errors()).collect();
+            for report in &failing {
+                eprintln!("lint: {:?}", report);
+            }
+            println!(
+                "{} tasks have validation problems, see /lint_report",
+                failing.len()
+            );
+        }
+        Err(e) => eprintln!("lint failure: {:?}", e),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct LintFilter {
+    problems_only: Option<bool>,
+}
+
+///
+/// check every stored yaml as an edit or a load would, e.g. after upgrading tasker
+/// or the OS, with lint warnings. Only tasks with errors or warnings if `problems_only`.
+///
+#[get("/lint_report")]
+pub async fn lint_report(param: Query<LintFilter>) -> impl Responder {
+    let problems_only = param.problems_only.unwrap_or(false);
+    match web::block(lint_all).await {
+        Ok(reports) => HttpResponse::Ok().json(
+            reports
+                .into_iter()
+                .filter(|report| !problems_only || report.has_problems())
+                .collect::<Vec<_>>(),
+        ),
+        Err(actix_web::error::BlockingError::Error(e)) => task_error(e),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// pairs of tasks likely to get in each other's way: the same command, the same
+/// `StartCalendarInterval` entry, or a `WorkingDirectory` in the task folder of another
+///
+#[get("/conflicts")]
+pub async fn conflicts() -> impl Responder {
+    match web::block(find_conflicts).await {
+        Ok(conflicts) => HttpResponse::Ok().json(conflicts),
+        Err(actix_web::error::BlockingError::Error(e)) => task_error(e),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// recreate output folders, and place the plists and load the tasks that should be loaded,
+/// from `meta` and `tasks` alone, reporting what is done to each task
+///
+#[post("/rebuild")]
+pub async fn rebuild() -> impl Responder {
+    match web::block(rebuild_all).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(actix_web::error::BlockingError::Error(e)) => task_error(e),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// check the size of stdout and stderr of all tasks every `LOG_ROTATION_INTERVAL_SECS`,
+/// and rotate those exceeding `MAX_LOG_MB`
+///
+pub async fn rotate_logs_periodically() {
+    let mut interval =
+        actix_web::rt::time::interval(Duration::from_secs(LOG_ROTATION_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        if let Err(e) = web::block(rotate_oversized_logs).await {
+            eprintln!("log rotation failure: {:?}", e);
+        }
+    }
+}
+
+///
+/// remove stale temp files every `TEMP_CLEANUP_INTERVAL_SECS`
+///
+pub async fn clean_temp_periodically() {
+    let env = Env::get();
+    let mut interval = actix_web::rt::time::interval(env.temp_cleanup_interval);
+    // the first tick completes immediately, and startup cleanup is done in `main`
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match web::block(move || Ok::<_, Error>(clean_temp(env.temp_max_age))).await {
+            Ok(report) => println!(
+                "temp cleanup: {} files removed, {} bytes freed",
+                report.files_removed, report.bytes_freed
+            ),
+            Err(e) => eprintln!("temp cleanup failure: {:?}", e),
+        }
+    }
+}
+
+///
+/// purge trash older than `TRASH_RETENTION_DAYS` every day
+///
+pub async fn purge_trash_periodically() {
+    let retention = match Env::get().trash_retention {
+        Some(retention) => retention,
+        None => return,
+    };
+    let mut interval =
+        actix_web::rt::time::interval(Duration::from_secs(TRASH_PURGE_INTERVAL_SECS));
+    // the first tick completes immediately, and startup purge is done in `main`
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = web::block(move || Ok::<_, Error>(purge_expired_trash(retention))).await {
+            eprintln!("trash purge failure: {:?}", e);
+        }
+    }
+}
+
+///
+/// back up all tasks into `BACKUP_DIR` every `BACKUP_INTERVAL_HOURS`,
+/// counting from the last successful backup so that restarts do not postpone it
+///
+pub async fn backup_periodically() {
+    let env = Env::get();
+    let every = match env.backup_interval {
+        Some(every) => every,
+        None => return,
+    };
+    let next = BackupState::load(&backup_state_file()).next_backup_in(every, SystemTime::now());
+    let start = actix_web::rt::time::Instant::now() + next;
+    let mut interval = actix_web::rt::time::interval_at(start, every);
+    loop {
+        interval.tick().await;
+        let backup = move || {
+            run_backup(
+                &env.backup_dir,
+                env.backup_keep,
+                &backup_state_file(),
+                SystemTime::now(),
+            )
+        };
+        // a failure is logged and recorded by `run_backup`
+        if let Ok(outcome) = web::block(backup).await {
+            println!(
+                "backup: {} tasks in {} bytes",
+                outcome.tasks,
+                outcome.bytes.unwrap_or(0)
+            );
+        }
+    }
+}
+
+///
+/// back up all tasks into `BACKUP_DIR` now, pruning the backups beyond `BACKUP_KEEP`
+///
+#[post("/backup_now")]
+pub async fn backup_now_param() -> impl Responder {
+    let env = Env::get();
+    let backup = move || {
+        run_backup(
+            &env.backup_dir,
+            env.backup_keep,
+            &backup_state_file(),
+            SystemTime::now(),
+        )
+    };
+    match web::block(backup).await {
+        Ok(outcome) => HttpResponse::Ok().json(outcome),
+        Err(actix_web::error::BlockingError::Error(e @ Error::BackupInProgress(_))) => {
+            error_response(&mut HttpResponse::Conflict(), &e)
+        }
+        Err(actix_web::error::BlockingError::Error(e)) => {
+            error_response(&mut HttpResponse::InternalServerError(), &e)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// the backups in `BACKUP_DIR`, newest first
+///
+#[get("/backups")]
+pub async fn backups_param() -> impl Responder {
+    match web::block(move || Ok::<_, Error>(list_backups(&Env::get().backup_dir))).await {
+        Ok(backups) => HttpResponse::Ok().json(backups),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+#[post("/cleanup_temp")]
+pub async fn cleanup_temp_param() -> impl Responder {
+    let max_age = Env::get().temp_max_age;
+    match web::block(move || Ok::<_, Error>(clean_temp(max_age))).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TrashParams {
+    #[serde(default)]
+    sort: TrashSort,
+}
+
+///
+/// the entries of trash, oldest or largest first, with their size, time of deletion,
+/// and whether a task with the same label exists
+///
+#[get("/trash")]
+pub async fn trash_param(param: Query<TrashParams>) -> impl Responder {
+    let sort = param.sort;
+    match web::block(move || trash_info(sort)).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(actix_web::error::BlockingError::Error(e)) => {
+            error_response(&mut HttpResponse::InternalServerError(), &e)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+/// tasks listed by default in each category of `/disk_usage`
+static DISK_USAGE_TOP: usize = 10;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DiskUsageParams {
+    top: Option<usize>,
+}
+
+///
+/// bytes used by `meta`, `tasks`, `out` and `trash`, with the `top` (default 10) largest
+/// tasks in `tasks` and `out`, and the space available on the volume
+///
+#[get("/disk_usage")]
+pub async fn disk_usage_param(param: Query<DiskUsageParams>) -> impl Responder {
+    let top = param.top.unwrap_or(DISK_USAGE_TOP);
+    match web::block(move || Ok::<_, Error>(disk_usage().top(top))).await {
+        Ok(usage) => HttpResponse::Ok().json(usage),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// send a synthetic failure of `com.tasker.tasks.test_notification` through every configured
+/// notifier, ignoring their backoff, and report whether each one sent it
+///
+#[post("/test_notification")]
+pub async fn test_notification_param() -> impl Responder {
+    match web::block(move || Ok::<_, Error>(test_notification())).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:?}", e)),
+    }
+}
+
+///
+/// the tasker app with request timing, authentication, rate limiting, maintenance mode,
+/// and all services, shared by `main` and integration tests
+///
+pub fn build_app(
+    limiter: Arc<Mutex<RateLimiter>>,
+    confirmations: Arc<Mutex<Confirmations>>,
+    maintenance: Arc<RwLock<MaintenanceState>>,
+    manual_runs: Arc<Mutex<ManualRuns>>,
+    metrics: Arc<Mutex<RequestMetrics>>,
+    concurrency: Arc<ConcurrencyLimiter>,
+) -> App<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse<Body>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+    Body,
+> {
+    App::new()
+        .app_data(yaml_payload_config())
+        .app_data(web::Data::from(confirmations))
+        .app_data(web::Data::from(maintenance.clone()))
+        .app_data(web::Data::from(manual_runs))
+        .app_data(web::Data::from(metrics.clone()))
+        .app_data(web::Data::from(concurrency))
+        .wrap(MaintenanceGuard::new(maintenance))
+        .wrap(Auth)
+        .wrap(RateLimit::new(limiter))
+        .wrap(ForwardedClient::from_env())
+        // outermost, so that the time spent in authentication and rate limiting counts
+        .wrap(RequestTiming::from_env(metrics))
+        .service(delete_param)
+        .service(load_param)
+        .service(unload_param)
+        .service(stderr_param)
+        .service(stdout_param)
+        .service(get_yaml)
+        .service(post_yaml)
+        .service(get_task_zip)
+        .service(export_all_param)
+        .service(stop_param)
+        .service(restart_param)
+        .service(rotate_logs_param)
+        .service(labels)
+        .service(clear_output_param)
+        .service(update_task)
+        .service(get_plist)
+        .service(diff_param)
+        .service(provenance_param)
+        .service(usage_param)
+        .service(adopt_param)
+        .service(rebuild)
+        .service(lint_report)
+        .service(conflicts)
+        .service(cleanup_temp_param)
+        .service(disk_usage_param)
+        .service(trash_param)
+        .service(backup_now_param)
+        .service(backups_param)
+        .service(test_notification_param)
+        .service(config_set)
+        .service(config_remove)
+        .service(validate_label)
+        .service(validate_program)
+        .service(validate_config_entry)
+        .service(get_schedule)
+        .service(post_schedule)
+        .service(run_once_param)
+        .service(run_once_status_param)
+        .service(create_from_template_param)
+        .service(create_from_git_param)
+        .service(refresh_from_git_param)
+        .service(mark_secret_param)
+        .service(unmark_secret_param)
+        .service(ws_param)
+        .service(shutdown_param)
+        .service(reload_tls_param)
+        .service(command_param)
+        .service(stats_param)
+        .service(openapi_param)
+        .service(yaml_schema)
+        .service(files_param)
+        .service(file_param)
+        .service(upload_file_param)
+        .service(artifacts_param)
+        .service(artifact_param)
+        .service(delete_artifact_param)
+        .service(health_param)
+        .service(metrics_param)
+        .service(get_maintenance)
+        .service(post_maintenance)
+        .service(
+            web::resource("/")
+                .route(web::get().to(index))
+                .route(web::post().to(create_new_tasks)),
+        )
+        .service(web::resource("/list_all").route(web::get().to(list_all)))
+        .service(web::resource("/list_part.html").route(web::get().to(list_part)))
+        .service(web::resource("/edit_yaml.html").route(web::get().to(edit_yaml)))
+        .service(web::resource("/stderr.html").route(web::get().to(stderr)))
+        .service(web::resource("/stdout.html").route(web::get().to(stdout)))
+        .service(list_raw_json)
+        .service(list_text_param)
+}
+
+#[cfg(test)]
+mod test_server_mod {
+    use super::*;
+
+    #[test]
+    fn output_ansi_modes() {
+        let param = |strip_ansi: Option<bool>, render_ansi: Option<bool>| OutputLimited {
+            label: "label".to_string(),
+            limit: 10,
+            filter: String::new(),
+            since: None,
+            until: None,
+            strip_ansi,
+            render_ansi,
+        };
+        let log = "\x1b[31m<error>\x1b[0m\n\x1b[2K\n";
+        assert_eq!(param(None, None).render_ansi(log), "<error>\n\n");
+        assert_eq!(param(Some(false), None).render_ansi(log), log);
+        assert_eq!(
+            param(Some(false), Some(true)).render_ansi(log),
+            "<span style=\"color:#cd0000\">&lt;error&gt;</span>\n\n"
+        );
+    }
+
+    #[test]
+    fn output_time_range() {
+        let param = |since: Option<&str>, until: Option<&str>| OutputLimited {
+            label: "label".to_string(),
+            limit: 10,
+            filter: String::new(),
+            since: since.map(String::from),
+            until: until.map(String::from),
+            strip_ansi: None,
+            render_ansi: None,
+        };
+        assert_eq!(param(None, None).time_range().unwrap(), (None, None));
+        assert_eq!(
+            param(
+                Some("2020-09-13T12:26:40Z"),
+                Some("2020-09-13T13:26:40+01:00")
+            )
+            .time_range()
+            .unwrap(),
+            (Some(1_600_000_000_000), Some(1_600_000_000_000))
+        );
+        let error = param(Some("02:00"), None).time_range().unwrap_err();
+        assert!(format!("{:?}", error).contains(ACCEPTED_TIME_FORMATS));
+        assert!(param(None, Some("2020-09-13 03:00:00"))
+            .time_range()
+            .is_err());
+        assert!(
+            param(Some("2020-09-13T12:26:41Z"), Some("2020-09-13T12:26:40Z"))
+                .time_range()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn pages_fully_rendered() {
+        for page in [
+            INDEX_HTML,
+            LIST_ALL_HTML,
+            LIST_PART_HTML,
+            CREATE_SUCCESS,
+            EDIT_YAML,
+            STDOUT,
+            STDERR,
+        ]
+        .iter()
+        {
+            let rendered = render_page_with(
+                page,
+                "/tasker",
+                "localhost",
+                "http://localhost:54321/tasker",
+            );
+            assert!(!rendered.contains("{{"));
+            assert!(!rendered.contains("'/list_all'"));
+        }
+        let index = render_page_with(
+            INDEX_HTML,
+            "/tasker",
+            "localhost",
+            "http://localhost:54321/tasker",
+        );
+        assert!(index.contains("'/tasker/list_all'"));
+        assert!(index.contains(&format!(
+            "Tasker {} on <a href=\"http://localhost:54321/tasker/\">localhost</a>",
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+
+    #[test]
+    fn temp_uploads_unique_and_removed() {
+        let first = TempUpload::new();
+        let second = TempUpload::new();
+        assert_ne!(first.path, second.path);
+        std::fs::create_dir_all(TEMP_ZIP_PATH).unwrap();
+        std::fs::write(&first.path, "zip").unwrap();
+        let path = first.path.clone();
+        drop(first);
+        assert!(!path.exists());
+        // never saved, nothing to remove
+        drop(second);
     }
 }
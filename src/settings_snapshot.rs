@@ -0,0 +1,239 @@
+use crate::error::Error;
+use crate::initialize::Env;
+use crate::utils::{read_utf8_file, write_atomic};
+use crate::TASKER_TASK_NAME;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::RwLock;
+
+static SETTINGS_SNAPSHOT_FILE: &str = "settings_snapshot.json";
+pub static MIGRATION_GUIDANCE: &str =
+    "run /rebuild to regenerate plists for the new root, or migrate data";
+
+lazy_static! {
+    /// what changed since the snapshot, until `/rebuild` regenerates the plists
+    static ref CHANGES: RwLock<Vec<SettingChange>> = RwLock::new(Vec::new());
+}
+
+///
+/// the settings that the plists of existing tasks depend on, persisted in
+/// `meta/settings_snapshot.json` next to the yamls of the tasks, so that it moves with them
+///
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SettingsSnapshot {
+    pub tasker_root: String,
+    pub out_dir: String,
+    pub task_dir: String,
+    pub plist_folder: String,
+    pub domain_prefix: String,
+}
+
+///
+/// a setting that differs from the snapshot
+///
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Clone)]
+pub struct SettingChange {
+    pub name: String,
+    pub previous: String,
+    pub current: String,
+}
+
+impl SettingsSnapshot {
+    pub fn of(env: &Env) -> SettingsSnapshot {
+        SettingsSnapshot::new(
+            &env.tasker_root,
+            &env.out_dir,
+            &env.task_dir,
+            &env.plist_dir,
+        )
+    }
+
+    fn new(
+        tasker_root: &Path,
+        out_dir: &Path,
+        task_dir: &Path,
+        plist_dir: &Path,
+    ) -> SettingsSnapshot {
+        SettingsSnapshot {
+            tasker_root: tasker_root.display().to_string(),
+            out_dir: out_dir.display().to_string(),
+            task_dir: task_dir.display().to_string(),
+            plist_folder: plist_dir.display().to_string(),
+            domain_prefix: TASKER_TASK_NAME.to_string(),
+        }
+    }
+
+    ///
+    /// `None` if the snapshot is missing or corrupted
+    ///
+    pub fn load(meta_dir: &Path) -> Option<SettingsSnapshot> {
+        let s = read_utf8_file(&meta_dir.join(SETTINGS_SNAPSHOT_FILE)).ok()?;
+        serde_json::from_str(&s).ok()
+    }
+
+    pub fn save(&self, meta_dir: &Path) -> Result<(), Error> {
+        match serde_json::to_string_pretty(self) {
+            Ok(s) => write_atomic(&meta_dir.join(SETTINGS_SNAPSHOT_FILE), s.as_bytes()),
+            Err(e) => Err(Error::CopyError(format!("{}", e))),
+        }
+    }
+
+    ///
+    /// the settings of `current` that differ from this snapshot
+    ///
+    pub fn changes(&self, current: &SettingsSnapshot) -> Vec<SettingChange> {
+        let settings = [
+            ("tasker_root", &self.tasker_root, &current.tasker_root),
+            ("out_dir", &self.out_dir, &current.out_dir),
+            ("task_dir", &self.task_dir, &current.task_dir),
+            ("plist_folder", &self.plist_folder, &current.plist_folder),
+            ("domain_prefix", &self.domain_prefix, &current.domain_prefix),
+        ];
+        settings
+            .iter()
+            .filter(|(_, previous, current)| previous != current)
+            .map(|(name, previous, current)| SettingChange {
+                name: name.to_string(),
+                previous: previous.to_string(),
+                current: current.to_string(),
+            })
+            .collect()
+    }
+}
+
+///
+/// compare `current` with the snapshot in `meta_dir`. Changes only matter while
+/// `meta_dir` has yamls, whose plists still point at the previous paths.
+/// The snapshot is replaced by `current` unless there are such changes,
+/// so that they are reported until `/rebuild` regenerates the plists.
+///
+pub fn check_settings(
+    meta_dir: &Path,
+    current: &SettingsSnapshot,
+) -> Result<Vec<SettingChange>, Error> {
+    let changes = match SettingsSnapshot::load(meta_dir) {
+        Some(previous) if has_yaml(meta_dir) => previous.changes(current),
+        _ => Vec::new(),
+    };
+    if changes.is_empty() {
+        current.save(meta_dir)?;
+    }
+    Ok(changes)
+}
+
+fn has_yaml(meta_dir: &Path) -> bool {
+    match meta_dir.read_dir() {
+        Ok(dir) => dir.filter_map(|entry| entry.ok()).any(|entry| {
+            let path = entry.path();
+            path.is_file() && path.extension().unwrap_or_default() == "yaml"
+        }),
+        Err(_) => false,
+    }
+}
+
+///
+/// check the settings at startup, warn about changes that orphan existing tasks,
+/// and keep them for `/health`
+///
+pub fn check_settings_on_start() {
+    let env = Env::get();
+    let changes = match check_settings(&env.meta_dir, &SettingsSnapshot::of(env)) {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("settings snapshot failure: {:?}", e);
+            return;
+        }
+    };
+    if !changes.is_empty() {
+        eprintln!("****************************************************************");
+        eprintln!("warning: settings changed since the tasks in meta were created:");
+        for change in &changes {
+            eprintln!(
+                "  {}: `{}` -> `{}`",
+                change.name, change.previous, change.current
+            );
+        }
+        eprintln!("the plists of these tasks still use the previous paths,");
+        eprintln!("{}", MIGRATION_GUIDANCE);
+        eprintln!("****************************************************************");
+    }
+    *CHANGES.write().unwrap() = changes;
+}
+
+///
+/// changes found at startup that are not migrated yet
+///
+pub fn settings_changes() -> Vec<SettingChange> {
+    CHANGES.read().unwrap().clone()
+}
+
+///
+/// record the current settings after `/rebuild` regenerated every plist
+///
+pub fn settings_migrated() -> Result<(), Error> {
+    let env = Env::get();
+    SettingsSnapshot::of(env).save(&env.meta_dir)?;
+    CHANGES.write().unwrap().clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_settings_snapshot_mod {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn snapshot_of(root: &Path) -> SettingsSnapshot {
+        SettingsSnapshot::new(
+            root,
+            &root.join("out"),
+            &root.join("tasks"),
+            Path::new(crate::PLIST_FOLDER),
+        )
+    }
+
+    #[test]
+    fn changes_reported_while_tasks_exist() {
+        let test_root = PathBuf::from("test_settings_snapshot");
+        let (old_root, new_root) = (test_root.join("old"), test_root.join("new"));
+        // the whole root including meta was moved
+        let meta_dir = new_root.join("meta");
+        std::fs::create_dir_all(&meta_dir).unwrap();
+        let (old, new) = (snapshot_of(&old_root), snapshot_of(&new_root));
+
+        // a first start records the snapshot
+        assert!(SettingsSnapshot::load(&meta_dir).is_none());
+        assert!(check_settings(&meta_dir, &old).unwrap().is_empty());
+        assert_eq!(SettingsSnapshot::load(&meta_dir).unwrap(), old);
+        assert!(check_settings(&meta_dir, &old).unwrap().is_empty());
+
+        // without tasks, nothing is orphaned
+        assert!(check_settings(&meta_dir, &new).unwrap().is_empty());
+        assert_eq!(SettingsSnapshot::load(&meta_dir).unwrap(), new);
+        old.save(&meta_dir).unwrap();
+
+        // with tasks, the changed paths are reported on every start
+        std::fs::write(meta_dir.join("com.tasker.tasks.backup.yaml"), "").unwrap();
+        for _ in 0..2 {
+            let changes = check_settings(&meta_dir, &new).unwrap();
+            let names: Vec<&str> = changes.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, vec!["tasker_root", "out_dir", "task_dir"]);
+            assert_eq!(
+                changes[1].previous,
+                old_root.join("out").display().to_string()
+            );
+            assert_eq!(
+                changes[1].current,
+                new_root.join("out").display().to_string()
+            );
+            assert_eq!(SettingsSnapshot::load(&meta_dir).unwrap(), old);
+        }
+
+        // a corrupted snapshot is replaced
+        std::fs::write(meta_dir.join(SETTINGS_SNAPSHOT_FILE), "{").unwrap();
+        assert!(SettingsSnapshot::load(&meta_dir).is_none());
+        assert!(check_settings(&meta_dir, &new).unwrap().is_empty());
+        assert_eq!(SettingsSnapshot::load(&meta_dir).unwrap(), new);
+        std::fs::remove_dir_all(test_root).unwrap();
+    }
+}
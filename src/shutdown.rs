@@ -0,0 +1,162 @@
+use crate::cleanup::clean_temp;
+use actix_web::dev::Server;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref IN_FLIGHT: Mutex<usize> = Mutex::new(0);
+    static ref IDLE: Condvar = Condvar::new();
+    static ref SERVER: Mutex<Option<Server>> = Mutex::new(None);
+    static ref RESTART: AtomicBool = AtomicBool::new(false);
+}
+
+///
+/// a task operation that modifies files or launchd, which shutdown waits for
+/// until this guard is dropped
+///
+pub struct Operation {
+    _private: (),
+}
+
+impl Operation {
+    pub fn begin() -> Operation {
+        *IN_FLIGHT.lock().unwrap() += 1;
+        Operation { _private: () }
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        *in_flight -= 1;
+        if *in_flight == 0 {
+            IDLE.notify_all();
+        }
+    }
+}
+
+///
+/// number of task operations in progress
+///
+pub fn in_flight() -> usize {
+    *IN_FLIGHT.lock().unwrap()
+}
+
+///
+/// wait until no task operation is in progress, return `false` on timeout
+///
+pub fn wait_idle(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    while *in_flight > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        in_flight = IDLE.wait_timeout(in_flight, deadline - now).unwrap().0;
+    }
+    true
+}
+
+///
+/// keep the handle of the running server for `/shutdown`
+///
+pub fn register(server: Server) {
+    *SERVER.lock().unwrap() = Some(server);
+}
+
+///
+/// stop the registered server gracefully, return `false` if no server is registered.
+/// The server stops accepting connections and finishes requests in progress
+/// within `SHUTDOWN_TIMEOUT_SECS`, and then `main` calls `finish`.
+///
+pub fn request_shutdown() -> bool {
+    match SERVER.lock().unwrap().take() {
+        Some(server) => {
+            actix_web::rt::spawn(async move {
+                server.stop(true).await;
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+///
+/// stop the registered server gracefully like `request_shutdown`, and then start
+/// tasker again in the same process, return `false` if no server is registered
+///
+pub fn request_restart() -> bool {
+    RESTART.store(true, Ordering::SeqCst);
+    if request_shutdown() {
+        true
+    } else {
+        RESTART.store(false, Ordering::SeqCst);
+        false
+    }
+}
+
+///
+/// `true` after `request_restart`
+///
+pub fn restart_requested() -> bool {
+    RESTART.load(Ordering::SeqCst)
+}
+
+///
+/// replace the current process by a new tasker with the same arguments,
+/// which keeps the pid, so that launchd does not notice the restart.
+/// Only returns if the exec fails.
+///
+pub fn re_exec() -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => return e,
+    };
+    std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .exec()
+}
+
+///
+/// after the server stopped: wait for task operations in progress,
+/// then remove temp files
+///
+pub fn finish(timeout: Duration) {
+    println!("shutdown: waiting for {} task operations", in_flight());
+    if !wait_idle(timeout) {
+        eprintln!(
+            "shutdown: {} task operations still running after {:?}",
+            in_flight(),
+            timeout
+        );
+    }
+    let report = clean_temp(Duration::from_secs(0));
+    println!(
+        "temp cleanup: {} files removed, {} bytes freed",
+        report.files_removed, report.bytes_freed
+    );
+}
+
+#[cfg(test)]
+mod test_shutdown_mod {
+    use super::*;
+
+    #[test]
+    fn slow_operation_delays_shutdown() {
+        let operation = Operation::begin();
+        assert!(!wait_idle(Duration::from_millis(50)));
+
+        let slow = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            drop(operation);
+        });
+        let start = Instant::now();
+        assert!(wait_idle(Duration::from_secs(5)));
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        assert_eq!(in_flight(), 0);
+        slow.join().unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use crate::initialize::{Env, META_FOLDER, OUT_FOLDER, TASK_FOLDER, TRASH_FOLDER};
+use crate::utils::format_rfc3339;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// a walk of the tasker folders taking longer than this is stopped and marked partial
+static MAX_WALK_SECS: u64 = 10;
+/// the deadline is checked every this many entries
+static DEADLINE_CHECK_ENTRIES: usize = 256;
+lazy_static! {
+    static ref DISK_USAGE_CACHE: Mutex<UsageCache> = Mutex::new(UsageCache::default());
+    static ref TASK_SIZES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+///
+/// bytes used by the folder of a single task
+///
+#[derive(Debug, Serialize, JsonSchema, Clone, PartialEq)]
+pub struct LabelUsage {
+    pub label: String,
+    pub bytes: u64,
+}
+
+///
+/// bytes used by `tasks/` or `out/`, and by each task in it, largest first
+///
+#[derive(Debug, Serialize, JsonSchema, Clone, PartialEq, Default)]
+pub struct CategoryUsage {
+    pub bytes: u64,
+    pub labels: Vec<LabelUsage>,
+}
+
+///
+/// bytes used under `TASKER_ROOT` by category, and the space left on its volume
+///
+#[derive(Debug, Serialize, JsonSchema, Clone, PartialEq, Default)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    pub meta_bytes: u64,
+    pub tasks: CategoryUsage,
+    pub out: CategoryUsage,
+    pub trash_bytes: u64,
+    /// bytes available to tasker on the volume containing `TASKER_ROOT`
+    pub available_bytes: Option<u64>,
+    /// size of the volume containing `TASKER_ROOT`
+    pub volume_bytes: Option<u64>,
+    /// the walk took longer than 10 seconds and was stopped, so the sizes are too small
+    pub partial: bool,
+    pub computed_at: Option<String>,
+}
+
+impl DiskUsage {
+    ///
+    /// keep only the `n` largest tasks of `tasks` and `out`
+    ///
+    pub fn top(mut self, n: usize) -> DiskUsage {
+        self.tasks.labels.truncate(n);
+        self.out.labels.truncate(n);
+        self
+    }
+}
+
+///
+/// the sum of file sizes under a folder, in total and by its first level entries.
+/// Symlinks are not followed, and a missing folder is empty.
+///
+struct Walk {
+    bytes: u64,
+    children: BTreeMap<String, u64>,
+    partial: bool,
+}
+
+fn walk(folder: &Path, deadline: Instant) -> Walk {
+    let mut result = Walk {
+        bytes: 0,
+        children: BTreeMap::new(),
+        partial: false,
+    };
+    for (count, entry) in walkdir::WalkDir::new(folder).into_iter().enumerate() {
+        if count % DEADLINE_CHECK_ENTRIES == 0 && Instant::now() >= deadline {
+            result.partial = true;
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let bytes = match entry.metadata() {
+            Ok(meta) => meta.len(),
+            Err(_) => continue,
+        };
+        result.bytes += bytes;
+        if let Ok(relative) = entry.path().strip_prefix(folder) {
+            if let Some(child) = relative.components().next() {
+                let child = child.as_os_str().to_string_lossy().to_string();
+                *result.children.entry(child).or_insert(0) += bytes;
+            }
+        }
+    }
+    result
+}
+
+///
+/// tasks by size, largest first, and by label among equal sizes
+///
+fn by_label(walk: &Walk) -> CategoryUsage {
+    let mut labels: Vec<LabelUsage> = walk
+        .children
+        .iter()
+        .map(|(label, bytes)| LabelUsage {
+            label: label.clone(),
+            bytes: *bytes,
+        })
+        .collect();
+    labels.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.label.cmp(&b.label)));
+    CategoryUsage {
+        bytes: walk.bytes,
+        labels,
+    }
+}
+
+///
+/// `(available bytes, volume bytes)` of the volume containing `path`
+///
+fn volume_space(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // the widths of these fields differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    let (available, total, fragment) = (
+        stat.f_bavail as u64,
+        stat.f_blocks as u64,
+        stat.f_frsize as u64,
+    );
+    Some((available * fragment, total * fragment))
+}
+
+///
+/// walk `meta`, `tasks`, `out` and `trash` of a tasker root, until `deadline`
+///
+fn compute_disk_usage(root: &Path, deadline: Instant) -> DiskUsage {
+    let meta = walk(&root.join(META_FOLDER), deadline);
+    let tasks = walk(&root.join(TASK_FOLDER), deadline);
+    let out = walk(&root.join(OUT_FOLDER), deadline);
+    let trash = walk(&root.join(TRASH_FOLDER), deadline);
+    let space = volume_space(root);
+    DiskUsage {
+        total_bytes: meta.bytes + tasks.bytes + out.bytes + trash.bytes,
+        meta_bytes: meta.bytes,
+        tasks: by_label(&tasks),
+        out: by_label(&out),
+        trash_bytes: trash.bytes,
+        available_bytes: space.map(|(available, _)| available),
+        volume_bytes: space.map(|(_, total)| total),
+        partial: meta.partial || tasks.partial || out.partial || trash.partial,
+        computed_at: format_rfc3339(SystemTime::now()),
+    }
+}
+
+///
+/// the last disk usage computed, reused until it is older than the max age
+///
+#[derive(Default)]
+struct UsageCache {
+    computed: Option<(Instant, DiskUsage)>,
+}
+
+impl UsageCache {
+    fn get_or_compute<F>(&mut self, now: Instant, max_age: Duration, compute: F) -> DiskUsage
+    where
+        F: FnOnce() -> DiskUsage,
+    {
+        if let Some((at, usage)) = &self.computed {
+            if now.duration_since(*at) < max_age {
+                return usage.clone();
+            }
+        }
+        let usage = compute();
+        self.computed = Some((now, usage.clone()));
+        usage
+    }
+}
+
+///
+/// disk usage of the tasker folders, cached for `DISK_USAGE_CACHE_SECS`
+///
+pub fn disk_usage() -> DiskUsage {
+    let env = Env::get();
+    let mut cache = DISK_USAGE_CACHE.lock().unwrap();
+    cache.get_or_compute(Instant::now(), env.disk_usage_cache, || {
+        let deadline = Instant::now() + Duration::from_secs(MAX_WALK_SECS);
+        let usage = compute_disk_usage(&env.tasker_root, deadline);
+        *TASK_SIZES.lock().unwrap() = task_sizes(&usage);
+        usage
+    })
+}
+
+///
+/// bytes of each task folder, empty if the walk was partial
+///
+fn task_sizes(usage: &DiskUsage) -> BTreeMap<String, u64> {
+    if usage.partial {
+        return BTreeMap::new();
+    }
+    usage
+        .tasks
+        .labels
+        .iter()
+        .map(|usage| (usage.label.clone(), usage.bytes))
+        .collect()
+}
+
+///
+/// bytes of the task folder as of the last `/disk_usage` walk, `None` before the first.
+/// The list never walks task folders itself.
+///
+pub fn task_size(label: &str) -> Option<u64> {
+    TASK_SIZES.lock().unwrap().get(label).copied()
+}
+
+#[cfg(test)]
+mod test_stats_mod {
+    use super::*;
+
+    fn write(path: &Path, bytes: usize) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![b'x'; bytes]).unwrap();
+    }
+
+    #[test]
+    fn disk_usage_by_category() {
+        let root = Path::new("test_disk_usage");
+        let _ = std::fs::remove_dir_all(root);
+        write(&root.join("meta/a.yaml"), 10);
+        write(&root.join("meta/b.yaml"), 5);
+        write(&root.join("tasks/a/run.sh"), 100);
+        write(&root.join("tasks/a/lib/data.bin"), 1000);
+        write(&root.join("tasks/b/run.sh"), 300);
+        write(&root.join("out/b/stdout.log"), 2000);
+        write(&root.join("trash/c/tasks/run.sh"), 7);
+        std::os::unix::fs::symlink("../../tasks/a", root.join("out/b/link")).unwrap();
+
+        let usage = compute_disk_usage(root, Instant::now() + Duration::from_secs(60));
+        assert_eq!(usage.meta_bytes, 15);
+        assert_eq!(usage.tasks.bytes, 1400);
+        assert_eq!(
+            usage.tasks.labels,
+            vec![
+                LabelUsage {
+                    label: "a".to_string(),
+                    bytes: 1100
+                },
+                LabelUsage {
+                    label: "b".to_string(),
+                    bytes: 300
+                },
+            ]
+        );
+        assert_eq!(usage.out.bytes, 2000);
+        assert_eq!(usage.trash_bytes, 7);
+        assert_eq!(usage.total_bytes, 15 + 1400 + 2000 + 7);
+        assert!(!usage.partial);
+        assert!(usage.available_bytes.is_some());
+        assert_eq!(usage.clone().top(1).tasks.labels.len(), 1);
+        let sizes = task_sizes(&usage);
+        assert_eq!(sizes.get("a"), Some(&1100));
+        assert_eq!(sizes.get("b"), Some(&300));
+
+        // a walk past its deadline is partial
+        let partial = compute_disk_usage(root, Instant::now());
+        assert!(partial.partial);
+        assert_eq!(partial.total_bytes, 0);
+        assert!(task_sizes(&partial).is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn disk_usage_cache() {
+        let mut cache = UsageCache::default();
+        let start = Instant::now();
+        let max_age = Duration::from_secs(60);
+        let usage = |bytes| DiskUsage {
+            total_bytes: bytes,
+            ..DiskUsage::default()
+        };
+        assert_eq!(
+            cache
+                .get_or_compute(start, max_age, || usage(1))
+                .total_bytes,
+            1
+        );
+        let later = start + Duration::from_secs(30);
+        assert_eq!(
+            cache
+                .get_or_compute(later, max_age, || usage(2))
+                .total_bytes,
+            1
+        );
+        let expired = start + Duration::from_secs(60);
+        assert_eq!(
+            cache
+                .get_or_compute(expired, max_age, || usage(3))
+                .total_bytes,
+            3
+        );
+        // no caching with a max age of zero
+        assert_eq!(
+            cache
+                .get_or_compute(expired, Duration::from_secs(0), || usage(4))
+                .total_bytes,
+            4
+        );
+    }
+}
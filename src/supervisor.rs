@@ -0,0 +1,365 @@
+use crate::error::Error;
+use crate::history::{record_run, RunRecord};
+use crate::initialize::Env;
+use crate::launchctl::{stop_run, StopStage, TaskInfo};
+use crate::utils::{read_utf8_file, write_atomic};
+use crate::RUN_STATE_FILE;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static KILL_GRACE: Duration = Duration::from_secs(10);
+lazy_static! {
+    static ref RUNS: Mutex<RunTracker> = Mutex::new(RunTracker::load(&run_state_file()));
+}
+
+fn run_state_file() -> PathBuf {
+    Env::get().meta_dir.join(RUN_STATE_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+///
+/// the current run of a task: its pid, and when the pid was first seen
+///
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+struct Run {
+    pid: i32,
+    started_at: u64,
+    /// the run was already running when tasker started, so it started before `started_at`
+    #[serde(default)]
+    start_unknown: bool,
+    /// why the run is being stopped by tasker, e.g. for exceeding `MaxRunSeconds`
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+///
+/// a run whose pid disappeared, with start and end in seconds since epoch
+///
+#[derive(Debug, PartialEq)]
+struct FinishedRun {
+    label: String,
+    start: Option<u64>,
+    end: Option<u64>,
+    stop_reason: Option<String>,
+}
+
+///
+/// the runs of running tasks, persisted in `meta/run_state.json` so that
+/// a run that outlives a restart of tasker keeps its start time
+///
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct RunTracker {
+    runs: BTreeMap<String, Run>,
+    /// labels whose run has been seen running since tasker started
+    #[serde(skip)]
+    seen: BTreeSet<String>,
+    /// whether tasks have been observed since tasker started
+    #[serde(skip)]
+    observed: bool,
+    #[serde(skip)]
+    finished: Vec<FinishedRun>,
+}
+
+impl RunTracker {
+    ///
+    /// an empty tracker is returned if the state file is missing or corrupted
+    ///
+    fn load(state_file: &Path) -> RunTracker {
+        match read_utf8_file(state_file) {
+            Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
+            Err(_) => RunTracker::default(),
+        }
+    }
+
+    fn save(&self, state_file: &Path) -> Result<(), Error> {
+        match serde_json::to_string_pretty(self) {
+            Ok(s) => write_atomic(state_file, s.as_bytes()),
+            Err(e) => Err(Error::CopyError(format!("{}", e))),
+        }
+    }
+
+    ///
+    /// record the pid of each running task at `now`. A run keeps its start time
+    /// while its pid is unchanged, and is finished once the task stops running.
+    /// Return whether anything changed.
+    ///
+    /// A run is only noticed if it is running when observed, and it is finished
+    /// at the first observation after its end. The start of runs already running
+    /// at the first observation is unknown, and so is the end of runs from
+    /// the persisted state that are gone by then.
+    ///
+    fn observe(&mut self, running: &BTreeMap<String, i32>, now: u64) -> bool {
+        let first_observation = !self.observed;
+        self.observed = true;
+        let mut changed = false;
+        let labels: Vec<String> = self.runs.keys().cloned().collect();
+        for label in labels {
+            if running.get(&label) == Some(&self.runs[&label].pid) {
+                self.seen.insert(label);
+                continue;
+            }
+            let run = self.runs.remove(&label).unwrap();
+            let end_known = self.seen.remove(&label);
+            self.finished.push(FinishedRun {
+                label,
+                start: if run.start_unknown {
+                    None
+                } else {
+                    Some(run.started_at)
+                },
+                end: if end_known { Some(now) } else { None },
+                stop_reason: run.stop_reason,
+            });
+            changed = true;
+        }
+        for (label, pid) in running {
+            if !self.runs.contains_key(label) {
+                self.runs.insert(
+                    label.clone(),
+                    Run {
+                        pid: *pid,
+                        started_at: now,
+                        start_unknown: first_observation,
+                        stop_reason: None,
+                    },
+                );
+                self.seen.insert(label.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn take_finished(&mut self) -> Vec<FinishedRun> {
+        std::mem::take(&mut self.finished)
+    }
+
+    fn elapsed(&self, label: &str, now: u64) -> Option<u64> {
+        self.runs
+            .get(label)
+            .map(|run| now.saturating_sub(run.started_at))
+    }
+
+    ///
+    /// runs exceeding their `limits` (in seconds) that are not being stopped yet,
+    /// which are marked as stopping with the reason recorded in their history
+    ///
+    fn take_overdue(&mut self, limits: &BTreeMap<String, u64>, now: u64) -> Vec<(String, i32)> {
+        let mut overdue = Vec::new();
+        for (label, run) in self.runs.iter_mut() {
+            if let Some(limit) = limits.get(label) {
+                if run.stop_reason.is_none() && now.saturating_sub(run.started_at) > *limit {
+                    run.stop_reason = Some(format!("exceeded MaxRunSeconds of {}s", limit));
+                    overdue.push((label.clone(), run.pid));
+                }
+            }
+        }
+        overdue
+    }
+}
+
+///
+/// how long the current run of a task has lasted, `None` if it is not running
+///
+pub fn run_elapsed_seconds(label: &str) -> Option<u64> {
+    RUNS.lock().unwrap().elapsed(label, now_secs())
+}
+
+///
+/// track the runs in a snapshot of all tasks, record finished runs in
+/// their history, and stop runs longer than `MaxRunSeconds` in the background.
+/// This writes files, so it is called in `web::block`.
+///
+pub fn supervise(tasks: &BTreeSet<TaskInfo>) {
+    let running = tasks
+        .iter()
+        .filter_map(|task| task.pid().map(|pid| (task.label().to_string(), pid)))
+        .collect();
+    let limits = tasks
+        .iter()
+        .filter_map(|task| {
+            task.max_run_seconds()
+                .map(|limit| (task.label().to_string(), limit))
+        })
+        .collect();
+    let now = now_secs();
+    let (finished, overdue) = {
+        let mut runs = RUNS.lock().unwrap();
+        let mut changed = runs.observe(&running, now);
+        let overdue = runs.take_overdue(&limits, now);
+        changed |= !overdue.is_empty();
+        if changed {
+            if let Err(e) = runs.save(&run_state_file()) {
+                eprintln!("failed to save run state: {:?}", e);
+            }
+        }
+        (runs.take_finished(), overdue)
+    };
+    for run in finished {
+        let exit_status = tasks
+            .iter()
+            .find(|task| task.label() == run.label)
+            .and_then(|task| task.last_exit_status());
+        let record = RunRecord {
+            stop_reason: run.stop_reason,
+            ..RunRecord::new(run.start, run.end, exit_status)
+        };
+        if let Err(e) = record_run(&run.label, &record) {
+            eprintln!("failed to record run of `{}`: {:?}", run.label, e);
+        }
+    }
+    for (label, pid) in overdue {
+        std::thread::spawn(move || {
+            eprintln!(
+                "`{}` (pid {}) exceeded MaxRunSeconds, stopping it",
+                label, pid
+            );
+            match stop_run(&label, pid, KILL_GRACE) {
+                Ok(StopStage::Kill) => {
+                    println!("`{}` (pid {}) killed after {:?}", label, pid, KILL_GRACE)
+                }
+                Ok(_) => println!("`{}` (pid {}) terminated", label, pid),
+                Err(e) => eprintln!("failed to stop `{}` (pid {}): {:?}", label, pid, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_supervisor_mod {
+    use super::*;
+
+    fn running(tasks: &[(&str, i32)]) -> BTreeMap<String, i32> {
+        tasks
+            .iter()
+            .map(|(label, pid)| (label.to_string(), *pid))
+            .collect()
+    }
+
+    #[test]
+    fn runaway_run_is_stopped() {
+        let mut limits = BTreeMap::new();
+        limits.insert("scraper".to_string(), 60);
+        let mut tracker = RunTracker::default();
+
+        // the same pid persists across polls
+        assert!(tracker.observe(&running(&[("scraper", 42)]), 1000));
+        assert!(!tracker.observe(&running(&[("scraper", 42)]), 1030));
+        assert_eq!(tracker.elapsed("scraper", 1030), Some(30));
+        assert!(tracker.take_overdue(&limits, 1030).is_empty());
+        assert!(!tracker.observe(&running(&[("scraper", 42)]), 1061));
+        assert_eq!(
+            tracker.take_overdue(&limits, 1061),
+            vec![("scraper".to_string(), 42)]
+        );
+        // stopped only once
+        assert!(tracker.take_overdue(&limits, 1062).is_empty());
+
+        // a new run starts a new clock, and the stopped run is recorded with the reason
+        assert!(tracker.observe(&running(&[("scraper", 43)]), 1070));
+        assert_eq!(
+            tracker.take_finished(),
+            vec![FinishedRun {
+                label: "scraper".to_string(),
+                // already running when tasker started
+                start: None,
+                end: Some(1070),
+                stop_reason: Some("exceeded MaxRunSeconds of 60s".to_string()),
+            }]
+        );
+        assert_eq!(tracker.elapsed("scraper", 1075), Some(5));
+        assert!(tracker.observe(&running(&[]), 1080));
+        assert_eq!(tracker.elapsed("scraper", 1080), None);
+    }
+
+    #[test]
+    fn run_survives_restart() {
+        let state_file = Path::new("test_run_state.json");
+        let mut tracker = RunTracker::default();
+        tracker.observe(&running(&[("scraper", 42), ("backup", 7)]), 1000);
+        tracker.save(state_file).unwrap();
+
+        let mut restarted = RunTracker::load(state_file);
+        assert_eq!(restarted.runs, tracker.runs);
+        restarted.observe(&running(&[("scraper", 42), ("backup", 8)]), 2000);
+        assert_eq!(restarted.elapsed("scraper", 2000), Some(1000));
+        assert_eq!(restarted.elapsed("backup", 2000), Some(0));
+        std::fs::remove_file(state_file).unwrap();
+
+        // the old run of backup ended while tasker was not running,
+        // and its new run started before tasker started
+        assert_eq!(
+            restarted.take_finished(),
+            vec![FinishedRun {
+                label: "backup".to_string(),
+                start: None,
+                end: None,
+                stop_reason: None,
+            }]
+        );
+        restarted.observe(&running(&[("scraper", 42)]), 2010);
+        restarted.observe(&running(&[]), 2020);
+        assert_eq!(
+            restarted.take_finished(),
+            vec![
+                FinishedRun {
+                    label: "backup".to_string(),
+                    start: None,
+                    end: Some(2010),
+                    stop_reason: None,
+                },
+                FinishedRun {
+                    label: "scraper".to_string(),
+                    start: None,
+                    end: Some(2020),
+                    stop_reason: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn run_durations() {
+        let mut tracker = RunTracker::default();
+        // already running when tasker started
+        tracker.observe(&running(&[("backup", 7)]), 1000);
+        tracker.observe(&running(&[("scraper", 42)]), 1002);
+        tracker.observe(&running(&[("scraper", 42)]), 1004);
+        // a new pid of the same task is a new run
+        tracker.observe(&running(&[("scraper", 43)]), 1006);
+        tracker.observe(&running(&[]), 1010);
+        assert_eq!(
+            tracker.take_finished(),
+            vec![
+                FinishedRun {
+                    label: "backup".to_string(),
+                    start: None,
+                    end: Some(1002),
+                    stop_reason: None,
+                },
+                FinishedRun {
+                    label: "scraper".to_string(),
+                    start: Some(1002),
+                    end: Some(1006),
+                    stop_reason: None,
+                },
+                FinishedRun {
+                    label: "scraper".to_string(),
+                    start: Some(1006),
+                    end: Some(1010),
+                    stop_reason: None,
+                },
+            ]
+        );
+        assert!(tracker.take_finished().is_empty());
+    }
+}
@@ -0,0 +1,353 @@
+use crate::auth::authenticated_user;
+use crate::initialize::Env;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::web::Query;
+use actix_web::{HttpMessage, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+pub static REQUEST_ID_HEADER: &str = "x-request-id";
+pub static RESPONSE_TIME_HEADER: &str = "x-response-time-ms";
+static REQUEST_ID_BYTES: usize = 8;
+/// a longer `X-Request-Id`, or one with other than visible ASCII, is replaced
+static MAX_REQUEST_ID_LEN: usize = 128;
+/// upper bounds of the histogram buckets in milliseconds, followed by `+Inf`
+static BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+/// requests matching no route are counted together, so that unknown paths add no series
+static UNMATCHED_ROUTE: &str = "unmatched";
+static METRIC_NAME: &str = "tasker_request_duration_ms";
+
+///
+/// the id of a request, from `X-Request-Id` or random, inserted into the request
+/// extensions by `RequestTiming`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+///
+/// the internals of an error left out of a response, inserted into the response
+/// extensions by handlers, and logged with the request id by `RequestTiming`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorDetail(pub String);
+
+fn new_request_id() -> String {
+    let mut bytes = [0u8; REQUEST_ID_BYTES];
+    openssl::rand::rand_bytes(&mut bytes).expect("cannot generate random request id");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+///
+/// the `X-Request-Id` of a client if it is reasonable
+///
+fn client_request_id(value: Option<&HeaderValue>) -> Option<String> {
+    let id = value?.to_str().ok()?;
+    if id.is_empty() || id.len() > MAX_REQUEST_ID_LEN || !id.bytes().all(|b| b.is_ascii_graphic()) {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+///
+/// durations of requests in `BUCKETS_MS`, with the count of each bucket
+/// not including the smaller buckets
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Histogram {
+    buckets: [u64; 12],
+    sum_ms: u64,
+    count: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, ms: u64) {
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.sum_ms += ms;
+        self.count += 1;
+    }
+}
+
+///
+/// a histogram of request durations per method and route since tasker started,
+/// shared by all workers
+///
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    routes: BTreeMap<(String, String), Histogram>,
+}
+
+impl RequestMetrics {
+    pub fn shared() -> Arc<Mutex<RequestMetrics>> {
+        Arc::new(Mutex::new(RequestMetrics::default()))
+    }
+
+    fn record(&mut self, method: &str, route: &str, duration: Duration) {
+        self.routes
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .record(duration.as_millis() as u64);
+    }
+
+    ///
+    /// the histograms in the Prometheus text format, with cumulative buckets
+    ///
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "# HELP {} wall time of requests including authentication\n# TYPE {} histogram\n",
+            METRIC_NAME, METRIC_NAME
+        );
+        for ((method, route), histogram) in &self.routes {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, route);
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let bound = match BUCKETS_MS.get(i) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                text += &format!(
+                    "{}_bucket{{{},le=\"{}\"}} {}\n",
+                    METRIC_NAME, labels, bound, cumulative
+                );
+            }
+            text += &format!("{}_sum{{{}}} {}\n", METRIC_NAME, labels, histogram.sum_ms);
+            text += &format!("{}_count{{{}}} {}\n", METRIC_NAME, labels, histogram.count);
+        }
+        text
+    }
+}
+
+///
+/// middleware timing each request, to be wrapped outside of all others so that
+/// authentication is timed too. Every response gets `X-Request-Id` and
+/// `X-Response-Time-Ms`, and requests slower than `slow` are logged.
+///
+pub struct RequestTiming {
+    metrics: Arc<Mutex<RequestMetrics>>,
+    slow: Duration,
+}
+
+impl RequestTiming {
+    pub fn new(metrics: Arc<Mutex<RequestMetrics>>, slow: Duration) -> RequestTiming {
+        RequestTiming { metrics, slow }
+    }
+
+    ///
+    /// logging requests slower than `SLOW_REQUEST_MS`
+    ///
+    pub fn from_env(metrics: Arc<Mutex<RequestMetrics>>) -> RequestTiming {
+        RequestTiming::new(metrics, Env::get().slow_request)
+    }
+}
+
+impl<S, B> Transform<S> for RequestTiming
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RequestTimingMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestTimingMiddleware {
+            service,
+            metrics: self.metrics.clone(),
+            slow: self.slow,
+        })
+    }
+}
+
+pub struct RequestTimingMiddleware<S> {
+    service: S,
+    metrics: Arc<Mutex<RequestMetrics>>,
+    slow: Duration,
+}
+
+///
+/// set `X-Request-Id` and `X-Response-Time-Ms`
+///
+fn set_timing_headers(headers: &mut HeaderMap, request_id: &str, elapsed: Duration) {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        headers.insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    headers.insert(
+        HeaderName::from_static(RESPONSE_TIME_HEADER),
+        HeaderValue::from(elapsed.as_millis() as u64),
+    );
+}
+
+impl<S, B> Service for RequestTimingMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let request_id =
+            client_request_id(req.headers().get(REQUEST_ID_HEADER)).unwrap_or_else(new_request_id);
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+        // taken now, since a rejection by an inner middleware does not give the request back
+        let method = req.method().to_string();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+        let path = req.path().to_string();
+        let label = Query::<HashMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.get("label").cloned());
+        let metrics = self.metrics.clone();
+        let slow = self.slow;
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let response = response.await;
+            let elapsed = start.elapsed();
+            metrics.lock().unwrap().record(&method, &route, elapsed);
+            if elapsed >= slow {
+                let user = match &response {
+                    Ok(response) => authenticated_user(response.request()),
+                    Err(_) => "-".to_string(),
+                };
+                eprintln!(
+                    "warning: slow request {} {} {} label={} user={} {} ms",
+                    request_id,
+                    method,
+                    path,
+                    label.as_deref().unwrap_or("-"),
+                    user,
+                    elapsed.as_millis()
+                );
+            }
+            match response {
+                Ok(mut response) => {
+                    if let Some(detail) = response.response().extensions().get::<ErrorDetail>() {
+                        eprintln!("error {} {} {}: {}", request_id, method, path, detail.0);
+                    }
+                    set_timing_headers(response.headers_mut(), &request_id, elapsed);
+                    Ok(response)
+                }
+                // rejected by an inner middleware, like `Auth`
+                Err(e) => {
+                    let mut response = HttpResponse::from_error(e);
+                    set_timing_headers(response.headers_mut(), &request_id, elapsed);
+                    Err(actix_web::Error::from(response))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_timing_mod {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    #[test]
+    fn histogram_buckets() {
+        let mut metrics = RequestMetrics::default();
+        metrics.record("GET", "/list_raw_json", Duration::from_millis(3));
+        metrics.record("GET", "/list_raw_json", Duration::from_millis(700));
+        metrics.record("GET", "/list_raw_json", Duration::from_secs(20));
+        let text = metrics.render();
+        let labels = "method=\"GET\",route=\"/list_raw_json\"";
+        for (bound, count) in &[("5", 1), ("500", 1), ("1000", 2), ("10000", 2), ("+Inf", 3)] {
+            let line = format!(
+                "{}_bucket{{{},le=\"{}\"}} {}\n",
+                METRIC_NAME, labels, bound, count
+            );
+            assert!(text.contains(&line), "{}", line);
+        }
+        assert!(text.contains(&format!("{}_sum{{{}}} 20703\n", METRIC_NAME, labels)));
+        assert!(text.contains(&format!("{}_count{{{}}} 3\n", METRIC_NAME, labels)));
+    }
+
+    #[test]
+    fn request_ids() {
+        let id = |value: &'static str| client_request_id(Some(&HeaderValue::from_static(value)));
+        assert_eq!(id("abc-123"), Some("abc-123".to_string()));
+        assert_eq!(id(""), None);
+        assert_eq!(id("has space"), None);
+        assert_eq!(client_request_id(None), None);
+        assert_eq!(new_request_id().len(), REQUEST_ID_BYTES * 2);
+    }
+
+    #[test]
+    fn slow_request_timed() {
+        actix_web::rt::System::new("test_timing").block_on(async {
+            let metrics = RequestMetrics::shared();
+            let mut app = test::init_service(
+                App::new()
+                    .wrap(RequestTiming::new(
+                        metrics.clone(),
+                        Duration::from_millis(50),
+                    ))
+                    .route(
+                        "/slow",
+                        web::get().to(|| async {
+                            actix_web::rt::time::delay_for(Duration::from_millis(60)).await;
+                            "done"
+                        }),
+                    ),
+            )
+            .await;
+            let request = test::TestRequest::get()
+                .uri("/slow?label=com.tasker.tasks.slow")
+                .header(REQUEST_ID_HEADER, "client-id-1")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(REQUEST_ID_HEADER).unwrap(),
+                "client-id-1"
+            );
+            let ms: u64 = response
+                .headers()
+                .get(RESPONSE_TIME_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(ms >= 60);
+
+            // unknown paths are timed too, with a new request id
+            let request = test::TestRequest::get().uri("/missing").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let request_id = response.headers().get(REQUEST_ID_HEADER).unwrap();
+            assert_eq!(request_id.len(), REQUEST_ID_BYTES * 2);
+
+            let metrics = metrics.lock().unwrap();
+            let slow = &metrics.routes[&("GET".to_string(), "/slow".to_string())];
+            assert_eq!(slow.count, 1);
+            // not in the buckets up to 50 ms
+            assert_eq!(slow.buckets[..4].iter().sum::<u64>(), 0);
+            assert_eq!(slow.buckets.iter().sum::<u64>(), 1);
+            let unmatched = &metrics.routes[&("GET".to_string(), UNMATCHED_ROUTE.to_string())];
+            assert_eq!(unmatched.count, 1);
+        });
+    }
+}
@@ -1,17 +1,48 @@
 use crate::error::Error;
-use std::collections::VecDeque;
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::CString;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read, Seek, Write};
 use std::iter::FromIterator;
 use std::os::macos::fs::MetadataExt;
 use std::os::unix::ffi::OsStrExt;
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use users::{Group, User};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tar::EntryType;
 use zip;
 use zip::write::FileOptions;
 
+static MAX_COMMAND_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+static COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+static MAX_ZIP_ENTRIES: usize = 100_000;
+static MAX_COMPRESSION_RATIO: u64 = 1000;
+static JUNK_FILE_NAMES: [&str; 3] = ["__MACOSX", ".DS_Store", "Thumbs.db"];
+static ZIP_MAGIC: [&[u8]; 2] = [b"PK\x03\x04", b"PK\x05\x06"];
+static GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+/// permission bits kept from a zip or tar.gz, without setuid, setgid and sticky bits
+static ARCHIVE_MODE_MASK: u32 = 0o777;
+pub static COMPRESSION_METHODS: [&str; 3] = ["stored", "deflate", "bzip2"];
+/// timestamps accepted by `since` and `until` of log views
+pub static ACCEPTED_TIME_FORMATS: &str =
+    "RFC3339 like `2020-09-13T02:00:00Z`, `2020-09-13T02:00:00.250+08:00`";
+/// bytes read to find a timestamp at each step of the binary search in a log file
+static TIME_SEARCH_BLOCK_BYTES: u64 = 64 * 1024;
+/// longer lines of a log, like a progress bar redrawn without newline, are truncated
+static MAX_LOG_LINE_BYTES: usize = 64 * 1024;
+static TRUNCATED_LINE_MARKER: &str = "…";
+/// `-1` as `uid_t` or `gid_t`, which chown leaves unchanged
+static UNCHANGED_ID: u32 = u32::MAX;
+/// numbers the temp files of zips being built, which are unique within the process
+static ZIP_BUILD_COUNT: AtomicUsize = AtomicUsize::new(0);
+static SYSLOG_MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
 pub fn create_dir_check<P: AsRef<Path>>(dest: P) -> Result<(), Error> {
     if std::fs::metadata(&dest).is_err() {
         return match std::fs::create_dir_all(&dest) {
@@ -39,40 +70,208 @@ pub fn delete_file_check<P: AsRef<Path>>(dest: P) -> Result<(), Error> {
     };
 }
 
-pub fn execute_command(command: &mut Command) -> Result<String, Error> {
-    let output = command.output();
-    let output = match output {
-        Ok(o) => o,
+///
+/// execute a command, killing it if it does not exit within `timeout`.
+/// At most `MAX_COMMAND_OUTPUT_BYTES` of stdout and stderr are captured,
+/// longer output is truncated with a note. The output must also be closed by the
+/// deadline, which a background process inheriting the pipes can prevent.
+///
+pub fn execute_command_timeout(command: &mut Command, timeout: Duration) -> Result<String, Error> {
+    let mut child = match command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
         Err(_) => {
             return Err(Error::CommandExecutionError("unknown error".to_string()));
         }
     };
-    if !output.status.success() {
+    // read pipes in other threads so that a full pipe does not block the child
+    let stdout = read_bounded_in_thread(child.stdout.take());
+    let stderr = read_bounded_in_thread(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(Error::CommandTimeout(format!(
+                        "{:?} timed out after {:?}",
+                        command, timeout
+                    )));
+                }
+                std::thread::sleep(COMMAND_POLL_INTERVAL);
+            }
+            Err(e) => {
+                return Err(Error::CommandExecutionError(format!(
+                    "failed to wait for command: {}",
+                    e
+                )));
+            }
+        }
+    };
+    let output_timeout = || {
+        Error::CommandTimeout(format!(
+            "output of {:?} not closed after {:?}",
+            command, timeout
+        ))
+    };
+    let stdout = stdout
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| output_timeout())?;
+    let stderr = stderr
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| output_timeout())?;
+    if !status.success() {
         return Err(Error::CommandExecutionError(format!(
             "failed to execute command: {}",
-            std::str::from_utf8(&output.stderr).unwrap()
+            bounded_output_to_string(stderr).unwrap_or_default()
         )));
     };
-    if let Ok(output) = std::str::from_utf8(&output.stdout) {
-        Ok(output.to_string())
-    } else {
-        Err(Error::CommandExecutionError(
+    match bounded_output_to_string(stdout) {
+        Some(output) => Ok(output),
+        None => Err(Error::CommandExecutionError(
             "non-utf8 output not supported".to_string(),
+        )),
+    }
+}
+
+///
+/// read at most `MAX_COMMAND_OUTPUT_BYTES` from a pipe and discard the rest,
+/// sending the bytes read and whether the output is truncated once the pipe is closed
+///
+fn read_bounded_in_thread<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> mpsc::Receiver<(Vec<u8>, bool)> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut pipe = match pipe {
+            Some(p) => p,
+            None => {
+                let _ = sender.send((Vec::new(), false));
+                return;
+            }
+        };
+        let mut bytes = Vec::new();
+        let _ = (&mut pipe)
+            .take(MAX_COMMAND_OUTPUT_BYTES as u64)
+            .read_to_end(&mut bytes);
+        let discarded = std::io::copy(&mut pipe, &mut std::io::sink()).unwrap_or(0);
+        let _ = sender.send((bytes, discarded > 0));
+    });
+    receiver
+}
+
+fn bounded_output_to_string(output: (Vec<u8>, bool)) -> Option<String> {
+    let (bytes, truncated) = output;
+    if truncated {
+        // a multi-byte character might be cut at the end
+        Some(format!(
+            "{}\n... output truncated at {} bytes",
+            String::from_utf8_lossy(&bytes),
+            MAX_COMMAND_OUTPUT_BYTES
         ))
+    } else {
+        String::from_utf8(bytes).ok()
+    }
+}
+
+///
+/// metadata files created by macOS and Windows: `__MACOSX/`, `.DS_Store`,
+/// AppleDouble `._*` files and `Thumbs.db`, at any level of `path`
+///
+pub fn is_junk_path(path: &Path) -> bool {
+    path.components()
+        .any(|component| match component.as_os_str().to_str() {
+            Some(name) => JUNK_FILE_NAMES.contains(&name) || name.starts_with("._"),
+            None => false,
+        })
+}
+
+///
+/// check the entries of a zip archive before extracting anything: the total
+/// uncompressed size must not exceed `max_uncompressed_bytes`, the number of entries
+/// must not exceed `MAX_ZIP_ENTRIES`, no entry may have a compression ratio
+/// above `MAX_COMPRESSION_RATIO`, and every path must stay inside of the archive.
+/// Returns the total uncompressed size.
+///
+fn check_zip_size<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    max_uncompressed_bytes: u64,
+) -> Result<u64, Error> {
+    if zip.len() > MAX_ZIP_ENTRIES {
+        return Err(Error::ZipTooLarge(format!(
+            "zip contains {} entries, exceeding {}",
+            zip.len(),
+            MAX_ZIP_ENTRIES
+        )));
+    }
+    let mut total: u64 = 0;
+    for i in 0..zip.len() {
+        let f = match zip.by_index(i) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(Error::DecompressionError(
+                    "failed to read zip entry".to_string(),
+                ))
+            }
+        };
+        zip_entry_path(&f)?;
+        if f.size() > f.compressed_size().max(1) * MAX_COMPRESSION_RATIO {
+            return Err(Error::ZipTooLarge(format!(
+                "`{}` has a suspicious compression ratio: {} bytes compressed to {} bytes",
+                f.name(),
+                f.size(),
+                f.compressed_size()
+            )));
+        }
+        total = total.saturating_add(f.size());
+        if total > max_uncompressed_bytes {
+            return Err(Error::ZipTooLarge(format!(
+                "uncompressed size {} bytes exceeds {} bytes",
+                total, max_uncompressed_bytes
+            )));
+        }
     }
+    Ok(total)
+}
+
+///
+/// the path of a zip entry as written by the archiver, relative to the extracted folder.
+/// `ZipFile::name` decodes names not flagged as UTF-8 as CP437,
+/// which mangles the UTF-8 names written by many archivers without the flag,
+/// so the raw name is decoded as UTF-8 instead, lossily if it is not,
+/// before its components are checked by `archive_entry_path`.
+///
+fn zip_entry_path(f: &zip::read::ZipFile) -> Result<PathBuf, Error> {
+    let name = String::from_utf8_lossy(f.name_raw());
+    archive_entry_path(Path::new(name.as_ref()), ArchiveFormat::Zip)
 }
 
-pub fn decompress(zip_path: &Path, out_dir: &Path) -> Result<(), Error> {
+pub fn decompress(
+    zip_path: &Path,
+    out_dir: &Path,
+    max_uncompressed_bytes: u64,
+) -> Result<(), Error> {
     if let Ok(zip_file) = File::open(zip_path) {
         if let Ok(mut zip) = zip::ZipArchive::new(zip_file) {
+            check_zip_size(&mut zip, max_uncompressed_bytes)?;
+            // the sizes in the headers are only claims of the archiver,
+            // so the bytes actually written are counted as well
+            let mut remaining = max_uncompressed_bytes;
             match create_dir_check(&out_dir) {
                 Ok(_) => {
                     for i in 0..zip.len() {
                         if let Ok(mut f) = zip.by_index(i) {
-                            if f.name().starts_with("__MACOSX") {
+                            let relative = zip_entry_path(&f)?;
+                            if is_junk_path(&relative) || relative.as_os_str().is_empty() {
                                 continue;
                             }
-                            let new_path = out_dir.join(f.name());
+                            let new_path = out_dir.join(&relative);
                             if f.is_dir() {
                                 match create_dir_check(&new_path) {
                                     Ok(_) => {}
@@ -83,8 +282,39 @@ pub fn decompress(zip_path: &Path, out_dir: &Path) -> Result<(), Error> {
                                     }
                                 };
                             } else if f.is_file() {
+                                if let Some(parent) = new_path.parent() {
+                                    create_dir_check(parent)?;
+                                }
                                 if let Ok(mut outfile) = std::fs::File::create(&new_path) {
-                                    std::io::copy(&mut f, &mut outfile).unwrap();
+                                    let written = std::io::copy(
+                                        &mut (&mut f).take(remaining.saturating_add(1)),
+                                        &mut outfile,
+                                    )
+                                    .map_err(|_| {
+                                        Error::DecompressionError(format!(
+                                            "failed to extract `{}`",
+                                            relative.display()
+                                        ))
+                                    })?;
+                                    if written > remaining {
+                                        return Err(Error::ZipTooLarge(format!(
+                                            "uncompressed size exceeds {} bytes",
+                                            max_uncompressed_bytes
+                                        )));
+                                    }
+                                    remaining -= written;
+                                    if let Some(mode) = f.unix_mode() {
+                                        use std::os::unix::fs::PermissionsExt;
+                                        let permissions = std::fs::Permissions::from_mode(
+                                            mode & ARCHIVE_MODE_MASK,
+                                        );
+                                        if std::fs::set_permissions(&new_path, permissions).is_err()
+                                        {
+                                            return Err(Error::DecompressionError(
+                                                "failed to set permissions".to_string(),
+                                            ));
+                                        }
+                                    }
                                 } else {
                                     return Err(Error::DecompressionError(
                                         "decompression failure".parse().unwrap(),
@@ -117,261 +347,1149 @@ pub fn decompress(zip_path: &Path, out_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn read_utf8_file(file: &Path) -> std::io::Result<String> {
-    let mut file = File::open(file)?;
-    let mut utf8_string = String::new();
-    file.read_to_string(&mut utf8_string)?;
-    Ok(utf8_string)
+///
+/// the archive formats accepted for a task
+///
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
 }
 
-pub fn read_last_n_lines(file: &Path, n: usize, pattern: &str) -> std::io::Result<String> {
-    let file = File::open(file)?;
-    let lines = BufReader::new(file).lines();
-    let mut lines_queue = VecDeque::with_capacity(n + 1);
-    for line in lines {
-        match line {
-            Ok(l) => {
-                if l.contains(pattern) {
-                    lines_queue.push_back(l);
-                }
-            }
-            Err(e) => return Err(std::io::Error::from(e)),
+impl ArchiveFormat {
+    ///
+    /// the format by the extension of `filename`: `.zip`, `.tar.gz` or `.tgz`
+    ///
+    pub fn from_filename(filename: &str) -> Option<ArchiveFormat> {
+        let filename = filename.to_lowercase();
+        if filename.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
         }
-        if lines_queue.len() > n {
-            let _ = lines_queue.pop_front();
+    }
+
+    ///
+    /// the format by the first bytes of the file at `path`
+    ///
+    pub fn from_magic(path: &Path) -> Option<ArchiveFormat> {
+        let mut magic = Vec::with_capacity(4);
+        File::open(path)
+            .ok()?
+            .take(4)
+            .read_to_end(&mut magic)
+            .ok()?;
+        if ZIP_MAGIC.iter().any(|zip| magic.starts_with(zip)) {
+            Some(ArchiveFormat::Zip)
+        } else if magic.starts_with(GZIP_MAGIC) {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
         }
     }
-    Ok(Vec::from_iter(lines_queue).join("\n"))
 }
 
 ///
-/// this function moves files in a folder recursively using rename method.
+/// extract a zip or tar.gz archive into `out_dir`, telling the format by its first bytes
 ///
-pub fn move_by_rename(from: &Path, to: &Path) -> Result<(), Error> {
-    match move_by_rename_inner(from, to) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::RenameError(format!(
-            "error moving from {} to {}: {:?}",
-            from.display(),
-            to.display(),
-            e
-        ))),
+pub fn extract_archive(
+    archive_path: &Path,
+    out_dir: &Path,
+    max_uncompressed_bytes: u64,
+) -> Result<(), Error> {
+    match ArchiveFormat::from_magic(archive_path) {
+        Some(ArchiveFormat::Zip) => decompress(archive_path, out_dir, max_uncompressed_bytes),
+        Some(ArchiveFormat::TarGz) => extract_tar_gz(archive_path, out_dir, max_uncompressed_bytes),
+        None => Err(Error::DecompressionError(
+            "the archive is neither zip nor tar.gz".to_string(),
+        )),
     }
 }
 
-fn create_dir_io_error(dir: &Path) -> Result<(), std::io::Error> {
-    match create_dir_check(&dir) {
-        Ok(_) => Ok(()),
-        Err(_) => return Err(std::io::Error::from(std::io::ErrorKind::Other)),
-    }
+fn tar_gz_error<E: std::fmt::Debug>(e: E) -> Error {
+    Error::DecompressionError(format!("failed to decompress tar.gz archive: {:?}", e))
 }
 
-fn move_by_rename_inner(from: &Path, to: &Path) -> Result<(), std::io::Error> {
-    create_dir_io_error(&to)?;
-    let from = std::path::Path::new(from);
-    let to = std::path::Path::new(to);
-    let mut stack = Vec::new();
-    stack.push(PathBuf::from(&from));
-
-    let output_root = PathBuf::from(&to);
-    let input_root = PathBuf::from(&from).components().count();
-
-    while let Some(working_path) = stack.pop() {
-        // relative path
-        let src: PathBuf = working_path.components().skip(input_root).collect();
-
-        // Create a destination if missing
-        let dest = if src.components().count() == 0 {
-            output_root.clone()
-        } else {
-            output_root.join(&src)
-        };
-        create_dir_io_error(&to)?;
+fn open_tar_gz(archive_path: &Path) -> Result<tar::Archive<GzDecoder<File>>, Error> {
+    match File::open(archive_path) {
+        Ok(file) => Ok(tar::Archive::new(GzDecoder::new(file))),
+        Err(_) => Err(Error::DecompressionError(
+            "failed to open tar.gz file".to_string(),
+        )),
+    }
+}
 
-        for entry in std::fs::read_dir(working_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                stack.push(path);
-            } else {
-                match path.file_name() {
-                    Some(filename) => {
-                        let dest_path = dest.join(filename);
-                        std::fs::rename(&path, &dest_path)?;
-                    }
-                    None => {}
-                }
+///
+/// a path of a zip or tar.gz entry, which must stay inside of the folder it is extracted to
+///
+fn archive_entry_path(path: &Path, format: ArchiveFormat) -> Result<PathBuf, Error> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            _ => {
+                return Err(Error::DecompressionError(format!(
+                    "`{}` in {} points outside of the archive",
+                    path.display(),
+                    format.name()
+                )))
             }
         }
     }
-
-    std::fs::remove_dir_all(from).unwrap();
-
-    Ok(())
+    Ok(relative)
 }
 
-pub fn copy_folder(from: &Path, to: &Path) -> Result<(), Error> {
-    match copy_folder_inner(from, to) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::CopyError(format!(
-            "error copying from {} to {}: {}",
-            from.display(),
-            to.display(),
-            e.to_string()
-        ))),
+///
+/// check the entries of a tar.gz before extracting anything, with the same limits as
+/// `check_zip_size`, where the compression ratio is that of the whole archive,
+/// and every path must stay inside of the archive. Links are not supported.
+///
+fn check_tar_gz(archive_path: &Path, max_uncompressed_bytes: u64) -> Result<u64, Error> {
+    let mut archive = open_tar_gz(archive_path)?;
+    let mut total: u64 = 0;
+    for (i, entry) in archive.entries().map_err(tar_gz_error)?.enumerate() {
+        if i >= MAX_ZIP_ENTRIES {
+            return Err(Error::ZipTooLarge(format!(
+                "tar.gz contains more than {} entries",
+                MAX_ZIP_ENTRIES
+            )));
+        }
+        let entry = entry.map_err(tar_gz_error)?;
+        let path = entry.path().map_err(tar_gz_error)?;
+        archive_entry_path(&path, ArchiveFormat::TarGz)?;
+        if matches!(
+            entry.header().entry_type(),
+            EntryType::Symlink | EntryType::Link
+        ) {
+            return Err(Error::DecompressionError(format!(
+                "`{}` in tar.gz is a link, which is not supported",
+                path.display()
+            )));
+        }
+        total = total.saturating_add(entry.size());
+        if total > max_uncompressed_bytes {
+            return Err(Error::ZipTooLarge(format!(
+                "uncompressed size of tar.gz {} bytes exceeds {} bytes",
+                total, max_uncompressed_bytes
+            )));
+        }
+    }
+    let compressed = std::fs::metadata(archive_path).map_or(0, |m| m.len());
+    if total > compressed.max(1) * MAX_COMPRESSION_RATIO {
+        return Err(Error::ZipTooLarge(format!(
+            "tar.gz has a suspicious compression ratio: {} bytes compressed to {} bytes",
+            total, compressed
+        )));
     }
+    Ok(total)
 }
 
-fn copy_folder_inner(from: &Path, to: &Path) -> Result<(), std::io::Error> {
-    create_dir_io_error(&to)?;
-    let from = std::path::Path::new(from);
-    let to = std::path::Path::new(to);
-    let mut stack = Vec::new();
-    stack.push(PathBuf::from(&from));
-
-    let output_root = PathBuf::from(&to);
-    let input_root = PathBuf::from(&from).components().count();
-
-    while let Some(working_path) = stack.pop() {
-        // relative path
-        let src: PathBuf = working_path.components().skip(input_root).collect();
-
-        // Create a destination if missing
-        let dest = if src.components().count() == 0 {
-            output_root.clone()
-        } else {
-            output_root.join(&src)
-        };
-        create_dir_io_error(&to)?;
-
-        for entry in std::fs::read_dir(working_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                stack.push(path);
-            } else {
-                match path.file_name() {
-                    Some(filename) => {
-                        let dest_path = dest.join(filename);
-                        std::fs::copy(&path, &dest_path)?;
-                    }
-                    None => {}
+///
+/// extract a tar.gz like `decompress`, skipping junk files,
+/// and keeping the permission bits of files (e.g. the executable bit)
+///
+pub fn extract_tar_gz(
+    archive_path: &Path,
+    out_dir: &Path,
+    max_uncompressed_bytes: u64,
+) -> Result<(), Error> {
+    check_tar_gz(archive_path, max_uncompressed_bytes)?;
+    if create_dir_check(out_dir).is_err() {
+        return Err(Error::DecompressionError(
+            "failed to create decompression folder".to_string(),
+        ));
+    }
+    let mut archive = open_tar_gz(archive_path)?;
+    for entry in archive.entries().map_err(tar_gz_error)? {
+        let mut entry = entry.map_err(tar_gz_error)?;
+        let relative =
+            archive_entry_path(&entry.path().map_err(tar_gz_error)?, ArchiveFormat::TarGz)?;
+        if is_junk_path(&relative) || relative.as_os_str().is_empty() {
+            continue;
+        }
+        let new_path = out_dir.join(&relative);
+        match entry.header().entry_type() {
+            EntryType::Directory => create_dir_check(&new_path)?,
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = new_path.parent() {
+                    create_dir_check(parent)?;
+                }
+                let mut outfile = File::create(&new_path).map_err(tar_gz_error)?;
+                std::io::copy(&mut entry, &mut outfile).map_err(tar_gz_error)?;
+                if let Ok(mode) = entry.header().mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    let permissions = std::fs::Permissions::from_mode(mode & ARCHIVE_MODE_MASK);
+                    std::fs::set_permissions(&new_path, permissions).map_err(tar_gz_error)?;
                 }
             }
+            // e.g. pax global headers
+            _ => {}
         }
     }
-
     Ok(())
 }
 
+pub fn read_utf8_file(file: &Path) -> std::io::Result<String> {
+    let mut file = File::open(file)?;
+    let mut utf8_string = String::new();
+    file.read_to_string(&mut utf8_string)?;
+    Ok(utf8_string)
+}
+
 ///
-/// chown function for path
+/// read the last `n` lines across several files ordered from newest to oldest
+/// (e.g. `stdout.log`, `stdout.log.1`, ...), continuing into older files only when
+/// newer files have fewer than `n` matching lines.
 ///
-fn chown_by_name(
-    path: &Path,
-    username: &Option<String>,
-    group_name: &Option<String>,
-) -> Result<(), Error> {
-    let (uid, gid) = get_user_group_pair_id(path, username, group_name)?;
-    if let Ok(path) = CString::new(path.as_os_str().as_bytes()) {
-        if unsafe { libc::chown(path.as_ptr(), uid, gid) } == 0 {
-            return Ok(());
+pub fn read_last_n_lines_multi(
+    files: &[PathBuf],
+    n: usize,
+    pattern: &str,
+) -> std::io::Result<String> {
+    let mut collected = VecDeque::with_capacity(n);
+    for file in files {
+        if collected.len() >= n {
+            break;
+        }
+        let older = read_last_n_lines_queue(file, n - collected.len(), pattern)?;
+        for line in older.into_iter().rev() {
+            collected.push_front(line);
         }
     }
-    Err(Error::FailedToChown(format!(
-        "failed to change owner ship of `{}`",
-        path.to_str().unwrap_or("unknown path")
-    )))
+    Ok(Vec::from_iter(collected).join("\n"))
 }
 
-///
-/// recursively change ownership of all included file of a directory
-///
-pub fn chown_by_name_recursive(
-    path: &Path,
-    username: &Option<String>,
-    group_name: &Option<String>,
-) -> Result<(), Error> {
-    chown_by_name(path, username, group_name)?;
-    if path.is_dir() {
-        let mut stack = Vec::new();
-        stack.push(PathBuf::from(&path));
-
-        while let Some(working_path) = stack.pop() {
-            if let Ok(dir) = std::fs::read_dir(&working_path) {
-                for entry in dir {
-                    if let Ok(entry) = entry {
-                        let path = entry.path();
-                        if path.is_dir() {
-                            stack.push(PathBuf::from(&path));
-                        }
-                        chown_by_name(&path, username, group_name)?;
-                    } else {
-                        return Err(Error::FailedToChown(format!(
-                            "failed to chown entry: {}",
-                            working_path.to_str().unwrap_or("unknown path")
-                        )));
-                    }
-                }
-            } else {
-                return Err(Error::FailedToChown(format!(
-                    "{}",
-                    working_path.to_str().unwrap_or("unknown path")
-                )));
-            }
+fn read_last_n_lines_queue(
+    file: &Path,
+    n: usize,
+    pattern: &str,
+) -> std::io::Result<VecDeque<String>> {
+    let file = File::open(file)?;
+    let lines = LossyLines::new(BufReader::new(file), MAX_LOG_LINE_BYTES);
+    let mut lines_queue = VecDeque::with_capacity(n + 1);
+    for line in lines {
+        let line = line?;
+        if line.contains(pattern) {
+            lines_queue.push_back(line);
+        }
+        if lines_queue.len() > n {
+            let _ = lines_queue.pop_front();
         }
     }
-    Ok(())
+    Ok(lines_queue)
 }
 
 ///
-/// Convert `(user name, group name)` to `(user id, group id)` pair,
-/// and find primary group if only user is supplied.
-/// It return the original uid of the file for uid if only group is supplied.
+/// lines of a log, which may contain anything a task wrote. Unlike `BufRead::lines`,
+/// invalid UTF-8 becomes `U+FFFD`, a trailing `\r` is removed, and lines longer than
+/// `max_bytes` are truncated with `…` without being read into memory as a whole.
 ///
-fn get_user_group_pair_id(
-    path: &Path,
-    username: &Option<String>,
-    group_name: &Option<String>,
-) -> Result<(u32, u32), Error> {
-    if let Ok(meta) = std::fs::metadata(&path) {
-        let user: Option<User> = match username {
-            None => None,
-            Some(name) => match users::get_user_by_name(name) {
-                None => None,
-                Some(u) => Some(u),
-            },
-        };
+struct LossyLines<R> {
+    reader: R,
+    max_bytes: usize,
+}
 
-        let group: Option<Group> = match group_name {
-            None => None,
-            Some(name) => match users::get_group_by_name(name) {
-                None => None,
-                Some(g) => Some(g),
-            },
-        };
+impl<R: BufRead> LossyLines<R> {
+    fn new(reader: R, max_bytes: usize) -> LossyLines<R> {
+        LossyLines { reader, max_bytes }
+    }
 
-        match user {
-            None => {
-                if let Some(g) = group {
-                    Ok((meta.st_uid(), g.gid()))
-                } else {
-                    Ok((meta.st_uid(), meta.st_gid()))
-                }
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = Vec::new();
+        let mut truncated = false;
+        let mut eof = true;
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            eof = false;
+            let (content, used, ended) = match available.iter().position(|b| *b == b'\n') {
+                Some(i) => (&available[..i], i + 1, true),
+                None => (available, available.len(), false),
+            };
+            let room = self.max_bytes.saturating_sub(line.len());
+            if content.len() > room {
+                truncated = true;
+            }
+            line.extend_from_slice(&content[..content.len().min(room)]);
+            self.reader.consume(used);
+            if ended {
+                break;
             }
-            Some(u) => match group {
-                None => Ok((u.uid(), u.primary_group_id())),
-                Some(g) => Ok((u.uid(), g.gid())),
-            },
         }
-    } else {
-        Err(Error::PathDoesNotExist(format!(
-            "path does not exist in chown"
-        )))
+        if eof {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let mut line = String::from_utf8_lossy(&line).into_owned();
+        if truncated {
+            line.push_str(TRUNCATED_LINE_MARKER);
+        }
+        Ok(Some(line))
     }
 }
 
-fn zip_inner<T>(
+impl<R: BufRead> Iterator for LossyLines<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
+
+///
+/// days since epoch of a date in the proleptic Gregorian calendar,
+/// the inverse of `civil_from_days`
+///
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+///
+/// `(year, month, day)` of a number of days since epoch, in the proleptic Gregorian calendar
+///
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+///
+/// the local date (in days since epoch), hour and minute of `secs` since epoch
+///
+pub fn local_day_and_minute(secs: i64) -> (i64, i64, i64) {
+    let time = secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&time, &mut tm) };
+    let days = days_from_civil(
+        tm.tm_year as i64 + 1900,
+        tm.tm_mon as i64 + 1,
+        tm.tm_mday as i64,
+    );
+    (days, tm.tm_hour as i64, tm.tm_min as i64)
+}
+
+///
+/// seconds since epoch of a local date (in days since epoch), hour and minute
+///
+pub fn local_secs(days: i64, hour: i64, minute: i64) -> Option<i64> {
+    let (year, month, day) = civil_from_days(days);
+    let time = CivilTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second: 0,
+        millis: 0,
+    };
+    time.to_millis_local().map(|millis| millis / 1000)
+}
+
+///
+/// a date and time of day without time zone, like `2020-09-13 02:00:00`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CivilTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    millis: i64,
+}
+
+impl CivilTime {
+    fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second <= 60
+    }
+
+    ///
+    /// milliseconds since epoch, of UTC with `offset_secs` east of UTC
+    ///
+    fn to_millis_with_offset(self, offset_secs: i64) -> i64 {
+        let secs = days_from_civil(self.year, self.month, self.day) * 86400
+            + self.hour * 3600
+            + self.minute * 60
+            + self.second
+            - offset_secs;
+        secs * 1000 + self.millis
+    }
+
+    ///
+    /// milliseconds since epoch, in the local time zone of the server
+    ///
+    fn to_millis_local(self) -> Option<i64> {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        tm.tm_year = (self.year - 1900) as libc::c_int;
+        tm.tm_mon = (self.month - 1) as libc::c_int;
+        tm.tm_mday = self.day as libc::c_int;
+        tm.tm_hour = self.hour as libc::c_int;
+        tm.tm_min = self.minute as libc::c_int;
+        tm.tm_sec = self.second as libc::c_int;
+        // let mktime decide whether daylight saving time applies
+        tm.tm_isdst = -1;
+        match unsafe { libc::mktime(&mut tm) } {
+            -1 => None,
+            secs => Some(secs * 1000 + self.millis),
+        }
+    }
+}
+
+fn parse_digits(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |n, b| n * 10 + (b - b'0') as i64))
+}
+
+///
+/// a leading `YYYY-MM-DD[T ]HH:MM:SS[.fff]` with an optional `Z` or `+HH:MM` offset,
+/// returning the time, the offset in seconds east of UTC, and the length parsed
+///
+fn parse_iso_prefix(s: &str) -> Option<(CivilTime, Option<i64>, usize)> {
+    let b = s.as_bytes();
+    if b.len() < 19
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't' | b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return None;
+    }
+    let mut time = CivilTime {
+        year: parse_digits(&b[0..4])?,
+        month: parse_digits(&b[5..7])?,
+        day: parse_digits(&b[8..10])?,
+        hour: parse_digits(&b[11..13])?,
+        minute: parse_digits(&b[14..16])?,
+        second: parse_digits(&b[17..19])?,
+        millis: 0,
+    };
+    let mut i = 19;
+    // fraction of second, `,` as in python logging
+    if matches!(b.get(i), Some(b'.') | Some(b',')) {
+        let start = i + 1;
+        i = start;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == start {
+            return None;
+        }
+        let digits = &b[start..std::cmp::min(i, start + 3)];
+        time.millis = parse_digits(digits)? * 10i64.pow(3 - digits.len() as u32);
+    }
+    let offset = parse_offset(&b[i..]).map(|(offset, len)| {
+        i += len;
+        offset
+    });
+    if !time.is_valid() {
+        return None;
+    }
+    Some((time, offset, i))
+}
+
+///
+/// a leading `Z`, `+HH:MM` or `+HHMM` in seconds east of UTC, and its length
+///
+fn parse_offset(b: &[u8]) -> Option<(i64, usize)> {
+    let sign = match b.first()? {
+        b'Z' | b'z' => return Some((0, 1)),
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes, len) = match b.get(1..6) {
+        Some(o) if o[2] == b':' => (parse_digits(&o[0..2])?, parse_digits(&o[3..5])?, 6),
+        _ => {
+            let o = b.get(1..5)?;
+            (parse_digits(&o[0..2])?, parse_digits(&o[2..4])?, 5)
+        }
+    };
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some((sign * (hours * 3600 + minutes * 60), len))
+}
+
+///
+/// a leading syslog timestamp like `Oct  3 02:00:01`, which has no year
+///
+fn parse_syslog_prefix(s: &str, year: i64) -> Option<CivilTime> {
+    let b = s.as_bytes();
+    if b.len() < 15 || b[3] != b' ' || b[6] != b' ' || b[9] != b':' || b[12] != b':' {
+        return None;
+    }
+    let month = SYSLOG_MONTHS
+        .iter()
+        .position(|m| m.as_bytes() == &b[0..3])? as i64
+        + 1;
+    let day = if b[4] == b' ' {
+        parse_digits(&b[5..6])?
+    } else {
+        parse_digits(&b[4..6])?
+    };
+    let time = CivilTime {
+        year,
+        month,
+        day,
+        hour: parse_digits(&b[7..9])?,
+        minute: parse_digits(&b[10..12])?,
+        second: parse_digits(&b[13..15])?,
+        millis: 0,
+    };
+    if time.is_valid() {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+///
+/// a RFC3339 timestamp in milliseconds since epoch, e.g. `2020-09-13T02:00:00+08:00`
+///
+pub fn parse_rfc3339(s: &str) -> Option<i64> {
+    match parse_iso_prefix(s.trim())? {
+        (time, Some(offset), len) if len == s.trim().len() => {
+            Some(time.to_millis_with_offset(offset))
+        }
+        _ => None,
+    }
+}
+
+///
+/// the leading timestamp of a log line in milliseconds since epoch, optionally in `[]`.
+/// Formats are RFC3339, `YYYY-MM-DD HH:MM:SS[.fff]`, and syslog `Mmm dd HH:MM:SS`,
+/// where timestamps without time zone are in local time, and syslog ones in `syslog_year`.
+///
+fn parse_log_timestamp(line: &str, syslog_year: i64) -> Option<i64> {
+    let line = line.strip_prefix('[').unwrap_or(line);
+    match parse_iso_prefix(line) {
+        Some((time, Some(offset), _)) => Some(time.to_millis_with_offset(offset)),
+        Some((time, None, _)) => time.to_millis_local(),
+        None => parse_syslog_prefix(line, syslog_year)?.to_millis_local(),
+    }
+}
+
+fn current_local_year() -> i64 {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    tm.tm_year as i64 + 1900
+}
+
+///
+/// the first timestamp in the `block_bytes` after `offset`, skipping the partial line at `offset`
+///
+fn first_timestamp_after(
+    file: &mut File,
+    offset: u64,
+    block_bytes: u64,
+    syslog_year: i64,
+) -> std::io::Result<Option<i64>> {
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    let mut block = Vec::new();
+    Read::by_ref(file)
+        .take(block_bytes)
+        .read_to_end(&mut block)?;
+    let start = match block.iter().position(|b| *b == b'\n') {
+        Some(i) => i + 1,
+        None => return Ok(None),
+    };
+    Ok(block[start..]
+        .split(|b| *b == b'\n')
+        .find_map(|line| parse_log_timestamp(&String::from_utf8_lossy(line), syslog_year)))
+}
+
+///
+/// an offset before the first line at or after `since`, assuming timestamps never decrease.
+/// The search stops at a block without any timestamp (e.g. inside a long stack trace).
+///
+fn find_time_offset(
+    file: &mut File,
+    since: i64,
+    block_bytes: u64,
+    syslog_year: i64,
+) -> std::io::Result<u64> {
+    let (mut low, mut high) = (0, file.metadata()?.len());
+    while high - low > block_bytes {
+        let middle = low + (high - low) / 2;
+        match first_timestamp_after(file, middle, block_bytes, syslog_year)? {
+            Some(time) if time < since => low = middle,
+            Some(_) => high = middle,
+            None => break,
+        }
+    }
+    Ok(low)
+}
+
+///
+/// the last `limit` lines containing `pattern` with a leading timestamp between `since`
+/// and `until` (inclusive, milliseconds since epoch), and lines without timestamp
+/// following such a line, like the continuation lines of a stack trace.
+///
+/// Timestamps are assumed to never decrease within a file, so the start is found by
+/// binary search and reading stops after `until`.
+///
+pub fn read_lines_in_time_range(
+    path: &Path,
+    since: Option<i64>,
+    until: Option<i64>,
+    pattern: &str,
+    limit: usize,
+) -> std::io::Result<VecDeque<String>> {
+    read_lines_in_time_range_with(path, since, until, pattern, limit, TIME_SEARCH_BLOCK_BYTES)
+}
+
+fn read_lines_in_time_range_with(
+    path: &Path,
+    since: Option<i64>,
+    until: Option<i64>,
+    pattern: &str,
+    limit: usize,
+    block_bytes: u64,
+) -> std::io::Result<VecDeque<String>> {
+    let syslog_year = current_local_year();
+    let mut file = File::open(path)?;
+    let offset = match since {
+        Some(since) => find_time_offset(&mut file, since, block_bytes, syslog_year)?,
+        None => 0,
+    };
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+    if offset > 0 {
+        // skip the partial line
+        reader.read_until(b'\n', &mut Vec::new())?;
+    }
+    let mut lines = VecDeque::with_capacity(limit + 1);
+    // lines before the first timestamp are only known to be before `until`
+    let mut included = since.is_none();
+    for line in LossyLines::new(reader, MAX_LOG_LINE_BYTES) {
+        let line = line?;
+        if let Some(time) = parse_log_timestamp(&line, syslog_year) {
+            if matches!(until, Some(until) if time > until) {
+                break;
+            }
+            included = !matches!(since, Some(since) if time < since);
+        }
+        if included && line.contains(pattern) {
+            lines.push_back(line);
+            if lines.len() > limit {
+                let _ = lines.pop_front();
+            }
+        }
+    }
+    Ok(lines)
+}
+
+///
+/// `read_lines_in_time_range` across several files ordered from newest to oldest
+///
+pub fn read_lines_in_time_range_multi(
+    files: &[PathBuf],
+    since: Option<i64>,
+    until: Option<i64>,
+    pattern: &str,
+    limit: usize,
+) -> std::io::Result<String> {
+    let mut collected = VecDeque::with_capacity(limit + 1);
+    for file in files.iter().rev() {
+        collected.extend(read_lines_in_time_range(
+            file, since, until, pattern, limit,
+        )?);
+        while collected.len() > limit {
+            let _ = collected.pop_front();
+        }
+    }
+    Ok(Vec::from_iter(collected).join("\n"))
+}
+
+///
+/// this function moves files in a folder recursively using rename method.
+///
+pub fn move_by_rename(from: &Path, to: &Path) -> Result<(), Error> {
+    match move_by_rename_inner(from, to) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::RenameError(format!(
+            "error moving from {} to {}: {:?}",
+            from.display(),
+            to.display(),
+            e
+        ))),
+    }
+}
+
+fn create_dir_io_error(dir: &Path) -> Result<(), std::io::Error> {
+    match create_dir_check(&dir) {
+        Ok(_) => Ok(()),
+        Err(_) => return Err(std::io::Error::from(std::io::ErrorKind::Other)),
+    }
+}
+
+fn move_by_rename_inner(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    let mut moved = Vec::new();
+    match move_files(from, to, &mut moved) {
+        Ok(_) => std::fs::remove_dir_all(from),
+        Err(e) => {
+            // move back what has been moved so that the source is left intact
+            for (src, dest) in moved.iter().rev() {
+                let _ = move_file(dest, src);
+            }
+            Err(e)
+        }
+    }
+}
+
+///
+/// move every file under `from` to the same relative path under `to`,
+/// recording each `(source, destination)` pair that has been moved.
+///
+fn move_files(
+    from: &Path,
+    to: &Path,
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), std::io::Error> {
+    let mut stack = Vec::new();
+    stack.push(PathBuf::from(&from));
+
+    let output_root = PathBuf::from(&to);
+    let input_root = PathBuf::from(&from).components().count();
+
+    while let Some(working_path) = stack.pop() {
+        // relative path
+        let src: PathBuf = working_path.components().skip(input_root).collect();
+
+        // Create a destination if missing
+        let dest = if src.components().count() == 0 {
+            output_root.clone()
+        } else {
+            output_root.join(&src)
+        };
+        create_dir_io_error(&dest)?;
+
+        for entry in std::fs::read_dir(working_path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                stack.push(path);
+            } else {
+                let dest_path = dest.join(entry.file_name());
+                move_file(&path, &dest_path)?;
+                moved.push((path, dest_path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// rename a single file, or copy then delete it if `from` and `to` are on different devices.
+///
+fn move_file(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+///
+/// copy a folder recursively, keeping symlinks as symlinks and optionally
+/// preserving modification time of files. Returns the number of files
+/// (including symlinks) copied.
+///
+pub fn copy_folder(from: &Path, to: &Path, preserve_mtime: bool) -> Result<usize, Error> {
+    match copy_folder_inner(from, to, preserve_mtime) {
+        Ok(count) => Ok(count),
+        Err(e) => Err(Error::CopyError(format!(
+            "error copying from {} to {}: {}",
+            from.display(),
+            to.display(),
+            e.to_string()
+        ))),
+    }
+}
+
+fn copy_folder_inner(
+    from: &Path,
+    to: &Path,
+    preserve_mtime: bool,
+) -> Result<usize, std::io::Error> {
+    let from = std::path::Path::new(from);
+    let to = std::path::Path::new(to);
+    let mut stack = Vec::new();
+    stack.push(PathBuf::from(&from));
+
+    let output_root = PathBuf::from(&to);
+    let input_root = PathBuf::from(&from).components().count();
+    let mut count: usize = 0;
+
+    while let Some(working_path) = stack.pop() {
+        // relative path
+        let src: PathBuf = working_path.components().skip(input_root).collect();
+
+        // Create a destination if missing
+        let dest = if src.components().count() == 0 {
+            output_root.clone()
+        } else {
+            output_root.join(&src)
+        };
+        create_dir_io_error(&dest)?;
+
+        for entry in std::fs::read_dir(working_path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let dest_path = dest.join(entry.file_name());
+            if file_type.is_dir() {
+                stack.push(path);
+            } else if file_type.is_symlink() {
+                std::os::unix::fs::symlink(std::fs::read_link(&path)?, &dest_path)?;
+                count += 1;
+            } else {
+                std::fs::copy(&path, &dest_path)?;
+                if preserve_mtime {
+                    set_mtime(&dest_path, entry.metadata()?.modified()?)?;
+                }
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+///
+/// set both access time and modification time of a file to `mtime`
+///
+pub fn set_mtime(path: &Path, mtime: SystemTime) -> Result<(), std::io::Error> {
+    let since_epoch = match mtime.duration_since(UNIX_EPOCH) {
+        Ok(d) => d,
+        Err(_) => return Ok(()),
+    };
+    let time = libc::timeval {
+        tv_sec: since_epoch.as_secs() as libc::time_t,
+        tv_usec: since_epoch.subsec_micros() as libc::suseconds_t,
+    };
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+    };
+    if unsafe { libc::utimes(c_path.as_ptr(), [time, time].as_ptr()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+///
+/// chown function for path
+///
+pub fn chown_by_name(
+    path: &Path,
+    username: &Option<String>,
+    group_name: &Option<String>,
+) -> Result<(), Error> {
+    let (uid, gid) = get_user_group_pair_id(path, username, group_name)?;
+    chown_by_id(path, uid, gid)
+}
+
+///
+/// remove a unix socket left by a previous run. Other kinds of files are not touched.
+///
+pub fn remove_stale_socket(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::FileTypeExt;
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => match std::fs::remove_file(path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::UnixSocketError(format!(
+                "cannot remove stale socket `{}`: {}",
+                path.display(),
+                e
+            ))),
+        },
+        Ok(_) => Err(Error::UnixSocketError(format!(
+            "`{}` exists and is not a socket",
+            path.display()
+        ))),
+        Err(_) => Ok(()),
+    }
+}
+
+///
+/// set permission bits of a path, e.g. `0o660`
+///
+pub fn set_mode(path: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+    match std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::UnixSocketError(format!(
+            "cannot set mode {:o} of `{}`: {}",
+            mode,
+            path.display(),
+            e
+        ))),
+    }
+}
+
+///
+/// chown function for path using user id and group id
+///
+pub fn chown_by_id(path: &Path, uid: u32, gid: u32) -> Result<(), Error> {
+    if let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) {
+        if unsafe { libc::chown(c_path.as_ptr(), uid, gid) } == 0 {
+            return Ok(());
+        }
+    }
+    Err(Error::FailedToChown(format!(
+        "failed to change owner ship of `{}`",
+        path.to_string_lossy()
+    )))
+}
+
+///
+/// recursively change ownership of all included file of a directory
+///
+pub fn chown_by_name_recursive(
+    path: &Path,
+    username: &Option<String>,
+    group_name: &Option<String>,
+) -> Result<(), Error> {
+    chown_by_name(path, username, group_name)?;
+    if path.is_dir() {
+        let mut stack = Vec::new();
+        stack.push(PathBuf::from(&path));
+
+        while let Some(working_path) = stack.pop() {
+            if let Ok(dir) = std::fs::read_dir(&working_path) {
+                for entry in dir {
+                    if let Ok(entry) = entry {
+                        let path = entry.path();
+                        if path.is_dir() {
+                            stack.push(PathBuf::from(&path));
+                        }
+                        chown_by_name(&path, username, group_name)?;
+                    } else {
+                        return Err(Error::FailedToChown(format!(
+                            "failed to chown entry: {}",
+                            working_path.to_string_lossy()
+                        )));
+                    }
+                }
+            } else {
+                return Err(Error::FailedToChown(format!(
+                    "{}",
+                    working_path.to_string_lossy()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// a file whose ownership could not be changed, e.g. deleted during the walk,
+/// or on a mounted volume that rejects chown
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChownWarning {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for ChownWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to change ownership of `{}`: {}",
+            self.path.display(),
+            self.reason
+        )
+    }
+}
+
+impl ChownWarning {
+    ///
+    /// the warning with the path relative to `root`, e.g. the task folder,
+    /// so that responses do not reveal where tasks are stored
+    ///
+    pub fn relative_to(&self, root: &Path) -> String {
+        let path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        ChownWarning {
+            path: path.to_path_buf(),
+            reason: self.reason.clone(),
+        }
+        .to_string()
+    }
+}
+
+///
+/// `chown_by_name_recursive` that carries on when single files cannot be chowned,
+/// returning the number of changed files and a warning for each failure.
+/// Unknown user or group names are still errors, found before any file is touched.
+///
+pub fn chown_by_name_recursive_lossy(
+    path: &Path,
+    username: &Option<String>,
+    group_name: &Option<String>,
+) -> Result<(usize, Vec<ChownWarning>), Error> {
+    chown_recursive_lossy_with(path, username, group_name, &mut |_| {})
+}
+
+///
+/// `before_chown` is called with each path right before it is chowned
+///
+fn chown_recursive_lossy_with(
+    path: &Path,
+    username: &Option<String>,
+    group_name: &Option<String>,
+    before_chown: &mut dyn FnMut(&Path),
+) -> Result<(usize, Vec<ChownWarning>), Error> {
+    let (uid, gid) = run_as_ids(username, group_name)?;
+    if uid.is_none() && gid.is_none() {
+        return Ok((0, Vec::new()));
+    }
+    let mut changed = 0;
+    let mut warnings = Vec::new();
+    let mut warn = |path: &Path, e: std::io::Error| {
+        warnings.push(ChownWarning {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    };
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(working_path) = stack.pop() {
+        before_chown(&working_path);
+        match chown_keeping_unset(&working_path, uid, gid) {
+            Ok(_) => changed += 1,
+            Err(e) => warn(&working_path, e),
+        }
+        if working_path.is_dir() {
+            match std::fs::read_dir(&working_path) {
+                Ok(dir) => {
+                    for entry in dir {
+                        match entry {
+                            Ok(entry) => stack.push(entry.path()),
+                            Err(e) => warn(&working_path, e),
+                        }
+                    }
+                }
+                Err(e) => warn(&working_path, e),
+            }
+        }
+    }
+    Ok((changed, warnings))
+}
+
+///
+/// chown with the ids that are `None` left unchanged
+///
+fn chown_keeping_unset(path: &Path, uid: Option<u32>, gid: Option<u32>) -> std::io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let uid = uid.unwrap_or(UNCHANGED_ID);
+    let gid = gid.unwrap_or(UNCHANGED_ID);
+    if unsafe { libc::chown(c_path.as_ptr(), uid, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+///
+/// `(user id, group id)` to run a task as, where the group defaults to the primary group
+/// of the user, and `None` is not configured
+///
+pub fn run_as_ids(
+    username: &Option<String>,
+    group_name: &Option<String>,
+) -> Result<(Option<u32>, Option<u32>), Error> {
+    let user = match username {
+        Some(name) => match users::get_user_by_name(name) {
+            Some(u) => Some(u),
+            None => {
+                return Err(Error::IllegalUserName(format!(
+                    "user `{}` does not exist",
+                    name
+                )))
+            }
+        },
+        None => None,
+    };
+    let gid = match group_name {
+        Some(name) => match users::get_group_by_name(name) {
+            Some(g) => Some(g.gid()),
+            None => {
+                return Err(Error::IllegalGroupName(format!(
+                    "group `{}` does not exist",
+                    name
+                )))
+            }
+        },
+        None => user.as_ref().map(|u| u.primary_group_id()),
+    };
+    Ok((user.map(|u| u.uid()), gid))
+}
+
+///
+/// Convert `(user name, group name)` to `(user id, group id)` pair,
+/// and find primary group if only user is supplied.
+/// It return the original uid of the file for uid if only group is supplied.
+/// Unknown user or group names are errors.
+///
+fn get_user_group_pair_id(
+    path: &Path,
+    username: &Option<String>,
+    group_name: &Option<String>,
+) -> Result<(u32, u32), Error> {
+    let (uid, gid) = run_as_ids(username, group_name)?;
+    if let Ok(meta) = std::fs::metadata(&path) {
+        Ok((
+            uid.unwrap_or_else(|| meta.st_uid()),
+            gid.unwrap_or_else(|| meta.st_gid()),
+        ))
+    } else {
+        Err(Error::PathDoesNotExist(format!(
+            "path does not exist in chown"
+        )))
+    }
+}
+
+fn zip_inner<T>(
     it: &mut dyn Iterator<Item = walkdir::DirEntry>,
     prefix: &Path,
     writer: T,
@@ -385,121 +1503,1898 @@ where
         .compression_method(method)
         .unix_permissions(0o755);
 
-    let mut buffer = Vec::new();
-    for entry in it {
-        let path = entry.path();
-        let name = path.strip_prefix(prefix).unwrap();
+    let mut buffer = Vec::new();
+    for entry in it {
+        let path = entry.path();
+        let name = path.strip_prefix(prefix).unwrap();
+        if is_junk_path(name) {
+            continue;
+        }
+
+        if path.is_file() {
+            zip.start_file_from_path(name, options)?;
+            let mut f = File::open(path)?;
+
+            f.read_to_end(&mut buffer)?;
+            zip.write_all(&*buffer)?;
+            buffer.clear();
+        } else if name.as_os_str().len() != 0 {
+            zip.add_directory_from_path(name, options)?;
+        }
+    }
+    zip.finish()?;
+    Result::Ok(())
+}
+
+///
+/// quote `arg` for POSIX shells. Arguments of only safe characters are kept as they are,
+/// others are single-quoted with `'` escaped as `'\''`.
+///
+pub fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+///
+/// substitute `{{key}}` placeholders in `template` with their values in `vars`
+///
+pub fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut rendered = template.to_string();
+    for (key, value) in vars {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", key), value);
+    }
+    rendered
+}
+
+///
+/// a piece of a line with ANSI escape sequences: visible text, or the parameters
+/// of an SGR sequence (`ESC [ <params> m`) setting colors
+///
+enum AnsiPiece<'a> {
+    Text(&'a str),
+    Sgr(&'a str),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum AnsiState {
+    Text,
+    /// after `ESC`
+    Escape,
+    /// after `ESC` and intermediate bytes like `(`, until the final byte
+    EscapeIntermediate,
+    /// `ESC [` (CSI), until a final byte in `@`..=`~`
+    Csi,
+    /// `ESC ]` (OSC, e.g. the window title), until `BEL` or `ESC \`
+    Osc,
+    /// `ESC` inside an OSC sequence
+    OscEscape,
+}
+
+///
+/// split a line into text and SGR sequences, dropping every other escape sequence.
+/// A sequence cut off at the end of the line is dropped as well.
+///
+fn split_ansi(line: &str) -> Vec<AnsiPiece<'_>> {
+    let mut pieces = Vec::new();
+    let mut state = AnsiState::Text;
+    // start of the current text, or of the parameters of a CSI sequence
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        let next = i + c.len_utf8();
+        state = match (state, c) {
+            (AnsiState::Text, '\x1b')
+            | (AnsiState::Text, '\u{9b}')
+            | (AnsiState::Text, '\u{9d}') => {
+                if start < i {
+                    pieces.push(AnsiPiece::Text(&line[start..i]));
+                }
+                start = next;
+                match c {
+                    '\x1b' => AnsiState::Escape,
+                    '\u{9b}' => AnsiState::Csi,
+                    _ => AnsiState::Osc,
+                }
+            }
+            (AnsiState::Text, _) => AnsiState::Text,
+            (AnsiState::Escape, '[') => {
+                start = next;
+                AnsiState::Csi
+            }
+            (AnsiState::Escape, ']') => AnsiState::Osc,
+            (AnsiState::Escape, '\x1b') => AnsiState::Escape,
+            (AnsiState::Escape, ' '..='/') | (AnsiState::EscapeIntermediate, ' '..='/') => {
+                AnsiState::EscapeIntermediate
+            }
+            (AnsiState::Csi, '@'..='~') => {
+                if c == 'm' {
+                    pieces.push(AnsiPiece::Sgr(&line[start..i]));
+                }
+                start = next;
+                AnsiState::Text
+            }
+            (AnsiState::Csi, _) => AnsiState::Csi,
+            (AnsiState::Osc, '\x1b') | (AnsiState::OscEscape, '\x1b') => AnsiState::OscEscape,
+            (AnsiState::Osc, '\x07') | (AnsiState::OscEscape, '\\') => {
+                start = next;
+                AnsiState::Text
+            }
+            (AnsiState::Osc, _) | (AnsiState::OscEscape, _) => AnsiState::Osc,
+            // the final byte of a two-character sequence, like `ESC 7` or `ESC ( B`
+            (AnsiState::Escape, _) | (AnsiState::EscapeIntermediate, _) => {
+                start = next;
+                AnsiState::Text
+            }
+        };
+    }
+    if state == AnsiState::Text && start < line.len() {
+        pieces.push(AnsiPiece::Text(&line[start..]));
+    }
+    pieces
+}
+
+///
+/// remove ANSI escape sequences (colors, cursor movements, window titles, ...) from a line
+///
+pub fn strip_ansi_codes(line: &str) -> String {
+    split_ansi(line)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            AnsiPiece::Text(text) => Some(text),
+            AnsiPiece::Sgr(_) => None,
+        })
+        .collect()
+}
+
+///
+/// the 8 basic colors followed by their bright variants, as in xterm
+///
+static ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+#[derive(Default, Clone, Copy, PartialEq)]
+struct SgrStyle {
+    foreground: Option<&'static str>,
+    background: Option<&'static str>,
+    bold: bool,
+}
+
+impl SgrStyle {
+    ///
+    /// apply the parameters of an SGR sequence, ignoring those beyond the 16 colors and bold
+    ///
+    fn apply(&mut self, params: &str) {
+        let mut params = params.split(';');
+        while let Some(param) = params.next() {
+            match param.parse::<usize>().unwrap_or(0) {
+                0 => *self = SgrStyle::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                n @ 30..=37 => self.foreground = Some(ANSI_COLORS[n - 30]),
+                39 => self.foreground = None,
+                n @ 40..=47 => self.background = Some(ANSI_COLORS[n - 40]),
+                49 => self.background = None,
+                n @ 90..=97 => self.foreground = Some(ANSI_COLORS[n - 90 + 8]),
+                n @ 100..=107 => self.background = Some(ANSI_COLORS[n - 100 + 8]),
+                // skip the arguments of 256 colors (`38;5;n`) and true colors (`38;2;r;g;b`)
+                38 | 48 => match params.next() {
+                    Some("5") => {
+                        params.next();
+                    }
+                    Some("2") => {
+                        params.nth(2);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+        if let Some(color) = self.foreground {
+            css.push(format!("color:{}", color));
+        }
+        if let Some(color) = self.background {
+            css.push(format!("background-color:{}", color));
+        }
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        css.join(";")
+    }
+}
+
+///
+/// escape `&`, `<`, `>`, `"` and `'` so that `text` can be placed in html
+///
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+///
+/// render a line as html, with the 16 colors and bold of SGR sequences as
+/// `<span style>`. The text is escaped, and other escape sequences are removed.
+///
+pub fn render_ansi_html(line: &str) -> String {
+    let mut html = String::new();
+    let mut style = SgrStyle::default();
+    // the style of the open `<span>`, the default style if none is open
+    let mut rendered = SgrStyle::default();
+    for piece in split_ansi(line) {
+        match piece {
+            AnsiPiece::Sgr(params) => style.apply(params),
+            AnsiPiece::Text(text) => {
+                if style != rendered {
+                    if rendered != SgrStyle::default() {
+                        html.push_str("</span>");
+                    }
+                    if style != SgrStyle::default() {
+                        html.push_str(&format!("<span style=\"{}\">", style.css()));
+                    }
+                    rendered = style;
+                }
+                html.push_str(&escape_html(text));
+            }
+        }
+    }
+    if rendered != SgrStyle::default() {
+        html.push_str("</span>");
+    }
+    html
+}
+
+///
+/// parse a compression method name (case-insensitive) of `COMPRESSION_METHODS`
+///
+pub fn parse_compression_method(name: &str) -> Result<zip::CompressionMethod, Error> {
+    match name.trim().to_lowercase().as_str() {
+        "stored" => Ok(zip::CompressionMethod::Stored),
+        "deflate" => Ok(zip::CompressionMethod::Deflated),
+        "bzip2" => Ok(zip::CompressionMethod::Bzip2),
+        _ => Err(Error::UnsupportedCompression(format!(
+            "unknown compression method `{}`, supported methods are: {}",
+            name,
+            COMPRESSION_METHODS.join(", ")
+        ))),
+    }
+}
+
+pub fn zip_dir(
+    src_dir: &Path,
+    dst_file: &Path,
+    method: zip::CompressionMethod,
+) -> Result<(), Error> {
+    zip_dir_filtered(src_dir, dst_file, method, |_| true)
+}
+
+///
+/// zip `src_dir` like `zip_dir`, keeping only the paths for which `keep` is true.
+/// The zip is built in a temp file next to `dst_file` and renamed to it, so that
+/// `dst_file` is either the previous zip or the complete new one.
+///
+pub fn zip_dir_filtered<F>(
+    src_dir: &Path,
+    dst_file: &Path,
+    method: zip::CompressionMethod,
+    keep: F,
+) -> Result<(), Error>
+where
+    F: Fn(&Path) -> bool,
+{
+    if !src_dir.is_dir() {
+        return Err(Error::ZipFailure("Source Not A Directory".to_string()));
+    }
+
+    let file_name = match dst_file.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => {
+            return Err(Error::ZipFailure(format!(
+                "`{}` is not a file",
+                dst_file.display()
+            )))
+        }
+    };
+    let temp = dst_file.with_file_name(format!(
+        ".{}.{}.{}.tasker.tmp",
+        file_name,
+        std::process::id(),
+        ZIP_BUILD_COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let file = File::create(&temp)
+        .map_err(|e| Error::ZipFailure(format!("cannot create `{}`: {}", temp.display(), e)))?;
+
+    let walk_dir = walkdir::WalkDir::new(src_dir);
+    let mut it = walk_dir
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| keep(e.path()));
+
+    let built = match zip_inner(&mut it, src_dir, file, method) {
+        Ok(_) => std::fs::rename(&temp, dst_file).map_err(|e| {
+            Error::ZipFailure(format!(
+                "cannot move zip to `{}`: {}",
+                dst_file.display(),
+                e
+            ))
+        }),
+        Err(_) => Err(Error::ZipFailure("failed to compress zip".to_string())),
+    };
+    if built.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    built
+}
+
+///
+/// whether `path` is one of `logs`, or one of their rotated files such as `stdout.log.1`
+///
+pub fn is_log_file(path: &Path, logs: &[PathBuf]) -> bool {
+    let path = path.to_string_lossy();
+    logs.iter().any(|log| {
+        let log = log.to_string_lossy();
+        match path.strip_prefix(log.as_ref()) {
+            Some("") => true,
+            Some(rest) => match rest.strip_prefix('.') {
+                Some(index) => !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()),
+                None => false,
+            },
+            None => false,
+        }
+    })
+}
+
+///
+/// path of the `index`-th rotated file of `file`, e.g. `stdout.log.1`
+///
+pub fn rotated_file_name(file: &Path, index: usize) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+///
+/// rotate a log file: `file.(keep-1)` is moved to `file.keep`, ..., `file` to `file.1`,
+/// and the oldest one is dropped. A new empty `file` is then created with the same
+/// owner and group as the rotated file.
+///
+pub fn rotate_file(file: &Path, keep: usize) -> Result<(), Error> {
+    let meta = match std::fs::metadata(file) {
+        Ok(m) => m,
+        Err(_) => {
+            return Err(Error::FailedToRotateLog(format!(
+                "`{}` does not exist",
+                file.display()
+            )))
+        }
+    };
+    let rename_error = |from: &Path, to: &Path| {
+        Error::FailedToRotateLog(format!(
+            "failed to rename `{}` to `{}`",
+            from.display(),
+            to.display()
+        ))
+    };
+    if keep == 0 {
+        if std::fs::remove_file(file).is_err() {
+            return Err(Error::FailedToRotateLog(format!(
+                "failed to remove `{}`",
+                file.display()
+            )));
+        }
+    } else {
+        for i in (1..keep).rev() {
+            let older = rotated_file_name(file, i);
+            let oldest = rotated_file_name(file, i + 1);
+            if older.exists() && std::fs::rename(&older, &oldest).is_err() {
+                return Err(rename_error(&older, &oldest));
+            }
+        }
+        let newest = rotated_file_name(file, 1);
+        if std::fs::rename(file, &newest).is_err() {
+            return Err(rename_error(file, &newest));
+        }
+    }
+    if File::create(file).is_err() {
+        return Err(Error::FailedToRotateLog(format!(
+            "failed to create `{}`",
+            file.display()
+        )));
+    }
+    chown_by_id(file, meta.st_uid(), meta.st_gid())
+}
+
+///
+/// line-based unified diff from `old` to `new` computed by longest common subsequence,
+/// with `context` unchanged lines around each change. Returns an empty string if
+/// there is no difference.
+///
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    // walk the table to obtain the edit script
+    let mut ops: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            ops.push((' ', a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(('-', a[i]));
+            i += 1;
+        } else {
+            ops.push(('+', b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[i..].iter().map(|line| ('-', *line)));
+    ops.extend(b[j..].iter().map(|line| ('+', *line)));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k].0 != ' ').collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // group changes into hunks, merging those whose contexts overlap
+    let mut output = vec![format!("--- {}", old_name), format!("+++ {}", new_name)];
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(context);
+        let mut end = changes[k] + context + 1;
+        k += 1;
+        while k < changes.len() && changes[k] <= end + context {
+            end = changes[k] + context + 1;
+            k += 1;
+        }
+        let end = std::cmp::min(end, ops.len());
+        let count =
+            |range: &[(char, &str)], skip: char| range.iter().filter(|op| op.0 != skip).count();
+        output.push(format!(
+            "@@ -{},{} +{},{} @@",
+            count(&ops[..start], '+') + 1,
+            count(&ops[start..end], '+'),
+            count(&ops[..start], '-') + 1,
+            count(&ops[start..end], '-')
+        ));
+        for (prefix, line) in &ops[start..end] {
+            output.push(format!("{}{}", prefix, line));
+        }
+    }
+    output.join("\n")
+}
+
+///
+/// format a time as RFC3339 string in UTC, e.g. `2020-12-31T23:59:59Z`
+///
+pub fn format_rfc3339(time: SystemTime) -> Option<String> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    ))
+}
+
+///
+/// resolve `relative` inside `root`. `..`, absolute paths, and symlinks leading
+/// outside of `root` are rejected with `PathEscapesTask`.
+/// The path may not exist yet, in which case its deepest existing ancestor is checked.
+///
+pub fn resolve_inside(root: &Path, relative: &str) -> Result<PathBuf, Error> {
+    let escapes =
+        || Error::PathEscapesTask(format!("`{}` is outside of the task folder", relative));
+    let relative_path = Path::new(relative);
+    if relative.is_empty() || relative_path.is_absolute() {
+        return Err(escapes());
+    }
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            _ => return Err(escapes()),
+        }
+    }
+    let root = match root.canonicalize() {
+        Ok(root) => root,
+        Err(e) => {
+            return Err(Error::PathDoesNotExist(format!(
+                "cannot resolve `{}`: {}",
+                root.display(),
+                e
+            )))
+        }
+    };
+    let path = root.join(relative_path);
+    // canonicalize follows symlinks, so a link pointing outside is caught here
+    let mut existing = path.as_path();
+    while std::fs::symlink_metadata(existing).is_err() {
+        existing = existing.parent().ok_or_else(escapes)?;
+    }
+    let resolved = existing.canonicalize().map_err(|_| escapes())?;
+    if !resolved.starts_with(&root) {
+        return Err(escapes());
+    }
+    match path.strip_prefix(existing) {
+        // joining an empty path would add a trailing `/`, which files cannot be opened with
+        Ok(rest) if rest.as_os_str().is_empty() => Ok(resolved),
+        Ok(rest) => Ok(resolved.join(rest)),
+        Err(_) => Err(escapes()),
+    }
+}
+
+///
+/// `path` without `.` and `..`, and with the symlinks of its deepest existing ancestor
+/// resolved, for comparing paths that may not exist yet.
+/// `..` is removed before symlinks are resolved.
+///
+pub fn canonicalize_lossy(path: &Path) -> PathBuf {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                clean.pop();
+            }
+            component => clean.push(component),
+        }
+    }
+    let mut existing = clean.as_path();
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return match clean.strip_prefix(existing) {
+                Ok(rest) => resolved.join(rest),
+                Err(_) => clean.clone(),
+            };
+        }
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return clean.clone(),
+        }
+    }
+}
+
+///
+/// write `content` to a temp file next to `path` and rename it to `path`,
+/// so that readers never see a partially written file
+///
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => {
+            return Err(Error::CopyError(format!(
+                "`{}` is not a file",
+                path.display()
+            )))
+        }
+    };
+    let temp = path.with_file_name(format!(".{}.tasker.tmp", file_name));
+    let written = std::fs::write(&temp, content).and_then(|_| std::fs::rename(&temp, path));
+    match written {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(Error::CopyError(format!(
+                "failed to write `{}`: {}",
+                path.display(),
+                e
+            )))
+        }
+    }
+}
+
+///
+/// cache of values keyed by name, each valid until the associated modification time changes
+///
+pub struct MtimeCache<T> {
+    entries: HashMap<String, (SystemTime, T)>,
+}
+
+impl<T: Clone> MtimeCache<T> {
+    pub fn new() -> MtimeCache<T> {
+        MtimeCache {
+            entries: HashMap::new(),
+        }
+    }
+
+    ///
+    /// return the cached value if `mtime` is unchanged, otherwise compute and cache a new one
+    ///
+    pub fn get_or_update<F>(&mut self, key: &str, mtime: SystemTime, compute: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        if let Some((cached_mtime, value)) = self.entries.get(key) {
+            if *cached_mtime == mtime {
+                return value.clone();
+            }
+        }
+        let value = compute();
+        self.entries.insert(key.to_string(), (mtime, value.clone()));
+        value
+    }
+}
+
+///
+/// lowercase hex SHA-256 of `content`
+///
+pub fn sha256_hex(content: &[u8]) -> String {
+    openssl::sha::sha256(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+///
+/// lowercase hex SHA-256 of a file, `None` if it cannot be read
+///
+pub fn file_sha256(file: &Path) -> Option<String> {
+    std::fs::read(file).ok().map(|content| sha256_hex(&content))
+}
+
+///
+/// compare two byte strings in constant time with respect to their content
+///
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+pub fn try_to_remove_folder(folder_path: &Path) -> Result<(), Error> {
+    if folder_path.metadata().is_ok() {
+        return match std::fs::remove_dir_all(&folder_path) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::FailedToRemoveFolder(
+                "cannot clear folder: ".to_string()
+                    + folder_path.to_str().unwrap_or("unknown folder"),
+            )),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_utils_mod {
+
+    use super::*;
+
+    #[test]
+    fn execute_command_timeout_output() {
+        let output = execute_command_timeout(
+            Command::new("/bin/echo").arg("hello"),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_eq!(output, "hello\n");
+    }
+
+    #[test]
+    fn execute_command_timeout_kill() {
+        let pid_file = Path::new("/tmp/tasker_test_timeout.pid");
+        let _ = std::fs::remove_file(pid_file);
+        let start = Instant::now();
+        let result = execute_command_timeout(
+            Command::new("/bin/sh").args([
+                "-c",
+                "echo $$ > /tmp/tasker_test_timeout.pid; exec /bin/sleep 60",
+            ]),
+            Duration::from_millis(500),
+        );
+        assert!(matches!(result, Err(Error::CommandTimeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // the killed process no longer exists
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        let alive = Command::new("/bin/kill")
+            .args(["-0", pid.trim()])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!alive.success());
+        std::fs::remove_file(pid_file).unwrap();
+    }
+
+    #[test]
+    fn execute_command_timeout_open_output() {
+        // the background sleep keeps stdout open after the shell exits
+        let start = Instant::now();
+        let result = execute_command_timeout(
+            Command::new("/bin/sh").args(["-c", "/bin/sleep 5 & echo started"]),
+            Duration::from_millis(500),
+        );
+        assert!(matches!(result, Err(Error::CommandTimeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn execute_command_timeout_truncate() {
+        let output = execute_command_timeout(
+            Command::new("/bin/sh").args(["-c", "yes | head -c 5000000"]),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert!(output.ends_with("... output truncated at 4194304 bytes"));
+        assert_eq!(
+            output.len(),
+            MAX_COMMAND_OUTPUT_BYTES + "\n... output truncated at 4194304 bytes".len()
+        );
+    }
+
+    fn create_dir_and_file() -> Result<(), Error> {
+        create_dir_check("test")?;
+        create_dir_check("test/test_inner_0")?;
+        create_dir_check("test/test_inner_1")?;
+
+        let mut dir_vec = Vec::new();
+        for d in Path::new("test").read_dir().unwrap() {
+            let d = d.unwrap();
+            dir_vec.push(d);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// this test only pass with root user
+    ///
+    #[test]
+    fn chmod_test() -> Result<(), Error> {
+        create_dir_and_file()?;
+        std::fs::File::create("test/test_inner_1/test.txt").unwrap();
+        chown_by_name_recursive(Path::new("test"), &Some("Congyu WANG".to_string()), &None)?;
+        let uid = users::get_user_by_name("Congyu WANG").unwrap().uid();
+        let gid = users::get_group_by_name("staff").unwrap().gid();
+        assert_eq!(Path::new("test").metadata().unwrap().st_uid(), uid);
+        assert_eq!(Path::new("test").metadata().unwrap().st_gid(), gid);
+        assert_eq!(
+            Path::new("test/test_inner_1/test.txt")
+                .metadata()
+                .unwrap()
+                .st_uid(),
+            uid
+        );
+        assert_eq!(
+            Path::new("test/test_inner_1/test.txt")
+                .metadata()
+                .unwrap()
+                .st_gid(),
+            gid
+        );
+        assert_eq!(
+            Path::new("test/test_inner_1").metadata().unwrap().st_uid(),
+            uid
+        );
+        assert_eq!(
+            Path::new("test/test_inner_1").metadata().unwrap().st_gid(),
+            gid
+        );
+        assert_eq!(
+            Path::new("test/test_inner_0").metadata().unwrap().st_uid(),
+            uid
+        );
+        assert_eq!(
+            Path::new("test/test_inner_0").metadata().unwrap().st_gid(),
+            gid
+        );
+        std::fs::remove_dir_all("test").unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn chown_lossy_test() -> Result<(), Error> {
+        let dir = Path::new("test_chown_lossy");
+        create_dir_check(dir.join("inner"))?;
+        for file in &["gone.txt", "kept.txt", "inner/kept.txt"] {
+            std::fs::write(dir.join(file), file).unwrap();
+        }
+        let user = users::get_current_username().map(|u| u.to_string_lossy().to_string());
+
+        // unknown names are refused before any file is touched
+        let unknown = Some("tasker_no_such_user".to_string());
+        match chown_by_name_recursive_lossy(dir, &unknown, &None) {
+            Err(Error::IllegalUserName(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match chown_by_name(dir, &user, &unknown) {
+            Err(Error::IllegalGroupName(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // a file deleted between listing and chowning is a warning
+        let gone = dir.join("gone.txt");
+        let (changed, warnings) = chown_recursive_lossy_with(dir, &user, &None, &mut |path| {
+            if path == gone {
+                std::fs::remove_file(path).unwrap();
+            }
+        })?;
+        assert_eq!(changed, 4);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, gone);
+        assert!(warnings[0]
+            .to_string()
+            .starts_with("failed to change ownership of `test_chown_lossy/gone.txt`: "));
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn chown_error_response_test() -> Result<(), Error> {
+        let user = users::get_current_username()
+            .map(|u| u.to_string_lossy().to_string())
+            .unwrap();
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join(format!("test_chown_error_{}", user));
+        create_dir_check(&dir)?;
+        let missing = dir.join("missing.txt");
+        let e =
+            chown_by_id(&missing, users::get_current_uid(), users::get_current_gid()).unwrap_err();
+        let absolute = missing.to_string_lossy().to_string();
+        assert!(e.detail().contains(&absolute));
+
+        let body = e.response_body(false);
+        assert!(e.is_hidden());
+        assert!(body.starts_with("FailedToChown("), "{}", body);
+        assert!(!body.contains(&absolute), "{}", body);
+        assert!(!body.contains(&user), "{}", body);
+        // `VERBOSE_ERRORS=1`
+        assert_eq!(e.response_body(true), e.detail());
+
+        // errors about the request itself are returned as they are
+        let e = Error::IllegalLabel("bad label".to_string());
+        assert!(!e.is_hidden());
+        assert_eq!(e.response_body(false), "IllegalLabel(\"bad label\")");
+
+        let warning = ChownWarning {
+            path: missing,
+            reason: "No such file or directory".to_string(),
+        };
+        assert_eq!(
+            warning.relative_to(&dir),
+            "failed to change ownership of `missing.txt`: No such file or directory"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_file_test() -> Result<(), Error> {
+        create_dir_check("test_rotate")?;
+        let log = Path::new("test_rotate/stdout.log");
+        for content in &["first", "second", "third"] {
+            std::fs::write(log, content).unwrap();
+            rotate_file(log, 2)?;
+        }
+        assert_eq!(read_utf8_file(log).unwrap(), "");
+        assert_eq!(read_utf8_file(&rotated_file_name(log, 1)).unwrap(), "third");
+        assert_eq!(
+            read_utf8_file(&rotated_file_name(log, 2)).unwrap(),
+            "second"
+        );
+        assert!(!rotated_file_name(log, 3).exists());
+        std::fs::remove_dir_all("test_rotate").unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn read_last_n_lines_multi_test() -> Result<(), Error> {
+        create_dir_check("test_multi")?;
+        let log = Path::new("test_multi/stdout.log");
+        std::fs::write(rotated_file_name(log, 2), "1\n2\n3\n").unwrap();
+        std::fs::write(rotated_file_name(log, 1), "4\n5\n").unwrap();
+        std::fs::write(log, "6\n").unwrap();
+        let files = vec![
+            log.to_path_buf(),
+            rotated_file_name(log, 1),
+            rotated_file_name(log, 2),
+        ];
+        assert_eq!(read_last_n_lines_multi(&files, 1, "").unwrap(), "6");
+        assert_eq!(
+            read_last_n_lines_multi(&files, 4, "").unwrap(),
+            "3\n4\n5\n6"
+        );
+        assert_eq!(
+            read_last_n_lines_multi(&files, 10, "").unwrap(),
+            "1\n2\n3\n4\n5\n6"
+        );
+        std::fs::remove_dir_all("test_multi").unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn lossy_log_lines() {
+        create_dir_check("test_lossy").unwrap();
+        let log = Path::new("test_lossy/stdout.log");
+        std::fs::write(log, b"first\nbinary \xff\xfe\x00 here\nlast\n").unwrap();
+        let files = vec![log.to_path_buf()];
+        assert_eq!(
+            read_last_n_lines_multi(&files, 10, "").unwrap(),
+            "first\nbinary \u{fffd}\u{fffd}\u{0} here\nlast"
+        );
+        assert_eq!(
+            read_last_n_lines_multi(&files, 10, "\u{fffd}").unwrap(),
+            "binary \u{fffd}\u{fffd}\u{0} here"
+        );
+
+        std::fs::write(log, "one\r\ntwo\r\n\r\nthree").unwrap();
+        assert_eq!(
+            read_last_n_lines_multi(&files, 10, "").unwrap(),
+            "one\ntwo\n\nthree"
+        );
+
+        let mut huge = vec![b'#'; MAX_LOG_LINE_BYTES * 50];
+        huge.extend_from_slice(b"\r\nafter\n");
+        std::fs::write(log, &huge).unwrap();
+        let lines = read_last_n_lines_queue(log, 10, "").unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0].len(),
+            MAX_LOG_LINE_BYTES + TRUNCATED_LINE_MARKER.len()
+        );
+        assert!(lines[0].ends_with("#…"));
+        assert_eq!(lines[1], "after");
+        std::fs::remove_dir_all("test_lossy").unwrap();
+
+        // a line is cut between reads of the underlying reader
+        let reader = BufReader::with_capacity(4, &b"abcdefghij\nk"[..]);
+        let lines: Vec<String> = LossyLines::new(reader, 6).map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["abcdef…", "k"]);
+    }
+
+    #[test]
+    fn log_timestamps() {
+        let base = 1_600_000_000_000;
+        assert_eq!(parse_rfc3339("2020-09-13T12:26:40Z"), Some(base));
+        assert_eq!(
+            parse_rfc3339("2020-09-13T20:26:40.25+08:00"),
+            Some(base + 250)
+        );
+        assert_eq!(parse_rfc3339("2020-09-13t10:26:40-0200"), Some(base));
+        for illegal in &[
+            "2020-09-13 12:26:40",
+            "2020-09-13T12:26:40Z garbage",
+            "2020-13-13T12:26:40Z",
+            "2020-09-13T12:26:40+25:00",
+            "last night",
+            "",
+        ] {
+            assert_eq!(parse_rfc3339(illegal), None, "{}", illegal);
+        }
+        for days in &[0, 11016, 18518, 18627] {
+            let time = format_rfc3339(UNIX_EPOCH + Duration::from_secs(days * 86400)).unwrap();
+            assert_eq!(parse_rfc3339(&time), Some(*days as i64 * 86400 * 1000));
+        }
+
+        let local = parse_log_timestamp("2020-09-13 12:26:40,123 INFO start", 1999).unwrap();
+        assert_eq!(
+            parse_log_timestamp("[2020-09-13T12:26:40] start", 1999),
+            Some(local - 123)
+        );
+        assert_eq!(
+            parse_log_timestamp("Sep 13 12:26:40 host task[42]: start", 2020),
+            Some(local - 123)
+        );
+        assert_eq!(
+            parse_log_timestamp("Sep  3 12:26:40 host task[42]: start", 2020),
+            Some(local - 123 - 10 * 86400 * 1000)
+        );
+        assert_eq!(
+            parse_log_timestamp("2020-09-13T12:26:40Z-ish", 2020),
+            Some(base)
+        );
+        assert_eq!(parse_log_timestamp("    at main.rs:42", 2020), None);
+        assert_eq!(parse_log_timestamp("12:26:40 start", 2020), None);
+    }
 
-        if path.is_file() {
-            zip.start_file_from_path(name, options)?;
-            let mut f = File::open(path)?;
+    #[test]
+    fn lines_in_time_range() {
+        let file = Path::new("test_time_range.log");
+        let lines = [
+            "untimestamped header",
+            "2020-09-13T01:59:59Z before",
+            "    at before.rs:1",
+            "2020-09-13T02:00:00Z panic",
+            "    at main.rs:42",
+            "    at lib.rs:7",
+            "2020-09-13T02:30:00Z ok",
+            "2020-09-13T03:00:00.999Z last",
+            "2020-09-13T03:00:01Z after",
+            "    at after.rs:1",
+        ];
+        std::fs::write(file, lines.join("\n")).unwrap();
+        let since = parse_rfc3339("2020-09-13T02:00:00Z");
+        let until = parse_rfc3339("2020-09-13T03:00:00.999Z");
+        let read = |since, until, pattern: &str, limit| {
+            Vec::from(read_lines_in_time_range(file, since, until, pattern, limit).unwrap())
+        };
+        assert_eq!(read(since, until, "", 100), lines[3..8].to_vec());
+        assert_eq!(read(since, until, "at ", 100), lines[4..6].to_vec());
+        assert_eq!(read(since, until, "", 2), lines[6..8].to_vec());
+        assert_eq!(read(None, since, "", 100), lines[..6].to_vec());
+        assert_eq!(read(until, None, "", 100), lines[7..].to_vec());
+        std::fs::remove_file(file).unwrap();
+    }
 
-            f.read_to_end(&mut buffer)?;
-            zip.write_all(&*buffer)?;
-            buffer.clear();
-        } else if name.as_os_str().len() != 0 {
-            zip.add_directory_from_path(name, options)?;
+    #[test]
+    fn time_range_binary_search() {
+        let file = Path::new("test_time_search.log");
+        let base = parse_rfc3339("2020-09-13T00:00:00Z").unwrap();
+        let mut content = String::new();
+        let mut offsets = Vec::new();
+        for i in 0..50_000 {
+            offsets.push(content.len() as u64);
+            let time = format_rfc3339(UNIX_EPOCH + Duration::from_secs(1_599_955_200 + i));
+            content.push_str(&format!("{} line {}\n", time.unwrap(), i));
+            if i % 10 == 0 {
+                content.push_str(&format!("    at frame.rs:{}\n", i));
+            }
+        }
+        std::fs::write(file, &content).unwrap();
+        let block_bytes = 512;
+        for target in &[1, 777, 25_000, 49_999] {
+            let since = base + target * 1000;
+            let mut f = File::open(file).unwrap();
+            let offset = find_time_offset(&mut f, since, block_bytes, 2020).unwrap();
+            let expected = offsets[*target as usize];
+            assert!(offset < expected && expected - offset <= 3 * block_bytes);
+
+            // the same lines as a full scan
+            let until = Some(since + 20 * 1000);
+            let searched =
+                read_lines_in_time_range_with(file, Some(since), until, "", 100, block_bytes);
+            let scanned = read_lines_in_time_range_with(
+                file,
+                Some(since),
+                until,
+                "",
+                100,
+                content.len() as u64,
+            );
+            let searched = searched.unwrap();
+            assert_eq!(searched, scanned.unwrap());
+            assert!(searched[0].ends_with(&format!("line {}", target)));
         }
+        std::fs::remove_file(file).unwrap();
     }
-    zip.finish()?;
-    Result::Ok(())
-}
 
-pub fn zip_dir(
-    src_dir: &Path,
-    dst_file: &Path,
-    method: zip::CompressionMethod,
-) -> Result<(), Error> {
-    if !src_dir.is_dir() {
-        return Err(Error::ZipFailure("Source Not A Directory".to_string()));
+    fn create_nested_tree(root: &Path) {
+        create_dir_check(root.join("level_1/level_2")).unwrap();
+        std::fs::write(root.join("file_0.txt"), "0").unwrap();
+        std::fs::write(root.join("level_1/file_1.txt"), "1").unwrap();
+        std::fs::write(root.join("level_1/level_2/file_2.txt"), "2").unwrap();
     }
 
-    let file = File::create(dst_file).unwrap();
+    fn assert_nested_tree(root: &Path) {
+        assert_eq!(read_utf8_file(&root.join("file_0.txt")).unwrap(), "0");
+        assert_eq!(
+            read_utf8_file(&root.join("level_1/file_1.txt")).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            read_utf8_file(&root.join("level_1/level_2/file_2.txt")).unwrap(),
+            "2"
+        );
+    }
 
-    let walk_dir = walkdir::WalkDir::new(src_dir);
-    let it = walk_dir.into_iter();
+    #[test]
+    fn move_by_rename_nested_test() -> Result<(), Error> {
+        let from = Path::new("test_move/from");
+        let to = Path::new("test_move/to");
+        create_nested_tree(from);
+        move_by_rename(from, to)?;
+        assert!(!from.exists());
+        assert_nested_tree(to);
+        std::fs::remove_dir_all("test_move").unwrap();
+        Ok(())
+    }
 
-    match zip_inner(&mut it.filter_map(|e| e.ok()), src_dir, file, method) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Error::ZipFailure("failed to compress zip".to_string())),
+    #[test]
+    fn move_by_rename_failure_test() {
+        let from = Path::new("test_move_failure/from");
+        let to = Path::new("test_move_failure/to");
+        create_nested_tree(from);
+        // a non-empty directory in place of a file makes the rename fail
+        create_dir_check(to.join("level_1/level_2/file_2.txt/blocker")).unwrap();
+        assert!(move_by_rename(from, to).is_err());
+        assert_nested_tree(from);
+        std::fs::remove_dir_all("test_move_failure").unwrap();
     }
-}
 
-pub fn try_to_remove_folder(folder_path: &Path) -> Result<(), Error> {
-    if folder_path.metadata().is_ok() {
-        return match std::fs::remove_dir_all(&folder_path) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::FailedToRemoveFolder(
-                "cannot clear folder: ".to_string()
-                    + folder_path.to_str().unwrap_or("unknown folder"),
-            )),
+    #[test]
+    fn copy_folder_test() -> Result<(), Error> {
+        let from = Path::new("test_copy/from");
+        let to = Path::new("test_copy/to");
+        create_nested_tree(from);
+        create_dir_check(from.join("empty"))?;
+        std::os::unix::fs::symlink("level_1/file_1.txt", from.join("link")).unwrap();
+        assert_eq!(copy_folder(from, to, true)?, 4);
+        assert_nested_tree(from);
+        assert_nested_tree(to);
+        assert!(to.join("empty").is_dir());
+        assert_eq!(
+            std::fs::read_link(to.join("link")).unwrap(),
+            Path::new("level_1/file_1.txt")
+        );
+        let mtime_secs = |p: &Path| {
+            let mtime = p.metadata().unwrap().modified().unwrap();
+            mtime.duration_since(UNIX_EPOCH).unwrap().as_secs()
         };
+        assert_eq!(
+            mtime_secs(&to.join("file_0.txt")),
+            mtime_secs(&from.join("file_0.txt"))
+        );
+        std::fs::remove_dir_all("test_copy").unwrap();
+        Ok(())
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod test_utils_mod {
+    #[test]
+    fn junk_files_test() -> Result<(), Error> {
+        let zip_path = Path::new("test_junk.zip");
+        let mut zip = zip::ZipWriter::new(File::create(zip_path).unwrap());
+        let options = FileOptions::default();
+        for dir in &["task/", "__MACOSX/", "__MACOSX/task/"] {
+            zip.add_directory(*dir, options).unwrap();
+        }
+        for file in &[
+            "task/task.yaml",
+            "task/script.py",
+            "task/.DS_Store",
+            "task/._script.py",
+            "task/Thumbs.db",
+            "__MACOSX/task/._task.yaml",
+        ] {
+            zip.start_file(*file, options).unwrap();
+            zip.write_all(b"content").unwrap();
+        }
+        zip.finish().unwrap();
 
-    use super::*;
+        let extracted = Path::new("test_junk");
+        decompress(zip_path, extracted, 1024 * 1024)?;
+        let mut files: Vec<String> = walkdir::WalkDir::new(extracted)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "test_junk",
+                "test_junk/task",
+                "test_junk/task/script.py",
+                "test_junk/task/task.yaml"
+            ]
+        );
 
-    fn create_dir_and_file() -> Result<(), Error> {
-        create_dir_check("test")?;
-        create_dir_check("test/test_inner_0")?;
-        create_dir_check("test/test_inner_1")?;
+        // junk files created after extraction are not zipped
+        std::fs::write(extracted.join("task/.DS_Store"), "junk").unwrap();
+        zip_dir(extracted, zip_path, zip::CompressionMethod::Deflated)?;
+        let mut zip = zip::ZipArchive::new(File::open(zip_path).unwrap()).unwrap();
+        let mut names: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["task/", "task/script.py", "task/task.yaml"]);
 
-        let mut dir_vec = Vec::new();
-        for d in Path::new("test").read_dir().unwrap() {
-            let d = d.unwrap();
-            dir_vec.push(d);
+        std::fs::remove_dir_all(extracted).unwrap();
+        std::fs::remove_file(zip_path).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn zip_non_ascii_names_test() -> Result<(), Error> {
+        let zip_path = Path::new("test_zip_non_ascii.zip");
+        let out_dir = Path::new("test_zip_non_ascii");
+        let mut zip = zip::ZipWriter::new(File::create(zip_path).unwrap());
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.add_directory("タスク 📦/", options).unwrap();
+        zip.start_file("タスク 📦/日本語.py", options).unwrap();
+        zip.write_all(b"print(1)").unwrap();
+        zip.finish().unwrap();
+        decompress(zip_path, out_dir, 1024 * 1024)?;
+        assert_eq!(
+            std::fs::read(out_dir.join("タスク 📦/日本語.py")).unwrap(),
+            b"print(1)"
+        );
+        std::fs::remove_dir_all(out_dir).unwrap();
+
+        // archivers often write UTF-8 names without the UTF-8 flag
+        let mut bytes = std::fs::read(zip_path).unwrap();
+        for (signature, flag_offset) in &[(b"PK\x03\x04", 6), (b"PK\x01\x02", 8)] {
+            let mut i = 0;
+            while i + 4 <= bytes.len() {
+                if &bytes[i..i + 4] == *signature {
+                    bytes[i + flag_offset + 1] &= !(1 << 3);
+                }
+                i += 1;
+            }
         }
+        std::fs::write(zip_path, &bytes).unwrap();
+        let mut zip = zip::ZipArchive::new(File::open(zip_path).unwrap()).unwrap();
+        assert_ne!(zip.by_index(1).unwrap().name(), "タスク 📦/日本語.py");
+        decompress(zip_path, out_dir, 1024 * 1024)?;
+        assert_eq!(
+            std::fs::read(out_dir.join("タスク 📦/日本語.py")).unwrap(),
+            b"print(1)"
+        );
+        std::fs::remove_dir_all(out_dir).unwrap();
 
+        // a name that is not UTF-8 is still checked for `..` after decoding
+        let mut zip = zip::ZipWriter::new(File::create(zip_path).unwrap());
+        zip.start_file("../XX.py", options).unwrap();
+        zip.write_all(b"print(1)").unwrap();
+        zip.finish().unwrap();
+        let bytes = std::fs::read(zip_path).unwrap();
+        let mut patched = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i..].starts_with(b"XX.py") {
+                patched.extend_from_slice(b"\xff\xfe");
+                i += 2;
+            } else {
+                patched.push(bytes[i]);
+                i += 1;
+            }
+        }
+        std::fs::write(zip_path, &patched).unwrap();
+        match decompress(zip_path, out_dir, 1024 * 1024) {
+            Err(Error::DecompressionError(e)) => assert!(e.contains("outside"), "{}", e),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(!out_dir.exists());
+        std::fs::remove_file(zip_path).unwrap();
         Ok(())
     }
 
+    #[test]
+    fn zip_unsafe_paths_test() {
+        let root = Path::new("test_zip_unsafe");
+        create_dir_check(root).unwrap();
+        let zip_path = root.join("task.zip");
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+        // the executable bit is kept
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file("run.sh", options.unix_permissions(0o755))
+            .unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        zip.start_file("level_1/file_1.txt", options.unix_permissions(0o644))
+            .unwrap();
+        zip.write_all(b"1").unwrap();
+        zip.finish().unwrap();
+        let out_dir = root.join("out");
+        extract_archive(&zip_path, &out_dir, 1024 * 1024).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| out_dir.join(path).metadata().unwrap().permissions().mode();
+        assert_eq!(mode("run.sh") & 0o7777, 0o755);
+        assert_eq!(mode("level_1/file_1.txt") & 0o7777, 0o644);
+
+        for unsafe_path in &[
+            "../escaped.txt",
+            "level_1/../../escaped.txt",
+            "/tmp/escaped.txt",
+        ] {
+            let out_dir = root.join("unsafe");
+            let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+            zip.start_file("file_0.txt", options).unwrap();
+            zip.write_all(b"0").unwrap();
+            zip.start_file(*unsafe_path, options).unwrap();
+            zip.write_all(b"!").unwrap();
+            zip.finish().unwrap();
+            match extract_archive(&zip_path, &out_dir, 1024 * 1024) {
+                Err(Error::DecompressionError(e)) => assert!(e.contains("zip"), "{}", e),
+                other => panic!("unexpected {:?} for {}", other, unsafe_path),
+            }
+            assert!(!out_dir.exists());
+            assert!(!root.join("escaped.txt").exists());
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn create_zeros_zip(zip_path: &Path, sizes: &[usize], method: zip::CompressionMethod) {
+        let mut zip = zip::ZipWriter::new(File::create(zip_path).unwrap());
+        let options = FileOptions::default().compression_method(method);
+        for (i, size) in sizes.iter().enumerate() {
+            zip.start_file(format!("zeros_{}", i), options).unwrap();
+            zip.write_all(&vec![0u8; *size]).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
     ///
-    /// this test only pass with root user
+    /// a tar.gz of `(path, mode, content)` entries, a path ending with `/` being a folder.
+    /// Paths are written as they are, so that unsafe paths can be tested.
     ///
+    fn create_tar_gz(tar_gz_path: &Path, entries: &[(&str, u32, &[u8])]) {
+        let gz = flate2::write::GzEncoder::new(
+            File::create(tar_gz_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        for (path, mode, content) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_mode(*mode);
+            header.set_size(content.len() as u64);
+            if path.ends_with('/') {
+                header.set_entry_type(EntryType::Directory);
+            }
+            header.set_cksum();
+            tar.append(&header, *content).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
     #[test]
-    fn chmod_test() -> Result<(), Error> {
-        create_dir_and_file()?;
-        std::fs::File::create("test/test_inner_1/test.txt").unwrap();
-        chown_by_name_recursive(Path::new("test"), &Some("Congyu WANG".to_string()), &None)?;
-        let uid = users::get_user_by_name("Congyu WANG").unwrap().uid();
-        let gid = users::get_group_by_name("staff").unwrap().gid();
-        assert_eq!(Path::new("test").metadata().unwrap().st_uid(), uid);
-        assert_eq!(Path::new("test").metadata().unwrap().st_gid(), gid);
+    fn tar_gz_test() {
+        let root = Path::new("test_tar_gz");
+        create_dir_check(root).unwrap();
+        let tar_gz_path = root.join("task.tar.gz");
+        create_tar_gz(
+            &tar_gz_path,
+            &[
+                ("level_1/", 0o755, b""),
+                ("file_0.txt", 0o644, b"0"),
+                ("level_1/file_1.txt", 0o644, b"1"),
+                // folders are created even if not listed
+                ("level_1/level_2/file_2.txt", 0o644, b"2"),
+                ("level_1/run.sh", 0o4755, b"#!/bin/sh\n"),
+                ("__MACOSX/._file_0.txt", 0o644, b"junk"),
+                ("level_1/.DS_Store", 0o644, b"junk"),
+            ],
+        );
         assert_eq!(
-            Path::new("test/test_inner_1/test.txt")
-                .metadata()
-                .unwrap()
-                .st_uid(),
-            uid
+            ArchiveFormat::from_magic(&tar_gz_path),
+            Some(ArchiveFormat::TarGz)
         );
+        let out_dir = root.join("out");
+        extract_archive(&tar_gz_path, &out_dir, 1024 * 1024).unwrap();
+        assert_nested_tree(&out_dir);
+        assert!(!out_dir.join("__MACOSX").exists());
+        assert!(!out_dir.join("level_1/.DS_Store").exists());
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| out_dir.join(path).metadata().unwrap().permissions().mode();
+        assert_eq!(mode("level_1/run.sh") & 0o7777, 0o755);
+        assert_eq!(mode("file_0.txt") & 0o7777, 0o644);
+
+        for unsafe_path in &[
+            "../escaped.txt",
+            "level_1/../../escaped.txt",
+            "/tmp/escaped.txt",
+        ] {
+            let out_dir = root.join("unsafe");
+            create_tar_gz(
+                &tar_gz_path,
+                &[("file_0.txt", 0o644, b"0"), (unsafe_path, 0o644, b"!")],
+            );
+            match extract_archive(&tar_gz_path, &out_dir, 1024 * 1024) {
+                Err(Error::DecompressionError(e)) => assert!(e.contains("tar.gz"), "{}", e),
+                other => panic!("unexpected {:?} for {}", other, unsafe_path),
+            }
+            assert!(!out_dir.exists());
+            assert!(!root.join("escaped.txt").exists());
+        }
+
+        create_tar_gz(&tar_gz_path, &[("zeros", 0o644, &[0u8; 2048])]);
+        let result = extract_archive(&tar_gz_path, &root.join("large"), 1024);
+        assert!(matches!(result, Err(Error::ZipTooLarge(_))));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn archive_format_test() {
+        for (filename, format) in &[
+            ("task.zip", Some(ArchiveFormat::Zip)),
+            ("task.ZIP", Some(ArchiveFormat::Zip)),
+            ("task.tar.gz", Some(ArchiveFormat::TarGz)),
+            ("task.tgz", Some(ArchiveFormat::TarGz)),
+            ("task.tar", None),
+            ("task.gz.txt", None),
+        ] {
+            assert_eq!(
+                ArchiveFormat::from_filename(filename),
+                *format,
+                "{}",
+                filename
+            );
+        }
+        let zip_path = Path::new("test_archive_format.zip");
+        create_zeros_zip(zip_path, &[1], zip::CompressionMethod::Stored);
         assert_eq!(
-            Path::new("test/test_inner_1/test.txt")
-                .metadata()
-                .unwrap()
-                .st_gid(),
-            gid
+            ArchiveFormat::from_magic(zip_path),
+            Some(ArchiveFormat::Zip)
+        );
+        std::fs::remove_file(zip_path).unwrap();
+        let unknown = Path::new("test_archive_format.txt");
+        std::fs::write(unknown, "Label: task\n").unwrap();
+        assert_eq!(ArchiveFormat::from_magic(unknown), None);
+        assert!(matches!(
+            extract_archive(unknown, Path::new("test_archive_format"), 1024),
+            Err(Error::DecompressionError(_))
+        ));
+        std::fs::remove_file(unknown).unwrap();
+    }
+
+    #[test]
+    fn zip_too_large_test() {
+        let zip_path = Path::new("test_zip_too_large.zip");
+        let out_dir = Path::new("test_zip_too_large");
+        create_zeros_zip(
+            zip_path,
+            &[600 * 1024, 600 * 1024],
+            zip::CompressionMethod::Stored,
         );
+        let result = decompress(zip_path, out_dir, 1024 * 1024);
+        assert!(matches!(result, Err(Error::ZipTooLarge(_))));
+        assert!(!out_dir.exists());
+        decompress(zip_path, out_dir, 2 * 1024 * 1024).unwrap();
         assert_eq!(
-            Path::new("test/test_inner_1").metadata().unwrap().st_uid(),
-            uid
+            out_dir.join("zeros_1").metadata().unwrap().len(),
+            600 * 1024
+        );
+        std::fs::remove_dir_all(out_dir).unwrap();
+        std::fs::remove_file(zip_path).unwrap();
+    }
+
+    #[test]
+    fn zip_understated_size_test() {
+        let zip_path = Path::new("test_zip_understated.zip");
+        let out_dir = Path::new("test_zip_understated");
+        create_zeros_zip(zip_path, &[64 * 1024], zip::CompressionMethod::Deflated);
+        // claim 1 KiB uncompressed in the local (offset 22) and central (offset 24) headers
+        let mut bytes = std::fs::read(zip_path).unwrap();
+        for (signature, offset) in [(b"PK\x03\x04", 22), (b"PK\x01\x02", 24)].iter() {
+            let i = bytes.windows(4).position(|w| w == &signature[..]).unwrap();
+            bytes[i + offset..i + offset + 4].copy_from_slice(&1024u32.to_le_bytes());
+        }
+        std::fs::write(zip_path, &bytes).unwrap();
+        let result = decompress(zip_path, out_dir, 32 * 1024);
+        assert!(matches!(result, Err(Error::ZipTooLarge(_))), "{:?}", result);
+        assert!(out_dir.join("zeros_0").metadata().unwrap().len() <= 32 * 1024 + 1);
+        std::fs::remove_dir_all(out_dir).unwrap();
+        std::fs::remove_file(zip_path).unwrap();
+    }
+
+    #[test]
+    fn zip_compression_ratio_test() {
+        let zip_path = Path::new("test_zip_ratio.zip");
+        let out_dir = Path::new("test_zip_ratio");
+        create_zeros_zip(
+            zip_path,
+            &[16 * 1024 * 1024],
+            zip::CompressionMethod::Deflated,
+        );
+        let result = decompress(zip_path, out_dir, 1024 * 1024 * 1024);
+        assert!(matches!(result, Err(Error::ZipTooLarge(_))));
+        assert!(!out_dir.exists());
+        std::fs::remove_file(zip_path).unwrap();
+    }
+
+    #[test]
+    fn compression_methods_test() {
+        let src = Path::new("test_compression/src");
+        create_nested_tree(src);
+        for name in COMPRESSION_METHODS.iter() {
+            let zip_path = Path::new("test_compression").join(format!("{}.zip", name));
+            let out_dir = Path::new("test_compression").join(name);
+            zip_dir(src, &zip_path, parse_compression_method(name).unwrap()).unwrap();
+            decompress(&zip_path, &out_dir, 1024 * 1024).unwrap();
+            assert_nested_tree(&out_dir);
+        }
+        assert!(matches!(
+            parse_compression_method("lzma"),
+            Err(Error::UnsupportedCompression(_))
+        ));
+        std::fs::remove_dir_all("test_compression").unwrap();
+    }
+
+    #[test]
+    fn is_log_file_test() {
+        let out = Path::new("/tasker/out/label");
+        let logs = vec![out.join("stdout.log"), out.join("stderr.log")];
+        for log in ["stdout.log", "stderr.log", "stdout.log.1", "stderr.log.12"].iter() {
+            assert!(is_log_file(&out.join(log), &logs), "{}", log);
+        }
+        for artifact in [
+            "result.csv",
+            "stdout.log.csv",
+            "stdout.log.",
+            "stdout.logs",
+            "old/stdout.log",
+            "stdout.log.1/result.csv",
+        ]
+        .iter()
+        {
+            assert!(!is_log_file(&out.join(artifact), &logs), "{}", artifact);
+        }
+    }
+
+    #[test]
+    fn zip_dir_filtered_test() {
+        let src = Path::new("test_zip_filtered/src");
+        create_nested_tree(src);
+        std::fs::write(src.join("stdout.log"), "log").unwrap();
+        std::fs::write(src.join("stdout.log.1"), "old log").unwrap();
+        let zip_path = Path::new("test_zip_filtered/artifacts.zip");
+        let out_dir = Path::new("test_zip_filtered/out");
+        let logs = vec![src.join("stdout.log")];
+        zip_dir_filtered(src, zip_path, zip::CompressionMethod::Deflated, |path| {
+            !is_log_file(path, &logs)
+        })
+        .unwrap();
+        decompress(zip_path, out_dir, 1024 * 1024).unwrap();
+        assert_nested_tree(out_dir);
+        assert!(!out_dir.join("stdout.log").exists());
+        assert!(!out_dir.join("stdout.log.1").exists());
+        std::fs::remove_dir_all("test_zip_filtered").unwrap();
+    }
+
+    #[test]
+    fn zip_dir_replace_test() {
+        let src = Path::new("test_zip_replace/src");
+        create_nested_tree(src);
+        let zip_path = Path::new("test_zip_replace/task.zip");
+        std::fs::write(zip_path, "stale zip").unwrap();
+        zip_dir(src, zip_path, zip::CompressionMethod::Deflated).unwrap();
+        let out_dir = Path::new("test_zip_replace/out");
+        decompress(zip_path, out_dir, 1024 * 1024).unwrap();
+        assert_nested_tree(out_dir);
+        // only the zip is left next to the source, no temp file
+        let mut names: Vec<String> = std::fs::read_dir("test_zip_replace")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["out", "src", "task.zip"]);
+        // an error instead of a panic
+        let missing = Path::new("test_zip_replace/missing/task.zip");
+        assert!(matches!(
+            zip_dir(src, missing, zip::CompressionMethod::Deflated),
+            Err(Error::ZipFailure(_))
+        ));
+        std::fs::remove_dir_all("test_zip_replace").unwrap();
+    }
+
+    #[test]
+    fn shell_quote_test() {
+        assert_eq!(shell_quote("/usr/bin/python3"), "/usr/bin/python3");
+        assert_eq!(shell_quote("--token=abc"), "--token=abc");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("hello world"), "'hello world'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("it's"), r#"'it'\''s'"#);
+        assert_eq!(shell_quote("'"), r#"''\'''"#);
+        assert_eq!(shell_quote("\"quoted\""), "'\"quoted\"'");
+        assert_eq!(shell_quote("a;rm -rf /"), "'a;rm -rf /'");
+        assert_eq!(shell_quote("`id`$(id)"), "'`id`$(id)'");
+        assert_eq!(shell_quote(r"back\slash"), r"'back\slash'");
+        assert_eq!(shell_quote("line\nbreak"), "'line\nbreak'");
+        assert_eq!(shell_quote("*.py"), "'*.py'");
+        assert_eq!(shell_quote("~root~/run.sh"), "'~root~/run.sh'");
+    }
+
+    #[test]
+    fn render_test() {
+        let rendered = render(
+            "<a href='{{base_path}}/list_all'>{{name}} {{name}}</a> {{unknown}}",
+            &[("base_path", "/tasker"), ("name", "tasks")],
         );
         assert_eq!(
-            Path::new("test/test_inner_1").metadata().unwrap().st_gid(),
-            gid
+            rendered,
+            "<a href='/tasker/list_all'>tasks tasks</a> {{unknown}}"
         );
+    }
+
+    #[test]
+    fn format_rfc3339_test() {
+        let time = |secs| UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        assert_eq!(format_rfc3339(time(0)).unwrap(), "1970-01-01T00:00:00Z");
         assert_eq!(
-            Path::new("test/test_inner_0").metadata().unwrap().st_uid(),
-            uid
+            format_rfc3339(time(951825599)).unwrap(),
+            "2000-02-29T11:59:59Z"
         );
         assert_eq!(
-            Path::new("test/test_inner_0").metadata().unwrap().st_gid(),
-            gid
+            format_rfc3339(time(1609459199)).unwrap(),
+            "2020-12-31T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn mtime_cache_test() {
+        let mut cache = MtimeCache::new();
+        let mut computed = 0;
+        let old = UNIX_EPOCH;
+        let new = UNIX_EPOCH + std::time::Duration::from_secs(1);
+        assert_eq!(
+            cache.get_or_update("a", old, || {
+                computed += 1;
+                1
+            }),
+            1
+        );
+        assert_eq!(
+            cache.get_or_update("a", old, || {
+                computed += 1;
+                2
+            }),
+            1
+        );
+        assert_eq!(computed, 1);
+        // modification time changed, value is computed again
+        assert_eq!(
+            cache.get_or_update("a", new, || {
+                computed += 1;
+                3
+            }),
+            3
+        );
+        assert_eq!(
+            cache.get_or_update("b", old, || {
+                computed += 1;
+                4
+            }),
+            4
+        );
+        assert_eq!(computed, 3);
+    }
+
+    #[test]
+    fn canonicalize_lossy_test() {
+        let root = Path::new("test_canonicalize_lossy");
+        let _ = std::fs::remove_dir_all(root);
+        create_dir_check(root.join("real")).unwrap();
+        std::os::unix::fs::symlink("real", root.join("link")).unwrap();
+        let real = root.join("real").canonicalize().unwrap();
+        assert_eq!(
+            canonicalize_lossy(&root.join("link/a/../b")),
+            real.join("b")
+        );
+        assert_eq!(
+            canonicalize_lossy(&root.join("./real/x/y")),
+            real.join("x/y")
+        );
+        assert_eq!(
+            canonicalize_lossy(Path::new("/no/such/../dir")),
+            Path::new("/no/dir")
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn resolve_inside_test() {
+        let root = Path::new("test_resolve/task");
+        create_nested_tree(root);
+        create_dir_check("test_resolve/outside").unwrap();
+        std::fs::write("test_resolve/outside/secret.txt", "secret").unwrap();
+        std::os::unix::fs::symlink("../outside", root.join("escape_dir")).unwrap();
+        std::os::unix::fs::symlink("../outside/secret.txt", root.join("escape_file")).unwrap();
+        std::os::unix::fs::symlink("level_1/file_1.txt", root.join("inner_link")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent/target", root.join("dangling")).unwrap();
+        let canonical_root = root.canonicalize().unwrap();
+
+        // paths inside the task folder, existing or not
+        for (relative, expected) in [
+            ("file_0.txt", "file_0.txt"),
+            ("./level_1/file_1.txt", "level_1/file_1.txt"),
+            ("level_1/level_2", "level_1/level_2"),
+            ("inner_link", "level_1/file_1.txt"),
+            ("new.txt", "new.txt"),
+            ("new_dir/new.txt", "new_dir/new.txt"),
+            ("level_1/new_dir/new.txt", "level_1/new_dir/new.txt"),
+        ]
+        .iter()
+        {
+            let resolved = resolve_inside(root, relative).unwrap();
+            assert_eq!(resolved, canonical_root.join(expected), "{}", relative);
+            assert!(!resolved.to_string_lossy().ends_with('/'), "{}", relative);
+        }
+        assert!(std::fs::metadata(resolve_inside(root, "file_0.txt").unwrap()).is_ok());
+
+        // traversal, absolute paths, and symlink escapes
+        for relative in [
+            "",
+            "..",
+            "../outside/secret.txt",
+            "level_1/../../outside/secret.txt",
+            "level_1/../file_0.txt",
+            "/etc/passwd",
+            "escape_dir",
+            "escape_dir/secret.txt",
+            "escape_dir/new.txt",
+            "escape_file",
+            "dangling",
+            "dangling/new.txt",
+        ]
+        .iter()
+        {
+            match resolve_inside(root, relative) {
+                Err(Error::PathEscapesTask(_)) => {}
+                other => panic!("`{}` resolved to {:?}", relative, other),
+            }
+        }
+        std::fs::remove_dir_all("test_resolve").unwrap();
+    }
+
+    #[test]
+    fn sha256_test() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let file = Path::new("test_sha256.txt");
+        std::fs::write(file, b"abc").unwrap();
+        assert_eq!(file_sha256(file), Some(sha256_hex(b"abc")));
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(file_sha256(file), None);
+    }
+
+    #[test]
+    fn write_atomic_test() {
+        let folder = Path::new("test_write_atomic");
+        create_dir_check(folder).unwrap();
+        let file = folder.join("run.sh");
+        write_atomic(&file, b"echo 1").unwrap();
+        write_atomic(&file, b"echo 2").unwrap();
+        assert_eq!(read_utf8_file(&file).unwrap(), "echo 2");
+        let entries: Vec<_> = std::fs::read_dir(folder).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn unified_diff_same() {
+        assert_eq!(unified_diff("a\nb\nc", "a\nb\nc", "old", "new", 3), "");
+    }
+
+    #[test]
+    fn unified_diff_change() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10";
+        let expected = String::new()
+            + "--- old\n"
+            + "+++ new\n"
+            + "@@ -2,8 +2,9 @@\n"
+            + " 2\n"
+            + " 3\n"
+            + " 4\n"
+            + "-5\n"
+            + "+five\n"
+            + " 6\n"
+            + " 7\n"
+            + " 8\n"
+            + " 9\n"
+            + "+10";
+        assert_eq!(unified_diff(old, new, "old", "new", 3), expected);
+    }
+
+    #[test]
+    fn unified_diff_separate_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8";
+        let new = "one\n2\n3\n4\n5\n6\n7\neight";
+        let expected = String::new()
+            + "--- old\n"
+            + "+++ new\n"
+            + "@@ -1,2 +1,2 @@\n"
+            + "-1\n"
+            + "+one\n"
+            + " 2\n"
+            + "@@ -7,2 +7,2 @@\n"
+            + " 7\n"
+            + "-8\n"
+            + "+eight";
+        assert_eq!(unified_diff(old, new, "old", "new", 1), expected);
+    }
+
+    #[test]
+    fn strip_ansi_color_codes() {
+        assert_eq!(
+            strip_ansi_codes("\x1b[31merror\x1b[0m: \x1b[1;32mok\x1b[m"),
+            "error: ok"
+        );
+        assert_eq!(strip_ansi_codes("\x1b[38;5;208morange\x1b[39m"), "orange");
+        assert_eq!(
+            strip_ansi_codes("plain text, ünïcode"),
+            "plain text, ünïcode"
+        );
+    }
+
+    #[test]
+    fn strip_ansi_cursor_movements() {
+        // a progress bar redrawing its line
+        assert_eq!(
+            strip_ansi_codes("\x1b[2K\x1b[1A\x1b[10;20H50%\x1b[?25l done\x1b[?25h"),
+            "50% done"
+        );
+        // charset selection and cursor save/restore
+        assert_eq!(strip_ansi_codes("\x1b(Bsaved\x1b7 \x1b8here"), "saved here");
+    }
+
+    #[test]
+    fn strip_ansi_window_titles() {
+        assert_eq!(strip_ansi_codes("\x1b]0;building\x07step 1"), "step 1");
+        assert_eq!(
+            strip_ansi_codes("\x1b]2;title with \x1b[31m inside\x1b\\step 2"),
+            "step 2"
+        );
+        assert_eq!(
+            strip_ansi_codes("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"),
+            "link"
+        );
+    }
+
+    #[test]
+    fn strip_ansi_only_escapes() {
+        assert_eq!(strip_ansi_codes("\x1b[0m\x1b[2J\x1b[H\x1b]0;t\x07"), "");
+        assert_eq!(strip_ansi_codes(""), "");
+        // sequences cut off at the end of a line
+        assert_eq!(strip_ansi_codes("truncated\x1b[31"), "truncated");
+        assert_eq!(strip_ansi_codes("truncated\x1b]0;tit"), "truncated");
+        assert_eq!(strip_ansi_codes("truncated\x1b"), "truncated");
+    }
+
+    #[test]
+    fn render_ansi_colors() {
+        assert_eq!(
+            render_ansi_html("\x1b[1;31m<b>fail</b>\x1b[0m & \x1b[42mok\x1b[49m\x1b[K."),
+            "<span style=\"color:#cd0000;font-weight:bold\">&lt;b&gt;fail&lt;/b&gt;</span> \
+             &amp; <span style=\"background-color:#00cd00\">ok</span>."
+        );
+        // 256 colors are not rendered, nor mistaken for basic colors
+        assert_eq!(render_ansi_html("\x1b[38;5;31mblue\x1b[0m"), "blue");
+        assert_eq!(render_ansi_html("\x1b[91m\x1b[0m"), "");
+        assert_eq!(
+            render_ansi_html("\x1b[94m\"open"),
+            "<span style=\"color:#5c5cff\">&quot;open</span>"
         );
-        std::fs::remove_dir_all("test").unwrap();
-        Ok(())
     }
 }
@@ -0,0 +1,50 @@
+//!
+//! the config layer alone, which also builds with `--no-default-features`
+//!
+use tasker::config::{Configuration, Validation};
+use tasker::error::Error;
+
+fn remote_yaml() -> String {
+    String::new()
+        + "---\n"
+        + "Label: remote_task\n"
+        + "Program: /opt/remote/bin/task\n"
+        + "Configuration:\n"
+        + "  - UserName: nobody_on_this_machine\n"
+        + "  - GroupName: no_such_group\n"
+        + "  - WorkingDirectory: /opt/remote/work\n"
+        + "  - StandardOutPath: /opt/remote/logs/out.log\n"
+        + "  - StartInterval: 60"
+}
+
+#[test]
+fn lenient_yaml_to_plist() {
+    let config = Configuration::from_yaml_with(&remote_yaml(), Validation::Lenient).unwrap();
+    assert_eq!(config.label, "com.tasker.tasks.remote_task");
+    assert_eq!(
+        config.user_name().as_deref(),
+        Some("nobody_on_this_machine")
+    );
+    let plist = config.to_plist();
+    assert!(plist.contains("<string>/opt/remote/bin/task</string>"));
+    assert!(plist.contains("<string>/opt/remote/work</string>"));
+    assert!(plist.contains("<key>StartInterval</key>"));
+}
+
+#[test]
+fn strict_checks_local_machine() {
+    match Configuration::from_yaml(&remote_yaml()) {
+        Err(Error::ConfigProgramError(_)) => {}
+        other => panic!("expected ConfigProgramError, got {:?}", other),
+    }
+}
+
+#[test]
+fn lenient_still_checks_shape() {
+    let relative = remote_yaml().replace("/opt/remote/bin/task", "bin/task");
+    assert!(Configuration::from_yaml_with(&relative, Validation::Lenient).is_err());
+    let range = remote_yaml().replace("StartInterval: 60", "StartInterval: -1");
+    assert!(Configuration::from_yaml_with(&range, Validation::Lenient).is_err());
+    let label = remote_yaml().replace("remote_task", "remote task");
+    assert!(Configuration::from_yaml_with(&label, Validation::Lenient).is_err());
+}
@@ -0,0 +1,2129 @@
+#[macro_use]
+extern crate lazy_static;
+
+use actix_web::dev::Service;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use actix_web::HttpServer;
+use std::io::{Cursor, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex, MutexGuard, Once, RwLock};
+use std::time::Duration;
+use tasker::concurrency::ConcurrencyLimiter;
+use tasker::confirmation::Confirmations;
+use tasker::initialize::{self, Env};
+use tasker::launchctl_backend::{self, LaunchctlBackend, MockLaunchctl};
+use tasker::maintenance::MaintenanceState;
+use tasker::manual_run::ManualRuns;
+use tasker::rate_limit::RateLimiter;
+use tasker::server;
+use tasker::timing::{RequestMetrics, REQUEST_ID_HEADER, RESPONSE_TIME_HEADER};
+
+static TOKEN: &str = "integration_test_token_0123456789";
+static LABEL: &str = "com.tasker.tasks.integration_test";
+static BOUNDARY: &str = "tasker_integration_test_boundary";
+static INIT: Once = Once::new();
+
+lazy_static! {
+    /// launchd of all tests, which starts no process
+    static ref LAUNCHD: Arc<MockLaunchctl> = Arc::new(MockLaunchctl::default());
+    /// held by the tests that create `LABEL`
+    static ref TASK: Mutex<()> = Mutex::new(());
+}
+
+///
+/// point `Env` to a temporary `TASKER_ROOT` and `PLIST_DIR` before it is first used,
+/// and run launchctl commands against `LAUNCHD`
+///
+fn init_env() {
+    INIT.call_once(|| {
+        let root = std::env::temp_dir().join("tasker_integration_test");
+        std::env::set_var("TASKER_ROOT", root);
+        let plist_dir = std::env::temp_dir().join("tasker_integration_test_plists");
+        std::env::set_var("PLIST_DIR", plist_dir);
+        launchctl_backend::set_backend(LAUNCHD.clone());
+        std::env::set_var("USERNAME", "tasker_user");
+        std::env::set_var("PASSWORD", "tasker_password");
+        std::env::set_var("API_TOKENS", TOKEN);
+        let socket = std::env::temp_dir().join("tasker_integration_test_socket/tasker.sock");
+        std::env::set_var("UNIX_SOCKET", socket);
+        std::env::set_var("UNIX_SOCKET_MODE", "600");
+    });
+}
+
+///
+/// one test at a time creates `LABEL`, even after another one failed
+///
+fn task_lock() -> MutexGuard<'static, ()> {
+    TASK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn limiter() -> Arc<Mutex<RateLimiter>> {
+    Arc::new(Mutex::new(RateLimiter::new(600)))
+}
+
+fn confirmations() -> Arc<Mutex<Confirmations>> {
+    Confirmations::shared()
+}
+
+///
+/// off and not loaded from `meta`, so that tests enabling it do not affect each other
+///
+fn maintenance() -> Arc<RwLock<MaintenanceState>> {
+    Arc::new(RwLock::new(MaintenanceState::default()))
+}
+
+fn manual_runs() -> Arc<Mutex<ManualRuns>> {
+    ManualRuns::shared()
+}
+
+fn metrics() -> Arc<Mutex<RequestMetrics>> {
+    RequestMetrics::shared()
+}
+
+fn concurrency() -> Arc<ConcurrencyLimiter> {
+    ConcurrencyLimiter::shared()
+}
+
+fn get(uri: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+}
+
+fn post(uri: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+}
+
+fn task_yaml(start_interval: i64) -> String {
+    task_yaml_with(start_interval, "hello", false)
+}
+
+fn task_yaml_with(start_interval: i64, argument: &str, template: bool) -> String {
+    String::new()
+        + "---\n"
+        + "Label: integration_test\n"
+        + "Program: /bin/echo\n"
+        + &format!("Template: {}\n", template)
+        + "Configuration:\n"
+        + "  - ProgramArguments:\n"
+        + "      - /bin/echo\n"
+        + &format!("      - \"{}\"\n", argument)
+        + &format!("  - StartInterval: {}", start_interval)
+}
+
+///
+/// a zip of the task yaml, wrapped in a multipart form
+///
+fn task_upload() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("integration_test.yaml", zip::write::FileOptions::default())
+        .unwrap();
+    zip.write_all(task_yaml(60).as_bytes()).unwrap();
+    upload("task.zip", zip.finish().unwrap().into_inner())
+}
+
+///
+/// create and load `LABEL` from `task_upload()`
+///
+macro_rules! create_task {
+    ($app:expr) => {{
+        let request = post("/")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .set_payload(task_upload());
+        let response = test::call_service($app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }};
+}
+
+///
+/// unload and delete a task in one step
+///
+macro_rules! delete_task {
+    ($app:expr, $label:expr) => {{
+        let request = get(&format!("/delete?label={}&force=true", $label));
+        let response = test::call_service($app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }};
+}
+
+#[test]
+fn unauthorized_request() {
+    init_env();
+    actix_web::rt::System::new("test_unauthorized").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = test::TestRequest::get()
+            .uri("/list_raw_json?label=")
+            .to_request();
+        let error = app.call(request).await.err().unwrap();
+        let response = error.as_response_error().error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // rejected requests are timed too
+        assert!(response.headers().contains_key(RESPONSE_TIME_HEADER));
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+    });
+}
+
+#[test]
+fn request_metrics() {
+    init_env();
+    actix_web::rt::System::new("test_request_metrics").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = get("/health")
+            .header(REQUEST_ID_HEADER, "trace-42")
+            .to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "trace-42"
+        );
+        assert!(response.headers().contains_key(RESPONSE_TIME_HEADER));
+        let body = test::read_body(response).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["launchctl"]["concurrency"], 2);
+        assert_eq!(body["concurrency"]["get_task_zip"]["limit"], 2);
+        assert_eq!(body["concurrency"]["create_new_tasks"]["running"], 0);
+        assert!(body["launchctl"]["pending"].is_u64());
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["settings"]["passed"], true);
+        // backups are not scheduled without `BACKUP_INTERVAL_HOURS`
+        assert_eq!(body["backup"]["scheduled"], false);
+        // no SSL is configured
+        assert_eq!(body["tls"]["enabled"], false);
+        assert!(body["tls"]["expires_at"].is_null());
+
+        let response = test::call_service(&mut app, get("/metrics").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(
+            body.contains("tasker_request_duration_ms_count{method=\"GET\",route=\"/health\"} 1")
+        );
+        assert!(body.contains("# TYPE tasker_launchctl_in_flight gauge\n"));
+        assert!(body.contains("# TYPE tasker_launchctl_pending gauge\n"));
+    });
+}
+
+#[test]
+fn shutdown_requires_auth() {
+    init_env();
+    actix_web::rt::System::new("test_shutdown").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = test::TestRequest::post().uri("/shutdown").to_request();
+        let error = app.call(request).await.err().unwrap();
+        let response = error.as_response_error().error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // no server is registered in tests, so nothing is stopped
+        let response = test::call_service(&mut app, post("/shutdown").to_request()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // nothing to reload without SSL
+        let response = test::call_service(&mut app, post("/reload_tls").to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    });
+}
+
+#[test]
+fn self_check() {
+    init_env();
+    let env = Env::get();
+    let results = initialize::self_check();
+    let result = |name: &str| results.iter().find(|r| r.name == name).unwrap();
+    assert!(result("environment").passed);
+    for folder in &[
+        &env.tasker_root,
+        &env.meta_dir,
+        &env.task_dir,
+        &env.out_dir,
+        &env.plist_dir,
+        &env.agent_plist_dir,
+    ] {
+        let name = format!("write access to {}", folder.display());
+        assert!(result(&name).passed);
+    }
+    // answered by `LAUNCHD`
+    assert!(result("launchctl list").passed);
+    // no SSL is configured
+    assert!(results.iter().all(|r| r.name != "ssl"));
+
+    let config = env.effective_config();
+    let value = |name: &str| &config.iter().find(|(n, _)| *n == name).unwrap().1;
+    assert_eq!(value("USERNAME"), "tasker_user");
+    assert!(!value("PASSWORD").contains("tasker_password"));
+    assert!(!value("API_TOKENS").contains(TOKEN));
+    assert_eq!(value("UNIX_SOCKET_MODE"), "600");
+    assert_eq!(value("SMTP_HOST"), "(not set)");
+    assert!(value("PLIST_DIR").ends_with("tasker_integration_test_plists"));
+}
+
+#[test]
+fn unix_socket() {
+    init_env();
+    let env = Env::get();
+    let socket = env.unix_socket.clone().unwrap();
+    assert_eq!(env.address(), format!("unix:{}", socket.display()));
+
+    // a stale socket from a previous run is replaced
+    env.prepare_unix_socket().unwrap();
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    env.prepare_unix_socket().unwrap();
+    assert!(!socket.exists());
+
+    let server_socket = socket.clone();
+    std::thread::spawn(move || {
+        let system = actix_web::rt::System::new("test_unix_socket");
+        let limiter = limiter();
+        let server = HttpServer::new(move || {
+            server::build_app(
+                limiter.clone(),
+                confirmations(),
+                maintenance(),
+                manual_runs(),
+                metrics(),
+                concurrency(),
+            )
+        })
+        .bind_uds(&server_socket)
+        .unwrap();
+        Env::get().secure_unix_socket().unwrap();
+        server.run();
+        system.run()
+    });
+
+    let mut stream = None;
+    for _ in 0..50 {
+        if let Ok(s) = UnixStream::connect(&socket) {
+            stream = Some(s);
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let mut stream = stream.expect("server did not listen on unix socket");
+
+    stream
+        .write_all(
+            b"GET /list_raw_json?label= HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 401"));
+
+    // the server runs after the socket is secured
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+///
+/// `file` wrapped in a multipart form as `filename`
+///
+fn upload(filename: &str, file: Vec<u8>) -> Vec<u8> {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n",
+        BOUNDARY, filename
+    )
+    .into_bytes();
+    body.extend(file);
+    body.extend(format!("\r\n--{}--\r\n", BOUNDARY).into_bytes());
+    body
+}
+
+#[test]
+fn upload_must_be_archive() {
+    init_env();
+    actix_web::rt::System::new("test_upload_format").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = post("/")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .set_payload(upload("task.yaml", task_yaml(60).into_bytes()));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(response).await;
+        assert_eq!(body, "not a zip or tar.gz file");
+    });
+}
+
+#[test]
+fn upload_with_unknown_user() {
+    init_env();
+    actix_web::rt::System::new("test_upload_unknown_user").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let yaml = task_yaml(60).replace("Label: integration_test", "Label: unknown_user_test")
+            + "\n  - UserName: tasker_no_such_user";
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("unknown_user_test.yaml", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(yaml.as_bytes()).unwrap();
+        let request = post("/")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .set_payload(upload("task.zip", zip.finish().unwrap().into_inner()));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body)
+            .contains("ConfigUnknownUser(\"user `tasker_no_such_user` does not exist"));
+        let env = Env::get();
+        assert!(!env
+            .meta_dir
+            .join("com.tasker.tasks.unknown_user_test.yaml")
+            .exists());
+        assert!(!env
+            .task_dir
+            .join("com.tasker.tasks.unknown_user_test")
+            .exists());
+    });
+}
+
+#[test]
+fn tar_gz_upload() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_tar_gz_upload").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        let yaml = task_yaml(60);
+        let mut entry = tar::Header::new_gnu();
+        entry.set_size(yaml.len() as u64);
+        entry.set_mode(0o644);
+        entry.set_cksum();
+        tar.append_data(&mut entry, "task/integration_test.yaml", yaml.as_bytes())
+            .unwrap();
+        let tar_gz = tar.into_inner().unwrap().finish().unwrap();
+
+        // told by its first bytes, despite its name
+        let request = post("/")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .set_payload(upload("task.bin", tar_gz));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+#[test]
+fn unsupported_compression() {
+    init_env();
+    actix_web::rt::System::new("test_compression").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = get(&format!("/get_task_zip?label={}&compression=lzma", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("stored, deflate, bzip2"));
+    });
+}
+
+#[test]
+fn openapi_document() {
+    init_env();
+    actix_web::rt::System::new("test_openapi").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let response = test::call_service(&mut app, get("/openapi.json").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(doc["paths"]["/list_raw_json"]["get"].is_object());
+        assert!(doc["components"]["schemas"]["TaskInfo"].is_object());
+        assert!(doc["paths"]["/yaml_schema"]["get"].is_object());
+
+        let response = test::call_service(&mut app, get("/yaml_schema").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let schema: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["Configuration", "Label", "Program"])
+        );
+        assert!(schema["$defs"]["Config"]["oneOf"].is_array());
+    });
+}
+
+#[test]
+fn lint_report() {
+    init_env();
+    actix_web::rt::System::new("test_lint_report").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let label = "com.tasker.tasks.lint_report_test";
+        let yaml_file = Env::get().meta_dir.join(format!("{}.yaml", label));
+        std::fs::write(
+            &yaml_file,
+            "Label: lint_report_test\nProgram: /no/such/program\n",
+        )
+        .unwrap();
+
+        let request = get("/lint_report?problems_only=true");
+        let body = test::read_response(&mut app, request.to_request()).await;
+        std::fs::remove_file(&yaml_file).unwrap();
+        let reports: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let report = reports
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|report| report["label"] == label)
+            .unwrap();
+        assert_eq!(report["parse_error"], serde_json::Value::Null);
+        assert!(report["validation_error"]
+            .as_str()
+            .unwrap()
+            .contains("/no/such/program"));
+    });
+}
+
+#[test]
+fn conflicts() {
+    init_env();
+    actix_web::rt::System::new("test_conflicts").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let labels = ["com.tasker.tasks.conflict_1", "com.tasker.tasks.conflict_2"];
+        let yaml_files: Vec<_> = labels
+            .iter()
+            .map(|label| {
+                let yaml_file = Env::get().meta_dir.join(format!("{}.yaml", label));
+                let yaml = format!(
+                    "Label: {}\nProgram: /bin/echo\nConfiguration:\n  \
+                     - ProgramArguments: [/bin/echo, tasker_conflict_test]\n",
+                    label
+                );
+                std::fs::write(&yaml_file, yaml).unwrap();
+                yaml_file
+            })
+            .collect();
+
+        let body = test::read_response(&mut app, get("/conflicts").to_request()).await;
+        let conflicts: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let request = get("/lint_report?problems_only=true");
+        let body = test::read_response(&mut app, request.to_request()).await;
+        for yaml_file in &yaml_files {
+            std::fs::remove_file(yaml_file).unwrap();
+        }
+        let conflict = conflicts
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|conflict| conflict["labels"] == serde_json::json!(labels))
+            .unwrap();
+        assert_eq!(conflict["kind"], "same_command");
+        assert!(conflict["detail"]
+            .as_str()
+            .unwrap()
+            .ends_with("both run `/bin/echo tasker_conflict_test`"));
+        let reports: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        for label in &labels {
+            let report = reports
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|report| report["label"] == *label)
+                .unwrap();
+            assert_eq!(report["conflicts"][0], conflict["detail"]);
+        }
+    });
+}
+
+#[test]
+fn disk_usage() {
+    init_env();
+    actix_web::rt::System::new("test_disk_usage").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = get("/disk_usage?top=1");
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let usage: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let categories = ["meta_bytes", "trash_bytes"]
+            .iter()
+            .map(|key| usage[key].as_u64().unwrap())
+            .sum::<u64>()
+            + usage["tasks"]["bytes"].as_u64().unwrap()
+            + usage["out"]["bytes"].as_u64().unwrap();
+        assert_eq!(usage["total_bytes"].as_u64().unwrap(), categories);
+        assert!(usage["tasks"]["labels"].as_array().unwrap().len() <= 1);
+        assert!(usage["available_bytes"].as_u64().is_some());
+        assert_eq!(usage["partial"], false);
+    });
+}
+
+#[test]
+fn trash_listing() {
+    init_env();
+    actix_web::rt::System::new("test_trash_listing").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let entry = Env::get().trash_dir.join("com.tasker.tasks.trash_listing");
+        std::fs::create_dir_all(&entry).unwrap();
+        std::fs::write(entry.join("run.sh"), "echo").unwrap();
+        std::fs::write(
+            entry.join("deleted_at.json"),
+            r#"{"label":"com.tasker.tasks.trash_listing","deleted_at":"2020-09-13T12:26:40Z"}"#,
+        )
+        .unwrap();
+
+        for sort in &["", "?sort=age", "?sort=size"] {
+            let request = get(&format!("/trash{}", sort));
+            let body = test::read_response(&mut app, request.to_request()).await;
+            let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let listed = entries
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|e| e["name"] == "com.tasker.tasks.trash_listing")
+                .unwrap();
+            assert_eq!(listed["label"], "com.tasker.tasks.trash_listing");
+            assert_eq!(listed["deleted_at"], "2020-09-13T12:26:40Z");
+            assert_eq!(listed["estimated"], false);
+            assert_eq!(listed["has_yaml"], false);
+            assert_eq!(listed["live_task_exists"], false);
+            assert!(listed["bytes"].as_u64().unwrap() > 4);
+        }
+
+        let request = get("/trash?sort=name");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        std::fs::remove_dir_all(entry).unwrap();
+    });
+}
+
+#[test]
+fn list_text_params() {
+    init_env();
+    actix_web::rt::System::new("test_list_text_params").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        for (uri, error) in &[
+            ("/list_text?status=sleeping", "UnknownStatus"),
+            ("/list_text?label=(&match=regex", "InvalidLabelPattern"),
+            ("/list_text?width=wide", "Query deserialize error"),
+        ] {
+            let response = test::call_service(&mut app, get(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains(error));
+        }
+    });
+}
+
+#[test]
+fn validate_label() {
+    init_env();
+    actix_web::rt::System::new("test_validate_label").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let taken = Env::get()
+            .meta_dir
+            .join("com.tasker.tasks.wizard_taken.yaml");
+        std::fs::write(&taken, "Label: wizard_taken\nProgram: /bin/sh\n").unwrap();
+
+        // with or without the prefix
+        for label in &["wizard.backup_1", "com.tasker.tasks.wizard.backup_1"] {
+            let request = get(&format!("/validate/label?label={}", label));
+            let body = test::read_response(&mut app, request.to_request()).await;
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["label"], "com.tasker.tasks.wizard.backup_1");
+            assert_eq!(body["short_label"], "wizard.backup_1");
+        }
+
+        let too_long = "a".repeat(201);
+        for (label, error) in &[
+            ("wizard-backup", "ConfigLabelError"),
+            ("wizard..backup", "ConfigLabelError"),
+            ("", "ConfigLabelError"),
+            (too_long.as_str(), "longer than 200 bytes"),
+        ] {
+            let request = get(&format!("/validate/label?label={}", label));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", label);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains(error), "{}", label);
+        }
+
+        let request = get("/validate/label?label=wizard_taken");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        std::fs::remove_file(&taken).unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("TaskAlreadyExists"));
+    });
+}
+
+#[test]
+fn validate_program() {
+    init_env();
+    actix_web::rt::System::new("test_validate_program").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = get("/validate/program?program=/bin/sh");
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["program"], "/bin/sh");
+        assert_eq!(body["owner"], "root");
+        assert_eq!(body["mode"], "755");
+
+        let not_executable = std::env::temp_dir().join("tasker_validate_program.sh");
+        std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&not_executable, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let not_executable = not_executable.display().to_string();
+        for (program, error) in &[
+            ("bin/sh", "is not an absolute path"),
+            ("/no/such/program", "is not found"),
+            ("/bin", "is not found"),
+            (not_executable.as_str(), "is not executable (mode 644)"),
+        ] {
+            let request = get(&format!("/validate/program?program={}", program));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", program);
+            let body = test::read_body(response).await;
+            assert!(
+                String::from_utf8_lossy(&body).contains(error),
+                "{}",
+                program
+            );
+        }
+        std::fs::remove_file(&not_executable).unwrap();
+    });
+}
+
+#[test]
+fn validate_config_entry() {
+    init_env();
+    actix_web::rt::System::new("test_validate_config_entry").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = post("/validate/config_entry").set_payload("StartInterval:   3600");
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert_eq!(body, "---\nStartInterval: 3600");
+
+        for (entry, error) in &[
+            ("StartInterval: [", "YamlError"),
+            ("NoSuchConfig: 1", "YamlError"),
+            ("Label: backup", "InvalidConfigPatch"),
+            ("StartCalendarInterval:\n  - Minute: 60", "ConfigRangeError"),
+        ] {
+            let request = post("/validate/config_entry").set_payload(*entry);
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", entry);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains(error), "{}", entry);
+        }
+    });
+}
+
+#[test]
+fn test_notification_without_notifiers() {
+    init_env();
+    actix_web::rt::System::new("test_notification").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let request = post("/test_notification");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        assert_eq!(body, "[]");
+    });
+}
+
+#[test]
+fn maintenance_mode() {
+    init_env();
+    actix_web::rt::System::new("test_maintenance").block_on(async {
+        let state = maintenance();
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            state.clone(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let enable = serde_json::json!({"enabled": true, "message": "upgrading until 5pm"});
+        let request = post("/maintenance").set_json(&enable).to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.read().unwrap().enabled);
+        let persisted = Env::get().meta_dir.join("maintenance.json");
+        assert!(MaintenanceState::load(&persisted).enabled);
+
+        let label = "com.tasker.tasks.no_such_task";
+        let mutations = vec![
+            get(&format!("/delete?label={}", label)),
+            get(&format!("/load?label={}", label)),
+            get(&format!("/unload?label={}", label)),
+            get(&format!("/stop?label={}&signal=INT", label)),
+            get(&format!("/restart?label={}", label)),
+            get(&format!("/rotate_logs?label={}", label)),
+            post(&format!("/post_yaml?label={}", label)).set_payload(task_yaml(60)),
+            post("/").set_payload(task_upload()),
+            post(&format!("/update_task?label={}", label)).set_payload(task_upload()),
+            post(&format!("/file?label={}&path=run.sh", label)).set_payload("echo"),
+            test::TestRequest::delete()
+                .uri(&format!("/artifact?label={}&name=result.csv", label))
+                .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN)),
+        ];
+        for request in mutations {
+            let error = app.call(request.to_request()).await.err().unwrap();
+            let response = error.as_response_error().error_response();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "300");
+        }
+
+        // authentication comes first
+        let request = test::TestRequest::post().uri("/").to_request();
+        let error = app.call(request).await.err().unwrap();
+        let response = error.as_response_error().error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // reads are unaffected, and the health check does not fail
+        let response = test::call_service(&mut app, get("/openapi.json").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/get_yaml?label={}", label)).to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = test::call_service(&mut app, get("/health").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["maintenance"], true);
+        let response = test::call_service(&mut app, get("/maintenance").to_request()).await;
+        let body = test::read_body(response).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "upgrading until 5pm");
+
+        let disable = serde_json::json!({"enabled": false});
+        let request = post("/maintenance").set_json(&disable).to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!MaintenanceState::load(&persisted).enabled);
+        let request = get(&format!("/load?label={}", label)).to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    });
+}
+
+#[test]
+fn unknown_label_not_found() {
+    init_env();
+    actix_web::rt::System::new("test_not_found").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let label = "com.tasker.tasks.no_such_task";
+        let requests = vec![
+            get(&format!("/delete?label={}", label)),
+            get(&format!("/load?label={}", label)),
+            get(&format!("/unload?label={}", label)),
+            get(&format!("/stdout_raw?label={}&limit=10&filter=", label)),
+            get(&format!("/stderr_raw?label={}&limit=10&filter=", label)),
+            get(&format!("/get_yaml?label={}", label)),
+            post(&format!("/post_yaml?label={}", label)).set_payload(task_yaml(60)),
+            get(&format!("/get_task_zip?label={}", label)),
+            get(&format!("/schedule?label={}", label)),
+            get(&format!("/artifacts?label={}", label)),
+            get(&format!("/artifact?label={}&name=result.csv", label)),
+            get(&format!("/artifacts?label={}&zip=true", label)),
+            test::TestRequest::delete()
+                .uri(&format!("/artifact?label={}&name=result.csv", label))
+                .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN)),
+            get(&format!("/files?label={}", label)),
+            get(&format!("/file?label={}&path=run.sh", label)),
+            get(&format!("/command?label={}", label)),
+            post(&format!("/mark_secret?label={}&key=API_TOKEN", label)),
+            post(&format!("/unmark_secret?label={}&key=API_TOKEN", label)),
+            post(&format!("/refresh_from_git?label={}", label)),
+            get(&format!("/stats?label={}", label)),
+            get(&format!("/provenance?label={}", label)),
+            post(&format!("/run_once?label={}", label)),
+            post(&format!("/clear_output?label={}", label)),
+            post(&format!("/schedule?label={}", label))
+                .set_json(&serde_json::json!({"start_interval": 600})),
+            post(&format!(
+                "/create_from_template?template={}&new_label={}.copy",
+                label, label
+            ))
+            .set_json(&serde_json::json!({})),
+        ];
+        for request in requests {
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let body = test::read_body(response).await;
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["label"], label);
+            assert_eq!(body["error"], "task does not exist");
+        }
+    });
+}
+
+#[test]
+fn traversal_label_rejected() {
+    init_env();
+    actix_web::rt::System::new("test_traversal").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let labels = vec![
+            "../../../etc/passwd%00",
+            "com.tasker.tasks.a%2F..%2F..%2F..%2Fetc%2Fpasswd",
+            "com.tasker.tasks.a/../../secrets",
+            "com.tasker.tasks..",
+            "com.tasker.tasks.a%00",
+            "com.tasker.tasks.a%20b",
+            "com.example.daemon",
+        ];
+        for label in labels {
+            let requests = vec![
+                get(&format!("/stdout_raw?label={}&limit=10&filter=", label)),
+                get(&format!("/stderr_raw?label={}&limit=10&filter=", label)),
+                get(&format!("/get_yaml?label={}", label)),
+                get(&format!("/delete?label={}", label)),
+                get(&format!("/rotate_logs?label={}", label)),
+                get(&format!("/usage?label={}", label)),
+                get(&format!("/get_plist?label={}", label)),
+                get(&format!("/diff?label={}", label)),
+                get(&format!("/files?label={}", label)),
+                get(&format!("/artifacts?label={}", label)),
+                post(&format!("/config/remove?label={}&name=KeepAlive", label)),
+                post(&format!("/run_once?label={}", label)),
+                post(&format!("/clear_output?label={}", label)),
+            ];
+            for request in requests {
+                let response = test::call_service(&mut app, request.to_request()).await;
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", label);
+                let body = test::read_body(response).await;
+                assert!(String::from_utf8_lossy(&body).contains("IllegalLabel"));
+            }
+        }
+        for pattern in &["..%2F..%2Fetc", "a%00", "%2Fetc"] {
+            let requests = vec![
+                get(&format!("/list_raw_json?label={}", pattern)),
+                post(&format!("/adopt?label={}", pattern)),
+            ];
+            for request in requests {
+                let response = test::call_service(&mut app, request.to_request()).await;
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", pattern);
+            }
+        }
+
+        // an invalid regex, an unknown match mode, or an unsafe glob
+        let request = get("/list_raw_json?label=(prod&match=regex");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("InvalidLabelPattern"));
+        for query in &["label=*_prod&match=fuzzy", "label=..%2F*&match=glob"] {
+            let request = get(&format!("/list_raw_json?{}", query));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        }
+
+        // legitimate labels and patterns pass validation
+        let request = get("/get_yaml?label=com.tasker.tasks.no_such_task");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let request = get("/list_raw_json?label=tasker.tasks");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+}
+
+///
+/// create, list, edit, export, and delete a task.
+///
+#[test]
+fn task_lifecycle() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_lifecycle").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+
+        // create
+        create_task!(&mut app);
+
+        // list
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).contains(LABEL));
+        let request = get("/list_text?label=integration_test&match=glob&width=60");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        let body = test::read_body(response).await;
+        let table = String::from_utf8_lossy(&body).to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("LABEL"));
+        assert!(lines[1].starts_with("integration_test "));
+        assert!(lines[2].starts_with("1 task: 1 "));
+
+        // restart: `/bin/echo` has exited, so it is only started
+        for (uri, error) in &[
+            ("/stop?label={}&signal=STOP", "InvalidSignal"),
+            ("/restart?label={}&signal=SEGV", "InvalidSignal"),
+            ("/restart?label={}&grace_seconds=301", "grace_seconds"),
+        ] {
+            let request = get(&uri.replace("{}", LABEL));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains(error));
+        }
+        let request = get(&format!(
+            "/restart?label={}&signal=INT&grace_seconds=1",
+            LABEL
+        ));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["signal"], "SIGINT");
+        assert_eq!(report["stopped_by"], "not_running");
+
+        // edit
+        let request = post(&format!("/post_yaml?label={}", LABEL)).set_payload(task_yaml(3600));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        // stored in canonical form, without `Template: false`
+        let yaml = String::from_utf8_lossy(&body).to_string();
+        assert!(yaml.starts_with("---\nLabel: integration_test\nProgram: /bin/echo\n"));
+        assert!(yaml.ends_with("  - StartInterval: 3600\n"));
+        assert!(!yaml.contains("Template"));
+
+        // provenance
+        let request = get(&format!("/provenance?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let provenance: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(provenance["source"], "zip");
+        assert_eq!(provenance["created_by"], "token #1");
+        assert_eq!(provenance["original_filename"], "task.zip");
+        assert_eq!(provenance["tasker_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(provenance["edits"].as_array().unwrap().len(), 1);
+        assert_eq!(provenance["edits"][0]["by"], "token #1");
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tasks[0]["created_by"], "token #1");
+        assert_eq!(tasks[0]["created_at"], provenance["created_at"]);
+        assert_eq!(tasks[0]["program"], "/bin/echo");
+        assert_eq!(tasks[0]["schedule_summary"], "every 3600s");
+
+        // export
+        let request = get(&format!("/get_task_zip?label={}&compression=stored", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("x-compression-method").unwrap(),
+            "stored"
+        );
+        // downloaded under the name of the task, whatever the options
+        assert_eq!(
+            response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+            &format!("attachment; filename=\"{}.zip\"", LABEL)
+        );
+        let body = test::read_body(response).await;
+        let mut archive = zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+        let mut yaml = String::new();
+        archive
+            .by_name(&format!("{}.yaml", LABEL))
+            .unwrap()
+            .read_to_string(&mut yaml)
+            .unwrap();
+        assert!(yaml.contains("StartInterval: 3600"));
+
+        // export all tasks, each in its own folder
+        let request = get("/export_all?compression=stored");
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"tasker-export.zip\""
+        );
+        let body = test::read_body(response).await;
+        let mut archive = zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+        assert!(archive
+            .by_name(&format!("{}/{}.yaml", LABEL, LABEL))
+            .is_ok());
+
+        // back up the same archive
+        let body = test::read_response(&mut app, post("/backup_now").to_request()).await;
+        let outcome: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let backup = Env::get()
+            .backup_dir
+            .join(outcome["file"].as_str().unwrap());
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(backup).unwrap()).unwrap();
+        let mut yaml = String::new();
+        archive
+            .by_name(&format!("{}/{}.yaml", LABEL, LABEL))
+            .unwrap()
+            .read_to_string(&mut yaml)
+            .unwrap();
+        assert!(yaml.contains("StartInterval: 3600"));
+
+        // delete the loaded task in two steps
+        let request = get(&format!("/delete?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let conflict: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(conflict["expires_in_secs"], 60);
+        // a wrong token is answered with a new token
+        let request = get(&format!("/delete?label={}&confirm=wrong", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = test::read_body(response).await;
+        let retry: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_ne!(retry["confirm"], conflict["confirm"]);
+        let token = retry["confirm"].as_str().unwrap();
+        let request = get(&format!("/delete?label={}&confirm={}", LABEL, token));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(!String::from_utf8_lossy(&body).contains(LABEL));
+    });
+}
+
+///
+/// create a task from a template, which itself cannot be loaded.
+///
+#[test]
+fn template_lifecycle() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_template").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let new_label = format!("{}_copy", LABEL);
+
+        // create, and turn the task into a template
+        create_task!(&mut app);
+        let template = task_yaml_with(60, "{{greeting}}", true);
+        let request = post(&format!("/post_yaml?label={}", LABEL)).set_payload(template);
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // a template cannot be loaded
+        let request = get(&format!("/load?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("CannotLoadTemplate"));
+
+        // missing and unused placeholders
+        let uri = format!(
+            "/create_from_template?template={}&new_label={}",
+            LABEL, new_label
+        );
+        let request = post(&uri).set_json(&serde_json::json!({}));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).contains("MissingTemplateValues([\"greeting\"])"));
+        let request = post(&uri).set_json(&serde_json::json!({"greeting": "hi", "name": "tasker"}));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).contains("UnusedTemplateValues([\"name\"])"));
+
+        // create from template
+        let request = post(&uri).set_json(&serde_json::json!({"greeting": "hi"}));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/get_yaml?label={}", new_label));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let yaml = String::from_utf8_lossy(&body).to_string();
+        assert!(yaml.contains("hi"));
+        assert!(!yaml.contains("Template"));
+        let request = get(&format!("/provenance?label={}", new_label));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let provenance: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(provenance["source"], "template");
+        assert_eq!(provenance["parent_label"], LABEL);
+        assert_eq!(provenance["original_filename"], serde_json::Value::Null);
+
+        // templates are flagged in listing
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        for task in tasks.as_array().unwrap() {
+            let is_template = task["label"] == LABEL;
+            assert_eq!(task["is_template"], is_template);
+            assert_eq!(task["status"], "UNLOADED");
+        }
+
+        // unloaded tasks are deleted in one step
+        for label in &[LABEL, new_label.as_str()] {
+            let request = get(&format!("/delete?label={}", label));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    });
+}
+
+///
+/// run git in `dir` as a committer of the fixture repository
+///
+fn git(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args([
+            "-c",
+            "user.name=tasker",
+            "-c",
+            "user.email=tasker@localhost",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+///
+/// a bare repository `<name>.git` with branch `main` holding the task yaml in
+/// `tasks/integration_test` and a folder `docs` without yaml.
+/// Returns its `file://` url, and the work tree to push further commits from.
+///
+fn git_fixture(name: &str) -> (String, std::path::PathBuf) {
+    let root = std::env::temp_dir()
+        .join("tasker_integration_test_git")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&root);
+    let work = root.join("work");
+    std::fs::create_dir_all(work.join("tasks/integration_test")).unwrap();
+    std::fs::create_dir_all(work.join("docs")).unwrap();
+    std::fs::write(
+        work.join("tasks/integration_test/integration_test.yaml"),
+        task_yaml(60),
+    )
+    .unwrap();
+    std::fs::write(work.join("docs/README.md"), "no task here\n").unwrap();
+    let bare = root.join("repo.git");
+    git(&work, &["init", "--quiet", "--initial-branch", "main"]);
+    git(&work, &["add", "."]);
+    git(&work, &["commit", "--quiet", "-m", "task"]);
+    git(&root, &["init", "--quiet", "--bare", "repo.git"]);
+    git(&work, &["remote", "add", "origin", &bare.to_string_lossy()]);
+    git(&work, &["push", "--quiet", "origin", "main"]);
+    (format!("file://{}", bare.to_string_lossy()), work)
+}
+
+#[test]
+fn create_from_git_errors() {
+    init_env();
+    actix_web::rt::System::new("test_create_from_git_errors").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let (url, _) = git_fixture("errors");
+        let missing_url = url.replace("repo.git", "missing.git");
+        let cases = [
+            (
+                &missing_url,
+                "main",
+                None,
+                StatusCode::BAD_GATEWAY,
+                "GitRemoteUnreachable",
+            ),
+            (
+                &url,
+                "no-such-branch",
+                None,
+                StatusCode::NOT_FOUND,
+                "GitRefNotFound",
+            ),
+            (
+                &url,
+                "main",
+                Some("docs"),
+                StatusCode::BAD_REQUEST,
+                "YamlNotFound",
+            ),
+            (
+                &url,
+                "main",
+                Some("../.."),
+                StatusCode::BAD_REQUEST,
+                "PathEscapesTask",
+            ),
+            (
+                &url,
+                "--upload-pack=true",
+                None,
+                StatusCode::BAD_REQUEST,
+                "GitError",
+            ),
+        ];
+        for (url, git_ref, subdir, status, error) in &cases {
+            let request = post("/create_from_git").set_json(&serde_json::json!({
+                "url": url,
+                "ref": git_ref,
+                "subdir": subdir,
+            }));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), *status);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains(error));
+        }
+
+        // the temporary checkouts are removed
+        let leftovers = std::fs::read_dir("/tmp/tasker.task.com/")
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("git_checkout.")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    });
+}
+
+///
+/// create a task from a git repository, and refresh it after a new commit.
+///
+#[test]
+fn git_lifecycle() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_git_lifecycle").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let (url, work) = git_fixture("lifecycle");
+        let first = git(&work, &["rev-parse", "HEAD"]);
+        let label = format!("{}_git", LABEL);
+
+        // create under another label than the one in the yaml
+        let request = post("/create_from_git").set_json(&serde_json::json!({
+            "url": url,
+            "ref": "main",
+            "subdir": "tasks/integration_test",
+            "label": "integration_test_git",
+        }));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["label"], label.as_str());
+        assert_eq!(created["commit"], first.as_str());
+        let request = get(&format!("/provenance?label={}", label));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let provenance: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(provenance["source"], "git");
+        assert_eq!(provenance["git"]["url"], url.as_str());
+        assert_eq!(provenance["git"]["ref"], "main");
+        assert_eq!(provenance["git"]["commit"], first.as_str());
+
+        // the label is taken now
+        let request = post("/create_from_git").set_json(&serde_json::json!({
+            "url": url,
+            "ref": "main",
+            "label": label,
+        }));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // refresh to a new commit without yaml, which keeps the stored yaml and its label
+        std::fs::remove_file(work.join("tasks/integration_test/integration_test.yaml")).unwrap();
+        std::fs::write(work.join("tasks/integration_test/run.sh"), "echo hi\n").unwrap();
+        git(&work, &["add", "-A"]);
+        git(&work, &["commit", "--quiet", "-m", "script"]);
+        git(&work, &["push", "--quiet", "origin", "main"]);
+        let second = git(&work, &["rev-parse", "HEAD"]);
+        let request = post(&format!("/refresh_from_git?label={}", label));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let refreshed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(refreshed["old_commit"], first.as_str());
+        assert_eq!(refreshed["new_commit"], second.as_str());
+        let request = get(&format!("/files?label={}", label));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).contains("run.sh"));
+
+        // only tasks created from git can be refreshed
+        create_task!(&mut app);
+        let request = post(&format!("/refresh_from_git?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).contains("GitError"));
+
+        for label in &[LABEL, label.as_str()] {
+            delete_task!(&mut app, label);
+        }
+    });
+}
+
+///
+/// rebuild the plist and output folder of a task after they are lost, as after
+/// restoring only `meta` and `tasks` from a backup.
+///
+#[test]
+fn rebuild_from_meta() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_rebuild").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+        let rebuild = |report: &serde_json::Value| {
+            report["tasks"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|task| task["label"] == LABEL)
+                .unwrap()
+                .clone()
+        };
+
+        // a healthy task is left alone
+        let body = test::read_response(&mut app, post("/rebuild").to_request()).await;
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["desired_state_found"], true);
+        assert_eq!(rebuild(&report)["action"], "unchanged");
+        assert_eq!(rebuild(&report)["out_folder_created"], false);
+
+        // lose the plist and the output folder
+        let plist = Env::get().plist_dir.join(format!("{}.plist", LABEL));
+        let unload = ["unload".to_string(), plist.display().to_string()];
+        LAUNCHD.run(&unload).unwrap();
+        std::fs::remove_file(&plist).unwrap();
+        let out_folder = Env::get().out_dir.join(LABEL);
+        std::fs::remove_dir_all(&out_folder).unwrap();
+
+        let body = test::read_response(&mut app, post("/rebuild").to_request()).await;
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(rebuild(&report)["action"], "loaded");
+        assert_eq!(rebuild(&report)["out_folder_created"], true);
+        assert!(plist.is_file());
+        assert!(LAUNCHD.is_loaded(LABEL));
+        assert!(out_folder.is_dir());
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_ne!(tasks[0]["status"], "UNLOADED");
+
+        // running it again changes nothing
+        let body = test::read_response(&mut app, post("/rebuild").to_request()).await;
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(rebuild(&report)["action"], "unchanged");
+        assert_eq!(rebuild(&report)["out_folder_created"], false);
+
+        let request = get(&format!("/unload?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/delete?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+}
+
+///
+/// a plist edited by hand is not overwritten by `/post_yaml` unless `force=true`.
+///
+#[test]
+fn plist_tampering() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_plist_tampering").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let tampered = |body: &[u8]| -> serde_json::Value {
+            let tasks: serde_json::Value = serde_json::from_slice(body).unwrap();
+            tasks[0]["plist_tampered"].clone()
+        };
+
+        // create and load
+        create_task!(&mut app);
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert_eq!(tampered(&body), false);
+
+        // edit the plist by hand
+        let plist = Env::get().plist_dir.join(format!("{}.plist", LABEL));
+        let content = std::fs::read_to_string(&plist).unwrap();
+        std::fs::write(&plist, content + "<!-- edited by hand -->\n").unwrap();
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert_eq!(tampered(&body), true);
+
+        // refused without force
+        let request = post(&format!("/post_yaml?label={}", LABEL)).set_payload(task_yaml(3600));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("PlistModifiedExternally"));
+
+        // overwritten with force
+        let request =
+            post(&format!("/post_yaml?label={}&force=true", LABEL)).set_payload(task_yaml(3600));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/list_raw_json?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert_eq!(tampered(&body), false);
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+///
+/// `/post_yaml` with `If-Match` only updates the yaml version it was read at.
+///
+#[test]
+fn yaml_edit_conflicts() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_yaml_edit_conflicts").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+
+        // create
+        create_task!(&mut app);
+
+        // two editors read the same version
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        assert!(etag.to_str().unwrap().starts_with('"'));
+
+        // the first edit with a matching etag wins, and returns the new etag
+        let request = post(&format!("/post_yaml?label={}", LABEL))
+            .header(header::IF_MATCH, etag.clone())
+            .set_payload(task_yaml(3600));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let new_etag = response.headers().get(header::ETAG).unwrap().clone();
+        assert_ne!(new_etag, etag);
+
+        // the second edit with the stale etag gets the current yaml to merge with
+        let request = post(&format!("/post_yaml?label={}", LABEL))
+            .header(header::IF_MATCH, etag)
+            .set_payload(task_yaml(7200));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let body = test::read_body(response).await;
+        let current: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(current["etag"], new_etag.to_str().unwrap());
+        assert!(current["yaml"]
+            .as_str()
+            .unwrap()
+            .ends_with("  - StartInterval: 3600\n"));
+
+        // without `If-Match`, the last edit wins
+        let request = post(&format!("/post_yaml?label={}", LABEL)).set_payload(task_yaml(7200));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).ends_with("  - StartInterval: 7200\n"));
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+///
+/// list, download, zip, and delete files produced in the output folder.
+///
+#[test]
+fn artifacts() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_artifacts").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+
+        // drop files into the output folder next to the logs
+        let out = Env::get().out_dir.join(LABEL);
+        std::fs::create_dir_all(out.join("reports")).unwrap();
+        std::fs::write(out.join("result.csv"), "a,b\n1,2\n").unwrap();
+        std::fs::write(out.join("reports/summary.txt"), "ok").unwrap();
+        std::fs::write(out.join("stdout.log"), "log").unwrap();
+        std::fs::write(out.join("stdout.log.1"), "old log").unwrap();
+
+        let request = get(&format!("/artifacts?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let files: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let paths: Vec<&str> = files
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, vec!["reports/summary.txt", "result.csv"]);
+
+        let request = get(&format!("/artifact?label={}&name=result.csv", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/csv"
+        );
+        assert!(response
+            .headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("attachment"));
+        assert_eq!(test::read_body(response).await, "a,b\n1,2\n");
+
+        // logs are not artifacts, and paths must stay in the output folder
+        for (name, status) in &[
+            ("stdout.log", StatusCode::NOT_FOUND),
+            ("./stdout.log.1", StatusCode::NOT_FOUND),
+            ("missing.csv", StatusCode::NOT_FOUND),
+            ("../result.csv", StatusCode::BAD_REQUEST),
+            ("/etc/passwd", StatusCode::BAD_REQUEST),
+        ] {
+            let request = get(&format!("/artifact?label={}&name={}", LABEL, name));
+            let response = test::call_service(&mut app, request.to_request()).await;
+            assert_eq!(response.status(), *status, "{}", name);
+        }
+
+        // the whole output folder without logs
+        let request = get(&format!("/artifacts?label={}&zip=true", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let mut archive = zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(names, vec!["reports/", "reports/summary.txt", "result.csv"]);
+        let mut csv = String::new();
+        archive
+            .by_name("result.csv")
+            .unwrap()
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, "a,b\n1,2\n");
+
+        let delete = |name: &str| {
+            test::TestRequest::delete()
+                .uri(&format!("/artifact?label={}&name={}", LABEL, name))
+                .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+                .to_request()
+        };
+        let response = test::call_service(&mut app, delete("result.csv")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!out.join("result.csv").exists());
+        let response = test::call_service(&mut app, delete("stdout.log")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(out.join("stdout.log").exists());
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+#[test]
+fn output_kept_on_edit() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_output_kept_on_edit").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+        let out = Env::get().out_dir.join(LABEL);
+        let log = b"accumulated\nlogs\n".to_vec();
+        std::fs::write(out.join("stdout.log"), &log).unwrap();
+
+        // editing and loading keep the output
+        let request = post(&format!("/post_yaml?label={}", LABEL)).set_payload(task_yaml(120));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(std::fs::read(out.join("stdout.log")).unwrap(), log);
+        let request = get(&format!("/load?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(std::fs::read(out.join("stdout.log"))
+            .unwrap()
+            .starts_with(&log));
+
+        let request = post(&format!("/clear_output?label={}", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(out.is_dir());
+        assert!(!out.join("stdout.log").exists());
+        let trashed = std::fs::read_dir(&Env::get().trash_dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.starts_with(&format!("{}.out.", LABEL))
+                    && std::fs::read(entry.path().join("stdout.log")).ok() == Some(log.clone())
+            });
+        assert!(trashed);
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+///
+/// an update refused by its configuration leaves the task as it was
+///
+#[test]
+fn update_task_files() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_update_task_files").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+        let task_folder = Env::get().task_dir.join(LABEL);
+        std::fs::write(task_folder.join("run.sh"), "echo old\n").unwrap();
+        let update = |yaml: String| {
+            let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            let options = zip::write::FileOptions::default();
+            zip.start_file("integration_test.yaml", options).unwrap();
+            zip.write_all(yaml.as_bytes()).unwrap();
+            zip.start_file("run.sh", options).unwrap();
+            zip.write_all(b"echo new\n").unwrap();
+            post(&format!("/update_task?label={}", LABEL))
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", BOUNDARY),
+                )
+                .set_payload(upload("task.zip", zip.finish().unwrap().into_inner()))
+                .to_request()
+        };
+
+        // refused before the task is unloaded
+        let yaml = task_yaml(60) + "\n  - UserName: tasker_no_such_user";
+        let response = test::call_service(&mut app, update(yaml)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(LAUNCHD.is_loaded(LABEL));
+        let script = std::fs::read_to_string(task_folder.join("run.sh")).unwrap();
+        assert_eq!(script, "echo old\n");
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(!String::from_utf8_lossy(&body).contains("UserName"));
+
+        // replaced, and loaded again
+        let response = test::call_service(&mut app, update(task_yaml(120))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(LAUNCHD.is_loaded(LABEL));
+        let script = std::fs::read_to_string(task_folder.join("run.sh")).unwrap();
+        assert_eq!(script, "echo new\n");
+        assert!(!task_folder.join("integration_test.yaml").exists());
+        let request = get(&format!("/get_yaml?label={}", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&body).ends_with("  - StartInterval: 120\n"));
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+///
+/// the desired state of a task only changes once launchd loaded or unloaded it
+///
+#[test]
+fn desired_state_follows_launchd() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_desired_state").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+        let request = || get(&format!("/list_raw_json?label={}", LABEL)).to_request();
+
+        // launchd refuses to unload the task
+        LAUNCHD.fail_next("unload");
+        let response = test::call_service(
+            &mut app,
+            get(&format!("/unload?label={}", LABEL)).to_request(),
+        )
+        .await;
+        assert_ne!(response.status(), StatusCode::OK);
+        let body = test::read_response(&mut app, request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tasks[0]["should_be_loaded"], true);
+
+        let response = test::call_service(
+            &mut app,
+            get(&format!("/unload?label={}", LABEL)).to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_response(&mut app, request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tasks[0]["should_be_loaded"], false);
+
+        // launchd refuses to load the task
+        LAUNCHD.fail_next("load");
+        let response = test::call_service(
+            &mut app,
+            get(&format!("/load?label={}", LABEL)).to_request(),
+        )
+        .await;
+        assert_ne!(response.status(), StatusCode::OK);
+        assert!(!LAUNCHD.is_loaded(LABEL));
+        let body = test::read_response(&mut app, request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tasks[0]["should_be_loaded"], false);
+
+        let response = test::call_service(
+            &mut app,
+            get(&format!("/load?label={}", LABEL)).to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_response(&mut app, request()).await;
+        let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tasks[0]["should_be_loaded"], true);
+
+        delete_task!(&mut app, LABEL);
+    });
+}
+
+#[test]
+fn labels_follow_create_and_delete() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_labels").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let uri = "/labels?prefix=integration&strip_domain=true";
+        let body = test::read_response(&mut app, get(uri).to_request()).await;
+        let labels: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert!(!labels.contains(&"integration_test".to_string()));
+
+        create_task!(&mut app);
+        let body = test::read_response(&mut app, get(uri).to_request()).await;
+        let labels: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert!(labels.contains(&"integration_test".to_string()));
+        let request = get(&format!("/labels?prefix={}&with_status=true", LABEL));
+        let body = test::read_response(&mut app, request.to_request()).await;
+        let labels: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(labels[0]["label"], LABEL);
+
+        delete_task!(&mut app, LABEL);
+        let body = test::read_response(&mut app, get(uri).to_request()).await;
+        let labels: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert!(!labels.contains(&"integration_test".to_string()));
+    });
+}
+
+#[test]
+fn backup_now_and_list() {
+    init_env();
+    actix_web::rt::System::new("test_backup").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        let response = test::call_service(&mut app, post("/backup_now").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let outcome: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let file = outcome["file"].as_str().unwrap().to_string();
+        assert!(file.starts_with("tasker-backup-") && file.ends_with(".zip"));
+        assert!(outcome["error"].is_null());
+
+        // the backup is a zip in `BACKUP_DIR`, listed newest first
+        let backup = Env::get().backup_dir.join(&file);
+        let archive = zip::ZipArchive::new(std::fs::File::open(&backup).unwrap()).unwrap();
+        // at least the yaml of each task
+        assert!(archive.len() as u64 >= outcome["tasks"].as_u64().unwrap());
+        let body = test::read_response(&mut app, get("/backups").to_request()).await;
+        let backups: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let listed = backups
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["name"] == file.as_str())
+            .unwrap();
+        assert_eq!(listed["bytes"], outcome["bytes"]);
+        assert!(backups.as_array().unwrap().len() <= Env::get().backup_keep);
+
+        // recorded as the last backup
+        let body = test::read_response(&mut app, get("/health").to_request()).await;
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["backup"]["passed"], true);
+        assert!(health["backup"]["last_success"]["file"].is_string());
+    });
+}
+
+#[test]
+fn get_plist_sources() {
+    init_env();
+    let _task = task_lock();
+    actix_web::rt::System::new("test_get_plist_sources").block_on(async {
+        let mut app = test::init_service(server::build_app(
+            limiter(),
+            confirmations(),
+            maintenance(),
+            manual_runs(),
+            metrics(),
+            concurrency(),
+        ))
+        .await;
+        create_task!(&mut app);
+        let generated = get(&format!("/get_plist?label={}&source=generated", LABEL));
+        let generated = test::read_response(&mut app, generated.to_request()).await;
+        let default = get(&format!("/get_plist?label={}", LABEL));
+        let default = test::read_response(&mut app, default.to_request()).await;
+        assert_eq!(generated, default);
+
+        // the plist loaded is the one generated from yaml
+        let disk = get(&format!("/get_plist?label={}&source=disk", LABEL));
+        let disk = test::read_response(&mut app, disk.to_request()).await;
+        assert_eq!(generated, disk);
+        assert!(String::from_utf8_lossy(&disk).contains("<string>/bin/echo</string>"));
+
+        // only the plist on disk shows edits by hand
+        let plist = Env::get().plist_dir.join(format!("{}.plist", LABEL));
+        let content = std::fs::read_to_string(&plist).unwrap();
+        std::fs::write(&plist, content + "<!-- edited by hand -->\n").unwrap();
+        let disk = get(&format!("/get_plist?label={}&source=disk", LABEL));
+        let disk = test::read_response(&mut app, disk.to_request()).await;
+        assert!(String::from_utf8_lossy(&disk).ends_with("<!-- edited by hand -->\n"));
+        let regenerated = get(&format!("/get_plist?label={}", LABEL));
+        let regenerated = test::read_response(&mut app, regenerated.to_request()).await;
+        assert_eq!(generated, regenerated);
+
+        let request = get(&format!("/get_plist?label={}&source=cache", LABEL));
+        let response = test::call_service(&mut app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        delete_task!(&mut app, LABEL);
+    });
+}